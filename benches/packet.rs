@@ -0,0 +1,198 @@
+//! Field access, serialization, cloning, parsing and checksums, most next to a hand written
+//! baseline working on plain byte arrays.
+//!
+//! ```sh
+//! cargo bench --bench packet
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use packet_rs::builders::{create_tcp_packet, create_vxlan_packet, PacketConfig};
+use packet_rs::headers::*;
+use packet_rs::parser;
+use packet_rs::parser::{LayerKind, ParseOptions};
+use packet_rs::Packet;
+
+/// Length of the frame the checksums are computed on
+const FRAME_LEN: usize = 1500;
+
+fn field_access(c: &mut Criterion) {
+    let mut g = c.benchmark_group("field");
+    let mut ipv4 = IPv4::new();
+    let mut raw = ipv4.to_vec();
+
+    // ttl is the byte at offset 8
+    g.bench_function("get_aligned", |b| b.iter(|| black_box(&ipv4).ttl()));
+    g.bench_function("get_aligned_baseline", |b| {
+        b.iter(|| black_box(&raw)[8] as u64)
+    });
+    g.bench_function("set_aligned", |b| b.iter(|| ipv4.set_ttl(black_box(64))));
+    g.bench_function("set_aligned_baseline", |b| {
+        b.iter(|| raw[8] = black_box(64u64) as u8)
+    });
+
+    // the fragment offset is the low 13 bits of the bytes at offset 6
+    g.bench_function("get_unaligned", |b| {
+        b.iter(|| black_box(&ipv4).frag_offset())
+    });
+    g.bench_function("get_unaligned_baseline", |b| {
+        b.iter(|| {
+            let v = black_box(&raw);
+            (u16::from_be_bytes([v[6], v[7]]) & 0x1fff) as u64
+        })
+    });
+    g.bench_function("set_unaligned", |b| {
+        b.iter(|| ipv4.set_frag_offset(black_box(0x123)))
+    });
+    g.bench_function("set_unaligned_baseline", |b| {
+        b.iter(|| {
+            let value = black_box(0x123u64) as u16;
+            let old = u16::from_be_bytes([raw[6], raw[7]]);
+            let new = (old & !0x1fff) | (value & 0x1fff);
+            raw[6..8].copy_from_slice(&new.to_be_bytes());
+        })
+    });
+    g.finish();
+}
+
+fn serialization(c: &mut Criterion) {
+    let mut g = c.benchmark_group("serialize");
+    let ipv4 = IPv4::new();
+    let raw = ipv4.to_vec();
+    g.bench_function("header_to_vec", |b| b.iter(|| black_box(&ipv4).to_vec()));
+    g.bench_function("header_to_vec_baseline", |b| {
+        b.iter(|| black_box(&raw).clone())
+    });
+
+    let pkt = four_layers();
+    let layers: Vec<Vec<u8>> = (0..4).map(|i| pkt[i].to_vec()).collect();
+    g.bench_function("packet_to_vec", |b| b.iter(|| black_box(&pkt).to_vec()));
+    g.bench_function("packet_to_vec_baseline", |b| {
+        b.iter(|| {
+            let mut v = Vec::with_capacity(pkt.len());
+            for l in black_box(&layers) {
+                v.extend_from_slice(l);
+            }
+            v
+        })
+    });
+    g.finish();
+}
+
+fn cloning(c: &mut Criterion) {
+    let mut g = c.benchmark_group("clone");
+    let template = four_layers();
+    g.bench_function("clone_set_src", |b| {
+        b.iter(|| {
+            let mut pkt = black_box(&template).clone();
+            pkt.layer_mut::<IPv4>().unwrap().set_src(0x0a000001);
+            pkt
+        })
+    });
+    g.bench_function("clone_cow_set_src", |b| {
+        b.iter(|| {
+            let mut pkt = black_box(&template).clone_cow();
+            pkt.layer_mut::<IPv4>().unwrap().set_src(0x0a000001);
+            pkt
+        })
+    });
+    g.finish();
+}
+
+fn parsing(c: &mut Criterion) {
+    let mut g = c.benchmark_group("parse");
+    let v = create_tcp_packet(&PacketConfig::default()).to_vec();
+    g.bench_function("slow", |b| b.iter(|| parser::slow::parse(black_box(&v))));
+    g.bench_function("fast", |b| b.iter(|| parser::fast::parse(black_box(&v))));
+    g.bench_function("baseline", |b| {
+        b.iter(|| {
+            // ether type, ip header length and protocol, then the tcp ports
+            let v = black_box(&v);
+            let etype = u16::from_be_bytes([v[12], v[13]]);
+            let ihl = (v[14] & 0xf) as usize * 4;
+            let proto = v[23];
+            let l4 = 14 + ihl;
+            let sport = u16::from_be_bytes([v[l4], v[l4 + 1]]);
+            let dport = u16::from_be_bytes([v[l4 + 2], v[l4 + 3]]);
+            (etype, proto, sport, dport)
+        })
+    });
+    g.finish();
+}
+
+fn parse_depth(c: &mut Criterion) {
+    let mut g = c.benchmark_group("parse_depth");
+    let inner = create_tcp_packet(&PacketConfig::default());
+    let v = create_vxlan_packet(&PacketConfig::default(), 2000, inner).to_vec();
+    let shallow = ParseOptions {
+        stop_after: Some(LayerKind::Transport),
+        ..Default::default()
+    };
+    let raw = ParseOptions {
+        keep_raw: true,
+        ..shallow
+    };
+    g.bench_function("full", |b| b.iter(|| parser::slow::parse(black_box(&v))));
+    g.bench_function("transport", |b| {
+        b.iter(|| Packet::parse_with_options(black_box(&v), &shallow))
+    });
+    g.bench_function("transport_to_vec", |b| {
+        b.iter(|| Packet::parse_with_options(black_box(&v), &shallow).to_vec())
+    });
+    g.bench_function("raw_to_vec", |b| {
+        b.iter(|| Packet::parse_with_options(black_box(&v), &raw).to_vec())
+    });
+    g.finish();
+}
+
+fn checksum(c: &mut Criterion) {
+    let mut g = c.benchmark_group("checksum");
+    let frame: Vec<u8> = (0..FRAME_LEN).map(|i| i as u8).collect();
+    let src = [10, 0, 0, 1];
+    let dst = [10, 0, 0, 2];
+    g.bench_function("l4_checksum", |b| {
+        b.iter(|| Packet::l4_checksum(&src, &dst, 6, black_box(&frame[34..])))
+    });
+    g.bench_function("l4_checksum_baseline", |b| {
+        b.iter(|| {
+            let segment = black_box(&frame[34..]);
+            let mut sum: u32 = 0;
+            for c in src.chunks(2).chain(dst.chunks(2)).chain(segment.chunks(2)) {
+                sum += u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32;
+            }
+            sum += 6 + segment.len() as u32;
+            while sum >> 16 != 0 {
+                sum = (sum & 0xffff) + (sum >> 16);
+            }
+            !(sum as u16)
+        })
+    });
+    g.bench_function("ipv4_checksum", |b| {
+        b.iter(|| Packet::ipv4_checksum(black_box(&frame[14..34])))
+    });
+    g.finish();
+}
+
+fn four_layers() -> Packet {
+    let mut pkt = Packet::new();
+    pkt.push(Packet::ethernet(
+        "00:01:02:03:04:05",
+        "00:06:07:08:09:0a",
+        0x8100,
+    ));
+    pkt.push(Packet::vlan(0, 0, 10, 0x0800));
+    pkt.push(Packet::ipv4(5, 0, 1, 64, 0, 6, "10.0.0.1", "10.0.0.2", 58));
+    pkt.push(Packet::tcp(1234, 80, 0, 0, 5, 0, 2, 8192, 0, 0));
+    pkt
+}
+
+criterion_group!(
+    benches,
+    field_access,
+    serialization,
+    cloning,
+    parsing,
+    parse_depth,
+    checksum
+);
+criterion_main!(benches);
@@ -0,0 +1,63 @@
+//! Sending and receiving a burst of frames one system call per frame and batched with
+//! `sendmmsg` and `recvmmsg`, over a datagram socket pair.
+//!
+//! ```sh
+//! cargo bench --bench raw --features raw-socket
+//! ```
+
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use packet_rs::builders::{create_udp_packet, PacketConfig};
+use packet_rs::raw::RawSocket;
+
+/// Frames per burst
+const BURST: usize = 32;
+
+fn socket_pair() -> (RawSocket, RawSocket) {
+    let mut fds = [0; 2];
+    let ret = unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_DGRAM, 0, fds.as_mut_ptr()) };
+    assert_eq!(ret, 0);
+    unsafe {
+        (
+            RawSocket::from_raw_fd(fds[0]),
+            RawSocket::from_raw_fd(fds[1]),
+        )
+    }
+}
+
+fn burst(c: &mut Criterion) {
+    let mut g = c.benchmark_group("burst");
+    let (mut tx, mut rx) = socket_pair();
+    let frame = create_udp_packet(&PacketConfig {
+        payload_len: 64,
+        ..Default::default()
+    })
+    .to_vec();
+    let frames = vec![frame.as_slice(); BURST];
+    let mut buf = vec![0; 2048];
+    let mut bufs = vec![Vec::new(); BURST];
+
+    g.bench_function("single", |b| {
+        b.iter(|| {
+            for f in black_box(&frames) {
+                tx.write_all(f).unwrap();
+            }
+            for _ in 0..BURST {
+                assert_eq!(rx.read(&mut buf).unwrap(), frame.len());
+            }
+        })
+    });
+    g.bench_function("batch", |b| {
+        b.iter(|| {
+            assert_eq!(tx.send_batch(black_box(&frames)).unwrap(), BURST);
+            assert_eq!(rx.recv_batch(&mut bufs, None).unwrap(), BURST);
+        })
+    });
+    g.finish();
+}
+
+criterion_group!(benches, burst);
+criterion_main!(benches);
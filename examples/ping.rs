@@ -0,0 +1,59 @@
+//! Build an Ether/IPv4/ICMP echo request, complete its lengths and checksums, dump it and parse
+//! it back, checking the parsed layers match the built ones
+
+use packet_rs::addr::MacAddr;
+use packet_rs::headers::*;
+use packet_rs::payload;
+use packet_rs::{parser, Packet};
+
+const IPPROTO_ICMP: u64 = 1;
+/// Echo identifier and sequence number, the first 4 bytes after the ICMP header
+const ECHO_ID: u16 = 0x5052;
+const ECHO_SEQ: u16 = 1;
+/// Bytes of the pattern following the identifier and sequence number, as the 56 bytes of ping
+const DATA_LEN: usize = 52;
+
+fn main() {
+    let mut eth = Ether::new();
+    eth.set_dst_mac("00:11:22:33:44:55".parse::<MacAddr>().unwrap());
+    eth.set_src_mac("00:66:77:88:99:aa".parse::<MacAddr>().unwrap());
+
+    let mut ipv4 = IPv4::new();
+    ipv4.set_src_ip("192.0.2.1".parse().unwrap());
+    ipv4.set_dst_ip("198.51.100.7".parse().unwrap());
+    ipv4.set_protocol(IPPROTO_ICMP);
+
+    let mut icmp = ICMP::new();
+    icmp.set_icmp_type_typed(IcmpType::EchoRequest);
+
+    let mut pkt = Packet::new();
+    pkt.push(eth);
+    pkt.push(ipv4);
+    pkt.push(icmp);
+    let mut data = [ECHO_ID.to_be_bytes(), ECHO_SEQ.to_be_bytes()].concat();
+    data.extend(payload::incrementing(DATA_LEN));
+    pkt.set_payload(&data);
+
+    // the lengths first, the IPv4 header checksum covers the total length
+    pkt.update_lengths();
+    pkt.update_checksums();
+    assert!(pkt.verify_checksums().is_ok());
+
+    let bytes = pkt.to_vec();
+    println!("{}", pkt.summary());
+    print!("{}", pkt.layout().hexdump(&bytes));
+
+    let parsed = parser::slow::parse(&bytes);
+    assert_eq!(parsed.len(), bytes.len());
+    assert_eq!(parsed.to_vec(), bytes);
+    for i in 0..3 {
+        assert_eq!(parsed[i].name(), pkt[i].name());
+        assert_eq!(parsed[i].to_vec(), pkt[i].to_vec());
+    }
+    let ipv4 = parsed.layer::<IPv4>().unwrap();
+    assert_eq!(ipv4.total_len() as usize, bytes.len() - Ether::size());
+    let icmp = parsed.layer::<ICMP>().unwrap();
+    assert_eq!(icmp.icmp_type_typed(), Ok(IcmpType::EchoRequest));
+    assert!(parsed.verify_checksums().is_ok());
+    println!("parsed back {} bytes, the layers match", bytes.len());
+}
@@ -0,0 +1,27 @@
+// Send a TCP packet on an interface and print the next frame received on it
+//
+// cargo run --example raw_socket --features raw-socket -- eth0
+use std::env;
+use std::io::{Read, Write};
+
+use packet_rs::builders::*;
+use packet_rs::parser;
+use packet_rs::raw::RawSocket;
+
+fn main() -> std::io::Result<()> {
+    let ifname = env::args().nth(1).unwrap_or_else(|| String::from("lo"));
+    let mut sock = RawSocket::open(&ifname)?;
+
+    let pkt = create_tcp_packet(&PacketConfig {
+        payload_len: 64,
+        ..Default::default()
+    });
+    sock.write_all(&pkt.to_vec())?;
+
+    let mut buf = [0; 1514];
+    let n = sock.read(&mut buf)?;
+    let rx = parser::slow::parse(&buf[..n]);
+    println!("{}", rx.summary());
+    rx.show();
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::thread;
+
+use packet_rs::builders::*;
+use packet_rs::headers::*;
+
+const WORKERS: u16 = 4;
+const PACKETS: u16 = 1000;
+
+fn main() {
+    // build the template once, the workers clone it
+    let template = create_udp_packet(&Default::default()).template();
+
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|worker| {
+            let template = template.clone();
+            thread::spawn(move || {
+                let mut frame = [0u8; 1514];
+                let mut bytes = 0;
+                for seq in 0..PACKETS {
+                    // only the udp header is copied, the other headers stay shared
+                    let mut pkt = template.clone_cow();
+                    let udp = pkt.layer_mut::<UDP>().unwrap();
+                    udp.set_src(1024 + worker as u64);
+                    udp.set_dst(seq as u64);
+                    pkt.update_checksums();
+                    bytes += pkt.write_into(&mut frame).unwrap();
+                }
+                bytes
+            })
+        })
+        .collect();
+
+    for (i, worker) in workers.into_iter().enumerate() {
+        println!("worker {} built {} bytes", i, worker.join().unwrap());
+    }
+}
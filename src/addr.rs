@@ -0,0 +1,202 @@
+//! # Address types and helpers
+//!
+//! [`MacAddr`] parses and formats MAC addresses. Typed address getters and setters are provided
+//! for the headers carrying MAC and IP addresses.
+//!
+//! ```
+//! # use packet_rs::addr::MacAddr;
+//! # use packet_rs::headers::*;
+//! let mut eth = Ether::new();
+//! eth.set_dst_mac("aa:bb:cc:dd:ee:ff".parse().unwrap());
+//! assert_eq!(eth.dst(), 0xaabbccddeeff);
+//! assert!(eth.dst_mac().is_unicast());
+//! ```
+
+use core::fmt;
+use core::net::{Ipv4Addr, Ipv6Addr};
+use core::str::FromStr;
+
+use crate::headers::*;
+use crate::payload::splitmix64;
+use crate::types::MAC_LEN;
+use crate::PacketError;
+
+/// A MAC address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MacAddr(pub [u8; MAC_LEN]);
+
+impl MacAddr {
+    /// The broadcast address ff:ff:ff:ff:ff:ff
+    pub const BROADCAST: MacAddr = MacAddr([0xff; MAC_LEN]);
+
+    /// Create a random unicast and locally administered address from a seed
+    ///
+    /// The same seed always returns the same address.
+    pub fn random(seed: u64) -> MacAddr {
+        let mut state = seed;
+        let mut mac = MacAddr::from(splitmix64(&mut state));
+        mac.0[0] = (mac.0[0] | 0x02) & !0x01;
+        mac
+    }
+    /// Return the address as bytes
+    pub const fn octets(&self) -> [u8; MAC_LEN] {
+        self.0
+    }
+    /// Return true for ff:ff:ff:ff:ff:ff
+    pub fn is_broadcast(&self) -> bool {
+        *self == MacAddr::BROADCAST
+    }
+    /// Return true if the group bit is set, this includes broadcast
+    pub const fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 == 0x01
+    }
+    /// Return true if the group bit is not set
+    pub const fn is_unicast(&self) -> bool {
+        !self.is_multicast()
+    }
+    /// Return true if the locally administered bit is set
+    pub const fn is_local(&self) -> bool {
+        self.0[0] & 0x02 == 0x02
+    }
+}
+
+impl FromStr for MacAddr {
+    type Err = PacketError;
+
+    /// Parse "aa:bb:cc:dd:ee:ff", "aa-bb-cc-dd-ee-ff" or "aabb.ccdd.eeff"
+    fn from_str(s: &str) -> Result<MacAddr, PacketError> {
+        let err = || PacketError::InvalidValue {
+            field: String::from("MacAddr"),
+            value: String::from(s),
+        };
+        let digits: String = if s.contains('.') {
+            let groups: Vec<&str> = s.split('.').collect();
+            if groups.len() != 3 || groups.iter().any(|x| x.len() != 4) {
+                return Err(err());
+            }
+            groups.concat()
+        } else {
+            let octets: Vec<&str> = s.split([':', '-']).collect();
+            if octets.len() != MAC_LEN || octets.iter().any(|x| x.len() != 2) {
+                return Err(err());
+            }
+            octets.concat()
+        };
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(err());
+        }
+        let mut mac = [0; MAC_LEN];
+        for (i, m) in mac.iter_mut().enumerate() {
+            *m = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).map_err(|_| err())?;
+        }
+        Ok(MacAddr(mac))
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            m[0], m[1], m[2], m[3], m[4], m[5]
+        )
+    }
+}
+
+impl From<u64> for MacAddr {
+    /// Use the lower 48 bits of the value
+    fn from(v: u64) -> MacAddr {
+        let b = v.to_be_bytes();
+        MacAddr([b[2], b[3], b[4], b[5], b[6], b[7]])
+    }
+}
+
+impl From<MacAddr> for u64 {
+    fn from(mac: MacAddr) -> u64 {
+        let m = mac.0;
+        u64::from_be_bytes([0, 0, m[0], m[1], m[2], m[3], m[4], m[5]])
+    }
+}
+
+impl From<[u8; MAC_LEN]> for MacAddr {
+    fn from(v: [u8; MAC_LEN]) -> MacAddr {
+        MacAddr(v)
+    }
+}
+
+/// Convert an IPv4 address into the value taken by the 32 bit field setters
+pub fn ipv4_to_u64(ip: Ipv4Addr) -> u64 {
+    u32::from(ip) as u64
+}
+
+/// Convert a 32 bit field value into an IPv4 address
+pub fn u64_to_ipv4(v: u64) -> Ipv4Addr {
+    Ipv4Addr::from(v as u32)
+}
+
+/// Convert an IPv6 address into the bytes taken by `set_bytes`
+pub fn ipv6_to_bytes(ip: Ipv6Addr) -> [u8; 16] {
+    ip.octets()
+}
+
+/// Convert bytes returned by `bytes` into an IPv6 address, None unless there are 16 bytes
+pub fn bytes_to_ipv6(v: &[u8]) -> Option<Ipv6Addr> {
+    <[u8; 16]>::try_from(v).ok().map(Ipv6Addr::from)
+}
+
+macro_rules! mac_fields {
+    ($name: ident, $($field: ident),*) => {
+        paste! {
+            impl $name {
+                $(
+                pub fn [<$field _mac>](&self) -> MacAddr {
+                    MacAddr::from(self.$field())
+                }
+                pub fn [<set_ $field _mac>](&mut self, mac: MacAddr) {
+                    self.[<set_ $field>](mac.into());
+                }
+                )*
+            }
+        }
+    };
+}
+
+macro_rules! ipv4_fields {
+    ($name: ident, $($field: ident),*) => {
+        paste! {
+            impl $name {
+                $(
+                pub fn [<$field _ip>](&self) -> Ipv4Addr {
+                    u64_to_ipv4(self.$field())
+                }
+                pub fn [<set_ $field _ip>](&mut self, ip: Ipv4Addr) {
+                    self.[<set_ $field>](ipv4_to_u64(ip));
+                }
+                )*
+            }
+        }
+    };
+}
+
+mac_fields!(Ether, dst, src);
+mac_fields!(Dot3, dst, src);
+mac_fields!(ARP, sender_hw_addr, target_hw_addr);
+ipv4_fields!(IPv4, src, dst);
+ipv4_fields!(ARP, sender_proto_addr, target_proto_addr);
+ipv4_fields!(Igmp, group_address);
+
+impl IPv6 {
+    pub fn src_ip(&self) -> Ipv6Addr {
+        bytes_to_ipv6(&self.bytes(IPv6::src_msb(), IPv6::src_lsb())).unwrap()
+    }
+    pub fn set_src_ip(&mut self, ip: Ipv6Addr) {
+        self.set_bytes(IPv6::src_msb(), IPv6::src_lsb(), &ipv6_to_bytes(ip));
+    }
+    pub fn dst_ip(&self) -> Ipv6Addr {
+        bytes_to_ipv6(&self.bytes(IPv6::dst_msb(), IPv6::dst_lsb())).unwrap()
+    }
+    pub fn set_dst_ip(&mut self, ip: Ipv6Addr) {
+        self.set_bytes(IPv6::dst_msb(), IPv6::dst_lsb(), &ipv6_to_bytes(ip));
+    }
+}
@@ -0,0 +1,8 @@
+//! The alloc types of the std prelude, imported by every module so the crate builds without std
+
+pub use alloc::borrow::ToOwned;
+pub use alloc::boxed::Box;
+pub use alloc::format;
+pub use alloc::string::{String, ToString};
+pub use alloc::vec;
+pub use alloc::vec::Vec;
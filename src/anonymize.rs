@@ -0,0 +1,319 @@
+//! # Capture anonymization
+//!
+//! [`Anonymizer`] scrubs the addresses and payloads of packets so captures can be shared:
+//!
+//!  * IPv4 and IPv6 addresses are mapped preserving their prefixes, as Crypto-PAn does: two
+//!    addresses sharing their first n bits are mapped to addresses sharing their first n bits, so
+//!    subnets stay recognizable
+//!  * MAC addresses keep their OUI or are replaced by locally administered addresses, see
+//!    [`MacMode`]
+//!  * The payloads of TCP and UDP packets are zeroed or truncated, see [`PayloadMode`]
+//!
+//! The address fields are those declared `[mac]`, `[ipv4]` or `[ipv6]` in
+//! [`make_header!`](crate::make_header), in every layer, so the addresses of ARP, IGMP and
+//! tunneled packets are mapped as well, and so are the addresses of the packet an ICMP or ICMPv6
+//! error quotes and the gateway of an ICMP redirect. Addresses that do not identify a host,
+//! unspecified, loopback, multicast and broadcast, are kept.
+//!
+//! The other addresses are mapped bit by bit whatever range they are in: a mapped address may
+//! land in a private, reserved or multicast range, or in a public range from a private one. Tools
+//! classifying the hosts of a capture by address range see the ranges of the mapped addresses.
+//!
+//! The mapping is keyed and deterministic: the same key maps an address to the same address in
+//! every packet and every run, so flows stay correlated across a capture and analyses can be
+//! reproduced on the scrubbed data. It is not a cryptographic mapping, the key must be kept
+//! private. [`Anonymizer::mapping_table`] lists the addresses mapped so far.
+//!
+//! ```
+//! # use packet_rs::anonymize::*;
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! let mut pkt = create_tcp_packet(&Default::default());
+//! let mut anonymizer = Anonymizer::new(0x5eed);
+//! anonymizer.anonymize_packet(&mut pkt);
+//! let ip = pkt.layer::<IPv4>().unwrap();
+//! assert_ne!(ip.src_ip().to_string(), "192.168.0.1");
+//! // 192.168.0.1 and 192.168.0.2 share their first 30 bits, and so do their mappings
+//! assert_eq!(ip.src() >> 2, ip.dst() >> 2);
+//! assert!(pkt.verify_checksums().is_ok());
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+use crate::addr::MacAddr;
+use crate::headers::*;
+use crate::icmp::*;
+use crate::parser;
+use crate::payload::splitmix64;
+use crate::pcap::{read_ethernet_file, write_records_file};
+use crate::{Packet, PacketError};
+
+/// Mapping of the MAC addresses by an [`Anonymizer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MacMode {
+    /// Leave the MAC addresses as they are
+    Keep,
+    /// Keep the OUI, the first 3 bytes naming the vendor, and map the other 3
+    #[default]
+    KeepOui,
+    /// Map to a locally administered unicast address, see [`MacAddr::random`]
+    Randomize,
+}
+
+/// Scrubbing of the payloads of TCP and UDP packets by an [`Anonymizer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadMode {
+    /// Leave the payloads as they are
+    Keep,
+    /// Set every byte of the payloads to zero
+    #[default]
+    Zero,
+    /// Keep the first bytes of the payloads, as a snaplen cutting the packets would
+    ///
+    /// The lengths and checksums are those of the whole packet, see
+    /// [`Anonymizer::anonymize_packet`].
+    Truncate(usize),
+}
+
+/// Options of an [`Anonymizer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AnonymizeOptions {
+    pub mac: MacMode,
+    pub payload: PayloadMode,
+}
+
+/// Keyed mapping of the addresses of packets, and the table of the addresses it mapped
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    key: u64,
+    options: AnonymizeOptions,
+    ips: BTreeMap<IpAddr, IpAddr>,
+    macs: BTreeMap<MacAddr, MacAddr>,
+}
+
+impl Anonymizer {
+    /// Create an anonymizer keeping the OUI of MAC addresses and zeroing the payloads
+    pub fn new(key: u64) -> Anonymizer {
+        Anonymizer::with_options(key, AnonymizeOptions::default())
+    }
+    pub fn with_options(key: u64, options: AnonymizeOptions) -> Anonymizer {
+        Anonymizer {
+            key,
+            options,
+            ips: BTreeMap::new(),
+            macs: BTreeMap::new(),
+        }
+    }
+    /// Map an IPv4 address, preserving its prefix
+    pub fn map_ipv4(&mut self, ip: Ipv4Addr) -> Ipv4Addr {
+        if ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() || ip.is_broadcast() {
+            return ip;
+        }
+        let mapped = Ipv4Addr::from(prefix_preserving(self.key, u32::from(ip) as u128, 32) as u32);
+        self.ips.insert(IpAddr::V4(ip), IpAddr::V4(mapped));
+        mapped
+    }
+    /// Map an IPv6 address, preserving its prefix
+    pub fn map_ipv6(&mut self, ip: Ipv6Addr) -> Ipv6Addr {
+        if ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() {
+            return ip;
+        }
+        let mapped = Ipv6Addr::from(prefix_preserving(self.key, u128::from(ip), 128));
+        self.ips.insert(IpAddr::V6(ip), IpAddr::V6(mapped));
+        mapped
+    }
+    /// Map a MAC address as set by [`AnonymizeOptions::mac`]
+    pub fn map_mac(&mut self, mac: MacAddr) -> MacAddr {
+        if self.options.mac == MacMode::Keep || mac.is_multicast() || mac == MacAddr::default() {
+            return mac;
+        }
+        let mut state = self.key ^ u64::from(mac);
+        let random = MacAddr::random(splitmix64(&mut state));
+        let mapped = match self.options.mac {
+            MacMode::KeepOui => {
+                let mut m = mac.octets();
+                m[3..].copy_from_slice(&random.octets()[3..]);
+                MacAddr(m)
+            }
+            _ => random,
+        };
+        self.macs.insert(mac, mapped);
+        mapped
+    }
+    /// Map the address fields of every layer of pkt, scrub its payload and recompute its
+    /// checksums
+    ///
+    /// Returns the length of the packet on the wire. With [`PayloadMode::Truncate`] the payload
+    /// is cut after the checksums are computed, the packet keeps the lengths and checksums of the
+    /// whole packet and is shorter than the length returned, as a record truncated by a snaplen.
+    pub fn anonymize_packet(&mut self, pkt: &mut Packet) -> usize {
+        self.scrub(pkt);
+        pkt.update_checksums();
+        let len = pkt.len();
+        self.truncate(pkt);
+        len
+    }
+    /// Anonymize the packets of the ethernet capture at pcap_in and write them to pcap_out
+    ///
+    /// The timestamps are kept and the capture is written with nanosecond timestamps. The
+    /// checksums of a record truncated by the snaplen cannot be recomputed, they are updated
+    /// incrementally by [`Packet::edit`]. pcap_out may be pcap_in. Errors are wrapped in the path
+    /// of the file. Returns the number of packets.
+    /// ```
+    /// # use packet_rs::anonymize::*;
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::pcap::*;
+    /// # let path = std::env::temp_dir().join("anonymize_doctest.pcap");
+    /// let pkt = create_udp_packet(&Default::default());
+    /// std::fs::write(&path, write_pcap(&[pkt.to_vec()])).unwrap();
+    /// let mut anonymizer = Anonymizer::new(7);
+    /// assert_eq!(anonymizer.anonymize_pcap(&path, &path).unwrap(), 1);
+    /// assert_eq!(anonymizer.ip_mapping().len(), 2);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn anonymize_pcap<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        pcap_in: P,
+        pcap_out: Q,
+    ) -> Result<usize, PacketError> {
+        let mut records = read_ethernet_file(pcap_in.as_ref())?;
+        for record in records.iter_mut() {
+            if record.packet.len() < record.orig_len as usize {
+                record.packet.edit(|pkt| self.scrub(pkt));
+                self.truncate(&mut record.packet);
+            } else {
+                self.anonymize_packet(&mut record.packet);
+            }
+        }
+        write_records_file(pcap_out.as_ref(), &records)?;
+        Ok(records.len())
+    }
+    /// The IP addresses mapped so far, by original address
+    pub fn ip_mapping(&self) -> &BTreeMap<IpAddr, IpAddr> {
+        &self.ips
+    }
+    /// The MAC addresses mapped so far, by original address
+    pub fn mac_mapping(&self) -> &BTreeMap<MacAddr, MacAddr> {
+        &self.macs
+    }
+    /// List the addresses mapped so far, one `original -> mapped` line per address, the IP
+    /// addresses first
+    pub fn mapping_table(&self) -> String {
+        let mut out = String::new();
+        for (ip, mapped) in &self.ips {
+            writeln!(out, "{} -> {}", ip, mapped).unwrap();
+        }
+        for (mac, mapped) in &self.macs {
+            writeln!(out, "{} -> {}", mac, mapped).unwrap();
+        }
+        out
+    }
+    /// Map the addresses and zero the payload of pkt, leaving its checksums
+    fn scrub(&mut self, pkt: &mut Packet) {
+        self.map_fields(pkt);
+        self.map_quoted(pkt);
+        if self.options.payload == PayloadMode::Zero && has_transport(pkt) {
+            pkt.payload.fill(0);
+        }
+    }
+    /// Map the address fields of every layer of pkt
+    fn map_fields(&mut self, pkt: &mut Packet) {
+        pkt.invalidate();
+        for hdr in pkt.hdrs.iter_mut() {
+            for f in hdr.fields() {
+                let value = match hdr.get_field_bytes(f.name) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let mapped = match (f.kind, value.len()) {
+                    (FieldKind::Mac, 6) => {
+                        let mac = MacAddr(value.try_into().unwrap());
+                        self.map_mac(mac).octets().to_vec()
+                    }
+                    (FieldKind::Ipv4, 4) => {
+                        let ip: [u8; 4] = value.try_into().unwrap();
+                        self.map_ipv4(ip.into()).octets().to_vec()
+                    }
+                    (FieldKind::Ipv6, 16) => {
+                        let ip: [u8; 16] = value.try_into().unwrap();
+                        self.map_ipv6(ip.into()).octets().to_vec()
+                    }
+                    _ => continue,
+                };
+                hdr.set_field_bytes(f.name, &mapped);
+            }
+        }
+    }
+    /// Map the addresses of the packet an ICMP or ICMPv6 error quotes in the payload of pkt,
+    /// after the 4 bytes ending the ICMP header, and the gateway of an ICMP redirect
+    fn map_quoted(&mut self, pkt: &mut Packet) {
+        let (ip, icmp) = match pkt.hdrs.as_slice() {
+            [.., ip, icmp] if icmp.name() == "ICMP" => (ip.name(), icmp),
+            _ => return,
+        };
+        let icmp_type = icmp.get_field("icmp_type").unwrap() as u8;
+        let quoted = match (ip, icmp_type) {
+            ("IPv4", ICMP_REDIRECT) => "IPv4",
+            ("IPv4", ICMP_DEST_UNREACHABLE | ICMP_SOURCE_QUENCH) => "IPv4",
+            ("IPv4", ICMP_TIME_EXCEEDED | ICMP_PARAMETER_PROBLEM) => "IPv4",
+            ("IPv6", ICMPV6_DEST_UNREACHABLE..=ICMPV6_PARAMETER_PROBLEM) => "IPv6",
+            _ => return,
+        };
+        if pkt.payload.len() < 4 {
+            return;
+        }
+        pkt.invalidate();
+        if icmp_type == ICMP_REDIRECT {
+            let gateway: [u8; 4] = pkt.payload[..4].try_into().unwrap();
+            let mapped = self.map_ipv4(gateway.into());
+            pkt.payload[..4].copy_from_slice(&mapped.octets());
+        }
+        let mut inner = parser::slow::parse_from(quoted, &pkt.payload[4..]);
+        self.map_fields(&mut inner);
+        // the quoted packet is cut, only the checksum of its IPv4 header can be recomputed
+        let cut: Vec<_> = (inner.hdrs.iter().enumerate())
+            .filter(|(_, x)| x.name() != "IPv4")
+            .filter_map(|(i, x)| Some((i, x.checksum_field()?.name)))
+            .collect();
+        for (i, field) in cut {
+            inner.poison(i, field).unwrap();
+        }
+        inner.update_checksums();
+        let v = inner.to_vec();
+        if v.len() == pkt.payload.len() - 4 {
+            pkt.payload[4..].copy_from_slice(&v);
+        }
+    }
+    /// Cut the payload of pkt as set by [`PayloadMode::Truncate`]
+    fn truncate(&self, pkt: &mut Packet) {
+        if let PayloadMode::Truncate(len) = self.options.payload {
+            if has_transport(pkt) && pkt.payload.len() > len {
+                pkt.payload.truncate(len);
+                pkt.invalidate();
+            }
+        }
+    }
+}
+
+fn has_transport(pkt: &Packet) -> bool {
+    pkt.iter()
+        .any(|x| matches!(x.name(), "TCP" | "UDP" | "UdpLite"))
+}
+
+/// Map the low bits of addr, flipping each bit by a keyed function of the bits before it so the
+/// mapping preserves prefixes
+fn prefix_preserving(key: u64, addr: u128, bits: u32) -> u128 {
+    let mut out = 0;
+    for i in 0..bits {
+        let prefix = addr.checked_shr(bits - i).unwrap_or(0);
+        let mut state = key ^ ((bits as u64) << 8 | i as u64);
+        let mut state = splitmix64(&mut state) ^ (prefix >> 64) as u64;
+        let mut state = splitmix64(&mut state) ^ prefix as u64;
+        let flip = splitmix64(&mut state) as u128 & 1;
+        out = (out << 1) | ((addr >> (bits - 1 - i)) & 1) ^ flip;
+    }
+    out
+}
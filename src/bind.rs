@@ -0,0 +1,213 @@
+//! # Layer bindings
+//!
+//! A [`Binding`] tells the parser which header follows a layer when a field of that layer holds a
+//! value, as Scapy's `bind_layers`. Headers defined outside the crate with `make_header!` are
+//! dissected once bound, and a binding on a value the parser already knows overrides the built
+//! in header. The field of the bound header named by [`Binding::next`] selects the layer after
+//! it, with the values of the field it is bound on: a header bound on an ethertype carries an
+//! ethertype of its own.
+//!
+//! [`Packet::to_vec_with_fixup`](crate::Packet::to_vec_with_fixup) learns the demux values from
+//! the bindings, both the value selecting the bound header and the one its next field holds.
+//!
+//! `make_header!` declares a binding with a trailing `bind` clause. The declared bindings are
+//! collected when the bindings are first used, so both parsers dissect the header without any
+//! call from the application:
+//!
+//! ```
+//! # #[macro_use] extern crate packet_rs;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::parser;
+//! make_header!(
+//! Shim 4
+//! (
+//!     tag: 0-15,
+//!     etype: 16-31
+//! )
+//! bind(Ether.etype == 0x9988, next = etype)
+//! );
+//! # fn main() {
+//! let mut bytes = vec![0u8; 12];
+//! bytes.extend([0x99, 0x88, 0x00, 0x2a, 0x08, 0x06]);
+//! bytes.extend([0; 28]);
+//! let pkt = parser::slow::parse(&bytes);
+//! assert_eq!(pkt.layer::<Shim>().unwrap().tag(), 42);
+//! assert!(pkt.layer::<ARP>().is_some());
+//! assert_eq!(parser::fast::parse(&bytes).to_vec(), bytes);
+//! # }
+//! ```
+//!
+//! The parsers read the bindings from a snapshot, [`current`], without locking. [`bind`] and
+//! [`unbind`] publish a new snapshot and leak the previous one, as packets being parsed may still
+//! read it: bindings are meant to be set up at startup, not changed per packet. A [`Bindings`]
+//! set can also be passed to [`Packet::parse_more`](crate::Packet::parse_more) directly.
+
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::alloc_prelude::*;
+use crate::headers::Header;
+use crate::sync::Mutex;
+
+#[doc(hidden)]
+pub use inventory;
+
+/// A header following a layer when a field of that layer holds a value
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    /// Name of the layer before the header
+    pub lower: &'static str,
+    /// Field of the layer before the header selecting it
+    pub field: &'static str,
+    /// Value of the field selecting the header
+    pub value: u64,
+    /// Name of the header
+    pub name: &'static str,
+    /// Size of the header
+    pub size: usize,
+    /// Field of the header selecting the next layer, with the values of `field`
+    pub next: Option<&'static str>,
+    /// Create the header from its `size` bytes
+    pub from: fn(&[u8]) -> Box<dyn Header>,
+    /// Create the header borrowing its `size` bytes, for the fast parser
+    pub slice: for<'a> fn(&'a [u8]) -> Box<dyn Header + 'a>,
+}
+
+inventory::collect!(Binding);
+
+/// A set of bindings, at most one per field and value of a lower layer
+#[derive(Debug, Clone, Default)]
+pub struct Bindings {
+    list: Vec<Binding>,
+}
+
+impl Bindings {
+    /// Create an empty set, dissecting the built in headers only
+    pub const fn new() -> Bindings {
+        Bindings { list: Vec::new() }
+    }
+    /// Create the set of the bindings declared by the `bind` clauses of `make_header!`
+    pub fn declared() -> Bindings {
+        let mut bindings = Bindings::new();
+        for binding in inventory::iter::<Binding> {
+            bindings.bind(*binding);
+        }
+        bindings
+    }
+    /// Add a binding, replacing the one on the same field and value of the lower layer
+    pub fn bind(&mut self, binding: Binding) {
+        self.list
+            .retain(|x| !same_trigger(x, binding.lower, binding.field, binding.value));
+        self.list.push(binding);
+    }
+    /// Remove the binding on a field and value of the lower layer, returns it
+    pub fn unbind(&mut self, lower: &str, field: &str, value: u64) -> Option<Binding> {
+        let index = self
+            .list
+            .iter()
+            .position(|x| same_trigger(x, lower, field, value))?;
+        Some(self.list.remove(index))
+    }
+    /// Iterate over the bindings, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Binding> {
+        self.list.iter()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+    /// Binding of the header following a layer whose field holds value
+    pub fn lookup(&self, lower: &str, field: &str, value: u64) -> Option<&Binding> {
+        self.list
+            .iter()
+            .find(|x| same_trigger(x, lower, field, value))
+    }
+    /// First binding of the header named name
+    pub fn find(&self, name: &str) -> Option<&Binding> {
+        self.list.iter().find(|x| x.name == name)
+    }
+    /// Field of hdr and the value selecting next, learned from the bindings
+    ///
+    /// builtin gives the demux field and value of the layers the crate knows.
+    pub(crate) fn demux_value(
+        &self,
+        hdr: &str,
+        next: &str,
+        builtin: fn(&str, &str) -> Option<(&'static str, u64)>,
+    ) -> Option<(&'static str, u64)> {
+        if let Some(x) = self.list.iter().find(|x| x.lower == hdr && x.name == next) {
+            return Some((x.field, x.value));
+        }
+        // the next field of a bound header holds what its lower layer would
+        let binding = self
+            .list
+            .iter()
+            .find(|x| x.name == hdr && x.next.is_some())?;
+        let value = match self
+            .list
+            .iter()
+            .find(|x| x.lower == binding.lower && x.field == binding.field && x.name == next)
+        {
+            Some(x) => x.value,
+            None => match builtin(binding.lower, next)? {
+                (field, value) if field == binding.field => value,
+                _ => return None,
+            },
+        };
+        Some((binding.next.unwrap(), value))
+    }
+}
+
+/// The snapshot the parsers read, null until the bindings are first used
+static CURRENT: AtomicPtr<Bindings> = AtomicPtr::new(ptr::null_mut());
+/// Serializes the changes of the snapshot
+static WRITER: Mutex<()> = Mutex::new(());
+
+/// Return the bindings the parsers use, the declared ones until changed
+pub fn current() -> &'static Bindings {
+    if let Some(bindings) = load() {
+        return bindings;
+    }
+    let _writer = WRITER.lock().unwrap();
+    match load() {
+        Some(bindings) => bindings,
+        None => publish(Bindings::declared()),
+    }
+}
+
+/// Add a binding, replacing the one on the same field and value of the lower layer
+pub fn bind(binding: Binding) {
+    let _writer = WRITER.lock().unwrap();
+    let mut bindings = load().cloned().unwrap_or_else(Bindings::declared);
+    bindings.bind(binding);
+    publish(bindings);
+}
+
+/// Remove the binding on a field and value of the lower layer, returns it
+pub fn unbind(lower: &str, field: &str, value: u64) -> Option<Binding> {
+    let _writer = WRITER.lock().unwrap();
+    let mut bindings = load().cloned().unwrap_or_else(Bindings::declared);
+    let binding = bindings.unbind(lower, field, value)?;
+    publish(bindings);
+    Some(binding)
+}
+
+/// Return the bindings the parsers use, oldest first
+pub fn bindings() -> Vec<Binding> {
+    current().list.clone()
+}
+
+fn load() -> Option<&'static Bindings> {
+    // published snapshots are leaked, never freed
+    unsafe { CURRENT.load(Ordering::Acquire).as_ref() }
+}
+
+/// Make bindings the snapshot, called with the writer lock held
+fn publish(bindings: Bindings) -> &'static Bindings {
+    let bindings: &'static mut Bindings = Box::leak(Box::new(bindings));
+    CURRENT.store(bindings, Ordering::Release);
+    bindings
+}
+
+fn same_trigger(binding: &Binding, lower: &str, field: &str, value: u64) -> bool {
+    binding.lower == lower && binding.field == field && binding.value == value
+}
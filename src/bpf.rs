@@ -0,0 +1,392 @@
+//! # BPF device I/O
+//!
+//! macOS and FreeBSD only, enabled with the `raw-socket` feature. [`BpfDevice`] is a
+//! `/dev/bpf*` device bound to an interface, the counterpart of
+//! [`RawSocket`](../raw/struct.RawSocket.html) on systems without `AF_PACKET`. Every `write` sends
+//! one frame and every `read` receives one frame.
+//!
+//! A read on the device returns a buffer of captured frames, each behind a `bpf_hdr` and padded
+//! to a word boundary. [`BpfDevice::recv_batch`] splits that buffer back into frames.
+//!
+//! ```no_run
+//! # use packet_rs::builders::*;
+//! # use packet_rs::bpf::BpfDevice;
+//! # use std::io::Write;
+//! # use std::time::Duration;
+//! let mut dev = BpfDevice::open("en0").unwrap();
+//! println!("datalink {} mtu {}", dev.datalink(), dev.mtu().unwrap());
+//! dev.write_all(&create_tcp_packet(&Default::default()).to_vec()).unwrap();
+//! let mut bufs = vec![Vec::new(); 8];
+//! let n = dev.recv_batch(&mut bufs, Some(Duration::from_secs(1))).unwrap();
+//! println!("{} frames", n);
+//! ```
+
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::interface::Interface;
+
+/// Size of the buffer the kernel fills on each read
+const BUFFER_LEN: libc::c_uint = 1 << 20;
+
+/// Link type of ethernet interfaces
+pub const DLT_EN10MB: u32 = 1;
+/// Link type of loopback interfaces, frames start with the address family in host order
+pub const DLT_NULL: u32 = 0;
+
+/// `_IOWR('i', 51, struct ifreq)`
+const SIOCGIFMTU: libc::c_ulong = 0xc0206933;
+
+#[cfg(target_os = "macos")]
+const BPF_ALIGNMENT: usize = mem::size_of::<i32>();
+#[cfg(not(target_os = "macos"))]
+const BPF_ALIGNMENT: usize = mem::size_of::<libc::c_long>();
+
+const fn bpf_wordalign(x: usize) -> usize {
+    (x + (BPF_ALIGNMENT - 1)) & !(BPF_ALIGNMENT - 1)
+}
+
+/// Frames read from the device and not yet returned
+struct RecvState {
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+}
+
+/// A BPF device bound to an interface
+pub struct BpfDevice {
+    fd: RawFd,
+    ifname: String,
+    datalink: u32,
+    state: Mutex<RecvState>,
+}
+
+impl BpfDevice {
+    /// Open a free BPF device and bind it to the interface, needs root
+    ///
+    /// The device returns frames as soon as they arrive and sends frames with the link layer
+    /// header they carry.
+    pub fn open(ifname: &str) -> io::Result<BpfDevice> {
+        let mut ifr = ifreq(ifname)?;
+        let fd = open_device()?;
+        // closes the fd on the error paths below
+        let mut dev = BpfDevice {
+            fd,
+            ifname: String::from(ifname),
+            datalink: 0,
+            state: Mutex::new(RecvState {
+                buf: Vec::new(),
+                start: 0,
+                end: 0,
+            }),
+        };
+        let mut blen = BUFFER_LEN;
+        ioctl(fd, libc::BIOCSBLEN, &mut blen)?;
+        ioctl(fd, libc::BIOCSETIF, &mut ifr)?;
+        let mut on: libc::c_uint = 1;
+        ioctl(fd, libc::BIOCIMMEDIATE, &mut on)?;
+        ioctl(fd, libc::BIOCSHDRCMPLT, &mut on)?;
+        ioctl(fd, libc::BIOCGBLEN, &mut blen)?;
+        let mut dlt: libc::c_uint = 0;
+        ioctl(fd, libc::BIOCGDLT, &mut dlt)?;
+        dev.datalink = dlt;
+        dev.state.get_mut().unwrap().buf = vec![0; blen as usize];
+        debug!(ifname, fd, datalink = dlt, buffer = blen; "bpf device opened");
+        Ok(dev)
+    }
+    /// Return the link type of the interface, [`DLT_EN10MB`] for ethernet
+    pub fn datalink(&self) -> u32 {
+        self.datalink
+    }
+    /// Return the MTU of the interface
+    pub fn mtu(&self) -> io::Result<usize> {
+        let mut ifr = ifreq(&self.ifname)?;
+        let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+        if sock < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ret = ioctl(sock, SIOCGIFMTU, &mut ifr);
+        unsafe { libc::close(sock) };
+        ret?;
+        Ok(unsafe { ifr.ifr_ifru.ifru_mtu } as usize)
+    }
+    /// Send each frame with one write, returns the number of frames sent
+    ///
+    /// If a frame fails after others were sent, the count of frames sent so far is returned and
+    /// sending the remaining frames again reports the error.
+    pub fn send_batch(&self, frames: &[&[u8]]) -> io::Result<usize> {
+        for (i, frame) in frames.iter().enumerate() {
+            if let Err(e) = send(self.fd, frame) {
+                if i == 0 {
+                    return Err(e);
+                }
+                warn!(fd = self.fd, index = i, error:% = e; "bpf batch send stopped");
+                return Ok(i);
+            }
+        }
+        Ok(frames.len())
+    }
+    /// Receive up to one frame per buffer, returns the number of buffers filled
+    ///
+    /// Each buffer is cleared and holds a whole captured frame. Waits up to timeout for the first
+    /// frame, forever if None, then only takes the frames of the same read. Returns 0 when the
+    /// timeout expires.
+    pub fn recv_batch(&self, bufs: &mut [Vec<u8>], timeout: Option<Duration>) -> io::Result<usize> {
+        if bufs.is_empty() {
+            return Ok(0);
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.start >= state.end {
+            if !wait_readable(self.fd, timeout)? {
+                return Ok(0);
+            }
+            let ret = unsafe {
+                libc::read(
+                    self.fd,
+                    state.buf.as_mut_ptr() as *mut libc::c_void,
+                    state.buf.len(),
+                )
+            };
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                warn!(fd = self.fd, error:% = err; "bpf read failed");
+                return Err(err);
+            }
+            state.start = 0;
+            state.end = ret as usize;
+        }
+        let mut n = 0;
+        for buf in bufs.iter_mut() {
+            let RecvState {
+                buf: data,
+                start,
+                end,
+            } = &mut *state;
+            let (frame, next) = match next_record(&data[*start..*end]) {
+                Some(x) => x,
+                None => {
+                    *start = *end;
+                    break;
+                }
+            };
+            buf.clear();
+            buf.extend_from_slice(frame);
+            *start += next;
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+/// Split the first frame off a read buffer, returns it and the offset of the next record
+fn next_record(buf: &[u8]) -> Option<(&[u8], usize)> {
+    if buf.len() < mem::size_of::<libc::bpf_hdr>() {
+        return None;
+    }
+    let hdr = unsafe { (buf.as_ptr() as *const libc::bpf_hdr).read_unaligned() };
+    let start = hdr.bh_hdrlen as usize;
+    let end = start + hdr.bh_caplen as usize;
+    if end > buf.len() {
+        warn!(hdrlen = start, caplen = hdr.bh_caplen, len = buf.len(); "bpf record truncated");
+        return None;
+    }
+    // a record always moves past its header, even a corrupt one of length zero
+    let next = bpf_wordalign(end).max(bpf_wordalign(mem::size_of::<libc::bpf_hdr>()));
+    Some((&buf[start..end], next.min(buf.len())))
+}
+
+impl Interface for BpfDevice {
+    fn send_batch(&self, frames: &[&[u8]]) -> io::Result<usize> {
+        BpfDevice::send_batch(self, frames)
+    }
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], timeout: Option<Duration>) -> io::Result<usize> {
+        BpfDevice::recv_batch(self, bufs, timeout)
+    }
+}
+
+impl AsRawFd for BpfDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Read for BpfDevice {
+    /// Receive one frame, truncated to the length of buf
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut frame = [Vec::new()];
+        while self.recv_batch(&mut frame, None)? == 0 {}
+        let n = frame[0].len().min(buf.len());
+        buf[..n].copy_from_slice(&frame[0][..n]);
+        Ok(n)
+    }
+}
+
+impl Write for BpfDevice {
+    /// Send buf as one frame
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        send(self.fd, buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for BpfDevice {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Open the first BPF device not used by another process
+fn open_device() -> io::Result<RawFd> {
+    // FreeBSD clones /dev/bpf, macOS only has the numbered devices
+    let paths =
+        std::iter::once(String::from("/dev/bpf")).chain((0..256).map(|i| format!("/dev/bpf{}", i)));
+    for path in paths {
+        let cpath = CString::new(path.as_str()).unwrap();
+        let fd = unsafe { libc::open(cpath.as_ptr(), libc::O_RDWR) };
+        if fd >= 0 {
+            return Ok(fd);
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EBUSY) | Some(libc::ENOENT) => continue,
+            _ => {
+                warn!(path = path.as_str(), error:% = err; "bpf device open failed");
+                return Err(err);
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no free bpf device",
+    ))
+}
+
+fn ifreq(ifname: &str) -> io::Result<libc::ifreq> {
+    let mut ifr: libc::ifreq = unsafe { mem::zeroed() };
+    if ifname.is_empty() || ifname.len() >= ifr.ifr_name.len() || ifname.contains('\0') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid interface name {}", ifname),
+        ));
+    }
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(ifname.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    Ok(ifr)
+}
+
+fn ioctl<T>(fd: RawFd, request: libc::c_ulong, arg: &mut T) -> io::Result<()> {
+    if unsafe { libc::ioctl(fd, request, arg as *mut T) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn send(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    let ret = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+    if ret < 0 {
+        let err = io::Error::last_os_error();
+        warn!(fd, len = buf.len(), error:% = err; "bpf send failed");
+        return Err(err);
+    }
+    Ok(ret as usize)
+}
+
+/// Wait until fd is readable, returns false on timeout
+fn wait_readable(fd: RawFd, timeout: Option<Duration>) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ms = timeout.map_or(-1, |x| x.as_millis().min(i32::MAX as u128) as i32);
+    let ret = unsafe { libc::poll(&mut pfd, 1, ms) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builders::*;
+
+    fn record(frame: &[u8]) -> Vec<u8> {
+        let hdrlen = bpf_wordalign(mem::size_of::<libc::bpf_hdr>());
+        let mut hdr: libc::bpf_hdr = unsafe { mem::zeroed() };
+        hdr.bh_caplen = frame.len() as u32;
+        hdr.bh_datalen = frame.len() as u32;
+        hdr.bh_hdrlen = hdrlen as libc::c_ushort;
+        let mut v = vec![0; hdrlen];
+        unsafe { (v.as_mut_ptr() as *mut libc::bpf_hdr).write_unaligned(hdr) };
+        v.extend_from_slice(frame);
+        v.resize(bpf_wordalign(v.len()), 0);
+        v
+    }
+
+    #[test]
+    fn next_record_test() {
+        let frames: Vec<Vec<u8>> = (1..=3).map(|x| vec![x as u8; 60 + x]).collect();
+        let buf: Vec<u8> = frames.iter().flat_map(|x| record(x)).collect();
+        let mut rest = buf.as_slice();
+        for frame in &frames {
+            let (rx, next) = next_record(rest).unwrap();
+            assert_eq!(rx, frame.as_slice());
+            assert_eq!(next % BPF_ALIGNMENT, 0);
+            rest = &rest[next..];
+        }
+        assert!(rest.is_empty());
+        assert!(next_record(rest).is_none());
+        // a record cut by the end of the buffer is dropped
+        let cut = record(&frames[0]);
+        assert!(next_record(&cut[..cut.len() - 8]).is_none());
+        // a record of length zero is skipped rather than returned forever
+        let mut empty = record(&[]);
+        unsafe { (empty.as_mut_ptr() as *mut libc::bpf_hdr).write_unaligned(mem::zeroed()) };
+        empty.extend(record(&frames[0]));
+        let (rx, next) = next_record(&empty).unwrap();
+        assert!(rx.is_empty());
+        assert!(next > 0);
+        assert_eq!(next_record(&empty[next..]).unwrap().0, frames[0].as_slice());
+    }
+
+    // needs root, run with `sudo cargo test --features raw-socket --lib bpf -- --ignored`
+    #[test]
+    #[ignore]
+    fn loopback_capture_test() {
+        let dev = BpfDevice::open("lo0").unwrap();
+        assert_eq!(dev.datalink(), DLT_NULL);
+        assert!(dev.mtu().unwrap() >= 1500);
+        let pkt = create_udp_packet(&PacketConfig {
+            ip_src: String::from("127.0.0.1"),
+            ip_dst: String::from("127.0.0.1"),
+            payload_len: 32,
+            ..Default::default()
+        });
+        // loopback frames carry the address family instead of an ethernet header
+        let ip = &pkt.to_vec()[14..];
+        let frame = [&(libc::AF_INET as u32).to_ne_bytes()[..], ip].concat();
+        assert_eq!(dev.send_batch(&[&frame]).unwrap(), 1);
+        let mut bufs = vec![Vec::new(); 16];
+        for _ in 0..10 {
+            let n = dev
+                .recv_batch(&mut bufs, Some(Duration::from_millis(200)))
+                .unwrap();
+            if bufs[..n].iter().any(|x| x.ends_with(ip)) {
+                return;
+            }
+        }
+        panic!("udp packet sent on lo0 not captured");
+    }
+}
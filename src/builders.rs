@@ -0,0 +1,245 @@
+//! # Packet builders with default values
+//!
+//! Builders take a [`PacketConfig`] for all the commonly tuned fields and return a fully fixed-up
+//! [`Packet`] with correct lengths and checksums. Fields not of interest are left to their defaults.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! let pkt = create_tcp_packet(&PacketConfig {
+//!     ip_dst: String::from("10.10.10.1"),
+//!     dport: 8080,
+//!     vlan: Some(10),
+//!     payload_len: 100,
+//!     ..Default::default()
+//! });
+//! pkt.show();
+//! ```
+
+use crate::headers::*;
+use crate::payload;
+use crate::types::*;
+use crate::utils;
+use crate::Packet;
+
+/// Configuration shared by all the packet builders
+#[derive(Clone, Debug)]
+pub struct PacketConfig {
+    pub eth_dst: String,
+    pub eth_src: String,
+    /// Insert a vlan header with this vid
+    pub vlan: Option<u16>,
+    pub vlan_pcp: u8,
+    pub ip_src: String,
+    pub ip_dst: String,
+    pub ipv6_src: String,
+    pub ipv6_dst: String,
+    pub ip_tos: u8,
+    /// IPv4 ttl or IPv6 hop limit
+    pub ip_ttl: u8,
+    pub ip_id: u16,
+    pub sport: u16,
+    pub dport: u16,
+    pub tcp_flags: u8,
+    pub arp_opcode: u16,
+    /// Length of the payload filled with an incrementing byte pattern
+    pub payload_len: usize,
+}
+
+impl Default for PacketConfig {
+    fn default() -> Self {
+        PacketConfig {
+            eth_dst: String::from("00:01:02:03:04:05"),
+            eth_src: String::from("00:06:07:08:09:0a"),
+            vlan: None,
+            vlan_pcp: 0,
+            ip_src: String::from("192.168.0.1"),
+            ip_dst: String::from("192.168.0.2"),
+            ipv6_src: String::from("2001:db8::1"),
+            ipv6_dst: String::from("2001:db8::2"),
+            ip_tos: 0,
+            ip_ttl: 64,
+            ip_id: 1,
+            sport: 1234,
+            dport: 80,
+            tcp_flags: 0x2,
+            arp_opcode: 1,
+            payload_len: 0,
+        }
+    }
+}
+
+impl PacketConfig {
+    fn payload(&self) -> Vec<u8> {
+        payload::incrementing(self.payload_len)
+    }
+}
+
+/// Create an Ether/IPv4/TCP packet
+pub fn create_tcp_packet(cfg: &PacketConfig) -> Packet {
+    let mut pkt = utils::create_tcp_packet(
+        &cfg.eth_dst,
+        &cfg.eth_src,
+        cfg.vlan.is_some(),
+        cfg.vlan.unwrap_or(0),
+        cfg.vlan_pcp,
+        5,
+        &cfg.ip_src,
+        &cfg.ip_dst,
+        cfg.ip_tos,
+        cfg.ip_ttl,
+        cfg.ip_id,
+        0,
+        Vec::new(),
+        cfg.dport,
+        cfg.sport,
+        1,
+        0,
+        5,
+        0,
+        cfg.tcp_flags,
+        8192,
+        0,
+        true,
+        &cfg.payload(),
+    );
+    fixup_l4_checksum(&mut pkt);
+    pkt
+}
+
+/// Create an Ether/IPv4/UDP packet
+pub fn create_udp_packet(cfg: &PacketConfig) -> Packet {
+    let mut pkt = utils::create_udp_packet(
+        &cfg.eth_dst,
+        &cfg.eth_src,
+        cfg.vlan.is_some(),
+        cfg.vlan.unwrap_or(0),
+        cfg.vlan_pcp,
+        5,
+        &cfg.ip_src,
+        &cfg.ip_dst,
+        cfg.ip_tos,
+        cfg.ip_ttl,
+        cfg.ip_id,
+        0,
+        Vec::new(),
+        cfg.dport,
+        cfg.sport,
+        true,
+        &cfg.payload(),
+    );
+    fixup_l4_checksum(&mut pkt);
+    pkt
+}
+
+/// Create an Ether/IPv6/TCP packet
+pub fn create_ipv6_tcp_packet(cfg: &PacketConfig) -> Packet {
+    let mut pkt = utils::create_tcpv6_packet(
+        &cfg.eth_dst,
+        &cfg.eth_src,
+        cfg.vlan.is_some(),
+        cfg.vlan.unwrap_or(0),
+        cfg.vlan_pcp,
+        cfg.ip_tos,
+        0,
+        cfg.ip_ttl,
+        &cfg.ipv6_src,
+        &cfg.ipv6_dst,
+        cfg.dport,
+        cfg.sport,
+        1,
+        0,
+        5,
+        0,
+        cfg.tcp_flags,
+        8192,
+        0,
+        &cfg.payload(),
+    );
+    fixup_l4_checksum(&mut pkt);
+    pkt
+}
+
+/// Create an Ether/ARP packet
+///
+/// The sender is taken from `eth_src` and `ip_src` and the target ip from `ip_dst`.
+pub fn create_arp_packet(cfg: &PacketConfig) -> Packet {
+    let target_mac = if cfg.arp_opcode == 1 {
+        "00:00:00:00:00:00"
+    } else {
+        cfg.eth_dst.as_str()
+    };
+    utils::create_arp_packet(
+        &cfg.eth_dst,
+        &cfg.eth_src,
+        cfg.vlan.is_some(),
+        cfg.vlan.unwrap_or(0),
+        cfg.vlan_pcp,
+        cfg.arp_opcode,
+        &cfg.eth_src,
+        target_mac,
+        &cfg.ip_src,
+        &cfg.ip_dst,
+        &cfg.payload(),
+    )
+}
+
+/// Create an Ether/IPv4/UDP/Vxlan packet encapsulating the inner packet
+///
+/// The outer udp destination port is always the vxlan port, `payload_len` is ignored.
+pub fn create_vxlan_packet(cfg: &PacketConfig, vni: u32, inner: Packet) -> Packet {
+    let mut pkt = utils::create_vxlan_packet(
+        &cfg.eth_dst,
+        &cfg.eth_src,
+        cfg.vlan.is_some(),
+        cfg.vlan.unwrap_or(0),
+        cfg.vlan_pcp,
+        5,
+        &cfg.ip_src,
+        &cfg.ip_dst,
+        cfg.ip_tos,
+        cfg.ip_ttl,
+        cfg.ip_id,
+        0,
+        Vec::new(),
+        UDP_PORT_VXLAN,
+        cfg.sport,
+        true,
+        vni,
+        inner,
+    );
+    let ipv4: &mut IPv4 = pkt.layer_mut().unwrap();
+    let chksum = Packet::ipv4_checksum(ipv4.to_vec().as_slice());
+    ipv4.set_header_checksum(chksum as u64);
+    fixup_l4_checksum(&mut pkt);
+    pkt
+}
+
+fn fixup_l4_checksum(pkt: &mut Packet) {
+    let (src, dst) = if let Some(ipv4) = pkt.layer::<IPv4>() {
+        (
+            ipv4.bytes(IPv4::src_msb(), IPv4::src_lsb()),
+            ipv4.bytes(IPv4::dst_msb(), IPv4::dst_lsb()),
+        )
+    } else if let Some(ipv6) = pkt.layer::<IPv6>() {
+        (
+            ipv6.bytes(IPv6::src_msb(), IPv6::src_lsb()),
+            ipv6.bytes(IPv6::dst_msb(), IPv6::dst_lsb()),
+        )
+    } else {
+        return;
+    };
+    if let Some(offset) = pkt.layer_offset::<TCP>() {
+        pkt.layer_mut::<TCP>().unwrap().set_checksum(0);
+        let v = pkt.to_vec();
+        let chksum = Packet::l4_checksum(&src, &dst, IpProtocol::TCP as u8, &v[offset..]);
+        pkt.layer_mut::<TCP>().unwrap().set_checksum(chksum as u64);
+    } else if let Some(offset) = pkt.layer_offset::<UDP>() {
+        pkt.layer_mut::<UDP>().unwrap().set_checksum(0);
+        let v = pkt.to_vec();
+        let chksum = match Packet::l4_checksum(&src, &dst, IpProtocol::UDP as u8, &v[offset..]) {
+            0 => 0xffff,
+            x => x,
+        };
+        pkt.layer_mut::<UDP>().unwrap().set_checksum(chksum as u64);
+    }
+}
@@ -0,0 +1,328 @@
+//! # Capture ring
+//!
+//! [`CaptureRing`] keeps the last frames seen on one or more interfaces within a fixed number of
+//! frames or bytes, overwriting the oldest frames when full. Sniffing threads feed it with
+//! [`CaptureRing::record`] or [`CaptureRing::push`], each frame tagged with the id of its
+//! interface and the time it was captured. Any number of threads can feed a ring, frames are
+//! copied before the ring is locked so the lock is only held to queue them.
+//!
+//! Frames already captured can be searched with [`CaptureRing::find`] and taken out with
+//! [`CaptureRing::drain_matching`] and a [`Mask`]. [`CaptureRing::port`] views the frames of one
+//! interface as an [`Interface`], so the [`interface`](crate::interface) verify helpers can
+//! assert on traffic after the fact.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::capture::*;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::interface::*;
+//! # use std::time::Duration;
+//! let ring = CaptureRing::new(Capacity::Packets(2));
+//! let pkt = create_tcp_packet(&Default::default());
+//! for _ in 0..3 {
+//!     ring.push(1, &pkt.to_vec());
+//! }
+//! let stats = ring.stats();
+//! assert_eq!((stats.received, stats.dropped), (3, 1));
+//!
+//! let mut mask = Mask::new(&pkt);
+//! mask.ignore::<IPv4>("ttl").unwrap();
+//! assert!(ring.find(|frame| mask.matches(&frame.data)).is_some());
+//!
+//! verify_packet(&ring.port(1), &pkt, Duration::ZERO).unwrap();
+//! assert_eq!(ring.drain_matching(&mask).len(), 1);
+//! verify_no_other_packets(&ring.port(1), Duration::ZERO).unwrap();
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use log::debug;
+
+use crate::headers::Header;
+use crate::interface::Interface;
+use crate::{Packet, PacketError};
+
+/// Limit on what a [`CaptureRing`] holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capacity {
+    /// Number of frames
+    Packets(usize),
+    /// Total length of the frames in bytes
+    Bytes(usize),
+}
+
+/// A frame held by a [`CaptureRing`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedFrame {
+    /// Time the frame was captured
+    pub time: SystemTime,
+    /// Id of the interface the frame was captured on
+    pub intf: u32,
+    /// Bytes of the frame
+    pub data: Vec<u8>,
+}
+
+/// Counters of a [`CaptureRing`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RingStats {
+    /// Frames pushed into the ring
+    pub received: u64,
+    /// Frames overwritten, or too large to be held at all
+    pub dropped: u64,
+    /// Bytes pushed into the ring
+    pub bytes: u64,
+}
+
+struct Ring {
+    frames: VecDeque<CapturedFrame>,
+    /// Total length of the frames held
+    len: usize,
+    stats: RingStats,
+}
+
+/// A fixed capacity buffer of the last frames captured
+pub struct CaptureRing {
+    capacity: Capacity,
+    ring: Mutex<Ring>,
+    pushed: Condvar,
+}
+
+impl CaptureRing {
+    /// Create an empty ring
+    pub fn new(capacity: Capacity) -> CaptureRing {
+        CaptureRing {
+            capacity,
+            ring: Mutex::new(Ring {
+                frames: VecDeque::new(),
+                len: 0,
+                stats: RingStats::default(),
+            }),
+            pushed: Condvar::new(),
+        }
+    }
+    /// Return the capacity of the ring
+    pub fn capacity(&self) -> Capacity {
+        self.capacity
+    }
+    /// Copy a frame captured now on interface intf into the ring
+    pub fn push(&self, intf: u32, frame: &[u8]) {
+        self.push_frame(CapturedFrame {
+            time: SystemTime::now(),
+            intf,
+            data: frame.to_vec(),
+        });
+    }
+    /// Add a frame to the ring, overwriting the oldest frames to make room for it
+    ///
+    /// A frame larger than the whole capacity is dropped.
+    pub fn push_frame(&self, frame: CapturedFrame) {
+        let mut ring = self.ring.lock().unwrap();
+        ring.stats.received += 1;
+        ring.stats.bytes += frame.data.len() as u64;
+        let len = frame.data.len();
+        if matches!(self.capacity, Capacity::Packets(0))
+            || matches!(self.capacity, Capacity::Bytes(n) if len > n)
+        {
+            ring.stats.dropped += 1;
+            debug!(len = len; "frame exceeds the capture ring capacity");
+            return;
+        }
+        let fits = |ring: &Ring| match self.capacity {
+            Capacity::Packets(n) => ring.frames.len() < n,
+            Capacity::Bytes(n) => ring.len + len <= n,
+        };
+        while !fits(&ring) && !ring.frames.is_empty() {
+            let old = ring.frames.pop_front().unwrap();
+            ring.len -= old.data.len();
+            ring.stats.dropped += 1;
+        }
+        ring.len += len;
+        ring.frames.push_back(frame);
+        drop(ring);
+        self.pushed.notify_all();
+    }
+    /// Receive a batch of frames from an interface and push them with id intf
+    ///
+    /// Waits up to timeout for the first frame, forever if None, and returns the number of
+    /// frames pushed.
+    pub fn record<I: Interface + ?Sized>(
+        &self,
+        intf: &I,
+        id: u32,
+        timeout: Option<Duration>,
+    ) -> io::Result<usize> {
+        let mut bufs = vec![Vec::new(); 32];
+        let n = intf.recv_batch(&mut bufs, timeout)?;
+        for buf in bufs.drain(..n) {
+            self.push_frame(CapturedFrame {
+                time: SystemTime::now(),
+                intf: id,
+                data: buf,
+            });
+        }
+        Ok(n)
+    }
+    /// Return the number of frames held
+    pub fn len(&self) -> usize {
+        self.ring.lock().unwrap().frames.len()
+    }
+    /// Return true if no frame is held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Return the counters of the ring
+    pub fn stats(&self) -> RingStats {
+        self.ring.lock().unwrap().stats
+    }
+    /// Return the oldest frame for which f returns true
+    pub fn find(&self, mut f: impl FnMut(&CapturedFrame) -> bool) -> Option<CapturedFrame> {
+        let ring = self.ring.lock().unwrap();
+        ring.frames.iter().find(|x| f(x)).cloned()
+    }
+    /// Remove the frames matching mask and return them, oldest first
+    pub fn drain_matching(&self, mask: &Mask) -> Vec<CapturedFrame> {
+        self.take(|x| mask.matches(&x.data))
+    }
+    /// Remove all frames and return them, oldest first
+    pub fn drain(&self) -> Vec<CapturedFrame> {
+        self.take(|_| true)
+    }
+    /// View the frames of interface intf as an interface
+    pub fn port(&self, intf: u32) -> RingPort<'_> {
+        RingPort { ring: self, intf }
+    }
+    /// Remove the frames for which f returns true
+    fn take(&self, mut f: impl FnMut(&CapturedFrame) -> bool) -> Vec<CapturedFrame> {
+        let mut ring = self.ring.lock().unwrap();
+        let mut taken = Vec::new();
+        let mut kept = VecDeque::with_capacity(ring.frames.len());
+        for frame in ring.frames.drain(..) {
+            if f(&frame) {
+                taken.push(frame);
+            } else {
+                kept.push_back(frame);
+            }
+        }
+        ring.frames = kept;
+        ring.len = ring.frames.iter().map(|x| x.data.len()).sum();
+        taken
+    }
+}
+
+/// The frames of one interface held by a [`CaptureRing`]
+///
+/// Receiving removes the frames from the ring, oldest first, and waits for frames still to be
+/// pushed. Sending is not supported.
+pub struct RingPort<'a> {
+    ring: &'a CaptureRing,
+    intf: u32,
+}
+
+impl Interface for RingPort<'_> {
+    fn send_batch(&self, _frames: &[&[u8]]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cannot send on a capture ring",
+        ))
+    }
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], timeout: Option<Duration>) -> io::Result<usize> {
+        if bufs.is_empty() {
+            return Ok(0);
+        }
+        let deadline = timeout.map(|x| Instant::now() + x);
+        let mut ring = self.ring.ring.lock().unwrap();
+        loop {
+            let mut n = 0;
+            let mut i = 0;
+            while n < bufs.len() && i < ring.frames.len() {
+                if ring.frames[i].intf == self.intf {
+                    let frame = ring.frames.remove(i).unwrap();
+                    ring.len -= frame.data.len();
+                    bufs[n] = frame.data;
+                    n += 1;
+                } else {
+                    i += 1;
+                }
+            }
+            if n > 0 {
+                return Ok(n);
+            }
+            ring = match deadline {
+                None => self.ring.pushed.wait(ring).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(0);
+                    }
+                    self.ring
+                        .pushed
+                        .wait_timeout(ring, deadline - now)
+                        .unwrap()
+                        .0
+                }
+            };
+        }
+    }
+}
+
+/// Expected bytes of a frame with fields that may differ
+///
+/// A frame matches if it has the length of the packet and equals it outside the ignored bits.
+#[derive(Clone)]
+pub struct Mask {
+    pkt: Packet,
+    expected: Vec<u8>,
+    /// Bits that must equal the expected bits
+    care: Vec<u8>,
+}
+
+impl Mask {
+    /// Create a mask matching only the bytes of pkt
+    pub fn new(pkt: &Packet) -> Mask {
+        let expected = pkt.to_vec();
+        Mask {
+            pkt: pkt.clone(),
+            care: vec![0xff; expected.len()],
+            expected,
+        }
+    }
+    /// Ignore a field of the first header of type T
+    pub fn ignore<T: Header + 'static>(&mut self, field: &str) -> Result<&mut Mask, PacketError> {
+        let offset = self.pkt.layer_offset::<T>().ok_or_else(|| {
+            let name = core::any::type_name::<T>().rsplit("::").next().unwrap();
+            PacketError::MissingHeader(String::from(name))
+        })?;
+        let hdr = self.pkt.layer::<T>().unwrap();
+        let info = hdr
+            .fields()
+            .iter()
+            .find(|x| x.is_named(field))
+            .ok_or_else(|| PacketError::UnknownField {
+                header: String::from(hdr.name()),
+                field: String::from(field),
+            })?;
+        for bit in offset * 8 + info.lsb..=offset * 8 + info.msb {
+            self.care[bit / 8] &= !(0x80 >> (bit % 8));
+        }
+        Ok(self)
+    }
+    /// Ignore the bytes in range
+    pub fn ignore_bytes(&mut self, range: core::ops::Range<usize>) -> &mut Mask {
+        let end = range.end.min(self.care.len());
+        let start = range.start.min(end);
+        self.care[start..end].fill(0);
+        self
+    }
+    /// Return true if frame matches
+    pub fn matches(&self, frame: &[u8]) -> bool {
+        frame.len() == self.expected.len()
+            && frame
+                .iter()
+                .zip(&self.expected)
+                .zip(&self.care)
+                .all(|((a, b), m)| (a ^ b) & m == 0)
+    }
+}
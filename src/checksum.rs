@@ -0,0 +1,585 @@
+//! # Checksum rewriting
+//!
+//! [`Packet::update_checksums`] recomputes every field annotated `[checksum]`, innermost layer
+//! first so the checksum of an outer layer covers the final bytes of the inner ones. TCP, UDP,
+//! ICMPv6 and MLD checksums include the pseudo header of the closest IP layer before them.
+//! A UDP-Lite checksum only covers the first `coverage` bytes of its segment, see
+//! [`UdpLite::compute_checksum`]. [`TCP::checksum_over`] and its UDP and UDP-Lite counterparts
+//! take a pre-built pseudo header, for encapsulations covering other bytes than the standard one.
+//!
+//! [`Packet::with_zeroed_checksum`] wraps an edit of addresses or ports, as a NAT does, so the
+//! checksums can not be left stale. [`Packet::rewrite_5tuple`] rewrites the addresses and ports
+//! in one call and updates the checksums incrementally. [`Packet::edit`] does the same for any
+//! edit, diffing the packet before and after it.
+//!
+//! [`Packet::prepare_for_offload`] leaves the checksums as a driver hands them to a NIC with
+//! checksum offload, zeroed or holding the pseudo header sum, and [`Packet::complete_offload`]
+//! completes them as the NIC would.
+//!
+//! All of them leave a [poisoned](Packet::poison) checksum field as it is.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! let mut pkt = create_tcp_packet(&Default::default());
+//! pkt.with_zeroed_checksum(|pkt| {
+//!     pkt.layer_mut::<IPv4>().unwrap().set_src(0x0a000001);
+//!     pkt.layer_mut::<TCP>().unwrap().set_src(40000);
+//! });
+//! assert!(pkt.verify_checksums().is_ok());
+//! ```
+
+use core::any::TypeId;
+use core::net::IpAddr;
+
+use crate::alloc_prelude::*;
+use crate::headers::*;
+use crate::icmp::inet_checksum;
+use crate::types::*;
+use crate::{Packet, PacketError};
+
+/// Source, destination and end offset of an IP layer
+type IpContext = (Vec<u8>, Vec<u8>, usize);
+
+impl Packet {
+    /// Recompute the checksum field of every layer that has one
+    ///
+    /// A TCP, UDP or MLD layer with no IP layer before it keeps its checksum, as does a
+    /// [poisoned](Packet::poison) checksum.
+    pub fn update_checksums(&mut self) {
+        self.invalidate();
+        let mut v = self.to_vec();
+        let layers = self.ip_contexts();
+        let poisoned = self.poisoned.clone();
+        for (i, (hdr, (offset, ip))) in self.hdrs.iter_mut().zip(layers).enumerate().rev() {
+            let field = match hdr.checksum_field() {
+                Some(f) if !poisoned.contains(&(i, f.name)) => f,
+                _ => continue,
+            };
+            let pos = offset + field.lsb / 8;
+            let saved = [v[pos], v[pos + 1]];
+            v[pos..pos + 2].fill(0);
+            let start = offset.min(v.len());
+            let end = ip.as_ref().map_or(v.len(), |x| x.2).clamp(start, v.len());
+            let segment = &v[start..end];
+            let pseudo = |proto: IpProtocol| {
+                ip.as_ref()
+                    .map(|(src, dst, _)| Packet::l4_checksum(src, dst, proto as u8, segment))
+            };
+            let chksum = match hdr.name() {
+                "IPv4" => {
+                    let ihl = hdr.get_field("ihl").unwrap() as usize * 4;
+                    Some(inet_checksum(&v[start..(start + ihl).min(v.len())]))
+                }
+                "TCP" => pseudo(IpProtocol::TCP),
+                // zero means no checksum for UDP, a computed zero is sent as all ones
+                "UDP" => pseudo(IpProtocol::UDP).map(nonzero),
+                "Mld" | "MldV2Report" => pseudo(IpProtocol::ICMPV6),
+                "UdpLite" => ip
+                    .as_ref()
+                    .map(|(src, dst, _)| nonzero(udplite_checksum(src, dst, segment))),
+                _ => match ip.as_ref() {
+                    Some((src, _, _)) if src.len() == 16 => pseudo(IpProtocol::ICMPV6),
+                    _ => Some(inet_checksum(segment)),
+                },
+            };
+            match chksum {
+                Some(x) => {
+                    v[pos..pos + 2].copy_from_slice(&x.to_be_bytes());
+                    hdr.set_field(field.name, x as u64);
+                }
+                None => v[pos..pos + 2].copy_from_slice(&saved),
+            }
+        }
+    }
+    /// Zero the checksum fields, run f on the packet, then recompute the checksums
+    ///
+    /// The checksums are computed on the packet as left by f, including layers it added.
+    pub fn with_zeroed_checksum(&mut self, f: impl FnOnce(&mut Packet)) {
+        self.invalidate();
+        for (i, hdr) in self.hdrs.iter_mut().enumerate() {
+            match hdr.checksum_field() {
+                Some(field) if !self.poisoned.contains(&(i, field.name)) => {
+                    hdr.set_field(field.name, 0);
+                }
+                _ => (),
+            }
+        }
+        f(self);
+        self.update_checksums();
+    }
+    /// Run f on the packet, then update the checksums covering the bytes it changed
+    /// incrementally
+    ///
+    /// The edits of `set_field` and the other setters are diffed once f returns: the checksum of
+    /// every layer covering changed bytes, directly or through the pseudo header of its IP layer,
+    /// is adjusted by the difference as in RFC 1624, innermost layer first. As with
+    /// [`Packet::rewrite_5tuple`], a checksum that was wrong stays wrong, a checksum field set by
+    /// f keeps the value it was given and a zero UDP checksum over IPv4 stays zero. If f adds,
+    /// removes or resizes layers or the payload, every checksum is recomputed as
+    /// [`Packet::update_checksums`] does.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let mut pkt = create_tcp_packet(&Default::default());
+    /// pkt.edit(|pkt| {
+    ///     pkt[1].set_field("dst", 0x0a000001);
+    ///     pkt[2].set_field("dst", 8080);
+    /// });
+    /// assert!(pkt.verify_checksums().is_ok());
+    /// ```
+    pub fn edit<R>(&mut self, f: impl FnOnce(&mut Packet) -> R) -> R {
+        // the bytes the checksums cover before f, read back instead of cloning the packet
+        let old = self.to_vec();
+        let old_layout = layout(self);
+        let old_contexts = self.ip_contexts();
+        let ret = f(self);
+        self.invalidate();
+        if layout(self) != old_layout {
+            self.update_checksums();
+            return ret;
+        }
+        // the layout is the same, so the edited packet fills a buffer of the same length
+        let mut v = vec![0; old.len()];
+        self.write_into(&mut v).unwrap();
+        let contexts = old_contexts.into_iter().zip(self.ip_contexts());
+        let layers = self.hdrs.iter_mut().zip(contexts);
+        for (i, (hdr, ((offset, old_ip), (_, ip)))) in layers.enumerate().rev() {
+            let field = match hdr.checksum_field() {
+                Some(f) if !self.poisoned.contains(&(i, f.name)) => f,
+                _ => continue,
+            };
+            let pos = offset + field.lsb / 8;
+            let chksum = u16::from_be_bytes([old[pos], old[pos + 1]]);
+            let name = hdr.name();
+            let l4 = matches!(name, "TCP" | "UDP" | "UdpLite" | "Mld" | "MldV2Report");
+            // a checksum set by f, a transport checksum with no IP layer before it and a UDP
+            // over IPv4 with no checksum are left as they are
+            if hdr.get_field(field.name).unwrap() as u16 != chksum
+                || l4 && ip.is_none()
+                || name == "UDP" && chksum == 0 && ip.as_ref().is_some_and(|x| x.0.len() == 4)
+            {
+                continue;
+            }
+            let start = offset.min(v.len());
+            let end = match name {
+                "IPv4" => start + hdr.get_field("ihl").unwrap() as usize * 4,
+                _ => ip.as_ref().map_or(v.len(), |x| x.2),
+            };
+            let mut end = end.clamp(start, v.len());
+            if name == "UdpLite" {
+                end = start + udplite_coverage(&v[start..end]);
+            }
+            let proto = match (name, &ip) {
+                ("IPv4", _) | (_, None) => None,
+                ("TCP", _) => Some(IpProtocol::TCP as u8),
+                ("UDP", _) => Some(IpProtocol::UDP as u8),
+                ("UdpLite", _) => Some(IpProtocol::UDPLITE as u8),
+                ("Mld" | "MldV2Report", _) => Some(IpProtocol::ICMPV6 as u8),
+                (_, Some((src, _, _))) if src.len() == 16 => Some(IpProtocol::ICMPV6 as u8),
+                _ => None,
+            };
+            // the covered bytes with the checksum zeroed, after the pseudo header if any
+            let covered = |v: &[u8], ip: &Option<IpContext>| {
+                let pseudo = match (proto, ip) {
+                    (Some(proto), Some((src, dst, ip_end))) => {
+                        let len = (ip_end.saturating_sub(offset) as u32).to_be_bytes();
+                        [src.as_slice(), dst, &[0, proto], &len].concat()
+                    }
+                    _ => Vec::new(),
+                };
+                let mut segment = v[start..end].to_vec();
+                let pos = field.lsb / 8;
+                segment[pos..pos + 2].fill(0);
+                [pseudo, segment].concat()
+            };
+            let (prev_bytes, bytes) = (covered(&old, &old_ip), covered(&v, &ip));
+            if prev_bytes == bytes {
+                continue;
+            }
+            let chksum = incremental_checksum(chksum, &prev_bytes, &bytes);
+            let chksum = match name {
+                "UDP" | "UdpLite" => nonzero(chksum),
+                _ => chksum,
+            };
+            v[pos..pos + 2].copy_from_slice(&chksum.to_be_bytes());
+            hdr.set_field(field.name, chksum as u64);
+        }
+        ret
+    }
+    /// Rewrite the addresses of the outermost IP header and the ports of the TCP, UDP or UDP-Lite
+    /// header following it, as a NAT does, and update the checksums incrementally
+    ///
+    /// Only the values given are rewritten. The IPv4 header checksum and the transport checksum,
+    /// which covers the addresses through the pseudo header, are adjusted by the difference
+    /// between the old and new values as in RFC 1624, so a checksum that was wrong stays wrong.
+    /// A zero UDP checksum over IPv4 means no checksum and stays zero. Fails if the packet has
+    /// no IP header, an address is not of the version of the IP header or ports are given with
+    /// no transport header.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let mut pkt = create_udp_packet(&Default::default());
+    /// let public = "203.0.113.7".parse().unwrap();
+    /// pkt.rewrite_5tuple(Some(public), None, Some(61000), None).unwrap();
+    /// assert_eq!(pkt.layer::<IPv4>().unwrap().src_ip().to_string(), "203.0.113.7");
+    /// assert_eq!(pkt.layer::<UDP>().unwrap().src(), 61000);
+    /// assert!(pkt.verify_checksums().is_ok());
+    /// ```
+    pub fn rewrite_5tuple(
+        &mut self,
+        src_ip: Option<IpAddr>,
+        dst_ip: Option<IpAddr>,
+        src_port: Option<u16>,
+        dst_port: Option<u16>,
+    ) -> Result<(), PacketError> {
+        self.invalidate();
+        let ip = self
+            .hdrs
+            .iter()
+            .position(|x| matches!(x.name(), "IPv4" | "IPv6"))
+            .ok_or_else(|| PacketError::MissingHeader(String::from("IPv4")))?;
+        let l4 = self
+            .hdrs
+            .get(ip + 1)
+            .filter(|x| matches!(x.name(), "TCP" | "UDP" | "UdpLite"))
+            .map(|_| ip + 1);
+        if l4.is_none() && (src_port.is_some() || dst_port.is_some()) {
+            return Err(PacketError::MissingHeader(String::from("TCP")));
+        }
+        let is_ipv4 = self.hdrs[ip].name() == "IPv4";
+        let mut addrs = Vec::new();
+        for (field, addr) in [("src", src_ip), ("dst", dst_ip)] {
+            let bytes = match (addr, is_ipv4) {
+                (None, _) => continue,
+                (Some(IpAddr::V4(x)), true) => x.octets().to_vec(),
+                (Some(IpAddr::V6(x)), false) => x.octets().to_vec(),
+                (Some(x), _) => {
+                    return Err(PacketError::InvalidValue {
+                        field: String::from(field),
+                        value: x.to_string(),
+                    })
+                }
+            };
+            addrs.push((field, bytes));
+        }
+        // old and new bytes of the rewritten addresses, then of the rewritten ports
+        let (mut old, mut new) = (Vec::new(), Vec::new());
+        let hdr = &mut self.hdrs[ip];
+        for (field, bytes) in addrs {
+            old.extend(hdr.get_field_bytes(field).unwrap());
+            hdr.set_field_bytes(field, &bytes);
+            new.extend(bytes);
+        }
+        if is_ipv4 {
+            let chksum = hdr.get_field("header_checksum").unwrap() as u16;
+            let chksum = incremental_checksum(chksum, &old, &new);
+            hdr.set_field("header_checksum", chksum as u64);
+        }
+        let hdr = match l4 {
+            Some(l4) => &mut self.hdrs[l4],
+            None => return Ok(()),
+        };
+        for (field, port) in [("src", src_port), ("dst", dst_port)] {
+            if let Some(port) = port {
+                old.extend((hdr.get_field(field).unwrap() as u16).to_be_bytes());
+                hdr.set_field(field, port as u64);
+                new.extend(port.to_be_bytes());
+            }
+        }
+        let chksum = hdr.get_field("checksum").unwrap() as u16;
+        let chksum = match hdr.name() {
+            "UDP" if chksum == 0 && is_ipv4 => 0,
+            "UDP" | "UdpLite" => nonzero(incremental_checksum(chksum, &old, &new)),
+            _ => incremental_checksum(chksum, &old, &new),
+        };
+        hdr.set_field("checksum", chksum as u64);
+        Ok(())
+    }
+    /// Leave the IPv4, TCP and UDP checksums as a driver hands them to a NIC with checksum offload
+    ///
+    /// TCP and UDP layers with no IP layer before them are zeroed and left as is when completed.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::checksum::Offload;
+    /// # use packet_rs::headers::*;
+    /// let pkt = create_tcp_packet(&Default::default());
+    /// let mut offloaded = pkt.clone();
+    /// offloaded.prepare_for_offload(Offload::Zeroed);
+    /// assert_eq!(offloaded.layer::<TCP>().unwrap().checksum(), 0);
+    /// offloaded.complete_offload(Offload::Zeroed);
+    /// assert!(offloaded.compare(&pkt));
+    /// ```
+    pub fn prepare_for_offload(&mut self, offload: Offload) {
+        self.invalidate();
+        let layers = self.ip_contexts();
+        for (i, (hdr, (offset, ip))) in self.hdrs.iter_mut().zip(layers).enumerate() {
+            if hdr
+                .checksum_field()
+                .is_some_and(|x| self.poisoned.contains(&(i, x.name)))
+            {
+                continue;
+            }
+            let value = match (hdr.name(), offload, ip) {
+                ("IPv4", _, _) | ("TCP" | "UDP", Offload::Zeroed, _) => 0,
+                ("TCP", Offload::Partial, Some(ip)) => pseudo_sum(&ip, IpProtocol::TCP, offset),
+                ("UDP", Offload::Partial, Some(ip)) => pseudo_sum(&ip, IpProtocol::UDP, offset),
+                ("TCP" | "UDP", Offload::Partial, None) => 0,
+                _ => continue,
+            };
+            let field = hdr.checksum_field().unwrap();
+            hdr.set_field(field.name, value as u64);
+        }
+    }
+    /// Compute the IPv4, TCP and UDP checksums left by [`Packet::prepare_for_offload`], as the NIC
+    ///
+    /// With [`Offload::Partial`], the TCP and UDP checksums sum the segment from the value left
+    /// in the field, so a wrong partial sum gives a wrong checksum as it would on the wire.
+    pub fn complete_offload(&mut self, offload: Offload) {
+        self.invalidate();
+        let mut v = self.to_vec();
+        let layers = self.ip_contexts();
+        for (i, (hdr, (offset, ip))) in self.hdrs.iter_mut().zip(layers).enumerate().rev() {
+            let name = hdr.name();
+            if !(name == "IPv4" || matches!(name, "TCP" | "UDP") && ip.is_some()) {
+                continue;
+            }
+            let field = hdr.checksum_field().unwrap();
+            if self.poisoned.contains(&(i, field.name)) {
+                continue;
+            }
+            let pos = offset + field.lsb / 8;
+            let start = offset.min(v.len());
+            let end = ip.as_ref().map_or(v.len(), |x| x.2).clamp(start, v.len());
+            if name == "IPv4" || offload == Offload::Zeroed {
+                v[pos..pos + 2].fill(0);
+            }
+            let segment = &v[start..end];
+            let chksum = match (name, offload, ip) {
+                ("IPv4", _, _) => {
+                    let ihl = hdr.get_field("ihl").unwrap() as usize * 4;
+                    inet_checksum(&v[start..(start + ihl).min(v.len())])
+                }
+                (_, Offload::Partial, _) => inet_checksum(segment),
+                ("TCP", Offload::Zeroed, Some((src, dst, _))) => {
+                    Packet::l4_checksum(&src, &dst, IpProtocol::TCP as u8, segment)
+                }
+                (_, Offload::Zeroed, ip) => {
+                    let (src, dst, _) = ip.unwrap();
+                    Packet::l4_checksum(&src, &dst, IpProtocol::UDP as u8, segment)
+                }
+            };
+            let chksum = if name == "UDP" { nonzero(chksum) } else { chksum };
+            v[pos..pos + 2].copy_from_slice(&chksum.to_be_bytes());
+            hdr.set_field(field.name, chksum as u64);
+        }
+    }
+    /// Offset of every layer and the IP layer before it
+    fn ip_contexts(&self) -> Vec<(usize, Option<IpContext>)> {
+        let mut layers: Vec<(usize, Option<IpContext>)> = Vec::new();
+        let mut offset = 0;
+        let mut ip: Option<IpContext> = None;
+        for hdr in &self.hdrs {
+            layers.push((offset, ip.clone()));
+            match hdr.as_any() {
+                x if x.is::<IPv4>() => {
+                    let ipv4 = x.downcast_ref::<IPv4>().unwrap();
+                    ip = Some((
+                        ipv4.bytes(IPv4::src_msb(), IPv4::src_lsb()),
+                        ipv4.bytes(IPv4::dst_msb(), IPv4::dst_lsb()),
+                        offset + ipv4.total_len() as usize,
+                    ));
+                }
+                x if x.is::<IPv6>() => {
+                    let ipv6 = x.downcast_ref::<IPv6>().unwrap();
+                    ip = Some((
+                        ipv6.bytes(IPv6::src_msb(), IPv6::src_lsb()),
+                        ipv6.bytes(IPv6::dst_msb(), IPv6::dst_lsb()),
+                        offset + IPv6::size() + ipv6.payload_len() as usize,
+                    ));
+                }
+                _ => (),
+            }
+            offset += hdr.len();
+        }
+        layers
+    }
+}
+
+/// How checksums are left to a NIC with checksum offload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offload {
+    /// The IPv4, TCP and UDP checksums are zero
+    Zeroed,
+    /// The IPv4 checksum is zero, the TCP and UDP checksums hold the sum of the pseudo header,
+    /// as Linux hands down `CHECKSUM_PARTIAL` packets
+    Partial,
+}
+
+/// Type and length of every layer, then the length of the payload
+fn layout(pkt: &Packet) -> Vec<(Option<TypeId>, usize)> {
+    let layers = pkt.hdrs.iter().chain(&pkt.trailers);
+    let mut v: Vec<_> = layers
+        .map(|x| (Some(x.as_any().type_id()), x.len()))
+        .collect();
+    v.push((None, pkt.payload.len()));
+    v
+}
+
+/// Ones' complement sum of the pseudo header of the segment at offset, not complemented
+fn pseudo_sum((src, dst, end): &IpContext, proto: IpProtocol, offset: usize) -> u16 {
+    let len = (end.saturating_sub(offset) as u32).to_be_bytes();
+    !inet_checksum(&[src.as_slice(), dst, &[0, proto as u8], &len].concat())
+}
+
+impl UdpLite {
+    /// Compute the checksum of the header followed by payload, between the IP addresses src and dst
+    ///
+    /// Only the first `coverage` bytes of the header and payload are summed, all of them when
+    /// coverage is zero. The pseudo header holds the full length. The checksum field is taken as
+    /// zero.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// let mut udplite = UdpLite::new();
+    /// udplite.set_coverage(8);
+    /// let (src, dst) = ([10, 0, 0, 1], [10, 0, 0, 2]);
+    /// let chksum = udplite.compute_checksum(&src, &dst, b"payload");
+    /// // bytes past the coverage are not checked
+    /// assert_eq!(udplite.compute_checksum(&src, &dst, b"PAYLOAD"), chksum);
+    /// ```
+    pub fn compute_checksum(&self, src: &[u8], dst: &[u8], payload: &[u8]) -> u16 {
+        let len = self.len() + payload.len();
+        let pseudo = pseudo_header(src, dst, IpProtocol::UDPLITE, len);
+        self.checksum_over(&pseudo, payload)
+    }
+    /// Compute the checksum of a pre-built pseudo header, then the header followed by payload
+    ///
+    /// Only the first `coverage` bytes of the header and payload are summed, all of them when
+    /// coverage is zero. See [`TCP::checksum_over`].
+    pub fn checksum_over(&self, pseudo: &[u8], payload: &[u8]) -> u16 {
+        let segment = zeroed_segment(self, 6, payload);
+        let covered = &segment[..udplite_coverage(&segment)];
+        nonzero(inet_checksum(&[pseudo, covered].concat()))
+    }
+}
+
+impl TCP {
+    /// Compute the checksum of the header followed by payload, between the IP addresses src and dst
+    ///
+    /// The pseudo header is the standard IPv4 or IPv6 one, after the length of the addresses.
+    /// The checksum field is taken as zero. Options pushed as layers after the header belong to
+    /// payload.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let pkt = create_tcp_packet(&Default::default());
+    /// let (ipv4, tcp) = (pkt.layer::<IPv4>().unwrap(), pkt.layer::<TCP>().unwrap());
+    /// let src = ipv4.bytes(IPv4::src_msb(), IPv4::src_lsb());
+    /// let dst = ipv4.bytes(IPv4::dst_msb(), IPv4::dst_lsb());
+    /// let payload = &pkt.to_vec()[54..];
+    /// assert_eq!(tcp.compute_checksum(&src, &dst, payload), tcp.checksum() as u16);
+    /// ```
+    pub fn compute_checksum(&self, src: &[u8], dst: &[u8], payload: &[u8]) -> u16 {
+        let len = self.len() + payload.len();
+        self.checksum_over(&pseudo_header(src, dst, IpProtocol::TCP, len), payload)
+    }
+    /// Compute the checksum of a pre-built pseudo header, then the header followed by payload
+    ///
+    /// The escape hatch for a checksum coverage of its own, as an encapsulation summing other
+    /// fields than the IP addresses. The bytes of pseudo are summed as they are, so it should be
+    /// of an even length, and an empty one sums the segment alone. The checksum field is taken
+    /// as zero.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// let tcp = TCP::new();
+    /// let (src, dst) = ([10, 0, 0, 1], [10, 0, 0, 2]);
+    /// let pseudo = [&src[..], &dst, &[0, 6, 0, 0, 0, 24]].concat();
+    /// assert_eq!(tcp.checksum_over(&pseudo, b"data"), tcp.compute_checksum(&src, &dst, b"data"));
+    /// ```
+    pub fn checksum_over(&self, pseudo: &[u8], payload: &[u8]) -> u16 {
+        let segment = zeroed_segment(self, 16, payload);
+        inet_checksum(&[pseudo, &segment].concat())
+    }
+}
+
+impl UDP {
+    /// Compute the checksum of the header followed by payload, between the IP addresses src and dst
+    ///
+    /// See [`TCP::compute_checksum`]. A computed zero is returned as all ones.
+    pub fn compute_checksum(&self, src: &[u8], dst: &[u8], payload: &[u8]) -> u16 {
+        let len = self.len() + payload.len();
+        self.checksum_over(&pseudo_header(src, dst, IpProtocol::UDP, len), payload)
+    }
+    /// Compute the checksum of a pre-built pseudo header, then the header followed by payload
+    ///
+    /// See [`TCP::checksum_over`]. A computed zero is returned as all ones.
+    pub fn checksum_over(&self, pseudo: &[u8], payload: &[u8]) -> u16 {
+        let segment = zeroed_segment(self, 6, payload);
+        nonzero(inet_checksum(&[pseudo, &segment].concat()))
+    }
+}
+
+/// Pseudo header of a segment of len bytes between the IP addresses src and dst
+fn pseudo_header(src: &[u8], dst: &[u8], proto: IpProtocol, len: usize) -> Vec<u8> {
+    let len = (len as u32).to_be_bytes();
+    [src, dst, &[0, proto as u8], &len].concat()
+}
+
+/// Bytes of hdr with the 16 bit checksum at offset zeroed, followed by payload
+fn zeroed_segment(hdr: &dyn Header, offset: usize, payload: &[u8]) -> Vec<u8> {
+    let mut segment = hdr.to_vec();
+    segment[offset..offset + 2].fill(0);
+    segment.extend_from_slice(payload);
+    segment
+}
+
+/// Number of bytes of a UDP-Lite segment covered by its checksum
+pub(crate) fn udplite_coverage(segment: &[u8]) -> usize {
+    match segment.get(4..6).map(|x| u16::from_be_bytes([x[0], x[1]]) as usize) {
+        Some(0) | None => segment.len(),
+        Some(x) => x.min(segment.len()),
+    }
+}
+
+/// Internet checksum of the pseudo header and the covered bytes of a UDP-Lite segment
+pub(crate) fn udplite_checksum(src: &[u8], dst: &[u8], segment: &[u8]) -> u16 {
+    let pseudo = pseudo_header(src, dst, IpProtocol::UDPLITE, segment.len());
+    let covered = &segment[..udplite_coverage(segment)];
+    inet_checksum(&[pseudo.as_slice(), covered].concat())
+}
+
+/// Update chksum for 16 bit aligned bytes changed from old to new, as in RFC 1624
+///
+/// An odd last byte is padded with zero as the checksum sums it.
+pub(crate) fn incremental_checksum(chksum: u16, old: &[u8], new: &[u8]) -> u16 {
+    let word = |x: &[u8]| u16::from_be_bytes([x[0], x.get(1).copied().unwrap_or(0)]) as u32;
+    let mut sum = !chksum as u32;
+    for (o, n) in old.chunks(2).zip(new.chunks(2)) {
+        sum += (!word(o) & 0xffff) + word(n);
+    }
+    while sum >> 16 != 0 {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    !(sum as u16)
+}
+
+/// Zero means no checksum for UDP, a computed zero is sent as all ones
+pub(crate) fn nonzero(chksum: u16) -> u16 {
+    if chksum == 0 {
+        0xffff
+    } else {
+        chksum
+    }
+}
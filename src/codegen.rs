@@ -0,0 +1,233 @@
+//! # Rust code export
+//!
+//! [`Packet::to_rust_code`] renders a packet as the Rust statements building it with the headers
+//! of [`headers`](crate::headers), so a captured packet can be pasted into a regression test, as
+//! Scapy's `command()`. Each layer is built in a block of its own with only the fields differing
+//! from the default header set. The payload is a byte string literal. The statements end with
+//! [`Packet::fixup`], so the demux fields, lengths and checksums it computes to the captured
+//! values are left out, and the ones it computes differently are set after it. A layer the crate
+//! has no header for is kept as bytes, in the payload with the layers after it. Trailers are
+//! pushed after the payload, outermost first.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! # use packet_rs::Packet;
+//! let mut pkt = Packet::new();
+//! let mut udp = UDP::new();
+//! udp.set_dst(53);
+//! pkt.push(udp);
+//! pkt.set_payload(b"hi");
+//! pkt.layer_mut::<UDP>().unwrap().set_length(10);
+//! assert_eq!(
+//!     pkt.to_rust_code("query"),
+//!     "let mut query = Packet::new();\n\
+//!      {\n    \
+//!          let mut udp = UDP::new();\n    \
+//!          udp.set_dst(53);\n    \
+//!          query.push(udp);\n\
+//!      }\n\
+//!      query.set_payload(b\"hi\");\n\
+//!      query.fixup();\n"
+//! );
+//! ```
+
+use core::fmt::Write;
+
+use crate::alloc_prelude::*;
+use crate::headers::*;
+use crate::Packet;
+
+/// Bytes per line of a byte array or a byte string
+const LINE_BYTES: usize = 16;
+
+impl Packet {
+    /// Return the Rust statements rebuilding the packet in a variable called var
+    ///
+    /// The statements expect `packet_rs::headers::*` and `packet_rs::Packet` in scope. Fields of
+    /// up to 64 bits are set with their setter, in hex for addresses and checksums, wider ones
+    /// with [`Header::set_field_bytes`]. The packet they build serializes to the same bytes.
+    pub fn to_rust_code(&self, var: &str) -> String {
+        let base = self.known_layers();
+        // the fields the fixup computes are left out of the blocks, the ones it computes
+        // differently from the packet are set after it
+        let mut skip = auto_fields(&base);
+        skip.extend(fixed_differently(&base, &skip));
+        let mut out = format!("let mut {} = Packet::new();\n", var);
+        for (i, hdr) in base.hdrs.iter().enumerate() {
+            let push = format!("{}.push", var);
+            layer_code(&mut out, var, hdr.as_ref(), &push, |f| {
+                skip.contains(&(i, f))
+            });
+        }
+        if !base.payload.is_empty() {
+            writeln!(out, "{}.set_payload({});", var, byte_string(&base.payload)).unwrap();
+        }
+        for hdr in &base.trailers {
+            let push = format!("{}.push_trailer", var);
+            layer_code(&mut out, var, hdr.as_ref(), &push, |_| false);
+        }
+        writeln!(out, "{}.fixup();", var).unwrap();
+        // the layers are boxed once pushed, their fields are set by name
+        for (i, name) in fixed_differently(&base, &skip) {
+            let hdr = base.hdrs[i].as_ref();
+            let f = hdr.fields().iter().find(|x| x.name == name).unwrap();
+            let setter = field_setter(hdr, f, true);
+            writeln!(out, "{}[{}].{};", var, i, setter).unwrap();
+        }
+        out
+    }
+    /// Copy of the packet with the layers from the first one the crate has no header for in
+    /// the payload
+    fn known_layers(&self) -> Packet {
+        let mut pkt = self.clone();
+        let known = |x: &dyn Header| header_from_name(x.name()).is_some_and(|d| d.len() == x.len());
+        if let Some(i) = pkt.hdrs.iter().position(|x| !known(x.as_ref())) {
+            let mut bytes: Vec<u8> = pkt.hdrs[i..].iter().flat_map(|x| x.to_vec()).collect();
+            bytes.extend_from_slice(&pkt.payload);
+            pkt.hdrs.truncate(i);
+            pkt.payload = bytes;
+        }
+        // the trailers are all kept as bytes when one of them has no header of the crate
+        if !pkt.trailers.iter().all(|x| known(x.as_ref())) {
+            let trailers = core::mem::take(&mut pkt.trailers);
+            for hdr in trailers.iter().rev() {
+                pkt.payload.extend(hdr.to_vec());
+            }
+        }
+        pkt.invalidate();
+        pkt
+    }
+}
+
+/// Index and name of the fields of the packet that its fixup sets back to their value once
+/// reset to the default
+fn auto_fields(pkt: &Packet) -> Vec<(usize, &'static str)> {
+    let mut auto = Vec::new();
+    for (i, hdr) in pkt.hdrs.iter().enumerate() {
+        let default = header_from_name(hdr.name()).unwrap();
+        for f in hdr.fields() {
+            let value = hdr.get_field_bytes(f.name).unwrap();
+            let reset = default.get_field_bytes(f.name).unwrap();
+            if reset == value {
+                continue;
+            }
+            let mut probe = pkt.clone();
+            probe.hdrs[i].set_field_bytes(f.name, &reset);
+            probe.fixup();
+            if probe.hdrs[i].get_field_bytes(f.name).unwrap() == value {
+                auto.push((i, f.name));
+            }
+        }
+    }
+    auto
+}
+
+/// Index and name of the fields the fixup sets to another value than the one of the packet,
+/// once the skipped fields are reset to the default
+fn fixed_differently(pkt: &Packet, skip: &[(usize, &'static str)]) -> Vec<(usize, &'static str)> {
+    let mut rebuilt = pkt.clone();
+    for &(i, name) in skip {
+        let default = header_from_name(rebuilt.hdrs[i].name()).unwrap();
+        let reset = default.get_field_bytes(name).unwrap();
+        rebuilt.hdrs[i].set_field_bytes(name, &reset);
+    }
+    rebuilt.fixup();
+    let mut fields = Vec::new();
+    for (i, (hdr, fixed)) in pkt.hdrs.iter().zip(&rebuilt.hdrs).enumerate() {
+        for f in hdr.fields() {
+            if fixed.get_field_bytes(f.name) != hdr.get_field_bytes(f.name) {
+                fields.push((i, f.name));
+            }
+        }
+    }
+    fields
+}
+
+/// Write the block building a header with the fields differing from its default, except the
+/// skipped ones, then passing it to push
+fn layer_code(
+    out: &mut String,
+    var: &str,
+    hdr: &dyn Header,
+    push: &str,
+    skip: impl Fn(&str) -> bool,
+) {
+    let name = hdr.name();
+    let mut layer = name.to_lowercase();
+    if layer == var {
+        layer.push_str("_hdr");
+    }
+    let default = header_from_name(name).unwrap();
+    let mut setters = String::new();
+    for f in hdr.fields() {
+        let value = hdr.get_field_bytes(f.name).unwrap();
+        if default.get_field_bytes(f.name).unwrap() == value || skip(f.name) {
+            continue;
+        }
+        writeln!(setters, "    {}.{};", layer, field_setter(hdr, f, false)).unwrap();
+    }
+    let mutable = if setters.is_empty() { "" } else { "mut " };
+    out.push_str("{\n");
+    writeln!(out, "    let {}{} = {}::new();", mutable, layer, name).unwrap();
+    out.push_str(&setters);
+    match push.ends_with("_trailer") {
+        true => writeln!(out, "    {}(Box::new({}));", push, layer).unwrap(),
+        false => writeln!(out, "    {}({});", push, layer).unwrap(),
+    }
+    out.push_str("}\n");
+}
+
+/// Call setting a field to its value in hdr, with its setter or by name up to 64 bits and with
+/// [`Header::set_field_bytes`] for the wider ones
+fn field_setter(hdr: &dyn Header, f: &FieldInfo, by_name: bool) -> String {
+    let setter = match by_name {
+        true => format!("set_field(\"{}\", ", f.name),
+        false => format!("set_{}(", f.name),
+    };
+    match f.size() {
+        0..=16 if !f.checksum => format!("{}{})", setter, hdr.get_field(f.name).unwrap()),
+        0..=64 => format!("{}{:#x})", setter, hdr.get_field(f.name).unwrap()),
+        _ => {
+            let v = bytes(&hdr.get_field_bytes(f.name).unwrap());
+            format!("set_field_bytes(\"{}\", &[{}])", f.name, v)
+        }
+    }
+}
+
+/// Bytes as the elements of an array, on several lines for the long ones
+fn bytes(v: &[u8]) -> String {
+    let lines: Vec<String> = v
+        .chunks(LINE_BYTES)
+        .map(|x| {
+            let x: Vec<String> = x.iter().map(|b| format!("{:#04x}", b)).collect();
+            x.join(", ")
+        })
+        .collect();
+    match lines.len() {
+        0 | 1 => lines.concat(),
+        _ => format!("\n    {},\n", lines.join(",\n    ")),
+    }
+}
+
+/// Bytes as a byte string literal, continued on several lines for the long ones
+fn byte_string(v: &[u8]) -> String {
+    let lines: Vec<String> = v
+        .chunks(LINE_BYTES)
+        .enumerate()
+        .map(|(i, x)| {
+            let mut line = String::new();
+            for (j, &b) in x.iter().enumerate() {
+                match b {
+                    b'"' => line.push_str("\\\""),
+                    b'\\' => line.push_str("\\\\"),
+                    // a continued line skips its leading whitespace
+                    b' ' if i > 0 && j == 0 => line.push_str("\\x20"),
+                    0x20..=0x7e => line.push(b as char),
+                    _ => write!(line, "\\x{:02x}", b).unwrap(),
+                }
+            }
+            line
+        })
+        .collect();
+    format!("b\"{}\"", lines.join("\\\n    "))
+}
@@ -0,0 +1,95 @@
+//! # Layer composition
+//!
+//! The `/` operator stacks layers as Scapy does: a header or a [`Packet`] divided by a header, a
+//! packet or bytes gives a [`Packet`] with the second after the first. The demux field of the
+//! layer before each join, as the Ethernet etype or the IPv4 protocol, is set from the layer
+//! after it as [`Packet::to_vec_with_fixup`] sets it, including the
+//! [bindings](crate::bind). Lengths and checksums are left to the caller.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! let pkt = Ether::new() / IPv6::new() / UDP::new() / b"hello";
+//! let v = pkt.to_vec();
+//! assert_eq!(v[12..14], [0x86, 0xdd]);
+//! assert_eq!(v[20], 17);
+//! assert!(v.ends_with(b"hello"));
+//! ```
+//!
+//! A packet divided by a packet embeds the second as the first embeds a header, so an outer
+//! stack ending with a tunnel header carries the inner packet:
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! let inner = create_tcp_packet(&Default::default());
+//! let outer = Ether::new() / IPv4::new() / UDP::new() / Vxlan::new();
+//! let pkt = outer / inner;
+//! assert_eq!(pkt.layer::<UDP>().unwrap().dst(), 4789);
+//! assert_eq!(pkt.layers::<IPv4>().len(), 2);
+//!
+//! let pkt = Ether::new() / IPv4::new() / GRE::new() / GREKey::new() / (IPv6::new() / TCP::new());
+//! assert!(pkt.layer::<GRE>().unwrap().key_present());
+//! assert_eq!(pkt.layer::<GRE>().unwrap().proto(), 0x86dd);
+//! ```
+//!
+//! Headers follow a payload as bytes, as in [`Packet::extend`].
+
+use core::ops::Div;
+
+use crate::alloc_prelude::*;
+use crate::headers::*;
+use crate::parser::slow::group_start;
+use crate::Packet;
+
+/// What the `/` operator appends to a packet
+pub trait Layer {
+    /// Append the layer after the bytes of pkt
+    fn append_to(self, pkt: &mut Packet);
+}
+
+impl<T: Header> Layer for T {
+    fn append_to(self, pkt: &mut Packet) {
+        if pkt.payload.is_empty() {
+            pkt.push(self);
+        } else {
+            pkt.set_payload(&self.to_vec());
+        }
+    }
+}
+
+impl Layer for Packet {
+    fn append_to(self, pkt: &mut Packet) {
+        pkt.extend(self);
+    }
+}
+
+impl Layer for &[u8] {
+    fn append_to(self, pkt: &mut Packet) {
+        pkt.set_payload(self);
+    }
+}
+
+impl<const N: usize> Layer for &[u8; N] {
+    fn append_to(self, pkt: &mut Packet) {
+        pkt.set_payload(self);
+    }
+}
+
+/// Appends the layer and sets the demux fields around the join
+impl<R: Layer> Div<R> for Packet {
+    type Output = Packet;
+
+    fn div(mut self, rhs: R) -> Packet {
+        let at = self.hdrs.len();
+        rhs.append_to(&mut self);
+        if at == 0 || at == self.hdrs.len() {
+            return self;
+        }
+        let names: Vec<String> = self.hdrs.iter().map(|x| String::from(x.name())).collect();
+        // the options of the last layer sit between its header and the join
+        for i in group_start(&self.hdrs, at - 1)..=at {
+            self.fixup_layer(i, &names);
+        }
+        self
+    }
+}
@@ -0,0 +1,571 @@
+//! # Packet diagnostics
+//!
+//! [`Packet::diagnose`] reports, per layer, whether the length field matches the bytes that
+//! follow and whether the checksum is valid, without modifying the packet.
+//! [`Packet::verify_checksums`] fails on the first invalid checksum and [`Packet::validate`]
+//! checks that reserved fields are zero.
+//!
+//! [`Packet::verify`] runs every checksum and length check known for the layers of a packet, and
+//! the version and TTL checks of IP headers, and returns a [`VerifyReport`] with the expected and
+//! actual value of each check, printable in a test failure message. [`Packet::verify_with_fcs`] also checks the Ethernet FCS ending the frame.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! let mut pkt = create_udp_packet(&Default::default());
+//! pkt.layer_mut::<IPv4>().unwrap().set_ttl(1);
+//! let report = pkt.diagnose();
+//! assert_eq!(report[1].name, "IPv4");
+//! assert_eq!(report[1].length_ok, Some(true));
+//! assert_eq!(report[1].checksum_ok, Some(false));
+//! ```
+
+use log::debug;
+
+use crate::checksum::{nonzero, udplite_checksum};
+use crate::flow::crc32;
+use crate::headers::*;
+use crate::icmp::inet_checksum;
+use crate::types::*;
+use crate::{Packet, PacketError};
+
+/// Shortest ethernet frame without FCS, shorter packets are padded up to it
+const ETHER_MIN_LEN: usize = 60;
+/// The VXLAN flags with only the valid VNI flag set
+const VXLAN_FLAG_I: u64 = 0x08;
+
+/// Result of the checks on one layer, None when the layer has no such field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerDiagnosis {
+    pub name: String,
+    pub length_ok: Option<bool>,
+    pub checksum_ok: Option<bool>,
+}
+
+impl Packet {
+    /// Check the length and checksum fields of every layer
+    pub fn diagnose(&self) -> Vec<LayerDiagnosis> {
+        let v = self.to_vec();
+        self.diagnose_frame(&v[..v.len() - self.trailers_len()])
+    }
+    /// Check the length and checksum fields of every layer against v, the bytes of the packet
+    fn diagnose_frame(&self, v: &[u8]) -> Vec<LayerDiagnosis> {
+        let mut out = Vec::new();
+        let mut offset = 0;
+        // source, destination and end of the innermost ip header seen so far
+        let mut ip: Option<(Vec<u8>, Vec<u8>, usize)> = None;
+        for hdr in &self.hdrs {
+            let start = offset.min(v.len());
+            let end = ip.as_ref().map_or(v.len(), |x| x.2).clamp(start, v.len());
+            let rest = &v[start..];
+            let segment = &v[start..end];
+            let pseudo = |proto: IpProtocol| {
+                ip.as_ref()
+                    .map(|(src, dst, _)| Packet::l4_checksum(src, dst, proto as u8, segment) == 0)
+            };
+            let (length_ok, checksum_ok) = match hdr.as_any() {
+                x if x.is::<IPv4>() => {
+                    let ipv4 = x.downcast_ref::<IPv4>().unwrap();
+                    let total = ipv4.total_len() as usize;
+                    let ihl = ipv4.ihl() as usize * 4;
+                    ip = Some((
+                        ipv4.bytes(IPv4::src_msb(), IPv4::src_lsb()),
+                        ipv4.bytes(IPv4::dst_msb(), IPv4::dst_lsb()),
+                        offset + total,
+                    ));
+                    let checksum_ok = rest.get(..ihl).map(|h| inet_checksum(h) == 0);
+                    (
+                        Some(length_matches(total, rest.len(), v.len())),
+                        checksum_ok,
+                    )
+                }
+                x if x.is::<IPv6>() => {
+                    let ipv6 = x.downcast_ref::<IPv6>().unwrap();
+                    let total = IPv6::size() + ipv6.payload_len() as usize;
+                    ip = Some((
+                        ipv6.bytes(IPv6::src_msb(), IPv6::src_lsb()),
+                        ipv6.bytes(IPv6::dst_msb(), IPv6::dst_lsb()),
+                        offset + total,
+                    ));
+                    (Some(length_matches(total, rest.len(), v.len())), None)
+                }
+                x if x.is::<UDP>() => {
+                    let udp = x.downcast_ref::<UDP>().unwrap();
+                    let checksum_ok =
+                        if udp.checksum() == 0 && ip.as_ref().is_some_and(|x| x.0.len() == 4) {
+                            None
+                        } else {
+                            pseudo(IpProtocol::UDP)
+                        };
+                    (Some(udp.length() as usize == segment.len()), checksum_ok)
+                }
+                x if x.is::<UdpLite>() => {
+                    let coverage = x.downcast_ref::<UdpLite>().unwrap().coverage() as usize;
+                    let length_ok = coverage == 0 || (8..=segment.len()).contains(&coverage);
+                    let checksum_ok = ip
+                        .as_ref()
+                        .map(|(src, dst, _)| udplite_checksum(src, dst, segment) == 0);
+                    (Some(length_ok), checksum_ok)
+                }
+                x if x.is::<TCP>() => {
+                    let tcp = x.downcast_ref::<TCP>().unwrap();
+                    let data_offset = tcp.data_offset() as usize * 4;
+                    let length_ok = data_offset >= TCP::size() && data_offset <= segment.len();
+                    (Some(length_ok), pseudo(IpProtocol::TCP))
+                }
+                x if x.is::<ICMP>() || x.is::<Igmp>() || x.is::<IgmpV3Report>() => {
+                    match ip.as_ref() {
+                        Some((src, _, _)) if src.len() == 16 => (None, pseudo(IpProtocol::ICMPV6)),
+                        _ => (None, Some(inet_checksum(segment) == 0)),
+                    }
+                }
+                x if x.is::<Mld>() || x.is::<MldV2Report>() => (None, pseudo(IpProtocol::ICMPV6)),
+                _ => (None, None),
+            };
+            if length_ok == Some(false) {
+                debug!(layer = hdr.name(), offset; "length mismatch");
+            }
+            if checksum_ok == Some(false) {
+                debug!(layer = hdr.name(), offset; "checksum mismatch");
+            }
+            out.push(LayerDiagnosis {
+                name: String::from(hdr.name()),
+                length_ok,
+                checksum_ok,
+            });
+            offset += hdr.len();
+        }
+        out
+    }
+    /// Check the checksum of every layer that has one
+    ///
+    /// Fails with the first layer whose checksum is not valid.
+    pub fn verify_checksums(&self) -> Result<(), PacketError> {
+        let mut offset = 0;
+        for (hdr, diag) in self.hdrs.iter().zip(self.diagnose()) {
+            if diag.checksum_ok == Some(false) {
+                return Err(PacketError::ChecksumMismatch {
+                    layer: diag.name,
+                    offset,
+                });
+            }
+            offset += hdr.len();
+        }
+        Ok(())
+    }
+    /// Check that the reserved fields of every header are zero
+    ///
+    /// Fails with the first reserved field that is not zero.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let mut pkt = create_tcp_packet(&Default::default());
+    /// assert!(pkt.validate().is_ok());
+    /// pkt.layer_mut::<TCP>().unwrap().set_res(1);
+    /// assert!(pkt.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), PacketError> {
+        match self.hdrs.iter().find(|x| !x.validate_reserved()) {
+            Some(hdr) => {
+                let field = hdr
+                    .fields()
+                    .iter()
+                    .filter(|f| f.reserved)
+                    .find(|f| hdr.get_field_bytes(f.name).unwrap().iter().any(|b| *b != 0))
+                    .unwrap();
+                Err(PacketError::ReservedNotZero {
+                    header: String::from(hdr.name()),
+                    field: String::from(field.name),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Outcome of one check of a [`VerifyReport`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Bad,
+    /// The check does not apply, see the note of the check
+    Skipped,
+}
+
+/// A checksum or length check of one field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub layer: String,
+    /// Offset of the layer in the packet
+    pub offset: usize,
+    /// Name of the field checked, `fcs` for the Ethernet FCS
+    pub field: &'static str,
+    pub status: CheckStatus,
+    /// Value the field should hold, None when a range of values is valid
+    pub expected: Option<u64>,
+    /// Value the field holds
+    pub actual: Option<u64>,
+    /// Why the check was skipped or what the valid values are
+    pub note: Option<&'static str>,
+}
+
+/// The checks run by [`Packet::verify`], in the order of the layers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub checks: Vec<Check>,
+}
+
+impl VerifyReport {
+    /// Return true if no check failed
+    pub fn is_well_formed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+    /// Iterate over the checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|x| x.status == CheckStatus::Bad)
+    }
+    fn push(&mut self, hdr: (&str, usize), field: &'static str, status: CheckStatus) -> &mut Check {
+        self.checks.push(Check {
+            layer: String::from(hdr.0),
+            offset: hdr.1,
+            field,
+            status,
+            expected: None,
+            actual: None,
+            note: None,
+        });
+        self.checks.last_mut().unwrap()
+    }
+    /// Check a field against the valid value, or a range of values when expected is None
+    fn value(
+        &mut self,
+        hdr: (&str, usize),
+        field: &'static str,
+        actual: u64,
+        expected: Option<u64>,
+        ok: bool,
+    ) -> &mut Check {
+        let check = self.push(hdr, field, status(ok));
+        check.actual = Some(actual);
+        check.expected = expected;
+        check
+    }
+    /// Check the checksum at pos of bytes, ok being the diagnosis of the checksum
+    ///
+    /// The expected value is sum over the bytes with the checksum zeroed.
+    fn checksum(
+        &mut self,
+        hdr: (&str, usize),
+        field: &'static str,
+        (bytes, pos): (&[u8], usize),
+        ok: Option<bool>,
+        sum: impl Fn(&[u8]) -> u16,
+    ) -> &mut Check {
+        let (actual, ok) = match (bytes.get(pos..pos + 2), ok) {
+            (Some(x), Some(ok)) => (u16::from_be_bytes([x[0], x[1]]), ok),
+            _ => return self.skip(hdr, field, "truncated"),
+        };
+        let mut zeroed = bytes.to_vec();
+        zeroed[pos..pos + 2].fill(0);
+        let check = self.push(hdr, field, status(ok));
+        check.expected = Some(sum(&zeroed) as u64);
+        check.actual = Some(actual as u64);
+        check
+    }
+    fn skip(&mut self, hdr: (&str, usize), field: &'static str, why: &'static str) -> &mut Check {
+        let check = self.push(hdr, field, CheckStatus::Skipped);
+        check.note = Some(why);
+        check
+    }
+}
+
+impl core::fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let count = |s| self.checks.iter().filter(|x| x.status == s).count();
+        writeln!(
+            f,
+            "{} checks: {} ok, {} bad, {} skipped",
+            self.checks.len(),
+            count(CheckStatus::Ok),
+            count(CheckStatus::Bad),
+            count(CheckStatus::Skipped)
+        )?;
+        for check in &self.checks {
+            let status = match check.status {
+                CheckStatus::Ok => "ok",
+                CheckStatus::Bad => "BAD",
+                CheckStatus::Skipped => "skipped",
+            };
+            let layer = format!("{}@{}", check.layer, check.offset);
+            let value = |x: u64| match check.field {
+                "checksum" | "header_checksum" | "chksum" => format!("{:#06x}", x),
+                "fcs" => format!("{:#010x}", x),
+                _ => x.to_string(),
+            };
+            let mut detail = Vec::new();
+            match (check.status, check.expected, check.actual) {
+                (CheckStatus::Bad, Some(e), Some(a)) => {
+                    detail.push(format!("expected {}, found {}", value(e), value(a)))
+                }
+                (_, _, Some(a)) => detail.push(value(a)),
+                _ => (),
+            }
+            if let Some(note) = check.note {
+                detail.push(format!("({})", note));
+            }
+            let line = format!(
+                "  {:<8}{:<16}{:<16}{}",
+                status,
+                layer,
+                check.field,
+                detail.join(" ")
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl Packet {
+    /// Run every checksum and length check known for the layers of the packet
+    ///
+    /// The IP headers also have their version checked, and the IPv4 TTL must not be zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let mut pkt = create_udp_packet(&Default::default());
+    /// assert!(pkt.is_well_formed());
+    /// pkt.layer_mut::<UDP>().unwrap().set_length(10);
+    /// let report = pkt.verify();
+    /// let bad: Vec<_> = report.failures().map(|x| x.field).collect();
+    /// // the checksum covers the length
+    /// assert_eq!(bad, vec!["length", "checksum"]);
+    /// println!("{}", report);
+    /// ```
+    pub fn verify(&self) -> VerifyReport {
+        let v = self.to_vec();
+        verify_frame(self, &v[..v.len() - self.trailers_len()])
+    }
+    /// Run the checks of [`Packet::verify`] on a packet ending with the Ethernet FCS
+    ///
+    /// The FCS is the last 4 bytes before the trailers.
+    pub fn verify_with_fcs(&self) -> VerifyReport {
+        let v = self.to_vec();
+        let v = &v[..v.len() - self.trailers_len()];
+        let frame = &v[..v.len().saturating_sub(4)];
+        let mut report = verify_frame(self, frame);
+        let name = self.hdrs.first().map_or("Packet", |x| x.name());
+        match v.get(frame.len()..).filter(|x| x.len() == 4) {
+            Some(fcs) => {
+                let actual = u32::from_le_bytes([fcs[0], fcs[1], fcs[2], fcs[3]]);
+                let expected = crc32(frame);
+                let check = report.push((name, 0), "fcs", status(actual == expected));
+                check.expected = Some(expected as u64);
+                check.actual = Some(actual as u64);
+            }
+            None => {
+                report.skip((name, 0), "fcs", "truncated");
+            }
+        }
+        report
+    }
+    /// Return true if every check of [`Packet::verify`] passes
+    pub fn is_well_formed(&self) -> bool {
+        self.verify().is_well_formed()
+    }
+}
+
+fn status(ok: bool) -> CheckStatus {
+    match ok {
+        true => CheckStatus::Ok,
+        false => CheckStatus::Bad,
+    }
+}
+
+/// Run the checks on the bytes of pkt, v being cut before any trailer
+///
+/// The length and checksum checks take their outcome from [`Packet::diagnose`].
+fn verify_frame(pkt: &Packet, v: &[u8]) -> VerifyReport {
+    let mut report = VerifyReport::default();
+    let diagnosis = pkt.diagnose_frame(v);
+    let mut offset = 0;
+    // source, destination and end of the innermost ip header seen so far
+    let mut ip: Option<(Vec<u8>, Vec<u8>, usize)> = None;
+    for (i, (hdr, diag)) in pkt.hdrs.iter().zip(&diagnosis).enumerate() {
+        let start = offset.min(v.len());
+        let end = ip.as_ref().map_or(v.len(), |x| x.2).clamp(start, v.len());
+        let rest = &v[start..];
+        let segment = &v[start..end];
+        let next = pkt.hdrs.get(i + 1).map(|_| offset + hdr.len());
+        let at = (hdr.name(), offset);
+        let length_ok = diag.length_ok.unwrap_or(false);
+        let pseudo = |proto: IpProtocol| {
+            let (src, dst, _) = ip.clone().unwrap();
+            let proto = proto as u8;
+            move |x: &[u8]| Packet::l4_checksum(&src, &dst, proto, x)
+        };
+        let sum_field = hdr.checksum_field().map(|x| (x.name, x.lsb / 8));
+        match hdr.as_any() {
+            x if x.is::<IPv4>() => {
+                let ipv4 = x.downcast_ref::<IPv4>().unwrap();
+                let total = ipv4.total_len() as usize;
+                let ihl = ipv4.ihl() as usize;
+                let version = ipv4.version();
+                report.value(at, "version", version, Some(4), version == 4);
+                report.value(
+                    at,
+                    "total_len",
+                    total as u64,
+                    Some(rest.len() as u64),
+                    length_ok,
+                );
+                match next {
+                    // the parser places the layers right after the fixed header
+                    Some(next) => {
+                        let words = (next - offset) as u64 / 4;
+                        report.value(at, "ihl", ihl as u64, Some(words), ihl as u64 == words);
+                    }
+                    None => {
+                        let ok = ihl >= 5 && ihl * 4 <= total.min(rest.len());
+                        let check = report.value(at, "ihl", ihl as u64, None, ok);
+                        check.note = Some("5 words up to the total length");
+                    }
+                }
+                let ttl = ipv4.ttl();
+                let check = report.value(at, "ttl", ttl, None, ttl > 0);
+                check.note = Some("1 up to 255");
+                let header = &rest[..(ihl * 4).min(rest.len())];
+                let ok = diag.checksum_ok;
+                report.checksum(at, "header_checksum", (header, 10), ok, inet_checksum);
+                ip = Some((
+                    ipv4.bytes(IPv4::src_msb(), IPv4::src_lsb()),
+                    ipv4.bytes(IPv4::dst_msb(), IPv4::dst_lsb()),
+                    offset + total,
+                ));
+            }
+            x if x.is::<IPv6>() => {
+                let ipv6 = x.downcast_ref::<IPv6>().unwrap();
+                let payload = ipv6.payload_len() as usize;
+                let version = ipv6.version();
+                report.value(at, "version", version, Some(6), version == 6);
+                let available = rest.len().saturating_sub(IPv6::size());
+                report.value(
+                    at,
+                    "payload_len",
+                    payload as u64,
+                    Some(available as u64),
+                    length_ok,
+                );
+                ip = Some((
+                    ipv6.bytes(IPv6::src_msb(), IPv6::src_lsb()),
+                    ipv6.bytes(IPv6::dst_msb(), IPv6::dst_lsb()),
+                    offset + IPv6::size() + payload,
+                ));
+            }
+            x if x.is::<UDP>() => {
+                let len = x.downcast_ref::<UDP>().unwrap().length();
+                report.value(at, "length", len, Some(segment.len() as u64), length_ok);
+                match (ip.as_ref(), diag.checksum_ok) {
+                    (None, _) => {
+                        report.skip(at, "checksum", "no IP layer");
+                    }
+                    (Some(_), None) => {
+                        report.skip(at, "checksum", "disabled");
+                    }
+                    (Some(_), ok) => {
+                        let sum = pseudo(IpProtocol::UDP);
+                        let check = report.checksum(at, "checksum", (segment, 6), ok, sum);
+                        check.expected = check.expected.map(|x| nonzero(x as u16) as u64);
+                    }
+                }
+            }
+            x if x.is::<UdpLite>() => {
+                let coverage = x.downcast_ref::<UdpLite>().unwrap().coverage();
+                let check = report.value(at, "coverage", coverage, None, length_ok);
+                check.note = Some("0 or 8 up to the segment length");
+                match ip.clone() {
+                    None => {
+                        report.skip(at, "checksum", "no IP layer");
+                    }
+                    Some((src, dst, _)) => {
+                        let sum = |x: &[u8]| udplite_checksum(&src, &dst, x);
+                        let ok = diag.checksum_ok;
+                        let check = report.checksum(at, "checksum", (segment, 6), ok, sum);
+                        check.expected = check.expected.map(|x| nonzero(x as u16) as u64);
+                    }
+                }
+            }
+            x if x.is::<TCP>() => {
+                let data_offset = x.downcast_ref::<TCP>().unwrap().data_offset();
+                let check = report.value(at, "data_offset", data_offset, None, length_ok);
+                check.note = Some("5 words up to the segment length");
+                match ip.as_ref() {
+                    None => report.skip(at, "checksum", "no IP layer"),
+                    Some(_) => {
+                        let sum = pseudo(IpProtocol::TCP);
+                        report.checksum(at, "checksum", (segment, 16), diag.checksum_ok, sum)
+                    }
+                };
+            }
+            x if x.is::<ICMP>() || x.is::<Igmp>() || x.is::<IgmpV3Report>() => {
+                let (name, pos) = sum_field.unwrap();
+                let ok = diag.checksum_ok;
+                match ip.as_ref() {
+                    Some((src, _, _)) if src.len() == 16 => {
+                        let sum = pseudo(IpProtocol::ICMPV6);
+                        report.checksum(at, name, (segment, pos), ok, sum)
+                    }
+                    _ => report.checksum(at, name, (segment, pos), ok, inet_checksum),
+                };
+            }
+            x if x.is::<Mld>() || x.is::<MldV2Report>() => {
+                let (name, pos) = sum_field.unwrap();
+                match ip.as_ref() {
+                    None => report.skip(at, name, "no IP layer"),
+                    Some(_) => {
+                        let sum = pseudo(IpProtocol::ICMPV6);
+                        report.checksum(at, name, (segment, pos), diag.checksum_ok, sum)
+                    }
+                };
+            }
+            x if x.is::<GREChksumOffset>() => {
+                // the checksum covers the GRE header before it and the rest of the IP payload
+                let gre = i.checked_sub(1).map(|x| pkt.hdrs[x].as_ref());
+                match gre.filter(|x| x.get_field("chksum_present") == Some(1)) {
+                    Some(gre) => {
+                        let gre_start = (offset - gre.len()).min(v.len());
+                        let bytes = &v[gre_start..end.max(gre_start)];
+                        let ok = Some(inet_checksum(bytes) == 0);
+                        report.checksum(at, "chksum", (bytes, gre.len()), ok, inet_checksum);
+                    }
+                    None => {
+                        report.skip(at, "chksum", "checksum not present");
+                    }
+                }
+            }
+            x if x.is::<Vxlan>() => {
+                let flags = x.downcast_ref::<Vxlan>().unwrap().flags();
+                report.value(
+                    at,
+                    "flags",
+                    flags,
+                    Some(VXLAN_FLAG_I),
+                    flags == VXLAN_FLAG_I,
+                );
+            }
+            _ => (),
+        }
+        offset += hdr.len();
+    }
+    report
+}
+
+/// The declared length must match the available bytes, unless the frame was padded
+fn length_matches(declared: usize, available: usize, frame_len: usize) -> bool {
+    declared == available || (declared < available && frame_len <= ETHER_MIN_LEN)
+}
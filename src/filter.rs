@@ -0,0 +1,830 @@
+//! # Capture filters
+//!
+//! [`Filter`] compiles a subset of the tcpdump filter language into a predicate on ethernet
+//! frames, so expressions copied from a runbook select the same packets from a
+//! [`PcapReader`](crate::pcap::PcapReader) or an interface as they would with tcpdump.
+//!
+//! The supported primitives are
+//!  * `[ip|ip6|arp|rarp] [src|dst] host ADDR`, `[src|dst] net ADDR/LEN`, `net ADDR mask MASK`
+//!  * `[tcp|udp|sctp] [src|dst] port N`, `portrange N-M`
+//!  * `ether [src|dst] host MAC`, `ether proto N`, `[ether] broadcast`,
+//!    `[ether|ip|ip6] multicast`
+//!  * `ip`, `ip6`, `arp`, `rarp`, `tcp`, `udp`, `sctp`, `icmp`, `icmp6`, `[ip|ip6] proto N`
+//!  * `vlan [ID]`, `greater N`, `less N`
+//!
+//! combined with `and`, `or`, `not`, `&&`, `||`, `!` and parentheses. As in tcpdump, `src or dst`
+//! and `src and dst` are accepted as directions, a bare value repeats the qualifiers of the
+//! primitive before it, so `port 80 or 443` selects both ports, and every `vlan` keyword shifts
+//! the offsets of the primitives written after it by one VLAN tag. `ip` alone does not match a
+//! tagged frame, `vlan and ip` does. Fields are read at the offsets tcpdump reads them, and a
+//! frame too short for one of the fields read is rejected as a whole.
+//!
+//! Host names, service names and arithmetic expressions such as `tcp[13] & 2 != 0` are not
+//! supported and fail to compile.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::filter::Filter;
+//! let pkt = create_udp_packet(&Default::default());
+//! let filter = Filter::compile("udp port 4789 or 80 and host 192.168.0.1").unwrap();
+//! assert!(filter.matches(&pkt));
+//! assert!(!Filter::compile("vlan and udp").unwrap().matches(&pkt));
+//! assert!(Filter::compile("tcp[13] & 2 != 0").is_err());
+//! ```
+
+use core::net::{Ipv4Addr, Ipv6Addr};
+use core::str::FromStr;
+
+use crate::addr::MacAddr;
+use crate::alloc_prelude::*;
+use crate::{Packet, PacketError};
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_RARP: u16 = 0x8035;
+/// Ethertypes tcpdump accepts as a VLAN tag
+const ETHERTYPE_VLAN: [u16; 3] = [0x8100, 0x88a8, 0x9100];
+const ETHER_LEN: usize = 14;
+const VLAN_LEN: usize = 4;
+const IPV6_LEN: usize = 40;
+const IPPROTO_FRAGMENT: u8 = 44;
+
+/// A compiled capture filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    text: String,
+    expr: Option<Expr>,
+}
+
+impl Filter {
+    /// Compile a tcpdump filter expression, an empty expression matches every frame
+    ///
+    /// Fails with [`PacketError::InvalidFilter`] at the byte position of the first token that
+    /// cannot be compiled.
+    pub fn compile(text: &str) -> Result<Filter, PacketError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: text.len(),
+            vlans: 0,
+            last: None,
+        };
+        let expr = match parser.tokens.is_empty() {
+            true => None,
+            false => Some(parser.parse_expr()?),
+        };
+        if let Some(tok) = parser.tokens.get(parser.pos) {
+            return Err(invalid(tok.pos, "expected and or or"));
+        }
+        Ok(Filter {
+            text: String::from(text),
+            expr,
+        })
+    }
+    /// Return the expression the filter was compiled from
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+    /// Return true if the packet is selected by the filter
+    pub fn matches(&self, pkt: &Packet) -> bool {
+        self.matches_bytes(&pkt.to_vec())
+    }
+    /// Return true if the ethernet frame is selected by the filter
+    pub fn matches_bytes(&self, frame: &[u8]) -> bool {
+        match &self.expr {
+            Some(expr) => expr.eval(frame).unwrap_or(false),
+            None => true,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = PacketError;
+
+    fn from_str(s: &str) -> Result<Filter, PacketError> {
+        Filter::compile(s)
+    }
+}
+
+fn invalid(position: usize, reason: &str) -> PacketError {
+    PacketError::InvalidFilter {
+        position,
+        reason: String::from(reason),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dir {
+    SrcOrDst,
+    SrcAndDst,
+    Src,
+    Dst,
+}
+
+impl Dir {
+    /// Combine the checks of the source and destination fields
+    fn eval(
+        self,
+        src: impl FnOnce() -> Option<bool>,
+        dst: impl FnOnce() -> Option<bool>,
+    ) -> Option<bool> {
+        Some(match self {
+            Dir::Src => src()?,
+            Dir::Dst => dst()?,
+            Dir::SrcOrDst => src()? || dst()?,
+            Dir::SrcAndDst => src()? && dst()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Proto {
+    Ether,
+    Ip,
+    Ip6,
+    Arp,
+    Rarp,
+    Tcp,
+    Udp,
+    Sctp,
+    Icmp,
+    Icmp6,
+}
+
+impl Proto {
+    fn from_word(word: &str) -> Option<Proto> {
+        Some(match word {
+            "ether" => Proto::Ether,
+            "ip" => Proto::Ip,
+            "ip6" => Proto::Ip6,
+            "arp" => Proto::Arp,
+            "rarp" => Proto::Rarp,
+            "tcp" => Proto::Tcp,
+            "udp" => Proto::Udp,
+            "sctp" => Proto::Sctp,
+            "icmp" => Proto::Icmp,
+            "icmp6" => Proto::Icmp6,
+            _ => return None,
+        })
+    }
+    /// IP protocol number of a transport protocol
+    fn ip_proto(self) -> Option<u8> {
+        match self {
+            Proto::Tcp => Some(6),
+            Proto::Udp => Some(17),
+            Proto::Sctp => Some(132),
+            Proto::Icmp => Some(1),
+            Proto::Icmp6 => Some(58),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Host,
+    Net,
+    Port,
+    PortRange,
+    Proto,
+}
+
+/// The qualifiers of a primitive, repeated for a bare value that follows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Quals {
+    proto: Option<Proto>,
+    dir: Option<Dir>,
+    kind: Option<Kind>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Addr {
+    V4 { addr: [u8; 4], mask: [u8; 4] },
+    V6 { addr: [u8; 16], mask: [u8; 16] },
+}
+
+impl Addr {
+    fn matches(&self, frame: &[u8], off: usize) -> Option<bool> {
+        let (addr, mask): (&[u8], &[u8]) = match self {
+            Addr::V4 { addr, mask } => (addr, mask),
+            Addr::V6 { addr, mask } => (addr, mask),
+        };
+        let field = frame.get(off..off + addr.len())?;
+        Some(
+            field
+                .iter()
+                .zip(mask)
+                .map(|(b, m)| b & m)
+                .eq(addr.iter().copied()),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Prim {
+    /// Ethertype after `vlans` tags
+    EtherType(u16),
+    /// A VLAN tag after `vlans` tags, with this VID if given
+    Vlan(Option<u16>),
+    EtherAddr(Dir, [u8; 6]),
+    EtherBroadcast,
+    EtherMulticast,
+    IpProto(u8),
+    Ip6Proto(u8),
+    /// Host or network, restricted to a protocol of the network layer if given
+    Addr(Option<Proto>, Dir, Addr),
+    /// Port range of any of the IP protocols, restricted to IPv4 or IPv6 if given
+    Port(Option<Proto>, Vec<u8>, Dir, u16, u16),
+    IpMulticast,
+    Ip6Multicast,
+    Greater(usize),
+    Less(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// A primitive and the number of VLAN tags it skips
+    Prim(Prim, usize),
+}
+
+fn read_u16(frame: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*frame.get(off)?, *frame.get(off + 1)?]))
+}
+
+impl Expr {
+    /// Evaluate the expression, None if a field read is past the end of the frame
+    fn eval(&self, frame: &[u8]) -> Option<bool> {
+        match self {
+            Expr::And(a, b) => Some(a.eval(frame)? && b.eval(frame)?),
+            Expr::Or(a, b) => Some(a.eval(frame)? || b.eval(frame)?),
+            Expr::Not(a) => Some(!a.eval(frame)?),
+            Expr::Prim(prim, vlans) => prim.eval(frame, *vlans),
+        }
+    }
+}
+
+impl Prim {
+    fn eval(&self, frame: &[u8], vlans: usize) -> Option<bool> {
+        let etype_off = ETHER_LEN - 2 + vlans * VLAN_LEN;
+        let nl = ETHER_LEN + vlans * VLAN_LEN;
+        let etype = || read_u16(frame, etype_off);
+        match self {
+            Prim::EtherType(t) => Some(etype()? == *t),
+            Prim::Vlan(vid) => {
+                if !ETHERTYPE_VLAN.contains(&etype()?) {
+                    return Some(false);
+                }
+                match vid {
+                    Some(vid) => Some(read_u16(frame, nl)? & 0xfff == *vid),
+                    None => Some(true),
+                }
+            }
+            Prim::EtherAddr(dir, mac) => dir.eval(
+                || Some(frame.get(6..12)? == mac),
+                || Some(frame.get(0..6)? == mac),
+            ),
+            Prim::EtherBroadcast => Some(frame.get(0..6)?.iter().all(|x| *x == 0xff)),
+            Prim::EtherMulticast => Some(frame.first()? & 1 == 1),
+            Prim::IpProto(p) => Some(etype()? == ETHERTYPE_IPV4 && *frame.get(nl + 9)? == *p),
+            Prim::Ip6Proto(p) => {
+                if etype()? != ETHERTYPE_IPV6 {
+                    return Some(false);
+                }
+                let next = *frame.get(nl + 6)?;
+                Some(next == *p || (next == IPPROTO_FRAGMENT && *frame.get(nl + IPV6_LEN)? == *p))
+            }
+            Prim::Addr(proto, dir, addr) => {
+                let t = etype()?;
+                // offsets of the source and destination addresses in the network header
+                let (src, dst) = match (addr, t) {
+                    (Addr::V4 { .. }, ETHERTYPE_IPV4) => (12, 16),
+                    (Addr::V4 { .. }, ETHERTYPE_ARP | ETHERTYPE_RARP) => (14, 24),
+                    (Addr::V6 { .. }, ETHERTYPE_IPV6) => (8, 24),
+                    _ => return Some(false),
+                };
+                let allowed = match proto {
+                    None => true,
+                    Some(Proto::Ip) => t == ETHERTYPE_IPV4,
+                    Some(Proto::Ip6) => t == ETHERTYPE_IPV6,
+                    Some(Proto::Arp) => t == ETHERTYPE_ARP,
+                    Some(Proto::Rarp) => t == ETHERTYPE_RARP,
+                    Some(_) => false,
+                };
+                if !allowed {
+                    return Some(false);
+                }
+                dir.eval(
+                    || addr.matches(frame, nl + src),
+                    || addr.matches(frame, nl + dst),
+                )
+            }
+            Prim::Port(family, protos, dir, lo, hi) => {
+                let tl = match etype()? {
+                    ETHERTYPE_IPV4 if *family != Some(Proto::Ip6) => {
+                        let proto = *frame.get(nl + 9)?;
+                        // only the first fragment holds the ports
+                        if !protos.contains(&proto) || read_u16(frame, nl + 6)? & 0x1fff != 0 {
+                            return Some(false);
+                        }
+                        nl + (*frame.get(nl)? as usize & 0xf) * 4
+                    }
+                    ETHERTYPE_IPV6 if *family != Some(Proto::Ip) => {
+                        if !protos.contains(frame.get(nl + 6)?) {
+                            return Some(false);
+                        }
+                        nl + IPV6_LEN
+                    }
+                    _ => return Some(false),
+                };
+                let in_range = |port: u16| (*lo..=*hi).contains(&port);
+                dir.eval(
+                    || Some(in_range(read_u16(frame, tl)?)),
+                    || Some(in_range(read_u16(frame, tl + 2)?)),
+                )
+            }
+            Prim::IpMulticast => Some(etype()? == ETHERTYPE_IPV4 && *frame.get(nl + 16)? >= 224),
+            Prim::Ip6Multicast => Some(etype()? == ETHERTYPE_IPV6 && *frame.get(nl + 24)? == 0xff),
+            Prim::Greater(n) => Some(frame.len() >= *n),
+            Prim::Less(n) => Some(frame.len() <= *n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    tok: Tok,
+    pos: usize,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PacketError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let tok = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Tok::Open,
+            ')' => Tok::Close,
+            '!' if chars.peek().map(|x| x.1) != Some('=') => Tok::Not,
+            '&' if chars.next_if(|x| x.1 == '&').is_some() => Tok::And,
+            '|' if chars.next_if(|x| x.1 == '|').is_some() => Tok::Or,
+            c if is_word_char(c) => {
+                let mut end = pos + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|x| is_word_char(x.1)) {
+                    end = i + c.len_utf8();
+                }
+                match &text[pos..end] {
+                    "and" => Tok::And,
+                    "or" => Tok::Or,
+                    "not" => Tok::Not,
+                    x => Tok::Word(String::from(x)),
+                }
+            }
+            _ => return Err(invalid(pos, "unsupported syntax")),
+        };
+        tokens.push(Token { tok, pos });
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '/' | '-' | '_' | '\\')
+}
+
+const KEYWORDS: &[&str] = &[
+    "ether",
+    "ip",
+    "ip6",
+    "arp",
+    "rarp",
+    "tcp",
+    "udp",
+    "sctp",
+    "icmp",
+    "icmp6",
+    "src",
+    "dst",
+    "host",
+    "net",
+    "mask",
+    "port",
+    "portrange",
+    "proto",
+    "vlan",
+    "broadcast",
+    "multicast",
+    "greater",
+    "less",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Position of the end of the expression, for errors at the end
+    end: usize,
+    /// Number of `vlan` keywords parsed so far
+    vlans: usize,
+    /// Qualifiers of the last primitive
+    last: Option<Quals>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|x| &x.tok)
+    }
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Tok::Word(w)) => Some(w),
+            _ => None,
+        }
+    }
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |x| x.pos)
+    }
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = self.peek_word() == Some(word);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    /// Take the next token if it is a value rather than a keyword
+    fn value(&mut self) -> Option<(String, usize)> {
+        let w = self.peek_word().filter(|x| !KEYWORDS.contains(x))?;
+        let value = (String::from(w), self.position());
+        self.pos += 1;
+        Some(value)
+    }
+    fn expect_value(&mut self) -> Result<(String, usize), PacketError> {
+        self.value()
+            .ok_or_else(|| invalid(self.position(), "expected a value"))
+    }
+    /// Parse primitives joined by `and` and `or`, which have the same precedence in tcpdump and
+    /// associate to the left
+    fn parse_expr(&mut self) -> Result<Expr, PacketError> {
+        let mut expr = self.parse_not()?;
+        loop {
+            expr = match self.peek() {
+                Some(Tok::And) => {
+                    self.pos += 1;
+                    Expr::And(Box::new(expr), Box::new(self.parse_not()?))
+                }
+                Some(Tok::Or) => {
+                    self.pos += 1;
+                    Expr::Or(Box::new(expr), Box::new(self.parse_not()?))
+                }
+                _ => return Ok(expr),
+            };
+        }
+    }
+    fn parse_not(&mut self) -> Result<Expr, PacketError> {
+        match self.peek() {
+            Some(Tok::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_not()?)))
+            }
+            Some(Tok::Open) => {
+                self.pos += 1;
+                let expr = self.parse_expr()?;
+                if self.peek() != Some(&Tok::Close) {
+                    return Err(invalid(self.position(), "expected )"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Tok::Word(_)) => self.parse_primitive(),
+            _ => Err(invalid(self.position(), "expected a primitive")),
+        }
+    }
+    fn parse_primitive(&mut self) -> Result<Expr, PacketError> {
+        let start = self.position();
+        let vlans = self.vlans;
+        let prim = |prim| Ok::<Expr, PacketError>(Expr::Prim(prim, vlans));
+        if self.eat_word("vlan") {
+            let vid = match self.value() {
+                Some((v, pos)) => Some(
+                    parse_num(&v)
+                        .filter(|x| *x < 4096)
+                        .ok_or_else(|| invalid(pos, "invalid VLAN id"))? as u16,
+                ),
+                None => None,
+            };
+            self.vlans += 1;
+            self.last = None;
+            return prim(Prim::Vlan(vid));
+        }
+        for (word, less) in [("greater", false), ("less", true)] {
+            if self.eat_word(word) {
+                let (v, pos) = self.expect_value()?;
+                let n = parse_num(&v).ok_or_else(|| invalid(pos, "invalid length"))? as usize;
+                self.last = None;
+                return prim(if less {
+                    Prim::Less(n)
+                } else {
+                    Prim::Greater(n)
+                });
+            }
+        }
+        let mut quals = Quals {
+            proto: None,
+            dir: None,
+            kind: None,
+        };
+        if let Some(proto) = self.peek_word().and_then(Proto::from_word) {
+            self.pos += 1;
+            quals.proto = Some(proto);
+        }
+        if self.eat_word("src") {
+            quals.dir = Some(self.parse_dir_pair("dst", Dir::Src));
+        } else if self.eat_word("dst") {
+            quals.dir = Some(self.parse_dir_pair("src", Dir::Dst));
+        }
+        for (word, kind) in [
+            ("host", Kind::Host),
+            ("net", Kind::Net),
+            ("portrange", Kind::PortRange),
+            ("port", Kind::Port),
+            ("proto", Kind::Proto),
+        ] {
+            if self.eat_word(word) {
+                quals.kind = Some(kind);
+                break;
+            }
+        }
+        if quals.kind.is_none() && quals.dir.is_none() {
+            if self.eat_word("broadcast") {
+                return match quals.proto {
+                    None | Some(Proto::Ether) => prim(Prim::EtherBroadcast),
+                    _ => Err(invalid(start, "only ether broadcast is supported")),
+                };
+            }
+            if self.eat_word("multicast") {
+                return match quals.proto {
+                    None | Some(Proto::Ether) => prim(Prim::EtherMulticast),
+                    Some(Proto::Ip) => prim(Prim::IpMulticast),
+                    Some(Proto::Ip6) => prim(Prim::Ip6Multicast),
+                    _ => Err(invalid(
+                        start,
+                        "multicast of this protocol is not supported",
+                    )),
+                };
+            }
+        }
+        if quals
+            == (Quals {
+                proto: None,
+                dir: None,
+                kind: None,
+            })
+        {
+            // a bare value repeats the qualifiers of the primitive before it
+            let (value, pos) = self.expect_value()?;
+            let quals = self.last.unwrap_or(Quals {
+                proto: None,
+                dir: None,
+                kind: Some(Kind::Host),
+            });
+            return self.qualified(quals, &value, pos, vlans);
+        }
+        if quals.dir.is_none() && quals.kind.is_none() {
+            // a protocol alone, unless a value follows as in `ether 00:01:02:03:04:05`
+            if self.peek_word().is_some_and(|x| !KEYWORDS.contains(&x)) {
+                let (value, pos) = self.expect_value()?;
+                return self.qualified(quals, &value, pos, vlans);
+            }
+            self.last = None;
+            return self.protocol(quals.proto.unwrap(), start, vlans);
+        }
+        let (value, pos) = self.expect_value()?;
+        self.qualified(quals, &value, pos, vlans)
+    }
+    /// Parse the rest of `src or dst` and `src and dst` after the first direction
+    fn parse_dir_pair(&mut self, other: &str, dir: Dir) -> Dir {
+        let pair = match self.peek() {
+            Some(Tok::Or) => Dir::SrcOrDst,
+            Some(Tok::And) => Dir::SrcAndDst,
+            _ => return dir,
+        };
+        match self.tokens.get(self.pos + 1).map(|x| &x.tok) {
+            Some(Tok::Word(w)) if w == other => {
+                self.pos += 2;
+                pair
+            }
+            _ => dir,
+        }
+    }
+    /// The primitive of a protocol name alone
+    fn protocol(&self, proto: Proto, pos: usize, vlans: usize) -> Result<Expr, PacketError> {
+        let prim = match proto {
+            Proto::Ether => return Err(invalid(pos, "expected a qualifier after ether")),
+            Proto::Ip => Prim::EtherType(ETHERTYPE_IPV4),
+            Proto::Ip6 => Prim::EtherType(ETHERTYPE_IPV6),
+            Proto::Arp => Prim::EtherType(ETHERTYPE_ARP),
+            Proto::Rarp => Prim::EtherType(ETHERTYPE_RARP),
+            Proto::Icmp => Prim::IpProto(1),
+            Proto::Icmp6 => Prim::Ip6Proto(58),
+            Proto::Tcp | Proto::Udp | Proto::Sctp => {
+                return Ok(ip_or_ip6_proto(proto.ip_proto().unwrap(), vlans))
+            }
+        };
+        Ok(Expr::Prim(prim, vlans))
+    }
+    /// The primitive of a value and its qualifiers
+    fn qualified(
+        &mut self,
+        quals: Quals,
+        value: &str,
+        pos: usize,
+        vlans: usize,
+    ) -> Result<Expr, PacketError> {
+        let kind = quals.kind.unwrap_or(Kind::Host);
+        let dir = quals.dir.unwrap_or(Dir::SrcOrDst);
+        self.last = Some(Quals {
+            kind: Some(kind),
+            ..quals
+        });
+        let prim = match (kind, quals.proto) {
+            (Kind::Host, Some(Proto::Ether)) => {
+                let mac =
+                    MacAddr::from_str(value).map_err(|_| invalid(pos, "expected a MAC address"))?;
+                Prim::EtherAddr(dir, mac.0)
+            }
+            (
+                Kind::Host | Kind::Net,
+                None | Some(Proto::Ip | Proto::Ip6 | Proto::Arp | Proto::Rarp),
+            ) => {
+                let addr = match kind {
+                    Kind::Host => parse_host(value, pos)?,
+                    _ => self.parse_net(value, pos)?,
+                };
+                if matches!(addr, Addr::V6 { .. })
+                    && matches!(quals.proto, Some(Proto::Ip | Proto::Arp | Proto::Rarp))
+                    || matches!(addr, Addr::V4 { .. }) && quals.proto == Some(Proto::Ip6)
+                {
+                    return Err(invalid(pos, "address does not match the protocol"));
+                }
+                Prim::Addr(quals.proto, dir, addr)
+            }
+            (Kind::Port | Kind::PortRange, proto) => {
+                let (family, protos) = match proto {
+                    None | Some(Proto::Ip | Proto::Ip6) => (proto, vec![6, 17, 132]),
+                    Some(p @ (Proto::Tcp | Proto::Udp | Proto::Sctp)) => {
+                        (None, vec![p.ip_proto().unwrap()])
+                    }
+                    _ => return Err(invalid(pos, "ports need tcp, udp or sctp")),
+                };
+                let port = |x: &str| match parse_num(x) {
+                    Some(n) if n <= u16::MAX as u64 => Ok(n as u16),
+                    Some(_) => Err(invalid(pos, "invalid port")),
+                    None => Err(invalid(pos, "service names are not supported")),
+                };
+                let (lo, hi) = match kind {
+                    Kind::Port => (port(value)?, port(value)?),
+                    _ => {
+                        let (a, b) = value
+                            .split_once('-')
+                            .ok_or_else(|| invalid(pos, "expected a port range"))?;
+                        let (a, b) = (port(a)?, port(b)?);
+                        (a.min(b), a.max(b))
+                    }
+                };
+                Prim::Port(family, protos, dir, lo, hi)
+            }
+            (Kind::Proto, proto) if quals.dir.is_none() => {
+                let n = match value.strip_prefix('\\').unwrap_or(value) {
+                    "ip" if proto == Some(Proto::Ether) => ETHERTYPE_IPV4 as u64,
+                    "ip6" if proto == Some(Proto::Ether) => ETHERTYPE_IPV6 as u64,
+                    "arp" if proto == Some(Proto::Ether) => ETHERTYPE_ARP as u64,
+                    "rarp" if proto == Some(Proto::Ether) => ETHERTYPE_RARP as u64,
+                    x => match Proto::from_word(x).and_then(Proto::ip_proto) {
+                        Some(p) if proto != Some(Proto::Ether) => p as u64,
+                        _ => parse_num(x).ok_or_else(|| invalid(pos, "invalid protocol"))?,
+                    },
+                };
+                let too_large = || invalid(pos, "invalid protocol");
+                match proto {
+                    Some(Proto::Ether) => {
+                        Prim::EtherType(u16::try_from(n).map_err(|_| too_large())?)
+                    }
+                    Some(Proto::Ip) => Prim::IpProto(u8::try_from(n).map_err(|_| too_large())?),
+                    Some(Proto::Ip6) => Prim::Ip6Proto(u8::try_from(n).map_err(|_| too_large())?),
+                    None => {
+                        let n = u8::try_from(n).map_err(|_| too_large())?;
+                        return Ok(ip_or_ip6_proto(n, vlans));
+                    }
+                    _ => return Err(invalid(pos, "proto needs ether, ip or ip6")),
+                }
+            }
+            _ => return Err(invalid(pos, "unsupported combination of qualifiers")),
+        };
+        Ok(Expr::Prim(prim, vlans))
+    }
+    /// Parse a network, `ADDR/LEN`, a dotted prefix such as `10.1` or `ADDR mask MASK`
+    fn parse_net(&mut self, value: &str, pos: usize) -> Result<Addr, PacketError> {
+        let bad = || invalid(pos, "expected a network");
+        if let Some((addr, len)) = value.split_once('/') {
+            let len = parse_num(len).ok_or_else(bad)? as usize;
+            return match parse_host(addr, pos)? {
+                Addr::V4 { addr, .. } if len <= 32 => {
+                    let mask = prefix_mask::<4>(len);
+                    Ok(Addr::V4 {
+                        addr: core::array::from_fn(|i| addr[i] & mask[i]),
+                        mask,
+                    })
+                }
+                Addr::V6 { addr, .. } if len <= 128 => {
+                    let mask = prefix_mask::<16>(len);
+                    Ok(Addr::V6 {
+                        addr: core::array::from_fn(|i| addr[i] & mask[i]),
+                        mask,
+                    })
+                }
+                _ => Err(bad()),
+            };
+        }
+        if self.eat_word("mask") {
+            let (mask, mask_pos) = self.expect_value()?;
+            let mask = Ipv4Addr::from_str(&mask)
+                .map_err(|_| invalid(mask_pos, "expected an IPv4 mask"))?
+                .octets();
+            let addr = Ipv4Addr::from_str(value).map_err(|_| bad())?.octets();
+            return Ok(Addr::V4 {
+                addr: core::array::from_fn(|i| addr[i] & mask[i]),
+                mask,
+            });
+        }
+        if value.contains(':') {
+            return Err(invalid(pos, "IPv6 networks need a prefix length"));
+        }
+        // 10 is 10.0.0.0/8, 10.1 is 10.1.0.0/16 and so on
+        let mut addr = [0u8; 4];
+        let mut n = 0;
+        for part in value.split('.') {
+            if n == 4 {
+                return Err(bad());
+            }
+            addr[n] = part.parse().map_err(|_| bad())?;
+            n += 1;
+        }
+        Ok(Addr::V4 {
+            addr,
+            mask: prefix_mask::<4>(n * 8),
+        })
+    }
+}
+
+fn ip_or_ip6_proto(proto: u8, vlans: usize) -> Expr {
+    Expr::Or(
+        Box::new(Expr::Prim(Prim::IpProto(proto), vlans)),
+        Box::new(Expr::Prim(Prim::Ip6Proto(proto), vlans)),
+    )
+}
+
+fn parse_host(value: &str, pos: usize) -> Result<Addr, PacketError> {
+    if let Ok(ip) = Ipv4Addr::from_str(value) {
+        return Ok(Addr::V4 {
+            addr: ip.octets(),
+            mask: [0xff; 4],
+        });
+    }
+    if let Ok(ip) = Ipv6Addr::from_str(value) {
+        return Ok(Addr::V6 {
+            addr: ip.octets(),
+            mask: [0xff; 16],
+        });
+    }
+    match value.chars().any(|c| c.is_ascii_alphabetic()) && !value.contains(':') {
+        true => Err(invalid(pos, "host names are not supported")),
+        false => Err(invalid(pos, "expected an address")),
+    }
+}
+
+fn prefix_mask<const N: usize>(len: usize) -> [u8; N] {
+    core::array::from_fn(|i| match len.saturating_sub(i * 8) {
+        0 => 0,
+        n if n >= 8 => 0xff,
+        n => 0xff << (8 - n),
+    })
+}
+
+/// Parse a decimal or 0x prefixed hexadecimal number
+fn parse_num(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -0,0 +1,186 @@
+//! # Packet fixtures
+//!
+//! With the `serde` feature, [`Packet::from_value`] builds a packet from a fixture, a list of
+//! layers each written as a map of the header name to its fields:
+//!
+//! ```yaml
+//! - Ethernet: {dst: "ff:ff:ff:ff:ff:ff", etype: 0x0806}
+//! - ARP: {opcode: 1, sender_proto_addr: 10.0.0.1, target_proto_addr: 10.0.0.2}
+//! - payload: {pattern: repeat, byte: 0, len: 18}
+//! ```
+//!
+//! The header names are resolved as in the packet specs, `Ethernet` and `IP` included, and
+//! missing fields keep their defaults. A field is a number, a string parsed as a number, an
+//! address or TCP flag letters, or a boolean for a one bit field. A layer with no fields may be
+//! written as its name alone.
+//!
+//! The `payload` entry is a `0x` hex string, any other string as ascii, or a pattern of the
+//! [`payload`](crate::payload) module: `{pattern: incrementing, len: 32}`, `{pattern: repeat,
+//! byte: 0xaa, len: 32}`, `{pattern: random, seed: 7, len: 32}` or `{pattern: ascii, len: 32}`.
+//! The packet is completed by [`Packet::fixup`] unless the fixture has a `fixup: false` entry.
+//! The fixups leave the fields the fixture sets as they are, so a fixture may give a length or a
+//! checksum of its own and still have the others computed.
+//! A fixture may also be a map of `layers`, `payload` and `fixup`, the form TOML documents take.
+//!
+//! Any value implementing `Serialize` is accepted, so YAML and TOML fixtures are loaded with
+//! their own crates, as `serde_yaml::Value` or `toml::Value`. A failing entry is reported by
+//! [`PacketError::InvalidFixture`] with its path, as `[1].ARP.opcode`. The JSON, YAML and TOML
+//! fixtures of the `tests/fixtures` directory of the repository show each form.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! # use packet_rs::Packet;
+//! let fixture = serde_json::json!([
+//!     {"Ethernet": {"dst": "ff:ff:ff:ff:ff:ff"}},
+//!     {"IP": {"src": "10.0.0.1", "dst": "10.0.0.2"}},
+//!     {"UDP": {"dport": 53}},
+//!     {"payload": "query"},
+//! ]);
+//! let pkt = Packet::from_value(&fixture).unwrap();
+//! assert_eq!(pkt.layer::<IPv4>().unwrap().total_len(), 33);
+//! assert!(pkt.verify_checksums().is_ok());
+//!
+//! let fixture = serde_json::json!([{"IP": {"ttl": 256}}]);
+//! let err = Packet::from_value(&fixture).err().unwrap();
+//! assert_eq!(err.to_string(), "invalid fixture entry [0].IP.ttl: invalid value 256 for ttl field");
+//! ```
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::headers::*;
+use crate::spec::{header_by_name, parse_value, set_field_str};
+use crate::{payload, Packet, PacketError};
+
+impl Packet {
+    /// Build a packet from a fixture, see [`fixtures`](crate::fixtures)
+    pub fn from_value<T: Serialize + ?Sized>(value: &T) -> Result<Packet, PacketError> {
+        let value = serde_json::to_value(value).map_err(|e| invalid("", e))?;
+        let (prefix, layers, mut payload, mut fixup) = match &value {
+            Value::Array(layers) => ("", layers.as_slice(), None, None),
+            Value::Object(map) => {
+                if let Some(key) = map
+                    .keys()
+                    .find(|x| !matches!(x.as_str(), "layers" | "payload" | "fixup"))
+                {
+                    return Err(invalid(key, "unknown entry"));
+                }
+                let layers = match map.get("layers") {
+                    Some(Value::Array(layers)) => layers.as_slice(),
+                    None => &[],
+                    Some(_) => return Err(invalid("layers", "expected a list of layers")),
+                };
+                let entry = |key: &str| map.get(key).map(|x| (String::from(key), x));
+                ("layers", layers, entry("payload"), entry("fixup"))
+            }
+            _ => return Err(invalid("", "expected a list of layers")),
+        };
+        let mut pkt = Packet::new();
+        // the fields set by the fixture, left as they are by the fixups
+        let mut set = Vec::new();
+        for (i, entry) in layers.iter().enumerate() {
+            let at = format!("{}[{}]", prefix, i);
+            let (name, fields) = match entry {
+                Value::String(name) => (name, None),
+                Value::Object(map) if map.len() == 1 => {
+                    let (name, fields) = map.iter().next().unwrap();
+                    (name, Some(fields))
+                }
+                _ => return Err(invalid(&at, "expected a map of a layer name to its fields")),
+            };
+            let path = format!("{}.{}", at, name);
+            match (name.as_str(), fields) {
+                ("payload", Some(v)) => payload = Some((path, v)),
+                ("fixup", Some(v)) => fixup = Some((path, v)),
+                _ => {
+                    let (hdr, fields) = layer(&path, name, fields)?;
+                    let index = pkt.hdrs.len();
+                    set.extend(fields.into_iter().map(|x| (index, x)));
+                    pkt.hdrs.push(hdr);
+                }
+            }
+        }
+        if let Some((path, v)) = payload {
+            pkt.payload = payload_bytes(v).map_err(|e| invalid(&path, e))?;
+        }
+        match fixup {
+            None | Some((_, Value::Bool(true))) => {
+                for (index, field) in set {
+                    pkt.poison(index, field)?;
+                }
+                pkt.fixup();
+                pkt.clear_poison();
+            }
+            Some((_, Value::Bool(false))) => (),
+            Some((path, _)) => return Err(invalid(&path, "expected true or false")),
+        }
+        Ok(pkt)
+    }
+}
+
+/// Build the header called name with fields, at path in the fixture, with the names of the
+/// fields set
+fn layer(
+    path: &str,
+    name: &str,
+    fields: Option<&Value>,
+) -> Result<(Box<dyn Header>, Vec<&'static str>), PacketError> {
+    let mut hdr = header_by_name(name).map_err(|e| invalid(path, e))?;
+    let mut set = Vec::new();
+    let fields = match fields {
+        None | Some(Value::Null) => return Ok((hdr, set)),
+        Some(Value::Object(fields)) => fields,
+        Some(_) => return Err(invalid(path, "expected a map of field names to values")),
+    };
+    for (field, v) in fields {
+        let path = format!("{}.{}", path, field);
+        let value = match v {
+            Value::Number(_) => v.to_string(),
+            Value::String(s) => s.clone(),
+            Value::Bool(b) => String::from(if *b { "1" } else { "0" }),
+            _ => return Err(invalid(&path, "expected a number, a string or a boolean")),
+        };
+        set.push(set_field_str(hdr.as_mut(), field, &value).map_err(|e| invalid(&path, e))?);
+    }
+    Ok((hdr, set))
+}
+
+/// Bytes of a payload entry: hex, ascii or a pattern
+fn payload_bytes(v: &Value) -> Result<Vec<u8>, String> {
+    let pattern = match v {
+        Value::String(s) if s.starts_with("0x") => {
+            return parse_value(s).ok_or_else(|| format!("invalid hex string {}", s));
+        }
+        Value::String(s) => return Ok(s.as_bytes().to_vec()),
+        Value::Object(pattern) => pattern,
+        _ => return Err(String::from("expected a string or a pattern")),
+    };
+    let number = |key: &str, map: &Map<String, Value>| match map.get(key) {
+        Some(Value::Number(n)) => n.as_u64().ok_or_else(|| format!("invalid {} {}", key, n)),
+        Some(Value::String(s)) => parse_value(s)
+            .filter(|x| x.len() <= 8)
+            .map(|x| x.iter().fold(0, |n, b| n << 8 | *b as u64))
+            .ok_or_else(|| format!("invalid {} {}", key, s)),
+        Some(v) => Err(format!("invalid {} {}", key, v)),
+        None => Err(format!("missing {}", key)),
+    };
+    let len = number("len", pattern)? as usize;
+    match pattern.get("pattern").and_then(|x| x.as_str()) {
+        Some("incrementing") => Ok(payload::incrementing(len)),
+        Some("repeat") => match number("byte", pattern)? {
+            byte @ 0..=0xff => Ok(payload::repeat(byte as u8, len)),
+            byte => Err(format!("invalid byte {}", byte)),
+        },
+        Some("random") => Ok(payload::random_seeded(number("seed", pattern)?, len)),
+        Some("ascii") => Ok(payload::ascii_cycle(len)),
+        Some(x) => Err(format!("unknown pattern {}", x)),
+        None => Err(String::from("missing pattern")),
+    }
+}
+
+fn invalid(path: &str, reason: impl ToString) -> PacketError {
+    PacketError::InvalidFixture {
+        path: String::from(path),
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,185 @@
+//! # Flow extraction and hashing
+//!
+//! [`Packet::flow_tuple`] extracts the 5-tuple of a packet to predict ECMP and RSS distribution
+//! with [`toeplitz_hash`] or [`symmetric_hash`].
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::flow::*;
+//! let pkt = create_tcp_packet(&Default::default());
+//! let tuple = pkt.flow_tuple().unwrap();
+//! assert_eq!(tuple.ports, Some((1234, 80)));
+//! let queue = toeplitz_hash(&tuple, &DEFAULT_RSS_KEY) % 16;
+//! ```
+
+use core::net::IpAddr;
+
+use crate::headers::*;
+use crate::Packet;
+
+/// The Microsoft RSS verification key
+pub const DEFAULT_RSS_KEY: [u8; 40] = [
+    0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+    0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+    0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+];
+
+/// Addresses, protocol and ports of a flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FlowTuple {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub proto: u8,
+    /// Source and destination ports, None when the packet has no TCP/UDP header or is not the
+    /// first fragment
+    pub ports: Option<(u16, u16)>,
+}
+
+impl FlowTuple {
+    /// Return the tuple with source and destination swapped
+    pub fn reversed(&self) -> FlowTuple {
+        FlowTuple {
+            src: self.dst,
+            dst: self.src,
+            proto: self.proto,
+            ports: self.ports.map(|(s, d)| (d, s)),
+        }
+    }
+    /// Return the tuple with the lower endpoint as the source, the same for both directions
+    pub fn normalized(&self) -> FlowTuple {
+        let src = (self.src, self.ports.map(|x| x.0));
+        let dst = (self.dst, self.ports.map(|x| x.1));
+        match src <= dst {
+            true => *self,
+            false => self.reversed(),
+        }
+    }
+    /// The RSS hash input, addresses followed by the ports when present
+    fn hash_input(&self) -> Vec<u8> {
+        let mut v = ip_octets(&self.src);
+        v.extend(ip_octets(&self.dst));
+        if let Some((sport, dport)) = self.ports {
+            v.extend_from_slice(&sport.to_be_bytes());
+            v.extend_from_slice(&dport.to_be_bytes());
+        }
+        v
+    }
+}
+
+impl Packet {
+    /// Extract the flow of the outermost IPv4 or IPv6 header
+    pub fn flow_tuple(&self) -> Option<FlowTuple> {
+        let i = self.hdrs.iter().position(|x| is_ip(x.as_ref()))?;
+        self.flow_tuple_at(i)
+    }
+    /// Extract the flow of the innermost IPv4 or IPv6 header, looking inside tunnels
+    pub fn inner_flow_tuple(&self) -> Option<FlowTuple> {
+        let i = self.hdrs.iter().rposition(|x| is_ip(x.as_ref()))?;
+        self.flow_tuple_at(i)
+    }
+    fn flow_tuple_at(&self, i: usize) -> Option<FlowTuple> {
+        let ip = self.hdrs[i].as_any();
+        let (src, dst, proto, first_fragment) = if let Some(ipv4) = ip.downcast_ref::<IPv4>() {
+            (
+                IpAddr::V4(ipv4.src_ip()),
+                IpAddr::V4(ipv4.dst_ip()),
+                ipv4.protocol() as u8,
+                ipv4.frag_offset() == 0,
+            )
+        } else {
+            let ipv6 = ip.downcast_ref::<IPv6>()?;
+            (
+                IpAddr::V6(ipv6.src_ip()),
+                IpAddr::V6(ipv6.dst_ip()),
+                ipv6.next_hdr() as u8,
+                true,
+            )
+        };
+        let ports = match self.hdrs.get(i + 1) {
+            Some(l4) if first_fragment => {
+                if let Some(tcp) = l4.as_any().downcast_ref::<TCP>() {
+                    Some((tcp.src() as u16, tcp.dst() as u16))
+                } else {
+                    l4.as_any()
+                        .downcast_ref::<UDP>()
+                        .map(|udp| (udp.src() as u16, udp.dst() as u16))
+                }
+            }
+            _ => None,
+        };
+        Some(FlowTuple {
+            src,
+            dst,
+            proto,
+            ports,
+        })
+    }
+}
+
+/// Compute the Microsoft RSS Toeplitz hash of the tuple
+///
+/// The ports are part of the hash input when present.
+pub fn toeplitz_hash(tuple: &FlowTuple, key: &[u8; 40]) -> u32 {
+    toeplitz(&tuple.hash_input(), key)
+}
+
+/// Compute a hash which is the same for both directions of a flow
+///
+/// The endpoints are sorted before computing the CRC32 of the tuple.
+pub fn symmetric_hash(tuple: &FlowTuple) -> u32 {
+    let (sport, dport) = tuple.ports.unwrap_or((0, 0));
+    let a = (ip_octets(&tuple.src), sport);
+    let b = (ip_octets(&tuple.dst), dport);
+    let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+    let mut v = lo.0;
+    v.extend(hi.0);
+    v.push(tuple.proto);
+    v.extend_from_slice(&lo.1.to_be_bytes());
+    v.extend_from_slice(&hi.1.to_be_bytes());
+    crc32(&v)
+}
+
+fn toeplitz(input: &[u8], key: &[u8]) -> u32 {
+    let key_bit = |k: usize| match key.get(k / 8) {
+        Some(b) => ((b >> (7 - k % 8)) & 1) as u32,
+        None => 0,
+    };
+    let mut window = (0..32).fold(0, |w, k| (w << 1) | key_bit(k));
+    let mut hash = 0;
+    for (i, byte) in input.iter().enumerate() {
+        for b in 0..8 {
+            if byte & (0x80 >> b) != 0 {
+                hash ^= window;
+            }
+            window = (window << 1) | key_bit(i * 8 + b + 32);
+        }
+    }
+    hash
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffff;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn ip_octets(ip: &IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(x) => x.octets().to_vec(),
+        IpAddr::V6(x) => x.octets().to_vec(),
+    }
+}
+
+fn is_ip(hdr: &dyn Header) -> bool {
+    hdr.as_any().is::<IPv4>() || hdr.as_any().is::<IPv6>()
+}
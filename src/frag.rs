@@ -0,0 +1,177 @@
+//! # IPv4 fragmentation
+//!
+//! [`Packet::fragment_ipv4`] splits a packet into the fragments a router sends on a link with a
+//! smaller MTU and [`reassemble_ipv4`] joins them back. Everything after the first IPv4 header,
+//! including the headers it carries, is fragment data. The headers up to the IPv4 header are
+//! copied into every fragment, the options into the first one only: the fragments after it carry
+//! the options with the copied flag set, as RFC 791 requires.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::frag::reassemble_ipv4;
+//! let pkt = create_udp_packet(&PacketConfig {
+//!     payload_len: 1000,
+//!     ..Default::default()
+//! });
+//! let frags = pkt.fragment_ipv4(576);
+//! assert_eq!(frags.len(), 2);
+//! assert!(reassemble_ipv4(&frags).unwrap().compare(&pkt));
+//! ```
+
+use crate::alloc_prelude::*;
+use crate::headers::*;
+use crate::icmp::inet_checksum;
+use crate::Packet;
+
+/// Don't fragment bit of the IPv4 flags
+pub const IPV4_FLAG_DF: u64 = 0x2;
+/// More fragments bit of the IPv4 flags
+pub const IPV4_FLAG_MF: u64 = 0x1;
+
+/// Split of a packet around its first IPv4 header
+struct Ipv4Parts<'a> {
+    pkt: &'a Packet,
+    /// Index of the IPv4 header in the packet
+    index: usize,
+    ipv4: &'a IPv4,
+    /// IPv4 options following the fixed header
+    options: Vec<u8>,
+    /// Bytes carried by the IPv4 header, without the link layer padding
+    data: Vec<u8>,
+}
+
+impl<'a> Ipv4Parts<'a> {
+    fn of(pkt: &'a Packet) -> Option<Ipv4Parts<'a>> {
+        let index = pkt.hdrs.iter().position(|x| x.as_any().is::<IPv4>())?;
+        let ipv4 = pkt.hdrs[index].as_any().downcast_ref::<IPv4>().unwrap();
+        let v = pkt.to_vec();
+        let start = pkt.hdrs[..index].iter().map(|x| x.len()).sum::<usize>();
+        let hlen = (ipv4.ihl() as usize * 4).max(IPv4::size());
+        let options_end = (start + hlen).min(v.len());
+        let end = (start + ipv4.total_len() as usize).clamp(options_end, v.len());
+        Some(Ipv4Parts {
+            pkt,
+            index,
+            ipv4,
+            options: v[start + IPv4::size()..options_end].to_vec(),
+            data: v[options_end..end].to_vec(),
+        })
+    }
+}
+
+impl Packet {
+    /// Split the packet into fragments of at most mtu bytes of IPv4 header and data
+    ///
+    /// A packet that fits, or has no IPv4 header, is returned as the only fragment. The result is
+    /// empty if the packet must be fragmented but the DF flag is set or mtu leaves no room for 8
+    /// bytes of data. Fragmenting a fragment keeps its offset and its MF flag on the last piece.
+    pub fn fragment_ipv4(&self, mtu: usize) -> Vec<Packet> {
+        let parts = match Ipv4Parts::of(self) {
+            Some(x) => x,
+            None => return vec![self.clone()],
+        };
+        let hlen = IPv4::size() + parts.options.len();
+        if hlen + parts.data.len() <= mtu {
+            return vec![self.clone()];
+        }
+        let flags = parts.ipv4.flags();
+        if flags & IPV4_FLAG_DF != 0 || mtu < hlen + 8 {
+            return Vec::new();
+        }
+        let copied = copied_options(&parts.options);
+        let offset = parts.ipv4.frag_offset() as usize;
+        let mut frags = Vec::new();
+        let mut start = 0;
+        while start < parts.data.len() {
+            let options = match offset * 8 + start {
+                0 => &parts.options,
+                _ => &copied,
+            };
+            let hlen = IPv4::size() + options.len();
+            let end = (start + (mtu - hlen) / 8 * 8).min(parts.data.len());
+            let mut frag = Packet::new();
+            frag.hdrs = self.hdrs[..=parts.index].to_vec();
+            let ipv4: &mut IPv4 = (&mut frag.hdrs[parts.index]).into();
+            ipv4.set_ihl((hlen / 4) as u64);
+            ipv4.set_frag_offset((offset + start / 8) as u64);
+            if end < parts.data.len() {
+                ipv4.set_flags(flags | IPV4_FLAG_MF);
+            }
+            ipv4.set_total_len((hlen + end - start) as u64);
+            set_ipv4_checksum(ipv4, options);
+            frag.payload = [options.as_slice(), &parts.data[start..end]].concat();
+            frags.push(frag);
+            start = end;
+        }
+        frags
+    }
+}
+
+/// Join the fragments of a packet, in any order
+///
+/// Fails if the fragments are not all from the same packet, if some are missing or if they
+/// overlap. The headers after the IPv4 header are dissected again.
+pub fn reassemble_ipv4(frags: &[Packet]) -> Option<Packet> {
+    let mut parts: Vec<Ipv4Parts> = frags.iter().map(Ipv4Parts::of).collect::<Option<_>>()?;
+    let key = |x: &Ipv4Parts| {
+        let ipv4 = x.ipv4;
+        (
+            ipv4.identification(),
+            ipv4.src(),
+            ipv4.dst(),
+            ipv4.protocol(),
+        )
+    };
+    let first = parts.first()?;
+    if parts.iter().any(|x| key(x) != key(first)) {
+        return None;
+    }
+    parts.sort_by_key(|x| x.ipv4.frag_offset());
+    let mut data = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        let mf = part.ipv4.flags() & IPV4_FLAG_MF != 0;
+        if part.ipv4.frag_offset() as usize * 8 != data.len() || mf == last {
+            return None;
+        }
+        data.extend_from_slice(&part.data);
+    }
+    let first = &parts[0];
+    let mut pkt = Packet::new();
+    pkt.hdrs = first.pkt.hdrs[..=first.index].to_vec();
+    let ipv4: &mut IPv4 = (&mut pkt.hdrs[first.index]).into();
+    ipv4.set_flags(first.ipv4.flags() & !IPV4_FLAG_MF);
+    ipv4.set_total_len((IPv4::size() + first.options.len() + data.len()) as u64);
+    set_ipv4_checksum(ipv4, &first.options);
+    pkt.payload = [first.options.as_slice(), &data].concat();
+    pkt.reparse();
+    Some(pkt)
+}
+
+/// The options with the copied flag set, padded with end of list to whole words
+fn copied_options(options: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut rest = options;
+    while let Some(&typ) = rest.first() {
+        let len = match typ {
+            0 => break,
+            1 => 1,
+            _ => match rest.get(1) {
+                Some(&len) if len >= 2 && len as usize <= rest.len() => len as usize,
+                _ => break,
+            },
+        };
+        if typ & 0x80 != 0 {
+            out.extend_from_slice(&rest[..len]);
+        }
+        rest = &rest[len..];
+    }
+    out.resize(out.len().next_multiple_of(4), 0);
+    out
+}
+
+pub(crate) fn set_ipv4_checksum(ipv4: &mut IPv4, options: &[u8]) {
+    ipv4.set_header_checksum(0);
+    let v = [ipv4.to_vec().as_slice(), options].concat();
+    ipv4.set_header_checksum(inet_checksum(&v) as u64);
+}
@@ -18,6 +18,70 @@
 //!
 //! Use `ARPSlice::from()` to generate a sliced header.
 //!
+//! ## Array header
+//!
+//! These headers own their bytes in an array of the size of the header, so they can be built and
+//! read in const contexts, as the well-known packets of a `static` table. Their getters are
+//! `const fn` returning the value of a field as a `u64`, or an `i64` for the signed fields. The
+//! fields wider than 64 bits, as the IPv6 addresses, are read as a `u128`.
+//! `to_header()` and `as_slice()` convert them to the other kinds. They are types of their own,
+//! as `ARPArray`, rather than a byte array parameter of `ARP`: the owned header is a Python class
+//! with the `python-module` feature, which can not be generic.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! static ARP_REPLY: ARPArray = ARPArray::from_array([
+//!     0, 1, 8, 0, 6, 4, 0, 2, 0, 1, 2, 3, 4, 5, 10, 0, 0, 1, 0, 0, 0, 0, 0, 0, 10, 0, 0, 2,
+//! ]);
+//! const OPCODE: u64 = ARP_REPLY.opcode();
+//! assert_eq!(OPCODE, 2);
+//! assert_eq!(ARP_REPLY.to_header().opcode_typed(), Ok(ArpOp::Reply));
+//! ```
+//!
+//! ## Length check
+//!
+//! `fits(buf_len)` tells, without constructing the header, whether a buffer is long enough to
+//! hold it. Headers have a fixed size, options and variable parts follow as separate layers or
+//! payload, so `fits` checks the fixed part only.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! let frame = [0u8; 20];
+//! assert!(Ether::fits(frame.len()));
+//! assert!(!IPv4Slice::fits(frame.len() - Ether::size()));
+//! ```
+//!
+//! ## Renamed fields
+//!
+//! The data offset of TCP and the fragment offset of IPv4 were once misspelled `data_startset`
+//! and `frag_startset`. They are `data_offset` and `frag_offset` now, use these names: the old
+//! getters and setters remain as deprecated accessors delegating to them, and the lookups by
+//! name, as [`Header::get_field`], accept both names.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! let mut tcp = TCP::new();
+//! tcp.set_data_offset(6);
+//! assert_eq!(tcp.get_field("data_startset"), Some(6));
+//! let mut ipv4 = IPv4::new();
+//! ipv4.set_frag_offset(185);
+//! #[allow(deprecated)]
+//! let offset = ipv4.frag_startset();
+//! assert_eq!(offset, 185);
+//! ```
+//!
+//! ## Hex
+//!
+//! Both kinds of headers format their bytes as one hex string with `{:x}` or `{:X}`, `{:#x}`
+//! prefixes it with `0x`.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! let udp = UDP::new();
+//! assert_eq!(format!("{:x}", udp), "04d2005000000000");
+//! assert_eq!(format!("{:#X}", udp), "0x04D2005000000000");
+//! ```
+//!
 //!
 
 #[doc(hidden)]
@@ -27,53 +91,627 @@ pub use ::bitfield::BitRange;
 #[doc(hidden)]
 pub use paste::paste;
 #[doc(hidden)]
-pub use std::any::Any;
+pub use crate::alloc_prelude::*;
 #[doc(hidden)]
-pub use std::sync::Arc;
+pub use core::any::Any;
 #[doc(hidden)]
-pub use std::sync::Mutex;
+pub use alloc::sync::Arc;
+#[doc(hidden)]
+pub use crate::sync::Mutex;
+
+use crate::types::{EtherType, IpProtocol, IGMP_V3_REPORT, L2TP_V2, MLD_QUERY, MLD_V2_REPORT};
 
 /// Represents a generic packet header
-pub trait Header: Send {
+///
+/// Headers are `Send + Sync`, so the packets holding them can be shared between threads.
+pub trait Header: Send + Sync {
     /// Return the name of the header
     fn name(&self) -> &str;
     /// Return the length of the header
     fn len(&self) -> usize;
     /// Show the header
+    #[cfg(feature = "std")]
     fn show(&self);
+    /// Return the field table of the header, one line per field
+    fn field_table(&self) -> String;
     /// Return the header as a vector copy
     fn to_vec(&self) -> Vec<u8>;
     /// Return the header as a slice
     fn as_slice(&self) -> &[u8];
-    /// Clone the header
-    fn clone(&self) -> Box<dyn Header>;
+    /// Copy the header into the start of dst without allocating, returns the bytes written
+    fn write_into(&self, dst: &mut [u8]) -> Result<usize, crate::PacketError>;
+    /// Clone the header into a new box
+    fn clone_box(&self) -> Box<dyn Header>;
+    /// Clone the header into a new box sharing its bytes until either copy is written
+    ///
+    /// Headers created from the box with `From<&Box<dyn Header>>` stop seeing the writes to
+    /// it, and it stops seeing theirs, as they too copy the bytes before writing to them.
+    fn clone_cow(&self) -> Box<dyn Header> {
+        self.clone_box()
+    }
+    /// Copy the bytes of the header if they are shared with a copy-on-write clone
+    ///
+    /// Called before every write, the bytes are copied at most once per clone.
+    fn make_mut(&mut self) {}
+    /// Return true if the bytes of the header were shared with a copy writing to them directly,
+    /// as `From<&Box<dyn Header>>` returns, so they may have changed behind the packet
+    fn is_shared(&self) -> bool {
+        false
+    }
     /// Consume the header as owned
     fn to_owned(self) -> Box<dyn Header>;
     /// Get a reference to the underlying concrete type
     fn as_any(&self) -> &dyn Any;
     /// Get a mutable reference to the underlying concrete type
     fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Consume the boxed header into the underlying concrete type
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+    /// Return the description of each field of the header
+    fn fields(&self) -> &'static [FieldInfo];
+    /// Get the value of a field by name
+    fn get_field(&self, name: &str) -> Option<u64>;
+    /// Get the value of a field of any size by name as big endian bytes
+    ///
+    /// The value is aligned to the least significant bit of the last byte.
+    fn get_field_bytes(&self, name: &str) -> Option<Vec<u8>>;
+    /// Return false if a reserved field is not zero
+    fn validate_reserved(&self) -> bool;
+    /// Return the source address or port of the header as big endian bytes, None if it has no
+    /// `src` field
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// let hdrs: Vec<Box<dyn Header>> = vec![Box::new(IPv4::new()), Box::new(UDP::new())];
+    /// let src: Vec<_> = hdrs.iter().map(|x| x.src_bytes()).collect();
+    /// assert_eq!(src, [Some(vec![192, 168, 0, 1]), Some(vec![0x04, 0xd2])]);
+    /// assert_eq!(Vlan::new().src_bytes(), None);
+    /// ```
+    fn src_bytes(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Return the destination address or port of the header as big endian bytes, None if it has
+    /// no `dst` field
+    fn dst_bytes(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Return the bytes of the header covered by a field, None if there is no such field or it is
+    /// not byte aligned
+    ///
+    /// The range is exclusive, it ends past the last byte of the field and slices the bytes of the
+    /// header as is. A two byte field at the start of the header is `0..2`.
+    fn field_byte_range(&self, name: &str) -> Option<core::ops::Range<usize>> {
+        self.fields().iter().find(|f| f.is_named(name))?.byte_range()
+    }
+    /// Return the field annotated as the checksum of the header, if any
+    fn checksum_field(&self) -> Option<&'static FieldInfo> {
+        self.fields().iter().find(|f| f.checksum)
+    }
+    /// Summarize the header on one line
+    fn summary(&self) -> String;
+    /// Set the value of a field by name, returns false if there is no such field
+    fn set_field(&mut self, name: &str, value: u64) -> bool;
+    /// Set the value of a field by name from big endian bytes, returns false if there is no such field
+    ///
+    /// The bytes are aligned to the least significant bit of the field. Missing bits are zero filled.
+    fn set_field_bytes(&mut self, name: &str, value: &[u8]) -> bool;
+    /// Set the value of a field by name, failing if there is no such field or the value does not
+    /// fit in the field
+    fn try_set_field(&mut self, name: &str, value: u64) -> Result<(), crate::PacketError> {
+        let f = self
+            .fields()
+            .iter()
+            .find(|f| f.is_named(name))
+            .ok_or_else(|| crate::PacketError::UnknownField {
+                header: String::from(self.name()),
+                field: String::from(name),
+            })?;
+        if f.size() < 64 && value >> f.size() != 0 {
+            return Err(crate::PacketError::ValueOverflow {
+                field: String::from(name),
+                value,
+                bits: f.size(),
+            });
+        }
+        self.set_field(name, value);
+        Ok(())
+    }
+    /// Set the value of a field by name from a string, parsed by the [`FieldKind`] of the field
+    ///
+    /// Address fields take an address, as `192.168.0.1` or `00:11:22:33:44:55`, and every field
+    /// takes a number, decimal or `0x` prefixed hex. `TCP.flags` also takes flag letters, as `SA`.
+    /// Fails if there is no such field or the value does not fit in the field.
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// let mut ip = IPv4::new();
+    /// ip.set_field_str("dst", "10.0.0.1").unwrap();
+    /// ip.set_field_str("protocol", "0x11").unwrap();
+    /// assert_eq!((ip.dst(), ip.protocol()), (0x0a000001, 17));
+    /// assert!(ip.set_field_str("ttl", "10.0.0.1").is_err());
+    /// ```
+    fn set_field_str(&mut self, name: &str, value: &str) -> Result<(), crate::PacketError> {
+        crate::spec::set_field_str(self, name, value).map(|_| ())
+    }
+    /// Reverse the bytes of a field in place, to try the other byte order of a field
+    ///
+    /// Fails if there is no such field or it is not byte aligned.
+    fn swap_field_bytes(&mut self, name: &str) -> Result<(), crate::PacketError> {
+        let f = self
+            .fields()
+            .iter()
+            .find(|f| f.is_named(name))
+            .ok_or_else(|| crate::PacketError::UnknownField {
+                header: String::from(self.name()),
+                field: String::from(name),
+            })?;
+        if f.byte_range().is_none() {
+            return Err(crate::PacketError::MisalignedRange {
+                msb: f.msb,
+                lsb: f.lsb,
+            });
+        }
+        let mut value = self.get_field_bytes(name).unwrap_or_default();
+        value.reverse();
+        self.set_field_bytes(name, &value);
+        Ok(())
+    }
+}
+
+/// Format bytes as one hex number without separators, for the `LowerHex` and `UpperHex` impls
+#[doc(hidden)]
+pub fn fmt_hex(bytes: &[u8], f: &mut core::fmt::Formatter, upper: bool) -> core::fmt::Result {
+    use core::fmt::Write;
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        match upper {
+            true => write!(hex, "{:02X}", b)?,
+            false => write!(hex, "{:02x}", b)?,
+        }
+    }
+    f.pad_integral(true, "0x", &hex)
+}
+
+#[doc(hidden)]
+pub fn write_bytes(src: &[u8], dst: &mut [u8]) -> Result<usize, crate::PacketError> {
+    match dst.get_mut(..src.len()) {
+        Some(d) => {
+            d.copy_from_slice(src);
+            Ok(src.len())
+        }
+        None => Err(crate::PacketError::BufferTooSmall {
+            needed: src.len(),
+            available: dst.len(),
+        }),
+    }
+}
+
+/// Describes a field of a header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldInfo {
+    /// Name of the field
+    pub name: &'static str,
+    /// Start bit of the field within the header
+    pub lsb: usize,
+    /// End bit of the field within the header
+    pub msb: usize,
+    /// The field is reserved and must be zero
+    pub reserved: bool,
+    /// The field is the checksum of the header
+    pub checksum: bool,
+    /// The field is little endian on the wire
+    pub little_endian: bool,
+    /// Former name of the field, still accepted by the lookups by name
+    pub alias: Option<&'static str>,
+    /// Names of the bits of a field declared with `as flags { .. }`, the first being the most
+    /// significant, empty for the other fields
+    pub bits: &'static [&'static str],
+    /// Names of the values of a field declared with `enum { .. }` or `as Type { .. }`, empty for
+    /// the other fields
+    pub values: &'static [(u64, &'static str)],
+    /// How the value of the field is written as a string, see [`Header::set_field_str`]
+    pub kind: FieldKind,
+}
+
+/// How the value of a field is written as a string, declared with `[mac]`, `[ipv4]` or `[ipv6]`
+/// in [`make_header!`](crate::make_header)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FieldKind {
+    /// A number, decimal or `0x` prefixed hex
+    #[default]
+    Number,
+    /// A MAC address, as `00:11:22:33:44:55`
+    Mac,
+    /// An IPv4 address, as `192.168.0.1`
+    Ipv4,
+    /// An IPv6 address, as `2001:db8::1`
+    Ipv6,
+    /// A field declared `as bytes`, as `0x` prefixed hex
+    Bytes,
+}
+
+impl FieldInfo {
+    /// Return true if the field is called name, or was before being renamed
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name == name || self.alias == Some(name)
+    }
+    /// Return the name of a value of the field, None if the value has no name
+    pub fn value_name(&self, value: u64) -> Option<&'static str> {
+        self.values.iter().find(|x| x.0 == value).map(|x| x.1)
+    }
+    /// Return the value of the field named name, None if no value has that name
+    pub fn named_value(&self, name: &str) -> Option<u64> {
+        self.values.iter().find(|x| x.1 == name).map(|x| x.0)
+    }
+    /// Return the size of the field in bits
+    pub const fn size(&self) -> usize {
+        self.msb - self.lsb + 1
+    }
+    /// Return the bytes of the header covered by the field, None if it is not byte aligned
+    ///
+    /// The end of the range is exclusive, as in [`Header::field_byte_range`].
+    pub const fn byte_range(&self) -> Option<core::ops::Range<usize>> {
+        if !self.lsb.is_multiple_of(8) || !(self.msb + 1).is_multiple_of(8) {
+            return None;
+        }
+        Some(self.lsb / 8..(self.msb + 1) / 8)
+    }
+    /// Convert a value of the field between the wire and host order, big endian fields are
+    /// left as they are
+    pub fn wire_order(&self, value: u64) -> u64 {
+        match self.little_endian {
+            true => value.swap_field(self.size()),
+            false => value,
+        }
+    }
+    /// Convert the big endian bytes of a value of the field between the wire and host order,
+    /// aligned to the least significant bit as [`Header::get_field_bytes`] returns them
+    pub fn wire_order_bytes(&self, value: &[u8]) -> Vec<u8> {
+        if !self.little_endian {
+            return value.to_vec();
+        }
+        let len = self.size() / 8;
+        let mut bytes = vec![0u8; len];
+        let n = value.len().min(len);
+        bytes[len - n..].copy_from_slice(&value[value.len() - n..]);
+        bytes.reverse();
+        bytes
+    }
+}
+
+/// Byte swap of the value of a little endian field
+#[doc(hidden)]
+pub trait SwapField {
+    /// Reverse the order of the bytes of the low bits of the value
+    fn swap_field(self, bits: usize) -> Self;
+}
+
+impl SwapField for u64 {
+    fn swap_field(self, bits: usize) -> u64 {
+        self.swap_bytes() >> (64 - bits)
+    }
+}
+
+impl SwapField for u128 {
+    fn swap_field(self, bits: usize) -> u128 {
+        self.swap_bytes() >> (128 - bits)
+    }
+}
+
+/// Check that the bits msb..=lsb are within a buffer of len bytes
+///
+/// Fails with [`BufferTooSmall`](crate::PacketError::BufferTooSmall) if the range ends past the
+/// buffer and with [`InvalidValue`](crate::PacketError::InvalidValue) if msb is below lsb.
+pub fn check_bit_range(len: usize, msb: usize, lsb: usize) -> Result<(), crate::PacketError> {
+    if msb < lsb {
+        return Err(crate::PacketError::InvalidValue {
+            field: String::from("msb"),
+            value: msb.to_string(),
+        });
+    }
+    if msb / 8 >= len {
+        return Err(crate::PacketError::BufferTooSmall {
+            needed: msb / 8 + 1,
+            available: len,
+        });
+    }
+    Ok(())
+}
+
+/// Read the bits lsb..=msb of buf, numbered from the most significant bit of the first byte
+///
+/// The range is at most 64 bits and within buf.
+#[doc(hidden)]
+pub const fn read_bits(buf: &[u8], msb: usize, lsb: usize) -> u64 {
+    let width = msb - lsb + 1;
+    let mut acc = 0u128;
+    let mut i = lsb / 8;
+    while i <= msb / 8 {
+        acc = (acc << 8) | buf[i] as u128;
+        i += 1;
+    }
+    ((acc >> (7 - msb % 8)) & ((1u128 << width) - 1)) as u64
+}
+
+/// Read a field of at most 128 bits of buf in host order, for the const getters
+#[doc(hidden)]
+pub const fn read_field(buf: &[u8], msb: usize, lsb: usize, little_endian: bool) -> u128 {
+    let width = msb - lsb + 1;
+    let value = match width > 64 {
+        true => {
+            let hi = read_bits(buf, msb - 64, lsb) as u128;
+            hi << 64 | read_bits(buf, msb, msb - 63) as u128
+        }
+        false => read_bits(buf, msb, lsb) as u128,
+    };
+    match little_endian {
+        true => value.swap_bytes() >> (128 - width),
+        false => value,
+    }
+}
+
+/// Write the low bits of value to the bits lsb..=msb of buf, the counterpart of [`read_bits`]
+#[doc(hidden)]
+pub fn write_bits(buf: &mut [u8], msb: usize, lsb: usize, value: u64) {
+    let width = msb - lsb + 1;
+    let bytes = &mut buf[lsb / 8..=msb / 8];
+    let acc = bytes.iter().fold(0u128, |acc, b| (acc << 8) | *b as u128);
+    let shift = 7 - msb % 8;
+    let mask = ((1u128 << width) - 1) << shift;
+    let mut acc = (acc & !mask) | (((value as u128) << shift) & mask);
+    for b in bytes.iter_mut().rev() {
+        *b = acc as u8;
+        acc >>= 8;
+    }
+}
+
+#[doc(hidden)]
+pub fn set_field_bits<T: BitRange<u64>>(hdr: &mut T, msb: usize, lsb: usize, value: &[u8]) {
+    let mut bits = value
+        .iter()
+        .rev()
+        .flat_map(|b| (0..8).map(move |i| (b >> i) & 1));
+    for i in (lsb..=msb).rev() {
+        let bit = bits.next().unwrap_or(0);
+        hdr.set_bit_range(i, i, bit as u64);
+    }
+}
+
+/// Set the bits lsb..=msb of the bytes of a header to the low bits of value
+#[doc(hidden)]
+pub fn set_default_bits(data: &mut [u8], msb: usize, lsb: usize, value: u128) {
+    for (n, i) in (lsb..=msb).rev().enumerate() {
+        let bit = value.checked_shr(n as u32).unwrap_or(0) & 1 == 1;
+        let mask = 0x80 >> (i % 8);
+        match bit {
+            true => data[i / 8] |= mask,
+            false => data[i / 8] &= !mask,
+        }
+    }
+}
+
+/// Names of the set bits of a flags field separated by `|`, the first name being the most
+/// significant bit, None if no bit is set
+#[doc(hidden)]
+pub fn flag_names(value: u64, names: &[&str]) -> Option<String> {
+    let set: Vec<String> = names
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| value >> (names.len() - 1 - i) & 1 == 1)
+        .map(|(_, n)| n.to_uppercase())
+        .collect();
+    match set.is_empty() {
+        true => None,
+        false => Some(set.join("|")),
+    }
+}
+
+/// Zero the bit ranges of the padding of a header, given as (msb, lsb)
+#[doc(hidden)]
+pub fn clear_padding(data: &mut [u8], padding: &[(usize, usize)]) {
+    for (msb, lsb) in padding {
+        if *msb / 8 < data.len() {
+            set_default_bits(data, *msb, *lsb, 0);
+        }
+    }
+}
+
+/// Copy value to the start of the bytes lsb..=msb, zero filling the rest
+#[doc(hidden)]
+pub fn set_octets(
+    data: &mut [u8],
+    field: &str,
+    msb: usize,
+    lsb: usize,
+    value: &[u8],
+) -> Result<(), crate::PacketError> {
+    check_bit_range(data.len(), msb, lsb)?;
+    let dst = &mut data[lsb / 8..(msb + 1) / 8];
+    if value.len() > dst.len() {
+        return Err(crate::PacketError::InvalidValue {
+            field: String::from(field),
+            value: format!("{} bytes", value.len()),
+        });
+    }
+    dst.fill(0);
+    dst[..value.len()].copy_from_slice(value);
+    Ok(())
+}
+
+/// Bytes of a byte field shown by `show`, the rest is elided
+#[doc(hidden)]
+pub const SHOW_OCTETS: usize = 16;
+
+/// Compute the `[checksum]` field of a default header over its bytes
+///
+/// The field is left as it is if explicit names it, it holds a value from the default vector or
+/// it is not two aligned bytes.
+#[doc(hidden)]
+pub fn default_checksum(data: &mut [u8], fields: &[FieldInfo], explicit: &[&str]) {
+    let field = match fields.iter().find(|f| f.checksum) {
+        Some(f) if !explicit.contains(&f.name) => f,
+        _ => return,
+    };
+    let range = match field.byte_range() {
+        Some(r) if r.len() == 2 && r.end <= data.len() => r,
+        _ => return,
+    };
+    if data[range.clone()] != [0, 0] {
+        return;
+    }
+    let chksum = crate::icmp::inet_checksum(data);
+    data[range].copy_from_slice(&chksum.to_be_bytes());
+}
+
+#[doc(hidden)]
+pub fn get_field_bits<T: BitRange<u64>>(hdr: &T, msb: usize, lsb: usize) -> Vec<u8> {
+    let len = (msb - lsb + 1).div_ceil(8);
+    let mut value = vec![0u8; len];
+    for (n, i) in (lsb..=msb).rev().enumerate() {
+        let bit: u64 = hdr.bit_range(i, i);
+        value[len - 1 - n / 8] |= (bit as u8) << (n % 8);
+    }
+    value
+}
+
+/// Read the bits lsb..=msb of a header as a u128, the range is at most 128 bits
+#[doc(hidden)]
+pub fn get_field_u128<T: BitRange<u64>>(hdr: &T, msb: usize, lsb: usize) -> u128 {
+    if msb - lsb < 64 {
+        let value: u64 = hdr.bit_range(msb, lsb);
+        return value as u128;
+    }
+    let hi: u64 = hdr.bit_range(msb - 64, lsb);
+    let lo: u64 = hdr.bit_range(msb, msb - 63);
+    ((hi as u128) << 64) | lo as u128
+}
+
+/// Write the low bits of value to the bits lsb..=msb of a header, the range is at most 128 bits
+#[doc(hidden)]
+pub fn set_field_u128<T: BitRange<u64>>(hdr: &mut T, msb: usize, lsb: usize, value: u128) {
+    if msb - lsb < 64 {
+        hdr.set_bit_range(msb, lsb, value as u64);
+        return;
+    }
+    hdr.set_bit_range(msb - 64, lsb, (value >> 64) as u64);
+    hdr.set_bit_range(msb, msb - 63, value as u64);
+}
+
+/// Create a header with default values from its name
+/// # Example
+///
+/// ```
+/// # use packet_rs::headers::*;
+/// let hdr = header_from_name("IPv4").unwrap();
+/// assert_eq!(hdr.len(), IPv4::size());
+/// ```
+pub fn header_from_name(name: &str) -> Option<Box<dyn Header>> {
+    let b = match name {
+        "Ether" => Ether::new().to_owned(),
+        "ARP" => ARP::new().to_owned(),
+        "Vlan" => Vlan::new().to_owned(),
+        "ICMP" => ICMP::new().to_owned(),
+        "Igmp" => Igmp::new().to_owned(),
+        "IgmpV3Report" => IgmpV3Report::new().to_owned(),
+        "Mld" => Mld::new().to_owned(),
+        "MldV2Report" => MldV2Report::new().to_owned(),
+        "IPv4" => IPv4::new().to_owned(),
+        "IPv6" => IPv6::new().to_owned(),
+        "UDP" => UDP::new().to_owned(),
+        "UdpLite" => UdpLite::new().to_owned(),
+        "TCP" => TCP::new().to_owned(),
+        "Vxlan" => Vxlan::new().to_owned(),
+        "L2tp" => L2tp::new().to_owned(),
+        "L2tpLength" => L2tpLength::new().to_owned(),
+        "L2tpTunnel" => L2tpTunnel::new().to_owned(),
+        "L2tpSequence" => L2tpSequence::new().to_owned(),
+        "L2tpOffset" => L2tpOffset::new().to_owned(),
+        "L2tpV3Session" => L2tpV3Session::new().to_owned(),
+        "Dot3" => Dot3::new().to_owned(),
+        "LLC" => LLC::new().to_owned(),
+        "SNAP" => SNAP::new().to_owned(),
+        "STP" => STP::new().to_owned(),
+        "GRE" => GRE::new().to_owned(),
+        "GREChksumOffset" => GREChksumOffset::new().to_owned(),
+        "GREKey" => GREKey::new().to_owned(),
+        "GRESequenceNum" => GRESequenceNum::new().to_owned(),
+        "ERSPAN2" => ERSPAN2::new().to_owned(),
+        "ERSPAN3" => ERSPAN3::new().to_owned(),
+        "ERSPANPLATFORM" => ERSPANPLATFORM::new().to_owned(),
+        "MPLS" => MPLS::new().to_owned(),
+        "EthFcs" => EthFcs::new().to_owned(),
+        "HwTimestamp" => HwTimestamp::new().to_owned(),
+        "HwTimestamp32" => HwTimestamp32::new().to_owned(),
+        _ => return None,
+    };
+    Some(b)
+}
+
+impl Clone for Box<dyn Header> {
+    fn clone(&self) -> Box<dyn Header> {
+        self.clone_box()
+    }
+}
+
+/// Downcast a boxed header into its concrete type
+///
+/// Mirrors `Box<dyn Any>::downcast`. On a type mismatch the original header is handed back untouched.
+/// # Example
+///
+/// ```
+/// # use packet_rs::headers::*;
+/// let hdr: Box<dyn Header> = Ether::new().to_owned();
+/// let hdr = match downcast::<IPv4>(hdr) {
+///     Ok(_) => unreachable!(),
+///     Err(hdr) => hdr,
+/// };
+/// if let Ok(eth) = downcast::<Ether>(hdr) {
+///     println!("{}", eth.etype());
+/// }
+/// ```
+pub fn downcast<T: Header + 'static>(hdr: Box<dyn Header>) -> Result<Box<T>, Box<dyn Header>> {
+    if hdr.as_any().is::<T>() {
+        match hdr.into_any().downcast::<T>() {
+            Ok(b) => Ok(b),
+            Err(_) => unreachable!(),
+        }
+    } else {
+        Err(hdr)
+    }
 }
 
 #[cfg(not(feature = "python-module"))]
-use pyo3_nullify::*;
+#[doc(hidden)]
+pub use pyo3_nullify::*;
 
 #[cfg(feature = "python-module")]
-use pyo3::prelude::*;
+#[doc(hidden)]
+pub use pyo3::prelude::*;
 
 #[cfg(feature = "python-module")]
 impl<'source> ::pyo3::FromPyObject<'source> for Box<dyn Header> {
     fn extract(obj: &'source ::pyo3::PyAny) -> ::pyo3::PyResult<Self> {
-        let b = match obj.str()?.to_str()? {
+        let name = obj.get_type().name()?;
+        let b = match name {
             "Ether" => Ok(Ether::extract(obj)?.to_owned()),
             "ARP" => Ok(ARP::extract(obj)?.to_owned()),
             "Vlan" => Ok(Vlan::extract(obj)?.to_owned()),
             "ICMP" => Ok(ICMP::extract(obj)?.to_owned()),
+            "Igmp" => Ok(Igmp::extract(obj)?.to_owned()),
+            "IgmpV3Report" => Ok(IgmpV3Report::extract(obj)?.to_owned()),
+            "Mld" => Ok(Mld::extract(obj)?.to_owned()),
+            "MldV2Report" => Ok(MldV2Report::extract(obj)?.to_owned()),
             "IPv4" => Ok(IPv4::extract(obj)?.to_owned()),
             "IPv6" => Ok(IPv6::extract(obj)?.to_owned()),
             "UDP" => Ok(UDP::extract(obj)?.to_owned()),
+            "UdpLite" => Ok(UdpLite::extract(obj)?.to_owned()),
             "TCP" => Ok(TCP::extract(obj)?.to_owned()),
             "Vxlan" => Ok(Vxlan::extract(obj)?.to_owned()),
+            "L2tp" => Ok(L2tp::extract(obj)?.to_owned()),
+            "L2tpLength" => Ok(L2tpLength::extract(obj)?.to_owned()),
+            "L2tpTunnel" => Ok(L2tpTunnel::extract(obj)?.to_owned()),
+            "L2tpSequence" => Ok(L2tpSequence::extract(obj)?.to_owned()),
+            "L2tpOffset" => Ok(L2tpOffset::extract(obj)?.to_owned()),
+            "L2tpV3Session" => Ok(L2tpV3Session::extract(obj)?.to_owned()),
             "Dot3" => Ok(Dot3::extract(obj)?.to_owned()),
             "LLC" => Ok(LLC::extract(obj)?.to_owned()),
             "SNAP" => Ok(SNAP::extract(obj)?.to_owned()),
@@ -86,9 +724,12 @@ impl<'source> ::pyo3::FromPyObject<'source> for Box<dyn Header> {
             "ERSPAN3" => Ok(ERSPAN3::extract(obj)?.to_owned()),
             "ERSPANPLATFORM" => Ok(ERSPANPLATFORM::extract(obj)?.to_owned()),
             "MPLS" => Ok(MPLS::extract(obj)?.to_owned()),
+            "EthFcs" => Ok(EthFcs::extract(obj)?.to_owned()),
+            "HwTimestamp" => Ok(HwTimestamp::extract(obj)?.to_owned()),
+            "HwTimestamp32" => Ok(HwTimestamp32::extract(obj)?.to_owned()),
             _ => Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
                 "{} header not implemented",
-                obj.str()?.to_str()?
+                name
             ))),
         };
         b
@@ -103,11 +744,22 @@ impl ::pyo3::ToPyObject for Box<dyn Header> {
             "ARP" => <ARP>::from(self).into_py(py),
             "Vlan" => <Vlan>::from(self).into_py(py),
             "ICMP" => <ICMP>::from(self).into_py(py),
+            "Igmp" => <Igmp>::from(self).into_py(py),
+            "IgmpV3Report" => <IgmpV3Report>::from(self).into_py(py),
+            "Mld" => <Mld>::from(self).into_py(py),
+            "MldV2Report" => <MldV2Report>::from(self).into_py(py),
             "IPv4" => <IPv4>::from(self).into_py(py),
             "IPv6" => <IPv6>::from(self).into_py(py),
             "UDP" => <UDP>::from(self).into_py(py),
+            "UdpLite" => <UdpLite>::from(self).into_py(py),
             "TCP" => <TCP>::from(self).into_py(py),
             "Vxlan" => <Vxlan>::from(self).into_py(py),
+            "L2tp" => <L2tp>::from(self).into_py(py),
+            "L2tpLength" => <L2tpLength>::from(self).into_py(py),
+            "L2tpTunnel" => <L2tpTunnel>::from(self).into_py(py),
+            "L2tpSequence" => <L2tpSequence>::from(self).into_py(py),
+            "L2tpOffset" => <L2tpOffset>::from(self).into_py(py),
+            "L2tpV3Session" => <L2tpV3Session>::from(self).into_py(py),
             "Dot3" => <Dot3>::from(self).into_py(py),
             "LLC" => <LLC>::from(self).into_py(py),
             "SNAP" => <SNAP>::from(self).into_py(py),
@@ -120,18 +772,760 @@ impl ::pyo3::ToPyObject for Box<dyn Header> {
             "ERSPAN3" => <ERSPAN3>::from(self).into_py(py),
             "ERSPANPLATFORM" => <ERSPANPLATFORM>::from(self).into_py(py),
             "MPLS" => <MPLS>::from(self).into_py(py),
+            "EthFcs" => <EthFcs>::from(self).into_py(py),
+            "HwTimestamp" => <HwTimestamp>::from(self).into_py(py),
+            "HwTimestamp32" => <HwTimestamp32>::from(self).into_py(py),
             _ => panic!("{} header not found", self.name()),
         };
         b
     }
 }
 
+/// Selects the getter type of a field based on whether it is a single bit wide
+#[doc(hidden)]
+pub struct FieldWidth<const IS_BIT: bool>;
+
+/// The width in bits of a field
+#[doc(hidden)]
+pub struct FieldBits<const N: usize>;
+
+/// Implemented by the widths of the fields with `u128` accessors, 65 to 128 bits
+///
+/// ```compile_fail
+/// # use packet_rs::headers::*;
+/// IPv4::new().ttl_u128();
+/// ```
+#[doc(hidden)]
+pub trait WideField {}
+
+macro_rules! wide_field {
+    ($($n: literal)*) => {
+        $(impl WideField for FieldBits<$n> {})*
+    };
+}
+
+wide_field!(
+    65 66 67 68 69 70 71 72 73 74 75 76 77 78 79 80 81 82 83 84 85 86 87 88 89 90 91 92 93 94 95 96
+    97 98 99 100 101 102 103 104 105 106 107 108 109 110 111 112 113 114 115 116 117 118 119 120
+    121 122 123 124 125 126 127 128
+);
+
+/// The type of the const getters of the array headers for a width, `u64` up to 64 bits and
+/// `u128` above
+#[doc(hidden)]
+pub trait FieldInt {
+    type Int;
+}
+
+macro_rules! field_int {
+    ($int: ty; $($n: literal)*) => {
+        $(impl FieldInt for FieldBits<$n> {
+            type Int = $int;
+        })*
+    };
+}
+
+field_int!(
+    u64;
+    1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32 33 34 35
+    36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63 64
+);
+field_int!(
+    u128;
+    65 66 67 68 69 70 71 72 73 74 75 76 77 78 79 80 81 82 83 84 85 86 87 88 89 90 91 92 93 94 95 96
+    97 98 99 100 101 102 103 104 105 106 107 108 109 110 111 112 113 114 115 116 117 118 119 120
+    121 122 123 124 125 126 127 128
+);
+
+/// Converts a raw field value into the type returned by the field getter
+#[doc(hidden)]
+pub trait FieldValue {
+    type Value;
+    fn from_raw(raw: u64) -> Self::Value;
+}
+
+impl FieldValue for FieldWidth<true> {
+    type Value = bool;
+    fn from_raw(raw: u64) -> bool {
+        raw != 0
+    }
+}
+
+impl FieldValue for FieldWidth<false> {
+    type Value = u64;
+    fn from_raw(raw: u64) -> u64 {
+        raw
+    }
+}
+
+/// Sign extend the low bits of a raw field value
+#[doc(hidden)]
+pub const fn sign_extend(raw: u64, bits: usize) -> i64 {
+    let shift = 64 - bits;
+    ((raw << shift) as i64) >> shift
+}
+
+/// Range of the values of a field of bits, signed or not
+fn field_range(bits: usize, signed: bool) -> (i128, i128) {
+    match signed {
+        true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+        false => (0, (1i128 << bits) - 1),
+    }
+}
+
+/// Raw bits of a signed field value, failing if it is out of the range of the field
+#[doc(hidden)]
+pub fn signed_to_raw(field: &str, value: i64, bits: usize) -> Result<u64, crate::PacketError> {
+    let (min, max) = field_range(bits, true);
+    if (value as i128) < min || (value as i128) > max {
+        return Err(crate::PacketError::InvalidValue {
+            field: String::from(field),
+            value: value.to_string(),
+        });
+    }
+    Ok(value as u64 & (u64::MAX >> (64 - bits)))
+}
+
+/// Value of a fixed point field with scale fractional bits
+#[doc(hidden)]
+pub fn fixed_from_raw(raw: u64, bits: usize, scale: u32, signed: bool) -> f64 {
+    let value = match signed {
+        true => sign_extend(raw, bits) as f64,
+        false => raw as f64,
+    };
+    value / (1u128 << scale) as f64
+}
+
+/// Raw bits of a fixed point field value rounded to the nearest step, failing if it is out of
+/// the range of the field
+#[doc(hidden)]
+pub fn fixed_to_raw(
+    field: &str,
+    value: f64,
+    bits: usize,
+    scale: u32,
+    signed: bool,
+) -> Result<u64, crate::PacketError> {
+    let (min, max) = field_range(bits, signed);
+    let x = value * (1u128 << scale) as f64;
+    // round half away from zero, f64::round is not in core
+    let x = if x < 0.0 { x - 0.5 } else { x + 0.5 };
+    // false for NaN too
+    if !(x > min as f64 - 1.0 && x < max as f64 + 1.0) || (x as i128) < min || (x as i128) > max {
+        return Err(crate::PacketError::InvalidValue {
+            field: String::from(field),
+            value: value.to_string(),
+        });
+    }
+    Ok(x as i128 as u64 & (u64::MAX >> (64 - bits)))
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_type {
+    (unsigned $start: literal $end: literal) => {
+        <FieldWidth<{ $start == $end }> as FieldValue>::Value
+    };
+    (signed $start: literal $end: literal) => {
+        i64
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_value {
+    (unsigned $start: literal $end: literal; $raw: expr) => {
+        <FieldWidth<{ $start == $end }> as FieldValue>::from_raw($raw)
+    };
+    (signed $start: literal $end: literal; $raw: expr) => {
+        $crate::headers::sign_extend($raw, $end - $start + 1)
+    };
+}
+
+/// Type and value of the const getters of the array backed headers
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_const {
+    (@type unsigned $start: literal $end: literal) => {
+        <FieldBits<{ $end - $start + 1 }> as FieldInt>::Int
+    };
+    (@type signed $start: literal $end: literal) => {
+        i64
+    };
+    (unsigned $start: literal $end: literal; $raw: expr) => {
+        $raw as <FieldBits<{ $end - $start + 1 }> as FieldInt>::Int
+    };
+    (signed $start: literal $end: literal; $raw: expr) => {
+        $crate::headers::sign_extend($raw as u64, $end - $start + 1)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_arg {
+    (unsigned) => {
+        u64
+    };
+    (signed) => {
+        i64
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_raw {
+    (unsigned $name: ident $field: ident $start: literal $end: literal; $value: expr) => {
+        $value
+    };
+    (signed $name: ident $field: ident $start: literal $end: literal; $value: expr) => {
+        match $crate::headers::signed_to_raw(stringify!($field), $value, $end - $start + 1) {
+            Ok(raw) => raw,
+            Err(e) => panic!("{}: {}", stringify!($name), e),
+        }
+    };
+}
+
+/// Accessors of signed and fixed point fields beyond the getter and setter
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_extra {
+    (slice $order: ident unsigned [] $name: ident $field: ident $start: literal $end: literal) => {};
+    (slice $order: ident signed [] $name: ident $field: ident $start: literal $end: literal) => {};
+    (slice $order: ident $repr: ident [$scale: literal] $name: ident $field: ident $start: literal $end: literal) => {
+        $crate::headers::paste! {
+            /// Value of the fixed point field
+            pub fn [<$field _f64>](&self) -> f64 {
+                let signed = stringify!($repr) == "signed";
+                $crate::headers::fixed_from_raw(self.[<$field _raw>](), $end - $start + 1, $scale, signed)
+            }
+        }
+    };
+    (owned $order: ident unsigned [] $name: ident $field: ident $start: literal $end: literal) => {};
+    (owned $order: ident signed [] $name: ident $field: ident $start: literal $end: literal) => {
+        $crate::field_extra!(@try_set signed $order $name $field $start $end);
+    };
+    (owned $order: ident $repr: ident [$scale: literal] $name: ident $field: ident $start: literal $end: literal) => {
+        $crate::field_extra!(@try_set $repr $order $name $field $start $end);
+        $crate::headers::paste! {
+            /// Value of the fixed point field
+            pub fn [<$field _f64>](&self) -> f64 {
+                let signed = stringify!($repr) == "signed";
+                $crate::headers::fixed_from_raw(self.[<$field _raw>](), $end - $start + 1, $scale, signed)
+            }
+            /// Set the fixed point field to the nearest step of the value
+            ///
+            /// Panics if the value is out of the range of the field, see `try_set_<field>_f64`
+            /// for a fallible version.
+            pub fn [<set_ $field _f64>](&mut self, value: f64) {
+                if let Err(e) = self.[<try_set_ $field _f64>](value) {
+                    panic!("{}: {}", stringify!($name), e);
+                }
+            }
+            /// Set the fixed point field to the nearest step of the value, failing if it is out
+            /// of the range of the field
+            pub fn [<try_set_ $field _f64>](&mut self, value: f64) -> Result<(), $crate::PacketError> {
+                use ::bitfield::BitRange;
+                let signed = stringify!($repr) == "signed";
+                let raw = $crate::headers::fixed_to_raw(
+                    stringify!($field),
+                    value,
+                    $end - $start + 1,
+                    $scale,
+                    signed,
+                )?;
+                self.set_bit_range($end, $start, $crate::field_order!($order; raw, $end - $start + 1));
+                Ok(())
+            }
+        }
+    };
+    (@try_set unsigned $order: ident $name: ident $field: ident $start: literal $end: literal) => {};
+    (@try_set signed $order: ident $name: ident $field: ident $start: literal $end: literal) => {
+        $crate::headers::paste! {
+            /// Set the signed field, failing if the value is out of the range of the field
+            pub fn [<try_set_ $field>](&mut self, value: i64) -> Result<(), $crate::PacketError> {
+                use ::bitfield::BitRange;
+                let raw = $crate::headers::signed_to_raw(stringify!($field), value, $end - $start + 1)?;
+                self.set_bit_range($end, $start, $crate::field_order!($order; raw, $end - $start + 1));
+                Ok(())
+            }
+        }
+    };
+}
+
 #[pyclass]
 #[derive(Clone)]
 pub struct ProtectedArray {
     pub a: Arc<Mutex<Vec<u8>>>,
 }
 
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "std")]
+macro_rules! std_only {
+    ($($t: tt)*) => {
+        $($t)*
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "std"))]
+macro_rules! std_only {
+    ($($t: tt)*) => {};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! bind_header {
+    ($name: ident $size: literal $lower: ident $lfield: ident $value: expr $(, $next: ident)?) => {
+        $crate::headers::paste! {
+            impl $name {
+                /// Binding of the header after the layer selecting it
+                pub const BINDING: $crate::bind::Binding = $crate::bind::Binding {
+                    lower: stringify!($lower),
+                    field: stringify!($lfield),
+                    value: $value,
+                    name: stringify!($name),
+                    size: $size,
+                    next: $crate::bind_next!($($next)?),
+                    from: |x| Box::new($name::from(x.to_vec())),
+                    slice: |x| Box::new([<$name Slice>]::from(x)),
+                };
+                /// Add the binding of the header again, after it was unbound
+                ///
+                /// The binding is declared with the header, the parsers use it without this call.
+                pub fn register() {
+                    $crate::bind::bind($name::BINDING);
+                }
+            }
+        }
+        $crate::bind::inventory::submit! { $name::BINDING }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! bind_next {
+    () => {
+        None
+    };
+    ($next: ident) => {
+        Some(stringify!($next))
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_flag {
+    ($want: ident;) => {
+        false
+    };
+    (reserved; reserved) => {
+        true
+    };
+    (checksum; checksum) => {
+        true
+    };
+    ($want: ident; $other: ident) => {
+        false
+    };
+}
+
+/// Kind of a field from its kind in the field table and its flag
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_kind {
+    (bytes; $($flag: ident)?) => {
+        $crate::headers::FieldKind::Bytes
+    };
+    (num; mac) => {
+        $crate::headers::FieldKind::Mac
+    };
+    (num; ipv4) => {
+        $crate::headers::FieldKind::Ipv4
+    };
+    (num; ipv6) => {
+        $crate::headers::FieldKind::Ipv6
+    };
+    (num; $(reserved)? $(checksum)?) => {
+        $crate::headers::FieldKind::Number
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_order {
+    (be; $value: expr, $bits: expr) => {
+        $value
+    };
+    (le; $value: expr, $bits: expr) => {
+        $crate::headers::SwapField::swap_field($value, $bits)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_le {
+    (be) => {
+        false
+    };
+    (le) => {
+        true
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_le_check {
+    (be $name: ident $field: ident $start: literal $end: literal) => {};
+    (le $name: ident $field: ident $start: literal $end: literal) => {
+        const _: () = assert!(
+            $start % 8 == 0 && ($end + 1) % 8 == 0,
+            concat!(stringify!($name), ".", stringify!($field), " is little endian but not byte aligned")
+        );
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_name {
+    // the variants of an `as Type { .. }` field are named as the other values, ECHO_REQUEST
+    ($value: expr; { @typed $($val: literal => $variant: ident),* }) => {
+        $crate::headers::paste! {
+            match $value {
+                $($val => Some(stringify!([<$variant:snake:upper>])),)*
+                _ => None,
+            }
+        }
+    };
+    ($value: expr; { $($val: literal => $vname: ident),* $(,)? }) => {
+        match $value {
+            $($val => Some(stringify!($vname)),)*
+            _ => None,
+        }
+    };
+}
+
+/// Names of the bits of a field declared with `as flags { .. }`, for its [`FieldInfo`]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_bits {
+    ([$($bit: ident)*]) => {
+        &[$(stringify!($bit)),*]
+    };
+    ($($names: tt)?) => {
+        &[]
+    };
+}
+
+/// Names of the values of a field declared with `enum { .. }` or `as Type { .. }`, for its
+/// [`FieldInfo`]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_values {
+    ({ @typed $($val: literal => $variant: ident),* }) => {
+        $crate::headers::paste! {
+            &[$(($val, stringify!([<$variant:snake:upper>]))),*]
+        }
+    };
+    ({ $($val: literal => $vname: ident),* $(,)? }) => {
+        &[$(($val, stringify!($vname))),*]
+    };
+    ($($names: tt)?) => {
+        &[]
+    };
+}
+
+/// Former name of a field and its deprecated slice getter, the owned accessors are written in
+/// the `#[pymethods]` block of `make_header!`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_alias {
+    (@info) => {
+        None
+    };
+    (@info $old: ident) => {
+        Some(stringify!($old))
+    };
+    (slice $repr: ident $field: ident $old: ident $start: literal $end: literal) => {
+        #[doc = concat!("Renamed to `", stringify!($field), "`")]
+        #[deprecated]
+        pub fn $old(&self) -> $crate::field_type!($repr $start $end) {
+            self.$field()
+        }
+    };
+}
+
+/// Name of the value of a field for `show`, the set bits of a flags field
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_label {
+    ($value: expr; [$($bit: ident)*]) => {
+        $crate::headers::flag_names($value, &[$(stringify!($bit)),*])
+    };
+    ($value: expr; $names: tt) => {
+        $crate::field_name!($value; $names)
+    };
+}
+
+/// Enum of the values of a field declared with `as Type { Variant = value, .. }`, or struct of
+/// the bits of a field declared with `as flags { .. }`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_enum {
+    (() $name: ident $field: ident $start: literal $end: literal $($names: tt)?) => {};
+    (([$($bit: ident)*]) $name: ident $field: ident $start: literal $end: literal) => {
+        const _: () = assert!(
+            [$(stringify!($bit)),*].len() == $end - $start + 1,
+            concat!(stringify!($name), ".", stringify!($field), " does not name each of its bits")
+        );
+        $crate::headers::paste! {
+            #[doc = concat!("Bits of `", stringify!($name), ".", stringify!($field), "`")]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+            pub struct [<$name $field:camel>](u64);
+            impl [<$name $field:camel>] {
+                $crate::field_flags!(@consts $start $end ($start) $($bit)*);
+                /// No bit set
+                pub const fn empty() -> Self {
+                    Self(0)
+                }
+                /// The bits of the field, ignoring the bits beyond its size
+                pub const fn from_bits(bits: u64) -> Self {
+                    Self(bits & (u64::MAX >> (64 - ($end - $start + 1))))
+                }
+                /// Value of the field
+                pub const fn bits(&self) -> u64 {
+                    self.0
+                }
+                pub const fn is_empty(&self) -> bool {
+                    self.0 == 0
+                }
+                /// True if all the bits of `other` are set
+                pub const fn contains(&self, other: Self) -> bool {
+                    self.0 & other.0 == other.0
+                }
+                pub fn insert(&mut self, other: Self) {
+                    self.0 |= other.0;
+                }
+                pub fn remove(&mut self, other: Self) {
+                    self.0 &= !other.0;
+                }
+                /// Insert or remove the bits of `other`
+                pub fn set(&mut self, other: Self, value: bool) {
+                    match value {
+                        true => self.insert(other),
+                        false => self.remove(other),
+                    }
+                }
+            }
+            impl ::core::ops::BitOr for [<$name $field:camel>] {
+                type Output = Self;
+                fn bitor(self, other: Self) -> Self {
+                    Self(self.0 | other.0)
+                }
+            }
+            impl ::core::ops::BitOrAssign for [<$name $field:camel>] {
+                fn bitor_assign(&mut self, other: Self) {
+                    self.0 |= other.0;
+                }
+            }
+            impl ::core::ops::BitAnd for [<$name $field:camel>] {
+                type Output = Self;
+                fn bitand(self, other: Self) -> Self {
+                    Self(self.0 & other.0)
+                }
+            }
+            impl From<[<$name $field:camel>]> for u64 {
+                fn from(value: [<$name $field:camel>]) -> u64 {
+                    value.0
+                }
+            }
+            impl ::core::fmt::Display for [<$name $field:camel>] {
+                /// The names of the set bits separated by `|`, nothing if no bit is set
+                fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                    let names = $crate::headers::flag_names(self.0, &[$(stringify!($bit)),*]);
+                    f.write_str(&names.unwrap_or_default())
+                }
+            }
+        }
+    };
+    (
+        ($ty: ident) $name: ident $field: ident $start: literal $end: literal
+        { @typed $($val: literal => $variant: ident),* }
+    ) => {
+        #[doc = concat!("Values of `", stringify!($name), ".", stringify!($field), "`")]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u64)]
+        pub enum $ty {
+            $($variant = $val,)*
+        }
+        impl ::core::convert::TryFrom<u64> for $ty {
+            type Error = u64;
+            /// The variant of the value, the value itself if it has no variant
+            fn try_from(value: u64) -> Result<$ty, u64> {
+                match value {
+                    $($val => Ok($ty::$variant),)*
+                    _ => Err(value),
+                }
+            }
+        }
+        impl From<$ty> for u64 {
+            fn from(value: $ty) -> u64 {
+                value as u64
+            }
+        }
+        impl ::core::fmt::Display for $ty {
+            /// The name of the value, as `<field>_name()` and `show`
+            fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                f.write_str($crate::field_name!(*self as u64; { @typed $($val => $variant),* }).unwrap())
+            }
+        }
+    };
+}
+
+/// Constants and accessors of the bits of a flags field, the first bit being the most significant
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_flags {
+    (@consts $start: literal $end: literal ($pos: expr)) => {};
+    (@consts $start: literal $end: literal ($pos: expr) $bit: ident $($rest: ident)*) => {
+        $crate::headers::paste! {
+            pub const [<$bit:upper>]: Self = Self(1 << ($end - ($pos)));
+        }
+        $crate::field_flags!(@consts $start $end ($pos + 1) $($rest)*);
+    };
+    (@get ($pos: expr)) => {};
+    (@get ($pos: expr) $bit: ident $($rest: ident)*) => {
+        pub fn $bit(&self) -> bool {
+            use ::bitfield::BitRange;
+            BitRange::<u64>::bit_range(self, $pos, $pos) == 1
+        }
+        $crate::field_flags!(@get ($pos + 1) $($rest)*);
+    };
+    (@set ($pos: expr)) => {};
+    (@set ($pos: expr) $bit: ident $($rest: ident)*) => {
+        $crate::headers::paste! {
+            pub fn [<set_ $bit>](&mut self, value: bool) {
+                use ::bitfield::BitRange;
+                self.set_bit_range($pos, $pos, value as u64);
+            }
+        }
+        $crate::field_flags!(@set ($pos + 1) $($rest)*);
+    };
+}
+
+/// Typed accessors of a field declared with `as Type { .. }` or `as flags { .. }`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_typed {
+    (slice () $order: ident $name: ident $field: ident $start: literal $end: literal) => {};
+    (owned () $order: ident $name: ident $field: ident $start: literal $end: literal) => {};
+    (slice ([$($bit: ident)*]) $order: ident $name: ident $field: ident $start: literal $end: literal) => {
+        $crate::headers::paste! {
+            /// Bits of the field
+            pub fn [<$field _flags>](&self) -> [<$name $field:camel>] {
+                [<$name $field:camel>]::from_bits(self.[<$field _raw>]())
+            }
+        }
+        $crate::field_flags!(@get ($start) $($bit)*);
+    };
+    (owned ([$($bit: ident)*]) $order: ident $name: ident $field: ident $start: literal $end: literal) => {
+        $crate::field_typed!(slice ([$($bit)*]) $order $name $field $start $end);
+        $crate::headers::paste! {
+            /// Set all the bits of the field
+            pub fn [<set_ $field _flags>](&mut self, value: [<$name $field:camel>]) {
+                self.[<set_ $field>](value.bits());
+            }
+        }
+        $crate::field_flags!(@set ($start) $($bit)*);
+    };
+    (slice ($ty: ident) $order: ident $name: ident $field: ident $start: literal $end: literal) => {
+        $crate::headers::paste! {
+            /// Value of the field as its enum, the raw value if it has no variant
+            pub fn [<$field _typed>](&self) -> Result<$ty, u64> {
+                <$ty as ::core::convert::TryFrom<u64>>::try_from(self.[<$field _raw>]())
+            }
+        }
+    };
+    (owned ($ty: ident) $order: ident $name: ident $field: ident $start: literal $end: literal) => {
+        $crate::field_typed!(slice ($ty) $order $name $field $start $end);
+        $crate::headers::paste! {
+            /// Set the field to a variant of its enum
+            pub fn [<set_ $field _typed>](&mut self, value: $ty) {
+                use ::bitfield::BitRange;
+                let raw = u64::from(value);
+                self.set_bit_range($end, $start, $crate::field_order!($order; raw, $end - $start + 1));
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_row {
+    (
+        $hdr: ident, $out: ident,
+        num $order: ident $repr: ident [$($scale: literal)?]
+        $field: ident $start: literal $end: literal {$($names: tt)?}
+    ) => {
+        write!($out, "{:20}: {:4} : ", stringify!($field), $end - $start + 1).unwrap();
+        if (($end - $start + 1) <= 8) {
+            let x: u8 = $hdr.bit_range($end, $start) as u8;
+            write!($out, "{:02x}", x).unwrap();
+        } else if (($end - $start + 1)%8 == 0){
+            let d = ($end - $start + 1)/8;
+            for i in ($start..(d*8 + $start)).step_by(8) {
+                let x: u8 = $hdr.bit_range(i + 7, i) as u8;
+                write!($out, "{:02x} ", x).unwrap();
+            }
+        } else {
+            let d = ($end - $start + 1)/8;
+            let r = ($end - $start + 1)%8;
+            for i in ($start..(d*8 + $start)).step_by(8) {
+                let x: u8 = $hdr.bit_range(i + 7, i) as u8;
+                write!($out, "{:02x} ", x).unwrap();
+            }
+            let x: u8 = $hdr.bit_range($end, $end - r) as u8;
+            write!($out, "{:02x}", x).unwrap();
+        }
+        $(
+        let value: u64 = $hdr.bit_range($end, $start);
+        if let Some(n) = $crate::field_label!($crate::field_order!($order; value, $end - $start + 1); $names) {
+            write!($out, " ({})", n).unwrap();
+        }
+        )?
+        $crate::field_row!(@value $hdr, $out, $order $repr [$($scale)?] $start $end);
+        $out.push('\n');
+    };
+    (@value $hdr: ident, $out: ident, $order: ident unsigned [] $start: literal $end: literal) => {};
+    (@value $hdr: ident, $out: ident, $order: ident signed [] $start: literal $end: literal) => {
+        let raw: u64 = $hdr.bit_range($end, $start);
+        let value = $crate::field_order!($order; raw, $end - $start + 1);
+        write!($out, " ({})", $crate::headers::sign_extend(value, $end - $start + 1)).unwrap();
+    };
+    (@value $hdr: ident, $out: ident, $order: ident $repr: ident [$scale: literal] $start: literal $end: literal) => {
+        let raw: u64 = $hdr.bit_range($end, $start);
+        let value = $crate::field_order!($order; raw, $end - $start + 1);
+        let signed = stringify!($repr) == "signed";
+        let value = $crate::headers::fixed_from_raw(value, $end - $start + 1, $scale, signed);
+        write!($out, " ({})", value).unwrap();
+    };
+    (
+        $hdr: ident, $out: ident,
+        bytes $order: ident $repr: ident [] $field: ident $start: literal $end: literal {}
+    ) => {
+        write!($out, "{:20}: {:4} : ", stringify!($field), $end - $start + 1).unwrap();
+        for i in ($start..=$end).step_by(8).take($crate::headers::SHOW_OCTETS) {
+            let x: u8 = $hdr.bit_range(i + 7, i) as u8;
+            write!($out, "{:02x} ", x).unwrap();
+        }
+        if ($end - $start + 1) / 8 > $crate::headers::SHOW_OCTETS {
+            $out.push_str("...");
+        }
+        $out.push('\n');
+    };
+}
+
 /// Defines a header
 ///
 /// This macro will generate get and set methods for each field of the header.
@@ -145,6 +1539,58 @@ pub struct ProtectedArray {
 /// * This is followed by a comma separated field list with each field specifying the name, start and end bit location
 /// * Lastly, an optional vector is allowed to specify the default values of the header fields. The size of the vector has to match the header length
 ///
+/// A field followed by `= <expr>` takes the value of the expression in `new()`, a const like
+/// `etype: 96-111 = ETHERTYPE_IPV4` works too. Fields without one are zero, or the byte of the
+/// default vector when the header has one. A `[checksum]` field left without a default, and zero
+/// in the default vector, is computed over the bytes of the default header. The checksums covering
+/// a pseudo header or a payload default to `= 0` instead.
+///
+/// Field getters return a `u64`, except for single bit fields (start and end bit are the same) which return a `bool`.
+/// The raw `u64` value of any field is available with the `<field>_raw()` getter.
+///
+/// A field followed by `as bytes`, as in `chaddr: 224-351 as bytes`, is an opaque run of bytes:
+/// `<field>()` returns its bytes and `set_<field>(&[u8])` copies a value no longer than the
+/// field to its start, zero filling the rest. `show` prints the first 16 bytes and `...`. A field
+/// followed by `as pad` only reserves its bits, it has no accessors and is always written as zero.
+///
+/// A field followed by `le`, as in `timestamp: 64-127 le`, is little endian on the wire. Its
+/// getters and setters swap the bytes of the value, as [`Header::get_field`] and
+/// [`Header::set_field`] do, while the header keeps the wire order. A little endian field must
+/// be byte aligned.
+///
+/// A field followed by `signed`, after `le` if any, is two's complement: its getter
+/// returns an `i64` and its setter takes one, panicking if it is out of the range of the field
+/// while `try_set_<field>` fails. A field followed by `fixed(16)`, or `signed fixed(16)`, is a
+/// fixed point value with 16 fractional bits, `<field>_f64()` and `set_<field>_f64` convert it
+/// from and to `f64`. `show` prints the value of these fields after the raw bytes.
+///
+/// A field followed by `[reserved]` must be zero, [`Header::validate_reserved`] checks it.
+/// A field followed by `[checksum]` is the checksum of the header, see [`Header::checksum_field`].
+/// A field followed by `[mac]`, `[ipv4]` or `[ipv6]` holds an address, [`Header::set_field_str`]
+/// parses it from its usual notation, see [`FieldKind`].
+/// A field followed by `alias(old_name)`, before its default, was renamed: `old_name()` and
+/// `set_old_name` remain as deprecated accessors and the lookups by name accept both names, see
+/// [`FieldInfo::is_named`].
+/// A field followed by `enum { 6 => TCP, 17 => UDP }` names some of its values, the
+/// `<field>_name()` getter returns the name of the current value and `show` prints it after the value.
+/// A field followed by `as ArpOp { Request = 1, Reply = 2 }` also declares the `ArpOp` enum, with
+/// `TryFrom<u64>` and `Into<u64>`: `<field>_typed()` returns the variant of the value or the raw
+/// value if it has none, and `set_<field>_typed` takes a variant. The numeric accessors stay.
+/// `<field>_name()`, `show` and the `Display` of the enum name the variants in upper snake case,
+/// as `REQUEST`, like the values of an `enum { .. }` field.
+/// A field followed by `as flags { cwr, ece, urg, ack, psh, rst, syn, fin }` names each of its
+/// bits, the first being the most significant: `ack()` and `set_ack` access one bit while
+/// `<field>_flags()` and `set_<field>_flags` take the whole field as the `<Header><Field>` struct,
+/// as `TCPFlags::SYN | TCPFlags::ACK`. `show` prints the names of the set bits, as `SYN|ACK`.
+///
+/// A trailing `bind(Ether.etype == 0x9988, next = etype)`, after the default values if any,
+/// binds the header after the layer whose field holds the value, see [`bind`](crate::bind).
+/// `next` names the field of the header selecting the layer after it. The generated `BINDING`
+/// constant holds the binding, which both parsers use without any call from the application.
+///
+/// With the `python-module` feature the header is also a `#[pyclass]` with its getters, setters and
+/// helpers exposed through `#[pymethods]`, otherwise only the plain Rust struct is generated.
+///
 /// # Example
 ///
 /// ```rust
@@ -161,15 +1607,222 @@ pub struct ProtectedArray {
 /// )
 /// vec![0x0, 0xa, 0x8, 0x0]
 /// );
+/// make_header!(
+/// Shim 4
+/// (
+///     version: 0-3 = 1,
+///     reserved: 4-15 [reserved],
+///     proto: 16-31 = 0x0800
+/// )
+/// );
 /// ```
 #[macro_export]
 macro_rules! make_header {
     (
-        $name: ident $size: literal
-        ( $($field: ident: $start: literal-$end: literal),* )
-        $x:expr
+        $name: ident $size: literal
+        ( $($fields: tt)* )
+        $x:expr,
+        bind($lower: ident . $lfield: ident == $value: expr $(, next = $next: ident)?)
+    ) => {
+        make_header!(
+            $name $size
+            ( $($fields)* )
+            $x
+        );
+        $crate::bind_header!($name $size $lower $lfield $value $(, $next)?);
+    };
+    (
+        $name: ident $size: literal
+        ( $($fields: tt)* )
+        bind($lower: ident . $lfield: ident == $value: expr $(, next = $next: ident)?)
+    ) => {
+        make_header!(
+            $name $size
+            ( $($fields)* )
+        );
+        $crate::bind_header!($name $size $lower $lfield $value $(, $next)?);
+    };
+    (
+        $name: ident $size: literal
+        ( $($fields: tt)* )
+        $x:expr
+    ) => {
+        make_header!(
+            @split $name $size ($x) [] [] [] [];
+            $($fields)*,
+        );
+    };
+    // sort the fields by kind, keeping the numeric and byte fields in order for the field table
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        $field: ident: $start: literal-$end: literal as bytes, $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x)
+            [$($num)*] [$($oct)* ($field $start $end)] [$($pad)*]
+            [$($info)* (bytes be unsigned [] $field $start $end [] {} [])];
+            $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        $field: ident: $start: literal-$end: literal as pad, $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x)
+            [$($num)*] [$($oct)*] [$($pad)* ($start $end)] [$($info)*];
+            $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        $field: ident: $start: literal-$end: literal as flags { $($bit: ident),* $(,)? }
+        $(= $default: expr)?, $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x)
+            [$($num)* { be unsigned [] $field: $start-$end as [$($bit)*] $(= $default)? }]
+            [$($oct)*] [$($pad)*]
+            [$($info)* (num be unsigned [] $field $start $end [] {[$($bit)*]} [])];
+            $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        $field: ident: $start: literal-$end: literal $([$flag: ident])? $(enum $names: tt)?
+        $(= $default: expr)?, $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x)
+            [$($num)* { be unsigned [] $field: $start-$end $([$flag])? $(enum $names)? $(= $default)? }]
+            [$($oct)*] [$($pad)*]
+            [$($info)* (num be unsigned [] $field $start $end [$($flag)?] {$($names)?} [])];
+            $($rest)*
+        );
+    };
+    // byte order, then signed and fixed point annotations
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        $field: ident: $start: literal-$end: literal le $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x) [$($num)*] [$($oct)*] [$($pad)*] [$($info)*];
+            @repr le $field: $start-$end $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        $field: ident: $start: literal-$end: literal $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x) [$($num)*] [$($oct)*] [$($pad)*] [$($info)*];
+            @repr be $field: $start-$end $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        @repr $order: ident $field: ident: $start: literal-$end: literal
+        signed fixed($scale: literal) $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x) [$($num)*] [$($oct)*] [$($pad)*] [$($info)*];
+            @field $order signed [$scale] $field: $start-$end $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        @repr $order: ident $field: ident: $start: literal-$end: literal signed $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x) [$($num)*] [$($oct)*] [$($pad)*] [$($info)*];
+            @field $order signed [] $field: $start-$end $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        @repr $order: ident $field: ident: $start: literal-$end: literal
+        fixed($scale: literal) $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x) [$($num)*] [$($oct)*] [$($pad)*] [$($info)*];
+            @field $order unsigned [$scale] $field: $start-$end $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        @repr $order: ident $field: ident: $start: literal-$end: literal $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x) [$($num)*] [$($oct)*] [$($pad)*] [$($info)*];
+            @field $order unsigned [] $field: $start-$end $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+        @field $order: ident $repr: ident [$($scale: literal)?]
+        $field: ident: $start: literal-$end: literal $([$flag: ident])?
+        $(as $ty: ident { $($variant: ident = $val: literal),* $(,)? })? $(enum $names: tt)?
+        $(alias($old: ident))? $(= $default: expr)?, $($rest: tt)*
+    ) => {
+        make_header!(
+            @split $name $size ($x)
+            [$($num)* {
+                $order $repr [$($scale)?]
+                $field: $start-$end $([$flag])?
+                $(as $ty enum { @typed $($val => $variant),* })? $(enum $names)?
+                $(alias $old)? $(= $default)?
+            }]
+            [$($oct)*] [$($pad)*]
+            [$($info)* (
+                num $order $repr [$($scale)?] $field $start $end [$($flag)?]
+                {$($names)? $({ @typed $($val => $variant),* })?} [$($old)?]
+            )];
+            $($rest)*
+        );
+    };
+    (
+        @split $name: ident $size: literal ($x: expr)
+        [$($num: tt)*] [$($oct: tt)*] [$($pad: tt)*] [$($info: tt)*];
+    ) => {
+        make_header!(@build $name $size ($x) [$($num)*] [$($oct)*] [$($pad)*] [$($info)*]);
+    };
+    (
+        @build $name: ident $size: literal ($x: expr)
+        [$({
+            $order: ident $repr: ident [$($scale: literal)?]
+            $field: ident: $start: literal-$end: literal $([$flag: ident])? $(as $ty: tt)?
+            $(enum $names: tt)? $(alias $old: ident)? $(= $default: expr)?
+        })*]
+        [$(($ofield: ident $ostart: literal $oend: literal))*]
+        [$(($pstart: literal $pend: literal))*]
+        [$((
+            $kind: ident $iorder: ident $irepr: ident [$($iscale: literal)?]
+            $ifield: ident $istart: literal $iend: literal [$($iflag: ident)?] {$($inames: tt)?}
+            [$($ialias: ident)?]
+        ))*]
     ) => {
         paste! {
+            $(
+            const _: () = assert!(
+                $ostart % 8 == 0 && ($oend + 1) % 8 == 0,
+                concat!(stringify!($name), ".", stringify!($ofield), " is not byte aligned")
+            );
+            )*
+            $(
+            $crate::field_le_check!($order $name $field $start $end);
+            $crate::field_enum!(($($ty)?) $name $field $start $end $($names)?);
+            )*
             pub struct [<$name Slice>]<'a> {
                 slice: &'a [u8]
             }
@@ -185,7 +1838,7 @@ macro_rules! make_header {
                     [<$name Slice>] {
                         // guaranteed to be atleast min size after above check
                         slice: unsafe {
-                            std::slice::from_raw_parts(
+                            ::core::slice::from_raw_parts(
                                 slice.as_ptr(),
                                 $name::size()
                             )
@@ -193,11 +1846,37 @@ macro_rules! make_header {
                     }
                 }
                 $(
-                pub fn $field(&self) -> u64 {
+                pub fn $field(&self) -> $crate::field_type!($repr $start $end) {
+                    $crate::field_value!($repr $start $end; self.[<$field _raw>]())
+                }
+                pub fn [<$field _raw>](&self) -> u64 {
                     use ::bitfield::BitRange;
-                    let raw_value: u64 = self.bit_range($end, $start);
+                    let raw_value: u64 = $crate::field_order!(
+                        $order;
+                        BitRange::<u64>::bit_range(self, $end, $start),
+                        $end - $start + 1
+                    );
                     ::bitfield::Into::into(raw_value)
                 }
+                $(
+                /// Name of the value of the field, None if the value has no name
+                pub fn [<$field _name>](&self) -> Option<&'static str> {
+                    $crate::field_name!(self.[<$field _raw>](); $names)
+                }
+                )?
+                )*
+                $($(
+                $crate::field_alias!(slice $repr $field $old $start $end);
+                )?)*
+                $(
+                $crate::field_extra!(slice $order $repr [$($scale)?] $name $field $start $end);
+                $crate::field_typed!(slice ($($ty)?) $order $name $field $start $end);
+                )*
+                $(
+                /// Bytes of the field
+                pub fn $ofield(&self) -> &'a [u8] {
+                    &self.slice[$ostart / 8..($oend + 1) / 8]
+                }
                 )*
                 pub fn bytes(&self, msb: usize, lsb: usize) -> Vec<u8> {
                     let bit_len = ::bitfield::size_of::<u8>() * 8;
@@ -212,6 +1891,10 @@ macro_rules! make_header {
                 pub const fn size() -> usize {
                     $size
                 }
+                /// Return true if a buffer of buf_len bytes holds the header
+                pub const fn fits(buf_len: usize) -> bool {
+                    buf_len >= $size
+                }
                 pub const fn len(&self) -> usize {
                     $size
                 }
@@ -221,65 +1904,144 @@ macro_rules! make_header {
                 pub fn as_slice(&self) -> &[u8] {
                     self.slice
                 }
-                pub fn show(&self) -> () {
-                    println!("#### {:16} {} {}", stringify!($name), "Size  ", "Data");
-                    println!("-------------------------------------------");
-                    $(
-                    print!("{:20}: {:4} : ", stringify!($field), $end - $start + 1);
-                    if (($end - $start + 1) <= 8) {
-                        let x: u8 = self.bit_range($end, $start) as u8;
-                        print!("{:02x}", x);
-                    } else if (($end - $start + 1)%8 == 0){
-                        let d = ($end - $start + 1)/8;
-                        for i in ($start..(d*8 + $start)).step_by(8) {
-                            let x: u8 = self.bit_range(i + 7, i) as u8;
-                            print!("{:02x} ", x);
-                        }
-                    } else {
-                        let d = ($end - $start + 1)/8;
-                        let r = ($end - $start + 1)%8;
-                        for i in ($start..(d*8 + $start)).step_by(8) {
-                            let x: u8 = self.bit_range(i + 7, i) as u8;
-                            print!("{:02x} ", x);
-                        }
-                        let x: u8 = self.bit_range($end, $end - r) as u8;
-                        print!("{:02x}", x);
+                $crate::std_only! {
+                    pub fn show(&self) {
+                        print!("{}", self.dump());
                     }
-                    println!();
+                }
+                /// Return the output of `show` as a string
+                pub fn dump(&self) -> String {
+                    format!(
+                        "#### {:16} {} {}\n-------------------------------------------\n{}",
+                        stringify!($name),
+                        "Size  ",
+                        "Data",
+                        self.field_table()
+                    )
+                }
+                pub fn field_table(&self) -> String {
+                    use ::core::fmt::Write;
+                    let mut out = String::new();
+                    $(
+                    $crate::field_row!(
+                        self, out, $kind $iorder $irepr [$($iscale)?] $ifield $istart $iend {$($inames)?}
+                    );
                     )*
+                    out
+                }
+            }
+            /// The header in a byte array, built and read in const contexts
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            pub struct [<$name Array>]([u8; $size]);
+            impl [<$name Array>] {
+                pub const fn from_array(bytes: [u8; $size]) -> [<$name Array>] {
+                    [<$name Array>](bytes)
+                }
+                pub const fn as_array(&self) -> &[u8; $size] {
+                    &self.0
+                }
+                pub const fn size() -> usize {
+                    $size
+                }
+                pub fn as_slice(&self) -> [<$name Slice>]<'_> {
+                    [<$name Slice>]::from(&self.0)
+                }
+                /// Copy the bytes into an owned header
+                pub fn to_header(&self) -> $name {
+                    $name::from(self.0.to_vec())
+                }
+                $(
+                /// Value of the field, a field of one bit as 0 or 1, a `u128` for the fields wider
+                /// than 64 bits
+                pub const fn $field(&self) -> $crate::field_const!(@type $repr $start $end) {
+                    let raw = read_field(&self.0, $end, $start, $crate::field_le!($order));
+                    $crate::field_const!($repr $start $end; raw)
+                }
+                )*
+            }
+            $(
+            /// The u128 accessors of the field, only implemented for fields of 65 to 128 bits
+            #[doc(hidden)]
+            pub trait [<$name $field:camel U128>] {
+                /// Value of the field as a u128
+                fn [<$field _u128>](&self) -> u128;
+                /// Set the field from a u128
+                fn [<set_ $field _u128>](&mut self, value: u128);
+            }
+            impl [<$name $field:camel U128>] for $name
+            where
+                for<'w> FieldBits<{ $end - $start + 1 }>: WideField,
+            {
+                fn [<$field _u128>](&self) -> u128 {
+                    $crate::field_order!($order; get_field_u128(self, $end, $start), $end - $start + 1)
+                }
+                fn [<set_ $field _u128>](&mut self, value: u128) {
+                    set_field_u128(self, $end, $start, $crate::field_order!($order; value, $end - $start + 1))
                 }
             }
+            /// The u128 getter of the field, only implemented for fields of 65 to 128 bits
+            #[doc(hidden)]
+            pub trait [<$name Slice $field:camel U128>] {
+                /// Value of the field as a u128
+                fn [<$field _u128>](&self) -> u128;
+            }
+            impl <'a>[<$name Slice $field:camel U128>] for [<$name Slice>]<'a>
+            where
+                for<'w> FieldBits<{ $end - $start + 1 }>: WideField,
+            {
+                fn [<$field _u128>](&self) -> u128 {
+                    $crate::field_order!($order; get_field_u128(self, $end, $start), $end - $start + 1)
+                }
+            }
+            )*
             impl <'a>::bitfield::BitRange<u64> for [<$name Slice>]<'a> {
                 fn bit_range(&self, msb: usize, lsb: usize) -> u64 {
-                    let bit_len = ::bitfield::size_of::<u8>() * 8;
-                    let value_bit_len = ::bitfield::size_of::<u64>() * 8;
-                    let mut value: u64 = 0;
-                    for i in lsb..=msb {
-                        value <<= 1;
-                        let map = self.slice;
-                        value |= ((map[i / bit_len] >> (bit_len - i % bit_len - 1)) & 1) as u64;
+                    if let Err(e) = check_bit_range(self.slice.len(), msb, lsb) {
+                        panic!("{} bits {}..={}: {}", stringify!($name), msb, lsb, e);
                     }
-                    value << (value_bit_len - (msb - lsb + 1)) >> (value_bit_len - (msb - lsb + 1))
+                    read_bits(self.slice, msb, lsb)
                 }
                 fn set_bit_range(&mut self, _msb: usize, _lsb: usize, _value: u64) {
                     ()
                 }
             }
+            impl <'a>::core::fmt::LowerHex for [<$name Slice>]<'a> {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                    fmt_hex(self.slice, f, false)
+                }
+            }
+            impl <'a>::core::fmt::UpperHex for [<$name Slice>]<'a> {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                    fmt_hex(self.slice, f, true)
+                }
+            }
             impl <'a>Header for [<$name Slice>]<'a> {
-                fn show(&self) {
-                    self.show();
+                $crate::std_only! {
+                    fn show(&self) {
+                        self.show();
+                    }
+                }
+                fn field_table(&self) -> String {
+                    self.field_table()
                 }
                 fn to_vec(&self) -> Vec<u8> {
-                    self.as_slice().to_vec()
+                    let mut data = self.as_slice().to_vec();
+                    clear_padding(&mut data, $name::PADDING);
+                    data
                 }
                 fn as_slice(&self) -> &[u8] {
                     self.as_slice()
                 }
-                fn clone(&self) -> Box<dyn Header> {
-                    unimplemented!();
+                fn write_into(&self, dst: &mut [u8]) -> Result<usize, $crate::PacketError> {
+                    let len = write_bytes(self.slice, dst)?;
+                    clear_padding(&mut dst[..len], $name::PADDING);
+                    Ok(len)
+                }
+                fn clone_box(&self) -> Box<dyn Header> {
+                    Box::new($name::from(Header::to_vec(self)))
                 }
                 fn to_owned(self) -> Box<dyn Header> {
-                    unimplemented!();
+                    Box::new($name::from(Header::to_vec(&self)))
                 }
                 fn name(&self) -> &str {
                     self.name()
@@ -293,54 +2055,328 @@ macro_rules! make_header {
                 fn as_any_mut(&mut self) -> &mut dyn Any {
                     unimplemented!();
                 }
+                fn into_any(self: Box<Self>) -> Box<dyn Any> {
+                    unimplemented!();
+                }
+                fn fields(&self) -> &'static [FieldInfo] {
+                    $name::FIELDS
+                }
+                fn get_field(&self, name: &str) -> Option<u64> {
+                    $name::FIELDS
+                        .iter()
+                        .find(|f| f.is_named(name))
+                        .map(|f| f.wire_order(self.bit_range(f.msb, f.lsb)))
+                }
+                fn get_field_bytes(&self, name: &str) -> Option<Vec<u8>> {
+                    $name::FIELDS
+                        .iter()
+                        .find(|f| f.is_named(name))
+                        .map(|f| f.wire_order_bytes(&get_field_bits(self, f.msb, f.lsb)))
+                }
+                fn src_bytes(&self) -> Option<Vec<u8>> {
+                    self.get_field_bytes("src")
+                }
+                fn dst_bytes(&self) -> Option<Vec<u8>> {
+                    self.get_field_bytes("dst")
+                }
+                fn summary(&self) -> String {
+                    $crate::summary::header_summary(self)
+                }
+                fn validate_reserved(&self) -> bool {
+                    $name::FIELDS
+                        .iter()
+                        .filter(|f| f.reserved)
+                        .all(|f| get_field_bits(self, f.msb, f.lsb).iter().all(|b| *b == 0))
+                }
+                fn set_field(&mut self, name: &str, value: u64) -> bool {
+                    Header::set_field_bytes(self, name, &value.to_be_bytes())
+                }
+                fn set_field_bytes(&mut self, name: &str, value: &[u8]) -> bool {
+                    match $name::FIELDS.iter().find(|f| f.is_named(name)) {
+                        Some(f) => {
+                            set_field_bits(self, f.msb, f.lsb, &f.wire_order_bytes(value));
+                            true
+                        }
+                        None => false,
+                    }
+                }
             }
             #[pyclass]
-            #[derive(FromPyObject)]
+            #[cfg_attr(not(feature = "python-module"), derive(FromPyObject))]
             pub struct $name {
                 #[pyo3(get)]
-                data: ProtectedArray
+                data: ProtectedArray,
+                /// The data may be shared with copy-on-write clones
+                cow: ::core::sync::atomic::AtomicBool,
+                /// The data was shared with a copy writing to it directly
+                shared: ::core::sync::atomic::AtomicBool,
+            }
+            // stacks the layer after the header, see the compose module
+            impl<R: $crate::compose::Layer> ::core::ops::Div<R> for $name {
+                type Output = $crate::Packet;
+
+                fn div(self, rhs: R) -> $crate::Packet {
+                    let mut pkt = $crate::Packet::new();
+                    pkt.push(self);
+                    pkt / rhs
+                }
             }
             impl ::bitfield::BitRange<u64> for $name {
                 fn bit_range(&self, msb: usize, lsb: usize) -> u64 {
-                    let bit_len = ::bitfield::size_of::<u8>() * 8;
-                    let value_bit_len = ::bitfield::size_of::<u64>() * 8;
-                    let mut value: u64 = 0;
-                    for i in lsb..=msb {
-                        value <<= 1;
-                        let map = self.data.a.lock().unwrap();
-                        value |= ((map[i / bit_len] >> (bit_len - i % bit_len - 1)) & 1) as u64;
+                    // a single lock for the whole field, not one per bit
+                    let map = self.data.a.lock().unwrap();
+                    if let Err(e) = check_bit_range(map.len(), msb, lsb) {
+                        panic!("{} bits {}..={}: {}", stringify!($name), msb, lsb, e);
                     }
-                    value << (value_bit_len - (msb - lsb + 1)) >> (value_bit_len - (msb - lsb + 1))
+                    read_bits(&map, msb, lsb)
                 }
                 fn set_bit_range(&mut self, msb: usize, lsb: usize, value: u64) {
-                    let bit_len = ::bitfield::size_of::<u8>() * 8;
-                    let mut value = value;
-                    for i in (lsb..=msb).rev() {
-                        let mut map = self.data.a.lock().unwrap();
-                        map[i / bit_len] &= !(1 << (bit_len - i % bit_len - 1));
-                        map[i / bit_len] |= ((value & 1) as u8) << (bit_len - i % bit_len - 1);
-                        value >>= 1;
+                    Header::make_mut(self);
+                    let mut map = self.data.a.lock().unwrap();
+                    if let Err(e) = check_bit_range(map.len(), msb, lsb) {
+                        panic!("{} bits {}..={}: {}", stringify!($name), msb, lsb, e);
+                    }
+                    write_bits(&mut map, msb, lsb, value)
+                }
+            }
+            impl $name {
+                $crate::std_only! {
+                    pub fn show(&self) {
+                        print!("{}", self.dump());
+                    }
+                }
+                /// Read the bits msb..=lsb, failing instead of panicking if they are out of range
+                pub fn try_bit_range(
+                    &self,
+                    msb: usize,
+                    lsb: usize,
+                ) -> Result<u64, $crate::PacketError> {
+                    use ::bitfield::BitRange;
+                    check_bit_range(self.data.a.lock().unwrap().len(), msb, lsb)?;
+                    if msb - lsb >= 64 {
+                        return Err($crate::PacketError::InvalidValue {
+                            field: String::from("lsb"),
+                            value: lsb.to_string(),
+                        });
+                    }
+                    Ok(self.bit_range(msb, lsb))
+                }
+                /// Write the bits msb..=lsb, failing instead of panicking if they are out of range
+                pub fn try_set_bit_range(
+                    &mut self,
+                    msb: usize,
+                    lsb: usize,
+                    value: u64,
+                ) -> Result<(), $crate::PacketError> {
+                    use ::bitfield::BitRange;
+                    check_bit_range(self.data.a.lock().unwrap().len(), msb, lsb)?;
+                    self.set_bit_range(msb, lsb, value);
+                    Ok(())
+                }
+                /// Read whole bytes msb..=lsb, the fallible counterpart of `bytes`
+                pub fn try_bytes(
+                    &self,
+                    msb: usize,
+                    lsb: usize,
+                ) -> Result<Vec<u8>, $crate::PacketError> {
+                    if msb < lsb || (msb - lsb + 1) % 8 != 0 {
+                        return Err($crate::PacketError::MisalignedRange { msb, lsb });
+                    }
+                    check_bit_range(self.data.a.lock().unwrap().len(), msb, lsb)?;
+                    Ok(self.bytes(msb, lsb))
+                }
+                /// Write whole bytes msb..=lsb, the fallible counterpart of `set_bytes`
+                pub fn try_set_bytes(
+                    &mut self,
+                    msb: usize,
+                    lsb: usize,
+                    value: &[u8],
+                ) -> Result<(), $crate::PacketError> {
+                    if msb < lsb || value.len() * 8 != msb - lsb + 1 {
+                        return Err($crate::PacketError::MisalignedRange { msb, lsb });
+                    }
+                    check_bit_range(self.data.a.lock().unwrap().len(), msb, lsb)?;
+                    self.set_bytes(msb, lsb, value);
+                    Ok(())
+                }
+                /// Create the header from exactly `size()` bytes
+                pub fn from_bytes(data: &[u8]) -> Result<$name, $crate::PacketError> {
+                    if data.len() < $size {
+                        return Err($crate::PacketError::BufferTooSmall {
+                            needed: $size,
+                            available: data.len(),
+                        });
+                    }
+                    if data.len() > $size {
+                        return Err($crate::PacketError::InvalidValue {
+                            field: String::from("len"),
+                            value: data.len().to_string(),
+                        });
+                    }
+                    Ok($name::from(data.to_vec()))
+                }
+                $(
+                $crate::field_extra!(owned $order $repr [$($scale)?] $name $field $start $end);
+                $crate::field_typed!(owned ($($ty)?) $order $name $field $start $end);
+                )*
+                /// Create the default header with the named fields set, failing on an unknown
+                /// field or a value too large for its field
+                pub fn from_fields(fields: &[(&str, u64)]) -> Result<$name, $crate::PacketError> {
+                    let mut hdr = $name::new();
+                    for (name, value) in fields {
+                        hdr.try_set_field(name, *value)?;
+                    }
+                    Ok(hdr)
+                }
+                /// Borrow a header as this type, the fallible counterpart of `From`
+                pub fn downcast(hdr: &dyn Header) -> Result<&$name, $crate::PacketError> {
+                    hdr.as_any().downcast_ref::<$name>().ok_or_else(|| {
+                        $crate::PacketError::HeaderMismatch {
+                            expected: String::from(stringify!($name)),
+                            found: String::from(hdr.name()),
+                        }
+                    })
+                }
+                /// Mutably borrow a header as this type
+                pub fn downcast_mut(
+                    hdr: &mut dyn Header,
+                ) -> Result<&mut $name, $crate::PacketError> {
+                    let found = String::from(hdr.name());
+                    hdr.as_any_mut().downcast_mut::<$name>().ok_or_else(|| {
+                        $crate::PacketError::HeaderMismatch {
+                            expected: String::from(stringify!($name)),
+                            found,
+                        }
+                    })
+                }
+                /// Description of each field of the header
+                pub const FIELDS: &'static [FieldInfo] = &[
+                    $(
+                    FieldInfo {
+                        name: stringify!($ifield),
+                        lsb: $istart,
+                        msb: $iend,
+                        reserved: $crate::field_flag!(reserved; $($iflag)?),
+                        checksum: $crate::field_flag!(checksum; $($iflag)?),
+                        little_endian: $crate::field_le!($iorder),
+                        alias: $crate::field_alias!(@info $($ialias)?),
+                        bits: $crate::field_bits!($($inames)?),
+                        values: $crate::field_values!($($inames)?),
+                        kind: $crate::field_kind!($kind; $($iflag)?),
+                    },
+                    )*
+                ];
+                /// Bit ranges of the padding, always serialized as zero
+                #[doc(hidden)]
+                pub const PADDING: &'static [(usize, usize)] = &[$(($pend, $pstart)),*];
+                $(
+                /// Bytes of the header covered by the field, None if it is not byte aligned, the end
+                /// being exclusive
+                pub const fn [<$ifield _byte_range>]() -> Option<::core::ops::Range<usize>> {
+                    FieldInfo {
+                        name: stringify!($ifield),
+                        lsb: $istart,
+                        msb: $iend,
+                        reserved: false,
+                        checksum: false,
+                        little_endian: false,
+                        alias: None,
+                        bits: &[],
+                        values: &[],
+                        kind: FieldKind::Number,
                     }
+                    .byte_range()
+                }
+                )*
+                $(
+                /// Set the bytes of the field, zero filling the end, failing if the value is longer
+                /// than the field
+                pub fn [<try_set_ $ofield>](
+                    &mut self,
+                    value: &[u8],
+                ) -> Result<(), $crate::PacketError> {
+                    Header::make_mut(self);
+                    let mut map = self.data.a.lock().unwrap();
+                    set_octets(&mut map, stringify!($ofield), $oend, $ostart, value)
+                }
+                )*
+                $($(
+                /// Name of the value of the field, None if the value has no name
+                pub fn [<$field _name>](&self) -> Option<&'static str> {
+                    $crate::field_name!(self.[<$field _raw>](); $names)
                 }
+                )?)*
             }
             #[pymethods]
             impl $name {
                 #[new]
                 pub fn new() -> $name {
-                    let t = ProtectedArray { a: Arc::new(Mutex::new($x)) };
-                    $name{ data: t }
+                    let mut data: Vec<u8> = $x;
+                    $($(
+                    set_default_bits(
+                        &mut data,
+                        $end,
+                        $start,
+                        $crate::field_order!($order; ($default) as u128, $end - $start + 1),
+                    );
+                    )?)*
+                    clear_padding(&mut data, $name::PADDING);
+                    let explicit: &[&str] = &[$($({
+                        let _ = stringify!($default);
+                        stringify!($field)
+                    },)?)*];
+                    default_checksum(&mut data, $name::FIELDS, explicit);
+                    $name::from(data)
                 }
                 $(
                 #[getter]
-                pub fn $field(&self) -> u64 {
+                pub fn $field(&self) -> $crate::field_type!($repr $start $end) {
+                    $crate::field_value!($repr $start $end; self.[<$field _raw>]())
+                }
+                pub fn [<$field _raw>](&self) -> u64 {
                     use ::bitfield::BitRange;
-                    let raw_value: u64 = self.bit_range($end, $start);
+                    let raw_value: u64 = $crate::field_order!(
+                        $order;
+                        BitRange::<u64>::bit_range(self, $end, $start),
+                        $end - $start + 1
+                    );
                     ::bitfield::Into::into(raw_value)
                 }
                 #[setter]
-                pub fn [<set_ $field>](&mut self, value: u64) {
+                pub fn [<set_ $field>](&mut self, value: $crate::field_arg!($repr)) {
                     use ::bitfield::BitRange;
-                    self.set_bit_range($end, $start, ::bitfield::Into::<u64>::into(value));
+                    let raw = $crate::field_raw!($repr $name $field $start $end; value);
+                    self.set_bit_range($end, $start, $crate::field_order!($order; raw, $end - $start + 1));
+                }
+                )*
+                $($(
+                // written out here rather than with field_alias!, for pymethods to see them
+                #[getter($old)]
+                #[doc = concat!("Renamed to `", stringify!($field), "`")]
+                #[deprecated]
+                pub fn $old(&self) -> $crate::field_type!($repr $start $end) {
+                    self.$field()
+                }
+                #[setter($old)]
+                #[doc = concat!("Renamed to `set_", stringify!($field), "`")]
+                #[deprecated]
+                pub fn [<set_ $old>](&mut self, value: $crate::field_arg!($repr)) {
+                    self.[<set_ $field>](value);
+                }
+                )?)*
+                $(
+                /// Bytes of the field
+                pub fn $ofield(&self) -> Vec<u8> {
+                    self.data.a.lock().unwrap()[$ostart / 8..($oend + 1) / 8].to_vec()
+                }
+                /// Set the bytes of the field, zero filling the end
+                ///
+                /// Panics if the value is longer than the field, see `try_set_<field>` for a
+                /// fallible version.
+                pub fn [<set_ $ofield>](&mut self, value: &[u8]) {
+                    if let Err(e) = self.[<try_set_ $ofield>](value) {
+                        panic!("{}: {}", stringify!($name), e);
+                    }
                 }
                 )*
                 pub fn bytes(&self, msb: usize, lsb: usize) -> Vec<u8> {
@@ -366,6 +2402,11 @@ macro_rules! make_header {
                 pub const fn size() -> usize {
                     $size
                 }
+                /// Return true if a buffer of buf_len bytes holds the header
+                #[staticmethod]
+                pub const fn fits(buf_len: usize) -> bool {
+                    buf_len >= $size
+                }
                 pub const fn len(&self) -> usize {
                     $size
                 }
@@ -375,60 +2416,88 @@ macro_rules! make_header {
                 $(
                     #[doc(hidden)]
                     #[staticmethod]
-                    pub const fn [<$field _size>]() -> usize {
-                        $end - $start + 1
+                    pub const fn [<$ifield _size>]() -> usize {
+                        $iend - $istart + 1
                     }
                     #[doc(hidden)]
                     #[staticmethod]
-                    pub const fn [<$field _lsb>]() -> usize {
-                        $start
+                    pub const fn [<$ifield _lsb>]() -> usize {
+                        $istart
                     }
                     #[doc(hidden)]
                     #[staticmethod]
-                    pub const fn [<$field _msb>]() -> usize {
-                        $end
+                    pub const fn [<$ifield _msb>]() -> usize {
+                        $iend
                     }
                 )*
                 pub fn replace(&mut self, other: &$name) {
+                    Header::make_mut(self);
                     let mut map = self.data.a.lock().unwrap();
                     map.clear();
                     map.extend_from_slice(other.data.a.lock().unwrap().as_ref());
                 }
-                pub fn show(&self) -> () {
-                    println!("#### {:16} {} {}", stringify!($name), "Size  ", "Data");
-                    println!("-------------------------------------------");
+                #[cfg(feature = "python-module")]
+                #[pyo3(name = "show")]
+                fn py_show(&self) {
+                    self.show();
+                }
+                /// Return the output of `show` as a string
+                pub fn dump(&self) -> String {
+                    format!(
+                        "#### {:16} {} {}\n-------------------------------------------\n{}",
+                        stringify!($name),
+                        "Size  ",
+                        "Data",
+                        self.field_table()
+                    )
+                }
+                pub fn field_table(&self) -> String {
+                    use ::core::fmt::Write;
+                    let mut out = String::new();
                     $(
-                    print!("{:20}: {:4} : ", stringify!($field), $end - $start + 1);
-                    if (($end - $start + 1) <= 8) {
-                        let x: u8 = self.bit_range($end, $start) as u8;
-                        print!("{:02x}", x);
-                    } else if (($end - $start + 1)%8 == 0){
-                        let d = ($end - $start + 1)/8;
-                        for i in ($start..(d*8 + $start)).step_by(8) {
-                            let x: u8 = self.bit_range(i + 7, i) as u8;
-                            print!("{:02x} ", x);
-                        }
-                    } else {
-                        let d = ($end - $start + 1)/8;
-                        let r = ($end - $start + 1)%8;
-                        for i in ($start..(d*8 + $start)).step_by(8) {
-                            let x: u8 = self.bit_range(i + 7, i) as u8;
-                            print!("{:02x} ", x);
-                        }
-                        let x: u8 = self.bit_range($end, $end - r) as u8;
-                        print!("{:02x}", x);
-                    }
-                    println!();
+                    $crate::field_row!(
+                        self, out, $kind $iorder $irepr [$($iscale)?] $ifield $istart $iend {$($inames)?}
+                    );
                     )*
+                    out
                 }
-                pub fn clone(&self) -> $name {
-                    let t1 = self.data.a.clone();
-                    let t = ProtectedArray { a: t1 };
-                    $name{ data: t }
+                #[cfg(feature = "python-module")]
+                #[pyo3(name = "bit_range")]
+                fn py_bit_range(&self, msb: usize, lsb: usize) -> ::pyo3::PyResult<u64> {
+                    Ok(self.try_bit_range(msb, lsb)?)
+                }
+                #[cfg(feature = "python-module")]
+                #[pyo3(name = "set_bit_range")]
+                fn py_set_bit_range(
+                    &mut self,
+                    msb: usize,
+                    lsb: usize,
+                    value: u64,
+                ) -> ::pyo3::PyResult<()> {
+                    Ok(self.try_set_bit_range(msb, lsb, value)?)
+                }
+                #[cfg(feature = "python-module")]
+                fn __bytes__<'py>(&self, py: ::pyo3::Python<'py>) -> &'py ::pyo3::types::PyBytes {
+                    ::pyo3::types::PyBytes::new(py, &self.to_vec())
+                }
+                #[cfg(feature = "python-module")]
+                #[classmethod]
+                #[pyo3(name = "from_bytes")]
+                fn py_from_bytes(
+                    _cls: &::pyo3::types::PyType,
+                    data: &[u8],
+                ) -> ::pyo3::PyResult<$name> {
+                    Ok($name::from_bytes(data)?)
+                }
+                #[cfg(feature = "python-module")]
+                #[pyo3(name = "clone")]
+                fn py_clone(&self) -> $name {
+                    Clone::clone(self)
                 }
                 pub fn to_vec(&self) -> Vec<u8> {
-                    let map = self.data.a.lock().unwrap();
-                    map.clone()
+                    let mut data = self.data.a.lock().unwrap().clone();
+                    clear_padding(&mut data, $name::PADDING);
+                    data
                 }
                 /*
                 #[cfg(feature = "python-module")]
@@ -444,23 +2513,61 @@ macro_rules! make_header {
                 */
                 #[cfg(feature = "python-module")]
                 fn __str__(&self) -> ::pyo3::PyResult<String> {
-                    Ok(String::from(stringify!($name)))
+                    Ok(self.dump())
+                }
+                #[cfg(feature = "python-module")]
+                fn __repr__(&self) -> ::pyo3::PyResult<String> {
+                    Ok(self.dump())
+                }
+            }
+            impl Clone for $name {
+                fn clone(&self) -> $name {
+                    $name::from(self.to_vec())
+                }
+            }
+            impl ::core::fmt::LowerHex for $name {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                    fmt_hex(&self.data.a.lock().unwrap(), f, false)
+                }
+            }
+            impl ::core::fmt::UpperHex for $name {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                    fmt_hex(&self.data.a.lock().unwrap(), f, true)
                 }
             }
             impl From<Vec<u8>> for $name {
                 fn from(data: Vec<u8>) -> $name {
-                    $name{ data: ProtectedArray { a: Arc::new(Mutex::new(data)) } }
+                    $name {
+                        data: ProtectedArray { a: Arc::new(Mutex::new(data)) },
+                        cow: ::core::sync::atomic::AtomicBool::new(false),
+                        shared: ::core::sync::atomic::AtomicBool::new(false),
+                    }
                 }
             }
+            /// Panics if the header is of another type, see `downcast` for a fallible version
+            ///
+            /// The header shares its bytes with the boxed one, so writes through either are seen
+            /// by both. Once the boxed header was cloned with `clone_cow` this no longer holds:
+            /// the first write through either copy detaches it, as for any copy-on-write clone.
             impl<'a> From<&'a Box<dyn Header>> for $name {
                 fn from(s: &'a Box<dyn Header>) -> $name {
                     let b = match s.as_any().downcast_ref::<$name>() {
                         Some(b) => b,
                         None => panic!("Header is not a {}", stringify!($name)),
                     };
-                    b.clone()
+                    // shares the underlying data with the boxed header, unless it is written
+                    // while shared with copy-on-write clones
+                    b.shared.store(true, ::core::sync::atomic::Ordering::Relaxed);
+                    $name {
+                        data: b.data.clone(),
+                        cow: ::core::sync::atomic::AtomicBool::new(
+                            b.cow.load(::core::sync::atomic::Ordering::Relaxed),
+                        ),
+                        shared: ::core::sync::atomic::AtomicBool::new(true),
+                    }
                 }
             }
+            /// Panics if the header is of another type, see `downcast` for a fallible version
             impl<'a> From<&'a Box<dyn Header>> for &'a $name {
                 fn from(s: &'a Box<dyn Header>) -> &'a $name {
                     let b = match s.as_any().downcast_ref::<$name>() {
@@ -470,6 +2577,7 @@ macro_rules! make_header {
                     b
                 }
             }
+            /// Panics if the header is of another type, see `downcast` for a fallible version
             impl<'a> From<&'a mut Box<dyn Header>> for &'a mut $name {
                 fn from(s: &'a mut Box<dyn Header>) -> &'a mut $name {
                     let b = match s.as_any_mut().downcast_mut::<$name>() {
@@ -480,8 +2588,13 @@ macro_rules! make_header {
                 }
             }
             impl Header for $name {
-                fn show(&self) {
-                    self.show();
+                $crate::std_only! {
+                    fn show(&self) {
+                        self.show();
+                    }
+                }
+                fn field_table(&self) -> String {
+                    self.field_table()
                 }
                 fn to_vec(&self) -> Vec<u8> {
                     self.to_vec()
@@ -489,8 +2602,35 @@ macro_rules! make_header {
                 fn as_slice(&self) -> &[u8] {
                     unimplemented!();
                 }
-                fn clone(&self) -> Box<dyn Header> {
-                    Box::new(self.clone())
+                fn write_into(&self, dst: &mut [u8]) -> Result<usize, $crate::PacketError> {
+                    let data = self.data.a.lock().unwrap();
+                    let len = write_bytes(&data, dst)?;
+                    clear_padding(&mut dst[..len], $name::PADDING);
+                    Ok(len)
+                }
+                fn clone_box(&self) -> Box<dyn Header> {
+                    Box::new(Clone::clone(self))
+                }
+                fn clone_cow(&self) -> Box<dyn Header> {
+                    use ::core::sync::atomic::{AtomicBool, Ordering};
+                    // the original is now shared too and must copy before its next write
+                    self.cow.store(true, Ordering::Relaxed);
+                    Box::new($name {
+                        data: self.data.clone(),
+                        cow: AtomicBool::new(true),
+                        shared: AtomicBool::new(self.shared.load(Ordering::Relaxed)),
+                    })
+                }
+                fn is_shared(&self) -> bool {
+                    self.shared.load(::core::sync::atomic::Ordering::Relaxed)
+                }
+                fn make_mut(&mut self) {
+                    if ::core::mem::take(self.cow.get_mut())
+                        && Arc::strong_count(&self.data.a) > 1
+                    {
+                        let data = self.to_vec();
+                        self.data = ProtectedArray { a: Arc::new(Mutex::new(data)) };
+                    }
                 }
                 fn to_owned(self) -> Box<dyn Header> {
                     Box::from(self)
@@ -507,20 +2647,61 @@ macro_rules! make_header {
                 fn as_any_mut(&mut self) -> &mut dyn Any {
                     self
                 }
+                fn into_any(self: Box<Self>) -> Box<dyn Any> {
+                    self
+                }
+                fn fields(&self) -> &'static [FieldInfo] {
+                    $name::FIELDS
+                }
+                fn get_field(&self, name: &str) -> Option<u64> {
+                    $name::FIELDS
+                        .iter()
+                        .find(|f| f.is_named(name))
+                        .map(|f| f.wire_order(self.bit_range(f.msb, f.lsb)))
+                }
+                fn get_field_bytes(&self, name: &str) -> Option<Vec<u8>> {
+                    $name::FIELDS
+                        .iter()
+                        .find(|f| f.is_named(name))
+                        .map(|f| f.wire_order_bytes(&get_field_bits(self, f.msb, f.lsb)))
+                }
+                fn src_bytes(&self) -> Option<Vec<u8>> {
+                    self.get_field_bytes("src")
+                }
+                fn dst_bytes(&self) -> Option<Vec<u8>> {
+                    self.get_field_bytes("dst")
+                }
+                fn summary(&self) -> String {
+                    $crate::summary::header_summary(self)
+                }
+                fn validate_reserved(&self) -> bool {
+                    $name::FIELDS
+                        .iter()
+                        .filter(|f| f.reserved)
+                        .all(|f| get_field_bits(self, f.msb, f.lsb).iter().all(|b| *b == 0))
+                }
+                fn set_field(&mut self, name: &str, value: u64) -> bool {
+                    Header::set_field_bytes(self, name, &value.to_be_bytes())
+                }
+                fn set_field_bytes(&mut self, name: &str, value: &[u8]) -> bool {
+                    match $name::FIELDS.iter().find(|f| f.is_named(name)) {
+                        Some(f) => {
+                            set_field_bits(self, f.msb, f.lsb, &f.wire_order_bytes(value));
+                            true
+                        }
+                        None => false,
+                    }
+                }
             }
         }
     };
     (
         $name: ident $size: literal
-        ( $($field: ident: $start: literal-$end: literal),* )
+        ( $($fields: tt)* )
     ) => {
         make_header!(
             $name $size
-            (
-                $(
-                    $field: $start-$end
-                ),*
-            )
+            ( $($fields)* )
             vec![0; $size]
         );
     };
@@ -530,13 +2711,12 @@ macro_rules! make_header {
 make_header!(
 Ether 14
 (
-    dst: 0-47,
-    src: 48-95,
-    etype: 96-111
+    dst: 0-47 [mac] = 0x000102030405,
+    src: 48-95 [mac] = 0x060708090a0b,
+    etype: 96-111 enum {
+        0x0800 => IPV4, 0x0806 => ARP, 0x8100 => DOT1Q, 0x86dd => IPV6, 0x8847 => MPLS
+    } = EtherType::IPV4
 )
-vec![0x0, 0x1, 0x2, 0x3, 0x4, 0x5,
-     0x6, 0x7, 0x8, 0x9, 0xa, 0xb,
-     0x08, 0x00]
 );
 
 // vlan header
@@ -545,136 +2725,245 @@ Vlan 4
 (
     pcp: 0-2,
     cfi: 3-3,
-    vid: 4-15,
-    etype: 16-31
+    vid: 4-15 = 10,
+    etype: 16-31 = EtherType::IPV4
 )
-vec![0x0, 0xa, 0x08, 0x00]
 );
 
 // ipv4 header
 make_header!(
 IPv4 20
 (
-    version: 0-3,
-    ihl: 4-7,
+    version: 0-3 = 4,
+    ihl: 4-7 = 5,
     diffserv: 8-15,
-    total_len: 16-31,
-    identification: 32-47,
-    flags: 48-50,
-    frag_startset: 51-63,
-    ttl: 64-71,
-    protocol: 72-79,
-    header_checksum: 80-95,
-    src: 96-127,
-    dst: 128-159
+    total_len: 16-31 = 20,
+    identification: 32-47 = 51,
+    flags: 48-50 = 2,
+    frag_offset: 51-63 alias(frag_startset) = 221,
+    ttl: 64-71 = 64,
+    protocol: 72-79 enum {
+        1 => ICMP, 2 => IGMP, 4 => IPIP, 6 => TCP, 17 => UDP,
+        41 => IPV6, 47 => GRE, 58 => ICMPV6, 136 => UDPLITE
+    } = IpProtocol::TCP,
+    header_checksum: 80-95 [checksum],
+    src: 96-127 [ipv4] = 0xc0a80001,
+    dst: 128-159 [ipv4] = 0xc0a80002
 )
-vec![0x45, 0x00, 0x00, 0x14, 0x00, 0x33, 0x40, 0xdd, 0x40, 0x06, 0xfa, 0xec,
-     0xc0, 0xa8, 0x0, 0x1,
-     0xc0, 0xa8, 0x0, 0x2]
 );
 
 // ipv6 header
 make_header!(
 IPv6 40
 (
-    version: 0-3,
+    version: 0-3 = 6,
     traffic_class: 4-11,
     flow_label: 12-31,
-    payload_len: 32-47,
-    next_hdr: 48-55,
-    hop_limit: 56-63,
-    src: 64-191,
-    dst: 192-319
+    payload_len: 32-47 = 46,
+    next_hdr: 48-55 enum {
+        1 => ICMP, 2 => IGMP, 4 => IPIP, 6 => TCP, 17 => UDP,
+        41 => IPV6, 47 => GRE, 58 => ICMPV6, 136 => UDPLITE
+    } = IpProtocol::TCP,
+    hop_limit: 56-63 = 64,
+    src: 64-191 [ipv6] = 0x20010db885a3000000008a2e03707334,
+    dst: 192-319 [ipv6] = 0x20010db885a3000000008a2e03707335
 )
-vec![0x60, 0x00, 0x00, 0x00, 0x00, 0x2e, 0x06, 0x40,
-     0x20, 0x01, 0x0d, 0xb8, 0x85, 0xa3, 0x00, 0x00, 0x00, 0x00, 0x8a, 0x2e, 0x03, 0x70, 0x73, 0x34,
-     0x20, 0x01, 0x0d, 0xb8, 0x85, 0xa3, 0x00, 0x00, 0x00, 0x00, 0x8a, 0x2e, 0x03, 0x70, 0x73, 0x35]
 );
 
-// icmp header
+// icmp header. new() returns its 4 bytes, it used to return the 6 bytes of its default vector
+// and put 2 zero bytes before the payload, where an echo request has its identifier
 make_header!(
 ICMP 4
 (
-    icmp_type: 0-7,
+    icmp_type: 0-7 as IcmpType {
+        EchoReply = 0, DestUnreachable = 3, Redirect = 5, EchoRequest = 8, TimeExceeded = 11
+    } = IcmpType::EchoRequest,
     icmp_code: 8-15,
-    chksum: 16-31
+    chksum: 16-31 [checksum] = 0
+)
+);
+
+// igmp v1/v2 header and the fixed part of the v3 query
+make_header!(
+Igmp 8
+(
+    igmp_type: 0-7 = 0x11,
+    max_resp: 8-15 = 100,
+    checksum: 16-31 [checksum],
+    group_address: 32-63 [ipv4]
+)
+);
+
+// igmp v3 membership report, group records follow as payload
+make_header!(
+IgmpV3Report 8
+(
+    igmp_type: 0-7 = IGMP_V3_REPORT,
+    reserved: 8-15 [reserved],
+    checksum: 16-31 [checksum],
+    reserved2: 32-47 [reserved],
+    num_records: 48-63
+)
+);
+
+// mld v1 header and the fixed part of the v2 query, carried in icmpv6
+make_header!(
+Mld 24
+(
+    mld_type: 0-7 = MLD_QUERY,
+    code: 8-15,
+    checksum: 16-31 [checksum] = 0,
+    max_resp: 32-47 = 10000,
+    reserved: 48-63 [reserved],
+    mcast_address: 64-191 [ipv6]
+)
+);
+
+// mld v2 listener report, multicast address records follow as payload
+make_header!(
+MldV2Report 8
+(
+    mld_type: 0-7 = MLD_V2_REPORT,
+    reserved: 8-15 [reserved],
+    checksum: 16-31 [checksum] = 0,
+    reserved2: 32-47 [reserved],
+    num_records: 48-63
 )
-vec![0x8, 0x0, 0x0, 0x0, 0x0, 0x0]
 );
 
 // tcp header
 make_header!(
 TCP 20
 (
-    src: 0-15,
-    dst: 16-31,
+    src: 0-15 = 1234,
+    dst: 16-31 = 80,
     seq_no: 32-63,
     ack_no: 64-95,
-    data_startset: 96-99,
-    res: 100-103,
-    flags: 104-111,
-    window: 112-127,
-    checksum: 128-143,
+    data_offset: 96-99 alias(data_startset) = 5,
+    res: 100-103 [reserved],
+    flags: 104-111 as flags { cwr, ece, urg, ack, psh, rst, syn, fin } = 2,
+    window: 112-127 = 8192,
+    checksum: 128-143 [checksum] = 0,
     urgent_ptr: 144-159
 )
-vec![0x04, 0xd2 , 0x00, 0x50, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
-     0x50, 0x02, 0x20, 0x00, 0x0d, 0x2c, 0x0, 0x0]
 );
 
 // udp header
 make_header!(
 UDP 8
 (
-    src: 0-15,
-    dst: 16-31,
+    src: 0-15 = 1234,
+    dst: 16-31 = 80,
     length: 32-47,
-    checksum: 48-63
+    checksum: 48-63 [checksum] = 0
+)
+);
+
+// udp-lite header, coverage is the number of bytes covered by the checksum, 0 for all
+make_header!(
+UdpLite 8
+(
+    src: 0-15 = 1234,
+    dst: 16-31 = 80,
+    coverage: 32-47,
+    checksum: 48-63 [checksum] = 0
 )
-vec![0x04, 0xd2 , 0x00, 0x50, 0x0, 0x0, 0x0, 0x0]
 );
 
 // arp header
 make_header!(
 ARP 28
 (
-    hwtype: 0-15,
-    proto_type: 16-31,
-    hwlen: 32-39,
-    proto_len: 40-47,
-    opcode: 48-63,
-    sender_hw_addr: 64-111,
-    sender_proto_addr: 112-143,
-    target_hw_addr: 144-191,
-    target_proto_addr: 192-223
+    hwtype: 0-15 = 1,
+    proto_type: 16-31 = EtherType::IPV4,
+    hwlen: 32-39 = 6,
+    proto_len: 40-47 = 4,
+    opcode: 48-63 as ArpOp { Request = 1, Reply = 2 } = ArpOp::Request,
+    sender_hw_addr: 64-111 [mac] = 0x000102030405,
+    sender_proto_addr: 112-143 [ipv4] = 0x0a000001,
+    target_hw_addr: 144-191 [mac],
+    target_proto_addr: 192-223 [ipv4]
 )
-vec![0x0, 0x1, 0x8, 0x0, 0x6, 0x4, 0x0, 0x1,
-     0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0xa, 0x0, 0x0, 0x1,
-     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0, 0x0, 0x0, 0x0]
 );
 
 // vxlan header
 make_header!(
 Vxlan 8
 (
-    flags: 0-7,
-    reserved: 8-31,
-    vni: 32-55,
-    reserved2: 56-63
+    flags: 0-7 = 0x08,
+    reserved: 8-31 [reserved],
+    vni: 32-55 = 2000,
+    reserved2: 56-63 [reserved]
+)
+);
+
+// l2tp header, the fields after it depend on its flags and version
+make_header!(
+L2tp 2
+(
+    t: 0-0,
+    l: 1-1,
+    reserved: 2-3 [reserved],
+    s: 4-4,
+    reserved2: 5-5 [reserved],
+    o: 6-6,
+    p: 7-7,
+    reserved3: 8-11 [reserved],
+    version: 12-15 = L2TP_V2
+)
+);
+
+// l2tp length optional data
+make_header!(
+L2tpLength 2
+(
+    length: 0-15
+)
+);
+
+// l2tp version 2 tunnel and session
+make_header!(
+L2tpTunnel 4
+(
+    tunnel_id: 0-15,
+    session_id: 16-31
+)
+);
+
+// l2tp sequence numbers optional data
+make_header!(
+L2tpSequence 4
+(
+    ns: 0-15,
+    nr: 16-31
+)
+);
+
+// l2tp offset optional data, followed by offset_size bytes of padding
+make_header!(
+L2tpOffset 2
+(
+    offset_size: 0-15
+)
+);
+
+// l2tp version 3 data message session over udp
+make_header!(
+L2tpV3Session 6
+(
+    reserved: 0-15 [reserved],
+    session_id: 16-47
 )
-vec![0x8, 0x0 , 0x0, 0x0, 0x0, 0x07, 0xd0, 0x0]
 );
 
 // dot3 header
 make_header!(
 Dot3 14
 (
-    dst: 0-47,
-    src: 48-95,
+    dst: 0-47 [mac] = 0x000102030405,
+    src: 48-95 [mac] = 0x060708090a0b,
     length: 96-111
 )
-vec![0x0, 0x1, 0x2, 0x3, 0x4, 0x5,
-     0x6, 0x7, 0x8, 0x9, 0xa, 0xb,
-     0x00, 0x00]
 );
 
 // llc header
@@ -685,7 +2974,6 @@ LLC 3
     ssap: 8-15,
     ctrl: 16-23
 )
-vec![0x0, 0x0, 0x0]
 );
 
 // snap header
@@ -695,7 +2983,6 @@ SNAP 5
     oui: 0-23,
     code: 24-39
 )
-vec![0x0, 0x0, 0x0, 0x0, 0x0]
 );
 
 // gre header
@@ -712,7 +2999,6 @@ GRE 4
     version: 13-15,
     proto: 16-31
 )
-vec![0x0, 0x0, 0x0, 0x0]
 );
 
 // gre checksum offset optional data
@@ -722,7 +3008,6 @@ GREChksumOffset 4
     chksum: 0-15,
     offset: 16-31
 )
-vec![0, 0, 0, 0]
 );
 
 // gre sequence number optional data
@@ -731,7 +3016,6 @@ GRESequenceNum 4
 (
     seqnum: 0-31
 )
-vec![0, 0, 0, 0]
 );
 
 // gre key optional data
@@ -740,30 +3024,28 @@ GREKey 4
 (
     key: 0-31
 )
-vec![0, 0, 0, 0]
 );
 
 // erspan type 2 header
 make_header!(
 ERSPAN2 8
 (
-    version: 0-3,
+    version: 0-3 = 1,
     vlan: 4-15,
     cos: 16-18,
     en: 19-20,
     t: 21-21,
     session_id: 22-31,
-    reserved: 32-43,
+    reserved: 32-43 [reserved],
     index: 44-63
 )
-vec![0x10, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0]
 );
 
 // erspan type 3 header
 make_header!(
 ERSPAN3 12
 (
-    version: 0-3,
+    version: 0-3 = 2,
     vlan: 4-15,
     cos: 16-18,
     bos: 19-20,
@@ -778,7 +3060,6 @@ ERSPAN3 12
     gra: 93-94,
     o: 95-95
 )
-vec![0x20, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0]
 );
 
 // erspan 3 platform header
@@ -788,7 +3069,6 @@ ERSPANPLATFORM 8
     id: 0-5,
     info: 6-63
 )
-vec![0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0]
 );
 
 // stp header
@@ -800,18 +3080,16 @@ STP 35
     bpdu_type: 24-31,
     flags: 32-39,
     root_id: 40-55,
-    root_mac: 56-103,
+    root_mac: 56-103 [mac],
     root_path_cost: 104-135,
     bridge_id: 136-151,
-    bridge_mac: 152-199,
+    bridge_mac: 152-199 [mac],
     port_id: 200-215,
-    message_age: 216-231,
-    max_age: 232-247,
-    hello_time: 248-263,
-    fwd_delay: 264-279
+    message_age: 216-231 = 1,
+    max_age: 232-247 = 20,
+    hello_time: 248-263 = 2,
+    fwd_delay: 264-279 = 15
 )
-vec![0x0, 0x0 , 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
-     0x0, 0x0 , 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x0, 0x14, 0x0, 0x2, 0x0, 0xF]
 );
 
 // mpls header
@@ -823,7 +3101,31 @@ MPLS 4
     bos: 23-23,
     ttl: 24-31
 )
-vec![0, 0, 0, 0]
+);
+
+// ethernet frame check sequence, as a trailer
+make_header!(
+EthFcs 4
+(
+    fcs: 0-31 le
+)
+);
+
+// hardware timestamp trailer appended after the fcs by capture cards
+make_header!(
+HwTimestamp 8
+(
+    seconds: 0-31,
+    nanoseconds: 32-63
+)
+);
+
+// hardware timestamp trailer of 32 bits, in ticks of the capture card clock
+make_header!(
+HwTimestamp32 4
+(
+    ticks: 0-31
+)
 );
 
 make_header!(
@@ -842,7 +3144,7 @@ Tester 40
     byte1: 48-55,
     byte2: 56-71,
     byte3: 72-95,
-    byte4: 66-127,
+    byte4: 96-127,
     byte8: 128-191,
     byte16: 192-319
 )
@@ -856,7 +3158,8 @@ vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
 #[test]
 fn test_header_get() {
     let test = Tester::new();
-    assert_eq!(test.bit1(), 1);
+    assert!(test.bit1());
+    assert_eq!(test.bit1_raw(), 1);
     assert_eq!(test.bit2(), 3);
     assert_eq!(test.bit3(), 7);
     assert_eq!(test.bit4(), 15);
@@ -883,7 +3186,8 @@ fn test_header_get() {
 fn test_header_set() {
     let mut test = Tester::new();
     test.set_bit1(0);
-    assert_eq!(test.bit1(), 0);
+    assert!(!test.bit1());
+    assert_eq!(test.bit1_raw(), 0);
     test.set_bit2(2);
     assert_eq!(test.bit2(), 2);
     test.set_bit3(3);
@@ -0,0 +1,180 @@
+//! # Routed hop simulation
+//!
+//! [`Packet::routed_copy`] returns the packet as it is expected to leave a router: MACs rewritten,
+//! TTL or hop limit decremented and the IPv4 checksum updated. [`HopOptions`] additionally
+//! rewrites DSCP and the vlan tag.
+//!
+//! The same rewrites of a single header are [`IPv4::set_dscp`], [`IPv4::set_ecn`],
+//! [`IPv4::decrement_ttl`] and [`Vlan::set_priority`].
+//!
+//! ```
+//! # use packet_rs::addr::MacAddr;
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! let pkt = create_tcp_packet(&Default::default());
+//! let expected = pkt
+//!     .routed_copy(MacAddr::from(0x0a0000000001), MacAddr::from(0x0a0000000002))
+//!     .unwrap();
+//! assert_eq!(expected.layer::<IPv4>().unwrap().ttl(), 63);
+//! ```
+
+use crate::addr::MacAddr;
+use crate::checksum::incremental_checksum;
+use crate::headers::*;
+use crate::types::*;
+use crate::{Packet, PacketError};
+
+/// Vlan tag rewrite applied by [`Packet::routed_copy_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VlanRewrite {
+    /// Leave the vlan tags as they are
+    #[default]
+    Keep,
+    /// Remove the outer vlan tag
+    Pop,
+    /// Set the outer vlan tag, pushing one if the packet is untagged
+    Set { vid: u16, pcp: u8 },
+}
+
+/// Optional rewrites of a routed hop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HopOptions {
+    /// New DSCP value, the ECN bits are preserved
+    pub dscp: Option<u8>,
+    pub vlan: VlanRewrite,
+}
+
+impl Packet {
+    /// Return a copy of the packet as forwarded by a router
+    ///
+    /// Fails with [`PacketError::TtlExceeded`] if the TTL or hop limit of the outer IP header
+    /// would reach zero.
+    pub fn routed_copy(&self, src: MacAddr, dst: MacAddr) -> Result<Packet, PacketError> {
+        self.routed_copy_with(src, dst, &HopOptions::default())
+    }
+    /// Return a copy of the packet as forwarded by a router, with additional rewrites
+    pub fn routed_copy_with(
+        &self,
+        src: MacAddr,
+        dst: MacAddr,
+        opts: &HopOptions,
+    ) -> Result<Packet, PacketError> {
+        let mut pkt = self.clone();
+        let eth = pkt
+            .layer_mut::<Ether>()
+            .ok_or_else(|| PacketError::MissingHeader(String::from("Ether")))?;
+        eth.set_src_mac(src);
+        eth.set_dst_mac(dst);
+
+        let ip = pkt
+            .hdrs
+            .iter_mut()
+            .find(|x| x.as_any().is::<IPv4>() || x.as_any().is::<IPv6>())
+            .ok_or_else(|| PacketError::MissingHeader(String::from("IPv4")))?;
+        if let Some(ipv4) = ip.as_any_mut().downcast_mut::<IPv4>() {
+            if ipv4.ttl() <= 1 {
+                return Err(PacketError::TtlExceeded);
+            }
+            ipv4.set_ttl(ipv4.ttl() - 1);
+            if let Some(dscp) = opts.dscp {
+                ipv4.set_dscp(dscp);
+            }
+            let chksum = Packet::ipv4_checksum(ipv4.to_vec().as_slice());
+            ipv4.set_header_checksum(chksum as u64);
+        } else if let Some(ipv6) = ip.as_any_mut().downcast_mut::<IPv6>() {
+            if ipv6.hop_limit() <= 1 {
+                return Err(PacketError::TtlExceeded);
+            }
+            ipv6.set_hop_limit(ipv6.hop_limit() - 1);
+            if let Some(dscp) = opts.dscp {
+                ipv6.set_traffic_class(((dscp as u64) << 2) | (ipv6.traffic_class() & 0x3));
+            }
+        }
+
+        match opts.vlan {
+            VlanRewrite::Keep => (),
+            VlanRewrite::Pop => {
+                if let Some(i) = pkt.layer_position::<Vlan>() {
+                    let vlan = pkt.hdrs.remove(i);
+                    pkt.poison_removed(i);
+                    pkt.invalidate();
+                    let etype = vlan.get_field("etype").unwrap();
+                    pkt.hdrs[i - 1].set_field("etype", etype);
+                }
+            }
+            VlanRewrite::Set { vid, pcp } => {
+                if pkt.layer::<Vlan>().is_none() {
+                    let i = pkt.layer_position::<Ether>().unwrap();
+                    let eth = pkt.layer_mut::<Ether>().unwrap();
+                    let mut vlan = Vlan::new();
+                    vlan.set_etype(eth.etype());
+                    eth.set_etype(EtherType::DOT1Q as u64);
+                    pkt.hdrs.insert(i + 1, vlan.to_owned());
+                    pkt.poison_inserted(i + 1);
+                    pkt.invalidate();
+                }
+                let vlan = pkt.layer_mut::<Vlan>().unwrap();
+                vlan.set_vid(vid as u64);
+                vlan.set_priority(pcp);
+            }
+        }
+        Ok(pkt)
+    }
+    fn layer_position<T: Header + 'static>(&self) -> Option<usize> {
+        self.hdrs.iter().position(|x| x.as_any().is::<T>())
+    }
+}
+
+impl IPv4 {
+    /// DSCP, the upper 6 bits of `diffserv`
+    pub fn dscp(&self) -> u8 {
+        (self.diffserv() >> 2) as u8
+    }
+    /// Set the DSCP, preserving the ECN bits, only the low 6 bits of the value are used
+    pub fn set_dscp(&mut self, dscp: u8) {
+        self.set_diffserv(((dscp as u64 & 0x3f) << 2) | (self.diffserv() & 0x3));
+    }
+    /// ECN, the lower 2 bits of `diffserv`
+    pub fn ecn(&self) -> u8 {
+        (self.diffserv() & 0x3) as u8
+    }
+    /// Set the ECN, preserving the DSCP, only the low 2 bits of the value are used
+    pub fn set_ecn(&mut self, ecn: u8) {
+        self.set_diffserv((self.diffserv() & !0x3) | (ecn as u64 & 0x3));
+    }
+    /// Decrement the TTL and update the checksum incrementally, returning the new TTL
+    ///
+    /// A checksum that was wrong stays wrong. Fails with [`PacketError::TtlExceeded`] if the TTL
+    /// is already zero.
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::Packet;
+    /// let mut ipv4 = IPv4::new();
+    /// assert_eq!(ipv4.decrement_ttl(), Ok(63));
+    /// assert_eq!(ipv4.header_checksum(), Packet::ipv4_checksum(&ipv4.to_vec()) as u64);
+    /// ```
+    pub fn decrement_ttl(&mut self) -> Result<u8, PacketError> {
+        let ttl = self.ttl() as u8;
+        if ttl == 0 {
+            return Err(PacketError::TtlExceeded);
+        }
+        let old = self.to_vec();
+        self.set_ttl(ttl as u64 - 1);
+        let new = self.to_vec();
+        let chksum = incremental_checksum(self.header_checksum() as u16, &old[8..10], &new[8..10]);
+        self.set_header_checksum(chksum as u64);
+        Ok(ttl - 1)
+    }
+}
+
+impl Vlan {
+    /// Priority code point
+    pub fn priority(&self) -> u8 {
+        self.pcp() as u8
+    }
+    /// Set the priority code point, only the low 3 bits of the value are used
+    pub fn set_priority(&mut self, pcp: u8) {
+        self.set_pcp(pcp as u64 & 0x7);
+    }
+}
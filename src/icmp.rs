@@ -0,0 +1,202 @@
+//! # ICMP error generation
+//!
+//! Build the ICMP or ICMPv6 error a router sends back in response to an offending packet. The
+//! reply is addressed to the sender of the offending packet and quotes as much of it as fits the
+//! size limits: 576 bytes for the IPv4 reply (RFC 1812) and the IPv6 minimum MTU of 1280 bytes
+//! for the IPv6 reply (RFC 4443).
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::icmp;
+//! let pkt = create_tcp_packet(&Default::default());
+//! let reply = icmp::icmp_time_exceeded(&pkt, "10.0.0.1".parse().unwrap()).unwrap();
+//! assert_eq!(reply.layer::<ICMP>().unwrap().icmp_type(), 11);
+//! ```
+
+use core::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::headers::*;
+use crate::types::*;
+use crate::{Packet, PacketError};
+
+/// Largest IPv4 datagram carrying an ICMP error
+pub const IPV4_ERROR_MAX_LEN: usize = 576;
+/// Largest IPv6 packet carrying an ICMPv6 error, the IPv6 minimum MTU
+pub const IPV6_ERROR_MAX_LEN: usize = 1280;
+
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_SOURCE_QUENCH: u8 = 4;
+pub const ICMP_REDIRECT: u8 = 5;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+pub const ICMP_PARAMETER_PROBLEM: u8 = 12;
+pub const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+pub const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+pub const ICMPV6_PARAMETER_PROBLEM: u8 = 4;
+
+/// Build an ICMP time exceeded in transit error for the offending IPv4 packet
+pub fn icmp_time_exceeded(offending: &Packet, router_src: Ipv4Addr) -> Result<Packet, PacketError> {
+    icmp_error(offending, ICMP_TIME_EXCEEDED, 0, [0; 4], router_src)
+}
+
+/// Build an ICMP destination unreachable error with the given code for the offending IPv4 packet
+pub fn icmp_dest_unreachable(
+    offending: &Packet,
+    code: u8,
+    router_src: Ipv4Addr,
+) -> Result<Packet, PacketError> {
+    icmp_error(offending, ICMP_DEST_UNREACHABLE, code, [0; 4], router_src)
+}
+
+/// Build an ICMPv6 hop limit exceeded in transit error for the offending IPv6 packet
+pub fn icmpv6_time_exceeded(
+    offending: &Packet,
+    router_src: Ipv6Addr,
+) -> Result<Packet, PacketError> {
+    icmpv6_error(offending, ICMPV6_TIME_EXCEEDED, 0, [0; 4], router_src)
+}
+
+/// Build an ICMPv6 destination unreachable error with the given code for the offending IPv6 packet
+pub fn icmpv6_dest_unreachable(
+    offending: &Packet,
+    code: u8,
+    router_src: Ipv6Addr,
+) -> Result<Packet, PacketError> {
+    icmpv6_error(offending, ICMPV6_DEST_UNREACHABLE, code, [0; 4], router_src)
+}
+
+/// Build an ICMPv6 packet too big error carrying the MTU of the next hop link
+pub fn icmpv6_packet_too_big(
+    offending: &Packet,
+    mtu: u32,
+    router_src: Ipv6Addr,
+) -> Result<Packet, PacketError> {
+    icmpv6_error(
+        offending,
+        ICMPV6_PACKET_TOO_BIG,
+        0,
+        mtu.to_be_bytes(),
+        router_src,
+    )
+}
+
+fn icmp_error(
+    offending: &Packet,
+    icmp_type: u8,
+    code: u8,
+    rest: [u8; 4],
+    src: Ipv4Addr,
+) -> Result<Packet, PacketError> {
+    let ip_offset = offending
+        .layer_offset::<IPv4>()
+        .ok_or_else(|| PacketError::MissingHeader(String::from("IPv4")))?;
+    let orig = offending.layer::<IPv4>().unwrap();
+    let max = IPV4_ERROR_MAX_LEN - IPv4::size() - ICMP::size() - rest.len();
+    let data = icmp_data(offending, ip_offset, max, rest);
+
+    let mut icmp = Packet::icmp(icmp_type, code);
+    let mut v = icmp.to_vec();
+    v.extend_from_slice(&data);
+    icmp.set_chksum(inet_checksum(&v) as u64);
+
+    let mut ipv4 = IPv4::new();
+    ipv4.set_identification(0);
+    ipv4.set_flags(0);
+    ipv4.set_frag_offset(0);
+    ipv4.set_ttl(64);
+    ipv4.set_protocol(IpProtocol::ICMP as u64);
+    ipv4.set_total_len((IPv4::size() + v.len()) as u64);
+    ipv4.set_src_ip(src);
+    ipv4.set_dst(orig.src());
+    ipv4.set_header_checksum(0);
+    ipv4.set_header_checksum(inet_checksum(&ipv4.to_vec()) as u64);
+
+    let mut reply = reply_l2(offending, EtherType::IPV4)?;
+    reply.push(ipv4);
+    reply.push(icmp);
+    reply.set_payload(&data);
+    Ok(reply)
+}
+
+fn icmpv6_error(
+    offending: &Packet,
+    icmp_type: u8,
+    code: u8,
+    rest: [u8; 4],
+    src: Ipv6Addr,
+) -> Result<Packet, PacketError> {
+    let ip_offset = offending
+        .layer_offset::<IPv6>()
+        .ok_or_else(|| PacketError::MissingHeader(String::from("IPv6")))?;
+    let orig = offending.layer::<IPv6>().unwrap();
+    let max = IPV6_ERROR_MAX_LEN - IPv6::size() - ICMP::size() - rest.len();
+    let data = icmp_data(offending, ip_offset, max, rest);
+
+    let mut icmp = Packet::icmp(icmp_type, code);
+    let mut v = icmp.to_vec();
+    v.extend_from_slice(&data);
+    let dst = orig.src_ip();
+    let chksum = Packet::l4_checksum(&src.octets(), &dst.octets(), IpProtocol::ICMPV6 as u8, &v);
+    icmp.set_chksum(chksum as u64);
+
+    let mut ipv6 = IPv6::new();
+    ipv6.set_traffic_class(0);
+    ipv6.set_flow_label(0);
+    ipv6.set_hop_limit(64);
+    ipv6.set_next_hdr(IpProtocol::ICMPV6 as u64);
+    ipv6.set_payload_len(v.len() as u64);
+    ipv6.set_src_ip(src);
+    ipv6.set_dst_ip(dst);
+
+    let mut reply = reply_l2(offending, EtherType::IPV6)?;
+    reply.push(ipv6);
+    reply.push(icmp);
+    reply.set_payload(&data);
+    Ok(reply)
+}
+
+/// Ethernet and vlan headers of the reply, sent back to where the offending packet came from
+fn reply_l2(offending: &Packet, etype: EtherType) -> Result<Packet, PacketError> {
+    let etype = etype as u64;
+    let orig = offending
+        .layer::<Ether>()
+        .ok_or_else(|| PacketError::MissingHeader(String::from("Ether")))?;
+    let mut eth = Ether::new();
+    eth.set_dst(orig.src());
+    eth.set_src(orig.dst());
+    eth.set_etype(etype);
+    let mut reply = Packet::new();
+    reply.push(eth);
+    if let Some(orig) = offending.layer::<Vlan>() {
+        let mut vlan = Vlan::from(orig.to_vec());
+        vlan.set_etype(etype);
+        reply
+            .layer_mut::<Ether>()
+            .unwrap()
+            .set_etype(EtherType::DOT1Q as u64);
+        reply.push(vlan);
+    }
+    Ok(reply)
+}
+
+/// Rest of the ICMP header followed by the quoted packet truncated to max bytes
+fn icmp_data(offending: &Packet, ip_offset: usize, max: usize, rest: [u8; 4]) -> Vec<u8> {
+    let quoted = offending.to_vec();
+    let quoted = &quoted[ip_offset..];
+    let mut data = rest.to_vec();
+    data.extend_from_slice(&quoted[..quoted.len().min(max)]);
+    data
+}
+
+pub(crate) fn inet_checksum(v: &[u8]) -> u16 {
+    let mut chksum: u32 = 0;
+    for c in v.chunks(2) {
+        let lsb = if c.len() == 2 { c[1] } else { 0 };
+        chksum += ((c[0] as u32) << 8) | lsb as u32;
+    }
+    while chksum >> 16 != 0 {
+        chksum = (chksum >> 16) + (chksum & 0xFFFF);
+    }
+    !(chksum as u16)
+}
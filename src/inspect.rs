@@ -0,0 +1,108 @@
+//! # Generic header inspector
+//!
+//! [`HeaderInspector`] lists the fields of any header with their sizes and hex values and accepts
+//! hex input back into the fields, or addresses and numbers with
+//! [`set_value`](HeaderInspector::set_value), the editing surface of header editors and TUIs.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! # use packet_rs::inspect::HeaderInspector;
+//! let mut vlan = Vlan::new();
+//! let mut inspector = HeaderInspector::new(&mut vlan);
+//! inspector.set_row("vid", "0x64").unwrap();
+//! assert_eq!(inspector.rows()[2], ("vid", 12, String::from("0x064")));
+//! print!("{}", inspector.render());
+//! ```
+
+use core::fmt::Write;
+
+use crate::headers::*;
+use crate::spec::significant_bits;
+use crate::PacketError;
+
+/// Table view and hex editor over the fields of a header
+pub struct HeaderInspector<'a> {
+    hdr: &'a mut dyn Header,
+}
+
+impl<'a> HeaderInspector<'a> {
+    pub fn new(hdr: &'a mut dyn Header) -> HeaderInspector<'a> {
+        HeaderInspector { hdr }
+    }
+    /// Return the name of the inspected header
+    pub fn name(&self) -> &str {
+        self.hdr.name()
+    }
+    /// Return the field descriptions of the inspected header
+    pub fn fields(&self) -> &'static [FieldInfo] {
+        self.hdr.fields()
+    }
+    /// Return one row per field: name, size in bits and value as 0x prefixed hex
+    ///
+    /// The hex value has one digit per 4 bits of the field.
+    pub fn rows(&self) -> Vec<(&'static str, usize, String)> {
+        self.hdr
+            .fields()
+            .iter()
+            .map(|f| {
+                let bytes = self.hdr.get_field_bytes(f.name).unwrap();
+                let mut hex = String::new();
+                for b in bytes {
+                    write!(hex, "{:02x}", b).unwrap();
+                }
+                let digits = f.size().div_ceil(4);
+                (f.name, f.size(), format!("0x{}", &hex[hex.len() - digits..]))
+            })
+            .collect()
+    }
+    /// Set a field from hex input, with or without a 0x prefix
+    pub fn set_row(&mut self, name: &str, value: &str) -> Result<(), PacketError> {
+        let info = match self.hdr.fields().iter().find(|f| f.is_named(name)) {
+            Some(f) => *f,
+            None => {
+                return Err(PacketError::UnknownField {
+                    header: String::from(self.hdr.name()),
+                    field: String::from(name),
+                })
+            }
+        };
+        let invalid = || PacketError::InvalidValue {
+            field: String::from(name),
+            value: String::from(value),
+        };
+        let hex = value.trim();
+        let hex = hex.strip_prefix("0x").unwrap_or(hex);
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let hex = if hex.len() % 2 == 1 {
+            format!("0{}", hex)
+        } else {
+            String::from(hex)
+        };
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        if significant_bits(&bytes) > info.size() {
+            return Err(invalid());
+        }
+        self.hdr.set_field_bytes(name, &bytes);
+        Ok(())
+    }
+    /// Set a field from a number or, for an address field, an address, see
+    /// [`Header::set_field_str`]
+    pub fn set_value(&mut self, name: &str, value: &str) -> Result<(), PacketError> {
+        self.hdr.set_field_str(name, value.trim())
+    }
+    /// Render the header as a table of fields, bit ranges and hex values
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "#### {}", self.hdr.name()).unwrap();
+        for (f, (name, size, value)) in self.hdr.fields().iter().zip(self.rows()) {
+            let bits = format!("{}-{}", f.lsb, f.msb);
+            writeln!(out, "{:20} {:>9} {:4} {}", name, bits, size, value).unwrap();
+        }
+        out
+    }
+}
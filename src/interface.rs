@@ -0,0 +1,311 @@
+//! # Interfaces and packet verification
+//!
+//! [`Interface`] is the send and receive API shared by the backends: `RawSocket` and
+//! `XdpInterface` on Linux, `BpfDevice` on macOS and FreeBSD, `PcapInterface` with npcap.
+//! [`verify_packet`], [`verify_no_packet`] and [`verify_no_other_packets`] check what an
+//! interface receives, in the style of the PTF `verify_*` helpers, and [`sniff`] collects the
+//! packets selected by a [`Filter`]. They also check the frames
+//! already held by a [`CaptureRing`](crate::capture::CaptureRing) through its ports.
+//!
+//! [`MockInterface`] is an in-memory interface for tests that need neither root nor a veth pair.
+//! Frames it sends are recorded and, through an optional transform standing in for the device
+//! under test, queued back to be received, with seeded latency, drops and reordering.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::interface::*;
+//! # use packet_rs::parser;
+//! # use std::time::Duration;
+//! // a device decrementing the TTL
+//! let intf = MockInterface::new();
+//! intf.set_transform(|frame| {
+//!     let mut pkt = parser::slow::parse(frame);
+//!     pkt.with_zeroed_checksum(|pkt| {
+//!         let ipv4 = pkt.layer_mut::<IPv4>().unwrap();
+//!         ipv4.set_ttl(ipv4.ttl() - 1);
+//!     });
+//!     Some(pkt.to_vec())
+//! });
+//! let pkt = create_tcp_packet(&Default::default());
+//! intf.send_batch(&[&pkt.to_vec()]).unwrap();
+//! let mut expected = pkt.clone();
+//! expected.with_zeroed_checksum(|pkt| pkt.layer_mut::<IPv4>().unwrap().set_ttl(63));
+//! let timeout = Duration::from_millis(100);
+//! verify_packet(&intf, &expected, timeout).unwrap();
+//! verify_no_other_packets(&intf, timeout).unwrap();
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::filter::Filter;
+use crate::payload::splitmix64;
+use crate::{parser, Packet, PacketError};
+
+/// Sends and receives frames on a network interface
+pub trait Interface {
+    /// Send frames in order, returns the number of frames sent
+    ///
+    /// Fails only if no frame could be sent.
+    fn send_batch(&self, frames: &[&[u8]]) -> io::Result<usize>;
+    /// Receive up to one frame per buffer, returns the number of buffers filled
+    ///
+    /// Waits up to timeout for the first frame, forever if None. Returns 0 when the timeout
+    /// expires.
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], timeout: Option<Duration>) -> io::Result<usize>;
+    /// Send one packet
+    fn send_packet(&self, pkt: &Packet) -> io::Result<()> {
+        match self.send_batch(&[&pkt.to_vec()])? {
+            1 => Ok(()),
+            _ => Err(io::Error::new(io::ErrorKind::WouldBlock, "packet not sent")),
+        }
+    }
+}
+
+/// Impairments applied by a [`MockInterface`] to the frames its transform returns
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Impairment {
+    /// Delay before a frame can be received
+    pub latency: Duration,
+    /// Probability of dropping a frame, from 0 to 1
+    pub drop: f64,
+    /// Probability of a frame overtaking the frame queued before it, from 0 to 1
+    pub reorder: f64,
+}
+
+type Transform = Box<dyn FnMut(&[u8]) -> Option<Vec<u8>> + Send>;
+
+struct MockState {
+    /// Frames to receive and the time they can be received
+    rx: VecDeque<(Instant, Vec<u8>)>,
+    sent: Vec<Vec<u8>>,
+    transform: Option<Transform>,
+    impairment: Impairment,
+    rng: u64,
+}
+
+impl MockState {
+    /// Return true with probability p
+    fn chance(&mut self, p: f64) -> bool {
+        let x = (splitmix64(&mut self.rng) >> 11) as f64 / (1u64 << 53) as f64;
+        x < p
+    }
+    /// Queue frame to be received after the latency, counted from sent
+    fn queue(&mut self, frame: Vec<u8>, sent: Instant) {
+        if self.chance(self.impairment.drop) {
+            debug!(len = frame.len(); "mock interface dropped frame");
+            return;
+        }
+        let ready = sent + self.impairment.latency;
+        if !self.rx.is_empty() && self.chance(self.impairment.reorder) {
+            let i = self.rx.len() - 1;
+            self.rx.insert(i, (ready, frame));
+        } else {
+            self.rx.push_back((ready, frame));
+        }
+    }
+}
+
+/// An in-memory interface for tests
+///
+/// Without a transform, sent frames are only recorded. The same seed always applies the same
+/// impairments to the same sequence of frames.
+pub struct MockInterface {
+    state: Mutex<MockState>,
+}
+
+impl Default for MockInterface {
+    fn default() -> Self {
+        MockInterface::new()
+    }
+}
+
+impl MockInterface {
+    /// Create an interface with nothing to receive, no transform and no impairment
+    pub fn new() -> MockInterface {
+        MockInterface {
+            state: Mutex::new(MockState {
+                rx: VecDeque::new(),
+                sent: Vec::new(),
+                transform: None,
+                impairment: Impairment::default(),
+                rng: 0,
+            }),
+        }
+    }
+    /// Queue a frame to be received, without impairment
+    pub fn push_rx(&self, frame: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.rx.push_back((Instant::now(), frame.to_vec()));
+    }
+    /// Queue a packet to be received, without impairment
+    pub fn push_packet(&self, pkt: &Packet) {
+        self.push_rx(&pkt.to_vec());
+    }
+    /// Pass every frame sent from now on to f and queue the frame it returns to be received
+    ///
+    /// f returning None drops the frame.
+    pub fn set_transform(&self, f: impl FnMut(&[u8]) -> Option<Vec<u8>> + Send + 'static) {
+        self.state.lock().unwrap().transform = Some(Box::new(f));
+    }
+    /// Apply impairment to the frames returned by the transform, drawing from seed
+    ///
+    /// The frames of one `send_batch` call are delayed from the same instant, so they become
+    /// ready together whatever the order the reordering leaves them in.
+    pub fn set_impairment(&self, impairment: Impairment, seed: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.impairment = impairment;
+        state.rng = seed;
+    }
+    /// Return the frames sent so far
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().sent.clone()
+    }
+    /// Return the frames sent so far and forget them
+    pub fn take_sent(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.state.lock().unwrap().sent)
+    }
+    /// Return the number of frames waiting to be received, including delayed ones
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().rx.len()
+    }
+}
+
+impl Interface for MockInterface {
+    fn send_batch(&self, frames: &[&[u8]]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        for frame in frames {
+            state.sent.push(frame.to_vec());
+            let out = match state.transform.as_mut() {
+                Some(f) => f(frame),
+                None => None,
+            };
+            if let Some(out) = out {
+                state.queue(out, now);
+            }
+        }
+        Ok(frames.len())
+    }
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], timeout: Option<Duration>) -> io::Result<usize> {
+        if bufs.is_empty() {
+            return Ok(0);
+        }
+        let deadline = timeout.map(|x| Instant::now() + x);
+        loop {
+            let now = Instant::now();
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let mut n = 0;
+                while n < bufs.len() && state.rx.front().is_some_and(|x| x.0 <= now) {
+                    bufs[n] = state.rx.pop_front().unwrap().1;
+                    n += 1;
+                }
+                if n > 0 {
+                    return Ok(n);
+                }
+                state.rx.front().map(|x| x.0 - now)
+            };
+            // frames may also be queued by another thread
+            let mut sleep = wait.unwrap_or(Duration::from_millis(1));
+            if let Some(deadline) = deadline {
+                if now >= deadline {
+                    return Ok(0);
+                }
+                sleep = sleep.min(deadline - now);
+            }
+            thread::sleep(sleep);
+        }
+    }
+}
+
+/// Receive frames until one equals expected, frames that differ are skipped
+///
+/// Fails if no such frame arrives within timeout.
+pub fn verify_packet<I: Interface + ?Sized>(
+    intf: &I,
+    expected: &Packet,
+    timeout: Duration,
+) -> Result<(), PacketError> {
+    let deadline = Instant::now() + timeout;
+    let mut bufs = vec![Vec::new()];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if intf.recv_batch(&mut bufs, Some(left))? == 0 {
+            return Err(PacketError::VerifyFailed(format!(
+                "{} not received",
+                expected.summary()
+            )));
+        }
+        if expected.compare_with_slice(&bufs[0]) {
+            return Ok(());
+        }
+        debug!(len = bufs[0].len(); "skipped frame not matching the expected packet");
+    }
+}
+
+/// Fail if a frame equal to pkt arrives within timeout, other frames are skipped
+pub fn verify_no_packet<I: Interface + ?Sized>(
+    intf: &I,
+    pkt: &Packet,
+    timeout: Duration,
+) -> Result<(), PacketError> {
+    match verify_packet(intf, pkt, timeout) {
+        Ok(()) => Err(PacketError::VerifyFailed(format!(
+            "{} received",
+            pkt.summary()
+        ))),
+        Err(PacketError::VerifyFailed(_)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Fail if any frame arrives within timeout
+pub fn verify_no_other_packets<I: Interface + ?Sized>(
+    intf: &I,
+    timeout: Duration,
+) -> Result<(), PacketError> {
+    let mut bufs = vec![Vec::new()];
+    match intf.recv_batch(&mut bufs, Some(timeout))? {
+        0 => Ok(()),
+        _ => Err(PacketError::VerifyFailed(format!(
+            "unexpected {} byte frame received",
+            bufs[0].len()
+        ))),
+    }
+}
+
+/// Receive frames until timeout expires or count packets are selected, returns the packets
+///
+/// Without a filter every frame is selected.
+pub fn sniff<I: Interface + ?Sized>(
+    intf: &I,
+    filter: Option<&Filter>,
+    count: Option<usize>,
+    timeout: Duration,
+) -> Result<Vec<Packet>, PacketError> {
+    let deadline = Instant::now() + timeout;
+    let mut bufs = vec![Vec::new(); 32];
+    let mut pkts = Vec::new();
+    while count.is_none_or(|x| pkts.len() < x) {
+        // never receive more frames than still wanted, so none are lost to the next call
+        let max = count.map_or(bufs.len(), |x| bufs.len().min(x - pkts.len()));
+        let left = deadline.saturating_duration_since(Instant::now());
+        let n = intf.recv_batch(&mut bufs[..max], Some(left))?;
+        if n == 0 {
+            break;
+        }
+        for buf in &bufs[..n] {
+            if filter.is_none_or(|x| x.matches_bytes(buf)) {
+                pkts.push(parser::slow::parse(buf));
+            }
+        }
+    }
+    Ok(pkts)
+}
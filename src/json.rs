@@ -0,0 +1,264 @@
+//! # Flat JSON documents
+//!
+//! With the `serde` feature, [`Packet::to_json_flat`] renders a packet as a flat document of
+//! dotted keys ready to be indexed as is, by Elasticsearch for instance. Each field is keyed by the
+//! lowercase name of its layer and its own name, as `ipv4.ttl`. A layer found again in the
+//! packet is keyed with its occurrence, as `ipv4_2.ttl` for the inner header of a tunnel.
+//!
+//! Fields declared `[mac]`, `[ipv4]` or `[ipv6]` are address strings, the bits of flags fields are
+//! booleans keyed by their names, as `tcp.flags.syn`, and the values of enum fields are their
+//! names, as `ECHO_REQUEST` for `icmp.icmp_type`. The other fields up to 64 bits are numbers,
+//! wider ones `0x` hex strings. The document also holds the `_summary` line, the `_len` in bytes,
+//! the `_layers` and `_trailers` names and the `_payload` in base64, unless left out with
+//! [`FlatJsonOptions`].
+//!
+//! [`Packet::from_json_flat`] rebuilds the packet from the layer names, the field keys and the
+//! payload, the other keys are ignored.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::Packet;
+//! let pkt = create_tcp_packet(&Default::default());
+//! let doc = pkt.to_json_flat();
+//! assert_eq!(doc["ipv4.src"], "192.168.0.1");
+//! assert_eq!(doc["tcp.flags.syn"], true);
+//! assert_eq!(doc["_layers"][2], "TCP");
+//! assert!(Packet::from_json_flat(&doc).unwrap().compare(&pkt));
+//! ```
+
+use core::net::{Ipv4Addr, Ipv6Addr};
+
+use serde_json::{json, Map, Value};
+
+use crate::addr::MacAddr;
+use crate::headers::*;
+use crate::spec::{parse_value, significant_bits};
+use crate::types::MAC_LEN;
+use crate::{Packet, PacketError};
+
+/// Alphabet of the standard base64 encoding
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Options of [`Packet::to_json_flat_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatJsonOptions {
+    /// Include the payload as `_payload`
+    pub payload: bool,
+}
+
+impl Default for FlatJsonOptions {
+    fn default() -> Self {
+        FlatJsonOptions { payload: true }
+    }
+}
+
+impl Packet {
+    /// Render the packet as a flat document of dotted keys, see [`json`](crate::json)
+    pub fn to_json_flat(&self) -> Value {
+        self.to_json_flat_with(&FlatJsonOptions::default())
+    }
+    /// Render the packet as a flat document of dotted keys with options
+    pub fn to_json_flat_with(&self, options: &FlatJsonOptions) -> Value {
+        let layers: Vec<&dyn Header> = self
+            .hdrs
+            .iter()
+            .chain(&self.trailers)
+            .map(|x| x.as_ref())
+            .collect();
+        let mut doc = Map::new();
+        for (hdr, key) in layers.iter().zip(layer_keys(&layers)) {
+            for f in hdr.fields() {
+                if !f.bits.is_empty() {
+                    let value = hdr.get_field(f.name).unwrap();
+                    for (i, bit) in f.bits.iter().enumerate() {
+                        let set = value >> (f.bits.len() - 1 - i) & 1 == 1;
+                        doc.insert(format!("{}.{}.{}", key, f.name, bit), json!(set));
+                    }
+                    continue;
+                }
+                let bytes = hdr.get_field_bytes(f.name).unwrap();
+                let value = match format_address(f, &bytes) {
+                    Some(addr) => json!(addr),
+                    None if f.size() <= 64 => {
+                        let value = hdr.get_field(f.name).unwrap();
+                        match f.value_name(value) {
+                            Some(name) => json!(name),
+                            None => json!(value),
+                        }
+                    }
+                    None => json!(hex(&bytes)),
+                };
+                doc.insert(format!("{}.{}", key, f.name), value);
+            }
+        }
+        let names = |x: &[Box<dyn Header>]| json!(x.iter().map(|x| x.name()).collect::<Vec<_>>());
+        doc.insert(String::from("_layers"), names(&self.hdrs));
+        doc.insert(String::from("_trailers"), names(&self.trailers));
+        doc.insert(String::from("_len"), json!(self.len()));
+        doc.insert(String::from("_summary"), json!(self.summary()));
+        if options.payload {
+            doc.insert(
+                String::from("_payload"),
+                json!(base64_encode(&self.payload)),
+            );
+        }
+        Value::Object(doc)
+    }
+    /// Rebuild a packet from its flat document
+    ///
+    /// Missing fields keep their defaults. Fails on unknown headers and on values that do not fit
+    /// their field.
+    pub fn from_json_flat(doc: &Value) -> Result<Packet, PacketError> {
+        let invalid = |field: &str, value: &Value| PacketError::InvalidValue {
+            field: String::from(field),
+            value: value.to_string(),
+        };
+        let mut names = Vec::new();
+        for key in ["_layers", "_trailers"] {
+            let layers = match doc.get(key) {
+                None if key == "_trailers" => Vec::new(),
+                Some(Value::Array(x)) => x.iter().map(|x| x.as_str()).collect(),
+                _ => return Err(invalid(key, doc)),
+            };
+            let layers: Option<Vec<&str>> = layers.into_iter().collect();
+            names.push(layers.ok_or_else(|| invalid(key, &doc[key]))?);
+        }
+        let mut hdrs = Vec::new();
+        for name in names.concat() {
+            hdrs.push(
+                header_from_name(name)
+                    .ok_or_else(|| PacketError::UnknownHeader(String::from(name)))?,
+            );
+        }
+        let layers: Vec<&dyn Header> = hdrs.iter().map(|x| x.as_ref()).collect();
+        let keys = layer_keys(&layers);
+        for (hdr, key) in hdrs.iter_mut().zip(keys) {
+            for f in hdr.fields() {
+                if !f.bits.is_empty() {
+                    let mut value = hdr.get_field(f.name).unwrap();
+                    for (i, bit) in f.bits.iter().enumerate() {
+                        let mask = 1 << (f.bits.len() - 1 - i);
+                        let name = format!("{}.{}.{}", key, f.name, bit);
+                        match doc.get(&name) {
+                            None => (),
+                            Some(Value::Bool(true)) => value |= mask,
+                            Some(Value::Bool(false)) => value &= !mask,
+                            Some(v) => return Err(invalid(&name, v)),
+                        }
+                    }
+                    hdr.set_field(f.name, value);
+                    continue;
+                }
+                let name = format!("{}.{}", key, f.name);
+                let v = match doc.get(&name) {
+                    Some(v) => v,
+                    None => continue,
+                };
+                let bytes = match v {
+                    Value::Number(n) => n.as_u64().map(|x| x.to_be_bytes().to_vec()),
+                    Value::String(s) => match f.named_value(s) {
+                        Some(x) => Some(x.to_be_bytes().to_vec()),
+                        None => parse_value(s),
+                    },
+                    _ => None,
+                };
+                match bytes {
+                    Some(bytes) if significant_bits(&bytes) <= f.size() => {
+                        hdr.set_field_bytes(f.name, &bytes);
+                    }
+                    _ => return Err(invalid(&name, v)),
+                }
+            }
+        }
+        let mut pkt = Packet::new();
+        pkt.trailers = hdrs.split_off(names[0].len());
+        pkt.hdrs = hdrs;
+        match doc.get("_payload") {
+            None => (),
+            Some(v @ Value::String(s)) => {
+                pkt.payload = base64_decode(s).ok_or_else(|| invalid("_payload", v))?;
+            }
+            Some(v) => return Err(invalid("_payload", v)),
+        }
+        Ok(pkt)
+    }
+}
+
+/// Key of each layer, its lowercase name followed by its occurrence from the second one on
+fn layer_keys(layers: &[&dyn Header]) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    for (i, hdr) in layers.iter().enumerate() {
+        let key = hdr.name().to_lowercase();
+        match layers[..i]
+            .iter()
+            .filter(|x| x.name() == hdr.name())
+            .count()
+        {
+            0 => keys.push(key),
+            n => keys.push(format!("{}_{}", key, n + 1)),
+        }
+    }
+    keys
+}
+
+/// Value of a field declared `[mac]`, `[ipv4]` or `[ipv6]` as an address, None for the other
+/// fields
+fn format_address(f: &FieldInfo, value: &[u8]) -> Option<String> {
+    let tail = |n: usize| &value[value.len().saturating_sub(n)..];
+    match f.kind {
+        FieldKind::Mac => Some(MacAddr(tail(MAC_LEN).try_into().ok()?).to_string()),
+        FieldKind::Ipv4 => {
+            let ip: [u8; 4] = tail(4).try_into().ok()?;
+            Some(Ipv4Addr::from(ip).to_string())
+        }
+        FieldKind::Ipv6 => {
+            let ip: [u8; 16] = tail(16).try_into().ok()?;
+            Some(Ipv6Addr::from(ip).to_string())
+        }
+        FieldKind::Number | FieldKind::Bytes => None,
+    }
+}
+
+fn hex(v: &[u8]) -> String {
+    let mut out = String::from("0x");
+    for b in v {
+        out.push_str(&format!("{:02x}", b));
+    }
+    out
+}
+
+fn base64_encode(v: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in v.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(BASE64[(n >> (18 - 6 * i)) as usize & 0x3f] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_end_matches('=');
+    let mut out = Vec::new();
+    for chunk in s.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let digit = BASE64.iter().position(|x| x == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
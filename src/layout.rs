@@ -0,0 +1,178 @@
+//! # Wire layout
+//!
+//! [`Packet::layout`] maps the serialized packet: the byte range of every layer and the absolute
+//! bit offset and width of every field, from the field descriptions of the headers. Layers of
+//! variable length, as options pushed as their own layers, are placed at their current size.
+//! The offsets are the ones a switch ACL or a P4 parser matching on raw bytes sees.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! let pkt = create_tcp_packet(&Default::default());
+//! // the ipv4 protocol is byte 23 of the frame
+//! assert_eq!(pkt.field_offset::<IPv4>("protocol"), Some((23 * 8, 8)));
+//! let layout = pkt.layout();
+//! assert_eq!(layout.layers[2].name, "TCP");
+//! assert_eq!(layout.layers[2].range, 34..54);
+//! print!("{}", layout.hexdump(&pkt.to_vec()));
+//! ```
+
+use core::fmt::Write;
+use core::ops::Range;
+
+use crate::alloc_prelude::*;
+use crate::headers::*;
+use crate::Packet;
+
+/// Bytes per line of the hexdump
+const LINE_BYTES: usize = 16;
+
+/// Position of a field in the serialized packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldLayout {
+    /// Name of the field
+    pub name: &'static str,
+    /// Offset in bits of the first bit of the field from the start of the packet
+    pub bit_offset: usize,
+    /// Width of the field in bits
+    pub bits: usize,
+}
+
+impl FieldLayout {
+    /// Return the bytes of the packet holding bits of the field
+    pub fn bytes(&self) -> Range<usize> {
+        self.bit_offset / 8..(self.bit_offset + self.bits).div_ceil(8)
+    }
+}
+
+/// Position of a layer in the serialized packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerLayout {
+    /// Name of the header, `Payload` for the payload
+    pub name: String,
+    /// Bytes of the packet covered by the layer
+    pub range: Range<usize>,
+    /// Fields of the layer, none for the payload
+    pub fields: Vec<FieldLayout>,
+}
+
+/// Layers and fields of a serialized packet, headers first then the payload and the trailers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketLayout {
+    pub layers: Vec<LayerLayout>,
+}
+
+impl PacketLayout {
+    /// Return the field of the first layer named layer
+    pub fn field(&self, layer: &str, field: &str) -> Option<&FieldLayout> {
+        let layer = self.layers.iter().find(|x| x.name == layer)?;
+        layer.fields.iter().find(|x| x.name == field)
+    }
+    /// Iterate over the layer name and the fields holding bits of the bytes in range
+    pub fn fields_in(
+        &self,
+        range: Range<usize>,
+    ) -> impl Iterator<Item = (&str, &FieldLayout)> + '_ {
+        self.layers.iter().flat_map(move |l| {
+            let range = range.clone();
+            l.fields
+                .iter()
+                .filter(move |f| f.bytes().start < range.end && range.start < f.bytes().end)
+                .map(move |f| (l.name.as_str(), f))
+        })
+    }
+    /// Render the bytes of the packet as a hexdump, each line tagged with the fields it holds
+    ///
+    /// A field spanning several lines is listed on each of them.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Vlan::new());
+    /// let dump = pkt.layout().hexdump(&pkt.to_vec());
+    /// assert!(dump.starts_with("0000  00 0a 08 00 "));
+    /// assert!(dump.ends_with("  Vlan.pcp Vlan.cfi Vlan.vid Vlan.etype\n"));
+    /// ```
+    pub fn hexdump(&self, v: &[u8]) -> String {
+        let mut out = String::new();
+        for (i, line) in v.chunks(LINE_BYTES).enumerate() {
+            let start = i * LINE_BYTES;
+            write!(out, "{:04x} ", start).unwrap();
+            for b in line {
+                write!(out, " {:02x}", b).unwrap();
+            }
+            let pad = (LINE_BYTES - line.len()) * 3;
+            write!(out, "{:pad$}  ", "", pad = pad).unwrap();
+            let names: Vec<String> = self
+                .fields_in(start..start + line.len())
+                .map(|(layer, f)| format!("{}.{}", layer, f.name))
+                .collect();
+            out.push_str(names.join(" ").trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn layer_layout(hdr: &dyn Header, offset: usize) -> LayerLayout {
+    let fields = hdr
+        .fields()
+        .iter()
+        .map(|f| FieldLayout {
+            name: f.name,
+            bit_offset: offset * 8 + f.lsb,
+            bits: f.size(),
+        })
+        .collect();
+    LayerLayout {
+        name: String::from(hdr.name()),
+        range: offset..offset + hdr.len(),
+        fields,
+    }
+}
+
+impl Packet {
+    /// Return the byte range of every layer and the position of every field on the wire
+    pub fn layout(&self) -> PacketLayout {
+        let mut layers = Vec::new();
+        let mut offset = 0;
+        for hdr in &self.hdrs {
+            layers.push(layer_layout(hdr.as_ref(), offset));
+            offset += hdr.len();
+        }
+        if !self.payload.is_empty() {
+            layers.push(LayerLayout {
+                name: String::from("Payload"),
+                range: offset..offset + self.payload.len(),
+                fields: Vec::new(),
+            });
+            offset += self.payload.len();
+        }
+        for hdr in self.trailers.iter().rev() {
+            layers.push(layer_layout(hdr.as_ref(), offset));
+            offset += hdr.len();
+        }
+        PacketLayout { layers }
+    }
+    /// Return the bit offset from the start of the packet and the width in bits of a field of
+    /// the first header of type `T`
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(Vlan::new());
+    /// assert_eq!(pkt.field_offset::<Vlan>("vid"), Some((14 * 8 + 4, 12)));
+    /// assert_eq!(pkt.field_offset::<Vlan>("nope"), None);
+    /// ```
+    pub fn field_offset<T: Header + 'static>(&self, field: &str) -> Option<(usize, usize)> {
+        let offset = self.layer_offset::<T>()?;
+        let hdr = self.hdrs.iter().find(|x| x.as_any().is::<T>())?;
+        let f = hdr.fields().iter().find(|x| x.is_named(field))?;
+        Some((offset * 8 + f.lsb, f.size()))
+    }
+}
@@ -13,6 +13,36 @@
 //!  * The [`headers`] module, defines commonly used network packet headers and allows for defining new header types
 //!  * The [`Packet`] struct, a convenient abstraction of a network packet and container to hold a group of headers
 //!  * The [`parser`] module, provides a super fast packet deserializer to compose Packets from slices
+//!  * The [`builders`] module, creates common test packets with correct lengths and checksums
+//!  * The [`compose`] module, stacks headers, packets and bytes with the `/` operator
+//!  * The [`payload`] module, generates and verifies payload patterns and sequence stamps
+//!  * The [`flow`] module, extracts flow 5-tuples and computes RSS/ECMP hashes
+//!  * The [`hop`] module, rewrites packets as they are expected to leave a router
+//!  * The [`rebase`] module, retargets captured packets onto the addressing of a template
+//!  * The [`icmp`] module, builds the ICMP errors a router sends for an offending packet
+//!  * The [`inspect`] module, lists and edits the fields of any header
+//!  * The [`layout`] module, maps the byte range of every layer and the bit offset of every field
+//!  * The [`probe`] module, generates ping and traceroute probes and correlates their replies
+//!  * The [`diagnose`] module, reports invalid length and checksum fields per layer
+//!  * The [`checksum`] module, recomputes the checksums after fields are rewritten
+//!  * The [`frag`] module, fragments IPv4 packets and reassembles them
+//!  * The [`malform`] module, breaks one length, checksum, flag or fragment invariant at a time
+//!  * The [`scapy`] module, exports packets as Scapy expressions
+//!  * The [`summary`] module, summarizes packets on one line for logs
+//!  * The [`tlv`] module, encodes and parses the type-length-value options of many headers
+//!  * The [`tunnel`] module, encapsulates packets in VXLAN and decapsulates them
+//!  * The [`pcap`] module, reads pcap captures from memory or from a file
+//!  * The [`filter`] module, compiles tcpdump filter expressions to select captured packets
+//!  * The [`interface`] module, verifies the packets received on an interface, with an in-memory interface for tests
+//!  * The [`capture`] module, keeps the last frames captured on each interface in a fixed capacity ring
+//!  * The [`reassembly`] module, rebuilds the byte stream of a TCP connection from its segments
+//!  * The [`tracker`] module, groups captured packets into flows with per flow counters and TCP state
+//!  * The `json` module, with the `serde` feature, renders packets as flat JSON documents for indexing
+//!  * The `fixtures` module, with the `serde` feature, builds packets from YAML, TOML or JSON fixtures
+//!  * The `wasm` module, with the `wasm` feature, exports the parser to javascript as JSON
+//!  * The [`ops`] module, decodes, builds and rewrites packets for command line tools
+//!  * The [`addr`] module, parses and formats MAC and IP addresses for the header setters
+//!  * The [`anonymize`] module, maps the addresses and scrubs the payloads of captures to share them
 //!
 //! ### Terminology
 //!  * Packet refers to a container which represents a network packet
@@ -111,26 +141,165 @@
 //! cargo build --features python-module
 //! ```
 //!
+//! ### Raw sockets
+//!
+//! On Linux, the `raw-socket` feature adds the `raw` module to send and receive packets on an
+//! interface. See examples/raw_socket.rs. On macOS and FreeBSD it adds the `bpf` module instead,
+//! built on the `/dev/bpf*` devices.
+//!
+//! ```sh
+//! cargo build --features raw-socket
+//! ```
+//!
+//! The `xdp` feature adds the `xdp` module, an `AF_XDP` socket for higher frame rates.
+//!
+//! ```sh
+//! cargo build --features xdp
+//! ```
+//!
+//! On Windows, the `npcap` feature adds the `npcap` module to send and receive packets through
+//! the npcap driver. It loads libpcap on other systems.
+//!
+//! ```sh
+//! cargo build --features npcap
+//! ```
+//!
+//! ### no_std
+//!
+//! The headers, [`Packet`], the parsers and the checksum and field helpers only need `alloc`.
+//! Building without the default `std` feature drops the `show` methods and the `probe` module.
+//!
+//! The cdylib output of the Python module needs a panic handler and an allocator to link, so
+//! the embedded build asks for the rlib alone:
+//!
+//! ```sh
+//! cargo rustc --lib --target thumbv7em-none-eabihf --no-default-features --features alloc --crate-type rlib
+//! # the host-shim feature links std into the no_std build to run its tests on the host
+//! cargo test --no-default-features --features alloc,host-shim --test no_std
+//! ```
+//!
+//! ### WebAssembly
+//!
+//! The default features build for `wasm32-unknown-unknown`. The `wasm` feature adds
+//! `wasm-bindgen` exports to parse packets and in-memory pcap captures into JSON and to build
+//! packets from it.
+//!
+//! ```sh
+//! wasm-pack build --target web -- --features wasm
+//! ```
+//!
+//! ### Performance
+//!
+//! Field getters and setters of owned headers take the header lock once and move the field as a
+//! whole, slices returned by [`parser::fast`] read fields without locking. In hot loops, parse
+//! with the fast parser, and assemble packets with [`Packet::write_into`] into a reused buffer
+//! rather than [`Packet::to_vec`]. `cargo bench --bench packet` compares these paths with
+//! hand written byte array baselines.
+//!
+//! ### Logging
+//!
+//! Only the `show` methods print. Parse warnings such as truncated layers, checksum mismatches
+//! found by [`Packet::diagnose`] and raw socket events are emitted through the `log` facade with
+//! the layer name, offsets and lengths as key-values.
+//!
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(not(feature = "alloc"))]
+compile_error!("packet_rs needs the std or alloc feature");
+
+extern crate alloc;
+#[cfg(all(not(feature = "std"), feature = "host-shim"))]
+extern crate std as _;
+
+mod alloc_prelude;
+pub mod addr;
+#[cfg(feature = "std")]
+pub mod anonymize;
+pub mod bind;
+#[cfg(all(
+    feature = "raw-socket",
+    any(target_os = "macos", target_os = "freebsd")
+))]
+pub mod bpf;
+pub mod builders;
+#[cfg(feature = "std")]
+pub mod capture;
+pub mod checksum;
+pub mod codegen;
+pub mod compose;
+pub mod diagnose;
+pub mod filter;
+#[cfg(feature = "serde")]
+pub mod fixtures;
+pub mod flow;
+pub mod frag;
 pub mod headers;
+pub mod hop;
+pub mod icmp;
+pub mod inspect;
+#[cfg(feature = "std")]
+pub mod interface;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod layout;
+pub mod malform;
+#[cfg(feature = "npcap")]
+pub mod npcap;
+#[cfg(feature = "std")]
+pub mod ops;
 mod packet;
 pub mod parser;
+pub mod payload;
+pub mod pcap;
+#[cfg(feature = "std")]
+pub mod probe;
+pub mod reassembly;
+pub mod rebase;
+#[cfg(all(feature = "raw-socket", target_os = "linux"))]
+pub mod raw;
+pub mod scapy;
+mod spec;
+pub mod summary;
+mod sync;
+pub mod testutil;
+pub mod tlv;
+#[cfg(feature = "std")]
+pub mod tracker;
+pub mod trailer;
 pub(crate) mod types;
+pub mod tunnel;
 pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(all(feature = "xdp", target_os = "linux"))]
+pub mod xdp;
 
 use headers::*;
 
-#[cfg(not(feature = "python-module"))]
-use pyo3_nullify::*;
-
-#[cfg(feature = "python-module")]
-use pyo3::prelude::*;
-
 #[pyclass]
 /// Structure used to hold an ordered list of headers
+///
+/// Packets are `Send + Sync`: a template built once can be shared between threads, see
+/// [`Packet::template`].
 pub struct Packet {
     hdrs: Vec<Box<dyn Header>>,
     payload: Vec<u8>,
+    /// Headers after the payload, outermost first
+    trailers: Vec<Box<dyn Header>>,
+    /// Fields left as set by the fixups, by layer index and field name
+    poisoned: Vec<(usize, &'static str)>,
+    /// Total length, computed by the first call to `len` after a change of the layers or payload,
+    /// `usize::MAX` until then
+    len: core::sync::atomic::AtomicUsize,
+    /// Bytes the packet was parsed from with `keep_raw`, dropped by the first change
+    raw: Option<Arc<[u8]>>,
+}
+
+/// Contiguous editable copy of a [`Packet`], dissected back into the packet on drop
+pub struct PacketBytesMut<'a> {
+    pkt: &'a mut Packet,
+    buf: Vec<u8>,
 }
 
 /// Structure used to hold an ordered list of header slices
@@ -139,8 +308,164 @@ pub struct PacketSlice<'a> {
     payload: &'a [u8],
 }
 
+/// Errors returned by the fallible APIs of the crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketError {
+    /// The packet spec is malformed
+    InvalidSpec(String),
+    /// No header exists with this name
+    UnknownHeader(String),
+    /// The header has no field with this name
+    UnknownField { header: String, field: String },
+    /// The value cannot be assigned to the field
+    InvalidValue { field: String, value: String },
+    /// The destination buffer cannot hold the data
+    BufferTooSmall { needed: usize, available: usize },
+    /// The packet has no header of this type
+    MissingHeader(String),
+    /// The TTL or hop limit reaches zero when forwarding the packet
+    TtlExceeded,
+    /// A reserved field is not zero
+    ReservedNotZero { header: String, field: String },
+    /// The bytes at offset cannot be parsed as the layer
+    Parse {
+        layer: String,
+        offset: usize,
+        reason: String,
+    },
+    /// The value does not fit in the bits of the field
+    ValueOverflow {
+        field: String,
+        value: u64,
+        bits: usize,
+    },
+    /// The bit range does not cover whole bytes
+    MisalignedRange { msb: usize, lsb: usize },
+    /// The header is not of the expected type
+    HeaderMismatch { expected: String, found: String },
+    /// An I/O operation failed
+    #[cfg(feature = "std")]
+    Io {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
+    /// The checksum of the layer at offset is not valid
+    ChecksumMismatch { layer: String, offset: usize },
+    /// The packets received are not the ones expected
+    VerifyFailed(String),
+    /// The capture filter cannot be compiled at this byte position
+    InvalidFilter { position: usize, reason: String },
+    /// The entry of a packet fixture at path, as `[1].IPv4.ttl`, cannot be applied
+    InvalidFixture { path: String, reason: String },
+    /// The error occurred on the file or packet described by context, as `flows.pcap: packet 3`
+    Context {
+        context: String,
+        error: Box<PacketError>,
+    },
+}
+
+/// The error type of the crate
+pub type Error = PacketError;
+
+impl core::fmt::Display for PacketError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PacketError::InvalidSpec(s) => write!(f, "invalid packet spec: {}", s),
+            PacketError::UnknownHeader(h) => write!(f, "{} header not found", h),
+            PacketError::UnknownField { header, field } => {
+                write!(f, "{} field not found in {} header", field, header)
+            }
+            PacketError::InvalidValue { field, value } => {
+                write!(f, "invalid value {} for {} field", value, field)
+            }
+            PacketError::BufferTooSmall { needed, available } => {
+                write!(f, "buffer of {} bytes cannot hold {} bytes", available, needed)
+            }
+            PacketError::MissingHeader(h) => write!(f, "packet has no {} header", h),
+            PacketError::TtlExceeded => write!(f, "ttl exceeded in transit"),
+            PacketError::ReservedNotZero { header, field } => {
+                write!(f, "reserved {} field of {} header is not zero", field, header)
+            }
+            PacketError::Parse {
+                layer,
+                offset,
+                reason,
+            } => write!(f, "cannot parse {} at offset {}: {}", layer, offset, reason),
+            PacketError::ValueOverflow { field, value, bits } => {
+                write!(f, "value {} does not fit in {} bit {} field", value, bits, field)
+            }
+            PacketError::MisalignedRange { msb, lsb } => {
+                write!(f, "bit range {}..={} is not byte aligned", msb, lsb)
+            }
+            PacketError::HeaderMismatch { expected, found } => {
+                write!(f, "expected {} header, found {}", expected, found)
+            }
+            #[cfg(feature = "std")]
+            PacketError::Io { message, .. } => write!(f, "i/o error: {}", message),
+            PacketError::ChecksumMismatch { layer, offset } => {
+                write!(f, "invalid {} checksum at offset {}", layer, offset)
+            }
+            PacketError::VerifyFailed(s) => write!(f, "verify failed: {}", s),
+            PacketError::InvalidFilter { position, reason } => {
+                write!(f, "invalid filter at position {}: {}", position, reason)
+            }
+            PacketError::InvalidFixture { path, reason } if path.is_empty() => {
+                write!(f, "invalid fixture: {}", reason)
+            }
+            PacketError::InvalidFixture { path, reason } => {
+                write!(f, "invalid fixture entry {}: {}", path, reason)
+            }
+            PacketError::Context { context, error } => write!(f, "{}: {}", context, error),
+        }
+    }
+}
+
+impl core::error::Error for PacketError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            PacketError::Context { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl PacketError {
+    /// Wrap the error with the file or packet it occurred on
+    ///
+    /// ```
+    /// # use packet_rs::PacketError;
+    /// let err = PacketError::MissingHeader(String::from("TCP")).context("packet 3");
+    /// assert_eq!(err.to_string(), "packet 3: packet has no TCP header");
+    /// ```
+    pub fn context(self, context: impl ToString) -> PacketError {
+        PacketError::Context {
+            context: context.to_string(),
+            error: Box::new(self),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for PacketError {
+    fn from(e: std::io::Error) -> PacketError {
+        PacketError::Io {
+            kind: e.kind(),
+            message: e.to_string(),
+        }
+    }
+}
+
+/// Errors reach python as `ValueError`
+#[cfg(feature = "python-module")]
+impl From<PacketError> for PyErr {
+    fn from(e: PacketError) -> PyErr {
+        pyo3::exceptions::PyValueError::new_err(e.to_string())
+    }
+}
+
 #[cfg(feature = "python-module")]
 #[pymodule]
+#[pyo3(name = "packet_rs")]
 fn packet(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Ether>()?;
     m.add_class::<LLC>()?;
@@ -151,9 +476,20 @@ fn packet(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<IPv4>()?;
     m.add_class::<IPv6>()?;
     m.add_class::<ICMP>()?;
+    m.add_class::<Igmp>()?;
+    m.add_class::<IgmpV3Report>()?;
+    m.add_class::<Mld>()?;
+    m.add_class::<MldV2Report>()?;
     m.add_class::<UDP>()?;
+    m.add_class::<UdpLite>()?;
     m.add_class::<TCP>()?;
     m.add_class::<Vxlan>()?;
+    m.add_class::<L2tp>()?;
+    m.add_class::<L2tpLength>()?;
+    m.add_class::<L2tpTunnel>()?;
+    m.add_class::<L2tpSequence>()?;
+    m.add_class::<L2tpOffset>()?;
+    m.add_class::<L2tpV3Session>()?;
     m.add_class::<GRE>()?;
     m.add_class::<GREChksumOffset>()?;
     m.add_class::<GREKey>()?;
@@ -163,6 +499,9 @@ fn packet(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<ERSPANPLATFORM>()?;
     m.add_class::<STP>()?;
     m.add_class::<MPLS>()?;
+    m.add_class::<EthFcs>()?;
+    m.add_class::<HwTimestamp>()?;
+    m.add_class::<HwTimestamp32>()?;
     m.add_class::<Packet>()?;
 
     Ok(())
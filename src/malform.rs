@@ -0,0 +1,452 @@
+//! # Malformed packets
+//!
+//! [`Malformer`] wraps a packet and breaks one invariant at a time, for robustness tests of
+//! parsers, stacks and middleboxes. Every malformation is a named method that documents what it
+//! breaks, and [poisons](Packet::poison) the fields it breaks so the fixups that would repair
+//! them leave them alone. The checksums of the layers not deliberately corrupted are recomputed
+//! after each malformation, so a receiver rejects the packet for the intended reason only.
+//!
+//! The [`Corrupt`] helpers apply the malformations to a [`Packet`] in place.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::malform::Malformer;
+//! let pkt = create_udp_packet(&Default::default());
+//! let mut malformer = Malformer::new(&pkt);
+//! malformer.corrupt_length("UDP").unwrap();
+//! malformer.corrupt_checksum("IPv4").unwrap();
+//! let report = malformer.packet().diagnose();
+//! assert_eq!(report[1].checksum_ok, Some(false));
+//! assert_eq!(report[2].length_ok, Some(false));
+//! assert_eq!(report[2].checksum_ok, Some(true));
+//! ```
+
+use crate::alloc_prelude::*;
+use crate::frag::{set_ipv4_checksum, IPV4_FLAG_DF, IPV4_FLAG_MF};
+use crate::headers::*;
+use crate::{Packet, PacketError};
+
+const TCP_FIN: u64 = 0x01;
+const TCP_SYN: u64 = 0x02;
+
+/// A packet being malformed
+#[derive(Clone)]
+pub struct Malformer {
+    pkt: Packet,
+    /// Index of the layers whose checksum is deliberately wrong
+    bad_checksums: Vec<usize>,
+}
+
+impl Malformer {
+    /// Start from a copy of pkt
+    pub fn new(pkt: &Packet) -> Malformer {
+        Malformer {
+            pkt: pkt.clone(),
+            bad_checksums: Vec::new(),
+        }
+    }
+    /// Return the malformed packet
+    pub fn packet(&self) -> &Packet {
+        &self.pkt
+    }
+    /// Return the malformed packet, consuming the malformer
+    pub fn into_packet(self) -> Packet {
+        self.pkt
+    }
+    /// Make the length field of the first layer named layer claim one byte more than it did
+    ///
+    /// The IPv4 total length, IPv6 payload length and UDP length then cover bytes that are not
+    /// there. The TCP data offset is set to 4 words, shorter than the fixed TCP header.
+    pub fn corrupt_length(&mut self, layer: &str) -> Result<&mut Malformer, PacketError> {
+        let index = self.position(layer)?;
+        let hdr = &mut self.pkt.hdrs[index];
+        let field = match hdr.name() {
+            "IPv4" => "total_len",
+            "IPv6" => "payload_len",
+            "UDP" => "length",
+            "TCP" => {
+                hdr.set_field("data_offset", 4);
+                self.pkt.poison(index, "data_offset")?;
+                return Ok(self.refresh());
+            }
+            name => {
+                return Err(PacketError::UnknownField {
+                    header: String::from(name),
+                    field: String::from("length"),
+                })
+            }
+        };
+        let len = hdr.get_field(field).unwrap();
+        // a length already at the maximum claims one byte less instead
+        hdr.set_field(field, if len < 0xffff { len + 1 } else { len - 1 });
+        self.pkt.poison(index, field)?;
+        Ok(self.refresh())
+    }
+    /// Make the checksum of the first layer named layer wrong
+    ///
+    /// The checksum stays wrong after later malformations and is never zero, which would mean no
+    /// checksum for UDP.
+    pub fn corrupt_checksum(&mut self, layer: &str) -> Result<&mut Malformer, PacketError> {
+        let index = self.position(layer)?;
+        self.corrupt_checksum_at(index)
+    }
+    /// Make the checksum of the first layer after the first IP header that has one wrong
+    ///
+    /// As [`Malformer::corrupt_checksum`], for the TCP, UDP or ICMP header of the packet.
+    pub fn corrupt_l4_checksum(&mut self) -> Result<&mut Malformer, PacketError> {
+        let ip = self.ip_index()?;
+        let index = (ip + 1..self.pkt.hdrs.len())
+            .find(|x| self.pkt.hdrs[*x].checksum_field().is_some())
+            .ok_or_else(|| PacketError::MissingHeader(String::from("TCP")))?;
+        self.corrupt_checksum_at(index)
+    }
+    fn corrupt_checksum_at(&mut self, index: usize) -> Result<&mut Malformer, PacketError> {
+        let hdr = &self.pkt.hdrs[index];
+        if hdr.checksum_field().is_none() {
+            return Err(PacketError::UnknownField {
+                header: String::from(hdr.name()),
+                field: String::from("checksum"),
+            });
+        }
+        if !self.bad_checksums.contains(&index) {
+            self.bad_checksums.push(index);
+        }
+        Ok(self.refresh())
+    }
+    /// Set the IPv4 header length to n words, whatever the options present
+    ///
+    /// Below 5 the header is shorter than its fixed fields, above it swallows the bytes that
+    /// follow as options.
+    pub fn set_ihl(&mut self, n: u8) -> Result<&mut Malformer, PacketError> {
+        let n = check_nibble("ihl", n)?;
+        self.set_ipv4("ihl", n)
+    }
+    /// Set the version of the first IP header to n, whatever the header is
+    pub fn set_version(&mut self, n: u8) -> Result<&mut Malformer, PacketError> {
+        let n = check_nibble("version", n)?;
+        let index = self.ip_index()?;
+        self.pkt.hdrs[index].set_field("version", n);
+        self.pkt.poison(index, "version")?;
+        Ok(self.refresh())
+    }
+    /// Set the IPv4 TTL to zero, a packet no router may forward
+    pub fn ttl_zero(&mut self) -> Result<&mut Malformer, PacketError> {
+        self.set_ipv4("ttl", 0)
+    }
+    /// Set the IPv4 total length to 65535, past the end of the packet
+    pub fn giant_total_len(&mut self) -> Result<&mut Malformer, PacketError> {
+        self.set_ipv4("total_len", 0xffff)
+    }
+    /// Set both the don't fragment and more fragments flags of the IPv4 header
+    ///
+    /// A packet that must not be fragmented cannot be followed by more fragments.
+    pub fn fragment_with_df(&mut self) -> Result<&mut Malformer, PacketError> {
+        let flags = self.ipv4()?.flags();
+        self.set_ipv4("flags", flags | IPV4_FLAG_DF | IPV4_FLAG_MF)
+    }
+    /// Set both the SYN and FIN flags of the TCP header, opening and closing the connection at once
+    pub fn syn_fin(&mut self) -> Result<&mut Malformer, PacketError> {
+        let index = self
+            .pkt
+            .hdrs
+            .iter()
+            .position(|x| x.as_any().is::<TCP>())
+            .ok_or_else(|| PacketError::MissingHeader(String::from("TCP")))?;
+        let tcp: &mut TCP = (&mut self.pkt.hdrs[index]).into();
+        tcp.set_flags(tcp.flags() | TCP_SYN | TCP_FIN);
+        self.pkt.poison(index, "flags")?;
+        Ok(self.refresh())
+    }
+    /// Set every bit of the reserved fields of every layer
+    pub fn set_reserved(&mut self) -> &mut Malformer {
+        for index in 0..self.pkt.hdrs.len() {
+            let hdr = &mut self.pkt.hdrs[index];
+            let fields: Vec<_> = hdr.fields().iter().filter(|x| x.reserved).collect();
+            for field in &fields {
+                let bits = field.msb - field.lsb + 1;
+                hdr.set_field(field.name, u64::MAX >> (64 - bits));
+            }
+            for field in fields {
+                self.pkt.poison(index, field.name).unwrap();
+            }
+        }
+        self.refresh()
+    }
+    /// Cut the packet to its first len bytes, keeping the length fields of the full packet
+    ///
+    /// The layers past len are lost, the bytes of a layer cut in the middle are left as payload.
+    pub fn truncate(&mut self, len: usize) -> &mut Malformer {
+        let poisoned = core::mem::take(&mut self.pkt.poisoned);
+        self.pkt = self.pkt.clone_truncated(len).0;
+        let layers = self.pkt.hdrs.len();
+        self.pkt.poisoned = poisoned;
+        self.pkt.poisoned.retain(|x| x.0 < layers);
+        self.bad_checksums.retain(|x| *x < layers);
+        self
+    }
+    /// Flip a bit of the byte at offset byte of the packet, bit 0 being the most significant
+    ///
+    /// Every checksum keeps the value it had before the flip, so the one covering the byte fails.
+    pub fn flip_bit(&mut self, byte: usize, bit: u8) -> Result<&mut Malformer, PacketError> {
+        let pkt = &mut self.pkt;
+        if byte >= pkt.len() || bit > 7 {
+            return Err(PacketError::InvalidValue {
+                field: String::from("byte"),
+                value: format!("{}.{}", byte, bit),
+            });
+        }
+        pkt.invalidate();
+        for index in 0..pkt.hdrs.len() {
+            if let Some(field) = pkt.hdrs[index].checksum_field() {
+                pkt.poison(index, field.name)?;
+            }
+        }
+        let mut offset = 0;
+        for hdr in pkt.hdrs.iter_mut() {
+            if byte >= offset + hdr.len() {
+                offset += hdr.len();
+                continue;
+            }
+            let pos = (byte - offset) * 8 + bit as usize;
+            let field = hdr
+                .fields()
+                .iter()
+                .find(|x| (x.lsb..=x.msb).contains(&pos))
+                .ok_or_else(|| PacketError::InvalidValue {
+                    field: String::from("byte"),
+                    value: format!("{}.{}", byte, bit),
+                })?;
+            // the field bytes are aligned to the least significant bit of the last byte
+            let mut value = hdr.get_field_bytes(field.name).unwrap();
+            let width = field.msb - field.lsb + 1;
+            let at = value.len() * 8 - width + pos - field.lsb;
+            value[at / 8] ^= 0x80 >> (at % 8);
+            hdr.set_field_bytes(field.name, &value);
+            return Ok(self);
+        }
+        pkt.payload[byte - offset] ^= 0x80 >> bit;
+        Ok(self)
+    }
+    /// Fragment the packet at mtu and make every fragment overlap the data of the previous one
+    ///
+    /// Each fragment after the first starts overlap bytes earlier, rounded up to whole 8 byte
+    /// blocks and limited to the previous fragment, and repeats the original bytes there. The
+    /// fragments are otherwise valid, a reassembler must choose which copy to keep or drop the
+    /// packet. Fails if the packet has no IPv4 header or fits in mtu.
+    pub fn overlap_fragments(
+        &self,
+        mtu: usize,
+        overlap: usize,
+    ) -> Result<Vec<Packet>, PacketError> {
+        self.pkt
+            .layer::<IPv4>()
+            .ok_or_else(|| PacketError::MissingHeader(String::from("IPv4")))?;
+        let mut frags = self.pkt.fragment_ipv4(mtu);
+        if frags.len() < 2 {
+            return Err(PacketError::InvalidValue {
+                field: String::from("mtu"),
+                value: mtu.to_string(),
+            });
+        }
+        let options_len = |frag: &Packet| {
+            let ipv4 = frag.layer::<IPv4>().unwrap();
+            (ipv4.ihl() as usize * 4).saturating_sub(IPv4::size())
+        };
+        let data: Vec<u8> = frags
+            .iter()
+            .flat_map(|x| x.payload[options_len(x)..].to_vec())
+            .collect();
+        let base = frags[0].layer::<IPv4>().unwrap().frag_offset() as usize * 8;
+        let mut prev_start = base;
+        for frag in frags.iter_mut() {
+            let options = frag.payload[..options_len(frag)].to_vec();
+            let ipv4 = frag.layer_mut::<IPv4>().unwrap();
+            let start = ipv4.frag_offset() as usize * 8;
+            let end = start + ipv4.total_len() as usize - IPv4::size() - options.len();
+            let new_start = start - overlap.div_ceil(8).min((start - prev_start) / 8) * 8;
+            ipv4.set_frag_offset((new_start / 8) as u64);
+            ipv4.set_total_len((IPv4::size() + options.len() + end - new_start) as u64);
+            set_ipv4_checksum(ipv4, &options);
+            frag.payload = [options.as_slice(), &data[new_start - base..end - base]].concat();
+            frag.invalidate();
+            prev_start = start;
+        }
+        Ok(frags)
+    }
+    /// Index of the first layer named layer
+    fn position(&self, layer: &str) -> Result<usize, PacketError> {
+        self.pkt
+            .hdrs
+            .iter()
+            .position(|x| x.name() == layer)
+            .ok_or_else(|| PacketError::UnknownHeader(String::from(layer)))
+    }
+    /// Index of the first IP layer
+    fn ip_index(&self) -> Result<usize, PacketError> {
+        self.pkt
+            .hdrs
+            .iter()
+            .position(|x| matches!(x.name(), "IPv4" | "IPv6"))
+            .ok_or_else(|| PacketError::MissingHeader(String::from("IPv4")))
+    }
+    fn ipv4(&mut self) -> Result<&mut IPv4, PacketError> {
+        self.pkt
+            .layer_mut::<IPv4>()
+            .ok_or_else(|| PacketError::MissingHeader(String::from("IPv4")))
+    }
+    /// Set a field of the first IPv4 layer and poison it
+    fn set_ipv4(&mut self, field: &str, value: u64) -> Result<&mut Malformer, PacketError> {
+        let index = self.pkt.ipv4_index()?;
+        self.pkt.hdrs[index].set_field(field, value);
+        self.pkt.poison(index, field)?;
+        Ok(self.refresh())
+    }
+    /// Recompute the checksums, then corrupt the ones meant to be wrong
+    fn refresh(&mut self) -> &mut Malformer {
+        let Malformer { pkt, bad_checksums } = self;
+        // the wrong checksums are one off the valid ones, recomputed as well
+        pkt.poisoned.retain(|x| {
+            !bad_checksums.contains(&x.0)
+                || pkt.hdrs[x.0].checksum_field().map(|f| f.name) != Some(x.1)
+        });
+        pkt.update_checksums();
+        for index in bad_checksums.iter() {
+            let field = off_by_one(pkt.hdrs[*index].as_mut());
+            pkt.poison(*index, field).unwrap();
+        }
+        self
+    }
+}
+
+/// Make the checksum of hdr one more than it is, returns the checksum field name
+fn off_by_one(hdr: &mut dyn Header) -> &'static str {
+    let field = hdr.checksum_field().unwrap();
+    // 0 and 0xffff are the same ones' complement sum, skip both
+    let bad = match hdr.get_field(field.name).unwrap() as u16 {
+        0xfffe | 0xffff => 1,
+        x => x + 1,
+    };
+    hdr.set_field(field.name, bad as u64);
+    field.name
+}
+
+/// Deliberate corruptions of a packet for negative tests
+///
+/// Each helper runs a malformation of [`Malformer`] on the packet in place, and names the counter
+/// a compliant receiver increments when it drops the packet: the interface group MIB counters of
+/// RFC 4293 for IP and the `InCsumErrors` counters of the TCP and UDP MIBs. The other checksums
+/// are recomputed first, then the broken field is [poisoned](Packet::poison) so
+/// [`Packet::update_checksums`] and [`Packet::to_vec_with_fixup`] leave it wrong.
+///
+/// ```
+/// # use packet_rs::builders::*;
+/// # use packet_rs::malform::Corrupt;
+/// let mut pkt = create_tcp_packet(&Default::default());
+/// pkt.ttl_zero().unwrap();
+/// pkt.update_checksums();
+/// let bad: Vec<_> = pkt.verify().failures().map(|x| x.field).collect();
+/// assert_eq!(bad, vec!["ttl"]);
+/// ```
+pub trait Corrupt {
+    /// Make the IPv4 header checksum one more than the valid one
+    ///
+    /// Counted in `ipSystemStatsInHdrErrors`.
+    fn bad_ipv4_checksum(&mut self) -> Result<&mut Self, PacketError>;
+    /// Make the checksum of the layer following the first IP header one more than the valid one
+    ///
+    /// Counted in `tcpInErrs` and `tcpInCsumErrors` for TCP, `udpInErrors` and
+    /// `udpInCsumErrors` for UDP, `icmpInErrors` for ICMP.
+    fn bad_l4_checksum(&mut self) -> Result<&mut Self, PacketError>;
+    /// Cut the packet to its first len bytes, shorter than its length fields claim
+    ///
+    /// Counted in `ipSystemStatsInTruncatedPkts`. The layers past len are lost, the bytes of a
+    /// layer cut in the middle are left as payload.
+    fn truncate(&mut self, len: usize) -> &mut Self;
+    /// Set the IPv4 header length to value words
+    ///
+    /// Counted in `ipSystemStatsInHdrErrors`. The header checksum covers the new length.
+    fn bad_ihl(&mut self, value: u8) -> Result<&mut Self, PacketError>;
+    /// Set the version of the first IP header to value
+    ///
+    /// Counted in `ipSystemStatsInHdrErrors`.
+    fn bad_version(&mut self, value: u8) -> Result<&mut Self, PacketError>;
+    /// Set the IPv4 TTL to zero
+    ///
+    /// A router drops the packet, counts it in `ipSystemStatsInHdrErrors` and sends an ICMP time
+    /// exceeded, counted in `icmpOutTimeExcds`.
+    fn ttl_zero(&mut self) -> Result<&mut Self, PacketError>;
+    /// Set the IPv4 total length to 65535, past the end of the packet
+    ///
+    /// Counted in `ipSystemStatsInTruncatedPkts`.
+    fn giant_total_len(&mut self) -> Result<&mut Self, PacketError>;
+    /// Flip a bit of the byte at offset byte of the packet, bit 0 being the most significant
+    ///
+    /// The checksums are left as they were, so the checksum covering the byte fails and is
+    /// counted as for [`Corrupt::bad_ipv4_checksum`] or [`Corrupt::bad_l4_checksum`]. A flip in
+    /// a field that is not covered by a checksum, as the Ethernet header, is only seen by the
+    /// check of that field.
+    fn flip_bit(&mut self, byte: usize, bit: u8) -> Result<&mut Self, PacketError>;
+}
+
+impl Corrupt for Packet {
+    fn bad_ipv4_checksum(&mut self) -> Result<&mut Packet, PacketError> {
+        self.ipv4_index()?;
+        self.malform(|m| m.corrupt_checksum("IPv4"))
+    }
+    fn bad_l4_checksum(&mut self) -> Result<&mut Packet, PacketError> {
+        self.malform(|m| m.corrupt_l4_checksum())
+    }
+    fn truncate(&mut self, len: usize) -> &mut Packet {
+        self.malform(|m| Ok(m.truncate(len))).unwrap()
+    }
+    fn bad_ihl(&mut self, value: u8) -> Result<&mut Packet, PacketError> {
+        self.malform(|m| m.set_ihl(value))
+    }
+    fn bad_version(&mut self, value: u8) -> Result<&mut Packet, PacketError> {
+        self.malform(|m| m.set_version(value))
+    }
+    fn ttl_zero(&mut self) -> Result<&mut Packet, PacketError> {
+        self.malform(|m| m.ttl_zero())
+    }
+    fn giant_total_len(&mut self) -> Result<&mut Packet, PacketError> {
+        self.malform(|m| m.giant_total_len())
+    }
+    fn flip_bit(&mut self, byte: usize, bit: u8) -> Result<&mut Packet, PacketError> {
+        self.malform(|m| m.flip_bit(byte, bit))
+    }
+}
+
+impl Packet {
+    /// Index of the first IPv4 layer
+    fn ipv4_index(&self) -> Result<usize, PacketError> {
+        self.hdrs
+            .iter()
+            .position(|x| x.name() == "IPv4")
+            .ok_or_else(|| PacketError::MissingHeader(String::from("IPv4")))
+    }
+    /// Apply a malformation of [`Malformer`] to the packet in place
+    fn malform(
+        &mut self,
+        f: impl FnOnce(&mut Malformer) -> Result<&mut Malformer, PacketError>,
+    ) -> Result<&mut Packet, PacketError> {
+        let mut malformer = Malformer {
+            pkt: core::mem::replace(self, Packet::new()),
+            bad_checksums: Vec::new(),
+        };
+        let result = f(&mut malformer).map(|_| ());
+        *self = malformer.pkt;
+        result.map(|_| self)
+    }
+}
+
+/// Check that value fits in the 4 bits of field
+fn check_nibble(field: &str, value: u8) -> Result<u64, PacketError> {
+    match value {
+        0..=0xf => Ok(value as u64),
+        _ => Err(PacketError::ValueOverflow {
+            field: String::from(field),
+            value: value as u64,
+            bits: 4,
+        }),
+    }
+}
@@ -0,0 +1,494 @@
+//! # Packet capture and injection with npcap
+//!
+//! Enabled with the `npcap` feature, this is the backend for Windows where `AF_PACKET` does not
+//! exist. [`PcapInterface`] sends and receives frames through the npcap driver with the same
+//! `send_batch`, `Read` and `Write` shape as [`RawSocket`](../raw/struct.RawSocket.html).
+//!
+//! `wpcap.dll` is loaded when first used, from the `Npcap` folder of the system directory, so
+//! building needs neither the npcap SDK nor the driver. Every function fails with
+//! [`io::ErrorKind::NotFound`] when npcap is not installed. Other systems load `libpcap`, which
+//! has the same API, so the backend can be tried on Linux and macOS as well.
+//!
+//! Interfaces are named `\Device\NPF_{GUID}`. [`PcapInterface::open`] also accepts the bare GUID
+//! or the adapter description shown by [`devices`].
+//!
+//! ```no_run
+//! # use packet_rs::builders::*;
+//! # use packet_rs::npcap::{devices, PcapInterface};
+//! # use std::io::Write;
+//! # use std::time::Duration;
+//! for dev in devices().unwrap() {
+//!     println!("{} {:?} {}", dev.name, dev.guid(), dev.description);
+//! }
+//! let mut intf = PcapInterface::open("Intel(R) Ethernet Connection").unwrap();
+//! intf.write_all(&create_tcp_packet(&Default::default()).to_vec()).unwrap();
+//! if let Some(frame) = intf.recv(Some(Duration::from_secs(1))).unwrap() {
+//!     println!("{:?} {} bytes", frame.time, frame.data.len());
+//! }
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{debug, warn};
+
+use crate::interface::Interface;
+
+/// Largest frame captured
+const SNAPLEN: c_int = 65535;
+
+/// Milliseconds a capture call waits for a frame
+const READ_TIMEOUT_MS: c_int = 100;
+
+const PCAP_ERRBUF_SIZE: usize = 256;
+
+/// The interface is a loopback interface
+const PCAP_IF_LOOPBACK: u32 = 0x1;
+
+#[repr(C)]
+struct PcapIf {
+    next: *mut PcapIf,
+    name: *mut c_char,
+    description: *mut c_char,
+    addresses: *mut c_void,
+    flags: u32,
+}
+
+/// `struct timeval` of the capture header, its fields are 32 bits wide on Windows
+#[cfg(windows)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Timeval {
+    tv_sec: i32,
+    tv_usec: i32,
+}
+
+#[cfg(not(windows))]
+type Timeval = libc::timeval;
+
+#[repr(C)]
+struct PcapPkthdr {
+    ts: Timeval,
+    caplen: u32,
+    len: u32,
+}
+
+/// The functions of `wpcap.dll` used by the backend
+struct Api {
+    findalldevs: unsafe extern "C" fn(*mut *mut PcapIf, *mut c_char) -> c_int,
+    freealldevs: unsafe extern "C" fn(*mut PcapIf),
+    open_live: unsafe extern "C" fn(*const c_char, c_int, c_int, c_int, *mut c_char) -> *mut c_void,
+    close: unsafe extern "C" fn(*mut c_void),
+    sendpacket: unsafe extern "C" fn(*mut c_void, *const u8, c_int) -> c_int,
+    next_ex: unsafe extern "C" fn(*mut c_void, *mut *mut PcapPkthdr, *mut *const u8) -> c_int,
+    geterr: unsafe extern "C" fn(*mut c_void) -> *mut c_char,
+}
+
+static API: OnceLock<Result<Api, String>> = OnceLock::new();
+
+fn api() -> io::Result<&'static Api> {
+    API.get_or_init(|| unsafe { load() })
+        .as_ref()
+        .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e.clone()))
+}
+
+unsafe fn load() -> Result<Api, String> {
+    let lib = dl::open().ok_or_else(|| format!("{} not found, is npcap installed?", dl::LIB))?;
+    Ok(Api {
+        findalldevs: sym(lib, "pcap_findalldevs\0")?,
+        freealldevs: sym(lib, "pcap_freealldevs\0")?,
+        open_live: sym(lib, "pcap_open_live\0")?,
+        close: sym(lib, "pcap_close\0")?,
+        sendpacket: sym(lib, "pcap_sendpacket\0")?,
+        next_ex: sym(lib, "pcap_next_ex\0")?,
+        geterr: sym(lib, "pcap_geterr\0")?,
+    })
+}
+
+/// Look up a function of the library, name must be nul terminated
+unsafe fn sym<T: Copy>(lib: *mut c_void, name: &str) -> Result<T, String> {
+    assert_eq!(mem::size_of::<T>(), mem::size_of::<*mut c_void>());
+    let p = dl::sym(lib, name.as_ptr() as *const c_char);
+    if p.is_null() {
+        return Err(format!(
+            "{} has no {}",
+            dl::LIB,
+            name.trim_end_matches('\0')
+        ));
+    }
+    Ok(mem::transmute_copy(&p))
+}
+
+#[cfg(windows)]
+mod dl {
+    use std::os::raw::{c_char, c_void};
+
+    pub const LIB: &str = "wpcap.dll";
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LoadLibraryA(name: *const c_char) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+        fn SetDllDirectoryA(path: *const c_char) -> i32;
+        fn GetSystemDirectoryA(buf: *mut c_char, size: u32) -> u32;
+    }
+
+    pub unsafe fn open() -> Option<*mut c_void> {
+        // npcap installs its dlls in System32\Npcap, which is not on the search path
+        let mut buf = [0u8; 260];
+        let n = GetSystemDirectoryA(buf.as_mut_ptr() as *mut c_char, buf.len() as u32) as usize;
+        if n > 0 && n < buf.len() {
+            let mut dir = buf[..n].to_vec();
+            dir.extend_from_slice(b"\\Npcap\0");
+            SetDllDirectoryA(dir.as_ptr() as *const c_char);
+        }
+        let lib = LoadLibraryA(b"wpcap.dll\0".as_ptr() as *const c_char);
+        (!lib.is_null()).then_some(lib)
+    }
+
+    pub unsafe fn sym(lib: *mut c_void, name: *const c_char) -> *mut c_void {
+        GetProcAddress(lib, name)
+    }
+}
+
+#[cfg(not(windows))]
+mod dl {
+    use std::os::raw::{c_char, c_void};
+
+    pub const LIB: &str = "libpcap";
+
+    const NAMES: &[&[u8]] = &[b"libpcap.so.1\0", b"libpcap.so\0", b"libpcap.A.dylib\0"];
+
+    pub unsafe fn open() -> Option<*mut c_void> {
+        NAMES
+            .iter()
+            .map(|x| libc::dlopen(x.as_ptr() as *const c_char, libc::RTLD_NOW))
+            .find(|x| !x.is_null())
+    }
+
+    pub unsafe fn sym(lib: *mut c_void, name: *const c_char) -> *mut c_void {
+        libc::dlsym(lib, name)
+    }
+}
+
+fn pcap_error(buf: &[c_char]) -> io::Error {
+    let msg = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    io::Error::other(msg.to_string_lossy().into_owned())
+}
+
+/// An interface npcap can capture on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    /// Name passed to npcap, `\Device\NPF_{GUID}` on Windows
+    pub name: String,
+    /// Description of the adapter, empty if npcap has none
+    pub description: String,
+    /// The interface is a loopback interface
+    pub loopback: bool,
+}
+
+impl Device {
+    /// Return the GUID of the adapter, with its braces
+    pub fn guid(&self) -> Option<&str> {
+        let start = self.name.find('{')?;
+        let end = self.name[start..].find('}')? + start;
+        Some(&self.name[start..=end])
+    }
+    /// Return true if name is the name, the GUID or the description of the interface
+    pub fn matches(&self, name: &str) -> bool {
+        let guid = name.trim_start_matches('{').trim_end_matches('}');
+        self.name == name
+            || (!self.description.is_empty() && self.description == name)
+            || self
+                .guid()
+                .is_some_and(|x| x[1..x.len() - 1].eq_ignore_ascii_case(guid))
+    }
+}
+
+/// List the interfaces npcap can capture on
+pub fn devices() -> io::Result<Vec<Device>> {
+    let api = api()?;
+    let mut errbuf = [0 as c_char; PCAP_ERRBUF_SIZE];
+    let mut all: *mut PcapIf = ptr::null_mut();
+    if unsafe { (api.findalldevs)(&mut all, errbuf.as_mut_ptr()) } != 0 {
+        return Err(pcap_error(&errbuf));
+    }
+    let string = |p: *mut c_char| match p.is_null() {
+        true => String::new(),
+        false => unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned(),
+    };
+    let mut devs = Vec::new();
+    let mut cur = all;
+    while let Some(dev) = unsafe { cur.as_ref() } {
+        devs.push(Device {
+            name: string(dev.name),
+            description: string(dev.description),
+            loopback: dev.flags & PCAP_IF_LOOPBACK != 0,
+        });
+        cur = dev.next;
+    }
+    unsafe { (api.freealldevs)(all) };
+    Ok(devs)
+}
+
+/// Find an interface by name, GUID or description
+pub fn find_device(name: &str) -> io::Result<Device> {
+    devices()?
+        .into_iter()
+        .find(|x| x.matches(name))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no interface {}", name)))
+}
+
+/// A frame captured by [`PcapInterface::recv`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The captured bytes
+    pub data: Vec<u8>,
+    /// Length of the frame on the wire, more than the data if the frame was truncated
+    pub len: usize,
+    /// Time the driver captured the frame
+    pub time: SystemTime,
+}
+
+fn to_system_time(ts: Timeval) -> SystemTime {
+    UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_usec as u32 * 1000)
+}
+
+struct Handle(*mut c_void);
+
+// npcap handles may move between threads, the mutex keeps calls on one handle serialized
+unsafe impl Send for Handle {}
+
+/// An interface opened for capture and injection with npcap
+pub struct PcapInterface {
+    handle: Mutex<Handle>,
+}
+
+impl PcapInterface {
+    /// Open an interface in promiscuous mode by name, GUID or description
+    pub fn open(name: &str) -> io::Result<PcapInterface> {
+        let api = api()?;
+        let dev = find_device(name)?;
+        let cname = CString::new(dev.name.as_str()).map_err(io::Error::other)?;
+        let mut errbuf = [0 as c_char; PCAP_ERRBUF_SIZE];
+        let p = unsafe {
+            (api.open_live)(
+                cname.as_ptr(),
+                SNAPLEN,
+                1,
+                READ_TIMEOUT_MS,
+                errbuf.as_mut_ptr(),
+            )
+        };
+        if p.is_null() {
+            let err = pcap_error(&errbuf);
+            warn!(ifname = dev.name.as_str(), error:% = err; "npcap open failed");
+            return Err(err);
+        }
+        debug!(ifname = dev.name.as_str(); "npcap interface opened");
+        Ok(PcapInterface {
+            handle: Mutex::new(Handle(p)),
+        })
+    }
+    /// Send one frame
+    pub fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let api = api()?;
+        let handle = self.lock()?;
+        if unsafe { (api.sendpacket)(handle.0, frame.as_ptr(), frame.len() as c_int) } != 0 {
+            return Err(last_error(api, &handle));
+        }
+        Ok(())
+    }
+    /// Send frames in order, returns the number of frames sent
+    ///
+    /// Fails only if the first frame cannot be sent.
+    pub fn send_batch(&self, frames: &[&[u8]]) -> io::Result<usize> {
+        for (i, frame) in frames.iter().enumerate() {
+            if let Err(e) = self.send(frame) {
+                if i == 0 {
+                    return Err(e);
+                }
+                return Ok(i);
+            }
+        }
+        Ok(frames.len())
+    }
+    /// Receive one frame, None if none arrives before the timeout
+    ///
+    /// Without a timeout, wait until a frame arrives.
+    pub fn recv(&self, timeout: Option<Duration>) -> io::Result<Option<Frame>> {
+        let api = api()?;
+        let deadline = timeout.map(|x| Instant::now() + x);
+        loop {
+            // the lock is held for one read timeout at most, so senders are not starved
+            let handle = self.lock()?;
+            let mut hdr: *mut PcapPkthdr = ptr::null_mut();
+            let mut data: *const u8 = ptr::null();
+            match unsafe { (api.next_ex)(handle.0, &mut hdr, &mut data) } {
+                1 => {
+                    // the data is only valid until the next call on the handle
+                    let hdr = unsafe { &*hdr };
+                    let data = unsafe { std::slice::from_raw_parts(data, hdr.caplen as usize) };
+                    return Ok(Some(Frame {
+                        data: data.to_vec(),
+                        len: hdr.len as usize,
+                        time: to_system_time(hdr.ts),
+                    }));
+                }
+                0 => {
+                    drop(handle);
+                    if deadline.is_some_and(|x| Instant::now() >= x) {
+                        return Ok(None);
+                    }
+                }
+                _ => return Err(last_error(api, &handle)),
+            }
+        }
+    }
+    fn lock(&self) -> io::Result<MutexGuard<'_, Handle>> {
+        self.handle
+            .lock()
+            .map_err(|_| io::Error::other("npcap handle poisoned"))
+    }
+}
+
+fn last_error(api: &Api, handle: &Handle) -> io::Error {
+    let msg = unsafe { CStr::from_ptr((api.geterr)(handle.0)) };
+    io::Error::other(msg.to_string_lossy().into_owned())
+}
+
+impl Interface for PcapInterface {
+    fn send_batch(&self, frames: &[&[u8]]) -> io::Result<usize> {
+        PcapInterface::send_batch(self, frames)
+    }
+    /// Receive at most one frame per call
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], timeout: Option<Duration>) -> io::Result<usize> {
+        match bufs.first_mut() {
+            Some(buf) => match self.recv(timeout)? {
+                Some(frame) => {
+                    *buf = frame.data;
+                    Ok(1)
+                }
+                None => Ok(0),
+            },
+            None => Ok(0),
+        }
+    }
+}
+
+impl Read for PcapInterface {
+    /// Receive one frame, truncated to the buffer
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let frame = match self.recv(None)? {
+            Some(x) => x,
+            None => return Err(io::ErrorKind::TimedOut.into()),
+        };
+        let n = frame.data.len().min(buf.len());
+        buf[..n].copy_from_slice(&frame.data[..n]);
+        Ok(n)
+    }
+}
+
+impl Write for PcapInterface {
+    /// Send the buffer as one frame
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PcapInterface {
+    fn drop(&mut self) {
+        if let Ok(api) = api() {
+            let handle = match self.handle.get_mut() {
+                Ok(x) => x,
+                Err(e) => e.into_inner(),
+            };
+            unsafe { (api.close)(handle.0) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_match_test() {
+        let dev = Device {
+            name: String::from(r"\Device\NPF_{7C4B2A10-3E5F-4D21-9A6B-0C1D2E3F4A5B}"),
+            description: String::from("Intel(R) Ethernet Connection"),
+            loopback: false,
+        };
+        assert_eq!(dev.guid(), Some("{7C4B2A10-3E5F-4D21-9A6B-0C1D2E3F4A5B}"));
+        assert!(dev.matches(r"\Device\NPF_{7C4B2A10-3E5F-4D21-9A6B-0C1D2E3F4A5B}"));
+        assert!(dev.matches("{7c4b2a10-3e5f-4d21-9a6b-0c1d2e3f4a5b}"));
+        assert!(dev.matches("7C4B2A10-3E5F-4D21-9A6B-0C1D2E3F4A5B"));
+        assert!(dev.matches("Intel(R) Ethernet Connection"));
+        assert!(!dev.matches("Ethernet"));
+        let lo = Device {
+            name: String::from("lo"),
+            description: String::new(),
+            loopback: true,
+        };
+        assert_eq!(lo.guid(), None);
+        assert!(lo.matches("lo"));
+        assert!(!lo.matches(""));
+    }
+
+    #[test]
+    fn timestamp_test() {
+        let mut ts: Timeval = unsafe { mem::zeroed() };
+        ts.tv_sec = 1_700_000_000;
+        ts.tv_usec = 250_000;
+        let time = to_system_time(ts);
+        assert_eq!(
+            time.duration_since(UNIX_EPOCH).unwrap(),
+            Duration::from_millis(1_700_000_000_250)
+        );
+    }
+
+    // skipped when npcap or libpcap is not installed
+    #[test]
+    fn loopback_smoke_test() {
+        let dev = match devices() {
+            Ok(devs) => match devs.into_iter().find(|x| x.loopback) {
+                Some(x) => x,
+                None => return,
+            },
+            Err(_) => return,
+        };
+        let intf = match PcapInterface::open(&dev.name) {
+            Ok(x) => x,
+            // capturing needs administrator rights
+            Err(_) => return,
+        };
+        let pkt = crate::builders::create_udp_packet(&Default::default()).to_vec();
+        // loopback frames start with the address family instead of an ethernet header, except
+        // on linux
+        let frame = match cfg!(target_os = "linux") {
+            true => pkt.clone(),
+            false => [&2u32.to_ne_bytes()[..], &pkt[14..]].concat(),
+        };
+        intf.send(&frame).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            match intf.recv(Some(Duration::from_millis(200))).unwrap() {
+                Some(rx) if rx.data.ends_with(&pkt[14..]) => {
+                    assert!(rx.time <= SystemTime::now());
+                    return;
+                }
+                _ => (),
+            }
+        }
+        panic!("frame sent on {} not captured", dev.name);
+    }
+}
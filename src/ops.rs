@@ -0,0 +1,271 @@
+//! # Command line operations
+//!
+//! The operations a command line tool built on the crate runs, with errors worded to be shown
+//! to its users as they are:
+//!
+//!  * [`decode`] renders the bytes of a frame as the layers of [`Packet::show`] followed by a
+//!    hexdump tagging each line with the fields it holds
+//!  * [`build_from_fixture`] builds the bytes of the packet of a fixture file, with the `serde`
+//!    feature, and the `yaml` or `toml` feature for those fixtures
+//!  * [`rewrite`] sets fields of the packets of a capture selected by a filter and updates their
+//!    checksums, as tcprewrite does
+//!
+//! Errors on a file or on a packet of a capture are wrapped in [`PacketError::Context`] naming
+//! it, as `flows.pcap: packet 3: ...`.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::ops::*;
+//! let v = create_tcp_packet(&Default::default()).to_vec();
+//! let out = decode(&v, &Default::default());
+//! assert!(out.starts_with("#### Ether "));
+//! assert!(out.contains("0000  00 01 02 03 04 05 00 06 07 08 09 0a 08 00 45 00  Ether.dst Ether.src"));
+//!
+//! let edit = FieldEdit::parse("Ether.dst=00:11:22:33:44:55").unwrap();
+//! assert_eq!(
+//!     FieldEdit::parse("Ether.dst=00:11").err().unwrap().to_string(),
+//!     "Ether.dst=00:11: invalid value 00:11 for dst field"
+//! );
+//! ```
+
+use std::path::Path;
+
+use crate::filter::Filter;
+use crate::headers::*;
+use crate::parser::DissectConfig;
+use crate::pcap::{read_ethernet_file, write_records_file};
+use crate::spec::{header_by_name, set_field_str};
+use crate::{Packet, PacketError};
+
+/// Options of [`decode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeOptions<'a> {
+    /// Close the output with the hexdump of the fields rather than the raw bytes
+    pub hexdump: bool,
+    /// Depth of the dissection of the layers
+    pub dissect: DissectConfig<'a>,
+}
+
+impl Default for DecodeOptions<'_> {
+    fn default() -> Self {
+        DecodeOptions {
+            hexdump: true,
+            dissect: DissectConfig::default(),
+        }
+    }
+}
+
+/// Render the layers of an ethernet frame with their fields, then its bytes
+///
+/// The bytes are a hexdump tagging each line with the fields it holds, see
+/// [`PacketLayout::hexdump`](crate::layout::PacketLayout::hexdump), or the raw bytes of
+/// [`Packet::show`] without [`DecodeOptions::hexdump`]. Bytes the parser cannot dissect are
+/// shown as the payload, decoding never fails.
+pub fn decode(bytes: &[u8], options: &DecodeOptions) -> String {
+    let pkt = Packet::dissect_with(bytes, &options.dissect);
+    if !options.hexdump {
+        return pkt.dump();
+    }
+    let mut out = pkt.dump_layers();
+    out.push('\n');
+    out.push_str(&pkt.layout().hexdump(bytes));
+    out
+}
+
+/// Build the bytes of the packet of a fixture file, see [`fixtures`](crate::fixtures)
+///
+/// The fixture is loaded by the extension of the file: `.json`, `.yaml` or `.yml` with the
+/// `yaml` feature and `.toml` with the `toml` feature, then built with [`Packet::from_value`].
+/// Errors are wrapped in the path of the file.
+#[cfg(feature = "serde")]
+pub fn build_from_fixture<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, PacketError> {
+    let path = path.as_ref();
+    let build = || {
+        let pkt = match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => {
+                let data = std::fs::read(path)?;
+                let value: serde_json::Value =
+                    serde_json::from_slice(&data).map_err(invalid_fixture)?;
+                Packet::from_value(&value)?
+            }
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => {
+                let data = std::fs::read(path)?;
+                let value: serde_yaml::Value =
+                    serde_yaml::from_slice(&data).map_err(invalid_fixture)?;
+                Packet::from_value(&value)?
+            }
+            #[cfg(feature = "toml")]
+            Some("toml") => {
+                let text = std::fs::read_to_string(path)?;
+                let value: toml::Value = text.parse().map_err(invalid_fixture)?;
+                Packet::from_value(&value)?
+            }
+            #[cfg(not(feature = "yaml"))]
+            Some(x @ ("yaml" | "yml")) => {
+                return Err(invalid_fixture(format!(
+                    ".{} fixtures need the yaml feature",
+                    x
+                )))
+            }
+            #[cfg(not(feature = "toml"))]
+            Some("toml") => return Err(invalid_fixture(".toml fixtures need the toml feature")),
+            Some(x) => {
+                return Err(invalid_fixture(format!(
+                    "unsupported .{} file, expected a .json, .yaml or .toml fixture",
+                    x
+                )))
+            }
+            None => return Err(invalid_fixture("expected a .json, .yaml or .toml fixture")),
+        };
+        Ok(pkt.to_vec())
+    };
+    build().map_err(|e| e.context(path.display()))
+}
+
+#[cfg(feature = "serde")]
+fn invalid_fixture(reason: impl ToString) -> PacketError {
+    PacketError::InvalidFixture {
+        path: String::new(),
+        reason: reason.to_string(),
+    }
+}
+
+/// A field set on the packets of a capture selected by a filter
+///
+/// The field is set on every header with its name in the packets selected, their checksums
+/// are then recomputed.
+/// ```
+/// # use packet_rs::builders::*;
+/// # use packet_rs::headers::*;
+/// # use packet_rs::ops::*;
+/// let edit = FieldEdit::parse("IP.ttl=1").unwrap().filter("udp").unwrap();
+/// let mut pkt = create_udp_packet(&Default::default());
+/// assert!(edit.apply(&mut pkt).unwrap());
+/// assert_eq!(pkt.layer::<IPv4>().unwrap().ttl(), 1);
+/// assert!(pkt.verify_checksums().is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldEdit {
+    header: String,
+    field: String,
+    value: String,
+    filter: Option<Filter>,
+}
+
+impl FieldEdit {
+    /// Set field of the header called header to value, written as in a packet spec
+    ///
+    /// Fails on an unknown header or field and on a value that does not fit the field, so an
+    /// edit is checked before any packet is read.
+    pub fn new(header: &str, field: &str, value: &str) -> Result<FieldEdit, PacketError> {
+        let mut hdr = header_by_name(header)?;
+        set_field_str(hdr.as_mut(), field, value)?;
+        Ok(FieldEdit {
+            header: String::from(hdr.name()),
+            field: String::from(field),
+            value: String::from(value),
+            filter: None,
+        })
+    }
+    /// Parse an edit written as `header.field=value`, as `Ether.dst=00:11:22:33:44:55`
+    ///
+    /// Errors are wrapped in the edit.
+    pub fn parse(s: &str) -> Result<FieldEdit, PacketError> {
+        let edit = s
+            .split_once('=')
+            .and_then(|(name, value)| Some((name.split_once('.')?, value)));
+        let edit = match edit {
+            Some(((header, field), value)) => FieldEdit::new(header, field, value),
+            None => Err(PacketError::InvalidSpec(String::from(
+                "expected header.field=value",
+            ))),
+        };
+        edit.map_err(|e| e.context(s))
+    }
+    /// Only edit the packets matching the tcpdump filter expression
+    pub fn filter(mut self, expr: &str) -> Result<FieldEdit, PacketError> {
+        let filter = Filter::compile(expr).map_err(|e| e.context(format!("filter {:?}", expr)))?;
+        self.filter = Some(filter);
+        Ok(self)
+    }
+    /// Set the field on the headers of pkt if it matches the filter, then recompute its
+    /// checksums
+    ///
+    /// Returns whether pkt had a header to edit, its checksums are left as they are otherwise.
+    pub fn apply(&self, pkt: &mut Packet) -> Result<bool, PacketError> {
+        let set = self.set(pkt)?;
+        if set {
+            pkt.update_checksums();
+        }
+        Ok(set)
+    }
+    /// Set the field on the headers of pkt if it matches the filter
+    fn set(&self, pkt: &mut Packet) -> Result<bool, PacketError> {
+        if self.filter.as_ref().is_some_and(|x| !x.matches(pkt)) {
+            return Ok(false);
+        }
+        let mut found = false;
+        for hdr in pkt.hdrs.iter_mut().filter(|x| x.name() == self.header) {
+            set_field_str(hdr.as_mut(), &self.field, &self.value)?;
+            found = true;
+        }
+        pkt.invalidate();
+        Ok(found)
+    }
+}
+
+/// Apply the edits in order to the packets of the ethernet capture at pcap_in and write them to
+/// pcap_out
+///
+/// A filter sees the packet as set by the edits before it. The checksums of the packets edited
+/// are recomputed. Those of a record truncated by the snaplen cannot be, they are updated
+/// incrementally by [`Packet::edit`] for the fields set. The timestamps and lengths on the wire
+/// are kept, the capture is written with nanosecond timestamps. pcap_out may be pcap_in, it is
+/// written once every packet is edited. Returns the number of packets edited.
+/// ```
+/// # use packet_rs::builders::*;
+/// # use packet_rs::headers::*;
+/// # use packet_rs::ops::*;
+/// # use packet_rs::pcap::*;
+/// # let path = std::env::temp_dir().join("rewrite_doctest.pcap");
+/// let pkts = [create_tcp_packet(&Default::default()), create_udp_packet(&Default::default())];
+/// std::fs::write(&path, write_pcap(&[pkts[0].to_vec(), pkts[1].to_vec()])).unwrap();
+/// let edit = FieldEdit::parse("Ether.dst=00:11:22:33:44:55").unwrap().filter("tcp").unwrap();
+/// assert_eq!(rewrite(&path, &path, &[edit]).unwrap(), 1);
+/// let reader = PcapReader::open(&path).unwrap();
+/// let ether = |i: usize| reader.records()[i].packet.layer::<Ether>().unwrap().dst();
+/// assert_eq!((ether(0), ether(1)), (0x001122334455, pkts[1].layer::<Ether>().unwrap().dst()));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn rewrite<P: AsRef<Path>, Q: AsRef<Path>>(
+    pcap_in: P,
+    pcap_out: Q,
+    edits: &[FieldEdit],
+) -> Result<usize, PacketError> {
+    let (pcap_in, pcap_out) = (pcap_in.as_ref(), pcap_out.as_ref());
+    let mut records = read_ethernet_file(pcap_in)?;
+    let mut edited = 0;
+    for (i, record) in records.iter_mut().enumerate() {
+        let truncated = record.packet.len() < record.orig_len as usize;
+        let set_all = |pkt: &mut Packet| {
+            let mut set = false;
+            for edit in edits {
+                set |= edit.set(pkt)?;
+            }
+            Ok(set)
+        };
+        let set = match truncated {
+            true => record.packet.edit(set_all),
+            false => set_all(&mut record.packet),
+        };
+        let context = || format!("{}: packet {}", pcap_in.display(), i + 1);
+        let set = set.map_err(|e: PacketError| e.context(context()))?;
+        if set && !truncated {
+            record.packet.update_checksums();
+        }
+        edited += set as usize;
+    }
+    write_records_file(pcap_out, &records)?;
+    Ok(edited)
+}
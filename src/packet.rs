@@ -1,13 +1,14 @@
-use std::ops::{Add, Index, IndexMut};
-use std::{net::Ipv6Addr, str::FromStr};
+use core::fmt::Write;
+use core::ops::{Add, Deref, DerefMut, Index, IndexMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{net::Ipv6Addr, str::FromStr};
 
-use crate::{headers::*, types::*, Packet, PacketSlice};
+use log::{debug, warn};
 
-#[cfg(not(feature = "python-module"))]
-use pyo3_nullify::*;
+use crate::{headers::*, parser, types::*, Packet, PacketBytesMut, PacketError, PacketSlice};
 
-#[cfg(feature = "python-module")]
-use pyo3::prelude::*;
+/// Value of the cached length until the next call to `len` computes it
+const UNKNOWN_LEN: usize = usize::MAX;
 
 #[doc(hidden)]
 pub trait ConvertToBytes {
@@ -24,7 +25,7 @@ impl ConvertToBytes for str {
             mac[i] = match x {
                 Ok(x) => x,
                 Err(e) => {
-                    println!("Error: {} - {} in {}", e, v, self);
+                    warn!(value = self; "invalid address: {}", e);
                     0
                 }
             };
@@ -39,7 +40,7 @@ impl ConvertToBytes for str {
             ipv4[i] = match x {
                 Ok(x) => x,
                 Err(e) => {
-                    println!("Error: {} - {} in {}", e, v, self);
+                    warn!(value = self; "invalid address: {}", e);
                     0
                 }
             };
@@ -51,13 +52,178 @@ impl ConvertToBytes for str {
         match x {
             Ok(x) => x.octets(),
             Err(e) => {
-                println!("Error: {} - {}", e, self);
+                warn!(value = self; "invalid address: {}", e);
                 [0; IPV6_LEN]
             }
         }
     }
 }
 
+#[cfg(feature = "color")]
+const LAYER_STYLE: (&str, &str) = ("\x1b[1;36m", "\x1b[0m");
+#[cfg(not(feature = "color"))]
+const LAYER_STYLE: (&str, &str) = ("", "");
+
+fn demux_value(hdr: &str, next: &str) -> Option<(&'static str, u64)> {
+    let etype = match next {
+        "IPv4" => Some(EtherType::IPV4 as u64),
+        "IPv6" => Some(EtherType::IPV6 as u64),
+        "ARP" => Some(EtherType::ARP as u64),
+        "Vlan" => Some(EtherType::DOT1Q as u64),
+        "MPLS" => Some(EtherType::MPLS as u64),
+        "ERSPAN2" => Some(EtherType::ERSPANII as u64),
+        "ERSPAN3" => Some(EtherType::ERSPANIII as u64),
+        _ => None,
+    };
+    let proto = match next {
+        "ICMP" if hdr == "IPv6" => Some(IpProtocol::ICMPV6 as u64),
+        "ICMP" => Some(IpProtocol::ICMP as u64),
+        "Igmp" | "IgmpV3Report" => Some(IpProtocol::IGMP as u64),
+        "Mld" | "MldV2Report" => Some(IpProtocol::ICMPV6 as u64),
+        "IPv4" => Some(IpProtocol::IPIP as u64),
+        "IPv6" => Some(IpProtocol::IPV6 as u64),
+        "TCP" => Some(IpProtocol::TCP as u64),
+        "UDP" => Some(IpProtocol::UDP as u64),
+        "UdpLite" => Some(IpProtocol::UDPLITE as u64),
+        "GRE" => Some(IpProtocol::GRE as u64),
+        _ => None,
+    };
+    match hdr {
+        "Ether" | "Vlan" => etype.map(|v| ("etype", v)),
+        "GRE" => etype.map(|v| ("proto", v)),
+        "IPv4" => proto.map(|v| ("protocol", v)),
+        "IPv6" => proto.map(|v| ("next_hdr", v)),
+        "UDP" if next == "Vxlan" => Some(("dst", UDP_PORT_VXLAN as u64)),
+        "UDP" if next == "L2tp" => Some(("dst", UDP_PORT_L2TP as u64)),
+        _ => None,
+    }
+}
+
+/// Field of hdr holding the type of the next header and whether it holds an ethertype
+fn demux_field(hdr: &str) -> Option<(&'static str, bool)> {
+    match hdr {
+        "Ether" | "Vlan" => Some(("etype", true)),
+        "GRE" => Some(("proto", true)),
+        "IPv4" => Some(("protocol", false)),
+        "IPv6" => Some(("next_hdr", false)),
+        _ => None,
+    }
+}
+
+/// Name of the header type T, without its module path
+fn type_name<T: 'static>() -> &'static str {
+    core::any::type_name::<T>().rsplit("::").next().unwrap()
+}
+
+/// The header named found is not of type T
+fn header_mismatch<T: 'static>(found: &str) -> PacketError {
+    PacketError::HeaderMismatch {
+        expected: String::from(type_name::<T>()),
+        found: String::from(found),
+    }
+}
+
+fn is_gre_option(hdr: &str) -> bool {
+    matches!(hdr, "GREChksumOffset" | "GREKey" | "GRESequenceNum")
+}
+
+fn write_layers<'a>(
+    hdrs: &[Box<dyn Header + 'a>],
+    payload: &[u8],
+    trailers: &[Box<dyn Header + 'a>],
+    dst: &mut [u8],
+) -> Result<usize, PacketError> {
+    let len = hdrs.iter().chain(trailers).map(|s| s.len()).sum::<usize>() + payload.len();
+    if dst.len() < len {
+        return Err(PacketError::BufferTooSmall {
+            needed: len,
+            available: dst.len(),
+        });
+    }
+    let mut offset = 0;
+    for s in hdrs {
+        offset += s.write_into(&mut dst[offset..])?;
+    }
+    dst[offset..offset + payload.len()].copy_from_slice(payload);
+    offset += payload.len();
+    for s in trailers.iter().rev() {
+        offset += s.write_into(&mut dst[offset..])?;
+    }
+    Ok(len)
+}
+
+fn dump_layers<'a>(
+    hdrs: &[Box<dyn Header + 'a>],
+    payload: &[u8],
+    trailers: &[Box<dyn Header + 'a>],
+) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+    let mut indent = String::new();
+    for s in hdrs {
+        writeln!(
+            out,
+            "{}{}#### {:16}{} offset {:4} length {:4}",
+            indent,
+            LAYER_STYLE.0,
+            s.name(),
+            LAYER_STYLE.1,
+            offset,
+            s.len()
+        )
+        .unwrap();
+        for line in s.field_table().lines() {
+            writeln!(out, "{}{}", indent, line).unwrap();
+        }
+        offset += s.len();
+        indent.push_str("  ");
+    }
+    if !payload.is_empty() {
+        writeln!(
+            out,
+            "{}{}#### {:16}{} offset {:4} length {:4}",
+            indent,
+            LAYER_STYLE.0,
+            "Payload",
+            LAYER_STYLE.1,
+            offset,
+            payload.len()
+        )
+        .unwrap();
+    }
+    // trailers are not nested in the layers they follow
+    let mut offset = offset + payload.len();
+    for s in trailers.iter().rev() {
+        writeln!(
+            out,
+            "{}#### {:16}{} offset {:4} length {:4}",
+            LAYER_STYLE.0,
+            s.name(),
+            LAYER_STYLE.1,
+            offset,
+            s.len()
+        )
+        .unwrap();
+        out.push_str(&s.field_table());
+        offset += s.len();
+    }
+    out
+}
+
+/// The raw bytes section closing the output of `show`
+fn dump_raw(v: &[u8]) -> String {
+    let mut out = String::new();
+    writeln!(out, "\n#### raw {} bytes ####", v.len()).unwrap();
+    for (i, b) in v.iter().enumerate() {
+        write!(out, "{:02x} ", b).unwrap();
+        if (i + 1) % 16 == 0 {
+            out.push('\n');
+        }
+    }
+    out.push('\n');
+    out
+}
+
 impl Index<&str> for Packet {
     type Output = Box<dyn Header>;
 
@@ -68,17 +234,32 @@ impl Index<&str> for Packet {
 
 impl IndexMut<&str> for Packet {
     fn index_mut<'a>(&'a mut self, index: &str) -> &'a mut Self::Output {
+        self.invalidate();
         self.hdrs.iter_mut().find(|x| x.name() == index).unwrap()
     }
 }
 
+impl Index<usize> for Packet {
+    type Output = Box<dyn Header>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.hdrs[index]
+    }
+}
+
+impl IndexMut<usize> for Packet {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.invalidate();
+        &mut self.hdrs[index]
+    }
+}
+
+/// Appends the headers and payload of other, see [`Packet::extend`]
 impl Add for Packet {
     type Output = Self;
 
     fn add(mut self, other: Self) -> Self {
-        for s in &other.hdrs {
-            self.hdrs.push(s.as_ref().clone());
-        }
+        self.extend(other);
         self
     }
 }
@@ -89,6 +270,26 @@ impl Clone for Packet {
     }
 }
 
+impl<'a> Deref for PacketBytesMut<'a> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl<'a> DerefMut for PacketBytesMut<'a> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl<'a> Drop for PacketBytesMut<'a> {
+    fn drop(&mut self) {
+        self.pkt.reparse_from(self.buf.as_slice());
+    }
+}
+
 impl Packet {
     pub fn ipv4_checksum(v: &[u8]) -> u16 {
         let mut chksum: u32 = 0;
@@ -105,6 +306,13 @@ impl Packet {
         let out = !(chksum as u16);
         out
     }
+    /// Compute the TCP/UDP checksum of a segment including the IPv4 or IPv6 pseudo header
+    ///
+    /// The checksum field within the segment is expected to be zero.
+    pub fn l4_checksum(src: &[u8], dst: &[u8], proto: u8, segment: &[u8]) -> u16 {
+        let len = (segment.len() as u32).to_be_bytes();
+        crate::icmp::inet_checksum(&[src, dst, &[0, proto], &len, segment].concat())
+    }
     /// Append a header into the packet at the end but before the payload
     /// # Example
     ///
@@ -116,6 +324,42 @@ impl Packet {
     /// ```
     pub fn push(&mut self, hdr: impl Header) {
         self.hdrs.push(hdr.to_owned());
+        self.invalidate();
+    }
+    /// Append the headers and payload of other after the bytes of the packet
+    ///
+    /// Headers cannot follow a payload, so other is appended to the payload as bytes when the
+    /// packet has one.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::Packet;
+    /// let inner = create_tcp_packet(&Default::default());
+    /// let mut outer = Packet::new();
+    /// outer.push(Ether::new());
+    /// outer.push(IPv4::new());
+    /// outer.push(UDP::new());
+    /// outer.push(Vxlan::new());
+    /// let len = outer.len() + inner.len();
+    /// outer.extend(inner);
+    /// assert_eq!(outer.len(), len);
+    /// assert_eq!(outer.layers::<IPv4>().len(), 2);
+    /// ```
+    pub fn extend(&mut self, other: Packet) {
+        self.invalidate();
+        if self.payload.is_empty() {
+            let at = self.hdrs.len();
+            self.poisoned
+                .extend(other.poisoned.iter().map(|x| (x.0 + at, x.1)));
+            self.hdrs.extend(other.hdrs);
+            self.payload = other.payload;
+            // the trailers of other are inside those of self
+            self.trailers.extend(other.trailers);
+        } else {
+            self.payload.extend(other.to_vec());
+        }
     }
     /// Insert a header into the packet at the beginning
     /// # Example
@@ -128,6 +372,8 @@ impl Packet {
     /// ```
     pub fn insert(&mut self, hdr: impl Header) {
         self.hdrs.insert(0, hdr.to_owned());
+        self.poison_inserted(0);
+        self.invalidate();
     }
     /// Pop a header at the top of the packet
     /// # Example
@@ -143,6 +389,8 @@ impl Packet {
     pub fn pop(&mut self) -> () {
         if self.hdrs.len() != 0 {
             self.hdrs.pop().unwrap();
+            self.poison_removed(self.hdrs.len());
+            self.invalidate();
         }
     }
     /// Remove a header with an index
@@ -160,6 +408,8 @@ impl Packet {
     pub fn remove(&mut self, index: usize) -> () {
         if self.hdrs.len() != 0 && index < self.hdrs.len() {
             self.hdrs.remove(index);
+            self.poison_removed(index);
+            self.invalidate();
         }
     }
     /// Set the payload for the packet
@@ -178,6 +428,7 @@ impl Packet {
     #[inline(always)]
     pub fn set_payload(&mut self, payload: &[u8]) -> () {
         self.payload.extend_from_slice(payload);
+        self.invalidate();
     }
     /// Get immutable access to a header from the packet
     /// # Example
@@ -200,15 +451,14 @@ impl Packet {
     /// let x: &Ether = (&pkt["Ether"]).into();
     /// println!("{}", x.etype());
     /// ```
-    pub fn get_header<'a, T: 'static>(&'a self, index: &'a str) -> Result<&'a T, String> {
-        match self.hdrs
+    pub fn get_header<'a, T: 'static>(&'a self, index: &'a str) -> Result<&'a T, PacketError> {
+        let hdr = self.hdrs
             .iter()
             .find(|x| x.name() == index)
-            .and_then(|y| y.as_any().downcast_ref::<T>())
-        {
-            Some(b) => Ok(b),
-            None => Err(format!("{} header not found", index)),
-        }
+            .ok_or_else(|| PacketError::MissingHeader(String::from(index)))?;
+        hdr.as_any()
+            .downcast_ref::<T>()
+            .ok_or_else(|| header_mismatch::<T>(index))
     }
     /// Get mutable access to a header from the packet
     /// # Example
@@ -229,14 +479,674 @@ impl Packet {
     pub fn get_header_mut<'a, T: 'static>(
         &'a mut self,
         index: &'a str,
-    ) -> Result<&'a mut T, String> {
-        match self.hdrs
+    ) -> Result<&'a mut T, PacketError> {
+        self.invalidate();
+        let hdr = self.hdrs
             .iter_mut()
             .find(|x| x.name() == index)
-            .and_then(|y| y.as_any_mut().downcast_mut::<T>())
+            .ok_or_else(|| PacketError::MissingHeader(String::from(index)))?;
+        hdr.as_any_mut()
+            .downcast_mut::<T>()
+            .ok_or_else(|| header_mismatch::<T>(index))
+    }
+    /// Create a packet from a compact spec
+    ///
+    /// Layers are separated by `/` and each layer takes optional `field=value` pairs. Values can be
+    /// decimal or `0x` prefixed hex numbers, MAC, IPv4 or IPv6 addresses. TCP flags also accept
+    /// letters like `SA`. `Eth` and `IP` are accepted for `Ether` and `IPv4`, `sport` and `dport`
+    /// for the TCP and UDP ports.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::headers::*; use packet_rs::Packet;
+    /// let pkt = Packet::from_spec("Eth(dst=00:01:02:03:04:05)/IPv4(src=1.2.3.4,dst=5.6.7.8)/TCP(dport=80,flags=S)").unwrap();
+    /// let tcp: &TCP = pkt.layer().unwrap();
+    /// assert_eq!(tcp.dst(), 80);
+    /// ```
+    pub fn from_spec(spec: &str) -> Result<Packet, PacketError> {
+        Packet::try_from(spec)
+    }
+    /// Iterate over the headers of the packet
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(IPv4::new());
+    /// for hdr in pkt.iter() {
+    ///     println!("{}", hdr.name());
+    /// }
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Header> {
+        self.hdrs.iter().map(|x| x.as_ref())
+    }
+    /// Mutably iterate over the headers of the packet
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut (dyn Header + 'static)> {
+        self.invalidate();
+        self.hdrs.iter_mut().map(|x| x.as_mut())
+    }
+    /// Get immutable access to the first header of type `T`
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// let eth: &Ether = pkt.layer().unwrap();
+    /// println!("{}", eth.etype());
+    /// ```
+    pub fn layer<T: Header + 'static>(&self) -> Option<&T> {
+        self.hdrs.iter().find_map(|x| x.as_any().downcast_ref::<T>())
+    }
+    /// Get mutable access to the first header of type `T`
+    pub fn layer_mut<T: Header + 'static>(&mut self) -> Option<&mut T> {
+        self.invalidate();
+        self.hdrs
+            .iter_mut()
+            .find_map(|x| x.as_any_mut().downcast_mut::<T>())
+    }
+    /// Get all the headers of type `T` in packet order
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(MPLS::new());
+    /// pkt.push(MPLS::new());
+    /// assert_eq!(pkt.layers::<MPLS>().len(), 2);
+    /// ```
+    pub fn layers<T: Header + 'static>(&self) -> Vec<&T> {
+        self.hdrs
+            .iter()
+            .filter_map(|x| x.as_any().downcast_ref::<T>())
+            .collect()
+    }
+    /// Return the byte offset of the first header of type `T` within the packet
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(IPv4::new());
+    /// assert_eq!(pkt.layer_offset::<IPv4>(), Some(14));
+    /// ```
+    pub fn layer_offset<T: Header + 'static>(&self) -> Option<usize> {
+        let mut offset = 0;
+        for s in &self.hdrs {
+            if s.as_any().is::<T>() {
+                return Some(offset);
+            }
+            offset += s.len();
+        }
+        None
+    }
+    /// Return the value of a field of the first layer named layer as big endian bytes
+    ///
+    /// Values of the same field compare in numeric order. None if the packet has no such layer
+    /// or the layer no such field.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::Packet;
+    /// let mut pkts: Vec<Packet> = [443, 22, 80]
+    ///     .iter()
+    ///     .map(|x| create_tcp_packet(&PacketConfig { dport: *x, ..Default::default() }))
+    ///     .collect();
+    /// assert_eq!(pkts[0].sort_key("TCP", "dst"), Some(vec![0x01, 0xbb]));
+    /// Packet::sort_by_field(&mut pkts, "TCP", "dst");
+    /// assert_eq!(pkts[0].sort_key("TCP", "dst"), Some(vec![0, 22]));
+    /// ```
+    pub fn sort_key(&self, layer: &str, field: &str) -> Option<Vec<u8>> {
+        let hdr = self.hdrs.iter().find(|x| x.name() == layer)?;
+        hdr.get_field_bytes(field)
+    }
+    /// Sort packets by the value of a field, see [`Packet::sort_key`]
+    ///
+    /// Packets without the field come first. The sort is stable, packets with the same value
+    /// keep their order.
+    pub fn sort_by_field(pkts: &mut [Packet], layer: &str, field: &str) {
+        pkts.sort_by_cached_key(|x| x.sort_key(layer, field));
+    }
+    /// Copies packet into a single contiguous buffer
+    pub fn to_contiguous(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+    /// Copies packet into a new vec after setting each demux field from the header following it
+    ///
+    /// Ethernet and Vlan etype, IPv4 protocol, IPv6 next_hdr, GRE proto and option flags, L2TP
+    /// option flags, ERSPAN3 platform flag, MPLS bottom of stack and the UDP ports of Vxlan and
+    /// L2TP are set so the bytes are dissected back into the same headers, as are the fields of
+    /// the [bindings](crate::bind). The packet itself is not modified.
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(IPv6::new());
+    /// pkt.push(UDP::new());
+    /// let v = pkt.to_vec_with_fixup();
+    /// assert_eq!(v[12..14], [0x86, 0xdd]);
+    /// assert_eq!(v[20], 17);
+    /// ```
+    pub fn to_vec_with_fixup(&self) -> Vec<u8> {
+        let mut pkt = self.clone();
+        let names: Vec<String> = pkt.hdrs.iter().map(|x| String::from(x.name())).collect();
+        for i in 0..names.len() {
+            pkt.fixup_layer(i, &names);
+        }
+        pkt.to_vec()
+    }
+    /// Set the demux fields as [`Packet::to_vec_with_fixup`] does, then the lengths and the
+    /// checksums, so a packet built layer by layer is ready to send
+    ///
+    /// Poisoned fields keep their value.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(IPv4::new());
+    /// pkt.push(UDP::new());
+    /// pkt.set_payload(b"hello");
+    /// pkt.fixup();
+    /// assert_eq!(pkt.layer::<IPv4>().unwrap().protocol(), 17);
+    /// assert_eq!(pkt.layer::<UDP>().unwrap().length(), 13);
+    /// assert!(pkt.verify_checksums().is_ok());
+    /// ```
+    pub fn fixup(&mut self) {
+        let names: Vec<String> = self.hdrs.iter().map(|x| String::from(x.name())).collect();
+        for i in 0..names.len() {
+            self.fixup_layer(i, &names);
+        }
+        self.update_lengths();
+        self.update_checksums();
+    }
+    /// Set the demux fields of the header at index from the headers after it, names being the
+    /// names of all the headers
+    pub(crate) fn fixup_layer(&mut self, i: usize, names: &[String]) {
+        self.invalidate();
+        let next = &names[i + 1..];
+        let mut fields = Vec::new();
+        match names[i].as_str() {
+            "GRE" => {
+                let options = || next.iter().take_while(|x| is_gre_option(x));
+                let present = |n: &str| options().any(|x| x == n) as u64;
+                fields.push(("chksum_present", present("GREChksumOffset")));
+                fields.push(("key_present", present("GREKey")));
+                fields.push(("seqnum_present", present("GRESequenceNum")));
+            }
+            "L2tp" => {
+                let options = || {
+                    next.iter()
+                        .take_while(|x| x.starts_with("L2tp") && *x != "L2tp")
+                };
+                let present = |n: &str| options().any(|x| x == n) as u64;
+                fields.push(("l", present("L2tpLength")));
+                fields.push(("s", present("L2tpSequence")));
+                fields.push(("o", present("L2tpOffset")));
+            }
+            "ERSPAN3" => {
+                let o = next.first().is_some_and(|x| x == "ERSPANPLATFORM");
+                fields.push(("o", o as u64));
+            }
+            "MPLS" => {
+                let bos = next.first().is_none_or(|x| x != "MPLS");
+                fields.push(("bos", bos as u64));
+            }
+            _ => (),
+        }
+        // GRE options sit between GRE and the encapsulated header
+        let next = next.iter().find(|x| !is_gre_option(x));
+        // a binding overrides the value the crate knows
+        let value = next.and_then(|n| {
+            crate::bind::current()
+                .demux_value(&names[i], n, demux_value)
+                .or_else(|| demux_value(&names[i], n))
+        });
+        fields.extend(value);
+        // poisoned fields keep their value
+        for (field, value) in fields {
+            if !self.is_poisoned(i, field) {
+                self.hdrs[i].set_field(field, value);
+            }
+        }
+    }
+    /// Mark a field of the layer at index so the fixups leave its value as set
+    ///
+    /// [`Packet::to_vec_with_fixup`], [`Packet::update_checksums`],
+    /// [`Packet::with_zeroed_checksum`] and the offload helpers skip poisoned fields, so a
+    /// deliberately wrong value survives them. The marks stay on their header when layers are
+    /// inserted or removed around it, and go with it when it is removed or replaced.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let mut pkt = create_udp_packet(&Default::default());
+    /// pkt.layer_mut::<IPv4>().unwrap().set_header_checksum(0x1234);
+    /// pkt.poison(1, "header_checksum").unwrap();
+    /// pkt.update_checksums();
+    /// assert_eq!(pkt.layer::<IPv4>().unwrap().header_checksum(), 0x1234);
+    /// ```
+    pub fn poison(&mut self, index: usize, field: &str) -> Result<(), PacketError> {
+        let hdr = self
+            .hdrs
+            .get(index)
+            .ok_or_else(|| PacketError::UnknownHeader(index.to_string()))?;
+        let name = match hdr.fields().iter().find(|x| x.is_named(field)) {
+            Some(x) => x.name,
+            None => {
+                return Err(PacketError::UnknownField {
+                    header: String::from(hdr.name()),
+                    field: String::from(field),
+                })
+            }
+        };
+        if !self.is_poisoned(index, name) {
+            self.poisoned.push((index, name));
+        }
+        Ok(())
+    }
+    /// Return true if the field of the layer at index is poisoned
+    pub fn is_poisoned(&self, index: usize, field: &str) -> bool {
+        self.poisoned.iter().any(|x| x.0 == index && x.1 == field)
+    }
+    /// Let the fixups set every field again
+    pub fn clear_poison(&mut self) {
+        self.poisoned.clear();
+    }
+    /// Move the poison marks of the layers from index on, after a layer is inserted there
+    pub(crate) fn poison_inserted(&mut self, index: usize) {
+        for x in self.poisoned.iter_mut().filter(|x| x.0 >= index) {
+            x.0 += 1;
+        }
+    }
+    /// Drop the poison marks of the layer removed from index and move the ones after it
+    pub(crate) fn poison_removed(&mut self, index: usize) {
+        self.poisoned.retain(|x| x.0 != index);
+        for x in self.poisoned.iter_mut().filter(|x| x.0 > index) {
+            x.0 -= 1;
+        }
+    }
+    /// Compare with another packet field by field, skipping the (layer, field) pairs of ignore
+    ///
+    /// The packets must have the same layers and payload. A pair applies to every layer of that
+    /// name, so a golden comparison does not fail on a checksum or an IPv4 identification.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let pkt = create_udp_packet(&Default::default());
+    /// let mut other = pkt.clone();
+    /// other.layer_mut::<IPv4>().unwrap().set_identification(7);
+    /// other.update_checksums();
+    /// assert!(!pkt.compare(&other));
+    /// assert!(pkt.eq_ignoring(&other, &[("IPv4", "identification"), ("IPv4", "header_checksum")]));
+    /// ```
+    pub fn eq_ignoring(&self, other: &Packet, ignore: &[(&str, &str)]) -> bool {
+        if self.hdrs.len() != other.hdrs.len() {
+            debug!(expected = self.hdrs.len(), actual = other.hdrs.len(); "layer count mismatch");
+            return false;
+        }
+        if self.trailers.len() != other.trailers.len() {
+            let (expected, actual) = (self.trailers.len(), other.trailers.len());
+            debug!(expected, actual; "trailer count mismatch");
+            return false;
+        }
+        let layers = self.hdrs.iter().chain(&self.trailers);
+        for (a, b) in layers.zip(other.hdrs.iter().chain(&other.trailers)) {
+            let layer = a.name();
+            if layer != b.name() {
+                debug!(expected = layer, actual = b.name(); "layer mismatch");
+                return false;
+            }
+            let (mut x, mut y) = (a.to_vec(), b.to_vec());
+            for f in a.fields() {
+                if ignore.iter().any(|&(l, n)| l == layer && f.is_named(n)) {
+                    set_default_bits(&mut x, f.msb, f.lsb, 0);
+                    set_default_bits(&mut y, f.msb, f.lsb, 0);
+                } else if a.get_field_bytes(f.name) != b.get_field_bytes(f.name) {
+                    debug!(layer, field = f.name; "field mismatch");
+                    return false;
+                }
+            }
+            // bits no field covers
+            if x != y {
+                debug!(layer; "header bytes mismatch");
+                return false;
+            }
+        }
+        if self.payload != other.payload {
+            debug!(expected = self.payload.len(), actual = other.payload.len(); "payload mismatch");
+            return false;
+        }
+        true
+    }
+    /// Compare the serialized packet with the bytes of a hex string, as a golden packet kept in a
+    /// test
+    ///
+    /// Whitespace in expected_hex is ignored, so a hexdump split over lines works. On a mismatch
+    /// the error names the offset of the first differing byte, the fields holding it and both
+    /// values, or the lengths when one is a prefix of the other.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Vlan::new());
+    /// assert!(pkt.compare_hex("000a 0800").is_ok());
+    /// assert_eq!(
+    ///     pkt.compare_hex("000a 86dd").unwrap_err(),
+    ///     "byte 2 differs (Vlan.etype): expected 0x86, got 0x08"
+    /// );
+    /// assert_eq!(
+    ///     pkt.compare_hex("000a 0800 00").unwrap_err(),
+    ///     "length differs: expected 5 bytes, got 4"
+    /// );
+    /// ```
+    pub fn compare_hex(&self, expected_hex: &str) -> Result<(), String> {
+        let hex: Vec<u8> = expected_hex
+            .bytes()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect();
+        if hex.len() % 2 == 1 {
+            return Err(format!("odd number of hex digits: {}", hex.len()));
+        }
+        let expected = hex
+            .chunks(2)
+            .enumerate()
+            .map(|(i, x)| {
+                core::str::from_utf8(x)
+                    .ok()
+                    .and_then(|x| u8::from_str_radix(x, 16).ok())
+                    .ok_or_else(|| format!("invalid hex at byte {}", i))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        let actual = self.to_vec();
+        match expected.iter().zip(&actual).position(|(a, b)| a != b) {
+            Some(i) => {
+                let fields: Vec<String> = self
+                    .layout()
+                    .fields_in(i..i + 1)
+                    .map(|(l, f)| format!("{}.{}", l, f.name))
+                    .collect();
+                let fields = match fields.is_empty() {
+                    true => String::new(),
+                    false => format!(" ({})", fields.join(" ")),
+                };
+                Err(format!(
+                    "byte {} differs{}: expected {:#04x}, got {:#04x}",
+                    i, fields, expected[i], actual[i]
+                ))
+            }
+            None if expected.len() != actual.len() => Err(format!(
+                "length differs: expected {} bytes, got {}",
+                expected.len(),
+                actual.len()
+            )),
+            None => Ok(()),
+        }
+    }
+    /// Assemble the packet into the start of dst without allocating, returns the bytes written
+    ///
+    /// Nothing is written if dst cannot hold the whole packet.
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// let mut frame = [0u8; 1514];
+    /// let n = pkt.write_into(&mut frame).unwrap();
+    /// assert_eq!(&frame[..n], pkt.to_vec().as_slice());
+    /// ```
+    pub fn write_into(&self, dst: &mut [u8]) -> Result<usize, PacketError> {
+        if let Some(raw) = self.raw() {
+            if dst.len() < raw.len() {
+                return Err(PacketError::BufferTooSmall {
+                    needed: raw.len(),
+                    available: dst.len(),
+                });
+            }
+            dst[..raw.len()].copy_from_slice(raw);
+            return Ok(raw.len());
+        }
+        write_layers(&self.hdrs, &self.payload, &self.trailers, dst)
+    }
+    /// Get a contiguous editable copy of the packet
+    ///
+    /// The edited bytes are dissected back into the packet when the returned buffer is dropped.
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(IPv4::new());
+    /// pkt.push(TCP::new());
+    /// {
+    ///     let mut buf = pkt.bytes_mut();
+    ///     // rewrite the ipv4 ttl in place
+    ///     buf[22] = 10;
+    /// }
+    /// let ipv4: &IPv4 = pkt.get_header("IPv4").unwrap();
+    /// assert_eq!(ipv4.ttl(), 10);
+    /// ```
+    pub fn bytes_mut(&mut self) -> PacketBytesMut<'_> {
+        let buf = self.to_vec();
+        PacketBytesMut { pkt: self, buf }
+    }
+    /// Re-run dissection over the serialized packet to refresh all the headers
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.set_payload(&IPv4::new().to_vec());
+    /// pkt.set_payload(&TCP::new().to_vec());
+    /// // the payload is now dissected as IPv4 and TCP headers
+    /// pkt.reparse();
+    /// ```
+    pub fn reparse(&mut self) {
+        let v = self.to_vec();
+        self.reparse_from(v.as_slice());
+    }
+    /// Replace the packet contents by dissecting a byte stream
+    ///
+    /// Dissection starts from the current top header of the packet. If the parser cannot start
+    /// from that header, the whole byte stream becomes the payload. The trailers of the packet
+    /// are kept as they are, the bytes they cover at the end of arr are not dissected.
+    pub fn reparse_from(&mut self, arr: &[u8]) {
+        let name = match self.hdrs.first() {
+            Some(h) => String::from(h.name()),
+            None => String::new(),
+        };
+        let end = arr.len().saturating_sub(self.trailers_len());
+        let pkt = parser::slow::parse_from(name.as_str(), &arr[..end]);
+        self.hdrs = pkt.hdrs;
+        self.payload = pkt.payload;
+        self.poisoned.clear();
+        self.invalidate();
+    }
+    /// Remove the first header of type `T` from the packet and return it
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(IPv4::new());
+    /// // move the IPv4 header into another packet
+    /// let ipv4: IPv4 = pkt.take_header().unwrap();
+    /// let mut other = Packet::new();
+    /// other.push(ipv4);
+    /// ```
+    pub fn take_header<T: Header + 'static>(&mut self) -> Result<T, PacketError> {
+        self.invalidate();
+        let i = self.position_of::<T>()?;
+        match downcast::<T>(self.hdrs.remove(i)) {
+            Ok(b) => {
+                self.poison_removed(i);
+                Ok(*b)
+            }
+            Err(_) => unreachable!(),
+        }
+    }
+    /// Insert a header after the first header of type `T`
+    ///
+    /// When strict, the demux field of the preceding header is set to point at the new header and
+    /// the demux field of the new header takes over the value the preceding header held, or the
+    /// one matching the following header.
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(IPv4::new());
+    /// pkt.insert_after::<Ether>(Vlan::new(), true).unwrap();
+    /// assert_eq!(pkt.layer::<Ether>().unwrap().etype(), 0x8100);
+    /// assert_eq!(pkt.layer::<Vlan>().unwrap().etype(), 0x0800);
+    /// ```
+    pub fn insert_after<T: Header + 'static>(
+        &mut self,
+        hdr: impl Header,
+        strict: bool,
+    ) -> Result<(), PacketError> {
+        let i = self.position_of::<T>()?;
+        let displaced = self.demux_of(i);
+        self.hdrs.insert(i + 1, hdr.to_owned());
+        self.poison_inserted(i + 1);
+        self.invalidate();
+        if strict {
+            self.relink(i, None);
+            self.relink(i + 1, displaced);
+        }
+        Ok(())
+    }
+    /// Insert a header before the first header of type `T`
+    ///
+    /// When strict, the demux field of the preceding header is set to point at the new header and
+    /// the demux field of the new header is set to point at the `T` header.
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.push(IPv4::new());
+    /// pkt.insert_before::<IPv4>(Vlan::new(), true).unwrap();
+    /// assert_eq!(pkt.layer::<Ether>().unwrap().etype(), 0x8100);
+    /// assert_eq!(pkt.layer::<Vlan>().unwrap().etype(), 0x0800);
+    /// ```
+    pub fn insert_before<T: Header + 'static>(
+        &mut self,
+        hdr: impl Header,
+        strict: bool,
+    ) -> Result<(), PacketError> {
+        let i = self.position_of::<T>()?;
+        let displaced = i.checked_sub(1).and_then(|p| self.demux_of(p));
+        self.hdrs.insert(i, hdr.to_owned());
+        self.poison_inserted(i);
+        self.invalidate();
+        if strict {
+            if i > 0 {
+                self.relink(i - 1, None);
+            }
+            self.relink(i, displaced);
+        }
+        Ok(())
+    }
+    /// Remove the first header of type `T` and return it
+    ///
+    /// When strict, the demux field of the preceding header is set to point at the following
+    /// header, or takes over the value the removed header held.
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Packet::ethernet("00:01:02:03:04:05", "00:06:07:08:09:0a", 0x8100));
+    /// pkt.push(Packet::vlan(0, 0, 10, 0x86dd));
+    /// let vlan: Vlan = pkt.remove_layer::<Vlan>(true).unwrap();
+    /// assert_eq!(vlan.vid(), 10);
+    /// assert_eq!(pkt.layer::<Ether>().unwrap().etype(), 0x86dd);
+    /// ```
+    pub fn remove_layer<T: Header + 'static>(&mut self, strict: bool) -> Result<T, PacketError> {
+        let i = self.position_of::<T>()?;
+        let displaced = self.demux_of(i);
+        let hdr = downcast::<T>(self.hdrs.remove(i)).unwrap_or_else(|_| unreachable!());
+        self.poison_removed(i);
+        self.invalidate();
+        if strict && i > 0 {
+            self.relink(i - 1, displaced);
+        }
+        Ok(*hdr)
+    }
+    /// Replace the first header of type `T` and return it
+    ///
+    /// When strict, the demux fields of the preceding and the new header are updated as in
+    /// [`Packet::insert_after`].
+    pub fn replace_layer<T: Header + 'static>(
+        &mut self,
+        hdr: impl Header,
+        strict: bool,
+    ) -> Result<T, PacketError> {
+        let i = self.position_of::<T>()?;
+        let displaced = self.demux_of(i);
+        let old = core::mem::replace(&mut self.hdrs[i], hdr.to_owned());
+        // the marks were on the header taken out
+        self.poisoned.retain(|x| x.0 != i);
+        self.invalidate();
+        if strict {
+            if i > 0 {
+                self.relink(i - 1, None);
+            }
+            self.relink(i, displaced);
+        }
+        Ok(*downcast::<T>(old).unwrap_or_else(|_| unreachable!()))
+    }
+    fn position_of<T: Header + 'static>(&self) -> Result<usize, PacketError> {
+        self.hdrs
+            .iter()
+            .position(|x| x.as_any().is::<T>())
+            .ok_or_else(|| PacketError::MissingHeader(String::from(type_name::<T>())))
+    }
+    /// Value of the demux field of the header at index i and whether it is an ethertype
+    fn demux_of(&self, i: usize) -> Option<(bool, u64)> {
+        let (field, etype) = demux_field(self.hdrs[i].name())?;
+        Some((etype, self.hdrs[i].get_field(field)?))
+    }
+    /// Point the demux field of the header at index i at the following header
+    ///
+    /// Without a known following header, the displaced value is used if it is of the same kind.
+    fn relink(&mut self, i: usize, displaced: Option<(bool, u64)>) {
+        let next = self.hdrs[i + 1..]
+            .iter()
+            .map(|x| x.name())
+            .find(|x| !is_gre_option(x))
+            .map(String::from);
+        self.invalidate();
+        let hdr = &mut self.hdrs[i];
+        if let Some((field, value)) = next.and_then(|n| demux_value(hdr.name(), &n)) {
+            hdr.set_field(field, value);
+        } else if let (Some((field, etype)), Some((kind, value))) =
+            (demux_field(hdr.name()), displaced)
         {
-            Some(b) => Ok(b),
-            None => Err(format!("{} header not found", index)),
+            if etype == kind {
+                hdr.set_field(field, value);
+            }
         }
     }
 }
@@ -255,20 +1165,20 @@ impl Packet {
     fn __getitem1__(slf: &PyCell<Self>, index: String) -> PyObject {
         let gil = ::pyo3::Python::acquire_gil();
         let mut pkt = slf.try_borrow_mut().unwrap();
-        let hdr: &mut Box<dyn Header> = &mut pkt[&index];
+        let hdr: &mut Box<dyn Header> = &mut pkt[index.as_str()];
         hdr.to_object(gil.python())
     }
     #[cfg(feature = "python-module")]
     fn __getitem__(&mut self, index: String) -> PyObject {
         let gil = ::pyo3::Python::acquire_gil();
-        let hdr: &mut Box<dyn Header> = &mut self[&index];
-        println!("Getting {}", hdr.name());
+        let hdr: &mut Box<dyn Header> = &mut self[index.as_str()];
+        debug!(layer = hdr.name(); "python header access");
         hdr.to_object(gil.python())
     }
     /*
     fn __getitem2__(mut slf : PyRef<'_, Self>, index: String) -> PyRef<'_, Ether> {
         let gil = ::pyo3::Python::acquire_gil();
-        let hdr: & Box<dyn Header> = & slf[&index];
+        let hdr: & Box<dyn Header> = & slf[index.as_str()];
         let e = &<Ether>::from(hdr);
         let n = PyCell::new(gil.python(), e).unwrap();
         let k = n.borrow();
@@ -276,6 +1186,14 @@ impl Packet {
     }
     */
     #[cfg(feature = "python-module")]
+    fn __str__(&self) -> String {
+        self.dump()
+    }
+    #[cfg(feature = "python-module")]
+    fn __repr__(&self) -> String {
+        self.dump()
+    }
+    #[cfg(feature = "python-module")]
     fn __setitem__(&mut self, index: String, value: Ether) -> () {
         let x: &mut Ether = self.get_header_mut(index.as_str()).unwrap();
         x.replace(&value);
@@ -294,6 +1212,10 @@ impl Packet {
         Packet {
             hdrs: Vec::new(),
             payload: Vec::new(),
+            trailers: Vec::new(),
+            poisoned: Vec::new(),
+            len: AtomicUsize::new(UNKNOWN_LEN),
+            raw: None,
         }
     }
     /// Compare this packet with another Packet
@@ -322,39 +1244,34 @@ impl Packet {
     pub fn compare_with_slice(&self, b: &[u8]) -> bool {
         let pktlen = self.len();
         if pktlen != b.len() {
-            println!("this {} other {}", pktlen, b.len());
+            debug!(expected = pktlen, actual = b.len(); "packet length mismatch");
             return false;
         }
         let a = self.to_vec();
         let matching = a.iter().zip(b).filter(|&(a, b)| a == b).count();
         if pktlen != matching || b.len() != matching {
-            println!(
-                "this {} other {}, matching upto {} bytes",
-                pktlen,
-                b.len(),
-                matching
-            );
+            debug!(expected = pktlen, actual = b.len(), matching; "packet bytes mismatch");
             return false;
         }
         true
     }
     /// Display the packet contents
+    ///
+    /// Each header is printed with its offset and length, indented one level deeper than the
+    /// header before it. Header names are colorized with the `color` feature.
+    #[cfg(feature = "std")]
     pub fn show(&self) -> () {
-        for s in &self.hdrs {
-            s.show();
-        }
-        let v = self.to_vec();
-        println!("\n#### raw {} bytes ####", v.len());
-        let mut x = 0;
-        for i in v.as_slice() {
-            print!("{:02x} ", i);
-            x += 1;
-            if x % 16 == 0 {
-                x = 0;
-                println!();
-            }
-        }
-        println!();
+        print!("{}", self.dump());
+    }
+    /// Return the output of [`show`](Packet::show) as a string
+    pub fn dump(&self) -> String {
+        let mut out = self.dump_layers();
+        out.push_str(&dump_raw(&self.to_vec()));
+        out
+    }
+    /// The output of [`show`](Packet::show) without the raw bytes
+    pub(crate) fn dump_layers(&self) -> String {
+        dump_layers(&self.hdrs, &self.payload, &self.trailers)
     }
     /// Copies packet into a new vec
     /// # Example
@@ -365,24 +1282,170 @@ impl Packet {
     /// let v = pkt.to_vec();
     /// ```
     pub fn to_vec(&self) -> Vec<u8> {
+        if let Some(raw) = self.raw() {
+            return raw.to_vec();
+        }
         let mut r = Vec::new();
         for s in &self.hdrs {
             r.extend_from_slice(&s.to_vec().as_slice());
         }
         r.extend_from_slice(&self.payload.as_slice());
+        for s in self.trailers.iter().rev() {
+            r.extend_from_slice(&s.to_vec());
+        }
         r
     }
     fn clone_me(&self) -> Packet {
+        Packet {
+            hdrs: self.hdrs.clone(),
+            payload: self.payload.clone(),
+            trailers: self.trailers.clone(),
+            poisoned: self.poisoned.clone(),
+            len: AtomicUsize::new(self.len.load(Ordering::Relaxed)),
+            raw: self.raw.clone(),
+        }
+    }
+    /// Clone the packet sharing the bytes of every header with the original
+    ///
+    /// A header is copied by the first write to it, in the clone or in the original, so the
+    /// clones of a template only pay for the headers they modify.
+    ///
+    /// This breaks the sharing of headers created with `From<&Box<dyn Header>>`: after a
+    /// `clone_cow`, writing through such a header copies its bytes, and the packet no longer
+    /// sees the write.
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate packet_rs; use packet_rs::headers::*; use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(IPv4::new());
+    /// let mut clone = pkt.clone_cow();
+    /// clone.layer_mut::<IPv4>().unwrap().set_ttl(1);
+    /// assert_eq!(pkt.layer::<IPv4>().unwrap().ttl(), 64);
+    /// ```
+    pub fn clone_cow(&self) -> Packet {
+        Packet {
+            hdrs: self.hdrs.iter().map(|x| x.clone_cow()).collect(),
+            payload: self.payload.clone(),
+            trailers: self.trailers.iter().map(|x| x.clone_cow()).collect(),
+            poisoned: self.poisoned.clone(),
+            len: AtomicUsize::new(self.len.load(Ordering::Relaxed)),
+            raw: self.raw.clone(),
+        }
+    }
+    /// Share the packet between threads as a template, each cloning it with [`Packet::clone_cow`]
+    ///
+    /// The clones share the bytes of the headers until they write to them, so a worker only
+    /// copies the headers it changes.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let template = create_udp_packet(&Default::default()).template();
+    /// let workers: Vec<_> = (0..4)
+    ///     .map(|i| {
+    ///         let template = template.clone();
+    ///         std::thread::spawn(move || {
+    ///             let mut pkt = template.clone_cow();
+    ///             pkt.layer_mut::<UDP>().unwrap().set_src(1000 + i);
+    ///             pkt.to_vec()
+    ///         })
+    ///     })
+    ///     .collect();
+    /// for worker in workers {
+    ///     assert_eq!(worker.join().unwrap().len(), template.len());
+    /// }
+    /// ```
+    pub fn template(self) -> Arc<Packet> {
+        Arc::new(self)
+    }
+    /// Clone the first snaplen bytes of the packet, returns the copy and the original length
+    ///
+    /// Headers that fit are kept, the bytes of a header cut by snaplen become the payload, as
+    /// a capture tool applying a snaplen would record the packet.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let pkt = create_tcp_packet(&Default::default());
+    /// let (short, len) = pkt.clone_truncated(40);
+    /// assert_eq!(len, pkt.len());
+    /// assert_eq!(short.len(), 40);
+    /// assert!(short.layer::<IPv4>().is_some());
+    /// assert!(short.layer::<TCP>().is_none());
+    /// ```
+    pub fn clone_truncated(&self, snaplen: usize) -> (Packet, usize) {
+        let len = self.len();
+        if snaplen >= len {
+            return (self.clone(), len);
+        }
         let mut pkt = Packet::new();
-        for s in &self.hdrs {
-            pkt.hdrs.push(s.as_ref().clone());
+        let mut offset = 0;
+        for hdr in &self.hdrs {
+            if offset + hdr.len() > snaplen {
+                pkt.payload = hdr.to_vec()[..snaplen - offset].to_vec();
+                return (pkt, len);
+            }
+            pkt.hdrs.push(hdr.clone_box());
+            offset += hdr.len();
         }
-        pkt.payload = self.payload.clone();
-        pkt
+        // the bytes of the trailers cut by snaplen are kept as payload too
+        pkt.payload = self.to_vec()[offset..snaplen].to_vec();
+        (pkt, len)
     }
     /// Return length of the packet
+    ///
+    /// The length is computed once and kept until a method taking the packet mutably, as
+    /// [`Packet::layer_mut`], is called.
     pub fn len(&self) -> usize {
-        self.hdrs.iter().map(|s| s.len()).sum::<usize>() + self.payload.len()
+        match self.len.load(Ordering::Relaxed) {
+            UNKNOWN_LEN => self.recompute_len(),
+            len => len,
+        }
+    }
+    /// Compute the length of the packet again and keep it
+    pub fn recompute_len(&self) -> usize {
+        let len = self.hdrs.iter().chain(&self.trailers).map(|s| s.len()).sum::<usize>()
+            + self.payload.len();
+        self.len.store(len, Ordering::Relaxed);
+        len
+    }
+    /// Forget the length and the raw bytes, before any change of the packet
+    pub(crate) fn invalidate(&mut self) {
+        self.len.store(UNKNOWN_LEN, Ordering::Relaxed);
+        self.raw = None;
+    }
+    /// Return the bytes the packet was parsed from, while it is unchanged
+    ///
+    /// They are kept by [`Packet::parse_with_options`] with
+    /// [`ParseOptions::keep_raw`](crate::parser::ParseOptions::keep_raw), and copied by
+    /// [`Packet::to_vec`] and [`Packet::write_into`] instead of serializing the layers. Any method
+    /// of the packet taking it mutably drops them, and they are ignored once a header shared its
+    /// bytes with a copy that may write to them, as `IPv4::from(&pkt[1])` does.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::parser::ParseOptions;
+    /// # use packet_rs::Packet;
+    /// let v = create_tcp_packet(&Default::default()).to_vec();
+    /// let opts = ParseOptions { keep_raw: true, ..Default::default() };
+    /// let pkt = Packet::parse_with_options(&v, &opts);
+    /// assert_eq!(pkt.raw(), Some(v.as_slice()));
+    /// let mut ip = IPv4::from(&pkt[1]);
+    /// ip.set_ttl(1);
+    /// assert_eq!(pkt.raw(), None);
+    /// assert_eq!(pkt.to_vec()[22], 1);
+    /// ```
+    pub fn raw(&self) -> Option<&[u8]> {
+        let mut hdrs = self.hdrs.iter().chain(&self.trailers);
+        match hdrs.any(|x| x.is_shared()) {
+            true => None,
+            false => self.raw.as_deref(),
+        }
     }
     #[staticmethod]
     pub fn ethernet(dst: &str, src: &str, etype: u16) -> Ether {
@@ -706,6 +1769,9 @@ impl<'a> PacketSlice<'a> {
     pub(crate) fn insert(&mut self, hdr: impl Header + 'a) {
         self.hdrs.insert(0, Box::new(hdr));
     }
+    pub(crate) fn insert_boxed(&mut self, hdr: Box<dyn Header + 'a>) {
+        self.hdrs.insert(0, hdr);
+    }
     pub(crate) fn set_payload(&mut self, payload: &'a [u8]) -> () {
         self.payload = payload;
     }
@@ -717,25 +1783,22 @@ impl<'a> PacketSlice<'a> {
         r.extend_from_slice(&self.payload);
         r
     }
+    /// Assemble the packet into the start of dst without allocating, returns the bytes written
+    pub fn write_into(&self, dst: &mut [u8]) -> Result<usize, PacketError> {
+        write_layers(&self.hdrs, self.payload, &[], dst)
+    }
     pub fn len(&self) -> usize {
         self.hdrs.iter().map(|s| s.len()).sum::<usize>() + self.payload.len()
     }
+    #[cfg(feature = "std")]
     pub fn show(&self) -> () {
-        for s in &self.hdrs {
-            s.show();
-        }
-        let v = self.to_vec();
-        println!("\n#### raw {} bytes ####", v.len());
-        let mut x = 0;
-        for i in v.as_slice() {
-            print!("{:02x} ", i);
-            x += 1;
-            if x % 16 == 0 {
-                x = 0;
-                println!();
-            }
-        }
-        println!();
+        print!("{}", self.dump());
+    }
+    /// Return the output of [`show`](PacketSlice::show) as a string
+    pub fn dump(&self) -> String {
+        let mut out = dump_layers(&self.hdrs, self.payload, &[]);
+        out.push_str(&dump_raw(&self.to_vec()));
+        out
     }
 }
 // https://www.reddit.com/r/learnrust/comments/yltr2f/how_to_create_an_iterator_over_two_slices_without/
@@ -1,8 +1,15 @@
+use log::debug;
+
+use super::fits;
+use crate::bind::{self, Binding};
 use crate::headers::*;
 use crate::types::*;
 use crate::PacketSlice;
 
 pub fn parse<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("Ether", arr, Ether::size()) {
+        return accept(arr);
+    }
     let length: u16 = ((arr[12] as u16) << 8) | arr[13] as u16;
     if length < 1500 {
         parse_dot3(arr)
@@ -11,12 +18,18 @@ pub fn parse<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
     }
 }
 pub fn parse_dot3<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("Dot3", arr, Dot3::size()) {
+        return accept(arr);
+    }
     let dot3 = Dot3Slice::from(&arr[0..Dot3::size()]);
     let mut pkt = parse_llc(&arr[Dot3::size()..]);
     pkt.insert(dot3);
     pkt
 }
 pub fn parse_llc<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("LLC", arr, LLC::size()) {
+        return accept(arr);
+    }
     let llc = LLCSlice::from(&arr[0..LLC::size()]);
     let mut pkt = if arr[0] == 0xAA && arr[1] == 0xAA && arr[2] == 0x03 {
         parse_snap(&arr[LLC::size()..])
@@ -27,44 +40,61 @@ pub fn parse_llc<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
     pkt
 }
 pub fn parse_snap<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("SNAP", arr, SNAP::size()) {
+        return accept(arr);
+    }
     let snap = SNAPSlice::from(&arr[0..SNAP::size()]);
     let mut pkt = accept(&arr[SNAP::size()..]);
     pkt.insert(snap);
     pkt
 }
 pub fn parse_ethernet<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("Ether", arr, Ether::size()) {
+        return accept(arr);
+    }
     let eth = EtherSlice::from(&arr[0..Ether::size()]);
-    let etype = EtherType::try_from(eth.etype() as u16);
-    let mut pkt = match etype {
-        Ok(EtherType::DOT1Q) => parse_vlan(&arr[Ether::size()..]),
-        Ok(EtherType::ARP) => parse_arp(&arr[Ether::size()..]),
-        Ok(EtherType::IPV4) => parse_ipv4(&arr[Ether::size()..]),
-        Ok(EtherType::IPV6) => parse_ipv6(&arr[Ether::size()..]),
-        Ok(EtherType::MPLS) => parse_mpls(&arr[Ether::size()..]),
-        _ => accept(&arr[Ether::size()..]),
+    let rest = &arr[Ether::size()..];
+    let mut pkt = match parse_bound("Ether", "etype", eth.etype(), rest) {
+        Some(pkt) => pkt,
+        None => parse_etype("Ether", eth.etype(), rest),
     };
     pkt.insert(eth);
     pkt
 }
 pub fn parse_vlan<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("Vlan", arr, Vlan::size()) {
+        return accept(arr);
+    }
     let vlan = VlanSlice::from(&arr[0..Vlan::size()]);
-    let etype = EtherType::try_from(vlan.etype() as u16);
-    let mut pkt = match etype {
-        Ok(EtherType::DOT1Q) => parse_vlan(&arr[Vlan::size()..]),
-        Ok(EtherType::ARP) => parse_arp(&arr[Vlan::size()..]),
-        Ok(EtherType::IPV4) => parse_ipv4(&arr[Vlan::size()..]),
-        Ok(EtherType::IPV6) => parse_ipv6(&arr[Vlan::size()..]),
-        Ok(EtherType::MPLS) => parse_mpls(&arr[Vlan::size()..]),
-        _ => accept(&arr[Vlan::size()..]),
+    let rest = &arr[Vlan::size()..];
+    let mut pkt = match parse_bound("Vlan", "etype", vlan.etype(), rest) {
+        Some(pkt) => pkt,
+        None => parse_etype("Vlan", vlan.etype(), rest),
     };
     pkt.insert(vlan);
     pkt
 }
+/// Parse the layer after the ethertype of a layer
+fn parse_etype<'a>(layer: &str, etype: u64, arr: &'a [u8]) -> PacketSlice<'a> {
+    match EtherType::try_from(etype as u16) {
+        Ok(EtherType::DOT1Q) => parse_vlan(arr),
+        Ok(EtherType::ARP) => parse_arp(arr),
+        Ok(EtherType::IPV4) => parse_ipv4(arr),
+        Ok(EtherType::IPV6) => parse_ipv6(arr),
+        Ok(EtherType::MPLS) => parse_mpls(arr),
+        _ => {
+            debug!(layer, etype; "unknown ethertype");
+            accept(arr)
+        }
+    }
+}
 pub fn parse_mpls<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("MPLS", arr, MPLS::size()) {
+        return accept(arr);
+    }
     let mpls = MPLSSlice::from(&arr[0..MPLS::size()]);
-    let bos = mpls.bos();
-    let mut pkt = if bos == 1 {
-        parse_mpls_bos(&arr[MPLS::size()..])
+    let mut pkt = if mpls.bos() {
+        parse_mpls_payload(&arr[MPLS::size()..])
     } else {
         parse_mpls(&arr[MPLS::size()..])
     };
@@ -72,54 +102,92 @@ pub fn parse_mpls<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
     pkt
 }
 pub fn parse_mpls_bos<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("MPLS", arr, MPLS::size()) {
+        return accept(arr);
+    }
     let mpls = MPLSSlice::from(&arr[0..MPLS::size()]);
-    let mut pkt = match IpType::try_from(arr[MPLS::size()] >> 4 & 0xf) {
-        Ok(IpType::V4) => parse_ipv4(&arr[MPLS::size()..]),
-        Ok(IpType::V6) => parse_ipv6(&arr[MPLS::size()..]),
-        _ => parse_ethernet(&arr[MPLS::size()..]),
-    };
+    let mut pkt = parse_mpls_payload(&arr[MPLS::size()..]);
     pkt.insert(mpls);
     pkt
 }
+/// Parse the packet under the bottom of the MPLS stack from its IP version
+fn parse_mpls_payload<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    match IpType::try_from(arr.first().map_or(0, |b| b >> 4 & 0xf)) {
+        Ok(IpType::V4) => parse_ipv4(arr),
+        Ok(IpType::V6) => parse_ipv6(arr),
+        _ => parse_ethernet(arr),
+    }
+}
 pub fn parse_ipv4<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("IPv4", arr, IPv4::size()) {
+        return accept(arr);
+    }
     let ipv4 = IPv4Slice::from(&arr[0..IPv4::size()]);
-    let proto = IpProtocol::try_from(ipv4.protocol() as u8);
-    let mut pkt = match proto {
-        Ok(IpProtocol::ICMP) => parse_icmp(&arr[IPv4::size()..]),
-        Ok(IpProtocol::IPIP) => parse_ipv4(&arr[IPv4::size()..]),
-        Ok(IpProtocol::TCP) => parse_tcp(&arr[IPv4::size()..]),
-        Ok(IpProtocol::UDP) => parse_udp(&arr[IPv4::size()..]),
-        Ok(IpProtocol::IPV6) => parse_ipv6(&arr[IPv4::size()..]),
-        Ok(IpProtocol::GRE) => parse_gre(&arr[IPv4::size()..]),
-        _ => accept(&arr[IPv4::size()..]),
+    let rest = &arr[IPv4::size()..];
+    let mut pkt = match parse_bound("IPv4", "protocol", ipv4.protocol(), rest) {
+        Some(pkt) => pkt,
+        None => parse_ipv4_proto(ipv4.protocol(), rest),
     };
     pkt.insert(ipv4);
     pkt
 }
+/// Parse the layer after the protocol of an IPv4 header
+fn parse_ipv4_proto(proto: u64, arr: &[u8]) -> PacketSlice<'_> {
+    match IpProtocol::try_from(proto as u8) {
+        Ok(IpProtocol::ICMP) => parse_icmp(arr),
+        Ok(IpProtocol::IGMP) => parse_igmp(arr),
+        Ok(IpProtocol::IPIP) => parse_ipv4(arr),
+        Ok(IpProtocol::TCP) => parse_tcp(arr),
+        Ok(IpProtocol::UDP) => parse_udp(arr),
+        Ok(IpProtocol::UDPLITE) => parse_udplite(arr),
+        Ok(IpProtocol::IPV6) => parse_ipv6(arr),
+        Ok(IpProtocol::GRE) => parse_gre(arr),
+        _ => accept(arr),
+    }
+}
 pub fn parse_ipv6<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("IPv6", arr, IPv6::size()) {
+        return accept(arr);
+    }
     let ipv6 = IPv6Slice::from(&arr[0..IPv6::size()]);
-    let next_hdr = IpProtocol::try_from(ipv6.next_hdr() as u8);
-    let mut pkt = match next_hdr {
-        Ok(IpProtocol::ICMPV6) => parse_icmp(&arr[IPv6::size()..]),
-        Ok(IpProtocol::IPIP) => parse_ipv4(&arr[IPv6::size()..]),
-        Ok(IpProtocol::TCP) => parse_tcp(&arr[IPv6::size()..]),
-        Ok(IpProtocol::UDP) => parse_udp(&arr[IPv6::size()..]),
-        Ok(IpProtocol::IPV6) => parse_ipv6(&arr[IPv6::size()..]),
-        Ok(IpProtocol::GRE) => parse_gre(&arr[IPv6::size()..]),
-        _ => accept(&arr[IPv6::size()..]),
+    let rest = &arr[IPv6::size()..];
+    let mut pkt = match parse_bound("IPv6", "next_hdr", ipv6.next_hdr(), rest) {
+        Some(pkt) => pkt,
+        None => parse_ipv6_next_hdr(ipv6.next_hdr(), rest),
     };
     pkt.insert(ipv6);
     pkt
 }
+/// Parse the layer after the next header of an IPv6 header
+fn parse_ipv6_next_hdr(next_hdr: u64, arr: &[u8]) -> PacketSlice<'_> {
+    match IpProtocol::try_from(next_hdr as u8) {
+        Ok(IpProtocol::ICMPV6) => parse_icmpv6(arr),
+        Ok(IpProtocol::IPIP) => parse_ipv4(arr),
+        Ok(IpProtocol::TCP) => parse_tcp(arr),
+        Ok(IpProtocol::UDP) => parse_udp(arr),
+        Ok(IpProtocol::UDPLITE) => parse_udplite(arr),
+        Ok(IpProtocol::IPV6) => parse_ipv6(arr),
+        Ok(IpProtocol::GRE) => parse_gre(arr),
+        _ => accept(arr),
+    }
+}
 pub fn parse_gre<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("GRE", arr, GRE::size()) {
+        return accept(arr);
+    }
     let gre = GRESlice::from(&arr[0..GRE::size()]);
-    let proto = EtherType::try_from(gre.proto() as u16);
     let chksum_present = gre.chksum_present();
     let seqnum_present = gre.seqnum_present();
     let key_present = gre.key_present();
+    let options = chksum_present as usize * GREChksumOffset::size()
+        + key_present as usize * GREKey::size()
+        + seqnum_present as usize * GRESequenceNum::size();
+    if !fits("GRE", arr, GRE::size() + options) {
+        return accept(arr);
+    }
     let mut offset = 0;
     offset += GRE::size();
-    let gco = if chksum_present == 1 {
+    let gco = if chksum_present {
         let p = Some(GREChksumOffsetSlice::from(
             &arr[offset..offset + GREChksumOffset::size()],
         ));
@@ -128,14 +196,14 @@ pub fn parse_gre<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
     } else {
         None
     };
-    let gk = if key_present == 1 {
+    let gk = if key_present {
         let p = Some(GREKeySlice::from(&arr[offset..offset + GREKey::size()]));
         offset += GREKey::size();
         p
     } else {
         None
     };
-    let gsn = if seqnum_present == 1 {
+    let gsn = if seqnum_present {
         let p = Some(GRESequenceNumSlice::from(
             &arr[offset..offset + GRESequenceNum::size()],
         ));
@@ -144,37 +212,59 @@ pub fn parse_gre<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
     } else {
         None
     };
-    let mut pkt = match proto {
-        Ok(EtherType::IPV4) => parse_ipv4(&arr[offset..]),
-        Ok(EtherType::IPV6) => parse_ipv6(&arr[offset..]),
-        Ok(EtherType::ERSPANII) => parse_erspan2(&arr[offset..]),
-        Ok(EtherType::ERSPANIII) => parse_erspan3(&arr[offset..]),
-        _ => accept(&arr[offset..]),
+    let rest = &arr[offset..];
+    let mut pkt = match parse_bound("GRE", "proto", gre.proto(), rest) {
+        Some(pkt) => pkt,
+        None => parse_gre_proto(gre.proto(), rest),
     };
-    if let Some(p) = gco {
+    // inserted innermost first, to keep the order of the options on the wire
+    if let Some(p) = gsn {
         pkt.insert(p);
     }
     if let Some(p) = gk {
         pkt.insert(p);
     }
-    if let Some(p) = gsn {
+    if let Some(p) = gco {
         pkt.insert(p);
     }
     pkt.insert(gre);
     pkt
 }
+/// Parse the layer after the protocol of a GRE header
+fn parse_gre_proto(proto: u64, arr: &[u8]) -> PacketSlice<'_> {
+    match EtherType::try_from(proto as u16) {
+        Ok(EtherType::IPV4) => parse_ipv4(arr),
+        Ok(EtherType::IPV6) => parse_ipv6(arr),
+        Ok(EtherType::ERSPANII) => parse_erspan2(arr),
+        Ok(EtherType::ERSPANIII) => parse_erspan3(arr),
+        _ => {
+            debug!(layer = "GRE", etype = proto; "unknown ethertype");
+            accept(arr)
+        }
+    }
+}
 pub fn parse_erspan2<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("ERSPAN2", arr, ERSPAN2::size()) {
+        return accept(arr);
+    }
     let erspan2 = ERSPAN2Slice::from(&arr[0..ERSPAN2::size()]);
     let mut pkt = parse_ethernet(&arr[ERSPAN2::size()..]);
     pkt.insert(erspan2);
     pkt
 }
 pub fn parse_erspan3<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("ERSPAN3", arr, ERSPAN3::size()) {
+        return accept(arr);
+    }
     let erspan3 = ERSPAN3Slice::from(&arr[0..ERSPAN3::size()]);
     let o = erspan3.o();
+    let needed = ERSPAN3::size() + o as usize * ERSPANPLATFORM::size();
+    if !fits("ERSPAN3", arr, needed) {
+        return accept(arr);
+    }
     let mut offset = 0;
     offset += ERSPAN3::size();
-    let platform = if o == 1 {
+    let platform = if o {
         let p = Some(ERSPANPLATFORMSlice::from(
             &arr[offset..offset + ERSPANPLATFORM::size()],
         ));
@@ -191,35 +281,177 @@ pub fn parse_erspan3<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
     pkt
 }
 pub fn parse_arp<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("ARP", arr, ARP::size()) {
+        return accept(arr);
+    }
     let mut pkt = accept(&arr[ARP::size()..]);
     pkt.insert(ARPSlice::from(&arr[0..ARP::size()]));
     pkt
 }
 pub fn parse_icmp<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("ICMP", arr, ICMP::size()) {
+        return accept(arr);
+    }
     let mut pkt = accept(&arr[ICMP::size()..]);
     pkt.insert(ICMPSlice::from(&arr[0..ICMP::size()]));
     pkt
 }
+pub fn parse_icmpv6<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("ICMP", arr, ICMP::size()) {
+        return accept(arr);
+    }
+    match arr[0] {
+        MLD_QUERY | MLD_REPORT | MLD_DONE => parse_mld(arr),
+        MLD_V2_REPORT => parse_mld_v2_report(arr),
+        _ => parse_icmp(arr),
+    }
+}
+pub fn parse_igmp<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("Igmp", arr, Igmp::size()) {
+        return accept(arr);
+    }
+    if arr[0] == IGMP_V3_REPORT {
+        let mut pkt = accept(&arr[IgmpV3Report::size()..]);
+        pkt.insert(IgmpV3ReportSlice::from(&arr[0..IgmpV3Report::size()]));
+        pkt
+    } else {
+        let mut pkt = accept(&arr[Igmp::size()..]);
+        pkt.insert(IgmpSlice::from(&arr[0..Igmp::size()]));
+        pkt
+    }
+}
+pub fn parse_mld<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("Mld", arr, Mld::size()) {
+        return accept(arr);
+    }
+    let mut pkt = accept(&arr[Mld::size()..]);
+    pkt.insert(MldSlice::from(&arr[0..Mld::size()]));
+    pkt
+}
+pub fn parse_mld_v2_report<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("MldV2Report", arr, MldV2Report::size()) {
+        return accept(arr);
+    }
+    let mut pkt = accept(&arr[MldV2Report::size()..]);
+    pkt.insert(MldV2ReportSlice::from(&arr[0..MldV2Report::size()]));
+    pkt
+}
 pub fn parse_tcp<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("TCP", arr, TCP::size()) {
+        return accept(arr);
+    }
     let mut pkt = accept(&arr[TCP::size()..]);
     pkt.insert(TCPSlice::from(&arr[0..TCP::size()]));
     pkt
 }
 pub fn parse_udp<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("UDP", arr, UDP::size()) {
+        return accept(arr);
+    }
     let udp = UDPSlice::from(&arr[0..UDP::size()]);
-    let dst = udp.dst() as u16;
-    let mut pkt = match dst {
-        UDP_PORT_VXLAN => parse_vxlan(&arr[UDP::size()..]),
-        _ => accept(&arr[UDP::size()..]),
+    let rest = &arr[UDP::size()..];
+    let mut pkt = match parse_bound("UDP", "dst", udp.dst(), rest) {
+        Some(pkt) => pkt,
+        None => parse_udp_dst(udp.dst(), rest),
     };
     pkt.insert(udp);
     pkt
 }
+/// Parse the layer after the destination port of a UDP header
+fn parse_udp_dst(dst: u64, arr: &[u8]) -> PacketSlice<'_> {
+    match dst as u16 {
+        UDP_PORT_VXLAN => parse_vxlan(arr),
+        UDP_PORT_L2TP => parse_l2tp(arr),
+        _ => accept(arr),
+    }
+}
+pub fn parse_udplite<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("UdpLite", arr, UdpLite::size()) {
+        return accept(arr);
+    }
+    let mut pkt = accept(&arr[UdpLite::size()..]);
+    pkt.insert(UdpLiteSlice::from(&arr[0..UdpLite::size()]));
+    pkt
+}
 pub fn parse_vxlan<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("Vxlan", arr, Vxlan::size()) {
+        return accept(arr);
+    }
     let mut pkt = parse_ethernet(&arr[Vxlan::size()..]);
     pkt.insert(VxlanSlice::from(&arr[0..Vxlan::size()]));
     pkt
 }
+pub fn parse_l2tp<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits("L2tp", arr, L2tp::size()) {
+        return accept(arr);
+    }
+    let l2tp = L2tpSlice::from(&arr[0..L2tp::size()]);
+    let v2 = l2tp.version() == L2TP_V2 as u64;
+    let v3_data = l2tp.version() == L2TP_V3 as u64 && !l2tp.t();
+    let tunnel_at = L2tp::size() + (v2 && l2tp.l()) as usize * L2tpLength::size();
+    let seq_at = tunnel_at + v2 as usize * L2tpTunnel::size();
+    let offset_at = seq_at + (v2 && l2tp.s()) as usize * L2tpSequence::size();
+    let end = offset_at
+        + (v2 && l2tp.o()) as usize * L2tpOffset::size()
+        + v3_data as usize * L2tpV3Session::size();
+    if !fits("L2tp", arr, end) {
+        return accept(arr);
+    }
+    let mut pkt = accept(&arr[end..]);
+    if v3_data {
+        pkt.insert(L2tpV3SessionSlice::from(&arr[L2tp::size()..end]));
+    }
+    if v2 && l2tp.o() {
+        pkt.insert(L2tpOffsetSlice::from(&arr[offset_at..end]));
+    }
+    if v2 && l2tp.s() {
+        pkt.insert(L2tpSequenceSlice::from(&arr[seq_at..offset_at]));
+    }
+    if v2 {
+        pkt.insert(L2tpTunnelSlice::from(&arr[tunnel_at..seq_at]));
+    }
+    if v2 && l2tp.l() {
+        pkt.insert(L2tpLengthSlice::from(&arr[L2tp::size()..tunnel_at]));
+    }
+    pkt.insert(l2tp);
+    pkt
+}
+/// Parse the header bound on the value of a demux field of a layer, None if there is none
+///
+/// A binding on the value takes precedence over the headers the parser knows.
+#[inline(always)]
+fn parse_bound<'a>(layer: &str, field: &str, value: u64, arr: &'a [u8]) -> Option<PacketSlice<'a>> {
+    let binding = bind::current().lookup(layer, field, value)?;
+    Some(parse_binding(binding, arr))
+}
+/// Parse a bound header, then the layer its next field selects as the layer it is bound on would
+fn parse_binding<'a>(binding: &Binding, arr: &'a [u8]) -> PacketSlice<'a> {
+    if !fits(binding.name, arr, binding.size) {
+        return accept(arr);
+    }
+    let hdr = (binding.slice)(&arr[..binding.size]);
+    let rest = &arr[binding.size..];
+    let mut pkt = match binding.next.and_then(|x| Some((x, hdr.get_field(x)?))) {
+        Some((field, value)) => match parse_bound(binding.name, field, value, rest) {
+            Some(pkt) => pkt,
+            None => parse_next(binding.lower, binding.field, value, rest),
+        },
+        None => accept(rest),
+    };
+    pkt.insert_boxed(hdr);
+    pkt
+}
+/// Parse the layer selected by the value of a demux field of a layer the parser knows
+fn parse_next<'a>(layer: &str, field: &str, value: u64, arr: &'a [u8]) -> PacketSlice<'a> {
+    match (layer, field) {
+        ("Ether" | "Vlan", "etype") => parse_etype(layer, value, arr),
+        ("GRE", "proto") => parse_gre_proto(value, arr),
+        ("IPv4", "protocol") => parse_ipv4_proto(value, arr),
+        ("IPv6", "next_hdr") => parse_ipv6_next_hdr(value, arr),
+        ("UDP", "dst") => parse_udp_dst(value, arr),
+        _ => accept(arr),
+    }
+}
 fn accept<'a>(arr: &'a [u8]) -> PacketSlice<'a> {
     let mut pkt = PacketSlice::new();
     pkt.set_payload(arr);
@@ -30,5 +30,203 @@
 //! * [`fast::parse_ipv6`] parses from the ipv6 header and below
 //! * [`fast::parse_gre`] parses from the gre header and below
 //!
+//! Layers too short for their header are left as payload and logged as a warning.
+//!
+//! ## Dissection depth
+//!
+//! [`Packet::parse_with_options`](crate::Packet::parse_with_options) stops the slow parser after
+//! a number of layers, after the first layer of a [`LayerKind`] or at the start of a tunnelled
+//! packet, leaving the bytes it does not dissect as payload. A filter reading the outer headers
+//! of encapsulated traffic does not pay for the inner ones, and
+//! [`Packet::parse_more`](crate::Packet::parse_more) dissects them later if needed, with the
+//! [bindings](crate::bind) given.
+//! [`Packet::dissect_with`](crate::Packet::dissect_with) takes the depth alone, as a
+//! [`DissectConfig`] naming the last layer to dissect.
+//!
+//! ```
+//! # use packet_rs::bind;
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::parser::{LayerKind, ParseOptions};
+//! # use packet_rs::Packet;
+//! let inner = create_tcp_packet(&Default::default());
+//! let v = create_vxlan_packet(&Default::default(), 2000, inner).to_vec();
+//! let opts = ParseOptions { stop_after: Some(LayerKind::Transport), ..Default::default() };
+//! let mut pkt = Packet::parse_with_options(&v, &opts);
+//! assert!(pkt.layer::<UDP>().is_some());
+//! assert!(pkt.layer::<Vxlan>().is_none());
+//! pkt.parse_more(bind::current());
+//! assert!(pkt.layer::<TCP>().is_some());
+//! ```
 pub mod fast;
 pub mod slow;
+
+use log::warn;
+
+use crate::bind::Bindings;
+
+/// The role of a layer, to stop the dissection after the first layer of a kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerKind {
+    /// Ethernet, 802.3, LLC, SNAP, VLAN and MPLS
+    Link,
+    /// IPv4, IPv6, ARP, ICMP, IGMP and MLD
+    Network,
+    /// TCP, UDP and UDP-Lite
+    Transport,
+    /// GRE, VXLAN, L2TP and ERSPAN, with their options
+    Tunnel,
+}
+
+impl LayerKind {
+    /// Return the kind of the header called name, None for the headers the crate does not know
+    pub fn of(name: &str) -> Option<LayerKind> {
+        let kind = match name {
+            "Ether" | "Dot3" | "LLC" | "SNAP" | "Vlan" | "MPLS" => LayerKind::Link,
+            "IPv4" | "IPv6" | "ARP" | "ICMP" | "Igmp" | "IgmpV3Report" | "Mld" | "MldV2Report" => {
+                LayerKind::Network
+            }
+            "TCP" | "UDP" | "UdpLite" => LayerKind::Transport,
+            "GRE" | "Vxlan" | "L2tp" | "ERSPAN2" | "ERSPAN3" => LayerKind::Tunnel,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// Limits of the dissection of the slow parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions<'a> {
+    /// Number of layers dissected at most, a header with its options counting as one
+    pub max_layers: usize,
+    /// Stop after the first layer of this kind
+    pub stop_after: Option<LayerKind>,
+    /// Stop after the first layer with this header name
+    pub stop_at: Option<&'a str>,
+    /// Dissect the options of GRE, ERSPAN and L2TP, else they are left as payload with the
+    /// layers after them
+    pub parse_options: bool,
+    /// Dissect the packet carried by a tunnel or by an IP layer
+    pub parse_tunnels: bool,
+    /// Keep the parsed bytes, serialized as they are until the packet is modified
+    pub keep_raw: bool,
+}
+
+impl Default for ParseOptions<'_> {
+    /// Dissect every layer, as [`slow::parse`] does
+    fn default() -> Self {
+        ParseOptions {
+            max_layers: usize::MAX,
+            stop_after: None,
+            stop_at: None,
+            parse_options: true,
+            parse_tunnels: true,
+            keep_raw: false,
+        }
+    }
+}
+
+/// Depth of the dissection of [`Packet::dissect_with`](crate::Packet::dissect_with)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DissectConfig<'a> {
+    /// Number of layers dissected at most, a header with its options counting as one
+    pub max_layers: usize,
+    /// Stop after the first layer with this header name, as `"IPv4"`
+    pub stop_at: Option<&'a str>,
+    /// Dissect the options of GRE, ERSPAN and L2TP
+    pub parse_options: bool,
+}
+
+impl Default for DissectConfig<'_> {
+    /// Dissect every layer
+    fn default() -> Self {
+        DissectConfig {
+            max_layers: usize::MAX,
+            stop_at: None,
+            parse_options: true,
+        }
+    }
+}
+
+impl<'a> From<DissectConfig<'a>> for ParseOptions<'a> {
+    fn from(config: DissectConfig<'a>) -> ParseOptions<'a> {
+        ParseOptions {
+            max_layers: config.max_layers,
+            stop_at: config.stop_at,
+            parse_options: config.parse_options,
+            ..Default::default()
+        }
+    }
+}
+
+/// Progress of a dissection against its options
+struct Dissector<'b> {
+    opts: ParseOptions<'b>,
+    /// Headers bound after the layers, see the bind module
+    bindings: &'b Bindings,
+    layers: usize,
+    /// A tunnel layer was dissected, the next layers are inner ones
+    tunnel: bool,
+    /// An IP layer was dissected, another one is tunnelled
+    ip: bool,
+    stopped: bool,
+}
+
+impl Dissector<'_> {
+    fn new<'b>(opts: &ParseOptions<'b>, bindings: &'b Bindings) -> Dissector<'b> {
+        Dissector {
+            opts: *opts,
+            bindings,
+            layers: 0,
+            tunnel: false,
+            ip: false,
+            stopped: false,
+        }
+    }
+    /// Return true if the layer is dissected, counting it
+    fn enter(&mut self, layer: &str) -> bool {
+        if self.stopped || self.layers >= self.opts.max_layers {
+            return false;
+        }
+        let ip = matches!(layer, "IPv4" | "IPv6");
+        if !self.opts.parse_tunnels && (self.tunnel || ip && self.ip) {
+            self.stopped = true;
+            return false;
+        }
+        self.note(layer);
+        self.layers += 1;
+        let kind = LayerKind::of(layer);
+        if kind.is_some() && kind == self.opts.stop_after || self.opts.stop_at == Some(layer) {
+            self.stopped = true;
+        }
+        true
+    }
+    /// Return true if the options present are left as payload, stopping the dissection
+    fn skip_options(&mut self, present: bool) -> bool {
+        if present && !self.opts.parse_options {
+            self.stopped = true;
+            return true;
+        }
+        false
+    }
+    /// Record a layer dissected before, without counting it
+    fn note(&mut self, layer: &str) {
+        self.tunnel |= LayerKind::of(layer) == Some(LayerKind::Tunnel);
+        self.ip |= matches!(layer, "IPv4" | "IPv6");
+    }
+    /// Return true if the layer is dissected and arr is long enough for its header
+    #[inline(always)]
+    fn fits(&mut self, layer: &str, arr: &[u8], needed: usize) -> bool {
+        self.enter(layer) && fits(layer, arr, needed)
+    }
+}
+
+/// Return false and log a warning if arr is too short for the header of the layer
+#[inline(always)]
+fn fits(layer: &str, arr: &[u8], needed: usize) -> bool {
+    if arr.len() < needed {
+        warn!(layer, expected = needed, actual = arr.len(); "truncated layer");
+        return false;
+    }
+    true
+}
@@ -1,125 +1,322 @@
+use log::debug;
+
+use super::{fits, DissectConfig, Dissector, ParseOptions};
+use crate::alloc_prelude::*;
+use crate::bind::{self, Binding, Bindings};
 use crate::headers::*;
 use crate::types::*;
 use crate::Packet;
 
-pub fn parse(arr: &[u8]) -> Packet {
+/// Public parsers, dissecting every layer from the header they are named after
+macro_rules! parsers {
+    ($($parse: ident => $dissect: ident),* $(,)?) => {
+        $(
+            pub fn $parse(arr: &[u8]) -> Packet {
+                $dissect(&mut Dissector::new(&ParseOptions::default(), bind::current()), arr)
+            }
+        )*
+    };
+}
+
+parsers!(
+    parse => dissect,
+    parse_dot3 => dissect_dot3,
+    parse_llc => dissect_llc,
+    parse_snap => dissect_snap,
+    parse_ethernet => dissect_ethernet,
+    parse_vlan => dissect_vlan,
+    parse_mpls => dissect_mpls,
+    parse_mpls_bos => dissect_mpls_bos,
+    parse_ipv4 => dissect_ipv4,
+    parse_ipv6 => dissect_ipv6,
+    parse_gre => dissect_gre,
+    parse_erspan2 => dissect_erspan2,
+    parse_erspan3 => dissect_erspan3,
+    parse_arp => dissect_arp,
+    parse_icmp => dissect_icmp,
+    parse_icmpv6 => dissect_icmpv6,
+    parse_igmp => dissect_igmp,
+    parse_mld => dissect_mld,
+    parse_mld_v2_report => dissect_mld_v2_report,
+    parse_tcp => dissect_tcp,
+    parse_udp => dissect_udp,
+    parse_udplite => dissect_udplite,
+    parse_vxlan => dissect_vxlan,
+    parse_l2tp => dissect_l2tp,
+);
+
+/// Parse a byte stream starting from the header with the given name
+///
+/// Header names the parser cannot start from leave the whole byte stream as payload.
+pub fn parse_from(name: &str, arr: &[u8]) -> Packet {
+    dissect_from(
+        &mut Dissector::new(&ParseOptions::default(), bind::current()),
+        name,
+        arr,
+    )
+}
+
+impl Packet {
+    /// Parse a packet from its first header, within the limits of opts
+    ///
+    /// The bytes after the last layer dissected are left as payload. See the
+    /// [parser](crate::parser#dissection-depth) module.
+    pub fn parse_with_options(arr: &[u8], opts: &ParseOptions) -> Packet {
+        let mut pkt = dissect(&mut Dissector::new(opts, bind::current()), arr);
+        if opts.keep_raw {
+            pkt.raw = Some(Arc::from(arr));
+        }
+        pkt
+    }
+    /// Parse a packet from its first header, within the depth of config
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::parser::DissectConfig;
+    /// # use packet_rs::Packet;
+    /// let v = create_tcp_packet(&Default::default()).to_vec();
+    /// let config = DissectConfig { stop_at: Some("IPv4"), ..Default::default() };
+    /// let pkt = Packet::dissect_with(&v, &config);
+    /// assert!(pkt.layer::<IPv4>().is_some());
+    /// assert!(pkt.layer::<TCP>().is_none());
+    /// assert_eq!(pkt.to_vec(), v);
+    /// ```
+    pub fn dissect_with(arr: &[u8], config: &DissectConfig) -> Packet {
+        Packet::parse_with_options(arr, &ParseOptions::from(*config))
+    }
+    /// Resume the dissection of a packet parsed within limits, dissecting every layer left with
+    /// the headers bound in bindings
+    ///
+    /// The last layer is dissected again from its current bytes, followed by the payload, and
+    /// the layers found replace it. A packet whose last layer the parser cannot start from is
+    /// left as it is. [`bind::current`] holds the bindings the parsers use.
+    pub fn parse_more(&mut self, bindings: &Bindings) {
+        self.parse_more_with(&ParseOptions::default(), bindings);
+    }
+    /// Resume the dissection of a packet parsed within limits, in the limits of opts
+    ///
+    /// As [`Packet::parse_more`], [`ParseOptions::max_layers`] counts the layers added.
+    pub fn parse_more_with(&mut self, opts: &ParseOptions, bindings: &Bindings) {
+        if self.payload.is_empty() {
+            return;
+        }
+        let start = match self.hdrs.len() {
+            0 => None,
+            n => Some(group_start(&self.hdrs, n - 1)),
+        };
+        let mut d = Dissector::new(opts, bindings);
+        let mut v = Vec::new();
+        let pkt = match start {
+            Some(start) => {
+                self.hdrs[..start].iter().for_each(|x| d.note(x.name()));
+                d.opts.max_layers = d.opts.max_layers.saturating_add(1);
+                for hdr in &self.hdrs[start..] {
+                    v.extend(hdr.to_vec());
+                }
+                v.extend_from_slice(&self.payload);
+                let pkt = dissect_from(&mut d, self.hdrs[start].name(), &v);
+                match pkt.hdrs.first() {
+                    Some(x) if x.name() == self.hdrs[start].name() => (),
+                    _ => return,
+                }
+                self.hdrs.truncate(start);
+                pkt
+            }
+            None => dissect(&mut d, &self.payload),
+        };
+        // the bytes are the same, the length and raw bytes stay valid
+        self.hdrs.extend(pkt.hdrs);
+        self.payload = pkt.payload;
+    }
+}
+
+/// Index of the header the options at index belong to
+pub(crate) fn group_start(hdrs: &[Box<dyn Header>], mut index: usize) -> usize {
+    while index > 0 {
+        match hdrs[index].name() {
+            "GREChksumOffset" | "GREKey" | "GRESequenceNum" | "ERSPANPLATFORM" => index -= 1,
+            "L2tpLength" | "L2tpTunnel" | "L2tpSequence" | "L2tpOffset" | "L2tpV3Session" => {
+                index -= 1
+            }
+            _ => break,
+        }
+    }
+    index
+}
+
+fn dissect(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !fits("Ether", arr, Ether::size()) {
+        return accept(arr);
+    }
     let length: u16 = ((arr[12] as u16) << 8) | arr[13] as u16;
     if length < 1500 {
-        parse_dot3(arr)
+        dissect_dot3(d, arr)
     } else {
-        parse_ethernet(arr)
+        dissect_ethernet(d, arr)
     }
 }
-pub fn parse_dot3(arr: &[u8]) -> Packet {
+fn dissect_dot3(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("Dot3", arr, Dot3::size()) {
+        return accept(arr);
+    }
     let dot3 = Dot3::from(arr[0..Dot3::size()].to_vec());
-    let mut pkt = parse_llc(&arr[Dot3::size()..]);
+    let mut pkt = dissect_llc(d, &arr[Dot3::size()..]);
     pkt.insert(dot3);
     pkt
 }
-pub fn parse_llc(arr: &[u8]) -> Packet {
+fn dissect_llc(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("LLC", arr, LLC::size()) {
+        return accept(arr);
+    }
     let llc = LLC::from(arr[0..LLC::size()].to_vec());
     let mut pkt = if arr[0] == 0xAA && arr[1] == 0xAA && arr[2] == 0x03 {
-        parse_snap(&arr[LLC::size()..])
+        dissect_snap(d, &arr[LLC::size()..])
     } else {
         accept(&arr[LLC::size()..])
     };
     pkt.insert(llc);
     pkt
 }
-pub fn parse_snap(arr: &[u8]) -> Packet {
+fn dissect_snap(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("SNAP", arr, SNAP::size()) {
+        return accept(arr);
+    }
     let snap = SNAP::from(arr[0..SNAP::size()].to_vec());
     let mut pkt = accept(&arr[SNAP::size()..]);
     pkt.insert(snap);
     pkt
 }
-pub fn parse_ethernet(arr: &[u8]) -> Packet {
+fn dissect_ethernet(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("Ether", arr, Ether::size()) {
+        return accept(arr);
+    }
     let eth = Ether::from(arr[0..Ether::size()].to_vec());
-    let etype = EtherType::try_from(eth.etype() as u16);
-    let mut pkt = match etype {
-        Ok(EtherType::DOT1Q) => parse_vlan(&arr[Ether::size()..]),
-        Ok(EtherType::ARP) => parse_arp(&arr[Ether::size()..]),
-        Ok(EtherType::IPV4) => parse_ipv4(&arr[Ether::size()..]),
-        Ok(EtherType::IPV6) => parse_ipv6(&arr[Ether::size()..]),
-        Ok(EtherType::MPLS) => parse_mpls(&arr[Ether::size()..]),
-        _ => accept(&arr[Ether::size()..]),
-    };
+    let mut pkt = dissect_next(d, "Ether", "etype", eth.etype(), &arr[Ether::size()..]);
     pkt.insert(eth);
     pkt
 }
-pub fn parse_vlan(arr: &[u8]) -> Packet {
+fn dissect_vlan(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("Vlan", arr, Vlan::size()) {
+        return accept(arr);
+    }
     let vlan = Vlan::from(arr[0..Vlan::size()].to_vec());
-    let etype = EtherType::try_from(vlan.etype() as u16);
-    let mut pkt = match etype {
-        Ok(EtherType::DOT1Q) => parse_vlan(&arr[Vlan::size()..]),
-        Ok(EtherType::ARP) => parse_arp(&arr[Vlan::size()..]),
-        Ok(EtherType::IPV4) => parse_ipv4(&arr[Vlan::size()..]),
-        Ok(EtherType::IPV6) => parse_ipv6(&arr[Vlan::size()..]),
-        Ok(EtherType::MPLS) => parse_mpls(&arr[Vlan::size()..]),
-        _ => accept(&arr[Vlan::size()..]),
-    };
+    let mut pkt = dissect_next(d, "Vlan", "etype", vlan.etype(), &arr[Vlan::size()..]);
     pkt.insert(vlan);
     pkt
 }
-pub fn parse_mpls(arr: &[u8]) -> Packet {
+/// Parse the layer after an ethertype held by a layer
+fn dissect_etype(d: &mut Dissector, layer: &str, etype: u64, arr: &[u8]) -> Packet {
+    match EtherType::try_from(etype as u16) {
+        Ok(EtherType::DOT1Q) => dissect_vlan(d, arr),
+        Ok(EtherType::ARP) => dissect_arp(d, arr),
+        Ok(EtherType::IPV4) => dissect_ipv4(d, arr),
+        Ok(EtherType::IPV6) => dissect_ipv6(d, arr),
+        Ok(EtherType::MPLS) => dissect_mpls(d, arr),
+        _ => {
+            debug!(layer, etype; "unknown ethertype");
+            accept(arr)
+        }
+    }
+}
+fn dissect_mpls(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("MPLS", arr, MPLS::size()) {
+        return accept(arr);
+    }
     let mpls = MPLS::from(arr[0..MPLS::size()].to_vec());
-    let bos = mpls.bos();
-    let mut pkt = if bos == 1 {
-        parse_mpls_bos(&arr[MPLS::size()..])
+    let mut pkt = if mpls.bos() {
+        dissect_mpls_payload(d, &arr[MPLS::size()..])
     } else {
-        parse_mpls(&arr[MPLS::size()..])
+        dissect_mpls(d, &arr[MPLS::size()..])
     };
     pkt.insert(mpls);
     pkt
 }
-pub fn parse_mpls_bos(arr: &[u8]) -> Packet {
+fn dissect_mpls_bos(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("MPLS", arr, MPLS::size()) {
+        return accept(arr);
+    }
     let mpls = MPLS::from(arr[0..MPLS::size()].to_vec());
-    let mut pkt = match IpType::try_from(arr[MPLS::size()] >> 4 & 0xf) {
-        Ok(IpType::V4) => parse_ipv4(&arr[MPLS::size()..]),
-        Ok(IpType::V6) => parse_ipv6(&arr[MPLS::size()..]),
-        _ => parse_ethernet(&arr[MPLS::size()..]),
-    };
+    let mut pkt = dissect_mpls_payload(d, &arr[MPLS::size()..]);
     pkt.insert(mpls);
     pkt
 }
-pub fn parse_ipv4(arr: &[u8]) -> Packet {
+/// Parse the packet under the bottom of the MPLS stack from its IP version
+fn dissect_mpls_payload(d: &mut Dissector, arr: &[u8]) -> Packet {
+    match IpType::try_from(arr.first().map_or(0, |b| b >> 4 & 0xf)) {
+        Ok(IpType::V4) => dissect_ipv4(d, arr),
+        Ok(IpType::V6) => dissect_ipv6(d, arr),
+        _ => dissect_ethernet(d, arr),
+    }
+}
+fn dissect_ipv4(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("IPv4", arr, IPv4::size()) {
+        return accept(arr);
+    }
     let ipv4 = IPv4::from(arr[0..IPv4::size()].to_vec());
-    let proto = IpProtocol::try_from(ipv4.protocol() as u8);
-    let mut pkt = match proto {
-        Ok(IpProtocol::ICMP) => parse_icmp(&arr[IPv4::size()..]),
-        Ok(IpProtocol::IPIP) => parse_ipv4(&arr[IPv4::size()..]),
-        Ok(IpProtocol::TCP) => parse_tcp(&arr[IPv4::size()..]),
-        Ok(IpProtocol::UDP) => parse_udp(&arr[IPv4::size()..]),
-        Ok(IpProtocol::IPV6) => parse_ipv6(&arr[IPv4::size()..]),
-        Ok(IpProtocol::GRE) => parse_gre(&arr[IPv4::size()..]),
-        _ => accept(&arr[IPv4::size()..]),
-    };
+    let mut pkt = dissect_next(d, "IPv4", "protocol", ipv4.protocol(), &arr[IPv4::size()..]);
     pkt.insert(ipv4);
     pkt
 }
-pub fn parse_ipv6(arr: &[u8]) -> Packet {
+/// Parse the layer after the protocol of an IPv4 header
+fn dissect_ipv4_proto(d: &mut Dissector, proto: u64, arr: &[u8]) -> Packet {
+    match IpProtocol::try_from(proto as u8) {
+        Ok(IpProtocol::ICMP) => dissect_icmp(d, arr),
+        Ok(IpProtocol::IGMP) => dissect_igmp(d, arr),
+        Ok(IpProtocol::IPIP) => dissect_ipv4(d, arr),
+        Ok(IpProtocol::TCP) => dissect_tcp(d, arr),
+        Ok(IpProtocol::UDP) => dissect_udp(d, arr),
+        Ok(IpProtocol::UDPLITE) => dissect_udplite(d, arr),
+        Ok(IpProtocol::IPV6) => dissect_ipv6(d, arr),
+        Ok(IpProtocol::GRE) => dissect_gre(d, arr),
+        _ => accept(arr),
+    }
+}
+fn dissect_ipv6(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("IPv6", arr, IPv6::size()) {
+        return accept(arr);
+    }
     let ipv6 = IPv6::from(arr[0..IPv6::size()].to_vec());
-    let next_hdr = IpProtocol::try_from(ipv6.next_hdr() as u8);
-    let mut pkt = match next_hdr {
-        Ok(IpProtocol::ICMPV6) => parse_icmp(&arr[IPv6::size()..]),
-        Ok(IpProtocol::IPIP) => parse_ipv4(&arr[IPv6::size()..]),
-        Ok(IpProtocol::TCP) => parse_tcp(&arr[IPv6::size()..]),
-        Ok(IpProtocol::UDP) => parse_udp(&arr[IPv6::size()..]),
-        Ok(IpProtocol::IPV6) => parse_ipv6(&arr[IPv6::size()..]),
-        Ok(IpProtocol::GRE) => parse_gre(&arr[IPv6::size()..]),
-        _ => accept(&arr[IPv6::size()..]),
-    };
+    let mut pkt = dissect_next(d, "IPv6", "next_hdr", ipv6.next_hdr(), &arr[IPv6::size()..]);
     pkt.insert(ipv6);
     pkt
 }
-pub fn parse_gre(arr: &[u8]) -> Packet {
+/// Parse the layer after the next header of an IPv6 header
+fn dissect_ipv6_next_hdr(d: &mut Dissector, next_hdr: u64, arr: &[u8]) -> Packet {
+    match IpProtocol::try_from(next_hdr as u8) {
+        Ok(IpProtocol::ICMPV6) => dissect_icmpv6(d, arr),
+        Ok(IpProtocol::IPIP) => dissect_ipv4(d, arr),
+        Ok(IpProtocol::TCP) => dissect_tcp(d, arr),
+        Ok(IpProtocol::UDP) => dissect_udp(d, arr),
+        Ok(IpProtocol::UDPLITE) => dissect_udplite(d, arr),
+        Ok(IpProtocol::IPV6) => dissect_ipv6(d, arr),
+        Ok(IpProtocol::GRE) => dissect_gre(d, arr),
+        _ => accept(arr),
+    }
+}
+fn dissect_gre(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("GRE", arr, GRE::size()) {
+        return accept(arr);
+    }
     let gre = GRE::from(arr[0..GRE::size()].to_vec());
-    let proto = EtherType::try_from(gre.proto() as u16);
     let chksum_present = gre.chksum_present();
     let seqnum_present = gre.seqnum_present();
     let key_present = gre.key_present();
+    let options = chksum_present as usize * GREChksumOffset::size()
+        + key_present as usize * GREKey::size()
+        + seqnum_present as usize * GRESequenceNum::size();
+    if d.skip_options(options > 0) {
+        let mut pkt = accept(&arr[GRE::size()..]);
+        pkt.insert(gre);
+        return pkt;
+    }
+    if !fits("GRE", arr, GRE::size() + options) {
+        return accept(arr);
+    }
     let mut offset = 0;
     offset += GRE::size();
-    let gco = if chksum_present == 1 {
+    let gco = if chksum_present {
         let p = Some(GREChksumOffset::from(
             arr[offset..offset + GREChksumOffset::size()].to_vec(),
         ));
@@ -128,14 +325,14 @@ pub fn parse_gre(arr: &[u8]) -> Packet {
     } else {
         None
     };
-    let gk = if key_present == 1 {
+    let gk = if key_present {
         let p = Some(GREKey::from(arr[offset..offset + GREKey::size()].to_vec()));
         offset += GREKey::size();
         p
     } else {
         None
     };
-    let gsn = if seqnum_present == 1 {
+    let gsn = if seqnum_present {
         let p = Some(GRESequenceNum::from(
             arr[offset..offset + GRESequenceNum::size()].to_vec(),
         ));
@@ -144,37 +341,60 @@ pub fn parse_gre(arr: &[u8]) -> Packet {
     } else {
         None
     };
-    let mut pkt = match proto {
-        Ok(EtherType::IPV4) => parse_ipv4(&arr[offset..]),
-        Ok(EtherType::IPV6) => parse_ipv6(&arr[offset..]),
-        Ok(EtherType::ERSPANII) => parse_erspan2(&arr[offset..]),
-        Ok(EtherType::ERSPANIII) => parse_erspan3(&arr[offset..]),
-        _ => accept(&arr[offset..]),
-    };
-    if let Some(p) = gco {
+    let mut pkt = dissect_next(d, "GRE", "proto", gre.proto(), &arr[offset..]);
+    // inserted innermost first, to keep the order of the options on the wire
+    if let Some(p) = gsn {
         pkt.insert(p);
     }
     if let Some(p) = gk {
         pkt.insert(p);
     }
-    if let Some(p) = gsn {
+    if let Some(p) = gco {
         pkt.insert(p);
     }
     pkt.insert(gre);
     pkt
 }
-pub fn parse_erspan2(arr: &[u8]) -> Packet {
+/// Parse the layer after the protocol of a GRE header
+fn dissect_gre_proto(d: &mut Dissector, proto: u64, arr: &[u8]) -> Packet {
+    match EtherType::try_from(proto as u16) {
+        Ok(EtherType::IPV4) => dissect_ipv4(d, arr),
+        Ok(EtherType::IPV6) => dissect_ipv6(d, arr),
+        Ok(EtherType::ERSPANII) => dissect_erspan2(d, arr),
+        Ok(EtherType::ERSPANIII) => dissect_erspan3(d, arr),
+        _ => {
+            debug!(layer = "GRE", etype = proto; "unknown ethertype");
+            accept(arr)
+        }
+    }
+}
+fn dissect_erspan2(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("ERSPAN2", arr, ERSPAN2::size()) {
+        return accept(arr);
+    }
     let erspan2 = ERSPAN2::from(arr[0..ERSPAN2::size()].to_vec());
-    let mut pkt = parse_ethernet(&arr[ERSPAN2::size()..]);
+    let mut pkt = dissect_ethernet(d, &arr[ERSPAN2::size()..]);
     pkt.insert(erspan2);
     pkt
 }
-pub fn parse_erspan3(arr: &[u8]) -> Packet {
+fn dissect_erspan3(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("ERSPAN3", arr, ERSPAN3::size()) {
+        return accept(arr);
+    }
     let erspan3 = ERSPAN3::from(arr[0..ERSPAN3::size()].to_vec());
     let o = erspan3.o();
+    let needed = ERSPAN3::size() + o as usize * ERSPANPLATFORM::size();
+    if d.skip_options(o) {
+        let mut pkt = accept(&arr[ERSPAN3::size()..]);
+        pkt.insert(erspan3);
+        return pkt;
+    }
+    if !fits("ERSPAN3", arr, needed) {
+        return accept(arr);
+    }
     let mut offset = 0;
     offset += ERSPAN3::size();
-    let platform = if o == 1 {
+    let platform = if o {
         let p = Some(ERSPANPLATFORM::from(
             arr[offset..offset + ERSPANPLATFORM::size()].to_vec(),
         ));
@@ -183,43 +403,215 @@ pub fn parse_erspan3(arr: &[u8]) -> Packet {
     } else {
         None
     };
-    let mut pkt = parse_ethernet(&arr[offset..]);
+    let mut pkt = dissect_ethernet(d, &arr[offset..]);
     if let Some(p) = platform {
         pkt.insert(p);
     }
     pkt.insert(erspan3);
     pkt
 }
-pub fn parse_arp(arr: &[u8]) -> Packet {
+fn dissect_arp(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("ARP", arr, ARP::size()) {
+        return accept(arr);
+    }
     let mut pkt = accept(&arr[ARP::size()..]);
     pkt.insert(ARP::from(arr[0..ARP::size()].to_vec()));
     pkt
 }
-pub fn parse_icmp(arr: &[u8]) -> Packet {
+fn dissect_icmp(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("ICMP", arr, ICMP::size()) {
+        return accept(arr);
+    }
     let mut pkt = accept(&arr[ICMP::size()..]);
     pkt.insert(ICMP::from(arr[0..ICMP::size()].to_vec()));
     pkt
 }
-pub fn parse_tcp(arr: &[u8]) -> Packet {
+fn dissect_icmpv6(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !fits("ICMP", arr, ICMP::size()) {
+        return accept(arr);
+    }
+    match arr[0] {
+        MLD_QUERY | MLD_REPORT | MLD_DONE => dissect_mld(d, arr),
+        MLD_V2_REPORT => dissect_mld_v2_report(d, arr),
+        _ => dissect_icmp(d, arr),
+    }
+}
+fn dissect_igmp(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("Igmp", arr, Igmp::size()) {
+        return accept(arr);
+    }
+    if arr[0] == IGMP_V3_REPORT {
+        let mut pkt = accept(&arr[IgmpV3Report::size()..]);
+        pkt.insert(IgmpV3Report::from(arr[0..IgmpV3Report::size()].to_vec()));
+        pkt
+    } else {
+        let mut pkt = accept(&arr[Igmp::size()..]);
+        pkt.insert(Igmp::from(arr[0..Igmp::size()].to_vec()));
+        pkt
+    }
+}
+fn dissect_mld(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("Mld", arr, Mld::size()) {
+        return accept(arr);
+    }
+    let mut pkt = accept(&arr[Mld::size()..]);
+    pkt.insert(Mld::from(arr[0..Mld::size()].to_vec()));
+    pkt
+}
+fn dissect_mld_v2_report(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("MldV2Report", arr, MldV2Report::size()) {
+        return accept(arr);
+    }
+    let mut pkt = accept(&arr[MldV2Report::size()..]);
+    pkt.insert(MldV2Report::from(arr[0..MldV2Report::size()].to_vec()));
+    pkt
+}
+fn dissect_tcp(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("TCP", arr, TCP::size()) {
+        return accept(arr);
+    }
     let mut pkt = accept(&arr[TCP::size()..]);
     pkt.insert(TCP::from(arr[0..TCP::size()].to_vec()));
     pkt
 }
-pub fn parse_udp(arr: &[u8]) -> Packet {
+fn dissect_udp(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("UDP", arr, UDP::size()) {
+        return accept(arr);
+    }
     let udp = UDP::from(arr[0..UDP::size()].to_vec());
-    let dst = udp.dst() as u16;
-    let mut pkt = match dst {
-        UDP_PORT_VXLAN => parse_vxlan(&arr[UDP::size()..]),
-        _ => accept(&arr[UDP::size()..]),
-    };
+    let mut pkt = dissect_next(d, "UDP", "dst", udp.dst(), &arr[UDP::size()..]);
     pkt.insert(udp);
     pkt
 }
-pub fn parse_vxlan(arr: &[u8]) -> Packet {
-    let mut pkt = parse_ethernet(&arr[Vxlan::size()..]);
+/// Parse the layer after the destination port of a UDP header
+fn dissect_udp_dst(d: &mut Dissector, dst: u64, arr: &[u8]) -> Packet {
+    match dst as u16 {
+        UDP_PORT_VXLAN => dissect_vxlan(d, arr),
+        UDP_PORT_L2TP => dissect_l2tp(d, arr),
+        _ => accept(arr),
+    }
+}
+fn dissect_udplite(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("UdpLite", arr, UdpLite::size()) {
+        return accept(arr);
+    }
+    let mut pkt = accept(&arr[UdpLite::size()..]);
+    pkt.insert(UdpLite::from(arr[0..UdpLite::size()].to_vec()));
+    pkt
+}
+fn dissect_vxlan(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("Vxlan", arr, Vxlan::size()) {
+        return accept(arr);
+    }
+    let mut pkt = dissect_ethernet(d, &arr[Vxlan::size()..]);
     pkt.insert(Vxlan::from(arr[0..Vxlan::size()].to_vec()));
     pkt
 }
+fn dissect_l2tp(d: &mut Dissector, arr: &[u8]) -> Packet {
+    if !d.fits("L2tp", arr, L2tp::size()) {
+        return accept(arr);
+    }
+    let l2tp = L2tp::from(arr[0..L2tp::size()].to_vec());
+    let v2 = l2tp.version() == L2TP_V2 as u64;
+    let v3_data = l2tp.version() == L2TP_V3 as u64 && !l2tp.t();
+    let tunnel_at = L2tp::size() + (v2 && l2tp.l()) as usize * L2tpLength::size();
+    let seq_at = tunnel_at + v2 as usize * L2tpTunnel::size();
+    let offset_at = seq_at + (v2 && l2tp.s()) as usize * L2tpSequence::size();
+    let end = offset_at
+        + (v2 && l2tp.o()) as usize * L2tpOffset::size()
+        + v3_data as usize * L2tpV3Session::size();
+    if d.skip_options(end > L2tp::size()) {
+        let mut pkt = accept(&arr[L2tp::size()..]);
+        pkt.insert(l2tp);
+        return pkt;
+    }
+    if !fits("L2tp", arr, end) {
+        return accept(arr);
+    }
+    // the offset padding and the PPP frame or control message are left as payload
+    let mut pkt = accept(&arr[end..]);
+    if v3_data {
+        pkt.insert(L2tpV3Session::from(arr[L2tp::size()..end].to_vec()));
+    }
+    if v2 && l2tp.o() {
+        pkt.insert(L2tpOffset::from(arr[offset_at..end].to_vec()));
+    }
+    if v2 && l2tp.s() {
+        pkt.insert(L2tpSequence::from(arr[seq_at..offset_at].to_vec()));
+    }
+    if v2 {
+        pkt.insert(L2tpTunnel::from(arr[tunnel_at..seq_at].to_vec()));
+    }
+    if v2 && l2tp.l() {
+        pkt.insert(L2tpLength::from(arr[L2tp::size()..tunnel_at].to_vec()));
+    }
+    pkt.insert(l2tp);
+    pkt
+}
+/// Dissect a byte stream starting from the header with the given name
+fn dissect_from(d: &mut Dissector, name: &str, arr: &[u8]) -> Packet {
+    match name {
+        "Ether" => dissect_ethernet(d, arr),
+        "Dot3" => dissect_dot3(d, arr),
+        "LLC" => dissect_llc(d, arr),
+        "SNAP" => dissect_snap(d, arr),
+        "Vlan" => dissect_vlan(d, arr),
+        "MPLS" => dissect_mpls(d, arr),
+        "IPv4" => dissect_ipv4(d, arr),
+        "IPv6" => dissect_ipv6(d, arr),
+        "GRE" => dissect_gre(d, arr),
+        "ERSPAN2" => dissect_erspan2(d, arr),
+        "ERSPAN3" => dissect_erspan3(d, arr),
+        "ARP" => dissect_arp(d, arr),
+        "ICMP" => dissect_icmp(d, arr),
+        "Igmp" | "IgmpV3Report" => dissect_igmp(d, arr),
+        "Mld" => dissect_mld(d, arr),
+        "MldV2Report" => dissect_mld_v2_report(d, arr),
+        "TCP" => dissect_tcp(d, arr),
+        "UDP" => dissect_udp(d, arr),
+        "UdpLite" => dissect_udplite(d, arr),
+        "Vxlan" => dissect_vxlan(d, arr),
+        "L2tp" => dissect_l2tp(d, arr),
+        name => match d.bindings.find(name) {
+            Some(binding) => dissect_bound(d, binding, arr),
+            None => accept(arr),
+        },
+    }
+}
+/// Parse the layer selected by the value of a demux field of a layer
+///
+/// A binding on the value takes precedence over the headers the parser knows.
+fn dissect_next(d: &mut Dissector, layer: &str, field: &str, value: u64, arr: &[u8]) -> Packet {
+    if let Some(binding) = d.bindings.lookup(layer, field, value) {
+        return dissect_bound(d, binding, arr);
+    }
+    match (layer, field) {
+        ("Ether" | "Vlan", "etype") => dissect_etype(d, layer, value, arr),
+        ("GRE", "proto") => dissect_gre_proto(d, value, arr),
+        ("IPv4", "protocol") => dissect_ipv4_proto(d, value, arr),
+        ("IPv6", "next_hdr") => dissect_ipv6_next_hdr(d, value, arr),
+        ("UDP", "dst") => dissect_udp_dst(d, value, arr),
+        _ => accept(arr),
+    }
+}
+/// Parse a bound header, then the layer its next field selects as the layer it is bound on would
+fn dissect_bound(d: &mut Dissector, binding: &Binding, arr: &[u8]) -> Packet {
+    if !d.fits(binding.name, arr, binding.size) {
+        return accept(arr);
+    }
+    let hdr = (binding.from)(&arr[..binding.size]);
+    let rest = &arr[binding.size..];
+    let mut pkt = match binding.next.and_then(|x| Some((x, hdr.get_field(x)?))) {
+        Some((field, value)) => match d.bindings.lookup(binding.name, field, value) {
+            Some(next) => dissect_bound(d, next, rest),
+            None => dissect_next(d, binding.lower, binding.field, value, rest),
+        },
+        None => accept(rest),
+    };
+    pkt.hdrs.insert(0, hdr);
+    pkt.invalidate();
+    pkt
+}
 fn accept(arr: &[u8]) -> Packet {
     let mut pkt = Packet::new();
     pkt.set_payload(arr);
@@ -0,0 +1,121 @@
+//! # Payload pattern generators and verifiers
+//!
+//! Each generator has a matching verifier returning the offset of the first corrupted byte. The
+//! verifiers only check the bytes they are given, so a capture truncated by snaplen is checked
+//! up to its captured length.
+//!
+//! ```
+//! # use packet_rs::payload;
+//! let mut data = payload::incrementing(64);
+//! assert_eq!(payload::verify_incrementing(&data), None);
+//! data[10] ^= 0x4;
+//! assert_eq!(payload::verify_incrementing(&data), Some(10));
+//! // truncated capture
+//! assert_eq!(payload::verify_incrementing(&data[..8]), None);
+//! ```
+
+use crate::alloc_prelude::*;
+use crate::icmp::inet_checksum;
+use crate::Packet;
+
+/// Number of payload bytes used by [`stamp_sequence`], a u64 sequence number and a u16 checksum
+pub const SEQUENCE_LEN: usize = 10;
+
+const ASCII_FIRST: u8 = b' ';
+const ASCII_LAST: u8 = b'~';
+
+/// Bytes 0, 1, 2, ... wrapping at 255
+pub fn incrementing(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+/// The same byte repeated
+pub fn repeat(byte: u8, len: usize) -> Vec<u8> {
+    vec![byte; len]
+}
+
+/// Pseudo random bytes, the same seed always returns the same bytes
+pub fn random_seeded(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    let mut v = Vec::with_capacity(len + 8);
+    while v.len() < len {
+        v.extend_from_slice(&splitmix64(&mut state).to_be_bytes());
+    }
+    v.truncate(len);
+    v
+}
+
+/// Printable ascii characters from ' ' to '~', cycling
+pub fn ascii_cycle(len: usize) -> Vec<u8> {
+    let n = (ASCII_LAST - ASCII_FIRST + 1) as usize;
+    (0..len).map(|i| ASCII_FIRST + (i % n) as u8).collect()
+}
+
+/// Return the offset of the first byte not matching [`incrementing`]
+pub fn verify_incrementing(data: &[u8]) -> Option<usize> {
+    first_mismatch(&incrementing(data.len()), data)
+}
+
+/// Return the offset of the first byte not matching [`repeat`]
+pub fn verify_repeat(byte: u8, data: &[u8]) -> Option<usize> {
+    data.iter().position(|b| *b != byte)
+}
+
+/// Return the offset of the first byte not matching [`random_seeded`]
+pub fn verify_random_seeded(seed: u64, data: &[u8]) -> Option<usize> {
+    first_mismatch(&random_seeded(seed, data.len()), data)
+}
+
+/// Return the offset of the first byte not matching [`ascii_cycle`]
+pub fn verify_ascii_cycle(data: &[u8]) -> Option<usize> {
+    first_mismatch(&ascii_cycle(data.len()), data)
+}
+
+/// Return the offset of the first byte in data differing from expected
+///
+/// Only the common prefix is compared.
+pub fn first_mismatch(expected: &[u8], data: &[u8]) -> Option<usize> {
+    expected.iter().zip(data).position(|(e, d)| e != d)
+}
+
+/// Write a sequence number and its checksum into the packet payload at offset
+///
+/// The payload is zero padded if it is too short to hold the [`SEQUENCE_LEN`] bytes.
+pub fn stamp_sequence(pkt: &mut Packet, seq: u64, offset: usize) {
+    if pkt.payload.len() < offset + SEQUENCE_LEN {
+        pkt.payload.resize(offset + SEQUENCE_LEN, 0);
+        pkt.invalidate();
+    }
+    let stamp = &mut pkt.payload[offset..offset + SEQUENCE_LEN];
+    stamp[..8].copy_from_slice(&seq.to_be_bytes());
+    stamp[8..].copy_from_slice(&sequence_checksum(seq).to_be_bytes());
+}
+
+/// Read back a sequence number written by [`stamp_sequence`]
+///
+/// Returns None if the payload was truncated before the end of the stamp or if the checksum
+/// does not match.
+pub fn extract_sequence(pkt: &Packet, offset: usize) -> Option<u64> {
+    let stamp = pkt.payload.get(offset..offset + SEQUENCE_LEN)?;
+    let mut seq = [0; 8];
+    seq.copy_from_slice(&stamp[..8]);
+    let seq = u64::from_be_bytes(seq);
+    let chksum = ((stamp[8] as u16) << 8) | stamp[9] as u16;
+    if chksum == sequence_checksum(seq) {
+        Some(seq)
+    } else {
+        None
+    }
+}
+
+fn sequence_checksum(seq: u64) -> u16 {
+    inet_checksum(&seq.to_be_bytes())
+}
+
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,460 @@
+//! # pcap reader
+//!
+//! [`PcapReader`] parses a classic pcap capture from memory, so files loaded by a browser or
+//! embedded in a test work as well as files on disk. Both byte orders and the nanosecond
+//! timestamp variant are accepted. Records are dissected with the slow parser.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::pcap::*;
+//! let pkt = create_udp_packet(&Default::default());
+//! let data = write_pcap(&[pkt.to_vec()]);
+//! let reader = PcapReader::from_bytes(&data).unwrap();
+//! assert_eq!(reader.records().len(), 1);
+//! assert!(reader.records()[0].packet.compare(&pkt));
+//! ```
+//!
+//! With `std`, [`PcapReader::from_reader`] streams the records of a capture of any size from a
+//! file or any other reader, and [`process`] runs a closure on each record of a capture file with
+//! memory bounded by a few records. With the `rayon` feature, `par_process` does the same on the
+//! rayon thread pool.
+
+#[cfg(feature = "std")]
+use std::io::Read;
+
+use crate::alloc_prelude::*;
+use crate::filter::Filter;
+use crate::{parser, Packet, PacketError};
+
+/// Magic number of captures with microsecond timestamps
+pub const PCAP_MAGIC_USEC: u32 = 0xa1b2c3d4;
+/// Magic number of captures with nanosecond timestamps
+pub const PCAP_MAGIC_NSEC: u32 = 0xa1b23c4d;
+/// Link type of ethernet captures
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+const GLOBAL_HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+
+/// A packet of the capture with its timestamp
+pub struct PcapRecord {
+    pub ts_sec: u32,
+    /// Fraction of the second in nanoseconds, whatever the resolution of the capture
+    pub ts_nsec: u32,
+    /// Length of the packet on the wire, larger than the captured bytes if truncated
+    pub orig_len: u32,
+    pub packet: Packet,
+}
+
+/// Records of a pcap capture
+pub struct PcapReader {
+    linktype: u32,
+    snaplen: u32,
+    records: Vec<PcapRecord>,
+}
+
+fn invalid(offset: usize, reason: &str) -> PacketError {
+    PacketError::Parse {
+        layer: String::from("pcap"),
+        offset,
+        reason: String::from(reason),
+    }
+}
+
+/// Byte order and timestamp resolution of a capture
+#[derive(Clone, Copy)]
+struct Format {
+    big_endian: bool,
+    nsec: bool,
+}
+
+impl Format {
+    /// Read the magic number of a global header
+    fn parse(hdr: &[u8]) -> Result<Format, PacketError> {
+        let magic = u32::from_le_bytes([hdr[0], hdr[1], hdr[2], hdr[3]]);
+        let (big_endian, nsec) = match magic {
+            PCAP_MAGIC_USEC => (false, false),
+            PCAP_MAGIC_NSEC => (false, true),
+            x if x.swap_bytes() == PCAP_MAGIC_USEC => (true, false),
+            x if x.swap_bytes() == PCAP_MAGIC_NSEC => (true, true),
+            _ => return Err(invalid(0, "unknown magic number")),
+        };
+        Ok(Format { big_endian, nsec })
+    }
+    fn word(&self, b: &[u8], i: usize) -> u32 {
+        let v = [b[i], b[i + 1], b[i + 2], b[i + 3]];
+        match self.big_endian {
+            true => u32::from_be_bytes(v),
+            false => u32::from_le_bytes(v),
+        }
+    }
+    /// Dissect the bytes of a record with its record header
+    fn record(&self, rec: &[u8], bytes: &[u8]) -> PcapRecord {
+        let frac = self.word(rec, 4);
+        PcapRecord {
+            ts_sec: self.word(rec, 0),
+            ts_nsec: if self.nsec {
+                frac
+            } else {
+                frac.saturating_mul(1000)
+            },
+            orig_len: self.word(rec, 12),
+            packet: parser::slow::parse(bytes),
+        }
+    }
+}
+
+impl PcapReader {
+    /// Parse a capture held in memory
+    ///
+    /// Fails with [`PacketError::Parse`] at the offset of a truncated record or on an unknown
+    /// magic number.
+    pub fn from_bytes(data: &[u8]) -> Result<PcapReader, PacketError> {
+        let hdr = data
+            .get(..GLOBAL_HEADER_LEN)
+            .ok_or_else(|| invalid(0, "truncated global header"))?;
+        let format = Format::parse(hdr)?;
+        let mut reader = PcapReader {
+            snaplen: format.word(hdr, 16),
+            linktype: format.word(hdr, 20),
+            records: Vec::new(),
+        };
+        let mut offset = GLOBAL_HEADER_LEN;
+        while offset < data.len() {
+            let rec = data
+                .get(offset..offset + RECORD_HEADER_LEN)
+                .ok_or_else(|| invalid(offset, "truncated record header"))?;
+            let incl_len = format.word(rec, 8) as usize;
+            let start = offset + RECORD_HEADER_LEN;
+            // a corrupt length can wrap around on 32 bit targets
+            let end = start
+                .checked_add(incl_len)
+                .ok_or_else(|| invalid(offset, "truncated record"))?;
+            let bytes = data
+                .get(start..end)
+                .ok_or_else(|| invalid(offset, "truncated record"))?;
+            reader.records.push(format.record(rec, bytes));
+            offset = end;
+        }
+        Ok(reader)
+    }
+    /// Read a capture file
+    #[cfg(feature = "std")]
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<PcapReader, PacketError> {
+        PcapReader::from_bytes(&std::fs::read(path)?)
+    }
+    /// Stream the records of a capture from r, one at a time, through a buffer
+    ///
+    /// r can be a file, stdin or any other reader. Fails if the global header cannot be read.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read>(r: R) -> Result<PcapStream<std::io::BufReader<R>>, PacketError> {
+        PcapStream::new(std::io::BufReader::new(r))
+    }
+    /// Link type of the capture, [`LINKTYPE_ETHERNET`] for ethernet frames
+    pub fn linktype(&self) -> u32 {
+        self.linktype
+    }
+    /// Maximum number of bytes captured per packet
+    pub fn snaplen(&self) -> u32 {
+        self.snaplen
+    }
+    pub fn records(&self) -> &[PcapRecord] {
+        &self.records
+    }
+    /// Return the records selected by filter
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::filter::Filter;
+    /// # use packet_rs::pcap::*;
+    /// let tcp = create_tcp_packet(&Default::default());
+    /// let udp = create_udp_packet(&Default::default());
+    /// let reader = PcapReader::from_bytes(&write_pcap(&[tcp.to_vec(), udp.to_vec()])).unwrap();
+    /// let filter = Filter::compile("udp").unwrap();
+    /// assert_eq!(reader.filtered(&filter).count(), 1);
+    /// ```
+    pub fn filtered<'a>(&'a self, filter: &'a Filter) -> impl Iterator<Item = &'a PcapRecord> {
+        self.records.iter().filter(|x| filter.matches(&x.packet))
+    }
+    /// Consume the reader into its packets
+    pub fn into_packets(self) -> Vec<Packet> {
+        self.records.into_iter().map(|x| x.packet).collect()
+    }
+}
+
+impl IntoIterator for PcapReader {
+    type Item = PcapRecord;
+    type IntoIter = alloc::vec::IntoIter<PcapRecord>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.into_iter()
+    }
+}
+
+/// Bytes of a record not dissected yet
+#[cfg(feature = "std")]
+struct RawRecord {
+    hdr: [u8; RECORD_HEADER_LEN],
+    bytes: Vec<u8>,
+}
+
+/// Records of a pcap capture read one at a time
+///
+/// Only the record being read is held in memory. The iterator yields the records in order and
+/// stops after the first error, a failed read or a truncated record.
+#[cfg(feature = "std")]
+pub struct PcapStream<R> {
+    reader: R,
+    format: Format,
+    linktype: u32,
+    snaplen: u32,
+    /// Offset of the next record in the capture
+    offset: usize,
+    failed: bool,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> PcapStream<R> {
+    /// Read the global header of a capture from reader
+    ///
+    /// Records are read with small reads, wrap an unbuffered reader in a `BufReader`.
+    pub fn new(mut reader: R) -> Result<PcapStream<R>, PacketError> {
+        let mut hdr = [0; GLOBAL_HEADER_LEN];
+        if read_full(&mut reader, &mut hdr)? < GLOBAL_HEADER_LEN {
+            return Err(invalid(0, "truncated global header"));
+        }
+        let format = Format::parse(&hdr)?;
+        Ok(PcapStream {
+            reader,
+            format,
+            snaplen: format.word(&hdr, 16),
+            linktype: format.word(&hdr, 20),
+            offset: GLOBAL_HEADER_LEN,
+            failed: false,
+        })
+    }
+    /// Link type of the capture, [`LINKTYPE_ETHERNET`] for ethernet frames
+    pub fn linktype(&self) -> u32 {
+        self.linktype
+    }
+    /// Maximum number of bytes captured per packet
+    pub fn snaplen(&self) -> u32 {
+        self.snaplen
+    }
+    fn next_raw(&mut self) -> Option<Result<RawRecord, PacketError>> {
+        if self.failed {
+            return None;
+        }
+        let raw = self.read_raw().transpose();
+        self.failed = matches!(raw, Some(Err(_)));
+        raw
+    }
+    fn read_raw(&mut self) -> Result<Option<RawRecord>, PacketError> {
+        let mut hdr = [0; RECORD_HEADER_LEN];
+        match read_full(&mut self.reader, &mut hdr)? {
+            0 => return Ok(None),
+            RECORD_HEADER_LEN => (),
+            _ => return Err(invalid(self.offset, "truncated record header")),
+        }
+        let incl_len = self.format.word(&hdr, 8) as usize;
+        // a corrupt length must not allocate gigabytes before the read fails
+        let mut bytes = Vec::with_capacity(incl_len.min(1 << 16));
+        (&mut self.reader)
+            .take(incl_len as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() < incl_len {
+            return Err(invalid(self.offset, "truncated record"));
+        }
+        self.offset += RECORD_HEADER_LEN + incl_len;
+        Ok(Some(RawRecord { hdr, bytes }))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Iterator for PcapStream<R> {
+    type Item = Result<PcapRecord, PacketError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let format = self.format;
+        self.next_raw()
+            .map(|x| x.map(|raw| format.record(&raw.hdr, &raw.bytes)))
+    }
+}
+
+/// Read until buf is full or the end of the reader, returns the number of bytes read
+#[cfg(feature = "std")]
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(x) => n += x,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Run f on each record of a capture file in order, returns the number of records
+///
+/// Only one record is held in memory at a time, whatever the size of the file.
+/// ```
+/// # use packet_rs::builders::*;
+/// # use packet_rs::pcap::*;
+/// # let path = std::env::temp_dir().join("process_doctest.pcap");
+/// let pkt = create_udp_packet(&Default::default());
+/// std::fs::write(&path, write_pcap(&[pkt.to_vec(), pkt.to_vec()])).unwrap();
+/// let mut bytes = 0;
+/// let n = process(&path, |rec| bytes += rec.packet.len()).unwrap();
+/// assert_eq!((n, bytes), (2, 2 * pkt.len()));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[cfg(feature = "std")]
+pub fn process<P: AsRef<std::path::Path>>(
+    path: P,
+    mut f: impl FnMut(PcapRecord),
+) -> Result<usize, PacketError> {
+    let mut n = 0;
+    for record in PcapReader::from_reader(std::fs::File::open(path)?)? {
+        f(record?);
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// Dissect the records of a capture file on the rayon thread pool and fold them into
+/// accumulators
+///
+/// The records are read in order on one thread at a time and handed to the pool with
+/// `par_bridge`, so f sees them in no particular order. Each fold starts from `init()` and the
+/// accumulators are returned to be merged, at most a few records per thread are held in memory.
+/// Run it inside [`rayon::ThreadPool::install`] to pick the number of threads. A panic of f is
+/// resumed on the calling thread.
+/// ```
+/// # use packet_rs::builders::*;
+/// # use packet_rs::pcap::*;
+/// # let path = std::env::temp_dir().join("par_process_doctest.pcap");
+/// let pkt = create_udp_packet(&Default::default());
+/// std::fs::write(&path, write_pcap(&vec![pkt.to_vec(); 100])).unwrap();
+/// let counts = par_process(&path, || 0, |n, _rec| *n += 1).unwrap();
+/// assert_eq!(counts.iter().sum::<usize>(), 100);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[cfg(feature = "rayon")]
+pub fn par_process<P, A, I, F>(path: P, init: I, f: F) -> Result<Vec<A>, PacketError>
+where
+    P: AsRef<std::path::Path>,
+    A: Send,
+    I: Fn() -> A + Send + Sync,
+    F: Fn(&mut A, PcapRecord) + Send + Sync,
+{
+    use rayon::iter::{ParallelBridge, ParallelIterator};
+    let mut stream = PcapReader::from_reader(std::fs::File::open(path)?)?;
+    let format = stream.format;
+    core::iter::from_fn(|| stream.next_raw())
+        .par_bridge()
+        .try_fold(init, |mut acc, raw| {
+            let raw = raw?;
+            f(&mut acc, format.record(&raw.hdr, &raw.bytes));
+            Ok(acc)
+        })
+        .collect()
+}
+
+/// Write packets as an ethernet capture with microsecond timestamps, all at time zero
+pub fn write_pcap(packets: &[Vec<u8>]) -> Vec<u8> {
+    let records = packets.iter().map(|x| (x.as_slice(), x.len(), 0, 0));
+    write_records(PCAP_MAGIC_USEC, records)
+}
+
+/// Write captured bytes with the length of each packet on the wire, like [`write_pcap`]
+///
+/// The pairs returned by [`Packet::clone_truncated`] reproduce a capture taken with a snaplen.
+/// ```
+/// # use packet_rs::builders::*;
+/// # use packet_rs::pcap::*;
+/// let pkt = create_udp_packet(&Default::default());
+/// let (short, len) = pkt.clone_truncated(34);
+/// let data = write_pcap_truncated(&[(short.to_vec(), len)]);
+/// let reader = PcapReader::from_bytes(&data).unwrap();
+/// assert_eq!(reader.records()[0].orig_len as usize, pkt.len());
+/// assert_eq!(reader.records()[0].packet.len(), 34);
+/// ```
+pub fn write_pcap_truncated(packets: &[(Vec<u8>, usize)]) -> Vec<u8> {
+    let records = packets.iter().map(|(x, len)| (x.as_slice(), *len, 0, 0));
+    write_records(PCAP_MAGIC_USEC, records)
+}
+
+/// Write records as an ethernet capture with nanosecond timestamps, keeping their timestamps
+/// and lengths on the wire
+///
+/// ```
+/// # use packet_rs::builders::*;
+/// # use packet_rs::pcap::*;
+/// let pkt = create_udp_packet(&Default::default());
+/// let reader = PcapReader::from_bytes(&write_pcap(&[pkt.to_vec()])).unwrap();
+/// let mut records: Vec<PcapRecord> = reader.into_iter().collect();
+/// records[0].ts_sec = 1700000000;
+/// let reader = PcapReader::from_bytes(&write_pcap_records(&records)).unwrap();
+/// assert_eq!(reader.records()[0].ts_sec, 1700000000);
+/// ```
+pub fn write_pcap_records(records: &[PcapRecord]) -> Vec<u8> {
+    let bytes: Vec<Vec<u8>> = records.iter().map(|x| x.packet.to_vec()).collect();
+    let records = records.iter().zip(&bytes).map(|(x, v)| {
+        let orig_len = (x.orig_len as usize).max(v.len());
+        (v.as_slice(), orig_len, x.ts_sec, x.ts_nsec)
+    });
+    write_records(PCAP_MAGIC_NSEC, records)
+}
+
+/// Read the records of the ethernet capture at path, errors are wrapped in the path
+#[cfg(feature = "std")]
+pub(crate) fn read_ethernet_file(path: &std::path::Path) -> Result<Vec<PcapRecord>, PacketError> {
+    let reader = PcapReader::open(path).map_err(|e| e.context(path.display()))?;
+    if reader.linktype() != LINKTYPE_ETHERNET {
+        let err = PacketError::InvalidValue {
+            field: String::from("link type"),
+            value: format!(
+                "{} (only ethernet captures are supported)",
+                reader.linktype()
+            ),
+        };
+        return Err(err.context(path.display()));
+    }
+    Ok(reader.into_iter().collect())
+}
+
+/// Write records to path with [`write_pcap_records`], errors are wrapped in the path
+#[cfg(feature = "std")]
+pub(crate) fn write_records_file(
+    path: &std::path::Path,
+    records: &[PcapRecord],
+) -> Result<(), PacketError> {
+    std::fs::write(path, write_pcap_records(records))
+        .map_err(|e| PacketError::from(e).context(path.display()))
+}
+
+/// Write the global header then each packet with its length on the wire and timestamp, the
+/// fraction of the second in the resolution of magic
+fn write_records<'a>(
+    magic: u32,
+    packets: impl Iterator<Item = (&'a [u8], usize, u32, u32)>,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&magic.to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&4u16.to_le_bytes());
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&u16::MAX.to_le_bytes());
+    out.extend_from_slice(&[0; 2]);
+    out.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    for (packet, orig_len, ts_sec, ts_frac) in packets {
+        out.extend_from_slice(&ts_sec.to_le_bytes());
+        out.extend_from_slice(&ts_frac.to_le_bytes());
+        out.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(orig_len as u32).to_le_bytes());
+        out.extend_from_slice(packet);
+    }
+    out
+}
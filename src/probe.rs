@@ -0,0 +1,203 @@
+//! # Ping and traceroute probes
+//!
+//! Generators for echo requests and TTL-sweep probes, and the logic correlating candidate replies
+//! with them. No sockets are involved, packets are sent and received by the caller.
+//!
+//! Echo requests carry the echo identifier and sequence number in the first 4 bytes after the
+//! ICMP header, followed by an 8 byte timestamp in microseconds since the unix epoch.
+//!
+//! ```
+//! # use packet_rs::probe::*;
+//! # use std::time::Duration;
+//! let dst = "10.0.0.1".parse().unwrap();
+//! let pings: Vec<_> = ping_sequence(dst, 3, Duration::from_secs(1), 56).collect();
+//! assert_eq!(pings.len(), 3);
+//! let probes: Vec<_> = traceroute_probes(dst, 16, 3, TraceMode::Udp).collect();
+//! assert_eq!(probes.len(), 48);
+//! ```
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::builders::{self, PacketConfig};
+use crate::headers::*;
+use crate::icmp::{inet_checksum, ICMP_DEST_UNREACHABLE, ICMP_TIME_EXCEEDED};
+use crate::payload;
+use crate::utils;
+use crate::Packet;
+
+/// Echo identifier of the generated echo requests
+pub const ECHO_ID: u16 = 0x5052;
+/// First destination port of UDP probes and first source port of TCP probes
+pub const TRACE_BASE_PORT: u16 = 33434;
+
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+
+/// Protocol of the traceroute probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceMode {
+    /// Echo requests, the sequence number identifies the probe
+    Icmp,
+    /// UDP datagrams, the destination port identifies the probe
+    Udp,
+    /// TCP SYNs to port 80, the source port identifies the probe
+    Tcp,
+}
+
+/// Generate count echo requests with incrementing sequence numbers
+///
+/// The embedded timestamps start at the current time and are spaced by interval. The payload
+/// holds the echo header and timestamp followed by an incrementing pattern, it is at least 12
+/// bytes long.
+pub fn ping_sequence(
+    dst: Ipv4Addr,
+    count: u16,
+    interval: Duration,
+    payload_len: usize,
+) -> impl Iterator<Item = Packet> {
+    let start = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (0..count).map(move |seq| {
+        let ts = start + interval * seq as u32;
+        echo_request(dst, 64, seq, ts.as_micros() as u64, payload_len)
+    })
+}
+
+/// Generate the probes of a traceroute, probes_per_hop probes for each TTL from 1 to max_ttl
+///
+/// The n-th probe, starting from 0, has IP identification n and either the echo sequence number
+/// n, the UDP destination port or the TCP source port `TRACE_BASE_PORT + n`, all modulo 65536.
+pub fn traceroute_probes(
+    dst: Ipv4Addr,
+    max_ttl: u8,
+    probes_per_hop: u16,
+    mode: TraceMode,
+) -> impl Iterator<Item = Packet> {
+    (1..=max_ttl)
+        .flat_map(move |ttl| std::iter::repeat_n(ttl, probes_per_hop as usize))
+        .enumerate()
+        .map(move |(n, ttl)| {
+            // past 65535 probes the numbers wrap
+            let n = n as u16;
+            let cfg = PacketConfig {
+                ip_dst: dst.to_string(),
+                ip_ttl: ttl,
+                ip_id: n,
+                ..Default::default()
+            };
+            match mode {
+                TraceMode::Icmp => echo_request(dst, ttl, n, 0, 0),
+                TraceMode::Udp => builders::create_udp_packet(&PacketConfig {
+                    sport: TRACE_BASE_PORT,
+                    dport: TRACE_BASE_PORT.wrapping_add(n),
+                    payload_len: 12,
+                    ..cfg
+                }),
+                TraceMode::Tcp => builders::create_tcp_packet(&PacketConfig {
+                    sport: TRACE_BASE_PORT.wrapping_add(n),
+                    dport: 80,
+                    tcp_flags: 0x2,
+                    ..cfg
+                }),
+            }
+        })
+}
+
+/// Return true if candidate is the echo reply to the echo request
+pub fn match_echo_reply(request: &Packet, candidate: &Packet) -> bool {
+    let (req_ip, reply_ip) = match (request.layer::<IPv4>(), candidate.layer::<IPv4>()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+    match candidate.layer::<ICMP>() {
+        Some(icmp) if icmp.icmp_type() == ICMP_ECHO_REPLY as u64 => (),
+        _ => return false,
+    }
+    req_ip.src() == reply_ip.dst()
+        && req_ip.dst() == reply_ip.src()
+        && echo_header(request).is_some()
+        && echo_header(request) == echo_header(candidate)
+}
+
+/// Return the hop of the probe if candidate is the ICMP error it caused
+///
+/// Both time exceeded and destination unreachable errors are matched, the latter is returned by
+/// the destination for UDP probes. The hop is the TTL of the probe.
+pub fn match_ttl_exceeded(probe: &Packet, candidate: &Packet) -> Option<u8> {
+    let probe_ip = probe.layer::<IPv4>()?;
+    let icmp = candidate.layer::<ICMP>()?;
+    if icmp.icmp_type() != ICMP_TIME_EXCEEDED as u64
+        && icmp.icmp_type() != ICMP_DEST_UNREACHABLE as u64
+    {
+        return None;
+    }
+    if candidate.layer::<IPv4>()?.dst() != probe_ip.src() {
+        return None;
+    }
+    // rest of the icmp header, then the quoted ip header and at least 8 bytes of its payload
+    let v = candidate.to_vec();
+    let quoted = v.get(candidate.layer_offset::<ICMP>()? + ICMP::size() + 4..)?;
+    let quoted_ip = IPv4::from(quoted.get(..IPv4::size())?.to_vec());
+    if quoted_ip.src() != probe_ip.src()
+        || quoted_ip.dst() != probe_ip.dst()
+        || quoted_ip.protocol() != probe_ip.protocol()
+        || quoted_ip.identification() != probe_ip.identification()
+    {
+        return None;
+    }
+    let ihl = quoted_ip.ihl() as usize * 4;
+    let probe_v = probe.to_vec();
+    let probe_l4 = &probe_v[probe.layer_offset::<IPv4>()? + probe_ip.ihl() as usize * 4..];
+    match (quoted.get(ihl..ihl + 8), probe_l4.get(..8)) {
+        (Some(a), Some(b)) if a == b => Some(probe_ip.ttl() as u8),
+        _ => None,
+    }
+}
+
+fn echo_request(dst: Ipv4Addr, ttl: u8, seq: u16, ts: u64, payload_len: usize) -> Packet {
+    let cfg = PacketConfig::default();
+    let mut data = ECHO_ID.to_be_bytes().to_vec();
+    data.extend_from_slice(&seq.to_be_bytes());
+    data.extend_from_slice(&ts.to_be_bytes());
+    if payload_len > data.len() {
+        data.extend(payload::incrementing(payload_len - data.len()));
+    }
+    let mut pkt = utils::create_icmp_packet(
+        &cfg.eth_dst,
+        &cfg.eth_src,
+        false,
+        0,
+        0,
+        5,
+        &cfg.ip_src,
+        &dst.to_string(),
+        0,
+        ttl,
+        seq,
+        0,
+        Vec::new(),
+        ICMP_ECHO_REQUEST,
+        0,
+        Vec::new(),
+        false,
+        &data,
+    );
+    let mut v = Packet::icmp(ICMP_ECHO_REQUEST, 0).to_vec();
+    v.extend_from_slice(&data);
+    let chksum = inet_checksum(&v);
+    pkt.layer_mut::<ICMP>().unwrap().set_chksum(chksum as u64);
+    pkt
+}
+
+/// Echo identifier and sequence number following the ICMP header
+fn echo_header(pkt: &Packet) -> Option<(u16, u16)> {
+    let v = pkt.to_vec();
+    let offset = pkt.layer_offset::<ICMP>()? + ICMP::size();
+    let h = v.get(offset..offset + 4)?;
+    Some((
+        u16::from_be_bytes([h[0], h[1]]),
+        u16::from_be_bytes([h[2], h[3]]),
+    ))
+}
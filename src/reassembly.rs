@@ -0,0 +1,271 @@
+//! # TCP stream reassembly
+//!
+//! [`TcpReassembler`] is fed the TCP segments of one direction of a connection, in any order, and
+//! rebuilds the byte stream they carry. Segments are placed by sequence number, across the
+//! wraparound of the 32 bit sequence space. Bytes received twice, from retransmissions or
+//! overlapping segments, are counted and resolved by an [`OverlapPolicy`].
+//!
+//! Contiguous bytes are handed out with [`TcpReassembler::read`], or to a callback set with
+//! [`TcpReassembler::on_chunk`], as soon as the bytes before them arrived. A missing segment
+//! holds back everything after it: [`TcpReassembler::holes`] lists the ranges missing and
+//! [`TcpReassembler::skip_hole`] gives up on the first one. Offsets count the bytes of the stream
+//! from 0, the byte following the SYN, or the first byte of the first segment when the SYN was
+//! not seen.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::reassembly::*;
+//! let segment = |seq: u32, data: &[u8]| {
+//!     let mut pkt = create_tcp_packet(&PacketConfig { tcp_flags: 0x10, ..Default::default() });
+//!     pkt.layer_mut::<TCP>().unwrap().set_seq_no(seq as u64);
+//!     pkt.set_payload(data);
+//!     pkt.layer_mut::<IPv4>().unwrap().set_total_len(40 + data.len() as u64);
+//!     pkt
+//! };
+//! let mut stream = TcpReassembler::new();
+//! stream.push(&segment(100, b"GET ")).unwrap();
+//! stream.push(&segment(108, b"HTTP/1.1")).unwrap();
+//! assert_eq!(stream.holes(), vec![4..8]);
+//! stream.push(&segment(104, b"/ \r\n")).unwrap();
+//! let mut buf = [0; 64];
+//! let n = stream.read(&mut buf);
+//! assert_eq!(&buf[..n], b"GET / \r\nHTTP/1.1");
+//! ```
+
+use core::ops::Range;
+
+use crate::alloc_prelude::*;
+use crate::headers::*;
+use crate::{Packet, PacketError};
+use alloc::collections::BTreeMap;
+
+const FIN: u64 = 0x01;
+const SYN: u64 = 0x02;
+
+/// Callback receiving the offset and bytes of each contiguous chunk
+type ChunkFn = Box<dyn FnMut(u64, &[u8])>;
+
+/// Which copy is kept of bytes received more than once
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Keep the bytes received first, as most stacks do
+    #[default]
+    First,
+    /// Keep the bytes received last, as long as they were not handed out yet
+    Last,
+}
+
+/// Counters of a [`TcpReassembler`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReassemblyStats {
+    /// Segments pushed
+    pub segments: u64,
+    /// Bytes of the stream made contiguous, including the bytes of skipped holes
+    pub bytes: u64,
+    /// Bytes received more than once
+    pub duplicate_bytes: u64,
+    /// Bytes missing from the holes skipped
+    pub skipped_bytes: u64,
+}
+
+/// The byte stream of one direction of a TCP connection
+#[derive(Default)]
+pub struct TcpReassembler {
+    policy: OverlapPolicy,
+    /// Sequence number of the byte at offset `contiguous`
+    next_seq: Option<u32>,
+    /// Offset of the first byte not yet contiguous
+    contiguous: u64,
+    /// Segments past a hole by offset, never overlapping
+    pending: BTreeMap<u64, Vec<u8>>,
+    /// Contiguous bytes not yet read
+    ready: Vec<u8>,
+    /// Offset of the FIN
+    fin: Option<u64>,
+    on_chunk: Option<ChunkFn>,
+    stats: ReassemblyStats,
+}
+
+impl TcpReassembler {
+    /// Create a reassembler keeping the first copy of overlapping bytes
+    pub fn new() -> TcpReassembler {
+        TcpReassembler::default()
+    }
+    /// Create a reassembler resolving overlapping bytes with policy
+    pub fn with_policy(policy: OverlapPolicy) -> TcpReassembler {
+        TcpReassembler {
+            policy,
+            ..Default::default()
+        }
+    }
+    /// Hand every contiguous chunk to f with its offset instead of keeping it for read
+    pub fn on_chunk(&mut self, f: impl FnMut(u64, &[u8]) + 'static) {
+        self.on_chunk = Some(Box::new(f));
+    }
+    /// Add the TCP segment of a packet
+    ///
+    /// The payload ends with the IP length, the link layer padding is not part of the stream.
+    pub fn push(&mut self, pkt: &Packet) -> Result<(), PacketError> {
+        let tcp = pkt
+            .layer::<TCP>()
+            .ok_or_else(|| PacketError::MissingHeader(String::from("TCP")))?;
+        let data = tcp_payload(pkt).unwrap_or_default();
+        self.push_segment(tcp.seq_no() as u32, tcp.flags() as u8, &data);
+        Ok(())
+    }
+    /// Add a segment of sequence number seq with the TCP flags and payload data
+    pub fn push_segment(&mut self, seq: u32, flags: u8, data: &[u8]) {
+        self.stats.segments += 1;
+        let flags = flags as u64;
+        // the SYN takes the sequence number before the data
+        let seq = seq.wrapping_add((flags & SYN != 0) as u32);
+        let next_seq = *self.next_seq.get_or_insert(seq);
+        let offset = self.contiguous as i64 + seq.wrapping_sub(next_seq) as i32 as i64;
+        let end = offset + data.len() as i64;
+        if flags & FIN != 0 && end >= 0 {
+            self.fin = Some(end as u64);
+        }
+        // bytes before the start of the stream were not seen from the beginning
+        let skip = (self.contiguous as i64 - offset).clamp(0, data.len() as i64) as usize;
+        let old = (-offset).clamp(0, data.len() as i64) as usize;
+        self.stats.duplicate_bytes += (skip - old) as u64;
+        if skip < data.len() {
+            self.insert((offset + skip as i64) as u64, &data[skip..]);
+        }
+        self.deliver();
+    }
+    /// Copy contiguous bytes not yet read into buf and return their number
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.ready.len());
+        buf[..n].copy_from_slice(&self.ready[..n]);
+        self.ready.drain(..n);
+        n
+    }
+    /// Return the contiguous bytes not yet read
+    pub fn read_all(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.ready)
+    }
+    /// Return the ranges of bytes missing before the last byte received, or the FIN
+    pub fn holes(&self) -> Vec<Range<u64>> {
+        let mut holes = Vec::new();
+        let mut pos = self.contiguous;
+        for (start, data) in &self.pending {
+            if *start > pos {
+                holes.push(pos..*start);
+            }
+            pos = start + data.len() as u64;
+        }
+        match self.fin {
+            Some(fin) if fin > pos => holes.push(pos..fin),
+            _ => (),
+        }
+        holes
+    }
+    /// Give up on the first hole and make the bytes after it contiguous
+    ///
+    /// Returns the range skipped, None if nothing is missing.
+    pub fn skip_hole(&mut self) -> Option<Range<u64>> {
+        let hole = self.holes().into_iter().next()?;
+        let len = hole.end - hole.start;
+        self.contiguous = hole.end;
+        self.next_seq = self.next_seq.map(|x| x.wrapping_add(len as u32));
+        self.stats.bytes += len;
+        self.stats.skipped_bytes += len;
+        self.deliver();
+        Some(hole)
+    }
+    /// Return the offset of the first byte not yet contiguous
+    pub fn offset(&self) -> u64 {
+        self.contiguous
+    }
+    /// Return true once the FIN was received and every byte before it is contiguous
+    pub fn is_closed(&self) -> bool {
+        self.fin.is_some_and(|x| x <= self.contiguous)
+    }
+    /// Return the counters of the reassembler
+    pub fn stats(&self) -> ReassemblyStats {
+        self.stats
+    }
+    /// Add data at offset, at or after the contiguous bytes
+    fn insert(&mut self, start: u64, data: &[u8]) {
+        let end = start + data.len() as u64;
+        // segments are disjoint, so those overlapping data end after start in reverse order
+        let mut overlapping: Vec<u64> = self
+            .pending
+            .range(..end)
+            .rev()
+            .take_while(|(s, d)| *s + d.len() as u64 > start)
+            .map(|(s, _)| *s)
+            .collect();
+        overlapping.reverse();
+        let mut pos = start;
+        for s in overlapping {
+            let len = self.pending[&s].len() as u64;
+            self.stats.duplicate_bytes += (s + len).min(end) - s.max(start);
+            match self.policy {
+                OverlapPolicy::First => {
+                    if s > pos {
+                        let piece = &data[(pos - start) as usize..(s - start) as usize];
+                        self.pending.insert(pos, piece.into());
+                    }
+                    pos = pos.max(s + len);
+                }
+                OverlapPolicy::Last => {
+                    // keep the parts of the old segment outside data
+                    let old = self.pending.remove(&s).unwrap();
+                    if s < start {
+                        self.pending.insert(s, old[..(start - s) as usize].into());
+                    }
+                    if s + len > end {
+                        self.pending.insert(end, old[(end - s) as usize..].into());
+                    }
+                }
+            }
+        }
+        if pos < end {
+            let piece = &data[(pos - start) as usize..];
+            self.pending.insert(pos, piece.into());
+        }
+    }
+    /// Move the segments following the contiguous bytes out of pending
+    fn deliver(&mut self) {
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() != self.contiguous {
+                break;
+            }
+            let offset = *entry.key();
+            let data = entry.remove();
+            self.contiguous += data.len() as u64;
+            self.next_seq = self.next_seq.map(|x| x.wrapping_add(data.len() as u32));
+            self.stats.bytes += data.len() as u64;
+            match self.on_chunk.as_mut() {
+                Some(f) => f(offset, &data),
+                None => self.ready.extend_from_slice(&data),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Read for TcpReassembler {
+    /// Read contiguous bytes, returning 0 while a hole or the end of the capture is reached
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(TcpReassembler::read(self, buf))
+    }
+}
+
+/// Payload of the first TCP header, ending with the IP layer before it
+pub(crate) fn tcp_payload(pkt: &Packet) -> Option<Vec<u8>> {
+    let tcp_offset = pkt.layer_offset::<TCP>()?;
+    let tcp_len = pkt.layer::<TCP>()?.data_offset() as usize * 4;
+    let v = pkt.to_vec();
+    let ip_end = match (pkt.layer_offset::<IPv4>(), pkt.layer_offset::<IPv6>()) {
+        (Some(off), _) if off < tcp_offset => off + pkt.layer::<IPv4>()?.total_len() as usize,
+        (_, Some(off)) => off + IPv6::size() + pkt.layer::<IPv6>()?.payload_len() as usize,
+        _ => v.len(),
+    };
+    let end = ip_end.min(v.len());
+    let start = (tcp_offset + tcp_len).min(end);
+    Some(v[start..end].to_vec())
+}
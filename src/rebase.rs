@@ -0,0 +1,149 @@
+//! # Differential replay
+//!
+//! [`Packet::rebase`] retargets a captured packet onto other addressing: the fields of the layers
+//! of the kinds given are copied from a template, as the Ethernet and IPv4 addresses of a lab,
+//! while the rest of the packet, as its ports, sequence numbers and payload, is kept.
+//! [`Packet::rebase_with`] also takes lists of fields to copy or to keep, as the MACs of the
+//! template but the DSCP of the capture.
+//!
+//! A layer of the packet takes the fields of the layer of the template with the same name and
+//! the same rank among the layers of that name, so the outer IPv4 header of a tunneled packet
+//! takes those of the first IPv4 header of the template. Layers with no counterpart are left as
+//! they are. The layers of the packet are not changed, so the fields describing its structure,
+//! its next protocol, length, option presence and fragmentation fields, are kept unless listed in
+//! [`RebaseOptions::include`]: a padded capture keeps its lengths and a protocol the crate does
+//! not parse keeps its number. The checksums are then [recomputed](Packet::update_checksums).
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::parser::LayerKind;
+//! let mut captured = create_tcp_packet(&Default::default());
+//! captured.set_payload(b"GET / HTTP/1.1\r\n\r\n");
+//! captured.fixup();
+//! let lab = create_udp_packet(&PacketConfig {
+//!     ip_src: String::from("10.1.0.1"),
+//!     ip_dst: String::from("10.1.0.2"),
+//!     ..Default::default()
+//! });
+//! let pkt = captured.rebase(&lab, &[LayerKind::Network]);
+//! assert_eq!(pkt.layer::<IPv4>().unwrap().dst_ip().to_string(), "10.1.0.2");
+//! assert_eq!(pkt.layer::<IPv4>().unwrap().protocol(), 6);
+//! assert_eq!(pkt.layer::<TCP>().unwrap().dst(), 80);
+//! assert!(pkt.verify_checksums().is_ok());
+//! ```
+
+use crate::alloc_prelude::*;
+use crate::headers::FieldInfo;
+use crate::parser::LayerKind;
+use crate::Packet;
+
+/// Fields kept by a rebase unless included, they describe the structure of the packet
+const STRUCTURE: &[(&str, &str)] = &[
+    ("Ether", "etype"),
+    ("Dot3", "length"),
+    ("Vlan", "etype"),
+    ("MPLS", "bos"),
+    ("IPv4", "ihl"),
+    ("IPv4", "total_len"),
+    ("IPv4", "identification"),
+    ("IPv4", "flags"),
+    ("IPv4", "frag_offset"),
+    ("IPv4", "protocol"),
+    ("IPv6", "payload_len"),
+    ("IPv6", "next_hdr"),
+    ("TCP", "data_offset"),
+    ("UDP", "length"),
+    ("UdpLite", "coverage"),
+    ("GRE", "chksum_present"),
+    ("GRE", "key_present"),
+    ("GRE", "seqnum_present"),
+    ("GRE", "proto"),
+    ("L2tp", "l"),
+    ("L2tp", "s"),
+    ("L2tp", "o"),
+    ("ERSPAN3", "o"),
+];
+
+/// Fields copied by [`Packet::rebase_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RebaseOptions<'a> {
+    /// Copy the fields of the layers of these kinds
+    pub layers: &'a [LayerKind],
+    /// Also copy these (layer, field) pairs, whatever the kind of the layer
+    pub include: &'a [(&'a str, &'a str)],
+    /// Keep these (layer, field) pairs of the packet, overriding the other lists
+    pub exclude: &'a [(&'a str, &'a str)],
+}
+
+impl RebaseOptions<'_> {
+    /// Return true if the field of the layer called layer is copied from the template
+    fn copies(&self, layer: &str, field: &FieldInfo) -> bool {
+        let listed =
+            |list: &[(&str, &str)]| list.iter().any(|&(l, f)| l == layer && field.is_named(f));
+        if listed(self.exclude) {
+            return false;
+        }
+        if listed(self.include) {
+            return true;
+        }
+        let kind = LayerKind::of(layer);
+        kind.is_some_and(|x| self.layers.contains(&x)) && !listed(STRUCTURE)
+    }
+}
+
+impl Packet {
+    /// Return a copy of the packet with the fields of its layers of the given kinds taken from
+    /// template, with its checksums recomputed
+    pub fn rebase(&self, template: &Packet, layers: &[LayerKind]) -> Packet {
+        let opts = RebaseOptions {
+            layers,
+            ..Default::default()
+        };
+        self.rebase_with(template, &opts)
+    }
+    /// Return a copy of the packet with the fields selected by opts taken from template, with
+    /// its checksums recomputed
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::parser::LayerKind;
+    /// # use packet_rs::rebase::RebaseOptions;
+    /// let mut captured = create_tcp_packet(&Default::default());
+    /// captured.layer_mut::<IPv4>().unwrap().set_dscp(46);
+    /// let lab = create_tcp_packet(&PacketConfig {
+    ///     eth_dst: String::from("02:00:00:00:00:01"),
+    ///     ip_dst: String::from("10.1.0.2"),
+    ///     dport: 8080,
+    ///     ..Default::default()
+    /// });
+    /// let opts = RebaseOptions {
+    ///     layers: &[LayerKind::Link, LayerKind::Network],
+    ///     include: &[("TCP", "dst")],
+    ///     exclude: &[("IPv4", "diffserv")],
+    /// };
+    /// let pkt = captured.rebase_with(&lab, &opts);
+    /// assert_eq!(pkt.layer::<Ether>().unwrap().dst(), 0x020000000001);
+    /// assert_eq!(pkt.layer::<IPv4>().unwrap().dscp(), 46);
+    /// assert_eq!(pkt.layer::<TCP>().unwrap().dst(), 8080);
+    /// ```
+    pub fn rebase_with(&self, template: &Packet, opts: &RebaseOptions) -> Packet {
+        let mut pkt = self.clone();
+        pkt.invalidate();
+        for (i, hdr) in pkt.hdrs.iter_mut().enumerate() {
+            let name = String::from(hdr.name());
+            let rank = self.hdrs[..i].iter().filter(|x| x.name() == name).count();
+            let from = match template.hdrs.iter().filter(|x| x.name() == name).nth(rank) {
+                Some(x) => x,
+                None => continue,
+            };
+            for f in hdr.fields().iter().filter(|f| opts.copies(&name, f)) {
+                hdr.set_field_bytes(f.name, &from.get_field_bytes(f.name).unwrap());
+            }
+        }
+        pkt.update_checksums();
+        pkt
+    }
+}
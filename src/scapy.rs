@@ -0,0 +1,166 @@
+//! # Scapy export
+//!
+//! [`Packet::to_scapy`] renders a packet as a Scapy expression rebuilding the same bytes. Every
+//! field is set explicitly so lengths and checksums are reproduced rather than recomputed by
+//! Scapy. Headers Scapy has no equivalent for are exported as `Raw` layers.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! # use packet_rs::Packet;
+//! let mut pkt = Packet::new();
+//! pkt.push(Packet::udp(1234, 80, 8));
+//! assert_eq!(pkt.to_scapy(), "UDP(sport=1234, dport=80, len=8, chksum=0)");
+//! ```
+
+use core::fmt::Write;
+
+use crate::addr::{bytes_to_ipv6, u64_to_ipv4, MacAddr};
+use crate::headers::*;
+use crate::Packet;
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Int,
+    Mac,
+    Ipv4,
+    Ipv6,
+}
+
+use Kind::*;
+
+/// Header name, scapy layer and (field, scapy field, kind) of each field
+type Layer = (&'static str, &'static str, &'static [(&'static str, &'static str, Kind)]);
+
+#[rustfmt::skip]
+const LAYERS: &[Layer] = &[
+    ("Ether", "Ether", &[("dst", "dst", Mac), ("src", "src", Mac), ("etype", "type", Int)]),
+    ("Vlan", "Dot1Q", &[("pcp", "prio", Int), ("cfi", "id", Int), ("vid", "vlan", Int),
+        ("etype", "type", Int)]),
+    ("IPv4", "IP", &[("version", "version", Int), ("ihl", "ihl", Int), ("diffserv", "tos", Int),
+        ("total_len", "len", Int), ("identification", "id", Int), ("flags", "flags", Int),
+        ("frag_offset", "frag", Int), ("ttl", "ttl", Int), ("protocol", "proto", Int),
+        ("header_checksum", "chksum", Int), ("src", "src", Ipv4), ("dst", "dst", Ipv4)]),
+    ("IPv6", "IPv6", &[("version", "version", Int), ("traffic_class", "tc", Int),
+        ("flow_label", "fl", Int), ("payload_len", "plen", Int), ("next_hdr", "nh", Int),
+        ("hop_limit", "hlim", Int), ("src", "src", Ipv6), ("dst", "dst", Ipv6)]),
+    ("UDP", "UDP", &[("src", "sport", Int), ("dst", "dport", Int), ("length", "len", Int),
+        ("checksum", "chksum", Int)]),
+    ("ARP", "ARP", &[("hwtype", "hwtype", Int), ("proto_type", "ptype", Int),
+        ("hwlen", "hwlen", Int), ("proto_len", "plen", Int), ("opcode", "op", Int),
+        ("sender_hw_addr", "hwsrc", Mac), ("sender_proto_addr", "psrc", Ipv4),
+        ("target_hw_addr", "hwdst", Mac), ("target_proto_addr", "pdst", Ipv4)]),
+    ("Dot3", "Dot3", &[("dst", "dst", Mac), ("src", "src", Mac), ("length", "len", Int)]),
+    ("LLC", "LLC", &[("dsap", "dsap", Int), ("ssap", "ssap", Int), ("ctrl", "ctrl", Int)]),
+    ("SNAP", "SNAP", &[("oui", "OUI", Int), ("code", "code", Int)]),
+    ("GRE", "GRE", &[("chksum_present", "chksum_present", Int),
+        ("routing_present", "routing_present", Int), ("key_present", "key_present", Int),
+        ("seqnum_present", "seqnum_present", Int),
+        ("strict_route_src", "strict_route_source", Int),
+        ("recurse", "recursion_control", Int), ("flags", "flags", Int),
+        ("version", "version", Int), ("proto", "proto", Int)]),
+    ("MPLS", "MPLS", &[("label", "label", Int), ("exp", "cos", Int), ("bos", "s", Int),
+        ("ttl", "ttl", Int)]),
+    ("Vxlan", "VXLAN", &[("flags", "flags", Int), ("reserved", "reserved1", Int),
+        ("vni", "vni", Int), ("reserved2", "reserved2", Int)]),
+];
+
+/// GRE option headers, exported as the optional fields of the preceding GRE layer
+const GRE_OPTIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "GREChksumOffset",
+        &[("chksum", "chksum"), ("offset", "offset")],
+    ),
+    ("GREKey", &[("key", "key")]),
+    ("GRESequenceNum", &[("seqnum", "seqence_number")]),
+];
+
+impl Packet {
+    /// Render the packet as a Scapy expression
+    pub fn to_scapy(&self) -> String {
+        let mut layers: Vec<(String, Vec<String>)> = Vec::new();
+        let mut ipv6 = false;
+        for hdr in &self.hdrs {
+            let field = |name: &str| hdr.get_field(name).unwrap();
+            if let Some((_, fields)) = GRE_OPTIONS.iter().find(|x| x.0 == hdr.name()) {
+                if let Some((_, args)) = layers.last_mut().filter(|x| x.0 == "GRE") {
+                    for (name, scapy) in fields.iter() {
+                        args.push(format!("{}={}", scapy, field(name)));
+                    }
+                    continue;
+                }
+            }
+            let layer = match (hdr.name(), LAYERS.iter().find(|x| x.0 == hdr.name())) {
+                (_, Some((_, class, fields))) => {
+                    let args = fields
+                        .iter()
+                        .map(|(name, scapy, kind)| {
+                            format!("{}={}", scapy, value(hdr.as_ref(), name, *kind))
+                        })
+                        .collect();
+                    (String::from(*class), args)
+                }
+                ("TCP", _) => {
+                    // scapy has a 3 bit reserved field and the NS bit among the flags
+                    let flags = ((field("res") & 1) << 8) | field("flags");
+                    let args = vec![
+                        format!("sport={}", field("src")),
+                        format!("dport={}", field("dst")),
+                        format!("seq={}", field("seq_no")),
+                        format!("ack={}", field("ack_no")),
+                        format!("dataofs={}", field("data_offset")),
+                        format!("reserved={}", field("res") >> 1),
+                        format!("flags={}", flags),
+                        format!("window={}", field("window")),
+                        format!("chksum={}", field("checksum")),
+                        format!("urgptr={}", field("urgent_ptr")),
+                    ];
+                    (String::from("TCP"), args)
+                }
+                ("ICMP", _) => {
+                    let class = if ipv6 { "ICMPv6Unknown" } else { "ICMP" };
+                    let chksum = if ipv6 { "cksum" } else { "chksum" };
+                    let args = vec![
+                        format!("type={}", field("icmp_type")),
+                        format!("code={}", field("icmp_code")),
+                        format!("{}={}", chksum, field("chksum")),
+                    ];
+                    (String::from(class), args)
+                }
+                _ => (String::from("Raw"), vec![raw(&hdr.to_vec())]),
+            };
+            ipv6 = hdr.name() == "IPv6";
+            layers.push(layer);
+        }
+        if !self.payload.is_empty() {
+            layers.push((String::from("Raw"), vec![raw(&self.payload)]));
+        }
+        let layers: Vec<String> = layers
+            .iter()
+            .map(|(class, args)| format!("{}({})", class, args.join(", ")))
+            .collect();
+        layers.join("/")
+    }
+}
+
+fn value(hdr: &dyn Header, name: &str, kind: Kind) -> String {
+    let field = || hdr.get_field(name).unwrap();
+    match kind {
+        Int => field().to_string(),
+        Mac => format!("'{}'", MacAddr::from(field())),
+        Ipv4 => format!("'{}'", u64_to_ipv4(field())),
+        // only the 128 bit IPv6 addresses are of this kind
+        Ipv6 => format!(
+            "'{}'",
+            bytes_to_ipv6(&hdr.get_field_bytes(name).unwrap()).unwrap()
+        ),
+    }
+}
+
+fn raw(v: &[u8]) -> String {
+    let mut out = String::from("load=b'");
+    for b in v {
+        write!(out, "\\x{:02x}", b).unwrap();
+    }
+    out.push('\'');
+    out
+}
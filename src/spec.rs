@@ -0,0 +1,200 @@
+//! Compact packet spec parser
+//!
+//! A spec is a list of layers separated by `/`. Each layer is a header name optionally followed
+//! by a comma separated list of `field=value` pairs, e.g.
+//! `Ether(dst=00:01:02:03:04:05)/IPv4(src=1.2.3.4,dst=5.6.7.8)/TCP(dport=80,flags=S)`.
+
+use core::convert::TryFrom;
+use core::net::{Ipv4Addr, Ipv6Addr};
+use core::str::FromStr;
+
+use crate::addr::MacAddr;
+use crate::headers::*;
+use crate::{Packet, PacketError};
+
+impl TryFrom<&str> for Packet {
+    type Error = PacketError;
+
+    fn try_from(spec: &str) -> Result<Packet, PacketError> {
+        let mut pkt = Packet::new();
+        for layer in split_layers(spec)? {
+            pkt.hdrs.push(parse_layer(layer)?);
+        }
+        Ok(pkt)
+    }
+}
+
+fn split_layers(spec: &str) -> Result<Vec<&str>, PacketError> {
+    let mut layers = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in spec.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(PacketError::InvalidSpec(String::from(spec))),
+            ')' => depth -= 1,
+            '/' if depth == 0 => {
+                layers.push(spec[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if depth != 0 {
+        return Err(PacketError::InvalidSpec(String::from(spec)));
+    }
+    layers.push(spec[start..].trim());
+    if layers.iter().any(|x| x.is_empty()) {
+        return Err(PacketError::InvalidSpec(String::from(spec)));
+    }
+    Ok(layers)
+}
+
+fn parse_layer(layer: &str) -> Result<Box<dyn Header>, PacketError> {
+    let (name, args) = match layer.find('(') {
+        Some(i) => match layer.strip_suffix(')') {
+            Some(l) => (l[..i].trim(), &l[i + 1..]),
+            None => return Err(PacketError::InvalidSpec(String::from(layer))),
+        },
+        None => (layer, ""),
+    };
+    let mut hdr = header_by_name(name)?;
+    for arg in args.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        let (field, value) = match arg.split_once('=') {
+            Some((f, v)) => (f.trim(), v.trim()),
+            None => return Err(PacketError::InvalidSpec(String::from(arg))),
+        };
+        set_field_str(hdr.as_mut(), field, value)?;
+    }
+    Ok(hdr)
+}
+
+/// Create the header called name, or by one of the names Scapy and the specs know it by
+pub(crate) fn header_by_name(name: &str) -> Result<Box<dyn Header>, PacketError> {
+    let name = match name {
+        "Eth" | "Ethernet" => "Ether",
+        "IP" => "IPv4",
+        "Dot1Q" => "Vlan",
+        x => x,
+    };
+    header_from_name(name).ok_or_else(|| PacketError::UnknownHeader(String::from(name)))
+}
+
+/// Set a field from its value as written in a spec: a number, an address for the address fields
+/// or TCP flag letters, see [`Header::set_field_str`], returns the name of the field
+pub(crate) fn set_field_str<H: Header + ?Sized>(
+    hdr: &mut H,
+    field: &str,
+    value: &str,
+) -> Result<&'static str, PacketError> {
+    let name = hdr.name();
+    let field = match (name, field) {
+        ("TCP", "sport") | ("UDP", "sport") => "src",
+        ("TCP", "dport") | ("UDP", "dport") => "dst",
+        (_, x) => x,
+    };
+    let info = match hdr.fields().iter().find(|f| f.is_named(field)) {
+        Some(f) => *f,
+        None => {
+            return Err(PacketError::UnknownField {
+                header: String::from(name),
+                field: String::from(field),
+            })
+        }
+    };
+    let invalid = || PacketError::InvalidValue {
+        field: String::from(field),
+        value: String::from(value),
+    };
+    let bytes = if name == "TCP" && field == "flags" {
+        parse_tcp_flags(value)
+    } else {
+        parse_field(&info, value)
+    };
+    let bytes = bytes.ok_or_else(invalid)?;
+    if significant_bits(&bytes) > info.size() {
+        return Err(invalid());
+    }
+    hdr.set_field_bytes(field, &bytes);
+    Ok(info.name)
+}
+
+/// Parse the value of a field by its kind, every field takes a number
+fn parse_field(info: &FieldInfo, value: &str) -> Option<Vec<u8>> {
+    let addr = match info.kind {
+        FieldKind::Mac => MacAddr::from_str(value).ok().map(|x| x.octets().to_vec()),
+        FieldKind::Ipv4 => Ipv4Addr::from_str(value).ok().map(|x| x.octets().to_vec()),
+        FieldKind::Ipv6 => Ipv6Addr::from_str(value).ok().map(|x| x.octets().to_vec()),
+        FieldKind::Number | FieldKind::Bytes => None,
+    };
+    addr.or_else(|| parse_number(value))
+}
+
+fn parse_tcp_flags(value: &str) -> Option<Vec<u8>> {
+    if let Some(v) = parse_number(value) {
+        return Some(v);
+    }
+    let mut flags: u8 = 0;
+    for c in value.chars() {
+        flags |= match c {
+            'F' => 0x01,
+            'S' => 0x02,
+            'R' => 0x04,
+            'P' => 0x08,
+            'A' => 0x10,
+            'U' => 0x20,
+            'E' => 0x40,
+            'C' => 0x80,
+            _ => return None,
+        };
+    }
+    Some(vec![flags])
+}
+
+/// Parse a number, an address or `0x` prefixed hex of any length as big endian bytes
+#[cfg(any(feature = "serde", feature = "wasm"))]
+pub(crate) fn parse_value(value: &str) -> Option<Vec<u8>> {
+    if let Some(v) = parse_number(value) {
+        return Some(v);
+    }
+    if let Ok(ip) = Ipv4Addr::from_str(value) {
+        return Some(ip.octets().to_vec());
+    }
+    if let Ok(ip) = Ipv6Addr::from_str(value) {
+        return Some(ip.octets().to_vec());
+    }
+    let octets: Vec<&str> = value.split([':', '-']).collect();
+    if octets.len() == 6 && octets.iter().all(|x| x.len() == 2) {
+        return octets
+            .iter()
+            .map(|x| u8::from_str_radix(x, 16).ok())
+            .collect();
+    }
+    None
+}
+
+/// Parse a decimal number or `0x` prefixed hex of any length as big endian bytes
+fn parse_number(value: &str) -> Option<Vec<u8>> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let hex = if hex.len() % 2 == 1 {
+            format!("0{}", hex)
+        } else {
+            String::from(hex)
+        };
+        return (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect();
+    }
+    u64::from_str(value).ok().map(|v| v.to_be_bytes().to_vec())
+}
+
+pub(crate) fn significant_bits(bytes: &[u8]) -> usize {
+    match bytes.iter().position(|b| *b != 0) {
+        Some(i) => (bytes.len() - i) * 8 - bytes[i].leading_zeros() as usize,
+        None => 0,
+    }
+}
@@ -0,0 +1,101 @@
+//! # One-line packet summaries
+//!
+//! [`Packet::summary`] renders a packet as a single line of layer summaries for logs and
+//! interactive debugging. Ether, Vlan, IPv4, IPv6, TCP, UDP and Vxlan have their own formats,
+//! other headers show their name and first three fields.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! let pkt = create_tcp_packet(&Default::default());
+//! assert_eq!(
+//!     pkt.summary(),
+//!     "Ether 00:06:07:08:09:0a > 00:01:02:03:04:05 / IPv4 192.168.0.1 > 192.168.0.2 ttl=64 / TCP 1234 > 80 [SYN] len=0"
+//! );
+//! ```
+
+use core::fmt::Write;
+
+use crate::addr::{bytes_to_ipv6, u64_to_ipv4, MacAddr};
+use crate::headers::*;
+use crate::Packet;
+
+/// Names of the TCP flags from the least significant bit
+const TCP_FLAGS: [&str; 8] = ["FIN", "SYN", "RST", "PSH", "ACK", "URG", "ECE", "CWR"];
+
+/// Number of fields shown by the default summary
+const DEFAULT_FIELDS: usize = 3;
+
+/// Summarize a header on one line, the implementation of [`Header::summary`]
+pub fn header_summary(hdr: &dyn Header) -> String {
+    let field = |name: &str| hdr.get_field(name).unwrap_or(0);
+    let bytes = |name: &str| hdr.get_field_bytes(name).unwrap_or_default();
+    match hdr.name() {
+        "Ether" => format!(
+            "Ether {} > {}",
+            MacAddr::from(field("src")),
+            MacAddr::from(field("dst"))
+        ),
+        "Vlan" => format!("Vlan vid={} pcp={}", field("vid"), field("pcp")),
+        "IPv4" => format!(
+            "IPv4 {} > {} ttl={}",
+            u64_to_ipv4(field("src")),
+            u64_to_ipv4(field("dst")),
+            field("ttl")
+        ),
+        "IPv6" => format!(
+            "IPv6 {} > {} hlim={}",
+            bytes_to_ipv6(&bytes("src")).unwrap(),
+            bytes_to_ipv6(&bytes("dst")).unwrap(),
+            field("hop_limit")
+        ),
+        "TCP" => {
+            let flags: Vec<&str> = (0..TCP_FLAGS.len())
+                .filter(|i| field("flags") & (1 << i) != 0)
+                .map(|i| TCP_FLAGS[i])
+                .collect();
+            format!(
+                "TCP {} > {} [{}]",
+                field("src"),
+                field("dst"),
+                flags.join(",")
+            )
+        }
+        "UDP" => format!("UDP {} > {}", field("src"), field("dst")),
+        "UdpLite" => format!("UdpLite {} > {}", field("src"), field("dst")),
+        "Vxlan" => format!("Vxlan vni={}", field("vni")),
+        name => {
+            let mut out = String::from(name);
+            for f in hdr.fields().iter().filter(|f| !f.reserved).take(DEFAULT_FIELDS) {
+                match f.size() {
+                    0..=64 => write!(out, " {}={}", f.name, field(f.name)).unwrap(),
+                    _ => {
+                        write!(out, " {}=0x", f.name).unwrap();
+                        for b in bytes(f.name) {
+                            write!(out, "{:02x}", b).unwrap();
+                        }
+                    }
+                }
+            }
+            out
+        }
+    }
+}
+
+impl Packet {
+    /// Summarize the packet on one line, the layers are separated by `/`
+    ///
+    /// The line ends with the length of the payload.
+    pub fn summary(&self) -> String {
+        let layers: Vec<String> = self.hdrs.iter().map(|x| x.summary()).collect();
+        format!("{} len={}", layers.join(" / "), self.payload.len())
+    }
+}
+
+/// Summarize each packet on its own line, prefixed by its index
+pub fn summaries(pkts: &[Packet]) -> String {
+    let mut out = String::new();
+    for (i, pkt) in pkts.iter().enumerate() {
+        writeln!(out, "{:4} {}", i, pkt.summary()).unwrap();
+    }
+    out
+}
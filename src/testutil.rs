@@ -0,0 +1,363 @@
+//! # Test helpers
+//!
+//! [`assert_checksum_roundtrips`] checks the checksum of a header the same way whatever its type,
+//! for the unit tests of code that edits headers, and [`assert_layer_checksum_roundtrips`] the
+//! checksum of a layer of a packet, with the pseudo header of its IP layer.
+//!
+//! ```
+//! # use packet_rs::headers::*;
+//! # use packet_rs::testutil::assert_checksum_roundtrips;
+//! let mut ipv4 = IPv4::new();
+//! ipv4.set_ttl(1);
+//! assert_eq!(assert_checksum_roundtrips(&ipv4), 0xf780);
+//! ```
+//!
+//! [`random_packet`] generates a packet of random layers and field values from a seed, and
+//! [`assert_parse_roundtrips`] serializes a packet, parses the bytes back and checks the layers
+//! and the bytes match, with the slow and the fast parser. Together they catch a header the
+//! parser and the serializer disagree on, or a demux value dispatching to the wrong layer.
+//! [`assert_fields_roundtrip`] catches fields overlapping in a header definition.
+//!
+//! ```
+//! # use packet_rs::testutil::{assert_parse_roundtrips, random_packet};
+//! for seed in 0..100 {
+//!     assert_parse_roundtrips(&random_packet(seed));
+//! }
+//! ```
+
+use crate::alloc_prelude::*;
+use crate::bind;
+use crate::headers::*;
+use crate::icmp::inet_checksum;
+use crate::parser;
+use crate::payload::{random_seeded, splitmix64};
+use crate::types::*;
+use crate::Packet;
+
+/// Tunnels nested at most in a random packet
+const MAX_TUNNELS: usize = 2;
+/// Bytes of random payload at most
+const MAX_PAYLOAD: u64 = 64;
+/// ICMPv6 echo request, replacing the types the parser dissects as MLD
+const ICMPV6_ECHO_REQUEST: u64 = 128;
+/// ICMPv6 types the parser dissects as MLD
+const MLD_TYPES: [u64; 4] = [
+    MLD_QUERY as u64,
+    MLD_REPORT as u64,
+    MLD_DONE as u64,
+    MLD_V2_REPORT as u64,
+];
+
+/// Compute the checksum of a header alone and verify it, see
+/// [`assert_layer_checksum_roundtrips`]
+///
+/// The checksum covers the header alone, as the one of IPv4, IGMP or an ICMP message with no
+/// data. Panics as [`assert_layer_checksum_roundtrips`] does, and if the checksum covers a pseudo
+/// header, as the TCP and UDP ones: check those in their packet.
+pub fn assert_checksum_roundtrips(hdr: &dyn Header) -> u16 {
+    let mut pkt = Packet::new();
+    pkt.hdrs.push(hdr.clone_box());
+    assert_layer_checksum_roundtrips(&pkt, 0)
+}
+
+/// Compute the checksum of the layer at index of a copy of pkt, as
+/// [`Packet::update_checksums`](crate::Packet::update_checksums) does, and verify it over the
+/// bytes of the copy on the wire
+///
+/// Returns the checksum, so a test can compare it with a known value. The bytes verified are
+/// gathered from the wire bytes alone: the IPv4 header up to its `ihl`, or the layer up to the
+/// end of the payload of the IP layer before it, after the pseudo header built from that IP
+/// layer for TCP, UDP, UDP-Lite, ICMPv6 and MLD.
+///
+/// Panics if the layer has no byte aligned checksum field, if its checksum covers a pseudo
+/// header and no IP layer comes before it, or if the checksum read back, its bytes on the wire or
+/// the sum over the bytes covered does not verify.
+pub fn assert_layer_checksum_roundtrips(pkt: &Packet, index: usize) -> u16 {
+    let name = pkt.hdrs[index].name().to_string();
+    let field = pkt.hdrs[index]
+        .checksum_field()
+        .unwrap_or_else(|| panic!("{} has no checksum field", name));
+    let range = field
+        .byte_range()
+        .unwrap_or_else(|| panic!("{}.{} is not byte aligned", name, field.name));
+    let mut copy = pkt.clone();
+    copy.clear_poison();
+    copy.hdrs[index].set_field(field.name, 0);
+    copy.update_checksums();
+    let hdr = copy.hdrs[index].as_ref();
+    let chksum = hdr.get_field(field.name).unwrap() as u16;
+
+    let v = copy.to_vec();
+    let offset: usize = copy.hdrs[..index].iter().map(|x| x.len()).sum();
+    let range = offset + range.start..offset + range.end;
+    assert!(
+        v[range.clone()] == chksum.to_be_bytes(),
+        "{}.{} is written as {:02x?} instead of {:#06x}",
+        name,
+        field.name,
+        &v[range],
+        chksum
+    );
+    let covered = covered_bytes(&copy, index, &v);
+    let sum = !inet_checksum(&covered);
+    assert!(
+        sum == 0xffff,
+        "{} checksum {:#06x} does not verify, the bytes it covers sum to {:#06x} instead of 0xffff",
+        name,
+        chksum,
+        sum
+    );
+    chksum
+}
+
+/// Bytes covered by the checksum of the layer at index, read from the wire bytes v of pkt
+fn covered_bytes(pkt: &Packet, index: usize, v: &[u8]) -> Vec<u8> {
+    let hdr = pkt.hdrs[index].as_ref();
+    let offset: usize = pkt.hdrs[..index].iter().map(|x| x.len()).sum();
+    if let Some(ipv4) = hdr.as_any().downcast_ref::<IPv4>() {
+        let end = offset + ipv4.ihl() as usize * 4;
+        return v[offset..end.min(v.len())].to_vec();
+    }
+    // the nearest IP layer before the layer, with its offset
+    let ip = (0..index).rev().find_map(|i| {
+        let ip = pkt.hdrs[i].as_ref();
+        let offset: usize = pkt.hdrs[..i].iter().map(|x| x.len()).sum();
+        let end = match ip.as_any() {
+            x if x.is::<IPv4>() => offset + x.downcast_ref::<IPv4>()?.total_len() as usize,
+            x if x.is::<IPv6>() => {
+                offset + IPv6::size() + x.downcast_ref::<IPv6>()?.payload_len() as usize
+            }
+            _ => return None,
+        };
+        Some((ip.get_field_bytes("src")?, ip.get_field_bytes("dst")?, end))
+    });
+    let any = hdr.as_any();
+    let proto = if any.is::<TCP>() {
+        Some(IpProtocol::TCP)
+    } else if any.is::<UDP>() {
+        Some(IpProtocol::UDP)
+    } else if any.is::<UdpLite>() {
+        Some(IpProtocol::UDPLITE)
+    } else if any.is::<Mld>()
+        || any.is::<MldV2Report>()
+        || any.is::<ICMP>() && ip.as_ref().is_some_and(|x| x.0.len() == 16)
+    {
+        Some(IpProtocol::ICMPV6)
+    } else {
+        None
+    };
+    let end = ip.as_ref().map_or(v.len(), |x| x.2).clamp(offset, v.len());
+    let mut segment = v[offset..end].to_vec();
+    let proto = match proto {
+        Some(proto) => proto,
+        None => return segment,
+    };
+    let (src, dst, _) = ip.unwrap_or_else(|| {
+        panic!(
+            "{} checksum covers a pseudo header, no IP layer before it",
+            hdr.name()
+        )
+    });
+    let len = segment.len() as u32;
+    if let Some(udplite) = any.downcast_ref::<UdpLite>() {
+        // the coverage counts from the start of the header, zero covers the whole datagram
+        match udplite.coverage() as usize {
+            0 => (),
+            n => segment.truncate(n),
+        }
+    }
+    let mut covered = [src, dst].concat();
+    covered.extend_from_slice(&[0, proto as u8]);
+    covered.extend_from_slice(&len.to_be_bytes());
+    covered.extend(segment);
+    covered
+}
+
+/// Set every field of a copy of the header to random bits from seed and read them all back
+///
+/// Returns the copy, so a test can go on with random field values. Panics if a field reads back
+/// another value, as when two fields overlap and the field set last changes bits of the other.
+pub fn assert_fields_roundtrip(hdr: &dyn Header, seed: u64) -> Box<dyn Header> {
+    let mut state = seed;
+    let mut copy = hdr.clone_box();
+    let mut values = Vec::new();
+    for f in hdr.fields() {
+        let mut bytes = random_seeded(splitmix64(&mut state), f.size().div_ceil(8));
+        if f.size() % 8 != 0 {
+            bytes[0] &= (1 << (f.size() % 8)) - 1;
+        }
+        copy.set_field_bytes(f.name, &bytes);
+        values.push(bytes);
+    }
+    for (f, value) in hdr.fields().iter().zip(values) {
+        let read = copy.get_field_bytes(f.name).unwrap();
+        assert!(
+            trim_zeros(&read) == trim_zeros(&value),
+            "{}.{} reads {:02x?} after writing {:02x?}, another field overlaps it",
+            hdr.name(),
+            f.name,
+            read,
+            value
+        );
+    }
+    copy
+}
+
+fn trim_zeros(v: &[u8]) -> &[u8] {
+    let start = v.iter().position(|&x| x != 0).unwrap_or(v.len());
+    &v[start..]
+}
+
+/// Generate a packet of random layers, field values and payload from seed
+///
+/// The layers are the ones the parser dissects: Ethernet, VLAN or MPLS, IPv4 or IPv6, then TCP,
+/// UDP, UDP-Lite, ICMP or a VXLAN or GRE tunnel around another packet, or ARP. The fields are
+/// random except the ones selecting the next layer, set as [`Packet::to_vec_with_fixup`] sets
+/// them, and the values the parser would dissect further, as a UDP port bound to a header. The
+/// same seed always returns the same packet.
+pub fn random_packet(seed: u64) -> Packet {
+    let mut state = seed;
+    let mut hdrs: Vec<Box<dyn Header>> = Vec::new();
+    random_frame(&mut state, &mut hdrs, 0);
+    let mut pkt = Packet::new();
+    pkt.hdrs = hdrs;
+    let names: Vec<String> = pkt.iter().map(|x| String::from(x.name())).collect();
+    for i in 0..names.len() {
+        pkt.fixup_layer(i, &names);
+    }
+    let len = splitmix64(&mut state) % (MAX_PAYLOAD + 1);
+    pkt.set_payload(&random_seeded(splitmix64(&mut state), len as usize));
+    pkt
+}
+
+/// Push a copy of the header with random fields
+fn random_header(state: &mut u64, hdrs: &mut Vec<Box<dyn Header>>, hdr: &dyn Header) {
+    hdrs.push(assert_fields_roundtrip(hdr, splitmix64(state)));
+}
+
+/// Set a field of the header pushed last
+fn set_last(hdrs: &mut [Box<dyn Header>], field: &str, value: u64) {
+    hdrs.last_mut().unwrap().set_field(field, value);
+}
+
+/// Return true if a binding dissects the layer after the field holding value
+fn bound(layer: &str, field: &str, value: u64) -> bool {
+    bind::current().lookup(layer, field, value).is_some()
+}
+
+fn random_frame(state: &mut u64, hdrs: &mut Vec<Box<dyn Header>>, tunnels: usize) {
+    random_header(state, hdrs, &Ether::new());
+    match splitmix64(state) % 4 {
+        0 => {
+            for _ in 0..=splitmix64(state) % 2 {
+                random_header(state, hdrs, &Vlan::new());
+            }
+        }
+        // the bottom of the stack is followed by the IP version
+        1 => {
+            for _ in 0..=splitmix64(state) % 3 {
+                random_header(state, hdrs, &MPLS::new());
+            }
+            return random_ip(state, hdrs, tunnels);
+        }
+        _ => (),
+    }
+    if splitmix64(state).is_multiple_of(8) {
+        return random_header(state, hdrs, &ARP::new());
+    }
+    random_ip(state, hdrs, tunnels)
+}
+
+fn random_ip(state: &mut u64, hdrs: &mut Vec<Box<dyn Header>>, tunnels: usize) {
+    let v6 = splitmix64(state).is_multiple_of(2);
+    if v6 {
+        random_header(state, hdrs, &IPv6::new());
+        set_last(hdrs, "version", 6);
+    } else {
+        random_header(state, hdrs, &IPv4::new());
+        set_last(hdrs, "version", 4);
+    }
+    let tunnel = tunnels < MAX_TUNNELS;
+    match splitmix64(state) % 6 {
+        0 => random_header(state, hdrs, &TCP::new()),
+        1 => random_header(state, hdrs, &UdpLite::new()),
+        2 => {
+            random_header(state, hdrs, &ICMP::new());
+            let icmp_type = hdrs.last().unwrap().get_field("icmp_type").unwrap();
+            if v6 && MLD_TYPES.contains(&icmp_type) {
+                set_last(hdrs, "icmp_type", ICMPV6_ECHO_REQUEST);
+            }
+        }
+        3 if tunnel => {
+            random_header(state, hdrs, &UDP::new());
+            random_header(state, hdrs, &Vxlan::new());
+            random_frame(state, hdrs, tunnels + 1);
+        }
+        4 if tunnel => {
+            random_header(state, hdrs, &GRE::new());
+            if splitmix64(state).is_multiple_of(2) {
+                random_header(state, hdrs, &GREChksumOffset::new());
+            }
+            if splitmix64(state).is_multiple_of(2) {
+                random_header(state, hdrs, &GREKey::new());
+            }
+            if splitmix64(state).is_multiple_of(2) {
+                random_header(state, hdrs, &GRESequenceNum::new());
+            }
+            random_ip(state, hdrs, tunnels + 1);
+        }
+        _ => {
+            random_header(state, hdrs, &UDP::new());
+            let dst = hdrs.last().unwrap().get_field("dst").unwrap();
+            if matches!(dst as u16, UDP_PORT_VXLAN | UDP_PORT_L2TP) || bound("UDP", "dst", dst) {
+                set_last(hdrs, "dst", dst ^ 0x8000);
+            }
+        }
+    }
+}
+
+/// Serialize the packet, parse the bytes back and check the layers and the bytes match
+///
+/// Both the slow and the fast parser are checked. Returns the packet parsed by the slow parser.
+/// Panics naming the first layer or byte differing.
+pub fn assert_parse_roundtrips(pkt: &Packet) -> Packet {
+    let v = pkt.to_vec();
+    let names: Vec<&str> = pkt.iter().map(|x| x.name()).collect();
+
+    let parsed = parser::slow::parse(&v);
+    let slow: Vec<&str> = parsed.iter().map(|x| x.name()).collect();
+    assert!(
+        slow == names,
+        "slow parser dissects {:?} from {:?}",
+        slow,
+        names
+    );
+    assert_same_bytes("slow parser", &v, &parsed.to_vec());
+
+    let slice = parser::fast::parse(&v);
+    let fast: Vec<&str> = slice.hdrs.iter().map(|x| x.name()).collect();
+    assert!(
+        fast == names,
+        "fast parser dissects {:?} from {:?}",
+        fast,
+        names
+    );
+    assert_same_bytes("fast parser", &v, &slice.to_vec());
+    parsed
+}
+
+fn assert_same_bytes(parser: &str, expected: &[u8], actual: &[u8]) {
+    if let Some(i) =
+        (0..expected.len().max(actual.len())).find(|&i| expected.get(i) != actual.get(i))
+    {
+        panic!(
+            "{} serializes {} bytes differing from the {} parsed at byte {}: {:02x?} instead of {:02x?}",
+            parser,
+            actual.len(),
+            expected.len(),
+            i,
+            actual.get(i),
+            expected.get(i)
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! # Type-length-value options
+//!
+//! Shared encoding of the TLV options carried by IP, TCP, DHCP, ND or Geneve. [`TlvFormat`]
+//! describes the width of the type and length fields, whether the length counts them, the
+//! alignment padding and the single byte end of list and no-op options of IP and TCP.
+//!
+//! ```
+//! # use packet_rs::tlv::*;
+//! // tcp mss, no-op and window scale options
+//! let opts = vec![
+//!     Tlv::new(2, vec![0x05, 0xb4]).unwrap(),
+//!     Tlv::new(1, vec![]).unwrap(),
+//!     Tlv::new(3, vec![7]).unwrap(),
+//! ];
+//! let v = encode_tlvs_with(&opts, &TlvFormat::IP_OPTIONS).unwrap();
+//! assert_eq!(v, [2, 4, 0x05, 0xb4, 1, 3, 3, 7]);
+//! assert_eq!(parse_tlvs_with(&v, &TlvFormat::IP_OPTIONS).unwrap(), opts);
+//! ```
+
+use crate::alloc_prelude::*;
+use crate::PacketError;
+
+/// End of option list, a single byte option in IP and TCP
+pub const TLV_END: u16 = 0;
+/// No-operation, a single byte option in IP and TCP
+pub const TLV_NOP: u16 = 1;
+
+/// An option, the type and length are up to 2 bytes wide
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlv {
+    pub typ: u16,
+    /// Length of the value in bytes, encoded as is even if it does not match the value
+    pub len: u16,
+    pub value: Vec<u8>,
+}
+
+impl Tlv {
+    /// Create an option with the length of value, fails if value is longer than 65535 bytes
+    pub fn new(typ: u16, value: Vec<u8>) -> Result<Tlv, PacketError> {
+        let len = u16::try_from(value.len()).map_err(|_| PacketError::ValueOverflow {
+            field: String::from("len"),
+            value: value.len() as u64,
+            bits: 16,
+        })?;
+        Ok(Tlv { typ, len, value })
+    }
+}
+
+/// Layout of the options of a protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlvFormat {
+    /// Width of the type and length fields, 1 or 2 bytes
+    pub width: usize,
+    /// The length field counts the type and length fields
+    pub len_includes_header: bool,
+    /// Pad the options with zeros to a multiple of this many bytes
+    pub align: usize,
+    /// Types [`TLV_END`] and [`TLV_NOP`] are single bytes without a length
+    pub single_byte: bool,
+}
+
+impl TlvFormat {
+    /// IPv4 and TCP options
+    pub const IP_OPTIONS: TlvFormat = TlvFormat {
+        width: 1,
+        len_includes_header: true,
+        align: 4,
+        single_byte: true,
+    };
+}
+
+impl Default for TlvFormat {
+    /// One byte type and length of the value, no padding
+    fn default() -> Self {
+        TlvFormat {
+            width: 1,
+            len_includes_header: false,
+            align: 1,
+            single_byte: false,
+        }
+    }
+}
+
+/// Encode options with one byte type and length and no padding
+pub fn encode_tlvs(tlvs: &[Tlv]) -> Result<Vec<u8>, PacketError> {
+    encode_tlvs_with(tlvs, &TlvFormat::default())
+}
+
+/// Encode options in the given format, padding the result to the alignment
+///
+/// Fails if the length of an option does not fit in the length field.
+pub fn encode_tlvs_with(tlvs: &[Tlv], fmt: &TlvFormat) -> Result<Vec<u8>, PacketError> {
+    let mut v = Vec::new();
+    for tlv in tlvs {
+        append_tlv(&mut v, tlv, fmt)?;
+    }
+    v.resize(v.len().next_multiple_of(fmt.align.max(1)), 0);
+    Ok(v)
+}
+
+/// Append one option in the given format to buf, without padding
+///
+/// Fails if the length of the option does not fit in the length field, buf is then unchanged.
+pub fn append_tlv(buf: &mut Vec<u8>, tlv: &Tlv, fmt: &TlvFormat) -> Result<(), PacketError> {
+    if fmt.single_byte && (tlv.typ == TLV_END || tlv.typ == TLV_NOP) {
+        buf.push(tlv.typ as u8);
+        return Ok(());
+    }
+    let len = match fmt.len_includes_header {
+        true => tlv.len as u64 + 2 * fmt.width as u64,
+        false => tlv.len as u64,
+    };
+    let bits = 8 * fmt.width;
+    if len >> bits != 0 {
+        return Err(PacketError::ValueOverflow {
+            field: String::from("len"),
+            value: len,
+            bits,
+        });
+    }
+    buf.extend_from_slice(&tlv.typ.to_be_bytes()[2 - fmt.width..]);
+    buf.extend_from_slice(&(len as u16).to_be_bytes()[2 - fmt.width..]);
+    buf.extend_from_slice(&tlv.value);
+    Ok(())
+}
+
+/// Parse options with one byte type and length and no padding
+pub fn parse_tlvs(data: &[u8]) -> Result<Vec<Tlv>, PacketError> {
+    parse_tlvs_with(data, &TlvFormat::default())
+}
+
+/// Parse options in the given format
+///
+/// Parsing stops at an end of list option or at zero padding shorter than the alignment. Fails
+/// with [`PacketError::Parse`] at the offset of an option that runs past the end of data.
+pub fn parse_tlvs_with(data: &[u8], fmt: &TlvFormat) -> Result<Vec<Tlv>, PacketError> {
+    let invalid = |rest: &[u8], reason: String| PacketError::Parse {
+        layer: String::from("TLV"),
+        offset: data.len() - rest.len(),
+        reason,
+    };
+    let field = |b: &[u8]| b.iter().fold(0u16, |x, b| (x << 8) | *b as u16);
+    let mut tlvs = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        if fmt.single_byte && rest[0] as u16 == TLV_END {
+            break;
+        }
+        if fmt.single_byte && rest[0] as u16 == TLV_NOP {
+            tlvs.push(Tlv::new(TLV_NOP, Vec::new())?);
+            rest = &rest[1..];
+            continue;
+        }
+        if rest.len() < fmt.align && rest.iter().all(|b| *b == 0) {
+            break;
+        }
+        let hdr_len = 2 * fmt.width;
+        if rest.len() < hdr_len {
+            return Err(invalid(rest, format!("{} byte option header", rest.len())));
+        }
+        let typ = field(&rest[..fmt.width]);
+        let len = field(&rest[fmt.width..hdr_len]) as usize;
+        let value_len = match fmt.len_includes_header {
+            true => len
+                .checked_sub(hdr_len)
+                .ok_or_else(|| invalid(rest, format!("length {} below header", len)))?,
+            false => len,
+        };
+        let value = rest
+            .get(hdr_len..hdr_len + value_len)
+            .ok_or_else(|| invalid(rest, format!("length {} past the end", len)))?;
+        tlvs.push(Tlv::new(typ, value.to_vec())?);
+        rest = &rest[hdr_len + value_len..];
+    }
+    Ok(tlvs)
+}
@@ -0,0 +1,255 @@
+//! # Flow tracking
+//!
+//! [`FlowTracker`] groups packets into flows by their [`FlowTuple`], both directions of a
+//! conversation in the same flow, and keeps per flow counters: packets and bytes in each
+//! direction, first and last timestamps, the TCP connection state and retransmitted and out of
+//! order TCP segments. Packets come from a capture with [`FlowTracker::add_record`], from a
+//! capture ring with [`FlowTracker::add_frame`] or from anywhere else with a timestamp.
+//!
+//! With the `serde` feature the flows serialize for post-processing.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::tracker::*;
+//! # use std::time::Duration;
+//! let mut tracker = FlowTracker::new();
+//! let cfg = PacketConfig { tcp_flags: 0x02, ..Default::default() };
+//! let syn = create_tcp_packet(&cfg);
+//! tracker.add(&syn, Duration::from_secs(1));
+//! let reply = create_tcp_packet(&PacketConfig {
+//!     ip_src: cfg.ip_dst.clone(),
+//!     ip_dst: cfg.ip_src.clone(),
+//!     sport: cfg.dport,
+//!     dport: cfg.sport,
+//!     tcp_flags: 0x04,
+//!     ..Default::default()
+//! });
+//! tracker.add(&reply, Duration::from_secs(2));
+//! assert_eq!(tracker.len(), 1);
+//! let flow = tracker.get(&reply.flow_tuple().unwrap()).unwrap();
+//! assert_eq!((flow.forward.packets, flow.reverse.packets), (1, 1));
+//! assert_eq!(flow.tcp_state, Some(TcpState::Reset));
+//! ```
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::capture::CapturedFrame;
+use crate::flow::FlowTuple;
+use crate::headers::*;
+use crate::pcap::PcapRecord;
+use crate::reassembly::tcp_payload;
+use crate::{parser, Packet};
+
+const FIN: u64 = 0x01;
+const SYN: u64 = 0x02;
+const RST: u64 = 0x04;
+const ACK: u64 = 0x10;
+
+/// State of a TCP connection as seen from the packets of both directions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TcpState {
+    /// The initiator sent a SYN
+    SynSent,
+    /// The responder answered with a SYN ACK
+    SynReceived,
+    /// The handshake completed, or the flow was first seen after it
+    Established,
+    /// One side sent a FIN
+    Closing,
+    /// Both sides sent a FIN
+    Closed,
+    /// Either side sent a RST
+    Reset,
+}
+
+/// Counters of one direction of a flow
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DirectionStats {
+    pub packets: u64,
+    /// Bytes on the wire, including the link layer
+    pub bytes: u64,
+    /// TCP segments starting before the next expected sequence number
+    pub retransmissions: u64,
+    /// TCP segments starting past the next expected sequence number
+    pub out_of_order: u64,
+    /// Sequence number following the furthest segment seen
+    #[cfg_attr(feature = "serde", serde(skip))]
+    next_seq: Option<u32>,
+}
+
+impl DirectionStats {
+    /// Count a TCP segment of len sequence numbers starting at seq
+    fn track_seq(&mut self, seq: u32, len: u32) {
+        let next = match self.next_seq {
+            Some(x) => x,
+            None => {
+                self.next_seq = Some(seq.wrapping_add(len));
+                return;
+            }
+        };
+        if len == 0 {
+            return;
+        }
+        // sequence numbers wrap, compare their distance
+        let ahead = seq.wrapping_sub(next) as i32;
+        if ahead < 0 {
+            self.retransmissions += 1;
+            let end = seq.wrapping_add(len);
+            if (end.wrapping_sub(next) as i32) > 0 {
+                self.next_seq = Some(end);
+            }
+        } else {
+            if ahead > 0 {
+                self.out_of_order += 1;
+            }
+            self.next_seq = Some(seq.wrapping_add(len));
+        }
+    }
+}
+
+/// A flow and its counters
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FlowStats {
+    /// Tuple of the first packet seen, its source is taken as the initiator
+    pub tuple: FlowTuple,
+    /// Packets from the initiator
+    pub forward: DirectionStats,
+    /// Packets to the initiator
+    pub reverse: DirectionStats,
+    /// Timestamp of the first packet, as a duration since the unix epoch
+    pub first: Duration,
+    /// Timestamp of the last packet, as a duration since the unix epoch
+    pub last: Duration,
+    /// Connection state of a TCP flow, None for other protocols
+    pub tcp_state: Option<TcpState>,
+}
+
+impl FlowStats {
+    /// Return the packets of both directions
+    pub fn packets(&self) -> u64 {
+        self.forward.packets + self.reverse.packets
+    }
+    /// Return the bytes of both directions
+    pub fn bytes(&self) -> u64 {
+        self.forward.bytes + self.reverse.bytes
+    }
+    /// Return the time between the first and last packets
+    pub fn duration(&self) -> Duration {
+        self.last.saturating_sub(self.first)
+    }
+    fn track_tcp(&mut self, flags: u64, forward: bool) {
+        let state = match (self.tcp_state, forward) {
+            _ if flags & RST != 0 => TcpState::Reset,
+            (Some(TcpState::Reset), _) => TcpState::Reset,
+            (Some(TcpState::Closing), _) if flags & FIN != 0 => TcpState::Closed,
+            (Some(TcpState::Closed), _) => TcpState::Closed,
+            (_, _) if flags & FIN != 0 => TcpState::Closing,
+            (None, true) if flags & (SYN | ACK) == SYN => TcpState::SynSent,
+            (Some(TcpState::SynSent), false) if flags & (SYN | ACK) == SYN | ACK => {
+                TcpState::SynReceived
+            }
+            (Some(TcpState::SynReceived), true) if flags & (SYN | ACK) == ACK => {
+                TcpState::Established
+            }
+            (None, _) => TcpState::Established,
+            (Some(state), _) => state,
+        };
+        self.tcp_state = Some(state);
+    }
+}
+
+/// Per flow counters of the packets added
+#[derive(Debug, Clone, Default)]
+pub struct FlowTracker {
+    flows: HashMap<FlowTuple, FlowStats>,
+}
+
+impl FlowTracker {
+    /// Create a tracker with no flows
+    pub fn new() -> FlowTracker {
+        FlowTracker::default()
+    }
+    /// Add a packet seen at ts, a duration since the unix epoch
+    ///
+    /// Packets without an IPv4 or IPv6 layer are ignored.
+    pub fn add(&mut self, pkt: &Packet, ts: Duration) {
+        self.update(pkt, ts, pkt.len());
+    }
+    /// Add a packet of a capture, counting its length on the wire
+    pub fn add_record(&mut self, record: &PcapRecord) {
+        let ts = Duration::new(record.ts_sec as u64, record.ts_nsec);
+        self.update(&record.packet, ts, record.orig_len as usize);
+    }
+    /// Add a frame held by a capture ring
+    pub fn add_frame(&mut self, frame: &CapturedFrame) {
+        let ts = frame
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.update(&parser::slow::parse(&frame.data), ts, frame.data.len());
+    }
+    fn update(&mut self, pkt: &Packet, ts: Duration, len: usize) {
+        let tuple = match pkt.flow_tuple() {
+            Some(x) => x,
+            None => return,
+        };
+        let flow = self
+            .flows
+            .entry(tuple.normalized())
+            .or_insert_with(|| FlowStats {
+                tuple,
+                forward: DirectionStats::default(),
+                reverse: DirectionStats::default(),
+                first: ts,
+                last: ts,
+                tcp_state: None,
+            });
+        let forward = flow.tuple == tuple;
+        flow.first = flow.first.min(ts);
+        flow.last = flow.last.max(ts);
+        let dir = match forward {
+            true => &mut flow.forward,
+            false => &mut flow.reverse,
+        };
+        dir.packets += 1;
+        dir.bytes += len as u64;
+        if let (Some(tcp), Some(data)) = (pkt.layer::<TCP>(), tcp_payload(pkt)) {
+            let flags = tcp.flags();
+            let len = data.len() as u32 + (flags & SYN != 0) as u32 + (flags & FIN != 0) as u32;
+            dir.track_seq(tcp.seq_no() as u32, len);
+            flow.track_tcp(flags, forward);
+        }
+    }
+    /// Return the flow of a tuple, in either direction
+    pub fn get(&self, tuple: &FlowTuple) -> Option<&FlowStats> {
+        self.flows.get(&tuple.normalized())
+    }
+    /// Return the number of flows
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+    /// Return true if no packet was added
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+    /// Iterate over the flows in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &FlowStats> {
+        self.flows.values()
+    }
+    /// Return the flows, most bytes first
+    pub fn by_bytes(&self) -> Vec<&FlowStats> {
+        let mut flows: Vec<_> = self.iter().collect();
+        flows.sort_by_key(|x| (std::cmp::Reverse(x.bytes()), x.first));
+        flows
+    }
+    /// Return the flows, most packets first
+    pub fn by_packets(&self) -> Vec<&FlowStats> {
+        let mut flows: Vec<_> = self.iter().collect();
+        flows.sort_by_key(|x| (std::cmp::Reverse(x.packets()), x.first));
+        flows
+    }
+}
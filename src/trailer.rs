@@ -0,0 +1,225 @@
+//! # Trailers
+//!
+//! Trailers are headers placed after the payload, as the Ethernet FCS or the hardware timestamp
+//! some capture cards append after it. [`Packet::push_trailer`] adds them outermost first: the
+//! first trailer pushed ends the packet and each following one sits before it, as headers are
+//! pushed outermost first from the start of the packet.
+//!
+//! A [`Trailer`] tells [`parse`] how many bytes a trailer claims at the end of the buffer, from
+//! its fixed length or from a length field. The trailers are claimed outermost first, the bytes
+//! left are dissected by [`parser::slow::parse`](crate::parser::slow::parse).
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::trailer;
+//! let pkt = create_udp_packet(&Default::default());
+//! let mut ts = HwTimestamp::new();
+//! ts.set_seconds(1_700_000_000);
+//! let mut bytes = pkt.to_vec();
+//! bytes.extend(ts.to_vec());
+//! let parsed = trailer::parse(&bytes, &[trailer::HW_TIMESTAMP]);
+//! assert!(parsed.layer::<UDP>().is_some());
+//! assert_eq!(parsed.trailer::<HwTimestamp>().unwrap().seconds(), 1_700_000_000);
+//! assert_eq!(parsed.to_vec(), bytes);
+//! ```
+//!
+//! A trailer of variable length reads its length field from the end of the bytes not claimed
+//! yet, here one ending with its length in bytes:
+//!
+//! ```
+//! # #[macro_use] extern crate packet_rs;
+//! # use packet_rs::headers::*;
+//! # use packet_rs::trailer::{self, Trailer};
+//! make_header!(
+//! Tag 5
+//! (
+//!     value: 0-31,
+//!     length: 32-39
+//! )
+//! );
+//! const TAG: Trailer = Trailer {
+//!     name: "Tag",
+//!     claim: |x| x.last().map(|&n| n as usize).filter(|&n| n > 0),
+//!     from: |x| Box::new(Tag::from(x.to_vec())),
+//! };
+//! # fn main() {
+//! let mut bytes = vec![0u8; 60];
+//! bytes.extend([0xaa, 0xbb, 0xcc, 0xdd, 0x05]);
+//! let pkt = trailer::parse(&bytes, &[TAG]);
+//! assert_eq!(pkt.trailers_len(), 5);
+//! assert_eq!(pkt.trailer::<Tag>().unwrap().value(), 0xaabbccdd);
+//! # }
+//! ```
+
+use crate::alloc_prelude::*;
+use crate::flow::crc32;
+use crate::headers::*;
+use crate::{parser, Packet};
+
+/// A header at the end of the packet, claimed by the dissection before the layers
+#[derive(Debug, Clone, Copy)]
+pub struct Trailer {
+    /// Name of the trailer
+    pub name: &'static str,
+    /// Number of bytes the trailer covers at the end of the bytes, None when it is absent
+    pub claim: fn(&[u8]) -> Option<usize>,
+    /// Create the trailer from the bytes it claims
+    pub from: fn(&[u8]) -> Box<dyn Header>,
+}
+
+/// Ethernet FCS, claimed when it matches the CRC-32 of the bytes before it
+pub const FCS: Trailer = Trailer {
+    name: "EthFcs",
+    claim: |x| {
+        let n = x.len().checked_sub(EthFcs::size())?;
+        let fcs = u32::from_le_bytes([x[n], x[n + 1], x[n + 2], x[n + 3]]);
+        (n >= Ether::size() && crc32(&x[..n]) == fcs).then_some(EthFcs::size())
+    },
+    from: |x| Box::new(EthFcs::from(x.to_vec())),
+};
+
+/// Hardware timestamp of seconds and nanoseconds, always present
+pub const HW_TIMESTAMP: Trailer = Trailer {
+    name: "HwTimestamp",
+    claim: |_| Some(HwTimestamp::size()),
+    from: |x| Box::new(HwTimestamp::from(x.to_vec())),
+};
+
+/// Hardware timestamp of 32 bits, always present
+pub const HW_TIMESTAMP32: Trailer = Trailer {
+    name: "HwTimestamp32",
+    claim: |_| Some(HwTimestamp32::size()),
+    from: |x| Box::new(HwTimestamp32::from(x.to_vec())),
+};
+
+/// Parse a packet whose bytes end with trailers, listed outermost first
+///
+/// A trailer claiming no bytes, or more than the bytes left, is skipped.
+///
+/// Panics if the header created by [`Trailer::from`] is not as long as the bytes claimed, they
+/// would be lost or duplicated when the packet is written back.
+/// # Example
+///
+/// ```
+/// # use packet_rs::builders::*;
+/// # use packet_rs::headers::*;
+/// # use packet_rs::trailer;
+/// let mut pkt = create_udp_packet(&Default::default());
+/// pkt.push_trailer(Box::new(HwTimestamp32::new()));
+/// pkt.push_trailer(Box::new(EthFcs::new()));
+/// let pkt = trailer::parse(&pkt.to_vec(), &[trailer::HW_TIMESTAMP32, trailer::FCS]);
+/// // the fcs does not match, so it is left in the payload
+/// let names: Vec<_> = pkt.trailers().map(|x| x.name()).collect();
+/// assert_eq!(names, vec!["HwTimestamp32"]);
+/// ```
+pub fn parse(arr: &[u8], trailers: &[Trailer]) -> Packet {
+    let mut end = arr.len();
+    let mut claimed = Vec::new();
+    for trailer in trailers {
+        match (trailer.claim)(&arr[..end]) {
+            Some(n) if n > 0 && n <= end => {
+                let hdr = (trailer.from)(&arr[end - n..end]);
+                assert_eq!(
+                    hdr.len(),
+                    n,
+                    "trailer {} created from {} bytes is {} bytes long",
+                    trailer.name,
+                    n,
+                    hdr.len()
+                );
+                claimed.push(hdr);
+                end -= n;
+            }
+            _ => (),
+        }
+    }
+    let mut pkt = parser::slow::parse(&arr[..end]);
+    for hdr in claimed {
+        pkt.push_trailer(hdr);
+    }
+    pkt
+}
+
+impl Packet {
+    /// Add a trailer before the trailers already pushed
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::headers::*;
+    /// # use packet_rs::Packet;
+    /// let mut pkt = Packet::new();
+    /// pkt.push(Ether::new());
+    /// pkt.set_payload(&[1, 2]);
+    /// pkt.push_trailer(Box::new(HwTimestamp32::new()));
+    /// pkt.push_trailer(Box::new(EthFcs::new()));
+    /// let v = pkt.to_vec();
+    /// assert_eq!(v.len(), 14 + 2 + 4 + 4);
+    /// // the timestamp ends the packet, after the fcs
+    /// assert_eq!(v[14..], [1, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+    /// ```
+    pub fn push_trailer(&mut self, hdr: Box<dyn Header>) {
+        self.trailers.push(hdr);
+        self.invalidate();
+    }
+    /// Remove the trailer pushed last, the innermost one
+    pub fn pop_trailer(&mut self) -> Option<Box<dyn Header>> {
+        self.invalidate();
+        self.trailers.pop()
+    }
+    /// Iterate over the trailers, outermost first
+    pub fn trailers(&self) -> impl Iterator<Item = &dyn Header> {
+        self.trailers.iter().map(|x| x.as_ref())
+    }
+    /// Get the first trailer of type `T`
+    pub fn trailer<T: Header + 'static>(&self) -> Option<&T> {
+        self.trailers
+            .iter()
+            .find_map(|x| x.as_any().downcast_ref::<T>())
+    }
+    /// Get mutable access to the first trailer of type `T`
+    pub fn trailer_mut<T: Header + 'static>(&mut self) -> Option<&mut T> {
+        self.invalidate();
+        self.trailers
+            .iter_mut()
+            .find_map(|x| x.as_any_mut().downcast_mut::<T>())
+    }
+    /// Return the number of bytes of the trailers
+    pub fn trailers_len(&self) -> usize {
+        self.trailers.iter().map(|x| x.len()).sum()
+    }
+    /// Set the IPv4 total length, IPv6 payload length and UDP length from the bytes after them
+    ///
+    /// The lengths end with the payload, the trailers are not covered. Ethernet padding in the
+    /// payload is counted, poisoned fields keep their value. Checksums are not updated.
+    /// # Example
+    ///
+    /// ```
+    /// # use packet_rs::builders::*;
+    /// # use packet_rs::headers::*;
+    /// let mut pkt = create_udp_packet(&Default::default());
+    /// let total = pkt.layer::<IPv4>().unwrap().total_len();
+    /// pkt.push_trailer(Box::new(HwTimestamp::new()));
+    /// pkt.layer_mut::<IPv4>().unwrap().set_total_len(0);
+    /// pkt.update_lengths();
+    /// assert_eq!(pkt.layer::<IPv4>().unwrap().total_len(), total);
+    /// ```
+    pub fn update_lengths(&mut self) {
+        self.invalidate();
+        let end = self.len() - self.trailers_len();
+        let mut offset = 0;
+        for i in 0..self.hdrs.len() {
+            let rest = end - offset;
+            offset += self.hdrs[i].len();
+            let (field, value) = match self.hdrs[i].name() {
+                "IPv4" => ("total_len", rest),
+                "IPv6" => ("payload_len", rest.saturating_sub(IPv6::size())),
+                "UDP" => ("length", rest),
+                _ => continue,
+            };
+            if !self.is_poisoned(i, field) {
+                self.hdrs[i].set_field(field, value as u64);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! # VXLAN encapsulation
+//!
+//! [`Packet::vxlan_encap`] wraps a packet as the inner frame of an Ether/IP/UDP/VXLAN stack with
+//! correct lengths and checksums, [`Packet::vxlan_decap`] validates the outer stack and returns the
+//! inner packet.
+//!
+//! The UDP source port is derived from the inner packet by [`VxlanOuterParams::sport`], vendors
+//! hash differently so it can be replaced to match the device under test.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::tunnel::VxlanOuterParams;
+//! let inner = create_tcp_packet(&Default::default());
+//! let outer = inner
+//!     .vxlan_encap(&VxlanOuterParams {
+//!         vni: 2000,
+//!         ..Default::default()
+//!     })
+//!     .unwrap();
+//! let decap = outer.vxlan_decap().unwrap();
+//! assert!(decap.compare(&inner));
+//! ```
+
+use core::net::{IpAddr, Ipv4Addr};
+
+use crate::addr::MacAddr;
+use crate::flow::{crc32, symmetric_hash};
+use crate::headers::*;
+use crate::parser;
+use crate::types::*;
+use crate::{Packet, PacketError};
+
+/// First port of the range the default source port hash maps into
+pub const VXLAN_SPORT_BASE: u16 = 49152;
+/// VXLAN flags with the valid VNI bit set
+pub const VXLAN_FLAG_VNI: u8 = 0x08;
+
+/// Outer headers of a VXLAN encapsulation
+#[derive(Debug, Clone, Copy)]
+pub struct VxlanOuterParams {
+    pub eth_dst: MacAddr,
+    pub eth_src: MacAddr,
+    /// Outer source address, the outer header is IPv6 if both addresses are IPv6
+    ///
+    /// An IPv6 address with an IPv4 outer header must be an IPv4-mapped one.
+    pub ip_src: IpAddr,
+    pub ip_dst: IpAddr,
+    /// IPv4 ttl or IPv6 hop limit
+    pub ip_ttl: u8,
+    pub vni: u32,
+    /// Compute the UDP checksum, otherwise it is left zero
+    pub udp_checksum: bool,
+    /// Derive the UDP source port from the inner packet
+    pub sport: fn(&Packet) -> u16,
+}
+
+impl Default for VxlanOuterParams {
+    fn default() -> Self {
+        VxlanOuterParams {
+            eth_dst: MacAddr([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]),
+            eth_src: MacAddr([0x00, 0x06, 0x07, 0x08, 0x09, 0x0a]),
+            ip_src: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+            ip_dst: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 2)),
+            ip_ttl: 64,
+            vni: 2000,
+            udp_checksum: false,
+            sport: vxlan_sport,
+        }
+    }
+}
+
+/// Default source port, a hash of the inner flow mapped into the dynamic port range
+///
+/// The hash is symmetric so both directions of a flow use the same port. Inner frames without
+/// an IP header are hashed on their ethernet header.
+pub fn vxlan_sport(inner: &Packet) -> u16 {
+    let hash = match inner.inner_flow_tuple() {
+        Some(tuple) => symmetric_hash(&tuple),
+        None => {
+            let v = inner.to_vec();
+            crc32(&v[..v.len().min(Ether::size())])
+        }
+    };
+    VXLAN_SPORT_BASE + (hash % (u16::MAX - VXLAN_SPORT_BASE + 1) as u32) as u16
+}
+
+impl Packet {
+    /// Encapsulate the packet as the inner ethernet frame of a VXLAN packet
+    ///
+    /// Fails if one outer address is IPv4 and the other an IPv6 address not mapped from IPv4.
+    pub fn vxlan_encap(&self, outer: &VxlanOuterParams) -> Result<Packet, PacketError> {
+        let inner = self.to_vec();
+        let udp_len = UDP::size() + Vxlan::size() + inner.len();
+        let mut udp = Packet::udp((outer.sport)(self), UDP_PORT_VXLAN, udp_len as u16);
+        udp.set_checksum(0);
+
+        let mut pkt = Packet::new();
+        let mut eth = Ether::new();
+        eth.set_dst_mac(outer.eth_dst);
+        eth.set_src_mac(outer.eth_src);
+        let (src, dst) = match (outer.ip_src, outer.ip_dst) {
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                eth.set_etype(EtherType::IPV6 as u64);
+                let mut ipv6 = IPv6::new();
+                ipv6.set_traffic_class(0);
+                ipv6.set_flow_label(0);
+                ipv6.set_payload_len(udp_len as u64);
+                ipv6.set_next_hdr(IpProtocol::UDP as u64);
+                ipv6.set_hop_limit(outer.ip_ttl as u64);
+                ipv6.set_src_ip(src);
+                ipv6.set_dst_ip(dst);
+                pkt.push(eth);
+                pkt.push(ipv6);
+                (src.octets().to_vec(), dst.octets().to_vec())
+            }
+            (src, dst) => {
+                let src = ipv4_of(src).ok_or_else(|| mixed("ip_src", src))?;
+                let dst = ipv4_of(dst).ok_or_else(|| mixed("ip_dst", dst))?;
+                eth.set_etype(EtherType::IPV4 as u64);
+                let mut ipv4 = IPv4::new();
+                ipv4.set_diffserv(0);
+                ipv4.set_total_len((IPv4::size() + udp_len) as u64);
+                ipv4.set_identification(0);
+                ipv4.set_flags(0);
+                ipv4.set_frag_offset(0);
+                ipv4.set_ttl(outer.ip_ttl as u64);
+                ipv4.set_protocol(IpProtocol::UDP as u64);
+                ipv4.set_src_ip(src);
+                ipv4.set_dst_ip(dst);
+                ipv4.set_header_checksum(0);
+                let chksum = Packet::ipv4_checksum(ipv4.to_vec().as_slice());
+                ipv4.set_header_checksum(chksum as u64);
+                pkt.push(eth);
+                pkt.push(ipv4);
+                (src.octets().to_vec(), dst.octets().to_vec())
+            }
+        };
+        let vxlan = Packet::vxlan(outer.vni);
+        if outer.udp_checksum {
+            let mut v = udp.to_vec();
+            v.extend_from_slice(&vxlan.to_vec());
+            v.extend_from_slice(&inner);
+            let chksum = match Packet::l4_checksum(&src, &dst, IpProtocol::UDP as u8, &v) {
+                0 => 0xffff,
+                x => x,
+            };
+            udp.set_checksum(chksum as u64);
+        }
+        pkt.push(udp);
+        pkt.push(vxlan);
+        pkt.set_payload(&inner);
+        Ok(pkt)
+    }
+    /// Validate the outer Ether/IP/UDP/VXLAN stack and return the inner packet
+    ///
+    /// Fails if a header is missing, the UDP destination port is not the VXLAN port or the VNI
+    /// flag is not set.
+    pub fn vxlan_decap(&self) -> Result<Packet, PacketError> {
+        let missing = |h: &str| PacketError::MissingHeader(String::from(h));
+        self.layer::<Ether>().ok_or_else(|| missing("Ether"))?;
+        let i = self
+            .hdrs
+            .iter()
+            .position(|x| x.as_any().is::<Vxlan>())
+            .ok_or_else(|| missing("Vxlan"))?;
+        let udp = match i.checked_sub(1).map(|u| self.hdrs[u].as_any()) {
+            Some(x) if x.is::<UDP>() => x.downcast_ref::<UDP>().unwrap(),
+            _ => return Err(missing("UDP")),
+        };
+        match i.checked_sub(2).map(|x| self.hdrs[x].name()) {
+            Some("IPv4") | Some("IPv6") => (),
+            _ => return Err(missing("IPv4")),
+        }
+        if udp.dst() != UDP_PORT_VXLAN as u64 {
+            return Err(PacketError::InvalidValue {
+                field: String::from("dst"),
+                value: udp.dst().to_string(),
+            });
+        }
+        let vxlan = self.hdrs[i].as_any().downcast_ref::<Vxlan>().unwrap();
+        if vxlan.flags() as u8 & VXLAN_FLAG_VNI == 0 {
+            return Err(PacketError::InvalidValue {
+                field: String::from("flags"),
+                value: vxlan.flags().to_string(),
+            });
+        }
+        let offset = self.hdrs[..=i].iter().map(|x| x.len()).sum::<usize>();
+        Ok(parser::slow::parse(&self.to_vec()[offset..]))
+    }
+}
+
+/// The IPv4 address of ip, None for an IPv6 address not mapped from IPv4
+fn ipv4_of(ip: IpAddr) -> Option<Ipv4Addr> {
+    match ip {
+        IpAddr::V4(x) => Some(x),
+        IpAddr::V6(x) => x.to_ipv4_mapped(),
+    }
+}
+
+/// Error for an outer address of the other family
+fn mixed(field: &str, ip: IpAddr) -> PacketError {
+    PacketError::InvalidValue {
+        field: String::from(field),
+        value: ip.to_string(),
+    }
+}
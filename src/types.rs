@@ -1,10 +1,22 @@
-use std::convert::TryFrom;
+use core::convert::TryFrom;
+
+use crate::alloc_prelude::*;
 
 pub const MAC_LEN: usize = 6;
 pub const IPV4_LEN: usize = 4;
 pub const IPV6_LEN: usize = 16;
 
 pub const UDP_PORT_VXLAN: u16 = 4789;
+pub const UDP_PORT_L2TP: u16 = 1701;
+
+pub const L2TP_V2: u8 = 2;
+pub const L2TP_V3: u8 = 3;
+
+pub const IGMP_V3_REPORT: u8 = 0x22;
+pub const MLD_QUERY: u8 = 130;
+pub const MLD_REPORT: u8 = 131;
+pub const MLD_DONE: u8 = 132;
+pub const MLD_V2_REPORT: u8 = 143;
 
 pub enum IpType {
     V4 = 4,
@@ -24,12 +36,14 @@ impl TryFrom<u8> for IpType {
 
 pub enum IpProtocol {
     ICMP = 1,
+    IGMP = 2,
     IPIP = 4,
     TCP = 6,
     UDP = 17,
     IPV6 = 41,
     GRE = 47,
     ICMPV6 = 58,
+    UDPLITE = 136,
 }
 impl TryFrom<u8> for IpProtocol {
     type Error = String;
@@ -37,12 +51,14 @@ impl TryFrom<u8> for IpProtocol {
     fn try_from(v: u8) -> Result<Self, Self::Error> {
         match v {
             x if x == IpProtocol::ICMP as u8 => Ok(IpProtocol::ICMP),
+            x if x == IpProtocol::IGMP as u8 => Ok(IpProtocol::IGMP),
             x if x == IpProtocol::IPIP as u8 => Ok(IpProtocol::IPIP),
             x if x == IpProtocol::TCP as u8 => Ok(IpProtocol::TCP),
             x if x == IpProtocol::UDP as u8 => Ok(IpProtocol::UDP),
             x if x == IpProtocol::IPV6 as u8 => Ok(IpProtocol::IPV6),
             x if x == IpProtocol::GRE as u8 => Ok(IpProtocol::GRE),
             x if x == IpProtocol::ICMPV6 as u8 => Ok(IpProtocol::ICMPV6),
+            x if x == IpProtocol::UDPLITE as u8 => Ok(IpProtocol::UDPLITE),
             _ => Err(format!("Unsupported IpProtocol {}", v)),
         }
     }
@@ -0,0 +1,152 @@
+//! # WebAssembly bindings
+//!
+//! With the `wasm` feature, [`parse_packet`], [`build_packet`] and [`parse_pcap`] are exported
+//! through `wasm-bindgen` for browser based viewers. Packets cross the boundary as JSON:
+//!
+//! ```json
+//! {"layers": [{"name": "UDP", "fields": {"src": 1234, "dst": 80, "length": 8, "checksum": 0}}],
+//!  "payload": ""}
+//! ```
+//!
+//! Fields up to 53 bits are numbers, wider fields are `0x` hex strings. When building, a field
+//! may also be given as an IPv4, IPv6 or MAC address string and missing fields keep their
+//! defaults.
+//!
+//! ```
+//! # use packet_rs::builders::*;
+//! # use packet_rs::wasm::*;
+//! let pkt = create_udp_packet(&Default::default());
+//! let json = packet_to_json(&pkt);
+//! assert_eq!(json["layers"][2]["fields"]["dst"], 80);
+//! assert!(packet_from_json(&json).unwrap().compare(&pkt));
+//! ```
+
+use serde_json::{json, Map, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::headers::*;
+use crate::pcap::PcapReader;
+use crate::spec::{parse_value, significant_bits};
+use crate::{parser, Packet, PacketError};
+
+/// Widest field encoded as a number, larger integers lose precision in javascript
+const MAX_NUMBER_BITS: usize = 53;
+
+/// Represent a packet as JSON, one object per layer
+pub fn packet_to_json(pkt: &Packet) -> Value {
+    let layers: Vec<Value> = pkt
+        .hdrs
+        .iter()
+        .map(|hdr| {
+            let mut fields = Map::new();
+            for f in hdr.fields() {
+                let value = match f.size() {
+                    0..=MAX_NUMBER_BITS => json!(hdr.get_field(f.name).unwrap()),
+                    _ => json!(hex(&hdr.get_field_bytes(f.name).unwrap())),
+                };
+                fields.insert(String::from(f.name), value);
+            }
+            json!({ "name": hdr.name(), "fields": fields })
+        })
+        .collect();
+    json!({ "layers": layers, "payload": hex(&pkt.payload) })
+}
+
+/// Build a packet from its JSON representation
+///
+/// Fails on unknown headers or fields and on values that do not fit their field.
+pub fn packet_from_json(value: &Value) -> Result<Packet, PacketError> {
+    let invalid = |field: &str, value: &Value| PacketError::InvalidValue {
+        field: String::from(field),
+        value: value.to_string(),
+    };
+    let layers = value
+        .get("layers")
+        .and_then(|x| x.as_array())
+        .ok_or_else(|| invalid("layers", value))?;
+    let mut pkt = Packet::new();
+    for layer in layers {
+        let name = layer
+            .get("name")
+            .and_then(|x| x.as_str())
+            .ok_or_else(|| invalid("name", layer))?;
+        let mut hdr =
+            header_from_name(name).ok_or_else(|| PacketError::UnknownHeader(String::from(name)))?;
+        let fields = layer.get("fields").and_then(|x| x.as_object());
+        for (field, v) in fields.into_iter().flatten() {
+            let bytes = match v {
+                Value::Number(n) => n.as_u64().map(|x| x.to_be_bytes().to_vec()),
+                Value::String(s) => parse_value(s),
+                _ => None,
+            };
+            let bytes = bytes.ok_or_else(|| invalid(field, v))?;
+            let info = hdr
+                .fields()
+                .iter()
+                .find(|f| f.is_named(field))
+                .ok_or_else(|| PacketError::UnknownField {
+                    header: String::from(name),
+                    field: field.clone(),
+                })?;
+            if significant_bits(&bytes) > info.size() {
+                return Err(invalid(field, v));
+            }
+            hdr.set_field_bytes(field, &bytes);
+        }
+        pkt.hdrs.push(hdr);
+    }
+    match value.get("payload") {
+        None => (),
+        Some(Value::String(s)) if s.is_empty() => (),
+        Some(v @ Value::String(s)) => {
+            pkt.payload = parse_value(s).ok_or_else(|| invalid("payload", v))?;
+        }
+        Some(v) => return Err(invalid("payload", v)),
+    }
+    Ok(pkt)
+}
+
+/// Parse a packet into its JSON representation
+#[wasm_bindgen]
+pub fn parse_packet(bytes: &[u8]) -> JsValue {
+    JsValue::from_str(&packet_to_json(&parser::slow::parse(bytes)).to_string())
+}
+
+/// Build the bytes of a packet from its JSON representation
+#[wasm_bindgen]
+pub fn build_packet(json: &str) -> Result<Vec<u8>, JsValue> {
+    let value: Value = serde_json::from_str(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    match packet_from_json(&value) {
+        Ok(pkt) => Ok(pkt.to_vec()),
+        Err(e) => Err(JsValue::from_str(&e.to_string())),
+    }
+}
+
+/// Parse a pcap capture held in memory into a JSON array of packets
+#[wasm_bindgen]
+pub fn parse_pcap(data: &[u8]) -> Result<JsValue, JsValue> {
+    let reader = PcapReader::from_bytes(data).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let packets: Vec<Value> = reader
+        .records()
+        .iter()
+        .map(|x| {
+            let mut v = packet_to_json(&x.packet);
+            v["ts_sec"] = json!(x.ts_sec);
+            v["ts_nsec"] = json!(x.ts_nsec);
+            v["orig_len"] = json!(x.orig_len);
+            v
+        })
+        .collect();
+    Ok(JsValue::from_str(&Value::from(packets).to_string()))
+}
+
+fn hex(v: &[u8]) -> String {
+    if v.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("0x");
+    for b in v {
+        out.push_str(&format!("{:02x}", b));
+    }
+    out
+}
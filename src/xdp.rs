@@ -0,0 +1,903 @@
+//! # AF_XDP sockets
+//!
+//! Linux only, enabled with the `xdp` feature. [`XdpInterface`] binds an `AF_XDP` socket to one
+//! queue of an interface. Frames are exchanged through rings shared with the kernel and a frame
+//! memory, the UMEM, bypassing the network stack. Opening the socket loads an XDP program
+//! redirecting the frames received on the queue to the socket, frames of other queues keep going
+//! to the stack. The program is detached when the interface is dropped.
+//!
+//! Drivers with zero-copy support transmit from and receive into the UMEM directly, other
+//! drivers, like veth, run in copy mode where the kernel copies frames between the UMEM and its
+//! own buffers. The API is the same in both modes, [`XdpMode`] forces one of them and
+//! [`XdpInterface::is_zero_copy`] tells which one is in use.
+//!
+//! [`XdpInterface::send_packets`] assembles packets in the UMEM with [`Packet::write_into`],
+//! without an intermediate buffer. Like [`RawSocket`](crate::raw::RawSocket), the interface
+//! implements `Read` and `Write` for one frame at a time and has `send_batch` and `recv_batch`.
+//!
+//! Needs Linux 5.9 or later, `CAP_NET_RAW`, `CAP_NET_ADMIN` and `CAP_BPF`, and no other XDP
+//! program attached to the interface.
+//!
+//! ```no_run
+//! # use packet_rs::builders::*;
+//! # use packet_rs::xdp::XdpInterface;
+//! let xdp = XdpInterface::open("eth0", 0).unwrap();
+//! let pkts = vec![create_udp_packet(&Default::default()); 64];
+//! let sent = xdp.send_packets(&pkts).unwrap();
+//! let mut bufs = vec![Vec::new(); 64];
+//! let received = xdp.recv_batch(&mut bufs, None).unwrap();
+//! ```
+
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::interface::Interface;
+use crate::Packet;
+
+/// Size of a UMEM frame
+///
+/// Frames sent are at most this long, frames received are at most this long minus the 256
+/// bytes of headroom the kernel keeps in front of them.
+pub const XDP_FRAME_SIZE: usize = 4096;
+
+/// Entries of each ring
+const RING_SIZE: u32 = 2048;
+/// Frames of the UMEM, the first half cycles between the fill and rx rings, the second half
+/// between the tx and completion rings
+const FRAME_COUNT: usize = 2 * RING_SIZE as usize;
+
+const BPF_MAP_CREATE: libc::c_int = 0;
+const BPF_MAP_UPDATE_ELEM: libc::c_int = 2;
+const BPF_PROG_LOAD: libc::c_int = 5;
+const BPF_LINK_CREATE: libc::c_int = 28;
+const BPF_MAP_TYPE_XSKMAP: u32 = 17;
+const BPF_PROG_TYPE_XDP: u32 = 6;
+const BPF_XDP: u32 = 37;
+
+/// How the socket shares the UMEM with the driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum XdpMode {
+    /// Zero-copy if the driver supports it, copy mode otherwise
+    #[default]
+    Auto,
+    /// Always copy, works with every driver
+    Copy,
+    /// Fail to open if the driver has no zero-copy support
+    ZeroCopy,
+}
+
+/// An `AF_XDP` socket bound to a queue of an interface
+pub struct XdpInterface {
+    sock: Fd,
+    zero_copy: bool,
+    /// Rings and free transmit frames, locked so the batch calls take `&self`
+    state: Mutex<XdpState>,
+    umem: Mmap,
+    _program: XdpProgram,
+}
+
+struct XdpState {
+    rx: Ring,
+    tx: Ring,
+    fill: Ring,
+    completion: Ring,
+    /// Transmit frames not owned by the kernel
+    tx_free: Vec<u64>,
+}
+
+impl XdpInterface {
+    /// Open the socket on a queue of the interface, in zero-copy mode if the driver supports it
+    pub fn open(ifname: &str, queue: u32) -> io::Result<XdpInterface> {
+        XdpInterface::open_with(ifname, queue, XdpMode::Auto)
+    }
+    /// Open the socket on a queue of the interface in the given mode
+    pub fn open_with(ifname: &str, queue: u32, mode: XdpMode) -> io::Result<XdpInterface> {
+        let name =
+            CString::new(ifname).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sock = Fd(check(unsafe {
+            libc::socket(libc::AF_XDP, libc::SOCK_RAW, 0)
+        })?);
+        let fd = sock.0;
+        let umem = Mmap::new(-1, FRAME_COUNT * XDP_FRAME_SIZE, 0)?;
+        let reg = libc::xdp_umem_reg {
+            addr: umem.ptr as u64,
+            len: umem.len as u64,
+            chunk_size: XDP_FRAME_SIZE as u32,
+            headroom: 0,
+            flags: 0,
+            tx_metadata_len: 0,
+        };
+        setsockopt(fd, libc::XDP_UMEM_REG, &reg)?;
+        for opt in [
+            libc::XDP_UMEM_FILL_RING,
+            libc::XDP_UMEM_COMPLETION_RING,
+            libc::XDP_RX_RING,
+            libc::XDP_TX_RING,
+        ] {
+            setsockopt(fd, opt, &RING_SIZE)?;
+        }
+        let off: libc::xdp_mmap_offsets = getsockopt(fd, libc::XDP_MMAP_OFFSETS)?;
+        let desc = mem::size_of::<libc::xdp_desc>();
+        let addr = mem::size_of::<u64>();
+        let state = XdpState::new(
+            Ring::new(fd, &off.rx, desc, libc::XDP_PGOFF_RX_RING)?,
+            Ring::new(fd, &off.tx, desc, libc::XDP_PGOFF_TX_RING)?,
+            Ring::new(fd, &off.fr, addr, libc::XDP_UMEM_PGOFF_FILL_RING as i64)?,
+            Ring::new(
+                fd,
+                &off.cr,
+                addr,
+                libc::XDP_UMEM_PGOFF_COMPLETION_RING as i64,
+            )?,
+        );
+
+        let flags = match mode {
+            XdpMode::Auto => 0,
+            XdpMode::Copy => libc::XDP_COPY,
+            XdpMode::ZeroCopy => libc::XDP_ZEROCOPY,
+        };
+        let sxdp = libc::sockaddr_xdp {
+            sxdp_family: libc::AF_XDP as u16,
+            sxdp_flags: flags | libc::XDP_USE_NEED_WAKEUP,
+            sxdp_ifindex: ifindex,
+            sxdp_queue_id: queue,
+            sxdp_shared_umem_fd: 0,
+        };
+        check(unsafe {
+            libc::bind(
+                fd,
+                &sxdp as *const libc::sockaddr_xdp as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_xdp>() as libc::socklen_t,
+            )
+        })?;
+        let options: libc::xdp_options = getsockopt(fd, libc::XDP_OPTIONS)?;
+        let zero_copy = options.flags & libc::XDP_OPTIONS_ZEROCOPY != 0;
+        let program = XdpProgram::attach(ifindex, queue, fd)?;
+        debug!(ifname, ifindex, queue, zero_copy, fd; "xdp socket opened");
+        Ok(XdpInterface {
+            sock,
+            zero_copy,
+            state: Mutex::new(state),
+            umem,
+            _program: program,
+        })
+    }
+    /// Return true if the driver works on the UMEM directly
+    pub fn is_zero_copy(&self) -> bool {
+        self.zero_copy
+    }
+    /// Queue each frame for transmission, returns the number of frames queued
+    ///
+    /// Fewer frames are queued when the transmit ring is full, the rest can be sent again once
+    /// the kernel completed earlier frames. Fails if the first frame is longer than
+    /// [`XDP_FRAME_SIZE`], a later one stops the batch.
+    pub fn send_batch(&self, frames: &[&[u8]]) -> io::Result<usize> {
+        self.send_with(frames.len(), |i, buf| {
+            match buf.get_mut(..frames[i].len()) {
+                Some(buf) => {
+                    buf.copy_from_slice(frames[i]);
+                    Ok(frames[i].len())
+                }
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "frame larger than the umem frame size",
+                )),
+            }
+        })
+    }
+    /// Assemble each packet in the UMEM and queue it, returns the number of packets queued
+    ///
+    /// Same as [`XdpInterface::send_batch`] without copying the packets into a buffer first.
+    pub fn send_packets(&self, pkts: &[Packet]) -> io::Result<usize> {
+        self.send_with(pkts.len(), |i, buf| {
+            pkts[i]
+                .write_into(buf)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+        })
+    }
+    /// Receive up to one frame per buffer, returns the number of buffers filled
+    ///
+    /// Each buffer is cleared and holds one frame. Waits up to timeout for the first frame,
+    /// forever if None, then only takes the frames already received. Returns 0 when the timeout
+    /// expires.
+    pub fn recv_batch(&self, bufs: &mut [Vec<u8>], timeout: Option<Duration>) -> io::Result<usize> {
+        let mut i = 0;
+        self.recv_with(bufs.len(), timeout, |frame| {
+            bufs[i].clear();
+            bufs[i].extend_from_slice(frame);
+            i += 1;
+        })
+    }
+    /// Pass up to max received frames to f, in the UMEM, returns the number of frames
+    ///
+    /// Waits like [`XdpInterface::recv_batch`]. The frames are handed back to the kernel once f
+    /// returns.
+    pub fn recv_with(
+        &self,
+        max: usize,
+        timeout: Option<Duration>,
+        f: impl FnMut(&[u8]),
+    ) -> io::Result<usize> {
+        if max == 0 {
+            return Ok(0);
+        }
+        if self.state.lock().unwrap().rx.ready() == 0 && !poll(self.sock.0, libc::POLLIN, timeout)?
+        {
+            return Ok(0);
+        }
+        let st = self.state.lock().unwrap();
+        let n = st.take_rx(&self.umem, max, f);
+        if st.fill.needs_wakeup() {
+            self.wakeup();
+        }
+        Ok(n)
+    }
+    fn send_with(
+        &self,
+        count: usize,
+        fill: impl FnMut(usize, &mut [u8]) -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        let mut st = self.state.lock().unwrap();
+        st.reclaim();
+        if st.tx_free.is_empty() {
+            self.wakeup();
+            st.reclaim();
+        }
+        let queued = st.queue_tx(&self.umem, self.sock.0, count, fill)?;
+        if queued > 0 && st.tx.needs_wakeup() {
+            self.wakeup();
+        }
+        Ok(queued)
+    }
+    /// Ask the kernel to process the rings
+    fn wakeup(&self) {
+        let ret = unsafe {
+            libc::sendto(
+                self.sock.0,
+                ptr::null(),
+                0,
+                libc::MSG_DONTWAIT,
+                ptr::null(),
+                0,
+            )
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EAGAIN | libc::EBUSY | libc::ENOBUFS | libc::ENETDOWN) => (),
+                _ => warn!(fd = self.sock.0, error:% = err; "xdp wakeup failed"),
+            }
+        }
+    }
+}
+
+impl XdpState {
+    /// Hand the first half of the UMEM to the kernel on the fill ring, keep the second half to
+    /// transmit
+    fn new(rx: Ring, tx: Ring, fill: Ring, completion: Ring) -> XdpState {
+        for i in 0..RING_SIZE {
+            unsafe { *fill.entry::<u64>(i) = i as u64 * XDP_FRAME_SIZE as u64 };
+        }
+        fill.producer().store(RING_SIZE, Ordering::Release);
+        XdpState {
+            rx,
+            tx,
+            fill,
+            completion,
+            tx_free: (RING_SIZE as usize..FRAME_COUNT)
+                .map(|i| (i * XDP_FRAME_SIZE) as u64)
+                .collect(),
+        }
+    }
+    /// Fill up to count free transmit frames with fill and queue them, returns the number queued
+    ///
+    /// Fails if fill fails on the first frame, a later failure stops the batch.
+    fn queue_tx(
+        &mut self,
+        umem: &Mmap,
+        fd: RawFd,
+        count: usize,
+        mut fill: impl FnMut(usize, &mut [u8]) -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        let n = count.min(self.tx_free.len()).min(self.tx.free() as usize);
+        let prod = self.tx.producer().load(Ordering::Relaxed);
+        let mut queued = 0;
+        while queued < n {
+            let addr = *self.tx_free.last().unwrap();
+            let frame = unsafe { umem.frame(addr, XDP_FRAME_SIZE) };
+            let len = match fill(queued, frame) {
+                Ok(len) => len,
+                Err(e) if queued == 0 => return Err(e),
+                Err(e) => {
+                    warn!(fd, index = queued, error:% = e; "xdp batch send stopped");
+                    break;
+                }
+            };
+            self.tx_free.pop();
+            let desc = libc::xdp_desc {
+                addr,
+                len: len as u32,
+                options: 0,
+            };
+            unsafe {
+                *self
+                    .tx
+                    .entry::<libc::xdp_desc>(prod.wrapping_add(queued as u32)) = desc
+            };
+            queued += 1;
+        }
+        self.tx
+            .producer()
+            .store(prod.wrapping_add(queued as u32), Ordering::Release);
+        Ok(queued)
+    }
+    /// Pass up to max received frames to f and give their frames back to the kernel on the fill
+    /// ring, returns the number of frames
+    fn take_rx(&self, umem: &Mmap, max: usize, mut f: impl FnMut(&[u8])) -> usize {
+        let n = self.rx.ready().min(max as u32);
+        let cons = self.rx.consumer().load(Ordering::Relaxed);
+        let prod = self.fill.producer().load(Ordering::Relaxed);
+        for i in 0..n {
+            let desc = unsafe { *self.rx.entry::<libc::xdp_desc>(cons.wrapping_add(i)) };
+            f(unsafe { umem.frame(desc.addr, desc.len as usize) });
+            // the fill ring has room for every receive frame
+            let chunk = desc.addr - desc.addr % XDP_FRAME_SIZE as u64;
+            unsafe { *self.fill.entry::<u64>(prod.wrapping_add(i)) = chunk };
+        }
+        self.rx
+            .consumer()
+            .store(cons.wrapping_add(n), Ordering::Release);
+        self.fill
+            .producer()
+            .store(prod.wrapping_add(n), Ordering::Release);
+        n as usize
+    }
+    /// Take back the transmit frames the kernel is done with
+    fn reclaim(&mut self) {
+        let n = self.completion.ready();
+        let cons = self.completion.consumer().load(Ordering::Relaxed);
+        for i in 0..n {
+            let addr = unsafe { *self.completion.entry::<u64>(cons.wrapping_add(i)) };
+            self.tx_free.push(addr);
+        }
+        self.completion
+            .consumer()
+            .store(cons.wrapping_add(n), Ordering::Release);
+    }
+}
+
+impl Interface for XdpInterface {
+    fn send_batch(&self, frames: &[&[u8]]) -> io::Result<usize> {
+        XdpInterface::send_batch(self, frames)
+    }
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], timeout: Option<Duration>) -> io::Result<usize> {
+        XdpInterface::recv_batch(self, bufs, timeout)
+    }
+}
+
+impl AsRawFd for XdpInterface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.0
+    }
+}
+
+impl Read for XdpInterface {
+    /// Receive one frame, truncated to the length of buf
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut len = 0;
+        self.recv_with(1, None, |frame| {
+            len = frame.len().min(buf.len());
+            buf[..len].copy_from_slice(&frame[..len]);
+        })?;
+        Ok(len)
+    }
+}
+
+impl Write for XdpInterface {
+    /// Send buf as one frame, waiting for room in the transmit ring
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        while self.send_batch(&[buf])? == 0 {
+            poll(self.sock.0, libc::POLLOUT, Some(Duration::from_millis(1)))?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A ring mapped from the socket, the producer and consumer indexes wrap around
+struct Ring {
+    _map: Mmap,
+    producer: *const AtomicU32,
+    consumer: *const AtomicU32,
+    flags: *const AtomicU32,
+    desc: *mut u8,
+}
+
+// the pointers are into the mapping owned by the ring
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn new(
+        fd: RawFd,
+        off: &libc::xdp_ring_offset,
+        entry_size: usize,
+        pgoff: libc::off_t,
+    ) -> io::Result<Ring> {
+        let map = Mmap::new(
+            fd,
+            off.desc as usize + RING_SIZE as usize * entry_size,
+            pgoff,
+        )?;
+        let at = |offset: u64| unsafe { (map.ptr as *mut u8).add(offset as usize) };
+        Ok(Ring {
+            producer: at(off.producer) as *const AtomicU32,
+            consumer: at(off.consumer) as *const AtomicU32,
+            flags: at(off.flags) as *const AtomicU32,
+            desc: at(off.desc),
+            _map: map,
+        })
+    }
+    fn producer(&self) -> &AtomicU32 {
+        unsafe { &*self.producer }
+    }
+    fn consumer(&self) -> &AtomicU32 {
+        unsafe { &*self.consumer }
+    }
+    fn needs_wakeup(&self) -> bool {
+        unsafe { &*self.flags }.load(Ordering::Relaxed) & libc::XDP_RING_NEED_WAKEUP != 0
+    }
+    /// Entries the producer can add
+    fn free(&self) -> u32 {
+        let used = self
+            .producer()
+            .load(Ordering::Relaxed)
+            .wrapping_sub(self.consumer().load(Ordering::Acquire));
+        RING_SIZE - used
+    }
+    /// Entries the consumer can take
+    fn ready(&self) -> u32 {
+        self.producer()
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.consumer().load(Ordering::Relaxed))
+    }
+    fn entry<T>(&self, index: u32) -> *mut T {
+        unsafe { (self.desc as *mut T).add((index & (RING_SIZE - 1)) as usize) }
+    }
+}
+
+/// The redirect program, its socket map and the link attaching it to the interface
+struct XdpProgram {
+    _map: Fd,
+    _prog: Fd,
+    _link: Fd,
+}
+
+#[repr(C)]
+struct MapCreate {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+}
+
+#[repr(C)]
+struct MapUpdate {
+    map_fd: u32,
+    pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+struct ProgLoad {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+    prog_name: [u8; 16],
+    prog_ifindex: u32,
+    expected_attach_type: u32,
+}
+
+#[repr(C)]
+struct LinkCreate {
+    prog_fd: u32,
+    target_ifindex: u32,
+    attach_type: u32,
+    flags: u32,
+}
+
+impl XdpProgram {
+    fn attach(ifindex: u32, queue: u32, xsk: RawFd) -> io::Result<XdpProgram> {
+        let map = Fd(bpf(
+            BPF_MAP_CREATE,
+            &MapCreate {
+                map_type: BPF_MAP_TYPE_XSKMAP,
+                key_size: 4,
+                value_size: 4,
+                max_entries: queue + 1,
+                map_flags: 0,
+            },
+        )?);
+        bpf(
+            BPF_MAP_UPDATE_ELEM,
+            &MapUpdate {
+                map_fd: map.0 as u32,
+                pad: 0,
+                key: &queue as *const u32 as u64,
+                value: &xsk as *const RawFd as u64,
+                flags: 0,
+            },
+        )?;
+        // r2 = ctx->rx_queue_index
+        // r1 = map
+        // r3 = XDP_PASS, when the queue has no socket
+        // return bpf_redirect_map(r1, r2, r3)
+        let insns: [[u8; 8]; 6] = [
+            [0x61, 0x12, 16, 0, 0, 0, 0, 0],
+            insn(0x18, 0x11, map.0),
+            [0; 8],
+            insn(0xb7, 0x03, 2),
+            insn(0x85, 0x00, 51),
+            insn(0x95, 0x00, 0),
+        ];
+        let license = b"Apache-2.0\0";
+        let mut attr = ProgLoad {
+            prog_type: BPF_PROG_TYPE_XDP,
+            insn_cnt: insns.len() as u32,
+            insns: insns.as_ptr() as u64,
+            license: license.as_ptr() as u64,
+            log_level: 0,
+            log_size: 0,
+            log_buf: 0,
+            kern_version: 0,
+            prog_flags: 0,
+            prog_name: *b"packet_rs_xsk\0\0\0",
+            prog_ifindex: 0,
+            expected_attach_type: BPF_XDP,
+        };
+        let prog = match bpf(BPF_PROG_LOAD, &attr) {
+            Ok(fd) => Fd(fd),
+            Err(err) => {
+                // load again for the verifier log
+                let mut log = vec![0u8; 4096];
+                attr.log_level = 1;
+                attr.log_size = log.len() as u32;
+                attr.log_buf = log.as_mut_ptr() as u64;
+                let _ = bpf(BPF_PROG_LOAD, &attr).map(Fd);
+                let end = log.iter().position(|x| *x == 0).unwrap_or(log.len());
+                let log = String::from_utf8_lossy(&log[..end]);
+                warn!(error:% = err, log:% = log; "xdp program rejected");
+                return Err(err);
+            }
+        };
+        let link = Fd(bpf(
+            BPF_LINK_CREATE,
+            &LinkCreate {
+                prog_fd: prog.0 as u32,
+                target_ifindex: ifindex,
+                attach_type: BPF_XDP,
+                flags: 0,
+            },
+        )?);
+        Ok(XdpProgram {
+            _map: map,
+            _prog: prog,
+            _link: link,
+        })
+    }
+}
+
+/// Encode an instruction, regs holds the source register in the high nibble
+fn insn(code: u8, regs: u8, imm: i32) -> [u8; 8] {
+    let imm = imm.to_le_bytes();
+    [code, regs, 0, 0, imm[0], imm[1], imm[2], imm[3]]
+}
+
+fn bpf<T>(cmd: libc::c_int, attr: &T) -> io::Result<RawFd> {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            cmd,
+            attr as *const T,
+            mem::size_of::<T>() as libc::c_uint,
+        )
+    };
+    check(ret as libc::c_int)
+}
+
+/// A file descriptor closed on drop
+struct Fd(RawFd);
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+/// A shared memory mapping, anonymous when fd is -1
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// the mapping is owned, the frames are handed out under the state lock
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    fn new(fd: RawFd, len: usize, pgoff: libc::off_t) -> io::Result<Mmap> {
+        let flags = match fd {
+            -1 => libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            _ => libc::MAP_SHARED | libc::MAP_POPULATE,
+        };
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, flags, fd, pgoff) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+    /// The bytes of a frame, which must not be accessed anywhere else meanwhile
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn frame(&self, addr: u64, len: usize) -> &mut [u8] {
+        slice::from_raw_parts_mut((self.ptr as *mut u8).add(addr as usize), len)
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+fn check(ret: libc::c_int) -> io::Result<RawFd> {
+    match ret {
+        x if x < 0 => Err(io::Error::last_os_error()),
+        x => Ok(x),
+    }
+}
+
+fn setsockopt<T>(fd: RawFd, opt: libc::c_int, value: &T) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_XDP,
+            opt,
+            value as *const T as *const libc::c_void,
+            mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    check(ret).map(|_| ())
+}
+
+fn getsockopt<T>(fd: RawFd, opt: libc::c_int) -> io::Result<T> {
+    let mut value: T = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<T>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_XDP,
+            opt,
+            &mut value as *mut T as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    check(ret)?;
+    if len as usize != mem::size_of::<T>() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "kernel xdp socket options too old",
+        ));
+    }
+    Ok(value)
+}
+
+/// Wait for events on fd, returns false on timeout
+fn poll(fd: RawFd, events: libc::c_short, timeout: Option<Duration>) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    let ms = timeout.map_or(-1, |x| x.as_millis().min(i32::MAX as u128) as i32);
+    let ret = unsafe { libc::poll(&mut pfd, 1, ms) };
+    check(ret).map(|x| x > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builders::*;
+    use crate::raw::RawSocket;
+    use std::process::Command;
+
+    fn ip(args: &str) -> bool {
+        let status = Command::new("ip").args(args.split(' ')).status();
+        status.is_ok_and(|x| x.success())
+    }
+
+    /// A ring in anonymous memory, standing in for one mapped from a socket
+    fn anon_ring(entry_size: usize) -> Ring {
+        let off = libc::xdp_ring_offset {
+            producer: 0,
+            consumer: 64,
+            flags: 128,
+            desc: 192,
+        };
+        Ring::new(-1, &off, entry_size, 0).unwrap()
+    }
+
+    #[test]
+    fn xdp_rings_test() {
+        let desc = mem::size_of::<libc::xdp_desc>();
+        let addr = mem::size_of::<u64>();
+        let umem = Mmap::new(-1, FRAME_COUNT * XDP_FRAME_SIZE, 0).unwrap();
+        let mut st = XdpState::new(
+            anon_ring(desc),
+            anon_ring(desc),
+            anon_ring(addr),
+            anon_ring(addr),
+        );
+        // the first half of the umem waits for frames, the second half is free to transmit
+        assert_eq!(st.fill.ready(), RING_SIZE);
+        assert_eq!(st.fill.free(), 0);
+        assert_eq!(unsafe { *st.fill.entry::<u64>(1) }, XDP_FRAME_SIZE as u64);
+        assert_eq!(st.tx_free.len(), RING_SIZE as usize);
+        assert!(st
+            .tx_free
+            .iter()
+            .all(|x| *x >= (RING_SIZE as usize * XDP_FRAME_SIZE) as u64));
+
+        // the indexes wrap around
+        st.tx.producer().store(u32::MAX - 1, Ordering::Relaxed);
+        st.tx.consumer().store(u32::MAX - 1, Ordering::Relaxed);
+        assert_eq!(st.tx.free(), RING_SIZE);
+        let frames: [&[u8]; 3] = [&[1; 60], &[2; 100], &[3; XDP_FRAME_SIZE + 1]];
+        let copy = |i: usize, buf: &mut [u8]| match buf.get_mut(..frames[i].len()) {
+            Some(buf) => {
+                buf.copy_from_slice(frames[i]);
+                Ok(frames[i].len())
+            }
+            None => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        };
+        // the oversized frame stops the batch after the first two
+        assert_eq!(st.queue_tx(&umem, -1, 3, copy).unwrap(), 2);
+        assert_eq!(st.tx.ready(), 2);
+        assert_eq!(st.tx_free.len(), RING_SIZE as usize - 2);
+        let mut done = Vec::new();
+        for i in 0..2 {
+            let d = unsafe {
+                *st.tx
+                    .entry::<libc::xdp_desc>((u32::MAX - 1).wrapping_add(i))
+            };
+            let frame = unsafe { umem.frame(d.addr, d.len as usize) };
+            assert_eq!(frame, frames[i as usize]);
+            done.push(d.addr);
+        }
+        assert_eq!(st.tx.entry::<libc::xdp_desc>(0), st.tx.entry(RING_SIZE));
+        // an oversized first frame fails
+        let big = |_: usize, buf: &mut [u8]| copy(2, buf);
+        assert!(st.queue_tx(&umem, -1, 1, big).is_err());
+        assert_eq!(st.tx.ready(), 2);
+
+        // the kernel sends both frames and completes them
+        st.tx
+            .consumer()
+            .store((u32::MAX - 1).wrapping_add(2), Ordering::Relaxed);
+        assert_eq!(st.tx.ready(), 0);
+        for (i, addr) in done.iter().enumerate() {
+            unsafe { *st.completion.entry::<u64>(i as u32) = *addr };
+        }
+        st.completion.producer().store(2, Ordering::Release);
+        st.reclaim();
+        assert_eq!(st.tx_free.len(), RING_SIZE as usize);
+        assert_eq!(st.completion.ready(), 0);
+
+        // the kernel receives two frames after its headroom
+        st.fill.consumer().store(2, Ordering::Relaxed);
+        for i in 0..2u32 {
+            let chunk = unsafe { *st.fill.entry::<u64>(i) };
+            let d = libc::xdp_desc {
+                addr: chunk + 256,
+                len: 64 + i,
+                options: 0,
+            };
+            unsafe { umem.frame(d.addr, d.len as usize) }.fill(0x10 + i as u8);
+            unsafe { *st.rx.entry::<libc::xdp_desc>(i) = d };
+        }
+        st.rx.producer().store(2, Ordering::Release);
+        let mut rx = Vec::new();
+        assert_eq!(st.take_rx(&umem, 1, |x| rx.push(x.to_vec())), 1);
+        assert_eq!(st.take_rx(&umem, 8, |x| rx.push(x.to_vec())), 1);
+        assert_eq!(rx, [vec![0x10; 64], vec![0x11; 65]]);
+        assert_eq!(st.rx.ready(), 0);
+        // their frames go back on the fill ring from the start of the chunk
+        assert_eq!(st.fill.ready(), RING_SIZE);
+        assert_eq!(unsafe { *st.fill.entry::<u64>(RING_SIZE) }, 0);
+        assert_eq!(
+            unsafe { *st.fill.entry::<u64>(RING_SIZE + 1) },
+            XDP_FRAME_SIZE as u64
+        );
+
+        assert!(!st.fill.needs_wakeup());
+        unsafe { &*st.fill.flags }.store(libc::XDP_RING_NEED_WAKEUP, Ordering::Relaxed);
+        assert!(st.fill.needs_wakeup());
+    }
+    #[test]
+    #[ignore = "needs root to create a veth pair and load an XDP program"]
+    fn xdp_veth_test() {
+        assert!(ip("link add pkrs_xa type veth peer name pkrs_xb"));
+        assert!(ip("link set pkrs_xa up") && ip("link set pkrs_xb up"));
+        // veth has no zero-copy support, copy mode is the fallback
+        assert!(XdpInterface::open_with("pkrs_xa", 0, XdpMode::ZeroCopy).is_err());
+        let xdp = XdpInterface::open("pkrs_xa", 0).unwrap();
+        assert!(!xdp.is_zero_copy());
+        let mut raw = RawSocket::open("pkrs_xb").unwrap();
+
+        let pkts: Vec<Packet> = (0..8)
+            .map(|i| {
+                create_udp_packet(&PacketConfig {
+                    sport: 5000 + i,
+                    payload_len: 100,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        assert_eq!(xdp.send_packets(&pkts).unwrap(), pkts.len());
+        let mut rx = Vec::new();
+        let mut buf = vec![0; 2048];
+        while rx.len() < pkts.len() {
+            let n = raw.read(&mut buf).unwrap();
+            if pkts.iter().any(|x| x.to_vec() == buf[..n]) {
+                rx.push(buf[..n].to_vec());
+            }
+        }
+        assert!(rx.iter().zip(&pkts).all(|(a, b)| *a == b.to_vec()));
+
+        for pkt in &pkts {
+            raw.write_all(&pkt.to_vec()).unwrap();
+        }
+        let mut bufs = vec![Vec::new(); 16];
+        let mut rx = Vec::new();
+        while rx.len() < pkts.len() {
+            let n = xdp
+                .recv_batch(&mut bufs, Some(Duration::from_secs(1)))
+                .unwrap();
+            assert_ne!(n, 0);
+            let ours = bufs[..n]
+                .iter()
+                .filter(|x| pkts.iter().any(|p| p.to_vec() == **x));
+            rx.extend(ours.cloned());
+        }
+        assert!(rx.iter().zip(&pkts).all(|(a, b)| *a == b.to_vec()));
+        drop(xdp);
+        assert!(ip("link del pkrs_xa"));
+    }
+}
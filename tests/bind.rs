@@ -0,0 +1,108 @@
+//! Headers defined outside the crate and bound with the `bind` clause of `make_header!`
+
+#![allow(clippy::len_without_is_empty, clippy::unused_unit)]
+
+#[macro_use]
+extern crate packet_rs;
+
+use packet_rs::bind;
+use packet_rs::builders;
+use packet_rs::headers::*;
+use packet_rs::parser;
+use packet_rs::Packet;
+
+make_header!(
+Shim 4
+(
+    tag: 0-15,
+    etype: 16-31
+)
+vec![0x00, 0x01, 0x08, 0x00],
+bind(Ether.etype == 0x9988, next = etype)
+);
+
+make_header!(
+Telemetry 4
+(
+    flags: 0-7,
+    hop: 8-15,
+    reserved: 16-31 [reserved]
+)
+bind(UDP.dst == 4789)
+);
+
+#[test]
+fn bound_shim_test() {
+    // declared by the bind clause, no registration needed
+    assert_eq!(Shim::BINDING.next, Some("etype"));
+
+    let mut pkt = Packet::new();
+    pkt.push(Ether::new());
+    let mut shim = Shim::new();
+    shim.set_tag(42);
+    shim.set_etype(0);
+    pkt.push(shim);
+    pkt.push(IPv4::new());
+    pkt.push(UDP::new());
+    pkt.set_payload(b"payload");
+    // the ethertype selecting the shim and the one it carries are learned from the binding
+    let v = pkt.to_vec_with_fixup();
+    assert_eq!(v[12..14], [0x99, 0x88]);
+    assert_eq!(v[16..18], [0x08, 0x00]);
+
+    let parsed = parser::slow::parse(&v);
+    let names: Vec<_> = (0..4).map(|i| parsed[i].name().to_string()).collect();
+    assert_eq!(names, vec!["Ether", "Shim", "IPv4", "UDP"]);
+    assert_eq!(parsed.layer::<Shim>().unwrap().tag(), 42);
+    assert_eq!(parsed.layer::<UDP>().unwrap().dst(), 80);
+    assert_eq!(parsed.to_vec(), v);
+    let slice = parser::fast::parse(&v);
+    assert_eq!(slice.to_vec(), v);
+    assert!(slice.dump().contains("Shim"));
+    assert!(slice.dump().contains("UDP"));
+    assert_eq!(
+        parser::slow::parse_from("Shim", &v[14..]).len(),
+        v.len() - 14
+    );
+
+    // a truncated shim is left as payload
+    let short = parser::slow::parse(&v[..16]);
+    assert!(short.layer::<Shim>().is_none());
+    assert!(short.layer::<Ether>().is_some());
+    assert_eq!(short.to_vec(), &v[..16]);
+}
+
+#[test]
+fn binding_override_test() {
+    let mut vxlan = builders::create_vxlan_packet(
+        &Default::default(),
+        10,
+        builders::create_tcp_packet(&Default::default()),
+    );
+    vxlan.update_checksums();
+    let v = vxlan.to_vec();
+
+    // the binding on the VXLAN port replaces the built in header
+    let parsed = parser::slow::parse(&v);
+    assert!(parsed.layer::<Vxlan>().is_none());
+    assert!(parsed.layer::<Telemetry>().is_some());
+    assert!(parsed.layer::<TCP>().is_none());
+    assert!(bind::bindings().iter().any(|x| x.name == "Telemetry"));
+    let slice = parser::fast::parse(&v).dump();
+    assert!(slice.contains("Telemetry") && !slice.contains("Vxlan"));
+
+    let removed = bind::unbind("UDP", "dst", 4789).unwrap();
+    assert_eq!(removed.name, "Telemetry");
+    assert!(bind::unbind("UDP", "dst", 4789).is_none());
+    assert!(parser::slow::parse(&v).layer::<Vxlan>().is_some());
+    assert!(parser::fast::parse(&v).dump().contains("Vxlan"));
+
+    // a set of bindings of its own, the snapshot the parsers use is left as it is
+    let mut bindings = bind::Bindings::new();
+    bindings.bind(Telemetry::BINDING);
+    assert!(bindings.lookup("UDP", "dst", 4789).is_some());
+    assert!(bind::current().lookup("UDP", "dst", 4789).is_none());
+
+    Telemetry::register();
+    assert!(parser::slow::parse(&v).layer::<Telemetry>().is_some());
+}
@@ -0,0 +1,79 @@
+#!/bin/sh
+# Print the frames of filter.pcap each expression of filter_test selects, as tcpdump selects them
+#
+#     sh tests/data/filter_expected.sh > tests/data/filter_expected.txt
+#
+# Each line is a case of filter_test: the expression, a tab, and the 0-based indices of the frames
+# tcpdump printed.
+# With -d, the BPF program of each expression is printed instead, as `tcpdump -d` compiles it
+# for an ethernet link.
+
+set -e
+cd "$(dirname "$0")"
+
+dump=
+if [ "$1" = "-d" ]; then
+    dump=1
+fi
+
+while IFS= read -r expr; do
+    if [ -n "$dump" ]; then
+        printf '# %s\n' "$expr"
+        tcpdump -d -y EN10MB ${expr:+"$expr"}
+        continue
+    fi
+    # -# numbers the printed frames from 1
+    frames=$(tcpdump -# -n -r filter.pcap ${expr:+"$expr"} 2>/dev/null | awk '{ printf "%s%d", sep, $1 - 1; sep = " " }')
+    printf '%s\t%s\n' "$expr" "$frames"
+done <<'EOF'
+
+ip
+ip6
+arp
+udp
+tcp
+icmp
+icmp6
+host 10.1.1.1
+src host 10.1.1.1
+ip host 10.1.1.1
+arp host 10.1.1.1
+host 10.1.1.1 or 10.2.0.1
+src or dst host 10.1.1.2
+src and dst net 10.1.1.0/24
+net 10.1.1.0/24
+dst net 10.1.1.0/24
+net 10
+net 10.2.0.0 mask 255.255.0.0
+host 2001:db8::1
+ip6 net 2001:db8::/32
+port 4789
+udp port 4789 or 80
+port 4789 or 80
+portrange 50-80
+tcp and (port 80 or port 443)
+udp port 4789 and host 10.1.1.1 or port 22
+vlan 10
+vlan 10 and udp
+vlan and port 4789
+vlan and ip
+vlan 20 and vlan 30 and tcp port 22
+ip or vlan
+vlan or ip
+not ip
+not (ip or ip6)
+! arp && ! vlan
+ether host 00:00:00:00:00:02
+ether src 00:00:00:00:00:01
+ether broadcast
+broadcast
+ether multicast
+ip multicast
+ip6 multicast
+ether proto 0x88a8
+ether proto \arp
+ip proto 1
+proto 17
+greater 60
+less 42
+EOF
@@ -0,0 +1,35 @@
+{
+    let mut frame = Packet::new();
+    {
+        let mut ether = Ether::new();
+        ether.set_dst(0x2);
+        ether.set_src(0x1);
+        frame.push(ether);
+    }
+    {
+        let vlan = Vlan::new();
+        frame.push(vlan);
+    }
+    {
+        let mut ipv6 = IPv6::new();
+        ipv6.set_field_bytes("src", &[0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05]);
+        ipv6.set_field_bytes("dst", &[0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06]);
+        frame.push(ipv6);
+    }
+    {
+        let mut udp = UDP::new();
+        udp.set_src(4789);
+        frame.push(udp);
+    }
+    {
+        let mut vxlan = Vxlan::new();
+        vxlan.set_flags(0);
+        vxlan.set_vni(0x0);
+        frame.push(vxlan);
+    }
+    frame.fixup();
+    frame[2].set_field("payload_len", 46);
+    frame[3].set_field("length", 0);
+    frame[3].set_field("checksum", 0x0);
+    frame
+}
@@ -0,0 +1,15 @@
+{
+    let mut frame = Packet::new();
+    {
+        let mut ether = Ether::new();
+        ether.set_dst(0x2);
+        ether.set_src(0x1);
+        ether.set_etype(34984);
+        frame.push(ether);
+    }
+    frame.set_payload(b"\x00\x14\x81\x00\x00\x1e\x08\x00E\x00\x00(\x00\x01\x00\x00\
+        @\x06d\xc9\x0a\x01\x01\x01\x0a\x01\x01\x04\x00\x16\xc3P\
+        \x00\x00\x00\x01\x00\x00\x00\x00P\x02 \x00\xb6t\x00\x00");
+    frame.fixup();
+    frame
+}
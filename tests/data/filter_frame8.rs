@@ -0,0 +1,27 @@
+{
+    let mut frame = Packet::new();
+    {
+        let mut ether = Ether::new();
+        ether.set_dst(0x2);
+        ether.set_src(0x1);
+        frame.push(ether);
+    }
+    {
+        let mut ipv4 = IPv4::new();
+        ipv4.set_identification(1);
+        ipv4.set_flags(0);
+        ipv4.set_frag_offset(100);
+        ipv4.set_src(0xa010101);
+        ipv4.set_dst(0xa010103);
+        frame.push(ipv4);
+    }
+    {
+        let mut udp = UDP::new();
+        udp.set_src(4789);
+        udp.set_dst(4789);
+        frame.push(udp);
+    }
+    frame.fixup();
+    frame[1].set_field("header_checksum", 0x64cb);
+    frame
+}
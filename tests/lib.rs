@@ -6,6 +6,7 @@ use packet_rs::utils;
 
 use std::time::Instant;
 
+mod logger;
 mod pcap;
 
 const UDP_PORT_VXLAN: u16 = 4789;
@@ -27,8 +28,33 @@ pub fn ipv4_checksum_verify(v: &[u8]) -> u16 {
 mod tests {
 
     use super::*;
+    use packet_rs::addr::{self, MacAddr};
+    use packet_rs::anonymize::{AnonymizeOptions, Anonymizer, MacMode, PayloadMode};
+    use packet_rs::builders::{self, PacketConfig};
+    use packet_rs::capture::{Capacity, CaptureRing, Mask};
+    use packet_rs::diagnose::CheckStatus;
+    use packet_rs::filter::Filter;
+    use packet_rs::flow::{self, FlowTuple};
+    use packet_rs::frag;
+    use packet_rs::hop::{HopOptions, VlanRewrite};
+    use packet_rs::icmp;
+    use packet_rs::inspect::HeaderInspector;
+    use packet_rs::interface::{self, Impairment, Interface, MockInterface};
+    use packet_rs::malform::{Corrupt, Malformer};
+    use packet_rs::ops;
     use packet_rs::parser;
-    use packet_rs::Packet;
+    use packet_rs::payload;
+    use packet_rs::probe::{self, TraceMode};
+    use packet_rs::reassembly::{OverlapPolicy, ReassemblyStats, TcpReassembler};
+    use packet_rs::parser::LayerKind;
+    use packet_rs::rebase::RebaseOptions;
+    use packet_rs::summary;
+    use packet_rs::tlv::{self, Tlv, TlvFormat};
+    use packet_rs::tracker::{FlowTracker, TcpState};
+    use packet_rs::tunnel::{self, VxlanOuterParams};
+    use packet_rs::{Packet, PacketError};
+    use log::Level;
+    use logger::capture;
     use pcap::pcap_write;
 
     #[test]
@@ -55,6 +81,87 @@ mod tests {
         my_header.show();
     }
     #[test]
+    fn gre_option_order_test() {
+        let mut pkt = Packet::new();
+        pkt.push(Packet::ethernet(
+            "00:01:02:03:04:05",
+            "00:06:07:08:09:0a",
+            0x0800,
+        ));
+        let mut ipv4 = IPv4::new();
+        ipv4.set_protocol(47);
+        pkt.push(ipv4);
+        pkt.push(Packet::gre(true, false, true, true, false, 0, 0, 0x0800));
+        pkt.push(Packet::gre_chksum_offset(0x1111, 0));
+        pkt.push(Packet::gre_key(0x2222));
+        pkt.push(Packet::gre_sequence_number(0x3333));
+        pkt.push(IPv4::new());
+        let v = pkt.to_vec();
+
+        // the options are dissected in the order they are on the wire
+        let parsed = parser::slow::parse(&v);
+        let names = parsed.iter().map(|x| x.name()).collect::<Vec<_>>();
+        assert_eq!(
+            names[2..6],
+            ["GRE", "GREChksumOffset", "GREKey", "GRESequenceNum"]
+        );
+        assert_eq!(parsed.layer::<GREChksumOffset>().unwrap().chksum(), 0x1111);
+        assert_eq!(parsed.layer::<GREKey>().unwrap().key(), 0x2222);
+        assert_eq!(parsed.layer::<GRESequenceNum>().unwrap().seqnum(), 0x3333);
+        assert_eq!(parsed.to_vec(), v);
+        assert_eq!(parser::fast::parse(&v).to_vec(), v);
+    }
+    #[test]
+    fn mpls_bottom_of_stack_test() {
+        for labels in 1..=3 {
+            let mut pkt = Packet::new();
+            pkt.push(Ether::new());
+            for _ in 0..labels {
+                pkt.push(MPLS::new());
+            }
+            pkt.push(IPv4::new());
+            pkt.push(UDP::new());
+            let v = pkt.to_vec_with_fixup();
+
+            // the header under the label with the bos bit is the IP header, not another label
+            let parsed = parser::slow::parse(&v);
+            assert_eq!(parsed.layers::<MPLS>().len(), labels);
+            assert!(parsed.layers::<MPLS>()[labels - 1].bos());
+            assert!(parsed.layer::<UDP>().is_some());
+            assert_eq!(parsed.to_vec(), v);
+            assert_eq!(parser::fast::parse(&v).to_vec(), v);
+        }
+    }
+    #[test]
+    fn tunnel_option_fixup_test() {
+        // the options of a tunnel nested in another do not set the flags of the outer one
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(IPv4::new());
+        pkt.push(GRE::new());
+        pkt.push(IPv4::new());
+        pkt.push(GRE::new());
+        pkt.push(GREKey::new());
+        pkt.push(IPv4::new());
+        let parsed = parser::slow::parse(&pkt.to_vec_with_fixup());
+        let gre = parsed.layers::<GRE>();
+        assert!(!gre[0].key_present());
+        assert!(gre[1].key_present());
+        assert_eq!(parsed.len(), pkt.len());
+
+        let mut pkt = Packet::new();
+        pkt.push(L2tp::new());
+        pkt.push(IPv4::new());
+        pkt.push(UDP::new());
+        pkt.push(L2tp::new());
+        pkt.push(L2tpLength::new());
+        pkt.push(L2tpTunnel::new());
+        let v = pkt.to_vec_with_fixup();
+        let inner = L2tp::size() + IPv4::size() + UDP::size();
+        assert!(!L2tp::from(v[..L2tp::size()].to_vec()).l());
+        assert!(L2tp::from(v[inner..inner + L2tp::size()].to_vec()).l());
+    }
+    #[test]
     fn ethernet_header_test() {
         let mut eth = Ether::new();
         eth.show();
@@ -96,9 +203,9 @@ mod tests {
         assert_eq!(vlan.pcp(), 0x5);
 
         // cfi
-        assert_eq!(vlan.cfi(), 0x0);
+        assert!(!vlan.cfi());
         vlan.set_cfi(0x1 as u64);
-        assert_eq!(vlan.cfi(), 0x1);
+        assert!(vlan.cfi());
 
         // vid
         assert_eq!(vlan.vid(), 0xa);
@@ -111,8 +218,12 @@ mod tests {
         let b = t.as_slice();
         assert_eq!(a.iter().zip(b).filter(|&(a, b)| a == b).count(), 4);
         assert_eq!(vlan.vid(), 4095);
+        let table = vlan.field_table();
+        assert_eq!(table.lines().count(), 4);
+        assert!(table.starts_with("pcp"));
         assert_eq!(vlan.pcp(), 3);
-        assert_eq!(vlan.cfi(), 1);
+        assert!(vlan.cfi());
+        assert_eq!(vlan.cfi_raw(), 1);
     }
     #[test]
     fn ip_header_test() {
@@ -669,7 +780,10 @@ mod tests {
             &_erspan2,
             &_erspan3,
         ];
-        pcap_write(&pkts.iter().map(|x| x.to_vec() as Vec<u8>).collect());
+        let path = pcap_write(&pkts.iter().map(|x| x.to_vec() as Vec<u8>).collect());
+        let reader = packet_rs::pcap::PcapReader::open(&path).unwrap();
+        assert_eq!(reader.records().len(), pkts.len());
+        std::fs::remove_file(&path).unwrap();
 
         for pkt in pkts {
             let parsed = parser::slow::parse(pkt.to_vec().as_slice());
@@ -748,6 +862,5063 @@ mod tests {
         x.show();
     }
     #[test]
+    fn downcast_header_test() {
+        let hdr: Box<dyn Header> = Packet::vxlan(2000).to_owned();
+
+        // failed downcast hands back the original header
+        let hdr = match downcast::<Ether>(hdr) {
+            Ok(_) => panic!("Vxlan downcast to Ether"),
+            Err(hdr) => hdr,
+        };
+        assert_eq!(hdr.name(), "Vxlan");
+        let vxlan: &Vxlan = (&hdr).into();
+        assert_eq!(vxlan.vni(), 2000);
+
+        let vxlan: Box<Vxlan> = downcast(hdr).ok().unwrap();
+        assert_eq!(vxlan.vni(), 2000);
+    }
+    #[test]
+    fn take_header_test() {
+        let mut pkt = test_tcp_packet();
+        let len = pkt.len();
+
+        let ipv4: IPv4 = pkt.take_header().unwrap();
+        assert_eq!(ipv4.ttl(), 64);
+        assert_eq!(pkt.len(), len - IPv4::size());
+        assert_eq!(
+            pkt.get_header::<IPv4>("IPv4").err(),
+            Some(PacketError::MissingHeader(String::from("IPv4")))
+        );
+        assert_eq!(
+            pkt.take_header::<Vxlan>().err(),
+            Some(PacketError::MissingHeader(String::from("Vxlan")))
+        );
+        assert_eq!(
+            pkt.get_header_mut::<UDP>("TCP").err(),
+            Some(PacketError::HeaderMismatch {
+                expected: String::from("UDP"),
+                found: String::from("TCP")
+            })
+        );
+        assert_eq!(pkt.len(), len - IPv4::size());
+
+        let mut other = Packet::new();
+        other.push(ipv4);
+        assert_eq!(other.len(), IPv4::size());
+    }
+    #[test]
+    fn bytes_mut_reparse_test() {
+        let mut pkt = test_tcp_packet();
+        let v = pkt.to_contiguous();
+        assert!(pkt.compare_with_slice(v.as_slice()));
+
+        // rewrite the ipv4 protocol to udp and let the packet be dissected again
+        {
+            let mut buf = pkt.bytes_mut();
+            buf[Ether::size() + 9] = 17;
+        }
+        let ipv4: &IPv4 = pkt.get_header("IPv4").unwrap();
+        assert_eq!(ipv4.protocol(), 17);
+        assert!(pkt.get_header::<UDP>("UDP").is_ok());
+        assert!(pkt.get_header::<TCP>("TCP").is_err());
+        assert_eq!(pkt.len(), v.len());
+
+        // headers carried as payload are picked up by reparse
+        let v = test_tcp_packet().to_vec();
+        let mut pkt = Packet::new();
+        pkt.push(Ether::from(v[..Ether::size()].to_vec()));
+        pkt.set_payload(&v[Ether::size()..]);
+        assert!(pkt.get_header::<IPv4>("IPv4").is_err());
+        pkt.reparse();
+        assert!(pkt.get_header::<IPv4>("IPv4").is_ok());
+        assert!(pkt.get_header::<TCP>("TCP").is_ok());
+        assert!(pkt.compare_with_slice(v.as_slice()));
+    }
+    #[test]
+    fn header_clone_test() {
+        // resolves to std::clone::Clone with the Header trait in scope
+        let eth = Ether::new();
+        let mut b: Ether = eth.clone();
+        b.set_etype(0x86dd);
+        assert_eq!(eth.etype(), 0x800);
+        assert_eq!(b.etype(), 0x86dd);
+
+        let hdrs: Vec<Box<dyn Header>> = vec![eth.clone_box(), b.to_owned()];
+        let mut copy = hdrs.clone();
+        let x: &mut Ether = (&mut copy[1]).into();
+        x.set_etype(0x8100);
+        let y: &Ether = (&hdrs[1]).into();
+        assert_eq!(y.etype(), 0x86dd);
+
+        let pkt = test_tcp_packet();
+        let mut new_pkt = pkt.clone();
+        let x: &mut Ether = new_pkt.get_header_mut("Ether").unwrap();
+        x.set_etype(0x9999);
+        assert!(!pkt.compare(&new_pkt));
+    }
+    #[test]
+    fn layer_access_test() {
+        let inner = utils::create_tcp_packet(
+            "00:11:11:11:11:11",
+            "00:22:22:22:22:22",
+            false,
+            10,
+            3,
+            5,
+            "10.10.10.1",
+            "11.11.11.1",
+            0,
+            64,
+            115,
+            0,
+            Vec::new(),
+            8888,
+            9090,
+            100,
+            101,
+            5,
+            0,
+            2,
+            0,
+            0,
+            false,
+            &[0; 10],
+        );
+        let vxlan = utils::create_vxlan_packet(
+            "00:01:02:03:04:05",
+            "00:06:07:08:09:0a",
+            false,
+            10,
+            3,
+            5,
+            "192.168.0.199",
+            "192.168.0.1",
+            0,
+            64,
+            0,
+            0x4000,
+            Vec::new(),
+            UDP_PORT_VXLAN,
+            9090,
+            false,
+            2000,
+            inner,
+        );
+        let mut pkt = parser::slow::parse(vxlan.to_vec().as_slice());
+
+        let names: Vec<&str> = pkt.iter().map(|x| x.name()).collect();
+        assert_eq!(
+            names,
+            vec!["Ether", "IPv4", "UDP", "Vxlan", "Ether", "IPv4", "TCP"]
+        );
+
+        // outer and inner layers by index
+        let outer: &Ether = (&pkt[0]).into();
+        assert_eq!(outer.dst(), 0x000102030405);
+        let inner: &Ether = (&pkt[4]).into();
+        assert_eq!(inner.dst(), 0x001111111111);
+        let inner_ip: &IPv4 = (&pkt[5]).into();
+        assert_eq!(inner_ip.src(), 0x0a0a0a01);
+
+        // first layer of a type is the outer one
+        let ip: &IPv4 = pkt.layer().unwrap();
+        assert_eq!(ip.src(), 0xc0a800c7);
+        assert!(pkt.layer::<IPv6>().is_none());
+
+        let eths = pkt.layers::<Ether>();
+        assert_eq!(eths.len(), 2);
+        assert_eq!(eths[1].src(), 0x002222222222);
+
+        assert_eq!(pkt.layer_offset::<Ether>(), Some(0));
+        assert_eq!(pkt.layer_offset::<Vxlan>(), Some(42));
+        assert_eq!(pkt.layer_offset::<TCP>(), Some(84));
+        assert_eq!(pkt.layer_offset::<IPv6>(), None);
+
+        let ip: &mut IPv4 = pkt.layer_mut().unwrap();
+        ip.set_ttl(10);
+        let x: &mut IPv4 = (&mut pkt[5]).into();
+        x.set_ttl(20);
+        let ips = pkt.layers::<IPv4>();
+        assert_eq!(ips[0].ttl(), 10);
+        assert_eq!(ips[1].ttl(), 20);
+
+        for hdr in pkt.iter_mut() {
+            if let Some(eth) = hdr.as_any_mut().downcast_mut::<Ether>() {
+                eth.set_etype(0x86dd);
+            }
+        }
+        assert!(pkt.layers::<Ether>().iter().all(|x| x.etype() == 0x86dd));
+
+        let udp: &dyn Header = pkt["UDP"].as_ref();
+        assert_eq!(udp.len(), UDP::size());
+    }
+    fn l4_checksum_verify(pkt: &Packet, offset: usize, proto: u8) -> u16 {
+        let v = pkt.to_vec();
+        let (src, dst) = match pkt.layer::<IPv4>() {
+            Some(ip) => (
+                ip.bytes(IPv4::src_msb(), IPv4::src_lsb()),
+                ip.bytes(IPv4::dst_msb(), IPv4::dst_lsb()),
+            ),
+            None => {
+                let ip: &IPv6 = pkt.layer().unwrap();
+                (
+                    ip.bytes(IPv6::src_msb(), IPv6::src_lsb()),
+                    ip.bytes(IPv6::dst_msb(), IPv6::dst_lsb()),
+                )
+            }
+        };
+        Packet::l4_checksum(&src, &dst, proto, &v[offset..])
+    }
+    #[test]
+    fn builders_tcp_test() {
+        let pkt = builders::create_tcp_packet(&PacketConfig {
+            ip_src: String::from("10.10.10.1"),
+            ip_dst: String::from("11.11.11.1"),
+            sport: 4000,
+            dport: 8080,
+            tcp_flags: 0x12,
+            ip_ttl: 32,
+            payload_len: 101,
+            ..Default::default()
+        });
+        assert_eq!(pkt.len(), 14 + 20 + 20 + 101);
+        let ip: &IPv4 = pkt.layer().unwrap();
+        assert_eq!(ip.total_len() as usize, pkt.len() - Ether::size());
+        assert_eq!(ip.ttl(), 32);
+        assert_eq!(ip.src(), 0x0a0a0a01);
+        assert_eq!(ipv4_checksum_verify(ip.to_vec().as_slice()), 0);
+        let tcp: &TCP = pkt.layer().unwrap();
+        assert_eq!(tcp.src(), 4000);
+        assert_eq!(tcp.dst(), 8080);
+        assert_eq!(tcp.flags(), 0x12);
+        assert_ne!(tcp.checksum(), 0);
+        assert_eq!(l4_checksum_verify(&pkt, 34, 6), 0);
+
+        // parsed packet matches the built one
+        let parsed = parser::slow::parse(pkt.to_vec().as_slice());
+        assert!(parsed.compare(&pkt));
+        assert!(pkt.layer::<Vlan>().is_none());
+    }
+    #[test]
+    fn builders_udp_test() {
+        let pkt = builders::create_udp_packet(&PacketConfig {
+            vlan: Some(100),
+            vlan_pcp: 5,
+            payload_len: 33,
+            ..Default::default()
+        });
+        assert_eq!(pkt.len(), 14 + 4 + 20 + 8 + 33);
+        let vlan: &Vlan = pkt.layer().unwrap();
+        assert_eq!(vlan.vid(), 100);
+        assert_eq!(vlan.pcp(), 5);
+        let ip: &IPv4 = pkt.layer().unwrap();
+        assert_eq!(ip.total_len(), 20 + 8 + 33);
+        assert_eq!(ipv4_checksum_verify(ip.to_vec().as_slice()), 0);
+        let udp: &UDP = pkt.layer().unwrap();
+        assert_eq!(udp.length(), 8 + 33);
+        assert_eq!(udp.src(), 1234);
+        assert_eq!(udp.dst(), 80);
+        assert_eq!(l4_checksum_verify(&pkt, 38, 17), 0);
+
+        let parsed = parser::slow::parse(pkt.to_vec().as_slice());
+        assert!(parsed.compare(&pkt));
+    }
+    #[test]
+    fn builders_ipv6_tcp_test() {
+        let pkt = builders::create_ipv6_tcp_packet(&PacketConfig {
+            ipv6_src: String::from("aaaa::1"),
+            ip_ttl: 10,
+            payload_len: 20,
+            ..Default::default()
+        });
+        assert_eq!(pkt.len(), 14 + 40 + 20 + 20);
+        let ip: &IPv6 = pkt.layer().unwrap();
+        assert_eq!(ip.payload_len(), 40);
+        assert_eq!(ip.hop_limit(), 10);
+        assert_eq!(ip.next_hdr(), 6);
+        assert_eq!(l4_checksum_verify(&pkt, 54, 6), 0);
+
+        let parsed = parser::slow::parse(pkt.to_vec().as_slice());
+        assert!(parsed.compare(&pkt));
+    }
+    #[test]
+    fn builders_arp_test() {
+        let pkt = builders::create_arp_packet(&PacketConfig {
+            eth_dst: String::from("ff:ff:ff:ff:ff:ff"),
+            ip_dst: String::from("192.168.0.100"),
+            ..Default::default()
+        });
+        assert_eq!(pkt.len(), 14 + 28);
+        let eth: &Ether = pkt.layer().unwrap();
+        assert_eq!(eth.etype(), 0x806);
+        let arp: &ARP = pkt.layer().unwrap();
+        assert_eq!(arp.opcode(), 1);
+        assert_eq!(arp.sender_hw_addr(), 0x00060708090a);
+        assert_eq!(arp.sender_proto_addr(), 0xc0a80001);
+        assert_eq!(arp.target_hw_addr(), 0);
+        assert_eq!(arp.target_proto_addr(), 0xc0a80064);
+    }
+    #[test]
+    fn builders_vxlan_test() {
+        let inner = builders::create_tcp_packet(&PacketConfig {
+            payload_len: 10,
+            ..Default::default()
+        });
+        let pkt = builders::create_vxlan_packet(
+            &PacketConfig {
+                ip_src: String::from("1.1.1.1"),
+                ip_dst: String::from("2.2.2.2"),
+                ..Default::default()
+            },
+            3000,
+            inner.clone(),
+        );
+        assert_eq!(pkt.len(), 14 + 20 + 8 + 8 + inner.len());
+        let ip: &IPv4 = pkt.layer().unwrap();
+        assert_eq!(ip.total_len() as usize, pkt.len() - Ether::size());
+        assert_eq!(ipv4_checksum_verify(ip.to_vec().as_slice()), 0);
+        let udp: &UDP = pkt.layer().unwrap();
+        assert_eq!(udp.dst() as u16, UDP_PORT_VXLAN);
+        assert_eq!(udp.length() as usize, pkt.len() - 34);
+        assert_eq!(l4_checksum_verify(&pkt, 34, 17), 0);
+        let vxlan: &Vxlan = pkt.layer().unwrap();
+        assert_eq!(vxlan.vni(), 3000);
+
+        let parsed = parser::slow::parse(pkt.to_vec().as_slice());
+        assert_eq!(parsed.layers::<Ether>().len(), 2);
+        assert_eq!(parsed.layers::<TCP>().len(), 1);
+        assert!(parsed.compare(&pkt));
+    }
+    #[test]
+    fn field_registry_test() {
+        let mut hdr: Box<dyn Header> = IPv6::new().to_owned();
+        let names: Vec<&str> = hdr.fields().iter().map(|f| f.name).collect();
+        assert_eq!(names[0], "version");
+        assert_eq!(IPv6::FIELDS[6].size(), 128);
+
+        assert_eq!(hdr.get_field("hop_limit"), Some(0x40));
+        assert!(hdr.set_field("hop_limit", 3));
+        assert_eq!(hdr.get_field("hop_limit"), Some(3));
+        assert!(!hdr.set_field("ttl", 3));
+        assert_eq!(hdr.get_field("ttl"), None);
+
+        let dst = [0xaa; 16];
+        assert!(hdr.set_field_bytes("dst", &dst));
+        let ipv6: &IPv6 = (&hdr).into();
+        assert_eq!(ipv6.bytes(IPv6::dst_msb(), IPv6::dst_lsb()), dst.to_vec());
+        assert_eq!(ipv6.hop_limit(), 3);
+    }
+    #[test]
+    fn packet_spec_test() {
+        let pkt = Packet::from_spec(
+            "Eth(dst=aa:bb:cc:dd:ee:ff, src=00-01-02-03-04-05)/IPv4(src=1.2.3.4,dst=5.6.7.8,ttl=10)/TCP(sport=0x400,dport=80,flags=SA)",
+        )
+        .unwrap();
+        let names: Vec<&str> = pkt.iter().map(|x| x.name()).collect();
+        assert_eq!(names, vec!["Ether", "IPv4", "TCP"]);
+        let eth: &Ether = pkt.layer().unwrap();
+        assert_eq!(eth.dst(), 0xaabbccddeeff);
+        assert_eq!(eth.src(), 0x000102030405);
+        let ip: &IPv4 = pkt.layer().unwrap();
+        assert_eq!(ip.src(), 0x01020304);
+        assert_eq!(ip.dst(), 0x05060708);
+        assert_eq!(ip.ttl(), 10);
+        let tcp: &TCP = pkt.layer().unwrap();
+        assert_eq!(tcp.src(), 0x400);
+        assert_eq!(tcp.dst(), 80);
+        assert_eq!(tcp.flags(), 0x12);
+
+        let pkt = Packet::try_from("Ether/Vlan(vid=10)/IPv6(dst=2001:db8::1)/UDP").unwrap();
+        let vlan: &Vlan = pkt.layer().unwrap();
+        assert_eq!(vlan.vid(), 10);
+        let ip: &IPv6 = pkt.layer().unwrap();
+        assert_eq!(
+            ip.bytes(IPv6::dst_msb(), IPv6::dst_lsb()),
+            "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap().octets().to_vec()
+        );
+        assert_eq!(pkt.len(), 14 + 4 + 40 + 8);
+
+        assert_eq!(
+            Packet::from_spec("Ether/Foo").err(),
+            Some(PacketError::UnknownHeader(String::from("Foo")))
+        );
+        assert_eq!(
+            Packet::from_spec("Ether(foo=1)").err(),
+            Some(PacketError::UnknownField {
+                header: String::from("Ether"),
+                field: String::from("foo")
+            })
+        );
+        assert_eq!(
+            Packet::from_spec("Vlan(pcp=8)").err(),
+            Some(PacketError::InvalidValue {
+                field: String::from("pcp"),
+                value: String::from("8")
+            })
+        );
+        assert!(Packet::from_spec("TCP(flags=SX)").is_err());
+        assert!(Packet::from_spec("Ether(dst=1").is_err());
+        assert!(Packet::from_spec("Ether//IPv4").is_err());
+        assert!(Packet::from_spec("").is_err());
+    }
+    #[test]
+    fn set_field_str_test() {
+        let kind =
+            |fields: &[FieldInfo], name: &str| fields.iter().find(|f| f.name == name).unwrap().kind;
+        assert_eq!(kind(IPv4::FIELDS, "src"), FieldKind::Ipv4);
+        assert_eq!(kind(Ether::FIELDS, "dst"), FieldKind::Mac);
+        assert_eq!(kind(IPv6::FIELDS, "dst"), FieldKind::Ipv6);
+        assert_eq!(kind(TCP::FIELDS, "src"), FieldKind::Number);
+
+        let mut hdrs: Vec<Box<dyn Header>> = vec![
+            Box::new(Ether::new()),
+            Box::new(ARP::new()),
+            Box::new(IPv6::new()),
+            Box::new(Igmp::new()),
+        ];
+        let sets = [
+            ("src", "0011.2233.4455"),
+            ("target_proto_addr", "10.0.0.2"),
+            ("dst", "ff02::1"),
+            ("group_address", "224.0.0.251"),
+        ];
+        for (hdr, (field, value)) in hdrs.iter_mut().zip(sets) {
+            hdr.set_field_str(field, value).unwrap();
+        }
+        assert_eq!(hdrs[0].get_field("src"), Some(0x001122334455));
+        assert_eq!(hdrs[1].get_field("target_proto_addr"), Some(0x0a000002));
+        let dst = "ff02::1".parse::<std::net::Ipv6Addr>().unwrap();
+        assert_eq!(hdrs[2].get_field_bytes("dst"), Some(dst.octets().to_vec()));
+        assert_eq!(hdrs[3].get_field("group_address"), Some(0xe00000fb));
+
+        // every field takes a number, only the address fields take an address
+        let mut eth = Ether::new();
+        eth.set_field_str("dst", "0xffffffffffff").unwrap();
+        eth.set_field_str("etype", "0x86dd").unwrap();
+        eth.set_field_str("etype", "2054").unwrap();
+        assert_eq!((eth.dst(), eth.etype()), (0xffffffffffff, 0x0806));
+        let invalid = |field: &str, value: &str| PacketError::InvalidValue {
+            field: String::from(field),
+            value: String::from(value),
+        };
+        assert_eq!(
+            eth.set_field_str("etype", "00:11:22:33:44:55"),
+            Err(invalid("etype", "00:11:22:33:44:55"))
+        );
+        assert_eq!(
+            eth.set_field_str("dst", "10.0.0.1"),
+            Err(invalid("dst", "10.0.0.1"))
+        );
+        let mut ip = IPv4::new();
+        assert_eq!(
+            ip.set_field_str("ttl", "1.2.3.4"),
+            Err(invalid("ttl", "1.2.3.4"))
+        );
+        assert_eq!(ip.set_field_str("src", "::1"), Err(invalid("src", "::1")));
+        assert!(ip.set_field_str("foo", "1").is_err());
+        let mut tcp = TCP::new();
+        tcp.set_field_str("flags", "SA").unwrap();
+        tcp.set_field_str("dport", "443").unwrap();
+        assert_eq!((tcp.flags(), tcp.dst()), (0x12, 443));
+
+        let mut arp = ARP::new();
+        let mut inspector = HeaderInspector::new(&mut arp);
+        inspector
+            .set_value("sender_hw_addr", " aa:bb:cc:dd:ee:ff ")
+            .unwrap();
+        inspector.set_value("opcode", "2").unwrap();
+        assert_eq!(inspector.rows()[5].2, "0xaabbccddeeff");
+        assert_eq!(arp.opcode(), 2);
+    }
+    #[test]
+    fn mac_addr_test() {
+        let mac: MacAddr = "00:11:22:aa:bb:cc".parse().unwrap();
+        assert_eq!(mac, MacAddr([0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc]));
+        assert_eq!("00-11-22-AA-BB-CC".parse::<MacAddr>(), Ok(mac));
+        assert_eq!("0011.22aa.bbcc".parse::<MacAddr>(), Ok(mac));
+        assert_eq!(mac.to_string(), "00:11:22:aa:bb:cc");
+        assert_eq!(u64::from(mac), 0x001122aabbcc);
+        assert_eq!(MacAddr::from(0xffff_0011_22aa_bbcc), mac);
+        assert!("00:11:22:aa:bb".parse::<MacAddr>().is_err());
+        assert!("00:11:22:aa:bb:zz".parse::<MacAddr>().is_err());
+        assert!("0011.22aa.bbc".parse::<MacAddr>().is_err());
+        assert!("+0:11:22:aa:bb:cc".parse::<MacAddr>().is_err());
+
+        assert!(mac.is_unicast());
+        assert!(!mac.is_local());
+        assert!(MacAddr::BROADCAST.is_broadcast());
+        assert!(MacAddr::BROADCAST.is_multicast());
+        assert!(MacAddr::from(0x01005e000001).is_multicast());
+        assert!(!MacAddr::from(0x01005e000001).is_broadcast());
+
+        let rand = MacAddr::random(7);
+        assert_eq!(rand, MacAddr::random(7));
+        assert_ne!(rand, MacAddr::random(8));
+        assert!(rand.is_local());
+        assert!(rand.is_unicast());
+
+        let mut eth = Ether::new();
+        eth.set_dst_mac(MacAddr::BROADCAST);
+        eth.set_src_mac(mac);
+        assert_eq!(eth.dst(), 0xffffffffffff);
+        assert_eq!(eth.src_mac(), mac);
+
+        let mut arp = ARP::new();
+        arp.set_sender_hw_addr_mac(mac);
+        arp.set_target_proto_addr_ip("10.0.0.1".parse().unwrap());
+        assert_eq!(arp.sender_hw_addr(), 0x001122aabbcc);
+        assert_eq!(arp.target_proto_addr(), 0x0a000001);
+    }
+    #[test]
+    fn ip_addr_test() {
+        let ip: std::net::Ipv4Addr = "192.168.1.2".parse().unwrap();
+        assert_eq!(addr::ipv4_to_u64(ip), 0xc0a80102);
+        assert_eq!(addr::u64_to_ipv4(0xc0a80102), ip);
+
+        let mut ipv4 = IPv4::new();
+        ipv4.set_dst_ip(ip);
+        assert_eq!(ipv4.dst(), 0xc0a80102);
+        assert_eq!(ipv4.dst_ip(), ip);
+
+        let ip6: std::net::Ipv6Addr = "2001:db8::1".parse().unwrap();
+        assert_eq!(addr::bytes_to_ipv6(&addr::ipv6_to_bytes(ip6)), Some(ip6));
+        assert_eq!(addr::bytes_to_ipv6(&[0; 4]), None);
+        let mut ipv6 = IPv6::new();
+        ipv6.set_src_ip(ip6);
+        assert_eq!(ipv6.src_ip(), ip6);
+        assert_eq!(
+            ipv6.bytes(IPv6::src_msb(), IPv6::src_lsb()),
+            ip6.octets().to_vec()
+        );
+        assert_ne!(ipv6.dst_ip(), ip6);
+    }
+    #[test]
+    fn igmp_mld_test() {
+        let mut ipv4 = IPv4::new();
+        ipv4.set_protocol(2);
+        let mut igmp = Igmp::new();
+        igmp.set_igmp_type(0x16);
+        igmp.set_group_address_ip("239.1.1.1".parse().unwrap());
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(ipv4.clone());
+        pkt.push(igmp);
+        let v = pkt.to_vec();
+        let parsed = parser::slow::parse(&v);
+        let igmp = parsed.layer::<Igmp>().unwrap();
+        assert_eq!(igmp.igmp_type(), 0x16);
+        assert_eq!(igmp.group_address(), 0xef010101);
+        let slice = parser::fast::parse(&v);
+        assert_eq!(slice.to_vec(), v);
+
+        let mut report = IgmpV3Report::new();
+        report.set_num_records(1);
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(ipv4);
+        pkt.push(report);
+        pkt.set_payload(&[0x4, 0x0, 0x0, 0x0, 0xef, 0x1, 0x1, 0x1]);
+        let parsed = parser::slow::parse(&pkt.to_vec());
+        assert_eq!(parsed.layer::<IgmpV3Report>().unwrap().num_records(), 1);
+        assert_eq!(parsed.to_vec(), pkt.to_vec());
+
+        let mut ipv6 = IPv6::new();
+        ipv6.set_next_hdr(58);
+        let mut eth = Ether::new();
+        eth.set_etype(0x86dd);
+        let mut pkt = Packet::new();
+        pkt.push(eth);
+        pkt.push(ipv6);
+        pkt.push(Mld::new());
+        let parsed = parser::slow::parse(&pkt.to_vec());
+        assert_eq!(parsed.layer::<Mld>().unwrap().max_resp(), 10000);
+        pkt[2] = MldV2Report::new().to_owned();
+        let parsed = parser::slow::parse(&pkt.to_vec());
+        assert!(parsed.layer::<MldV2Report>().is_some());
+        pkt[2] = ICMP::new().to_owned();
+        let parsed = parser::slow::parse(&pkt.to_vec());
+        assert!(parsed.layer::<ICMP>().is_some());
+        assert!(parsed.layer::<Mld>().is_none());
+    }
+    #[test]
+    fn payload_pattern_test() {
+        let v = payload::incrementing(300);
+        assert_eq!(v[255], 255);
+        assert_eq!(v[256], 0);
+        assert_eq!(payload::verify_incrementing(&v), None);
+        assert_eq!(payload::repeat(0xa5, 4), vec![0xa5; 4]);
+        assert_eq!(payload::verify_repeat(0xa5, &[0xa5, 0xa5, 0xa4]), Some(2));
+
+        let r = payload::random_seeded(42, 100);
+        assert_eq!(r.len(), 100);
+        assert_eq!(r, payload::random_seeded(42, 100));
+        assert_ne!(r, payload::random_seeded(43, 100));
+        assert_eq!(payload::random_seeded(42, 10), r[..10].to_vec());
+        assert_eq!(payload::verify_random_seeded(42, &r), None);
+
+        let a = payload::ascii_cycle(200);
+        assert_eq!(a[0], b' ');
+        assert_eq!(a[94], b'~');
+        assert_eq!(a[95], b' ');
+        assert_eq!(payload::verify_ascii_cycle(&a), None);
+
+        // corrupted and truncated captures
+        let mut r = r;
+        r[77] ^= 0x10;
+        assert_eq!(payload::verify_random_seeded(42, &r), Some(77));
+        assert_eq!(payload::verify_random_seeded(42, &r[..77]), None);
+        assert_eq!(payload::verify_ascii_cycle(&a[..3]), None);
+        assert_eq!(payload::first_mismatch(&[1, 2, 3], &[1, 2]), None);
+        assert_eq!(payload::verify_incrementing(&[]), None);
+    }
+    #[test]
+    fn sequence_stamp_test() {
+        let mut pkt = builders::create_udp_packet(&PacketConfig {
+            payload_len: 32,
+            ..Default::default()
+        });
+        let len = pkt.len();
+        payload::stamp_sequence(&mut pkt, 0x0123456789abcdef, 4);
+        assert_eq!(pkt.len(), len);
+        let rx = parser::slow::parse(&pkt.to_vec());
+        assert_eq!(payload::extract_sequence(&rx, 4), Some(0x0123456789abcdef));
+        assert_eq!(payload::extract_sequence(&rx, 5), None);
+
+        // truncated capture
+        let v = pkt.to_vec();
+        let rx = parser::slow::parse(&v[..len - 20]);
+        assert_eq!(payload::extract_sequence(&rx, 4), None);
+
+        // short payload is padded
+        let mut pkt = builders::create_udp_packet(&Default::default());
+        payload::stamp_sequence(&mut pkt, 7, 2);
+        assert_eq!(pkt.len(), len - 32 + 2 + payload::SEQUENCE_LEN);
+        assert_eq!(payload::extract_sequence(&pkt, 2), Some(7));
+    }
+    #[test]
+    fn toeplitz_hash_test() {
+        // Microsoft RSS verification suite, (src, sport, dst, dport, hash with ports, ip only hash)
+        let vectors = [
+            ("66.9.149.187", 2794, "161.142.100.80", 1766, 0x51ccc178, 0x323e8fc2),
+            ("199.92.111.2", 14230, "65.69.140.83", 4739, 0xc626b0ea, 0xd718262a),
+            ("24.19.198.95", 12898, "12.22.207.184", 38024, 0x5c2b394a, 0xd2d0a5de),
+            ("38.27.205.30", 48228, "209.142.163.6", 2217, 0xafc7327f, 0x82989176),
+            ("153.39.163.191", 44251, "202.188.127.2", 1303, 0x10e828a2, 0x5d1809c5),
+            ("3ffe:2501:200:1fff::7", 2794, "3ffe:2501:200:3::1", 1766, 0x40207d3d, 0x2cc18cd5),
+            ("3ffe:501:8::260:97ff:fe40:efab", 14230, "ff02::1", 4739, 0xdde51bbf, 0x0f0c461c),
+            ("3ffe:1900:4545:3:200:f8ff:fe21:67cf", 44251, "fe80::200:f8ff:fe21:67cf", 38024, 0x02d1feef, 0x4b61e985),
+        ];
+        for (src, sport, dst, dport, hash, ip_hash) in vectors {
+            let mut tuple = FlowTuple {
+                src: src.parse().unwrap(),
+                dst: dst.parse().unwrap(),
+                proto: 6,
+                ports: Some((sport, dport)),
+            };
+            assert_eq!(flow::toeplitz_hash(&tuple, &flow::DEFAULT_RSS_KEY), hash);
+            tuple.ports = None;
+            assert_eq!(flow::toeplitz_hash(&tuple, &flow::DEFAULT_RSS_KEY), ip_hash);
+        }
+    }
+    #[test]
+    fn flow_tuple_test() {
+        let cfg = PacketConfig {
+            ip_src: String::from("66.9.149.187"),
+            ip_dst: String::from("161.142.100.80"),
+            sport: 2794,
+            dport: 1766,
+            vlan: Some(100),
+            ..Default::default()
+        };
+        let pkt = builders::create_tcp_packet(&cfg);
+        let tuple = pkt.flow_tuple().unwrap();
+        assert_eq!(tuple.src, "66.9.149.187".parse::<std::net::IpAddr>().unwrap());
+        assert_eq!(tuple.proto, 6);
+        assert_eq!(tuple.ports, Some((2794, 1766)));
+        assert_eq!(flow::toeplitz_hash(&tuple, &flow::DEFAULT_RSS_KEY), 0x51ccc178);
+        let parsed = parser::slow::parse(&pkt.to_vec());
+        assert_eq!(parsed.flow_tuple(), Some(tuple));
+
+        let rev = builders::create_tcp_packet(&PacketConfig {
+            ip_src: cfg.ip_dst.clone(),
+            ip_dst: cfg.ip_src.clone(),
+            sport: cfg.dport,
+            dport: cfg.sport,
+            ..Default::default()
+        });
+        let rev = rev.flow_tuple().unwrap();
+        assert_eq!(rev, tuple.reversed());
+        assert_eq!(flow::symmetric_hash(&rev), flow::symmetric_hash(&tuple));
+        assert_ne!(
+            flow::toeplitz_hash(&rev, &flow::DEFAULT_RSS_KEY),
+            flow::toeplitz_hash(&tuple, &flow::DEFAULT_RSS_KEY)
+        );
+
+        // no ports on non-first fragments
+        let mut frag = builders::create_udp_packet(&cfg);
+        frag.layer_mut::<IPv4>().unwrap().set_frag_offset(100);
+        let tuple = frag.flow_tuple().unwrap();
+        assert_eq!(tuple.proto, 17);
+        assert_eq!(tuple.ports, None);
+
+        let ipv6 = builders::create_ipv6_tcp_packet(&cfg);
+        let tuple = ipv6.flow_tuple().unwrap();
+        assert_eq!(tuple.dst, "2001:db8::2".parse::<std::net::IpAddr>().unwrap());
+        assert_eq!(tuple.ports, Some((2794, 1766)));
+
+        // tunnels
+        let vxlan = builders::create_vxlan_packet(&Default::default(), 10, pkt);
+        let vxlan = parser::slow::parse(&vxlan.to_vec());
+        let outer = vxlan.flow_tuple().unwrap();
+        assert_eq!(outer.proto, 17);
+        assert_eq!(outer.ports.unwrap().1, 4789);
+        assert_eq!(vxlan.inner_flow_tuple().unwrap().ports, Some((2794, 1766)));
+
+        assert_eq!(builders::create_arp_packet(&cfg).flow_tuple(), None);
+    }
+    #[test]
+    fn write_into_test() {
+        let pkt = builders::create_tcp_packet(&PacketConfig {
+            payload_len: 10,
+            ..Default::default()
+        });
+        let v = pkt.to_vec();
+        let mut frame = [0xffu8; 128];
+        assert_eq!(pkt.write_into(&mut frame), Ok(v.len()));
+        assert_eq!(&frame[..v.len()], v.as_slice());
+        assert_eq!(frame[v.len()], 0xff);
+
+        let slice = parser::fast::parse(&v);
+        let mut frame2 = [0u8; 128];
+        assert_eq!(slice.write_into(&mut frame2), Ok(v.len()));
+        assert_eq!(&frame2[..v.len()], v.as_slice());
+
+        let mut short = [0u8; 60];
+        assert_eq!(
+            pkt.write_into(&mut short),
+            Err(PacketError::BufferTooSmall {
+                needed: v.len(),
+                available: 60
+            })
+        );
+        assert_eq!(short, [0u8; 60]);
+
+        let eth = pkt.layer::<Ether>().unwrap();
+        assert_eq!(eth.write_into(&mut short), Ok(Ether::size()));
+        assert_eq!(&short[..Ether::size()], eth.to_vec().as_slice());
+        assert!(eth.write_into(&mut short[..10]).is_err());
+    }
+    #[test]
+    fn routed_copy_test() {
+        let router = MacAddr::from(0x0a0000000001);
+        let nexthop = MacAddr::from(0x0a0000000002);
+        let pkt = builders::create_tcp_packet(&PacketConfig {
+            payload_len: 16,
+            ..Default::default()
+        });
+        let out = pkt.routed_copy(router, nexthop).unwrap();
+        assert_eq!(out.layer::<Ether>().unwrap().src_mac(), router);
+        assert_eq!(out.layer::<Ether>().unwrap().dst_mac(), nexthop);
+        assert_eq!(out.layer::<IPv4>().unwrap().ttl(), 63);
+        assert_eq!(ipv4_checksum_verify(&out.to_vec()[14..34]), 0);
+        assert_eq!(out.layer::<TCP>().unwrap().to_vec(), pkt.layer::<TCP>().unwrap().to_vec());
+        // the original packet is untouched
+        assert_eq!(pkt.layer::<IPv4>().unwrap().ttl(), 64);
+
+        let ttl1 = builders::create_tcp_packet(&PacketConfig {
+            ip_ttl: 1,
+            ..Default::default()
+        });
+        assert_eq!(ttl1.routed_copy(router, nexthop).err(), Some(PacketError::TtlExceeded));
+
+        let ipv6 = builders::create_ipv6_tcp_packet(&Default::default());
+        let opts = HopOptions {
+            dscp: Some(46),
+            vlan: VlanRewrite::Set { vid: 20, pcp: 5 },
+        };
+        let out = ipv6.routed_copy_with(router, nexthop, &opts).unwrap();
+        let ip = out.layer::<IPv6>().unwrap();
+        assert_eq!(ip.hop_limit(), 63);
+        assert_eq!(ip.traffic_class(), 46 << 2);
+        let vlan = out.layer::<Vlan>().unwrap();
+        assert_eq!((vlan.vid(), vlan.pcp(), vlan.etype()), (20, 5, 0x86dd));
+        assert_eq!(out.layer::<Ether>().unwrap().etype(), 0x8100);
+        let parsed = parser::slow::parse(&out.to_vec());
+        assert_eq!(parsed.layer::<IPv6>().unwrap().hop_limit(), 63);
+
+        let opts = HopOptions {
+            vlan: VlanRewrite::Pop,
+            ..Default::default()
+        };
+        let back = out.routed_copy_with(nexthop, router, &opts).unwrap();
+        assert!(back.layer::<Vlan>().is_none());
+        assert_eq!(back.layer::<Ether>().unwrap().etype(), 0x86dd);
+        assert_eq!(back.layer::<IPv6>().unwrap().hop_limit(), 62);
+
+        assert_eq!(
+            builders::create_arp_packet(&Default::default())
+                .routed_copy(router, nexthop)
+                .err(),
+            Some(PacketError::MissingHeader(String::from("IPv4")))
+        );
+    }
+    #[test]
+    fn to_vec_with_fixup_test() {
+        let mut eth = Ether::new();
+        eth.set_etype(0x1234);
+        let mut ipv4 = IPv4::new();
+        ipv4.set_protocol(0);
+        let mut udp = UDP::new();
+        udp.set_dst(53);
+        let mut pkt = Packet::new();
+        pkt.push(eth.clone());
+        pkt.push(ipv4.clone());
+        pkt.push(udp);
+        pkt.push(Vxlan::new());
+        pkt.push(eth);
+        pkt.push(ipv4);
+        pkt.push(TCP::new());
+        pkt.set_payload(&[0; 4]);
+
+        let names = |p: &Packet| p.iter().map(|x| String::from(x.name())).collect::<Vec<_>>();
+        let v = pkt.to_vec_with_fixup();
+        assert_eq!(v.len(), pkt.len());
+        let parsed = parser::slow::parse(&v);
+        assert_eq!(names(&parsed), names(&pkt));
+        assert_eq!(parsed.layer::<UDP>().unwrap().dst(), 4789);
+        assert_eq!(parsed.layers::<IPv4>()[1].protocol(), 6);
+        // the packet itself is unchanged
+        assert_eq!(pkt.layer::<UDP>().unwrap().dst(), 53);
+        assert_ne!(pkt.to_vec(), v);
+
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(Vlan::new());
+        pkt.push(IPv6::new());
+        pkt.push(IPv4::new());
+        pkt.push(GRE::new());
+        pkt.push(GREKey::new());
+        pkt.push(IPv6::new());
+        pkt.push(ICMP::new());
+        let parsed = parser::slow::parse(&pkt.to_vec_with_fixup());
+        assert_eq!(names(&parsed), names(&pkt));
+        assert!(parsed.layer::<GRE>().unwrap().key_present());
+        assert!(!parsed.layer::<GRE>().unwrap().chksum_present());
+        assert_eq!(parsed.layers::<IPv6>()[1].next_hdr(), 58);
+
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(MPLS::new());
+        pkt.push(MPLS::new());
+        pkt.push(IPv4::new());
+        let v = pkt.to_vec_with_fixup();
+        assert_eq!(v[12..14], [0x88, 0x47]);
+        assert_eq!(v[16] & 0x1, 0);
+        assert_eq!(v[20] & 0x1, 1);
+    }
+    #[test]
+    fn icmp_error_test() {
+        let router: std::net::Ipv4Addr = "10.0.0.254".parse().unwrap();
+        let pkt = builders::create_udp_packet(&PacketConfig {
+            vlan: Some(10),
+            payload_len: 100,
+            ..Default::default()
+        });
+        let reply = icmp::icmp_dest_unreachable(&pkt, 3, router).unwrap();
+        let eth = reply.layer::<Ether>().unwrap();
+        assert_eq!(eth.dst(), pkt.layer::<Ether>().unwrap().src());
+        assert_eq!(eth.etype(), 0x8100);
+        assert_eq!(reply.layer::<Vlan>().unwrap().vid(), 10);
+        let ip = reply.layer::<IPv4>().unwrap();
+        assert_eq!(ip.src_ip(), router);
+        assert_eq!(ip.dst(), pkt.layer::<IPv4>().unwrap().src());
+        assert_eq!(ip.protocol(), 1);
+        let v = reply.to_vec();
+        let ip_offset = reply.layer_offset::<IPv4>().unwrap();
+        assert_eq!(ip.total_len() as usize, v.len() - ip_offset);
+        assert_eq!(ipv4_checksum_verify(&v[ip_offset..ip_offset + 20]), 0);
+        assert_eq!(ipv4_checksum_verify(&v[ip_offset + 20..]), 0);
+        let icmp = reply.layer::<ICMP>().unwrap();
+        assert_eq!((icmp.icmp_type(), icmp.icmp_code()), (3, 3));
+        // the whole offending datagram fits in the quote
+        let quoted = &pkt.to_vec()[pkt.layer_offset::<IPv4>().unwrap()..];
+        assert_eq!(&v[ip_offset + 28..], quoted);
+        let parsed = parser::slow::parse(&v);
+        assert!(parsed.layer::<ICMP>().is_some());
+
+        // large packets are truncated to a 576 byte reply
+        let big = builders::create_tcp_packet(&PacketConfig {
+            payload_len: 1000,
+            ..Default::default()
+        });
+        let reply = icmp::icmp_time_exceeded(&big, router).unwrap();
+        let v = reply.to_vec();
+        assert_eq!(v.len() - 14, icmp::IPV4_ERROR_MAX_LEN);
+        assert_eq!(reply.layer::<IPv4>().unwrap().total_len(), 576);
+        assert_eq!(ipv4_checksum_verify(&v[34..]), 0);
+        assert_eq!(&v[34 + 8..], &big.to_vec()[14..14 + 576 - 28]);
+
+        let router6: std::net::Ipv6Addr = "2001:db8::fe".parse().unwrap();
+        let pkt6 = builders::create_ipv6_tcp_packet(&PacketConfig {
+            payload_len: 1500,
+            ..Default::default()
+        });
+        let reply = icmp::icmpv6_packet_too_big(&pkt6, 1400, router6).unwrap();
+        let v = reply.to_vec();
+        assert_eq!(v.len() - 14, icmp::IPV6_ERROR_MAX_LEN);
+        let ip = reply.layer::<IPv6>().unwrap();
+        assert_eq!(ip.payload_len() as usize, 1280 - 40);
+        assert_eq!(ip.dst_ip(), pkt6.layer::<IPv6>().unwrap().src_ip());
+        assert_eq!(&v[14 + 44..14 + 48], &1400u32.to_be_bytes());
+        assert_eq!(l4_checksum_verify(&reply, 54, 58), 0);
+        assert_eq!(&v[14 + 48..], &pkt6.to_vec()[14..14 + 1280 - 48]);
+
+        let reply = icmp::icmpv6_time_exceeded(&pkt6, router6).unwrap();
+        assert_eq!(reply.layer::<ICMP>().unwrap().icmp_type(), 3);
+        let reply = icmp::icmpv6_dest_unreachable(&pkt6, 4, router6).unwrap();
+        assert_eq!(reply.layer::<ICMP>().unwrap().icmp_code(), 4);
+        assert_eq!(
+            icmp::icmp_time_exceeded(&pkt6, router).err(),
+            Some(PacketError::MissingHeader(String::from("IPv4")))
+        );
+    }
+    #[test]
+    fn header_inspector_test() {
+        let mut ipv6 = IPv6::new();
+        let mut inspector = HeaderInspector::new(&mut ipv6);
+        assert_eq!(inspector.name(), "IPv6");
+        let rows = inspector.rows();
+        assert_eq!(rows.len(), 8);
+        assert_eq!(rows[0], ("version", 4, String::from("0x6")));
+        assert_eq!(rows[2], ("flow_label", 20, String::from("0x00000")));
+        assert_eq!(rows[4], ("next_hdr", 8, String::from("0x06")));
+        assert_eq!(
+            rows[6],
+            ("src", 128, String::from("0x20010db885a3000000008a2e03707334"))
+        );
+
+        inspector.set_row("flow_label", "abcde").unwrap();
+        inspector.set_row("next_hdr", "0x11").unwrap();
+        inspector.set_row("dst", "0x20010db8000000000000000000000001").unwrap();
+        assert_eq!(inspector.rows()[2].2, "0xabcde");
+        let table = inspector.render();
+        let line = table.lines().find(|l| l.starts_with("flow_label")).unwrap();
+        assert_eq!(
+            line.split_whitespace().collect::<Vec<_>>(),
+            ["flow_label", "12-31", "20", "0xabcde"]
+        );
+        assert_eq!(
+            inspector.set_row("version", "0x10"),
+            Err(PacketError::InvalidValue {
+                field: String::from("version"),
+                value: String::from("0x10")
+            })
+        );
+        assert!(inspector.set_row("version", "xyz").is_err());
+        assert!(inspector.set_row("version", "").is_err());
+        assert_eq!(
+            inspector.set_row("ttl", "1"),
+            Err(PacketError::UnknownField {
+                header: String::from("IPv6"),
+                field: String::from("ttl")
+            })
+        );
+        assert_eq!(ipv6.flow_label(), 0xabcde);
+        assert_eq!(ipv6.next_hdr(), 0x11);
+        assert_eq!(ipv6.dst_ip(), "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap());
+
+        let mut pkt = builders::create_tcp_packet(&Default::default());
+        for hdr in pkt.iter_mut() {
+            let inspector = HeaderInspector::new(hdr);
+            assert_eq!(inspector.rows().len(), inspector.fields().len());
+        }
+        let tcp = pkt.layer::<TCP>().unwrap();
+        assert_eq!(tcp.get_field_bytes("flags"), Some(vec![0x02]));
+        assert_eq!(tcp.get_field_bytes("data_offset"), Some(vec![0x05]));
+        assert_eq!(tcp.get_field_bytes("urg"), None);
+    }
+    #[test]
+    fn ping_sequence_test() {
+        let dst: std::net::Ipv4Addr = "10.0.0.1".parse().unwrap();
+        let interval = std::time::Duration::from_millis(100);
+        let pings: Vec<Packet> = probe::ping_sequence(dst, 4, interval, 64).collect();
+        assert_eq!(pings.len(), 4);
+        let ts = |p: &Packet| {
+            let v = p.to_vec();
+            u64::from_be_bytes(v[42..50].try_into().unwrap())
+        };
+        for (i, ping) in pings.iter().enumerate() {
+            let v = ping.to_vec();
+            assert_eq!(v.len(), 14 + 20 + 4 + 64);
+            assert_eq!(ping.layer::<IPv4>().unwrap().dst_ip(), dst);
+            assert_eq!(ping.layer::<IPv4>().unwrap().total_len(), 88);
+            assert_eq!(ping.layer::<ICMP>().unwrap().icmp_type(), 8);
+            assert_eq!(v[38..40], probe::ECHO_ID.to_be_bytes());
+            assert_eq!(v[40..42], (i as u16).to_be_bytes());
+            assert_eq!(ipv4_checksum_verify(&v[34..]), 0);
+        }
+        assert_eq!(ts(&pings[3]) - ts(&pings[0]), 300_000);
+
+        // canned reply
+        let v = pings[2].to_vec();
+        let mut reply = parser::slow::parse(&v);
+        let ip = reply.layer_mut::<IPv4>().unwrap();
+        let (src, dst) = (ip.src(), ip.dst());
+        ip.set_src(dst);
+        ip.set_dst(src);
+        reply.layer_mut::<ICMP>().unwrap().set_icmp_type(0);
+        assert!(probe::match_echo_reply(&pings[2], &reply));
+        assert!(!probe::match_echo_reply(&pings[1], &reply));
+        assert!(!probe::match_echo_reply(&pings[2], &pings[2]));
+    }
+    #[test]
+    fn traceroute_probes_test() {
+        let dst: std::net::Ipv4Addr = "10.0.0.1".parse().unwrap();
+        let router: std::net::Ipv4Addr = "192.168.0.254".parse().unwrap();
+        for mode in [TraceMode::Icmp, TraceMode::Udp, TraceMode::Tcp] {
+            let probes: Vec<Packet> = probe::traceroute_probes(dst, 5, 3, mode).collect();
+            assert_eq!(probes.len(), 15);
+            assert_eq!(probes[7].layer::<IPv4>().unwrap().ttl(), 3);
+            assert_eq!(probes[7].layer::<IPv4>().unwrap().identification(), 7);
+            match mode {
+                TraceMode::Udp => {
+                    assert_eq!(probes[7].layer::<UDP>().unwrap().dst(), 33441);
+                    assert_eq!(l4_checksum_verify(&probes[7], 34, 17), 0);
+                }
+                TraceMode::Tcp => assert_eq!(probes[7].layer::<TCP>().unwrap().src(), 33441),
+                TraceMode::Icmp => assert_eq!(probes[7].to_vec()[40..42], [0, 7]),
+            }
+
+            // the router receives the probe with ttl 1 and quotes it
+            let mut received = probes[7].clone();
+            received.layer_mut::<IPv4>().unwrap().set_ttl(1);
+            let err = icmp::icmp_time_exceeded(&received, router).unwrap();
+            let err = parser::slow::parse(&err.to_vec());
+            assert_eq!(probe::match_ttl_exceeded(&probes[7], &err), Some(3));
+            assert_eq!(probe::match_ttl_exceeded(&probes[6], &err), None);
+            assert_eq!(probe::match_ttl_exceeded(&probes[8], &err), None);
+            assert_eq!(probe::match_ttl_exceeded(&probes[7], &probes[7]), None);
+        }
+        let probes: Vec<Packet> = probe::traceroute_probes(dst, 2, 1, TraceMode::Udp).collect();
+        let unreach = icmp::icmp_dest_unreachable(&probes[1], 3, dst).unwrap();
+        assert_eq!(probe::match_ttl_exceeded(&probes[1], &unreach), Some(2));
+        // the probe numbers wrap past 65535 probes
+        let probe = probe::traceroute_probes(dst, 3, 40000, TraceMode::Icmp)
+            .nth(80001)
+            .unwrap();
+        let ipv4 = probe.layer::<IPv4>().unwrap();
+        assert_eq!((ipv4.ttl(), ipv4.identification()), (3, 80001 - 65536));
+    }
+    #[test]
+    fn diagnose_test() {
+        let all_ok = |pkt: &Packet| {
+            pkt.diagnose()
+                .iter()
+                .all(|d| d.length_ok != Some(false) && d.checksum_ok != Some(false))
+        };
+        let cfg = PacketConfig {
+            payload_len: 33,
+            ..Default::default()
+        };
+        let tcp = builders::create_tcp_packet(&cfg);
+        let report = tcp.diagnose();
+        assert_eq!(report.len(), 3);
+        assert_eq!(report[0].name, "Ether");
+        assert_eq!((report[0].length_ok, report[0].checksum_ok), (None, None));
+        assert_eq!((report[2].length_ok, report[2].checksum_ok), (Some(true), Some(true)));
+        assert!(all_ok(&builders::create_udp_packet(&cfg)));
+        assert!(all_ok(&builders::create_ipv6_tcp_packet(&cfg)));
+        let vxlan = builders::create_vxlan_packet(&cfg, 10, tcp.clone());
+        assert!(all_ok(&parser::slow::parse(&vxlan.to_vec())));
+        let router6 = "2001:db8::fe".parse().unwrap();
+        let ipv6 = builders::create_ipv6_tcp_packet(&cfg);
+        let err = icmp::icmpv6_time_exceeded(&ipv6, router6).unwrap();
+        assert_eq!(err.diagnose()[2].checksum_ok, Some(true));
+        let err = icmp::icmp_time_exceeded(&tcp, "10.0.0.1".parse().unwrap()).unwrap();
+        assert_eq!(err.diagnose()[2].checksum_ok, Some(true));
+
+        // broken lengths and checksums
+        let mut bad = tcp.clone();
+        bad.layer_mut::<IPv4>().unwrap().set_total_len(100);
+        let report = bad.diagnose();
+        assert_eq!(report[1].length_ok, Some(false));
+        assert_eq!(report[1].checksum_ok, Some(false));
+        let mut bad = builders::create_udp_packet(&cfg);
+        bad.layer_mut::<UDP>().unwrap().set_length(8);
+        bad.set_payload(&[1]);
+        let report = bad.diagnose();
+        assert_eq!(report[1].length_ok, Some(false));
+        assert_eq!(report[2].length_ok, Some(false));
+        assert_eq!(report[2].checksum_ok, Some(false));
+        // no udp checksum over ipv4
+        bad.layer_mut::<UDP>().unwrap().set_checksum(0);
+        assert_eq!(bad.diagnose()[2].checksum_ok, None);
+
+        // ethernet padding is accepted
+        let mut small = builders::create_udp_packet(&Default::default());
+        assert_eq!(small.len(), 42);
+        let pad = [0u8; 18];
+        small.set_payload(&pad);
+        let report = small.diagnose();
+        assert_eq!(report[1].length_ok, Some(true));
+        assert_eq!(report[1].checksum_ok, Some(true));
+
+        // the trailers are not part of the frame
+        let mut stamped = tcp.clone();
+        stamped.push_trailer(Box::new(HwTimestamp::new()));
+        assert!(all_ok(&stamped));
+        assert_eq!(stamped.diagnose(), tcp.diagnose());
+    }
+    #[test]
+    fn chain_mutation_test() {
+        let cfg = PacketConfig::default();
+        let mut pkt = builders::create_udp_packet(&cfg);
+        pkt.insert_after::<Ether>(Packet::vlan(0, 0, 20, 0), true)
+            .unwrap();
+        assert_eq!(pkt.layer::<Ether>().unwrap().etype(), 0x8100);
+        assert_eq!(pkt.layer::<Vlan>().unwrap().etype(), 0x0800);
+        let parsed = parser::slow::parse(&pkt.to_vec());
+        assert_eq!(parsed.layer::<Vlan>().unwrap().vid(), 20);
+        assert!(parsed.layer::<UDP>().is_some());
+
+        // udp port follows the tunnel header
+        pkt.insert_after::<UDP>(Vxlan::new(), true).unwrap();
+        assert_eq!(pkt.layer::<UDP>().unwrap().dst(), 4789);
+
+        // ipv4 replaced by ipv6 keeps the udp protocol
+        let old: IPv4 = pkt.replace_layer::<IPv4>(IPv6::new(), true).unwrap();
+        assert_eq!(old.protocol(), 17);
+        assert_eq!(pkt.layer::<Vlan>().unwrap().etype(), 0x86dd);
+        assert_eq!(pkt.layer::<IPv6>().unwrap().next_hdr(), 17);
+
+        // without a following header the displaced value is inherited
+        let mut pkt = Packet::new();
+        pkt.push(Packet::ethernet(
+            "00:01:02:03:04:05",
+            "00:06:07:08:09:0a",
+            0x88cc,
+        ));
+        pkt.insert_after::<Ether>(Vlan::new(), true).unwrap();
+        assert_eq!(pkt.layer::<Vlan>().unwrap().etype(), 0x88cc);
+        let vlan: Vlan = pkt.remove_layer::<Vlan>(true).unwrap();
+        assert_eq!(vlan.etype(), 0x88cc);
+        assert_eq!(pkt.layer::<Ether>().unwrap().etype(), 0x88cc);
+
+        // non strict mutation leaves the fields untouched
+        pkt.insert_after::<Ether>(Packet::vlan(0, 0, 1, 0x0800), false)
+            .unwrap();
+        assert_eq!(pkt.layer::<Ether>().unwrap().etype(), 0x88cc);
+        assert_eq!(pkt.layer::<Vlan>().unwrap().etype(), 0x0800);
+        pkt.remove_layer::<Vlan>(false).unwrap();
+        assert_eq!(pkt.layer::<Ether>().unwrap().etype(), 0x88cc);
+
+        assert_eq!(
+            pkt.remove_layer::<MPLS>(true).err(),
+            Some(PacketError::MissingHeader(String::from("MPLS")))
+        );
+
+        // insert before the first header of a type
+        let mut pkt = builders::create_udp_packet(&cfg);
+        pkt.insert_before::<IPv4>(Packet::vlan(0, 0, 30, 0), true)
+            .unwrap();
+        assert_eq!(pkt.layer::<Ether>().unwrap().etype(), 0x8100);
+        assert_eq!(pkt.layer::<Vlan>().unwrap().etype(), 0x0800);
+        let parsed = parser::slow::parse(&pkt.to_vec());
+        assert_eq!(parsed.layer::<Vlan>().unwrap().vid(), 30);
+        pkt.insert_before::<Ether>(MPLS::new(), false).unwrap();
+        assert_eq!(pkt[0].name(), "MPLS");
+        assert!(pkt.insert_before::<TCP>(Vlan::new(), true).is_err());
+    }
+    #[test]
+    fn validate_reserved_test() {
+        let cfg = PacketConfig::default();
+        let tcp = builders::create_tcp_packet(&cfg);
+        assert!(tcp.validate().is_ok());
+        let mut vxlan = builders::create_vxlan_packet(&cfg, 100, tcp);
+        assert!(vxlan.layer::<Vxlan>().unwrap().validate_reserved());
+        assert!(vxlan.validate().is_ok());
+        vxlan.layer_mut::<Vxlan>().unwrap().set_reserved2(0x80);
+        assert!(!vxlan.layer::<Vxlan>().unwrap().validate_reserved());
+        assert_eq!(
+            vxlan.validate(),
+            Err(PacketError::ReservedNotZero {
+                header: String::from("Vxlan"),
+                field: String::from("reserved2"),
+            })
+        );
+        // non reserved fields are not checked
+        assert!(Vxlan::FIELDS.iter().any(|f| f.name == "vni" && !f.reserved));
+        let slice = IgmpV3ReportSlice::from(&[0x22, 0, 0, 0, 0, 1, 0, 1]);
+        assert!(!slice.validate_reserved());
+        let slice = IgmpV3ReportSlice::from(&[0x22, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(slice.validate_reserved());
+    }
+    #[test]
+    fn vxlan_encap_test() {
+        let inner = builders::create_tcp_packet(&PacketConfig {
+            payload_len: 20,
+            ..Default::default()
+        });
+        let outer = VxlanOuterParams {
+            sport: |_| 0xc000,
+            ..Default::default()
+        };
+        let pkt = inner.vxlan_encap(&outer).unwrap();
+        // outer headers as sent by a vtep for this inner frame
+        #[rustfmt::skip]
+        let expected: [u8; 50] = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x08, 0x00,
+            0x45, 0x00, 0x00, 0x6e, 0x00, 0x00, 0x00, 0x00, 0x40, 0x11, 0xf9, 0x2b,
+            0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0x02,
+            0xc0, 0x00, 0x12, 0xb5, 0x00, 0x5a, 0x00, 0x00,
+            0x08, 0x00, 0x00, 0x00, 0x00, 0x07, 0xd0, 0x00,
+        ];
+        let v = pkt.to_vec();
+        assert_eq!(v[..50], expected);
+        assert_eq!(v[50..], inner.to_vec());
+        assert!(pkt.vxlan_decap().unwrap().compare(&inner));
+        assert!(pkt.diagnose().iter().all(|d| d.checksum_ok != Some(false)));
+
+        // ipv6 outer with udp checksum, default source port from the inner flow
+        let outer = VxlanOuterParams {
+            ip_src: "2001:db8::1".parse().unwrap(),
+            ip_dst: "2001:db8::2".parse().unwrap(),
+            udp_checksum: true,
+            vni: 10,
+            ..Default::default()
+        };
+        let pkt = inner.vxlan_encap(&outer).unwrap();
+        let report = pkt.diagnose();
+        assert_eq!(report[1].length_ok, Some(true));
+        assert_eq!(report[2].checksum_ok, Some(true));
+        let sport = pkt.layer::<UDP>().unwrap().src() as u16;
+        assert!(sport >= tunnel::VXLAN_SPORT_BASE);
+        assert_eq!(sport, tunnel::vxlan_sport(&inner));
+        let parsed = parser::slow::parse(&pkt.to_vec());
+        assert_eq!(parsed.layer::<Vxlan>().unwrap().vni(), 10);
+        assert!(parsed.vxlan_decap().unwrap().compare(&inner));
+
+        // both directions hash to the same port
+        let reply = builders::create_tcp_packet(&PacketConfig {
+            ip_src: String::from("192.168.0.2"),
+            ip_dst: String::from("192.168.0.1"),
+            sport: 80,
+            dport: 1234,
+            ..Default::default()
+        });
+        assert_eq!(tunnel::vxlan_sport(&reply), tunnel::vxlan_sport(&inner));
+
+        // invalid outer stacks
+        let mut bad = inner.vxlan_encap(&Default::default()).unwrap();
+        bad.layer_mut::<UDP>().unwrap().set_dst(4790);
+        assert!(matches!(
+            bad.vxlan_decap(),
+            Err(PacketError::InvalidValue { .. })
+        ));
+        let mut bad = inner.vxlan_encap(&Default::default()).unwrap();
+        bad.layer_mut::<Vxlan>().unwrap().set_flags(0);
+        assert!(bad.vxlan_decap().is_err());
+        assert_eq!(
+            inner.vxlan_decap().err(),
+            Some(PacketError::MissingHeader(String::from("Vxlan")))
+        );
+
+        // an IPv4 outer header takes IPv4-mapped addresses only
+        let outer = VxlanOuterParams {
+            ip_dst: "::ffff:192.168.0.2".parse().unwrap(),
+            ..Default::default()
+        };
+        let pkt = inner.vxlan_encap(&outer).unwrap();
+        assert_eq!(pkt.layer::<IPv4>().unwrap().dst(), 0xc0a80002);
+        let outer = VxlanOuterParams {
+            ip_dst: "2001:db8::2".parse().unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            inner.vxlan_encap(&outer).err(),
+            Some(PacketError::InvalidValue {
+                field: String::from("ip_dst"),
+                value: String::from("2001:db8::2"),
+            })
+        );
+    }
+    #[test]
+    fn tlv_test() {
+        // ipv4 router alert option padded with end of list
+        let opts = vec![Tlv::new(148, vec![0, 0]).unwrap()];
+        let v = tlv::encode_tlvs_with(&opts, &TlvFormat::IP_OPTIONS).unwrap();
+        assert_eq!(v, [148, 4, 0, 0]);
+        let opts = vec![
+            Tlv::new(tlv::TLV_NOP, vec![]).unwrap(),
+            Tlv::new(4, vec![]).unwrap(),
+        ];
+        let v = tlv::encode_tlvs_with(&opts, &TlvFormat::IP_OPTIONS).unwrap();
+        assert_eq!(v, [1, 4, 2, 0]);
+        assert_eq!(
+            tlv::parse_tlvs_with(&v, &TlvFormat::IP_OPTIONS).unwrap(),
+            opts
+        );
+
+        // dhcp style, length of the value only
+        let opts = vec![
+            Tlv::new(53, vec![1]).unwrap(),
+            Tlv::new(61, vec![1, 2, 3, 4, 5, 6, 7]).unwrap(),
+        ];
+        let v = tlv::encode_tlvs(&opts).unwrap();
+        assert_eq!(v.len(), 3 + 9);
+        assert_eq!(v[..3], [53, 1, 1]);
+        assert_eq!(tlv::parse_tlvs(&v).unwrap(), opts);
+
+        // two byte type and length aligned to 4 bytes
+        let fmt = TlvFormat {
+            width: 2,
+            align: 4,
+            ..Default::default()
+        };
+        let opts = vec![
+            Tlv::new(0x0102, vec![0xaa]).unwrap(),
+            Tlv::new(3, vec![]).unwrap(),
+        ];
+        let v = tlv::encode_tlvs_with(&opts, &fmt).unwrap();
+        assert_eq!(v, [1, 2, 0, 1, 0xaa, 0, 3, 0, 0, 0, 0, 0]);
+        assert_eq!(tlv::parse_tlvs_with(&v, &fmt).unwrap(), opts);
+
+        // append without padding, deliberately wrong length
+        let mut buf = vec![0xff];
+        let bad = Tlv {
+            typ: 7,
+            len: 9,
+            value: vec![1],
+        };
+        tlv::append_tlv(&mut buf, &bad, &TlvFormat::default()).unwrap();
+        assert_eq!(buf, [0xff, 7, 9, 1]);
+        assert!(matches!(
+            tlv::parse_tlvs(&buf[1..]),
+            Err(PacketError::Parse { .. })
+        ));
+        // length shorter than the header
+        assert!(tlv::parse_tlvs_with(&[7, 1, 0, 0], &TlvFormat::IP_OPTIONS).is_err());
+        assert!(tlv::parse_tlvs(&[7]).is_err());
+
+        // lengths that do not fit are rejected rather than truncated
+        assert!(matches!(
+            Tlv::new(7, vec![0; 65536]),
+            Err(PacketError::ValueOverflow { bits: 16, .. })
+        ));
+        let long = Tlv::new(7, vec![0; 254]).unwrap();
+        assert!(tlv::encode_tlvs(std::slice::from_ref(&long)).is_ok());
+        assert_eq!(
+            tlv::encode_tlvs_with(&[long], &TlvFormat::IP_OPTIONS),
+            Err(PacketError::ValueOverflow {
+                field: String::from("len"),
+                value: 256,
+                bits: 8,
+            })
+        );
+    }
+    #[test]
+    fn summary_test() {
+        let cfg = PacketConfig {
+            vlan: Some(10),
+            payload_len: 18,
+            ..Default::default()
+        };
+        assert_eq!(
+            builders::create_udp_packet(&cfg).summary(),
+            "Ether 00:06:07:08:09:0a > 00:01:02:03:04:05 / Vlan vid=10 pcp=0 / \
+             IPv4 192.168.0.1 > 192.168.0.2 ttl=64 / UDP 1234 > 80 len=18"
+        );
+        let cfg = PacketConfig {
+            tcp_flags: 0x12,
+            ..Default::default()
+        };
+        assert_eq!(
+            builders::create_ipv6_tcp_packet(&cfg).summary(),
+            "Ether 00:06:07:08:09:0a > 00:01:02:03:04:05 / \
+             IPv6 2001:db8::1 > 2001:db8::2 hlim=64 / TCP 1234 > 80 [SYN,ACK] len=0"
+        );
+        let tcp = builders::create_tcp_packet(&Default::default());
+        let vxlan = builders::create_vxlan_packet(&Default::default(), 100, tcp.clone());
+        assert_eq!(
+            parser::slow::parse(&vxlan.to_vec()).summary(),
+            "Ether 00:06:07:08:09:0a > 00:01:02:03:04:05 / \
+             IPv4 192.168.0.1 > 192.168.0.2 ttl=64 / UDP 1234 > 4789 / Vxlan vni=100 / \
+             Ether 00:06:07:08:09:0a > 00:01:02:03:04:05 / \
+             IPv4 192.168.0.1 > 192.168.0.2 ttl=64 / TCP 1234 > 80 [SYN] len=0"
+        );
+
+        // other headers show their first fields, reserved fields are skipped
+        let mut pkt = Packet::new();
+        pkt.push(IgmpV3Report::new());
+        assert_eq!(pkt.summary(), "IgmpV3Report igmp_type=34 checksum=56831 num_records=0 len=0");
+        let mut pkt = Packet::new();
+        pkt.push(Mld::new());
+        assert_eq!(pkt[0].summary(), "Mld mld_type=130 code=0 checksum=0");
+
+        assert_eq!(
+            summary::summaries(&[tcp, pkt]),
+            "   0 Ether 00:06:07:08:09:0a > 00:01:02:03:04:05 / \
+             IPv4 192.168.0.1 > 192.168.0.2 ttl=64 / TCP 1234 > 80 [SYN] len=0\n   \
+             1 Mld mld_type=130 code=0 checksum=0 len=0\n"
+        );
+    }
+    #[test]
+    fn to_scapy_test() {
+        let cfg = PacketConfig {
+            vlan: Some(10),
+            payload_len: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            builders::create_tcp_packet(&cfg).to_scapy(),
+            "Ether(dst='00:01:02:03:04:05', src='00:06:07:08:09:0a', type=33024)/\
+             Dot1Q(prio=0, id=0, vlan=10, type=2048)/\
+             IP(version=4, ihl=5, tos=0, len=44, id=1, flags=0, frag=0, ttl=64, proto=6, \
+             chksum=63863, src='192.168.0.1', dst='192.168.0.2')/\
+             TCP(sport=1234, dport=80, seq=1, ack=0, dataofs=5, reserved=0, flags=2, \
+             window=8192, chksum=1892, urgptr=0)/\
+             Raw(load=b'\\x00\\x01\\x02\\x03')"
+        );
+        assert_eq!(
+            builders::create_ipv6_tcp_packet(&Default::default()).to_scapy(),
+            "Ether(dst='00:01:02:03:04:05', src='00:06:07:08:09:0a', type=34525)/\
+             IPv6(version=6, tc=0, fl=0, plen=20, nh=6, hlim=64, src='2001:db8::1', \
+             dst='2001:db8::2')/\
+             TCP(sport=1234, dport=80, seq=1, ack=0, dataofs=5, reserved=0, flags=2, \
+             window=8192, chksum=12107, urgptr=0)"
+        );
+
+        // icmpv6, gre options folded into gre and raw headers
+        let mut pkt = Packet::new();
+        pkt.push(Packet::icmp(128, 0));
+        assert_eq!(pkt.to_scapy(), "ICMP(type=128, code=0, chksum=0)");
+        pkt.insert(IPv6::new());
+        pkt.push(Packet::gre(false, false, true, false, false, 0, 0, 0x6558));
+        pkt.push(Packet::gre_key(5));
+        pkt.push(Igmp::new());
+        let scapy = pkt.to_scapy();
+        assert!(scapy.contains("/ICMPv6Unknown(type=128, code=0, cksum=0)/"));
+        assert!(scapy.contains("version=0, proto=25944, key=5)/"));
+        assert!(scapy.ends_with("/Raw(load=b'\\x11\\x64\\xee\\x9b\\x00\\x00\\x00\\x00')"));
+    }
+    #[test]
+    fn parse_log_test() {
+        let pkt = builders::create_tcp_packet(&Default::default());
+        let v = pkt.to_vec();
+        assert!(capture(|| {
+            parser::slow::parse(&v);
+        })
+        .iter()
+        .all(|r| r.level > Level::Warn));
+
+        // truncated tcp header
+        let short = &v[..Ether::size() + IPv4::size() + 10];
+        let mut parsed = None;
+        let records = capture(|| parsed = Some(parser::slow::parse(short)));
+        let warnings: Vec<_> = records.iter().filter(|r| r.level == Level::Warn).collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "truncated layer");
+        assert_eq!(warnings[0].get("layer"), Some("TCP"));
+        assert_eq!(warnings[0].get("expected"), Some("20"));
+        assert_eq!(warnings[0].get("actual"), Some("10"));
+        let parsed = parsed.unwrap();
+        assert!(parsed.layer::<IPv4>().is_some());
+        assert!(parsed.layer::<TCP>().is_none());
+        assert_eq!(parsed.to_vec(), short);
+
+        let records = capture(|| {
+            let slice = parser::fast::parse(short);
+            assert_eq!(slice.to_vec(), short);
+        });
+        let warnings: Vec<_> = records.iter().filter(|r| r.level == Level::Warn).collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].get("layer"), Some("TCP"));
+
+        // truncated gre options and runt frames
+        let mut gre = Packet::new();
+        gre.push(Packet::ethernet("00:01:02:03:04:05", "00:06:07:08:09:0a", 0x0800));
+        gre.push(IPv4::new());
+        gre.push(Packet::gre(true, false, true, false, false, 0, 0, 0x0800));
+        let mut v = gre.to_vec();
+        v[23] = 47;
+        let records = capture(|| {
+            parser::slow::parse(&v);
+        });
+        let warning = records.iter().find(|r| r.level == Level::Warn).unwrap();
+        assert_eq!(warning.get("layer"), Some("GRE"));
+        let records = capture(|| {
+            assert_eq!(parser::slow::parse(&v[..6]).to_vec(), &v[..6]);
+        });
+        assert_eq!(records[0].get("layer"), Some("Ether"));
+
+        // unknown ethertypes are debug events
+        v[12] = 0x88;
+        v[13] = 0xb5;
+        let records = capture(|| {
+            parser::slow::parse(&v);
+        });
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].level, Level::Debug);
+        assert_eq!(records[0].get("etype"), Some("34997"));
+    }
+    #[test]
+    fn bit_range_bounds_test() {
+        // a truncated header, the ttl and dst fields are past the end
+        let mut ipv4 = IPv4::from(vec![0x45, 0x00, 0x00, 0x14]);
+        assert_eq!(ipv4.try_bit_range(15, 8), Ok(0));
+        assert_eq!(
+            ipv4.try_bit_range(IPv4::ttl_msb(), IPv4::ttl_lsb()),
+            Err(PacketError::BufferTooSmall {
+                needed: 9,
+                available: 4
+            })
+        );
+        assert!(ipv4.try_set_bit_range(159, 128, 1).is_err());
+        assert!(ipv4.try_bit_range(3, 8).is_err());
+        assert_eq!(ipv4.to_vec(), [0x45, 0x00, 0x00, 0x14]);
+
+        let result = std::panic::catch_unwind(|| IPv4::from(vec![0x45]).ttl());
+        let msg = *result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            msg,
+            "IPv4 bits 71..=64: buffer of 1 bytes cannot hold 9 bytes"
+        );
+    }
+    #[test]
+    fn error_test() {
+        let mut pkt = builders::create_tcp_packet(&Default::default());
+        assert_eq!(pkt.verify_checksums(), Ok(()));
+        let tcp = pkt.layer_mut::<TCP>().unwrap();
+        assert_eq!(
+            tcp.try_set_field("res", 16),
+            Err(PacketError::ValueOverflow {
+                field: String::from("res"),
+                value: 16,
+                bits: 4
+            })
+        );
+        assert!(matches!(
+            tcp.try_set_field("foo", 0),
+            Err(PacketError::UnknownField { .. })
+        ));
+        tcp.try_set_field("window", 512).unwrap();
+        assert_eq!(tcp.window(), 512);
+        let err = pkt.verify_checksums().unwrap_err();
+        assert_eq!(err.to_string(), "invalid TCP checksum at offset 34");
+
+        let boxed: Box<dyn Header> = Box::new(Ether::new());
+        assert!(Ether::downcast(boxed.as_ref()).is_ok());
+        assert_eq!(
+            IPv4::downcast(boxed.as_ref()).map(|x| x.ttl()),
+            Err(PacketError::HeaderMismatch {
+                expected: String::from("IPv4"),
+                found: String::from("Ether")
+            })
+        );
+
+        let eth = Ether::new();
+        assert_eq!(eth.try_bytes(47, 0).unwrap().len(), 6);
+        assert_eq!(
+            eth.try_bytes(46, 0),
+            Err(PacketError::MisalignedRange { msb: 46, lsb: 0 })
+        );
+        assert!(eth.try_bytes(119, 112).is_err());
+
+        let err = PacketError::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(matches!(
+            err,
+            PacketError::Io {
+                kind: std::io::ErrorKind::NotFound,
+                ..
+            }
+        ));
+        let err: Box<dyn std::error::Error> = Box::new(tlv::parse_tlvs(&[7, 4, 0]).unwrap_err());
+        assert_eq!(
+            err.to_string(),
+            "cannot parse TLV at offset 0: length 4 past the end"
+        );
+    }
+    #[test]
+    fn dump_test() {
+        let eth = Ether::new();
+        let dump = eth.dump();
+        assert!(dump.starts_with("#### Ether            Size   Data\n"));
+        assert!(dump.ends_with(&eth.field_table()));
+
+        let pkt = builders::create_udp_packet(&Default::default());
+        let dump = pkt.dump();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert!(lines[0].contains("Ether") && lines[0].contains("offset    0 length   14"));
+        assert!(dump.contains(&format!("\n#### raw {} bytes ####\n", pkt.len())));
+        // hex bytes, 16 per line
+        let hex = lines.iter().rev().take_while(|x| !x.starts_with("####"));
+        assert_eq!(hex.count(), pkt.len().div_ceil(16));
+        assert_eq!(parser::fast::parse(&pkt.to_vec()).dump(), dump);
+    }
+    #[test]
+    #[cfg(not(feature = "color"))]
+    fn show_layout_test() {
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(Vlan::new());
+        pkt.set_payload(&[1, 2, 3, 4]);
+        let expected = [
+            "#### Ether            offset    0 length   14",
+            "dst                 :   48 : 00 01 02 03 04 05 ",
+            "src                 :   48 : 06 07 08 09 0a 0b ",
+            "etype               :   16 : 08 00  (IPV4)",
+            "  #### Vlan             offset   14 length    4",
+            "  pcp                 :    3 : 00",
+            "  cfi                 :    1 : 00",
+            "  vid                 :   12 : 00 0a",
+            "  etype               :   16 : 08 00 ",
+            "    #### Payload          offset   18 length    4",
+            "",
+            "#### raw 22 bytes ####",
+            "00 01 02 03 04 05 06 07 08 09 0a 0b 08 00 00 0a ",
+            "08 00 01 02 03 04 ",
+        ];
+        assert_eq!(pkt.dump(), expected.join("\n") + "\n");
+    }
+    #[test]
+    fn from_bytes_test() {
+        let udp = Packet::udp(1234, 80, 8);
+        let copy = UDP::from_bytes(&udp.to_vec()).unwrap();
+        assert_eq!(copy.src(), 1234);
+        assert_eq!(copy.to_vec(), udp.to_vec());
+        assert_eq!(
+            UDP::from_bytes(&[0; 4]).map(|x| x.len()),
+            Err(PacketError::BufferTooSmall {
+                needed: 8,
+                available: 4
+            })
+        );
+        assert!(UDP::from_bytes(&[0; 9]).is_err());
+    }
+    #[test]
+    fn with_zeroed_checksum_test() {
+        let cfg = PacketConfig {
+            payload_len: 33,
+            ..Default::default()
+        };
+        let mut pkt = builders::create_tcp_packet(&cfg);
+        pkt.with_zeroed_checksum(|pkt| {
+            pkt.layer_mut::<IPv4>().unwrap().set_src(0x0a000001);
+            pkt.layer_mut::<TCP>().unwrap().set_src(40000);
+        });
+        let expected = builders::create_tcp_packet(&PacketConfig {
+            ip_src: String::from("10.0.0.1"),
+            sport: 40000,
+            ..cfg.clone()
+        });
+        assert!(pkt.compare(&expected));
+
+        let mut pkt = builders::create_ipv6_tcp_packet(&cfg);
+        pkt.with_zeroed_checksum(|pkt| {
+            pkt["IPv6"].set_field("src", 1);
+        });
+        assert!(pkt.verify_checksums().is_ok());
+
+        // the udp checksum covers the vxlan header and the inner packet
+        let inner = builders::create_udp_packet(&cfg);
+        let mut pkt = builders::create_vxlan_packet(&cfg, 10, inner);
+        pkt.with_zeroed_checksum(|pkt| {
+            pkt["Vxlan"].set_field("vni", 20);
+        });
+        assert!(pkt.verify_checksums().is_ok());
+        assert_ne!(pkt["UDP"].get_field("checksum"), Some(0));
+        assert_eq!(TCP::new().checksum_field().unwrap().name, "checksum");
+        assert!(Ether::new().checksum_field().is_none());
+    }
+    #[test]
+    fn clone_cow_test() {
+        let template = builders::create_tcp_packet(&Default::default());
+        let mut clones: Vec<Packet> = (0..3).map(|_| template.clone_cow()).collect();
+        clones[0].layer_mut::<IPv4>().unwrap().set_src(0x0a000001);
+        clones[1]["TCP"].set_field("src", 40000);
+        clones[2].layer_mut::<IPv4>().unwrap().replace(&IPv4::new());
+        assert!(template.compare(&builders::create_tcp_packet(&Default::default())));
+        assert_eq!(clones[0].layer::<IPv4>().unwrap().src(), 0x0a000001);
+        assert_eq!(clones[0].layer::<TCP>().unwrap().src(), 1234);
+        assert_eq!(clones[1].layer::<IPv4>().unwrap().src(), 0xc0a80001);
+        assert_eq!(clones[1].layer::<TCP>().unwrap().src(), 40000);
+        assert!(clones[2].layer::<IPv4>().unwrap().to_vec() == IPv4::new().to_vec());
+
+        // writes to the original are not seen by the clones either
+        let mut template = template;
+        let clone = template.clone_cow();
+        template.layer_mut::<IPv4>().unwrap().set_ttl(1);
+        assert_eq!(clone.layer::<IPv4>().unwrap().ttl(), 64);
+        let mut again = clone.clone_cow();
+        again.layer_mut::<IPv4>().unwrap().set_ttl(2);
+        assert_eq!(clone.layer::<IPv4>().unwrap().ttl(), 64);
+        assert_eq!(template.layer::<IPv4>().unwrap().ttl(), 1);
+
+        // a header created from the box shares its bytes until the box is cloned copy-on-write
+        let pkt = builders::create_tcp_packet(&Default::default());
+        IPv4::from(&pkt["IPv4"]).set_ttl(3);
+        assert_eq!(pkt.layer::<IPv4>().unwrap().ttl(), 3);
+        let _clone = pkt.clone_cow();
+        IPv4::from(&pkt["IPv4"]).set_ttl(4);
+        assert_eq!(pkt.layer::<IPv4>().unwrap().ttl(), 3);
+    }
+    #[test]
+    fn fragment_ipv4_test() {
+        let pkt = builders::create_tcp_packet(&PacketConfig {
+            payload_len: 3000,
+            ..Default::default()
+        });
+        let frags = pkt.fragment_ipv4(1500);
+        assert_eq!(frags.len(), 3);
+        for (i, frag) in frags.iter().enumerate() {
+            let ipv4 = frag.layer::<IPv4>().unwrap();
+            assert_eq!(ipv4.frag_offset(), i as u64 * 185);
+            assert_eq!(ipv4.flags() & frag::IPV4_FLAG_MF != 0, i < 2);
+            assert!(ipv4.total_len() <= 1500);
+            assert_eq!(frag.len(), 14 + ipv4.total_len() as usize);
+            assert!(frag.verify_checksums().is_ok());
+        }
+        let mut shuffled = vec![frags[2].clone(), frags[0].clone(), frags[1].clone()];
+        assert!(frag::reassemble_ipv4(&shuffled).unwrap().compare(&pkt));
+
+        // missing, overlapping and foreign fragments
+        assert!(frag::reassemble_ipv4(&frags[..2]).is_none());
+        shuffled.push(frags[1].clone());
+        assert!(frag::reassemble_ipv4(&shuffled).is_none());
+        let mut other = frags.clone();
+        other[1].layer_mut::<IPv4>().unwrap().set_identification(7);
+        assert!(frag::reassemble_ipv4(&other).is_none());
+
+        // fragments of fragments
+        let refrags: Vec<Packet> = frags.iter().flat_map(|x| x.fragment_ipv4(576)).collect();
+        assert_eq!(refrags.len(), 7);
+        assert!(frag::reassemble_ipv4(&refrags).unwrap().compare(&pkt));
+
+        assert_eq!(pkt.fragment_ipv4(9000).len(), 1);
+        assert_eq!(pkt.fragment_ipv4(27).len(), 0);
+        let mut df = pkt.clone();
+        df.layer_mut::<IPv4>().unwrap().set_flags(frag::IPV4_FLAG_DF);
+        assert!(df.fragment_ipv4(1500).is_empty());
+
+        // only the options with the copied flag follow the first fragment
+        let udp = builders::create_udp_packet(&PacketConfig {
+            payload_len: 200,
+            ..Default::default()
+        });
+        let mut v = udp.to_vec();
+        // record route, not copied, then router alert, copied
+        let options = [7, 7, 4, 0, 0, 0, 0, 148, 4, 0, 0, 0];
+        v.splice(34..34, options);
+        v[14] = 0x48;
+        let total_len = u16::from_be_bytes([v[16], v[17]]) + 12;
+        v[16..18].copy_from_slice(&total_len.to_be_bytes());
+        v[24..26].fill(0);
+        let chksum = Packet::ipv4_checksum(&v[14..46]);
+        v[24..26].copy_from_slice(&chksum.to_be_bytes());
+        // the parser reads the options as the next header, keep the bytes as they are
+        let pkt = parser::slow::parse(&v);
+        let frags = pkt.fragment_ipv4(100);
+        assert_eq!(frags.len(), 3);
+        let v = frags[0].to_vec();
+        assert_eq!(v[14] & 0xf, 8);
+        assert_eq!(v[34..46], options);
+        for frag in &frags[1..] {
+            let v = frag.to_vec();
+            assert_eq!(v[14] & 0xf, 6);
+            assert_eq!(v[34..38], [148, 4, 0, 0]);
+            assert!(frag.verify_checksums().is_ok());
+        }
+        assert_eq!(
+            frag::reassemble_ipv4(&frags).unwrap().to_vec(),
+            pkt.to_vec()
+        );
+    }
+    #[test]
+    fn field_name_test() {
+        let mut ipv4 = IPv4::new();
+        assert_eq!(ipv4.protocol_name(), Some("TCP"));
+        ipv4.set_protocol(17);
+        assert_eq!(ipv4.protocol_name(), Some("UDP"));
+        ipv4.set_protocol(200);
+        assert_eq!(ipv4.protocol_name(), None);
+        assert_eq!(Ether::new().etype_name(), Some("IPV4"));
+        assert_eq!(ICMP::new().icmp_type_name(), Some("ECHO_REQUEST"));
+
+        let pkt = builders::create_udp_packet(&Default::default());
+        let table = pkt["IPv4"].field_table();
+        assert!(table.lines().any(|l| l.starts_with("protocol") && l.ends_with("11 (UDP)")));
+        assert!(table.lines().any(|l| l.starts_with("ttl") && l.ends_with("40")));
+        let v = pkt.to_vec();
+        let slice = IPv4Slice::from(&v[14..]);
+        assert_eq!(slice.protocol_name(), Some("UDP"));
+        assert_eq!(slice.field_table(), table);
+    }
+    #[test]
+    fn field_byte_range_test() {
+        assert_eq!(Ether::src_byte_range(), Some(6..12));
+        assert_eq!(IPv4::ttl_byte_range(), Some(8..9));
+        assert_eq!(IPv4::flags_byte_range(), None);
+        assert_eq!(IPv6::dst_byte_range(), Some(24..40));
+        let pkt = builders::create_tcp_packet(&Default::default());
+        let range = pkt["TCP"].field_byte_range("dst").unwrap();
+        assert_eq!(pkt["TCP"].to_vec()[range], 80u16.to_be_bytes());
+        assert_eq!(pkt["IPv4"].field_byte_range("ihl"), None);
+        assert_eq!(pkt["IPv4"].field_byte_range("nope"), None);
+    }
+    #[test]
+    fn udplite_checksum_test() {
+        let mut pkt = builders::create_udp_packet(&PacketConfig {
+            payload_len: 40,
+            ..Default::default()
+        });
+        let mut udplite = UdpLite::new();
+        udplite.set_coverage(12);
+        pkt.replace_layer::<UDP>(udplite, true).unwrap();
+        assert_eq!(pkt.layer::<IPv4>().unwrap().protocol(), 136);
+        pkt.update_checksums();
+        assert!(pkt.verify_checksums().is_ok());
+        let ipv4 = pkt.layer::<IPv4>().unwrap();
+        let (src, dst) = (ipv4.src().to_be_bytes(), ipv4.dst().to_be_bytes());
+        let udplite = pkt.layer::<UdpLite>().unwrap();
+        let v = pkt.to_vec();
+        let chksum = udplite.compute_checksum(&src[4..], &dst[4..], &v[42..]);
+        assert_eq!(udplite.checksum(), chksum as u64);
+
+        let parsed = parser::slow::parse(&v);
+        assert_eq!(parsed.layer::<UdpLite>().unwrap().coverage(), 12);
+        assert_eq!(parser::fast::parse(&v).to_vec(), v);
+
+        // bytes past the coverage are not checked
+        let corrupt = |pkt: &Packet, i: usize| {
+            let mut v = pkt.to_vec();
+            v[i] ^= 0xff;
+            parser::slow::parse(&v)
+        };
+        assert!(corrupt(&pkt, 52).verify_checksums().is_ok());
+        assert!(corrupt(&pkt, 42).verify_checksums().is_err());
+
+        // a coverage of zero covers the whole segment
+        pkt.layer_mut::<UdpLite>().unwrap().set_coverage(0);
+        pkt.update_checksums();
+        assert!(pkt.verify_checksums().is_ok());
+        assert!(corrupt(&pkt, 72).verify_checksums().is_err());
+        pkt.layer_mut::<UdpLite>().unwrap().set_coverage(4);
+        assert_eq!(pkt.diagnose()[2].length_ok, Some(false));
+    }
+    #[test]
+    fn mock_interface_verify_test() {
+        let timeout = std::time::Duration::from_millis(50);
+        let pkt = builders::create_udp_packet(&Default::default());
+        let other = builders::create_tcp_packet(&Default::default());
+
+        // preloaded frames, others are skipped
+        let intf = MockInterface::new();
+        intf.push_packet(&other);
+        intf.push_packet(&pkt);
+        assert!(interface::verify_packet(&intf, &pkt, timeout).is_ok());
+        assert!(matches!(
+            interface::verify_packet(&intf, &pkt, timeout),
+            Err(PacketError::VerifyFailed(_))
+        ));
+        intf.push_packet(&other);
+        assert!(interface::verify_no_packet(&intf, &pkt, timeout).is_ok());
+        intf.push_packet(&pkt);
+        assert!(interface::verify_no_packet(&intf, &pkt, timeout).is_err());
+        intf.push_packet(&other);
+        assert!(interface::verify_no_other_packets(&intf, timeout).is_err());
+        assert!(interface::verify_no_other_packets(&intf, timeout).is_ok());
+
+        // sent frames are recorded and looped through the transform
+        intf.send_packet(&pkt).unwrap();
+        assert_eq!(intf.take_sent(), vec![pkt.to_vec()]);
+        assert_eq!(intf.pending(), 0);
+        intf.set_transform(|frame| {
+            let mut pkt = parser::slow::parse(frame);
+            pkt.layer_mut::<UDP>()?.set_dst(53);
+            pkt.update_checksums();
+            Some(pkt.to_vec())
+        });
+        intf.send_batch(&[&other.to_vec(), &pkt.to_vec()]).unwrap();
+        assert_eq!(intf.sent().len(), 2);
+        let mut expected = pkt.clone();
+        expected.layer_mut::<UDP>().unwrap().set_dst(53);
+        expected.update_checksums();
+        assert!(interface::verify_packet(&intf, &expected, timeout).is_ok());
+        assert!(interface::verify_no_other_packets(&intf, timeout).is_ok());
+    }
+    #[test]
+    fn mock_interface_impairment_test() {
+        let run = |seed: u64| {
+            let intf = MockInterface::new();
+            intf.set_transform(|frame| Some(frame.to_vec()));
+            intf.set_impairment(
+                Impairment {
+                    latency: std::time::Duration::from_millis(20),
+                    drop: 0.2,
+                    reorder: 0.2,
+                },
+                seed,
+            );
+            let frames: Vec<Vec<u8>> = (0..50u8).map(|x| vec![x; 60]).collect();
+            let refs: Vec<&[u8]> = frames.iter().map(|x| x.as_slice()).collect();
+            let start = Instant::now();
+            intf.send_batch(&refs).unwrap();
+            let queued = intf.pending();
+            // the frames of the batch are all ready at the same instant, received in one call
+            let mut bufs = vec![Vec::new(); 64];
+            let n = intf.recv_batch(&mut bufs, None).unwrap();
+            assert!(start.elapsed() >= std::time::Duration::from_millis(20));
+            assert_eq!(n, queued);
+            assert_eq!(intf.pending(), 0);
+            bufs[..n].iter().map(|x| x[0]).collect::<Vec<u8>>()
+        };
+        let order = run(7);
+        assert_eq!(order, run(7));
+        assert_ne!(order, run(8));
+        assert!(order.len() < 50 && order.len() > 25);
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_ne!(order, sorted);
+    }
+    #[test]
+    fn clone_truncated_test() {
+        let pkt = builders::create_tcp_packet(&PacketConfig {
+            payload_len: 100,
+            ..Default::default()
+        });
+        let (same, len) = pkt.clone_truncated(1500);
+        assert!(same.compare(&pkt));
+        assert_eq!(len, 154);
+        for snaplen in [0, 10, 14, 30, 54, 60] {
+            let (short, len) = pkt.clone_truncated(snaplen);
+            assert_eq!(len, pkt.len());
+            assert_eq!(short.to_vec(), pkt.to_vec()[..snaplen].to_vec());
+        }
+        assert_eq!(pkt.clone_truncated(54).0.layer::<TCP>().unwrap().dst(), 80);
+        assert!(pkt.clone_truncated(53).0.layer::<TCP>().is_none());
+
+        let records: Vec<(Vec<u8>, usize)> = [pkt.clone_truncated(64), pkt.clone_truncated(200)]
+            .iter()
+            .map(|(x, len)| (x.to_vec(), *len))
+            .collect();
+        let data = packet_rs::pcap::write_pcap_truncated(&records);
+        let reader = packet_rs::pcap::PcapReader::from_bytes(&data).unwrap();
+        for rec in reader.records() {
+            assert_eq!(rec.orig_len, 154);
+        }
+        assert_eq!(reader.records()[0].packet.len(), 64);
+        assert!(reader.records()[1].packet.compare(&pkt));
+        // a record claiming more bytes than the capture holds is an error
+        let mut data = data[..24 + 16].to_vec();
+        data[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(packet_rs::pcap::PcapReader::from_bytes(&data).is_err());
+    }
+    #[test]
+    fn capture_ring_test() {
+        let ring = std::sync::Arc::new(CaptureRing::new(Capacity::Bytes(1000)));
+        let feeders: Vec<_> = (0..2u32)
+            .map(|id| {
+                let ring = ring.clone();
+                std::thread::spawn(move || {
+                    let intf = MockInterface::new();
+                    for x in 0..10u8 {
+                        intf.push_rx(&[x; 100]);
+                    }
+                    let mut n = 0;
+                    while n < 10 {
+                        n += ring.record(&intf, id, None).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for feeder in feeders {
+            feeder.join().unwrap();
+        }
+        let stats = ring.stats();
+        assert_eq!((stats.received, stats.dropped, stats.bytes), (20, 10, 2000));
+        assert_eq!(ring.len(), 10);
+        ring.push(0, &[0; 1001]);
+        assert_eq!(ring.stats().dropped, 11);
+        assert_eq!(ring.len(), 10);
+
+        let newest = ring.find(|x| x.data[0] == 9).unwrap();
+        assert_eq!(newest.data, vec![9; 100]);
+        assert!(ring.find(|x| x.intf > 1).is_none());
+
+        // frames pushed after the verify started are received
+        let pkt = builders::create_udp_packet(&Default::default());
+        let late = ring.clone();
+        let bytes = pkt.to_vec();
+        let pusher = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            late.push(2, &bytes);
+        });
+        let port = ring.port(2);
+        interface::verify_packet(&port, &pkt, std::time::Duration::from_secs(5)).unwrap();
+        pusher.join().unwrap();
+        interface::verify_no_other_packets(&port, std::time::Duration::ZERO).unwrap();
+        assert!(port.send_packet(&pkt).is_err());
+
+        let mut expected = pkt.clone();
+        expected.layer_mut::<IPv4>().unwrap().set_ttl(1);
+        let mut mask = Mask::new(&expected);
+        assert!(!mask.matches(&pkt.to_vec()));
+        mask.ignore::<IPv4>("ttl").unwrap().ignore::<IPv4>("header_checksum").unwrap();
+        assert!(mask.matches(&pkt.to_vec()));
+        assert!(matches!(mask.ignore::<TCP>("src"), Err(PacketError::MissingHeader(_))));
+        assert!(matches!(
+            mask.ignore::<UDP>("nope"),
+            Err(PacketError::UnknownField { .. })
+        ));
+        ring.push(3, &pkt.to_vec());
+        ring.push(3, &[0; 10]);
+        let drained = ring.drain_matching(&mask);
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained[0].intf, 3);
+        assert!(ring.find(|x| x.intf == 3).is_some_and(|x| x.data.len() == 10));
+        let frames = ring.drain();
+        assert!(frames.iter().map(|x| x.data.len()).sum::<usize>() <= 1000);
+        assert!(ring.is_empty());
+    }
+    #[test]
+    fn field_u128_test() {
+        let mut ipv6 = IPv6::new();
+        let src: u128 = 0x2001_0db8_0000_0000_0000_0000_0000_00ff;
+        ipv6.set_src_u128(src);
+        assert_eq!(ipv6.src_u128(), src);
+        assert_eq!(ipv6.bytes(IPv6::src_msb(), IPv6::src_lsb()), src.to_be_bytes());
+        // next address in the subnet
+        ipv6.set_dst_u128(ipv6.src_u128() + 1);
+        assert_eq!(
+            ipv6.bytes(IPv6::dst_msb(), IPv6::dst_lsb()),
+            (src + 1).to_be_bytes()
+        );
+        assert_eq!(ipv6.flow_label(), 0);
+        assert_eq!(ipv6.next_hdr(), IPv6::new().next_hdr());
+
+        let v = ipv6.to_vec();
+        let slice = IPv6Slice::from(&v);
+        assert_eq!(slice.src_u128(), src);
+        assert_eq!(slice.dst_u128(), src + 1);
+    }
+    #[test]
+    fn filter_test() {
+        // 0  10.1.1.1 > 10.1.1.2 udp 1234 > 4789
+        // 1  10.1.1.2 > 10.1.1.1 tcp 80 > 40000
+        // 2  vlan 10, 10.1.1.1 > 192.168.0.1 udp 5353 > 53
+        // 3  2001:db8::1 > 2001:db8::2 tcp 1234 > 443
+        // 4  arp who-has 10.1.1.1 tell 10.1.1.9, broadcast
+        // 5  192.168.0.5 > 10.2.0.1 icmp echo request
+        // 6  fe80::1 > ff02::1 icmp6 echo request, multicast
+        // 7  vlan 20 (0x88a8), vlan 30, 10.1.1.1 > 10.1.1.4 tcp 22 > 50000
+        // 8  10.1.1.1 > 10.1.1.3 udp, fragment at offset 800 starting with 4789 4789
+        // 9  10.1.1.5 > 255.255.255.255 udp 68 > 67, broadcast
+        // 10 vlan 10, 2001:db8::5 > 2001:db8::6 udp 4789 > 4789
+        // all frames are from 00:00:00:00:00:01, unicast ones to 00:00:00:00:00:02
+        let reader = packet_rs::pcap::PcapReader::from_bytes(include_bytes!("data/filter.pcap"));
+        let reader = reader.unwrap();
+        // as tcpdump selects them, see tests/data/filter_expected.sh
+        let cases: Vec<(&str, Vec<usize>)> = include_str!("data/filter_expected.txt")
+            .lines()
+            .map(|line| {
+                let (expr, frames) = line.split_once('\t').unwrap();
+                (
+                    expr,
+                    frames
+                        .split_whitespace()
+                        .map(|x| x.parse().unwrap())
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(cases.len(), 50);
+        for (expr, expected) in &cases {
+            let filter = Filter::compile(expr).unwrap();
+            let selected: Vec<usize> = reader
+                .records()
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| filter.matches(&x.packet))
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(&selected, expected, "{}", expr);
+            assert_eq!(reader.filtered(&filter).count(), expected.len());
+        }
+        let errors = [
+            ("tcp[13] & 2 != 0", 3),
+            ("host example.com", 5),
+            ("port http", 5),
+            ("tcp host 10.1.1.1", 9),
+            ("(ip", 3),
+            ("ip ip", 3),
+            ("ip and", 6),
+            ("vlan 4096", 5),
+        ];
+        for (expr, position) in errors {
+            match Filter::compile(expr) {
+                Err(PacketError::InvalidFilter { position: p, .. }) => {
+                    assert_eq!(p, position, "{}", expr)
+                }
+                x => panic!("{}: {:?}", expr, x.map(|_| ())),
+            }
+        }
+
+        let intf = MockInterface::new();
+        for record in reader.records() {
+            intf.push_packet(&record.packet);
+        }
+        let filter: Filter = "udp".parse().unwrap();
+        let timeout = std::time::Duration::from_millis(10);
+        let pkts = interface::sniff(&intf, Some(&filter), Some(2), timeout).unwrap();
+        assert_eq!(pkts.len(), 2);
+        assert!(pkts[1].compare(&reader.records()[8].packet));
+        let pkts = interface::sniff(&intf, None, None, timeout).unwrap();
+        assert_eq!(pkts.len(), 2);
+    }
+    #[test]
+    fn checksum_offload_test() {
+        use packet_rs::checksum::Offload;
+        let cfg = PacketConfig {
+            payload_len: 33,
+            ..Default::default()
+        };
+        let inner = builders::create_tcp_packet(&cfg);
+        let pkts = [
+            builders::create_udp_packet(&cfg),
+            builders::create_ipv6_tcp_packet(&cfg),
+            builders::create_vxlan_packet(&cfg, 10, inner),
+        ];
+        for pkt in pkts {
+            for offload in [Offload::Zeroed, Offload::Partial] {
+                let mut p = pkt.clone();
+                p.prepare_for_offload(offload);
+                if let Some(ipv4) = p.layer::<IPv4>() {
+                    assert_eq!(ipv4.header_checksum(), 0);
+                }
+                p.complete_offload(offload);
+                assert!(p.compare(&pkt), "{}", pkt.summary());
+            }
+        }
+
+        // the partial sum is the folded sum of the pseudo header, not complemented
+        let mut p = builders::create_udp_packet(&cfg);
+        p.prepare_for_offload(Offload::Partial);
+        let udp_len = 8 + 33;
+        let mut sum: u32 = 0xc0a8 + 0x0001 + 0xc0a8 + 0x0002 + 17 + udp_len;
+        sum = (sum >> 16) + (sum & 0xffff);
+        assert_eq!(p.layer::<UDP>().unwrap().checksum(), sum as u64);
+        // a driver writing a wrong partial sum gets a wrong checksum on the wire
+        p.layer_mut::<UDP>().unwrap().set_checksum(sum as u64 + 1);
+        p.complete_offload(Offload::Partial);
+        assert!(p.verify_checksums().is_err());
+    }
+    #[test]
+    fn pcap_stream_test() {
+        let pkts: Vec<Vec<u8>> = (0..10)
+            .map(|x| {
+                builders::create_udp_packet(&PacketConfig {
+                    payload_len: x * 10,
+                    ..Default::default()
+                })
+                .to_vec()
+            })
+            .collect();
+        let data = packet_rs::pcap::write_pcap(&pkts);
+        let stream = packet_rs::pcap::PcapReader::from_reader(&data[..]).unwrap();
+        assert_eq!(stream.linktype(), packet_rs::pcap::LINKTYPE_ETHERNET);
+        let records: Vec<_> = stream.map(|x| x.unwrap()).collect();
+        let reader = packet_rs::pcap::PcapReader::from_bytes(&data).unwrap();
+        assert_eq!(records.len(), 10);
+        for (a, b) in records.iter().zip(reader) {
+            assert!(a.packet.compare(&b.packet));
+            assert_eq!(a.orig_len, b.orig_len);
+        }
+
+        let mut stream = packet_rs::pcap::PcapReader::from_reader(&data[..data.len() - 1]).unwrap();
+        assert_eq!(stream.by_ref().take(9).filter(|x| x.is_ok()).count(), 9);
+        assert!(matches!(stream.next(), Some(Err(PacketError::Parse { .. }))));
+        assert!(stream.next().is_none());
+        assert!(packet_rs::pcap::PcapReader::from_reader(&data[..10]).is_err());
+    }
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn par_process_test() {
+        let path = std::env::temp_dir().join(format!("par_{}.pcap", std::process::id()));
+        let pkts: Vec<Vec<u8>> = (0..200)
+            .map(|i| {
+                let pkt = builders::create_tcp_packet(&PacketConfig {
+                    sport: 1000 + i % 5,
+                    ..Default::default()
+                });
+                pkt.to_vec()
+            })
+            .collect();
+        std::fs::write(&path, packet_rs::pcap::write_pcap(&pkts)).unwrap();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let flows = pool
+            .install(|| {
+                packet_rs::pcap::par_process(&path, std::collections::HashMap::new, |flows, rec| {
+                    *flows.entry(rec.packet.flow_tuple()).or_insert(0) += 1
+                })
+            })
+            .unwrap();
+        assert!(!flows.is_empty());
+        let mut merged = std::collections::HashMap::new();
+        for (flow, n) in flows.iter().flatten() {
+            *merged.entry(flow).or_insert(0) += n;
+        }
+        assert_eq!(merged.len(), 5);
+        assert!(merged.values().all(|n| *n == 40));
+
+        // the panic of f reaches the caller
+        let panicked = std::panic::catch_unwind(|| {
+            packet_rs::pcap::par_process(&path, || (), |_, _| panic!("worker"))
+        });
+        let panic = panicked.unwrap_err();
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"worker"));
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    #[cfg(feature = "rayon")]
+    #[ignore = "writes a 256 MiB capture, run with --ignored"]
+    fn pcap_process_large_test() {
+        // peak resident memory of the process, linux only
+        let peak = || {
+            let status = std::fs::read_to_string("/proc/self/status").ok()?;
+            let line = status.lines().find(|x| x.starts_with("VmHWM:"))?;
+            line.split_whitespace().nth(1)?.parse::<usize>().ok().map(|x| x * 1024)
+        };
+        let path = std::env::temp_dir().join(format!("large_{}.pcap", std::process::id()));
+        let pkt = builders::create_tcp_packet(&PacketConfig {
+            payload_len: 1400,
+            ..Default::default()
+        });
+        let record = packet_rs::pcap::write_pcap(&[pkt.to_vec()])[24..].to_vec();
+        let count = (256 << 20) / record.len();
+        {
+            use std::io::Write;
+            let mut file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+            file.write_all(&packet_rs::pcap::write_pcap(&[])).unwrap();
+            for _ in 0..count {
+                file.write_all(&record).unwrap();
+            }
+        }
+        let before = peak();
+        let mut bytes = 0;
+        let n = packet_rs::pcap::process(&path, |rec| bytes += rec.packet.len()).unwrap();
+        assert_eq!((n, bytes), (count, count * pkt.len()));
+        let flows =
+            packet_rs::pcap::par_process(&path, std::collections::HashMap::new, |flows, rec| {
+                *flows.entry(rec.packet.flow_tuple()).or_insert(0) += 1
+            })
+            .unwrap();
+        let total: usize = flows.iter().flat_map(|x| x.values()).sum();
+        assert_eq!(total, count);
+        if let (Some(before), Some(after)) = (before, peak()) {
+            assert!(after - before < 64 << 20, "peak grew by {} bytes", after - before);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn sort_by_field_test() {
+        let tcp = |sport: u16, dport: u16| {
+            builders::create_tcp_packet(&PacketConfig {
+                sport,
+                dport,
+                ..Default::default()
+            })
+        };
+        let arp = builders::create_arp_packet(&Default::default());
+        let mut pkts = vec![tcp(3, 80), tcp(1, 443), arp.clone(), tcp(2, 80), tcp(4, 22)];
+        assert_eq!(pkts[2].sort_key("TCP", "dst"), None);
+        assert_eq!(pkts[0].sort_key("TCP", "nope"), None);
+        Packet::sort_by_field(&mut pkts, "TCP", "dst");
+        assert!(pkts[0].compare(&arp));
+        let sports: Vec<u64> = pkts[1..].iter().map(|x| x.layer::<TCP>().unwrap().src()).collect();
+        // packets to port 80 keep their order
+        assert_eq!(sports, vec![4, 3, 2, 1]);
+
+        let ipv6 = |src: &str| {
+            builders::create_ipv6_tcp_packet(&PacketConfig {
+                ipv6_src: String::from(src),
+                ..Default::default()
+            })
+        };
+        let mut pkts = vec![ipv6("2001:db8::10"), ipv6("2001:db8::2"), ipv6("fe80::1")];
+        Packet::sort_by_field(&mut pkts, "IPv6", "src");
+        let srcs: Vec<String> = pkts
+            .iter()
+            .map(|x| x.layer::<IPv6>().unwrap().src_ip().to_string())
+            .collect();
+        assert_eq!(srcs, vec!["2001:db8::2", "2001:db8::10", "fe80::1"]);
+    }
+    #[test]
+    fn flow_tracker_test() {
+        let reader =
+            packet_rs::pcap::PcapReader::from_bytes(include_bytes!("data/flows.pcap")).unwrap();
+        let mut tracker = FlowTracker::new();
+        for record in reader.records() {
+            tracker.add_record(record);
+        }
+        // the arp packet has no flow
+        assert_eq!(tracker.len(), 5);
+        let ip = |x: &str| x.parse::<std::net::IpAddr>().unwrap();
+        let tuple = |src, dst, proto, ports| FlowTuple {
+            src: ip(src),
+            dst: ip(dst),
+            proto,
+            ports,
+        };
+
+        let http = tuple("10.0.0.1", "10.0.0.2", 6, Some((40000, 80)));
+        let flow = tracker.get(&http.reversed()).unwrap();
+        assert_eq!(flow, tracker.get(&http).unwrap());
+        assert_eq!(flow.tuple, http);
+        assert_eq!((flow.forward.packets, flow.forward.bytes), (7, 652));
+        assert_eq!((flow.reverse.packets, flow.reverse.bytes), (3, 180));
+        assert_eq!(flow.forward.retransmissions, 1);
+        assert_eq!(flow.forward.out_of_order + flow.reverse.retransmissions, 0);
+        assert_eq!(flow.tcp_state, Some(TcpState::Closed));
+        assert_eq!(flow.first, std::time::Duration::from_secs(1_700_000_000));
+        assert_eq!(flow.duration().as_secs(), 5);
+
+        let refused = tuple("10.0.0.3", "10.0.0.2", 6, Some((40001, 443)));
+        let flow = tracker.get(&refused).unwrap();
+        assert_eq!((flow.forward.packets, flow.reverse.packets), (1, 1));
+        assert_eq!(flow.tcp_state, Some(TcpState::Reset));
+
+        // first seen after the handshake, with a missing segment
+        let ssh = tuple("10.0.0.6", "10.0.0.1", 6, Some((22, 50000)));
+        let flow = tracker.get(&ssh).unwrap();
+        assert_eq!(flow.tcp_state, Some(TcpState::Established));
+        assert_eq!((flow.forward.out_of_order, flow.reverse.packets), (1, 0));
+
+        let dns = tuple("10.0.0.5", "10.0.0.4", 17, Some((53, 5353)));
+        let flow = tracker.get(&dns).unwrap();
+        assert_eq!(flow.tuple, dns.reversed());
+        assert_eq!((flow.forward.bytes, flow.reverse.bytes), (144, 204));
+        assert_eq!(flow.tcp_state, None);
+
+        let ping = tuple("8.8.8.8", "10.0.0.1", 1, None);
+        assert_eq!(tracker.get(&ping).unwrap().packets(), 2);
+
+        let bytes: Vec<u64> = tracker.by_bytes().iter().map(|x| x.bytes()).collect();
+        assert_eq!(bytes, vec![832, 508, 348, 148, 120]);
+        // ties are ordered by the first packet
+        let ports: Vec<_> = tracker.by_packets().iter().map(|x| x.tuple.ports).collect();
+        assert_eq!(
+            ports,
+            vec![
+                Some((40000, 80)),
+                Some((5353, 53)),
+                Some((40001, 443)),
+                None,
+                Some((22, 50000))
+            ]
+        );
+
+        // live frames count the same as their records
+        let ring = CaptureRing::new(Capacity::Packets(32));
+        for record in reader.records() {
+            ring.push(0, &record.packet.to_vec());
+        }
+        let mut live = FlowTracker::new();
+        for frame in ring.drain() {
+            live.add_frame(&frame);
+        }
+        let flow = live.get(&http).unwrap();
+        assert_eq!((flow.packets(), flow.bytes()), (10, 832));
+        assert_eq!(flow.forward.retransmissions, 1);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(tracker.get(&http).unwrap()).unwrap();
+            assert_eq!(json["tuple"]["src"], "10.0.0.1");
+            assert_eq!(json["forward"]["retransmissions"], 1);
+            assert_eq!(json["tcp_state"], "Closed");
+        }
+    }
+    #[test]
+    fn malformer_test() {
+        let tcp = builders::create_tcp_packet(&Default::default());
+        let mut m = Malformer::new(&tcp);
+        m.corrupt_length("TCP").unwrap();
+        let report = m.packet().diagnose();
+        assert_eq!(report[2].length_ok, Some(false));
+        assert!(m.packet().verify_checksums().is_ok());
+
+        // a corrupted checksum survives later malformations
+        m.corrupt_checksum("TCP")
+            .unwrap()
+            .corrupt_length("IPv4")
+            .unwrap();
+        let report = m.packet().diagnose();
+        assert_eq!(report[1].length_ok, Some(false));
+        assert_eq!(report[1].checksum_ok, Some(true));
+        assert_eq!(report[2].checksum_ok, Some(false));
+        assert_eq!(
+            m.packet().layer::<IPv4>().unwrap().total_len(),
+            tcp.layer::<IPv4>().unwrap().total_len() + 1
+        );
+
+        let mut m = Malformer::new(&tcp);
+        m.set_ihl(3)
+            .unwrap()
+            .syn_fin()
+            .unwrap()
+            .fragment_with_df()
+            .unwrap();
+        let pkt = m.packet();
+        assert_eq!(pkt.layer::<IPv4>().unwrap().ihl(), 3);
+        assert_eq!(pkt.layer::<IPv4>().unwrap().flags(), 0x3);
+        assert_eq!(pkt.layer::<TCP>().unwrap().flags() & 0x3, 0x3);
+        assert!(pkt.verify_checksums().is_ok());
+
+        let mut m = Malformer::new(&tcp);
+        m.set_reserved();
+        assert_eq!(
+            m.packet().validate(),
+            Err(PacketError::ReservedNotZero {
+                header: String::from("TCP"),
+                field: String::from("res"),
+            })
+        );
+        let pkt = m.truncate(40).clone().into_packet();
+        assert_eq!(pkt.len(), 40);
+        assert!(pkt.layer::<TCP>().is_none());
+        assert_eq!(
+            pkt.layer::<IPv4>().unwrap().total_len() as usize,
+            tcp.len() - 14
+        );
+
+        let udp = builders::create_udp_packet(&Default::default());
+        let mut m = Malformer::new(&udp);
+        assert_eq!(
+            m.corrupt_length("Ether").err(),
+            Some(PacketError::UnknownField {
+                header: String::from("Ether"),
+                field: String::from("length"),
+            })
+        );
+        assert_eq!(
+            m.corrupt_checksum("TCP").err(),
+            Some(PacketError::UnknownHeader(String::from("TCP")))
+        );
+        assert!(matches!(
+            m.set_ihl(16),
+            Err(PacketError::ValueOverflow { .. })
+        ));
+        assert!(matches!(m.syn_fin(), Err(PacketError::MissingHeader(_))));
+        assert!(matches!(
+            m.overlap_fragments(576, 8),
+            Err(PacketError::InvalidValue { .. })
+        ));
+
+        let big = builders::create_udp_packet(&PacketConfig {
+            payload_len: 1500,
+            ..Default::default()
+        });
+        let frags = Malformer::new(&big).overlap_fragments(576, 10).unwrap();
+        assert_eq!(frags.len(), 3);
+        assert!(frag::reassemble_ipv4(&frags).is_none());
+        let data = &big.to_vec()[34..];
+        let mut prev_end = 0;
+        for frag in &frags {
+            let ipv4 = frag.layer::<IPv4>().unwrap();
+            let start = ipv4.frag_offset() as usize * 8;
+            assert_eq!(start, prev_end.max(16) - 16);
+            assert!(frag.verify_checksums().is_ok());
+            let end = start + ipv4.total_len() as usize - 20;
+            assert_eq!(&frag.to_vec()[34..], &data[start..end]);
+            prev_end = end;
+        }
+        // the overlap is limited to the previous fragment
+        let frags = Malformer::new(&big).overlap_fragments(576, 2000).unwrap();
+        let starts: Vec<u64> = frags
+            .iter()
+            .map(|x| x.layer::<IPv4>().unwrap().frag_offset())
+            .collect();
+        assert_eq!(starts, vec![0, 0, 69]);
+    }
+    #[test]
+    fn tcp_reassembly_test() {
+        let body: String = (0..21)
+            .map(|i| format!("line {:03} of the request body\n", i))
+            .collect();
+        let request = format!(
+            "POST /upload HTTP/1.1\r\nHost: 10.0.0.2\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        // shuffled and retransmitted segments across the sequence number wraparound, with a
+        // segment overwriting bytes 150..250 with 'X' before they are contiguous
+        let reader =
+            packet_rs::pcap::PcapReader::from_bytes(include_bytes!("data/stream.pcap")).unwrap();
+        let client = "10.0.0.1".parse::<std::net::IpAddr>().unwrap();
+        let segments: Vec<&Packet> = reader
+            .records()
+            .iter()
+            .map(|x| &x.packet)
+            .filter(|x| x.flow_tuple().unwrap().src == client)
+            .collect();
+
+        let mut stream = TcpReassembler::new();
+        let mut offsets = Vec::new();
+        for (i, pkt) in segments.iter().enumerate() {
+            stream.push(pkt).unwrap();
+            offsets.push(stream.offset());
+            if i == 5 {
+                assert_eq!(stream.holes(), vec![0..128, 384..512]);
+            }
+        }
+        assert_eq!(offsets, vec![0, 0, 0, 0, 0, 0, 384, 384, 384, 671, 671]);
+        assert!(stream.is_closed());
+        assert!(stream.holes().is_empty());
+        let mut data = Vec::new();
+        let mut buf = [0; 100];
+        loop {
+            match stream.read(&mut buf) {
+                0 => break,
+                n => data.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(String::from_utf8(data).unwrap(), request);
+        assert_eq!(
+            stream.stats(),
+            ReassemblyStats {
+                segments: 11,
+                bytes: 671,
+                duplicate_bytes: 100 + 128 + 128,
+                skipped_bytes: 0,
+            }
+        );
+
+        let mut stream = TcpReassembler::with_policy(OverlapPolicy::Last);
+        for pkt in &segments {
+            stream.push(pkt).unwrap();
+        }
+        let mut expected = request.clone().into_bytes();
+        expected[150..250].fill(b'X');
+        assert_eq!(stream.read_all(), expected);
+
+        // chunks go to the callback, and holes are reported until skipped
+        let chunks = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut stream = TcpReassembler::new();
+        let sink = chunks.clone();
+        stream.on_chunk(move |offset, data| sink.borrow_mut().push((offset, data.len())));
+        for (i, pkt) in segments.iter().enumerate() {
+            // drop both copies of the segment at 384
+            if i < 9 {
+                stream.push(pkt).unwrap();
+            }
+        }
+        assert!(!stream.is_closed());
+        assert_eq!(stream.holes(), vec![384..512]);
+        assert_eq!(stream.skip_hole(), Some(384..512));
+        assert_eq!(stream.skip_hole(), None);
+        assert!(stream.is_closed());
+        assert_eq!(stream.stats().skipped_bytes, 128);
+        assert!(stream.read_all().is_empty());
+        let chunks = chunks.borrow();
+        assert_eq!(chunks.first(), Some(&(0, 128)));
+        assert_eq!(chunks.last(), Some(&(640, 31)));
+        assert!(!chunks.iter().any(|(offset, _)| *offset == 384));
+
+        let udp = builders::create_udp_packet(&Default::default());
+        assert!(matches!(
+            TcpReassembler::new().push(&udp),
+            Err(PacketError::MissingHeader(_))
+        ));
+
+        // a segment starting before the first one seen only adds the bytes after the start
+        let mut stream = TcpReassembler::new();
+        stream.push_segment(108, 0x10, b"HTTP");
+        stream.push_segment(100, 0x10, b"GET / HTTP/1.1\r\n");
+        assert_eq!(stream.read_all(), b"HTTP.1\r\n");
+        assert_eq!(stream.stats().duplicate_bytes, 4);
+    }
+    #[test]
+    fn header_hex_test() {
+        let pkt = builders::create_tcp_packet(&Default::default());
+        let v = pkt.to_vec();
+        let ipv4 = pkt.layer::<IPv4>().unwrap();
+        let hex: String = v[14..34].iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(format!("{:x}", ipv4), hex);
+        assert_eq!(format!("{:X}", ipv4), hex.to_uppercase());
+        assert_eq!(format!("{:#x}", ipv4), format!("0x{}", hex));
+        let slice = IPv4Slice::from(&v[14..]);
+        assert_eq!(format!("{:x}", slice), hex);
+        assert_eq!(format!("{:#X}", slice), format!("0x{}", hex.to_uppercase()));
+
+        let vlan = Vlan::from(vec![0x00, 0x0a, 0x08, 0x00]);
+        assert_eq!(format!("{:x}", vlan), "000a0800");
+        // padding applies to the whole string, after the prefix
+        assert_eq!(format!("{:>10x}", vlan), "  000a0800");
+        assert_eq!(format!("{:#012x}", vlan), "0x00000a0800");
+    }
+    #[test]
+    fn verify_report_test() {
+        let mut pkt = builders::create_tcp_packet(&Default::default());
+        let report = pkt.verify();
+        assert!(report.is_well_formed());
+        let fields: Vec<_> = report
+            .checks
+            .iter()
+            .map(|x| (x.layer.as_str(), x.field))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("IPv4", "version"),
+                ("IPv4", "total_len"),
+                ("IPv4", "ihl"),
+                ("IPv4", "ttl"),
+                ("IPv4", "header_checksum"),
+                ("TCP", "data_offset"),
+                ("TCP", "checksum"),
+            ]
+        );
+        assert!(report.checks.iter().all(|x| x.status == CheckStatus::Ok));
+
+        pkt.layer_mut::<IPv4>().unwrap().set_total_len(100);
+        pkt.layer_mut::<IPv4>().unwrap().set_ihl(6);
+        let report = pkt.verify();
+        assert!(!pkt.is_well_formed());
+        let bad: Vec<_> = report.failures().map(|x| x.field).collect();
+        assert_eq!(bad, vec!["total_len", "ihl", "header_checksum"]);
+        let total_len = report.failures().next().unwrap();
+        assert_eq!(
+            (total_len.offset, total_len.expected, total_len.actual),
+            (14, Some(40), Some(100))
+        );
+        // the length and checksum checks agree with diagnose
+        let diagnosis = pkt.diagnose();
+        assert_eq!(diagnosis[1].length_ok, Some(false));
+        assert_eq!(diagnosis[1].checksum_ok, Some(false));
+        assert_eq!(diagnosis[2].length_ok, Some(true));
+        let text = report.to_string();
+        assert!(text.starts_with("7 checks: 4 ok, 3 bad, 0 skipped\n"));
+        assert!(text.contains("BAD     IPv4@14         total_len       expected 40, found 100\n"));
+        assert!(text.contains(
+            "ok      TCP@34          data_offset     5 (5 words up to the segment length)\n"
+        ));
+
+        // a zero UDP checksum over IPv4 is not checked
+        let mut udp = builders::create_udp_packet(&Default::default());
+        udp.layer_mut::<UDP>().unwrap().set_checksum(0);
+        let report = udp.verify();
+        assert!(report.is_well_formed());
+        let check = report.checks.last().unwrap();
+        assert_eq!(
+            (check.status, check.note),
+            (CheckStatus::Skipped, Some("disabled"))
+        );
+        let text = report.to_string();
+        assert!(text.contains("skipped UDP@34          checksum        (disabled)\n"));
+        let ipv6 = builders::create_ipv6_tcp_packet(&Default::default());
+        assert!(ipv6.is_well_formed());
+        assert_eq!(ipv6.verify().checks[1].field, "payload_len");
+
+        let mut vxlan = builders::create_vxlan_packet(&Default::default(), 10, udp.clone());
+        assert!(vxlan.is_well_formed());
+        vxlan.layer_mut::<Vxlan>().unwrap().set_flags(0x0c);
+        vxlan.update_checksums();
+        let bad: Vec<_> = vxlan
+            .verify()
+            .failures()
+            .map(|x| (x.field, x.expected))
+            .collect();
+        assert_eq!(bad, vec![("flags", Some(0x08))]);
+
+        let mut gre = utils::create_gre_packet(
+            "00:01:02:03:04:05",
+            "00:06:07:08:09:0a",
+            false,
+            0,
+            0,
+            5,
+            "10.0.0.1",
+            "10.0.0.2",
+            0,
+            64,
+            0,
+            0,
+            Vec::new(),
+            true,
+            false,
+            false,
+            false,
+            false,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            &[],
+            Some(udp.clone()),
+        );
+        let check = gre.verify().failures().next().unwrap().clone();
+        assert_eq!(
+            (check.layer.as_str(), check.field),
+            ("GREChksumOffset", "chksum")
+        );
+        let sum = check.expected.unwrap();
+        gre.layer_mut::<GREChksumOffset>().unwrap().set_chksum(sum);
+        assert!(gre.is_well_formed());
+
+        // the FCS is the CRC32 of the frame, least significant byte first
+        let crc32 = |data: &[u8]| {
+            let mut crc = 0xffffffffu32;
+            for b in data {
+                crc ^= *b as u32;
+                for _ in 0..8 {
+                    crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+                }
+            }
+            !crc
+        };
+        let crc = crc32(&udp.to_vec());
+        let mut with_fcs = udp.clone();
+        with_fcs.set_payload(&crc.to_le_bytes());
+        let report = with_fcs.verify_with_fcs();
+        assert!(report.is_well_formed());
+        assert_eq!(report.checks.last().unwrap().field, "fcs");
+        let mut bad_fcs = udp.clone();
+        bad_fcs.set_payload(&(crc ^ 1).to_le_bytes());
+        let check = bad_fcs.verify_with_fcs().failures().next().unwrap().clone();
+        assert_eq!((check.field, check.expected), ("fcs", Some(crc as u64)));
+    }
+    #[test]
+    fn packet_concat_test() {
+        let cfg = PacketConfig {
+            payload_len: 10,
+            ..Default::default()
+        };
+        let inner = builders::create_tcp_packet(&cfg);
+        let mut outer = Packet::new();
+        outer.push(Packet::ethernet(
+            "00:01:02:03:04:05",
+            "00:06:07:08:09:0a",
+            0x0800,
+        ));
+        let mut ip = Packet::new();
+        ip.push(IPv4::new());
+        ip.push(UDP::new());
+        ip.push(Vxlan::new());
+
+        let pkt = outer.clone() + ip.clone() + inner.clone();
+        let names: Vec<&str> = pkt.iter().map(|x| x.name()).collect();
+        assert_eq!(
+            names,
+            vec!["Ether", "IPv4", "UDP", "Vxlan", "Ether", "IPv4", "TCP"]
+        );
+        // the payload of the last packet is kept
+        assert_eq!(pkt.len(), outer.len() + ip.len() + inner.len());
+        assert_eq!(
+            &pkt.to_vec()[pkt.len() - inner.len()..],
+            inner.to_vec().as_slice()
+        );
+
+        let mut joined = outer.clone();
+        joined.extend(ip.clone());
+        joined.extend(inner.clone());
+        assert!(joined.compare(&pkt));
+
+        // a packet with a payload takes the next one as payload bytes
+        let mut with_payload = inner.clone();
+        with_payload.extend(outer.clone());
+        assert_eq!(with_payload.iter().count(), inner.iter().count());
+        assert_eq!(
+            with_payload.to_vec(),
+            [inner.to_vec(), outer.to_vec()].concat()
+        );
+
+        // the trailers of the appended packet are kept
+        let mut stamped = inner.clone();
+        stamped.push_trailer(Box::new(HwTimestamp::new()));
+        let mut joined = outer.clone();
+        joined.extend(stamped.clone());
+        assert_eq!(joined.len(), outer.len() + stamped.len());
+        assert_eq!(joined.to_vec(), [outer.to_vec(), stamped.to_vec()].concat());
+        assert_eq!((outer.clone() + stamped).to_vec(), joined.to_vec());
+    }
+    #[test]
+    fn corrupt_test() {
+        let tcp = builders::create_tcp_packet(&Default::default());
+        let failures = |pkt: &Packet| -> Vec<(String, &'static str)> {
+            pkt.verify()
+                .failures()
+                .map(|x| (x.layer.clone(), x.field))
+                .collect()
+        };
+        let bad = |layer: &str, field: &'static str| vec![(String::from(layer), field)];
+        // every corruption survives the fixups and is the only defect found
+        let check = |pkt: &mut Packet, expected: Vec<(String, &'static str)>| {
+            let v = pkt.to_vec();
+            assert_eq!(pkt.to_vec_with_fixup(), v);
+            pkt.update_checksums();
+            assert_eq!(pkt.to_vec(), v);
+            pkt.with_zeroed_checksum(|_| ());
+            assert_eq!(pkt.to_vec(), v);
+            assert_eq!(failures(pkt), expected);
+        };
+
+        let mut pkt = tcp.clone();
+        pkt.bad_ipv4_checksum().unwrap();
+        let ipv4 = pkt.layer::<IPv4>().unwrap();
+        assert_eq!(
+            ipv4.header_checksum(),
+            tcp.layer::<IPv4>().unwrap().header_checksum() + 1
+        );
+        assert!(pkt.is_poisoned(1, "header_checksum"));
+        check(&mut pkt, bad("IPv4", "header_checksum"));
+
+        let mut pkt = tcp.clone();
+        pkt.bad_l4_checksum().unwrap();
+        check(&mut pkt, bad("TCP", "checksum"));
+        let mut udp = builders::create_udp_packet(&Default::default());
+        udp.bad_l4_checksum().unwrap();
+        check(&mut udp, bad("UDP", "checksum"));
+
+        let mut pkt = tcp.clone();
+        pkt.bad_version(6).unwrap();
+        check(&mut pkt, bad("IPv4", "version"));
+        assert!(matches!(
+            pkt.bad_version(16),
+            Err(PacketError::ValueOverflow { bits: 4, .. })
+        ));
+
+        let mut pkt = tcp.clone();
+        pkt.ttl_zero().unwrap();
+        check(&mut pkt, bad("IPv4", "ttl"));
+
+        let mut pkt = tcp.clone();
+        pkt.bad_ihl(4).unwrap();
+        check(&mut pkt, bad("IPv4", "ihl"));
+
+        let mut pkt = tcp.clone();
+        pkt.giant_total_len().unwrap();
+        assert_eq!(pkt.layer::<IPv4>().unwrap().total_len(), 0xffff);
+        check(&mut pkt, bad("IPv4", "total_len"));
+
+        let mut pkt = tcp.clone();
+        pkt.truncate(tcp.len() - 4);
+        assert_eq!(pkt.len(), tcp.len() - 4);
+        let v = pkt.to_vec();
+        pkt.update_checksums();
+        assert_eq!(pkt.to_vec(), v);
+        assert_eq!(failures(&pkt)[0], (String::from("IPv4"), "total_len"));
+
+        // a flipped bit of the TCP destination port fails the TCP checksum only
+        let mut pkt = tcp.clone();
+        pkt.flip_bit(37, 7).unwrap();
+        assert_eq!(
+            pkt.layer::<TCP>().unwrap().dst(),
+            tcp.layer::<TCP>().unwrap().dst() ^ 1
+        );
+        check(&mut pkt, bad("TCP", "checksum"));
+        // in the IPv4 source address, the IPv4 and TCP checksums
+        let mut pkt = tcp.clone();
+        pkt.flip_bit(26, 0).unwrap();
+        check(
+            &mut pkt,
+            vec![
+                (String::from("IPv4"), "header_checksum"),
+                (String::from("TCP"), "checksum"),
+            ],
+        );
+        assert!(pkt.flip_bit(tcp.len(), 0).is_err());
+        assert!(pkt.flip_bit(0, 8).is_err());
+
+        // poisoned demux fields are not fixed up either
+        let mut pkt = tcp.clone();
+        pkt.layer_mut::<Ether>().unwrap().set_etype(0x86dd);
+        pkt.poison(0, "etype").unwrap();
+        assert_eq!(pkt.to_vec_with_fixup()[12..14], [0x86, 0xdd]);
+        pkt.clear_poison();
+        assert_eq!(pkt.to_vec_with_fixup()[12..14], [0x08, 0x00]);
+        assert!(matches!(
+            pkt.poison(0, "ttl"),
+            Err(PacketError::UnknownField { .. })
+        ));
+
+        // the marks stay on their header when layers are added or removed before it
+        let mut pkt = tcp.clone();
+        pkt.ttl_zero().unwrap();
+        pkt.insert_after::<Ether>(Vlan::new(), true).unwrap();
+        assert!(pkt.is_poisoned(2, "ttl"));
+        assert!(!pkt.is_poisoned(1, "ttl"));
+        assert_eq!(pkt.to_vec_with_fixup()[14 + 4 + 8], 0);
+        pkt.remove(1);
+        assert!(pkt.is_poisoned(1, "ttl"));
+        let mut outer = Packet::new();
+        outer.push(Ether::new());
+        outer.push(IPv4::new());
+        outer.push(UDP::new());
+        outer.push(Vxlan::new());
+        outer.extend(pkt.clone());
+        assert!(outer.is_poisoned(5, "ttl"));
+        assert!(!outer.is_poisoned(1, "ttl"));
+        // and go with it
+        pkt.take_header::<IPv4>().unwrap();
+        assert!(!pkt.is_poisoned(1, "ttl"));
+
+        // the malformations of a malformer are poisoned as well
+        let mut m = Malformer::new(&tcp);
+        m.syn_fin().unwrap().corrupt_checksum("IPv4").unwrap();
+        let pkt = m.into_packet();
+        let v = pkt.to_vec();
+        assert_eq!(pkt.to_vec_with_fixup(), v);
+        assert!(pkt.is_poisoned(2, "flags"));
+        assert!(pkt.is_poisoned(1, "header_checksum"));
+    }
+    #[test]
+    fn header_fits_test() {
+        assert!(Ether::fits(14));
+        assert!(!Ether::fits(13));
+        assert!(EtherSlice::fits(64));
+        assert!(TCP::fits(TCP::size()));
+        assert!(!TCP::fits(TCP::size() - 1));
+        // the slice of a frame is only taken once it holds the header
+        let frame = builders::create_udp_packet(&Default::default()).to_vec();
+        let rest = &frame[Ether::size() + IPv4::size()..];
+        assert!(UDPSlice::fits(rest.len()));
+        assert_eq!(UDPSlice::from(rest).dst(), 80);
+        assert!(!IPv6Slice::fits(rest.len()));
+    }
+    #[test]
+    fn rewrite_5tuple_test() {
+        let ip = |x: &str| Some(x.parse::<std::net::IpAddr>().unwrap());
+        let mut tcp = builders::create_tcp_packet(&Default::default());
+        tcp.rewrite_5tuple(
+            ip("198.51.100.1"),
+            ip("203.0.113.9"),
+            Some(40000),
+            Some(8080),
+        )
+        .unwrap();
+        let flow = tcp.flow_tuple().unwrap();
+        assert_eq!(flow.src, ip("198.51.100.1").unwrap());
+        assert_eq!(flow.dst, ip("203.0.113.9").unwrap());
+        assert_eq!(flow.ports, Some((40000, 8080)));
+        // the incremental update matches a full recomputation
+        let mut full = tcp.clone();
+        full.update_checksums();
+        assert!(tcp.compare(&full));
+
+        // only the ports, the IPv4 checksum is unchanged
+        let mut udp = builders::create_udp_packet(&Default::default());
+        let ipv4_chksum = udp.layer::<IPv4>().unwrap().header_checksum();
+        udp.rewrite_5tuple(None, None, None, Some(53)).unwrap();
+        assert_eq!(udp.layer::<IPv4>().unwrap().header_checksum(), ipv4_chksum);
+        assert!(udp.verify_checksums().is_ok());
+        // a disabled UDP checksum stays disabled
+        udp.layer_mut::<UDP>().unwrap().set_checksum(0);
+        udp.rewrite_5tuple(ip("10.1.1.1"), None, Some(1), None)
+            .unwrap();
+        assert_eq!(udp.layer::<UDP>().unwrap().checksum(), 0);
+        assert!(udp.verify_checksums().is_ok());
+
+        let mut ipv6 = builders::create_ipv6_tcp_packet(&Default::default());
+        ipv6.rewrite_5tuple(ip("2001:db8::1"), None, Some(443), None)
+            .unwrap();
+        assert_eq!(
+            ipv6.layer::<IPv6>().unwrap().src_ip(),
+            "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap()
+        );
+        assert!(ipv6.verify_checksums().is_ok());
+
+        assert!(matches!(
+            ipv6.rewrite_5tuple(ip("10.0.0.1"), None, None, None),
+            Err(PacketError::InvalidValue { .. })
+        ));
+        let mut icmp = Packet::new();
+        icmp.push(Ether::new());
+        icmp.push(IPv4::new());
+        icmp.push(ICMP::new());
+        icmp.layer_mut::<IPv4>().unwrap().set_total_len(28);
+        icmp.update_checksums();
+        assert!(matches!(
+            icmp.rewrite_5tuple(None, None, Some(1), None),
+            Err(PacketError::MissingHeader(_))
+        ));
+        icmp.rewrite_5tuple(ip("10.9.9.9"), None, None, None)
+            .unwrap();
+        assert!(icmp.verify_checksums().is_ok());
+        let mut eth = Packet::new();
+        eth.push(Ether::new());
+        assert!(eth.rewrite_5tuple(None, None, None, None).is_err());
+    }
+    #[test]
+    #[allow(clippy::unused_unit)]
+    fn header_defaults_test() {
+        // field defaults give the bytes of the default vectors they replaced
+        let cases: Vec<(Vec<u8>, Vec<u8>)> = vec![
+            (
+                Ether::new().to_vec(),
+                vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 8, 0],
+            ),
+            (Vlan::new().to_vec(), vec![0, 10, 8, 0]),
+            (
+                IPv4::new().to_vec(),
+                vec![
+                    69, 0, 0, 20, 0, 51, 64, 221, 64, 6, 184, 128, 192, 168, 0, 1, 192, 168, 0, 2,
+                ],
+            ),
+            (
+                IPv6::new().to_vec(),
+                vec![
+                    96, 0, 0, 0, 0, 46, 6, 64, 32, 1, 13, 184, 133, 163, 0, 0, 0, 0, 138, 46, 3,
+                    112, 115, 52, 32, 1, 13, 184, 133, 163, 0, 0, 0, 0, 138, 46, 3, 112, 115, 53,
+                ],
+            ),
+            (Igmp::new().to_vec(), vec![17, 100, 238, 155, 0, 0, 0, 0]),
+            (
+                IgmpV3Report::new().to_vec(),
+                vec![34, 0, 221, 255, 0, 0, 0, 0],
+            ),
+            (
+                Mld::new().to_vec(),
+                vec![
+                    130, 0, 0, 0, 39, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                ],
+            ),
+            (MldV2Report::new().to_vec(), vec![143, 0, 0, 0, 0, 0, 0, 0]),
+            (
+                TCP::new().to_vec(),
+                vec![
+                    4, 210, 0, 80, 0, 0, 0, 0, 0, 0, 0, 0, 80, 2, 32, 0, 0, 0, 0, 0,
+                ],
+            ),
+
+            (UDP::new().to_vec(), vec![4, 210, 0, 80, 0, 0, 0, 0]),
+            (UdpLite::new().to_vec(), vec![4, 210, 0, 80, 0, 0, 0, 0]),
+            (
+                ARP::new().to_vec(),
+                vec![
+                    0, 1, 8, 0, 6, 4, 0, 1, 0, 1, 2, 3, 4, 5, 10, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0,
+                    0, 0,
+                ],
+            ),
+            (Vxlan::new().to_vec(), vec![8, 0, 0, 0, 0, 7, 208, 0]),
+            (
+                Dot3::new().to_vec(),
+                vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0, 0],
+            ),
+            (LLC::new().to_vec(), vec![0, 0, 0]),
+            (SNAP::new().to_vec(), vec![0, 0, 0, 0, 0]),
+            (GRE::new().to_vec(), vec![0, 0, 0, 0]),
+            (GREChksumOffset::new().to_vec(), vec![0, 0, 0, 0]),
+            (GRESequenceNum::new().to_vec(), vec![0, 0, 0, 0]),
+            (GREKey::new().to_vec(), vec![0, 0, 0, 0]),
+            (ERSPAN2::new().to_vec(), vec![16, 0, 0, 0, 0, 0, 0, 0]),
+            (
+                ERSPAN3::new().to_vec(),
+                vec![32, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            ),
+            (ERSPANPLATFORM::new().to_vec(), vec![0, 0, 0, 0, 0, 0, 0, 0]),
+            (
+                STP::new().to_vec(),
+                vec![
+                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                    0, 0, 1, 0, 20, 0, 2, 0, 15,
+                ],
+            ),
+            (MPLS::new().to_vec(), vec![0, 0, 0, 0]),
+        ];
+        for (i, (new, old)) in cases.iter().enumerate() {
+            assert_eq!(new, old, "case {}", i);
+        }
+        // the old ICMP vector was 2 bytes longer than the header, they are no longer added
+        assert_eq!(ICMP::new().to_vec(), vec![8, 0, 0, 0]);
+        // the stale IPv4 checksum is computed instead
+        assert_eq!(IPv4::new().header_checksum(), 0xb880);
+
+        {
+            // a header named IPv4 of the application, its checksum found by its flag
+            make_header!(
+            IPv4 4
+            (
+                kind: 0-7 = 1,
+                sum: 8-23 [checksum],
+                ttl: 24-31 = 64
+            )
+            );
+            assert_eq!(IPv4::new().sum(), 0xfebf);
+            make_header!(
+            Tagged 4
+            (
+                kind: 0-7 = 1,
+                sum: 8-23 [checksum] = 0x1234,
+                ttl: 24-31 = 64
+            )
+            );
+            assert_eq!(Tagged::new().sum(), 0x1234);
+            make_header!(
+            Stamped 4
+            (
+                kind: 0-7,
+                sum: 8-23 [checksum],
+                ttl: 24-31
+            )
+            vec![1, 0xab, 0xcd, 64]
+            );
+            assert_eq!(Stamped::new().sum(), 0xabcd);
+        }
+    }
+    #[test]
+    fn from_fields_test() {
+        let ipv4 =
+            IPv4::from_fields(&[("ttl", 128), ("protocol", 17), ("src", 0x0a000001)]).unwrap();
+        assert_eq!(ipv4.ttl(), 128);
+        assert_eq!(ipv4.protocol(), 17);
+        assert_eq!(ipv4.src(), 0x0a000001);
+        // the fields not named keep their defaults
+        assert_eq!(ipv4.dst(), IPv4::new().dst());
+        assert_eq!(ipv4.ihl(), 5);
+        assert_eq!(
+            IPv4::from_fields(&[]).unwrap().to_vec(),
+            IPv4::new().to_vec()
+        );
+        assert!(matches!(
+            Vlan::from_fields(&[("vid", 100), ("vlan", 1)]),
+            Err(PacketError::UnknownField { .. })
+        ));
+        assert!(matches!(
+            Vlan::from_fields(&[("vid", 0x1000)]),
+            Err(PacketError::ValueOverflow { .. })
+        ));
+    }
+    #[test]
+    #[allow(clippy::unused_unit)]
+    fn byte_field_test() {
+        make_header!(
+        Boot 84
+        (
+            op: 0-7 = 1,
+            unused: 8-23 as pad,
+            hops: 24-31,
+            chaddr: 32-159 as bytes,
+            sname: 160-671 as bytes
+        )
+        );
+        let mut boot = Boot::new();
+        assert_eq!(boot.op(), 1);
+        assert_eq!(boot.chaddr(), vec![0; 16]);
+        boot.set_chaddr(&[0, 1, 2, 3, 4, 5]);
+        assert_eq!(boot.chaddr()[..7], [0, 1, 2, 3, 4, 5, 0]);
+        boot.set_sname(b"boot.example");
+        assert!(matches!(
+            boot.try_set_chaddr(&[0; 17]),
+            Err(PacketError::InvalidValue { .. })
+        ));
+        // padding has no accessors and is written as zero
+        let names: Vec<_> = Boot::FIELDS.iter().map(|f| f.name).collect();
+        assert_eq!(names, vec!["op", "hops", "chaddr", "sname"]);
+        assert_eq!(Boot::chaddr_byte_range(), Some(4..20));
+        let mut raw = boot.to_vec();
+        raw[1] = 0xff;
+        assert_eq!(Boot::from(raw.clone()).to_vec()[1], 0);
+        let slice = BootSlice::from(&raw);
+        assert_eq!(&slice.sname()[..4], b"boot");
+        assert_eq!(Header::to_vec(&slice)[1], 0);
+
+        let table = boot.field_table();
+        assert!(!table.contains("unused"));
+        assert!(table.contains("chaddr              :  128 : 00 01 02 03 04 05 00"));
+        // long byte fields are cut short
+        let sname = table.lines().find(|l| l.starts_with("sname")).unwrap();
+        assert!(sname.starts_with("sname               :  512 : 62 6f 6f 74 2e"));
+        assert!(sname.ends_with("..."));
+        let hex = sname.rsplit(" : ").next().unwrap();
+        assert_eq!(hex.split_whitespace().count(), 16 + 1);
+    }
+    #[test]
+    fn checksum_roundtrip_test() {
+        use packet_rs::testutil::assert_checksum_roundtrips;
+        assert_eq!(assert_checksum_roundtrips(&IPv4::new()), 0xb880);
+        assert_eq!(assert_checksum_roundtrips(&Igmp::new()), 0xee9b);
+        assert_eq!(assert_checksum_roundtrips(&ICMP::new()), 0xf7ff);
+        // a stale checksum in the header does not change the one computed
+        let mut ipv4 = IPv4::new();
+        ipv4.set_header_checksum(0x1234);
+        ipv4.set_src(0x0a000001);
+        let chksum = assert_checksum_roundtrips(&ipv4);
+        let mut pkt = Packet::new();
+        pkt.push(ipv4);
+        pkt.update_checksums();
+        assert_eq!(
+            pkt.layer::<IPv4>().unwrap().header_checksum(),
+            chksum as u64
+        );
+
+        let panic = std::panic::catch_unwind(|| assert_checksum_roundtrips(&Ether::new()));
+        let msg = *panic.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(msg, "Ether has no checksum field");
+
+        // a header borrowing its bytes is checked as its owned copy
+        let v = IPv4::new().to_vec();
+        let slice = IPv4Slice::from(&v[..]);
+        assert_eq!(assert_checksum_roundtrips(&slice), 0xb880);
+
+        // the transport checksums cover the pseudo header of the IP layer before them
+        use packet_rs::testutil::assert_layer_checksum_roundtrips;
+        let cfg = builders::PacketConfig {
+            payload_len: 7,
+            ..Default::default()
+        };
+        let tcp = builders::create_tcp_packet(&cfg);
+        assert_eq!(
+            assert_layer_checksum_roundtrips(&tcp, 2) as u64,
+            tcp.layer::<TCP>().unwrap().checksum()
+        );
+        let tcpv6 = builders::create_ipv6_tcp_packet(&cfg);
+        assert_eq!(
+            assert_layer_checksum_roundtrips(&tcpv6, 2) as u64,
+            tcpv6.layer::<TCP>().unwrap().checksum()
+        );
+        let udp = builders::create_udp_packet(&cfg);
+        assert_eq!(
+            assert_layer_checksum_roundtrips(&udp, 2) as u64,
+            udp.layer::<UDP>().unwrap().checksum()
+        );
+        let mut icmpv6 = Packet::new();
+        icmpv6.push(IPv6::new());
+        icmpv6.push(Packet::icmp(128, 0));
+        icmpv6.set_payload(b"ping");
+        icmpv6.fixup();
+        assert_eq!(
+            assert_layer_checksum_roundtrips(&icmpv6, 1) as u64,
+            icmpv6.layer::<ICMP>().unwrap().chksum()
+        );
+        let mut mld = Packet::new();
+        mld.push(IPv6::new());
+        mld.push(Mld::new());
+        mld.fixup();
+        assert_eq!(
+            assert_layer_checksum_roundtrips(&mld, 1) as u64,
+            mld.layer::<Mld>().unwrap().checksum()
+        );
+        // the same ICMP message over IPv4 has no pseudo header
+        let mut icmp = icmpv6.clone();
+        icmp[0] = IPv4::new().to_owned();
+        icmp.fixup();
+        assert_ne!(
+            assert_layer_checksum_roundtrips(&icmp, 1) as u64,
+            icmpv6.layer::<ICMP>().unwrap().chksum()
+        );
+        let panic = std::panic::catch_unwind(|| assert_checksum_roundtrips(&TCP::new()));
+        let msg = *panic.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            msg,
+            "TCP checksum covers a pseudo header, no IP layer before it"
+        );
+    }
+    #[test]
+    #[allow(clippy::unused_unit)]
+    fn little_endian_field_test() {
+        make_header!(
+        Meta 12
+        (
+            seq: 0-31,
+            stamp: 32-63 le,
+            queue: 64-79 le enum { 1 => HIGH } = 1,
+            port: 80-95
+        )
+        );
+        let mut meta = Meta::new();
+        meta.set_seq(0x01020304);
+        meta.set_stamp(0x01020304);
+        let v = meta.to_vec();
+        assert_eq!(v[0..4], [1, 2, 3, 4]);
+        assert_eq!(v[4..8], [4, 3, 2, 1]);
+        assert_eq!(meta.stamp(), 0x01020304);
+        // the default is little endian too
+        assert_eq!(v[8..10], [1, 0]);
+        assert_eq!(meta.queue(), 1);
+        assert_eq!(meta.queue_name(), Some("HIGH"));
+        assert!(meta.field_table().contains("01 00  (HIGH)"));
+
+        // reflection uses the host order
+        assert_eq!(meta.get_field("stamp"), Some(0x01020304));
+        assert_eq!(meta.get_field_bytes("stamp"), Some(vec![1, 2, 3, 4]));
+        assert!(meta.set_field("queue", 0x0203));
+        assert_eq!(meta.to_vec()[8..10], [3, 2]);
+        assert!(meta.set_field_bytes("stamp", &[0xaa, 0xbb]));
+        assert_eq!(meta.to_vec()[4..8], [0xbb, 0xaa, 0, 0]);
+        assert!(Meta::FIELDS[1].little_endian);
+        assert!(!Meta::FIELDS[0].little_endian);
+
+        let slice_bytes = meta.to_vec();
+        let slice = MetaSlice::from(&slice_bytes);
+        assert_eq!(slice.stamp(), 0xaabb);
+        assert_eq!(slice.queue(), 0x0203);
+    }
+    #[test]
+    fn l2tp_test() {
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(IPv4::new());
+        pkt.push(UDP::new());
+        pkt.push(L2tp::new());
+        pkt.push(L2tpLength::new());
+        let mut tunnel = L2tpTunnel::new();
+        tunnel.set_tunnel_id(7);
+        tunnel.set_session_id(9);
+        pkt.push(tunnel);
+        pkt.push(L2tpSequence::new());
+        pkt.set_payload(&[0xff, 0x03, 0x00, 0x21]);
+        // the port and the flags of the present fields are set from the layers
+        let v = pkt.to_vec_with_fixup();
+        let udp = Ether::size() + IPv4::size();
+        assert_eq!(v[udp + 2..udp + 4], [0x06, 0xa5]);
+        assert_eq!(v[udp + 8..udp + 10], [0x48, 0x02]);
+
+        let parsed = parser::slow::parse(&v);
+        let names: Vec<_> = (3..7).map(|i| parsed[i].name().to_string()).collect();
+        assert_eq!(
+            names,
+            vec!["L2tp", "L2tpLength", "L2tpTunnel", "L2tpSequence"]
+        );
+        assert_eq!(parsed.layer::<L2tpTunnel>().unwrap().session_id(), 9);
+        assert_eq!(parsed.to_vec(), v);
+        let dump = parser::fast::parse(&v).dump();
+        assert!(dump.contains("L2tpSequence"));
+        assert!(!dump.contains("L2tpOffset"));
+        assert_eq!(parser::fast::parse(&v).to_vec(), v);
+
+        // a data message without the optional fields
+        let mut l2tp = L2tp::new();
+        l2tp.set_o(1);
+        let mut bytes = l2tp.to_vec();
+        bytes.extend([0, 1, 0, 2, 0, 0, 0xff]);
+        let parsed = parser::slow::parse_from("L2tp", &bytes);
+        assert!(parsed.layer::<L2tpLength>().is_none());
+        assert_eq!(parsed.layer::<L2tpTunnel>().unwrap().tunnel_id(), 1);
+        assert_eq!(parsed.layer::<L2tpOffset>().unwrap().offset_size(), 0);
+        assert_eq!(parsed.len(), bytes.len());
+
+        // a version 3 data message carries its session id only
+        let mut l2tp = L2tp::new();
+        l2tp.set_version(3);
+        let mut bytes = l2tp.to_vec();
+        bytes.extend([0, 0, 0, 0, 0x12, 0x34]);
+        let parsed = parser::slow::parse_from("L2tp", &bytes);
+        assert!(parsed.layer::<L2tpTunnel>().is_none());
+        assert_eq!(
+            parsed.layer::<L2tpV3Session>().unwrap().session_id(),
+            0x1234
+        );
+
+        // truncated options are left as payload
+        let short = parser::slow::parse_from("L2tp", &v[udp + 8..udp + 12]);
+        assert!(short.layer::<L2tp>().is_none());
+    }
+    #[test]
+    #[allow(clippy::unused_unit)]
+    fn signed_field_test() {
+        make_header!(
+        Timing 16
+        (
+            precision: 0-7 signed,
+            offset: 8-27 signed,
+            flags: 28-31,
+            correction: 32-95 signed fixed(16),
+            gain: 96-111 le signed fixed(2),
+            ratio: 112-127 fixed(8)
+        )
+        );
+        let mut t = Timing::new();
+        t.set_precision(-20);
+        assert_eq!(t.precision(), -20);
+        assert_eq!(t.precision_raw(), 0xec);
+        t.set_precision(127);
+        assert_eq!(t.precision(), 127);
+        // a 20 bit field that is not byte aligned
+        t.set_flags(0xf);
+        t.set_offset(-1);
+        assert_eq!(t.offset(), -1);
+        assert_eq!(t.offset_raw(), 0xfffff);
+        t.set_offset(-(1 << 19));
+        assert_eq!(t.offset(), -(1 << 19));
+        assert_eq!(t.to_vec()[1..4], [0x80, 0x00, 0x0f]);
+        assert_eq!(t.flags(), 0xf);
+        assert!(matches!(
+            t.try_set_offset(1 << 19),
+            Err(PacketError::InvalidValue { .. })
+        ));
+        assert!(t.try_set_offset(-(1 << 19) - 1).is_err());
+        assert_eq!(t.offset(), -(1 << 19));
+
+        // 64 bit signed fixed point, nanoseconds times 2^16
+        t.set_correction(-3 << 16);
+        assert_eq!(t.correction_f64(), -3.0);
+        t.set_correction_f64(-1.5);
+        assert_eq!(t.correction(), -(3 << 15));
+        t.set_correction(i64::MIN);
+        assert_eq!(t.correction(), i64::MIN);
+
+        // little endian signed fixed point
+        t.set_gain_f64(-0.25);
+        assert_eq!(t.gain(), -1);
+        assert_eq!(t.to_vec()[12..14], [0xff, 0xff]);
+        t.set_gain_f64(2.6);
+        assert_eq!(t.gain(), 10);
+        assert_eq!(t.to_vec()[12..14], [0x0a, 0x00]);
+        assert!(t.try_set_gain_f64(8192.0).is_err());
+        assert!(t.try_set_gain_f64(f64::NAN).is_err());
+
+        // unsigned fixed point
+        t.set_ratio_f64(1.5);
+        assert_eq!(t.ratio(), 0x180);
+        assert!(t.try_set_ratio_f64(-1.0).is_err());
+        assert!(t.try_set_ratio_f64(256.0).is_err());
+
+        let table = t.field_table();
+        assert!(table.contains("precision           :    8 : 7f (127)"));
+        assert!(table.contains(" (-524288)"));
+        assert!(table.contains(" (2.5)"));
+        assert!(table.contains(" (1.5)"));
+        let slice_bytes = t.to_vec();
+        let slice = TimingSlice::from(&slice_bytes);
+        assert_eq!(slice.offset(), -(1 << 19));
+        assert_eq!(slice.gain_f64(), 2.5);
+    }
+    #[test]
+    fn eq_ignoring_test() {
+        let ignore = [("IPv4", "identification"), ("IPv4", "header_checksum")];
+        let pkt = test_tcp_packet();
+        let mut other = pkt.clone();
+        let ipv4 = other.layer_mut::<IPv4>().unwrap();
+        ipv4.set_identification(0x1234);
+        ipv4.set_header_checksum(0xbeef);
+        assert!(pkt.eq_ignoring(&other, &ignore));
+        assert!(!pkt.eq_ignoring(&other, &ignore[..1]));
+        assert!(!pkt.eq_ignoring(&other, &[]));
+        assert!(pkt.eq_ignoring(&pkt.clone(), &[]));
+
+        // a pair only skips the field of its layer
+        let mut ports = other.clone();
+        ports.layer_mut::<TCP>().unwrap().set_src(1);
+        assert!(!pkt.eq_ignoring(&ports, &ignore));
+        assert!(!pkt.eq_ignoring(&ports, &[("IPv4", "src")]));
+
+        let mut payload = other.clone();
+        payload.set_payload(b"x");
+        assert!(!pkt.eq_ignoring(&payload, &ignore));
+        let mut layers = other.clone();
+        layers.pop();
+        assert!(!pkt.eq_ignoring(&layers, &ignore));
+        assert!(!layers.eq_ignoring(&pkt, &ignore));
+    }
+    #[test]
+    fn compare_hex_test() {
+        let pkt = test_tcp_packet();
+        let hex = |v: &[u8]| v.iter().map(|x| format!("{:02x} ", x)).collect::<String>();
+        let golden = pkt.to_vec();
+        assert_eq!(pkt.compare_hex(&hex(&golden)), Ok(()));
+        assert_eq!(pkt.compare_hex(&hex(&golden).replace(' ', "\n")), Ok(()));
+
+        // the first differing byte, the field it belongs to and both values
+        let mut ttl = golden.clone();
+        ttl[22] = 0x3f;
+        ttl[40] ^= 0xff;
+        assert_eq!(
+            pkt.compare_hex(&hex(&ttl)).unwrap_err(),
+            format!(
+                "byte 22 differs (IPv4.ttl): expected 0x3f, got {:#04x}",
+                golden[22]
+            )
+        );
+        let mut payload = golden.clone();
+        *payload.last_mut().unwrap() ^= 1;
+        let err = pkt.compare_hex(&hex(&payload)).unwrap_err();
+        assert!(err.starts_with(&format!("byte {} differs: ", golden.len() - 1)));
+
+        // lengths, when one is a prefix of the other
+        assert_eq!(
+            pkt.compare_hex(&hex(&golden[..20])).unwrap_err(),
+            format!("length differs: expected 20 bytes, got {}", golden.len())
+        );
+        let longer = format!("{}00", hex(&golden));
+        assert!(pkt
+            .compare_hex(&longer)
+            .unwrap_err()
+            .starts_with("length differs"));
+        assert!(pkt.compare_hex("0").is_err());
+        assert!(pkt.compare_hex("zz").is_err());
+    }
+    #[test]
+    fn typed_field_test() {
+        let mut arp = ARP::new();
+        assert_eq!(arp.opcode_typed(), Ok(ArpOp::Request));
+        arp.set_opcode_typed(ArpOp::Reply);
+        assert_eq!(arp.opcode(), 2);
+        assert_eq!(arp.opcode_name(), Some("REPLY"));
+        assert_eq!(ArpOp::try_from(1), Ok(ArpOp::Request));
+        assert_eq!(u64::from(ArpOp::Reply), 2);
+        assert_eq!(ArpOp::Reply.to_string(), "REPLY");
+        assert_eq!(IcmpType::DestUnreachable.to_string(), "DEST_UNREACHABLE");
+
+        // an unknown value is kept as is
+        arp.set_opcode(9);
+        assert_eq!(arp.opcode_typed(), Err(9));
+        assert_eq!(arp.opcode_name(), None);
+        let v = arp.to_vec();
+        assert_eq!(v[6..8], [0, 9]);
+        let parsed = ARP::from(v.clone());
+        assert_eq!(parsed.opcode_typed(), Err(9));
+        assert_eq!(parsed.to_vec(), v);
+        assert_eq!(ARPSlice::from(&v).opcode_typed(), Err(9));
+
+        let mut icmp = ICMP::new();
+        assert_eq!(icmp.icmp_type_typed(), Ok(IcmpType::EchoRequest));
+        icmp.set_icmp_type_typed(IcmpType::TimeExceeded);
+        assert_eq!(icmp.icmp_type(), 11);
+        let table = icmp.field_table();
+        assert!(table
+            .lines()
+            .any(|l| l.starts_with("icmp_type") && l.ends_with("(TIME_EXCEEDED)")));
+        icmp.set_icmp_type(42);
+        assert_eq!(icmp.icmp_type_typed(), Err(42));
+        assert_eq!(IcmpType::try_from(42), Err(42));
+    }
+    #[test]
+    fn swap_field_bytes_test() {
+        let mut pkt = builders::create_udp_packet(&Default::default());
+        pkt["Ether"].swap_field_bytes("etype").unwrap();
+        assert_eq!(pkt["Ether"].get_field("etype"), Some(0x0008));
+        pkt["Ether"].swap_field_bytes("etype").unwrap();
+        assert_eq!(pkt["Ether"].get_field("etype"), Some(0x0800));
+
+        let mut ipv4 = IPv4::new();
+        ipv4.set_src(0x0a000001);
+        ipv4.swap_field_bytes("src").unwrap();
+        assert_eq!(ipv4.src(), 0x0100000a);
+        assert_eq!(ipv4.to_vec()[12..16], [1, 0, 0, 10]);
+        assert!(matches!(
+            ipv4.swap_field_bytes("flags"),
+            Err(PacketError::MisalignedRange { msb: 50, lsb: 48 })
+        ));
+        assert!(matches!(
+            ipv4.swap_field_bytes("nope"),
+            Err(PacketError::UnknownField { .. })
+        ));
+    }
+    #[test]
+    fn flags_field_test() {
+        let mut tcp = TCP::new();
+        assert!(tcp.syn() && !tcp.ack());
+        assert_eq!(tcp.flags_flags(), TCPFlags::SYN);
+        tcp.set_ack(true);
+        assert_eq!(tcp.flags(), 0x12);
+        assert_eq!(tcp.flags_flags(), TCPFlags::SYN | TCPFlags::ACK);
+        assert_eq!(tcp.flags_flags().to_string(), "ACK|SYN");
+
+        let mut flags = tcp.flags_flags();
+        flags.remove(TCPFlags::SYN);
+        flags.insert(TCPFlags::FIN | TCPFlags::CWR);
+        tcp.set_flags_flags(flags);
+        assert_eq!(tcp.flags(), 0x91);
+        assert!(tcp.cwr() && tcp.ack() && tcp.fin() && !tcp.syn());
+        tcp.set_cwr(false);
+        assert_eq!(tcp.flags_flags(), TCPFlags::ACK | TCPFlags::FIN);
+        assert_eq!(u64::from(tcp.flags_flags()), 0x11);
+        assert!(tcp.flags_flags().contains(TCPFlags::FIN));
+        assert_eq!(TCPFlags::from_bits(0x1ff), TCPFlags::from_bits(0xff));
+        assert_eq!(TCPFlags::empty().to_string(), "");
+
+        let table = tcp.field_table();
+        assert!(table
+            .lines()
+            .any(|l| l.starts_with("flags") && l.ends_with("11 (ACK|FIN)")));
+        let v = tcp.to_vec();
+        let slice = TCPSlice::from(&v);
+        assert!(slice.ack() && !slice.rst());
+        assert_eq!(slice.flags_flags(), tcp.flags_flags());
+    }
+    #[test]
+    fn qos_accessors_test() {
+        let mut ipv4 = IPv4::new();
+        ipv4.set_diffserv(0xb9);
+        assert_eq!(ipv4.dscp(), 46);
+        assert_eq!(ipv4.ecn(), 1);
+        ipv4.set_dscp(10);
+        assert_eq!(ipv4.diffserv(), 0x29);
+        ipv4.set_ecn(3);
+        assert_eq!(ipv4.diffserv(), 0x2b);
+        assert_eq!(ipv4.dscp(), 10);
+        ipv4.set_dscp(0xff);
+        assert_eq!(ipv4.dscp(), 63);
+        assert_eq!(ipv4.ecn(), 3);
+
+        ipv4.set_header_checksum(Packet::ipv4_checksum(&ipv4.to_vec()) as u64);
+        ipv4.set_ttl(2);
+        ipv4.set_header_checksum(Packet::ipv4_checksum(&ipv4.to_vec()) as u64);
+        assert_eq!(ipv4.decrement_ttl(), Ok(1));
+        assert_eq!(
+            ipv4.header_checksum(),
+            Packet::ipv4_checksum(&ipv4.to_vec()) as u64
+        );
+        assert_eq!(ipv4.decrement_ttl(), Ok(0));
+        assert_eq!(
+            ipv4.header_checksum(),
+            Packet::ipv4_checksum(&ipv4.to_vec()) as u64
+        );
+        assert_eq!(ipv4.decrement_ttl(), Err(PacketError::TtlExceeded));
+        assert_eq!(ipv4.ttl(), 0);
+
+        let mut vlan = Vlan::new();
+        vlan.set_priority(5);
+        assert_eq!(vlan.priority(), 5);
+        assert_eq!(vlan.pcp(), 5);
+        assert_eq!(vlan.vid(), 10);
+        assert_eq!(vlan.to_vec()[0] >> 5, 5);
+    }
+    #[test]
+    #[allow(deprecated)]
+    fn renamed_field_test() {
+        let mut ipv4 = IPv4::new();
+        assert_eq!(ipv4.frag_offset(), 221);
+        assert_eq!(ipv4.frag_startset(), ipv4.frag_offset());
+        ipv4.set_frag_startset(100);
+        assert_eq!(ipv4.frag_offset(), 100);
+        ipv4.set_frag_offset(7);
+        assert_eq!(ipv4.frag_startset(), 7);
+        let v = ipv4.to_vec();
+        assert_eq!(IPv4Slice::from(&v).frag_startset(), 7);
+
+        // both names are accepted by the lookups by name, the new one is shown
+        assert_eq!(ipv4.get_field("frag_startset"), Some(7));
+        assert!(ipv4.set_field("frag_startset", 9));
+        assert_eq!(ipv4.get_field("frag_offset"), Some(9));
+        assert!(ipv4.try_set_field("frag_startset", 1 << 13).is_err());
+        assert_eq!(ipv4.field_byte_range("data_startset"), None);
+        assert!(ipv4
+            .field_table()
+            .lines()
+            .any(|l| l.starts_with("frag_offset")));
+        assert!(!ipv4.field_table().contains("startset"));
+        let info = IPv4::FIELDS
+            .iter()
+            .find(|f| f.name == "frag_offset")
+            .unwrap();
+        assert_eq!(info.alias, Some("frag_startset"));
+        assert!(info.is_named("frag_startset") && info.is_named("frag_offset"));
+
+        let mut tcp = TCP::new();
+        assert_eq!(tcp.data_startset(), tcp.data_offset());
+        tcp.set_data_startset(6);
+        assert_eq!(tcp.data_offset(), 6);
+        assert_eq!(tcp.get_field("data_startset"), tcp.get_field("data_offset"));
+    }
+    #[test]
+    fn to_rust_code_test() {
+        let mut pkt = builders::create_tcp_packet(&PacketConfig {
+            tcp_flags: 0x12,
+            ..Default::default()
+        });
+        pkt.layer_mut::<IPv4>().unwrap().set_ttl(1);
+        pkt.update_checksums();
+        pkt.set_payload(&[0xaa; 20]);
+        let code = pkt.to_rust_code("pkt");
+        assert_eq!(
+            code,
+            r#"let mut pkt = Packet::new();
+{
+    let mut ether = Ether::new();
+    ether.set_src(0x60708090a);
+    pkt.push(ether);
+}
+{
+    let mut ipv4 = IPv4::new();
+    ipv4.set_identification(1);
+    ipv4.set_flags(0);
+    ipv4.set_frag_offset(0);
+    ipv4.set_ttl(1);
+    pkt.push(ipv4);
+}
+{
+    let mut tcp = TCP::new();
+    tcp.set_seq_no(0x1);
+    tcp.set_flags(18);
+    pkt.push(tcp);
+}
+pkt.set_payload(b"\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\
+    \xaa\xaa\xaa\xaa");
+pkt.fixup();
+pkt[1].set_field("total_len", 40);
+pkt[1].set_field("header_checksum", 0x387c);
+pkt[2].set_field("checksum", 0x95c);
+"#
+        );
+
+        // the code above rebuilds the same bytes
+        let expected = pkt;
+        let mut pkt = Packet::new();
+        {
+            let mut ether = Ether::new();
+            ether.set_src(0x60708090a);
+            pkt.push(ether);
+        }
+        {
+            let mut ipv4 = IPv4::new();
+            ipv4.set_identification(1);
+            ipv4.set_flags(0);
+            ipv4.set_frag_offset(0);
+            ipv4.set_ttl(1);
+            pkt.push(ipv4);
+        }
+        {
+            let mut tcp = TCP::new();
+            tcp.set_seq_no(0x1);
+            tcp.set_flags(18);
+            pkt.push(tcp);
+        }
+        pkt.set_payload(
+            b"\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\
+            \xaa\xaa\xaa\xaa",
+        );
+        pkt.fixup();
+        pkt[1].set_field("total_len", 40);
+        pkt[1].set_field("header_checksum", 0x387c);
+        pkt[2].set_field("checksum", 0x95c);
+        assert_eq!(pkt.to_vec(), expected.to_vec());
+        assert_eq!(pkt.to_rust_code("pkt"), code);
+
+        // a layer without a header of the crate is kept as bytes with the layers after it, a
+        // continued line of the payload does not start with a space
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(Tester::new());
+        pkt.push(UDP::new());
+        pkt.set_payload(b" quote \" and backslash \\ and a\n");
+        let code = pkt.to_rust_code("ether");
+        assert!(!code.contains("Tester") && !code.contains("UDP"));
+        let expected = pkt;
+        let mut ether = Packet::new();
+        {
+            let ether_hdr = Ether::new();
+            ether.push(ether_hdr);
+        }
+        ether.set_payload(
+            b"\xff\xff\xff\xff\xff\xff \x01\x0d\xb8\x85\xa3\xf0\xe0\xd0\xc0\
+            \x8a.\x03ps4Eg \x01\x0d\xb8\x85\xa3\x00\x00\
+            \x00\x00\x8a.\x03ps5\x04\xd2\x00P\x00\x00\x00\x00\
+            \x20quote \" and bac\
+            kslash \\ and a\x0a",
+        );
+        ether.fixup();
+        assert_eq!(ether.to_vec(), expected.to_vec());
+        assert_eq!(ether.to_rust_code("ether"), code);
+    }
+    #[test]
+    fn to_rust_code_fixture_test() {
+        // the fixtures are the code generated for frames of the capture, in a block
+        let reader = packet_rs::pcap::PcapReader::from_bytes(include_bytes!("data/filter.pcap"));
+        let records = reader.unwrap().into_iter().collect::<Vec<_>>();
+        let fixtures: [(usize, &str, Packet); 3] = [
+            (
+                7,
+                include_str!("data/filter_frame7.rs"),
+                include!("data/filter_frame7.rs"),
+            ),
+            (
+                8,
+                include_str!("data/filter_frame8.rs"),
+                include!("data/filter_frame8.rs"),
+            ),
+            (
+                10,
+                include_str!("data/filter_frame10.rs"),
+                include!("data/filter_frame10.rs"),
+            ),
+        ];
+        for (i, fixture, frame) in fixtures {
+            let pkt = &records[i].packet;
+            let code: String = pkt
+                .to_rust_code("frame")
+                .lines()
+                .map(|x| format!("    {}\n", x))
+                .collect();
+            assert_eq!(fixture, format!("{{\n{}    frame\n}}\n", code));
+            assert_eq!(frame.to_vec(), pkt.to_vec());
+        }
+    }
+    #[test]
+    #[allow(clippy::unused_unit)]
+    fn array_header_test() {
+        // well-known frames looked up by name, as in a firmware table
+        static ETHERS: [(&str, EtherArray); 2] = [
+            (
+                "broadcast",
+                EtherArray::from_array([
+                    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 1, 2, 3, 4, 5, 0x08, 0x06,
+                ]),
+            ),
+            (
+                "lldp",
+                EtherArray::from_array([
+                    0x01, 0x80, 0xc2, 0, 0, 0x0e, 0, 1, 2, 3, 4, 5, 0x88, 0xcc,
+                ]),
+            ),
+        ];
+        static PROBE: IPv4Array = IPv4Array::from_array([
+            0x45, 0, 0, 20, 0, 1, 0x40, 0, 1, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+        ]);
+        const LLDP_ETYPE: u64 = ETHERS[1].1.etype();
+        const PROBE_TTL: u64 = PROBE.ttl();
+        assert_eq!(LLDP_ETYPE, 0x88cc);
+        assert_eq!(PROBE_TTL, 1);
+
+        let find = |name: &str| ETHERS.iter().find(|x| x.0 == name).map(|x| x.1);
+        let bcast = find("broadcast").unwrap();
+        assert_eq!(bcast.dst(), 0xffffffffffff);
+        assert_eq!(bcast.etype(), 0x0806);
+        assert_eq!(bcast.to_header().to_vec(), bcast.as_array());
+        assert_eq!(bcast.as_slice().etype(), bcast.etype());
+        assert!(find("stp").is_none());
+
+        // sub byte and wide fields
+        assert_eq!(PROBE.flags(), 2);
+        assert_eq!(PROBE.protocol(), 17);
+        assert_eq!(PROBE.dst(), 0x0a000002);
+        let ipv6 = IPv6::new();
+        let ipv6 = IPv6Array::from_array(ipv6.to_vec().try_into().unwrap());
+        assert_eq!(ipv6.src(), IPv6::new().src_u128());
+        assert_eq!(ipv6.version(), 6);
+        const DST: u128 = IPv6Array::from_array([0xff; 40]).dst();
+        assert_eq!(DST, u128::MAX);
+        make_header!(
+        Wide 10
+        (
+            tag: 0-7,
+            value: 8-79 le
+        )
+        );
+        let bytes = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        assert_eq!(
+            WideArray::from_array(bytes).value(),
+            Wide::from(bytes.to_vec()).value_u128()
+        );
+        assert_eq!(
+            WideArray::from_array(bytes).value(),
+            0x0a0908070605040302
+        );
+        let tcp = TCP::new();
+        let array = TCPArray::from_array(tcp.to_vec().try_into().unwrap());
+        assert_eq!(array.data_offset(), tcp.data_offset());
+        assert_eq!(array.flags(), tcp.flags());
+    }
+    #[test]
+    fn cached_len_test() {
+        let mut pkt = Packet::new();
+        assert_eq!(pkt.len(), 0);
+        pkt.push(Ether::new());
+        assert_eq!(pkt.len(), 14);
+        pkt.push(IPv4::new());
+        pkt.insert(Vlan::new());
+        assert_eq!(pkt.len(), 38);
+        pkt.set_payload(&[0; 10]);
+        assert_eq!(pkt.len(), 48);
+        pkt.remove(0);
+        assert_eq!(pkt.len(), 44);
+        pkt.pop();
+        assert_eq!(pkt.len(), 24);
+        pkt[0] = IPv6::new().to_owned();
+        assert_eq!(pkt.len(), 50);
+        pkt.insert_after::<IPv6>(UDP::new(), false).unwrap();
+        assert_eq!(pkt.len(), 58);
+        pkt.remove_layer::<UDP>(false).unwrap();
+        assert_eq!(pkt.len(), 50);
+        pkt.extend(builders::create_udp_packet(&Default::default()));
+        assert_eq!(pkt.len(), 92);
+        assert_eq!(pkt.len(), pkt.to_vec().len());
+        assert_eq!(pkt.recompute_len(), 92);
+        assert_eq!(pkt.clone().len(), 92);
+
+        let mut pkt = builders::create_tcp_packet(&Default::default());
+        let len = pkt.len();
+        pkt.bytes_mut().truncate(len - 20);
+        assert_eq!(pkt.len(), len - 20);
+    }
+    #[test]
+    fn trailer_test() {
+        use packet_rs::trailer;
+        let mut pkt = builders::create_udp_packet(&Default::default());
+        let len = pkt.len();
+        let mut ts = HwTimestamp::new();
+        ts.set_seconds(7);
+        ts.set_nanoseconds(500);
+        pkt.push_trailer(Box::new(ts));
+        pkt.push_trailer(Box::new(EthFcs::new()));
+        assert_eq!(pkt.len(), len + 12);
+        let v = pkt.to_vec();
+        assert_eq!(v.len(), len + 12);
+        assert_eq!(v[len..], [0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 1, 0xf4]);
+        let mut frame = [0u8; 1514];
+        assert_eq!(pkt.write_into(&mut frame), Ok(len + 12));
+        assert_eq!(frame[..len + 12], v[..]);
+        assert!(pkt.is_well_formed());
+        assert!(pkt.dump().contains("HwTimestamp"));
+
+        // the lengths end with the payload
+        pkt.layer_mut::<IPv4>().unwrap().set_total_len(1);
+        pkt.layer_mut::<UDP>().unwrap().set_length(1);
+        pkt.update_lengths();
+        assert_eq!(pkt.layer::<IPv4>().unwrap().total_len() as usize, len - 14);
+        assert_eq!(pkt.layer::<UDP>().unwrap().length() as usize, len - 34);
+
+        let parsed = trailer::parse(&v, &[trailer::HW_TIMESTAMP, trailer::FCS]);
+        assert_eq!(parsed.trailer::<HwTimestamp>().unwrap().nanoseconds(), 500);
+        // the zero fcs is not claimed
+        assert!(parsed.trailer::<EthFcs>().is_none());
+        assert_eq!(parsed.trailers_len(), 8);
+        assert_eq!(parsed.to_vec(), v);
+        assert!(!parsed.eq_ignoring(&pkt, &[]));
+        let parsed = trailer::parse(&v, &[trailer::HW_TIMESTAMP32]);
+        assert_eq!(parsed.trailer::<HwTimestamp32>().unwrap().ticks(), 500);
+
+        let mut clone = pkt.clone();
+        assert!(clone.eq_ignoring(&pkt, &[]));
+        clone.trailer_mut::<HwTimestamp>().unwrap().set_seconds(8);
+        assert!(!clone.eq_ignoring(&pkt, &[]));
+        assert!(clone.eq_ignoring(&pkt, &[("HwTimestamp", "seconds")]));
+        clone.bytes_mut()[len - 1] = 0xff;
+        assert_eq!(clone.to_vec()[len - 1], 0xff);
+        assert_eq!(clone.trailers().count(), 2);
+        assert_eq!(clone.pop_trailer().unwrap().name(), "EthFcs");
+        assert_eq!(clone.len(), len + 8);
+        let (short, _) = pkt.clone_truncated(len + 2);
+        assert_eq!(short.to_vec(), v[..len + 2]);
+
+        // a trailer built shorter than its claim would drop bytes
+        const SHORT: trailer::Trailer = trailer::Trailer {
+            name: "Short",
+            claim: |_| Some(5),
+            from: |x| Box::new(HwTimestamp32::from(x[1..].to_vec())),
+        };
+        assert!(std::panic::catch_unwind(|| trailer::parse(&v, &[SHORT])).is_err());
+    }
+    #[test]
+    fn threaded_template_test() {
+        fn shareable<T: Send + Sync>() {}
+        shareable::<Packet>();
+        shareable::<Box<dyn Header>>();
+
+        let template = builders::create_udp_packet(&Default::default()).template();
+        let workers: Vec<_> = (0..8u64)
+            .map(|i| {
+                let template = template.clone();
+                std::thread::spawn(move || {
+                    let mut pkt = template.clone_cow();
+                    pkt.layer_mut::<UDP>().unwrap().set_src(2000 + i);
+                    pkt.layer_mut::<IPv4>().unwrap().set_ttl(32 + i);
+                    pkt.update_checksums();
+                    assert!(pkt.is_well_formed());
+                    pkt
+                })
+            })
+            .collect();
+        for (i, worker) in workers.into_iter().enumerate() {
+            let pkt = worker.join().unwrap();
+            assert_eq!(pkt.layer::<UDP>().unwrap().src(), 2000 + i as u64);
+            assert_eq!(pkt.len(), template.len());
+        }
+        // the template is left untouched
+        assert_eq!(template.layer::<UDP>().unwrap().src(), 1234);
+        assert_eq!(template.layer::<IPv4>().unwrap().ttl(), 64);
+    }
+    #[test]
+    fn layout_test() {
+        let mut pkt = builders::create_tcp_packet(&Default::default());
+        assert_eq!(pkt.field_offset::<IPv4>("protocol"), Some((184, 8)));
+        assert_eq!(
+            pkt.field_offset::<IPv4>("frag_offset"),
+            Some((14 * 8 + 51, 13))
+        );
+        assert_eq!(pkt.field_offset::<TCP>("syn"), None);
+        assert_eq!(pkt.field_offset::<UDP>("length"), None);
+        assert_eq!(pkt.to_vec()[23], 6);
+
+        // a vlan tag moves every following field by 4 bytes
+        pkt.insert_after::<Ether>(Vlan::new(), false).unwrap();
+        pkt.set_payload(&[0; 6]);
+        pkt.push_trailer(Box::new(HwTimestamp32::new()));
+        assert_eq!(pkt.field_offset::<IPv4>("protocol"), Some((216, 8)));
+        let layout = pkt.layout();
+        let names: Vec<_> = layout.layers.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["Ether", "Vlan", "IPv4", "TCP", "Payload", "HwTimestamp32"]
+        );
+        assert_eq!(layout.layers.last().unwrap().range.end, pkt.len());
+        let ttl = layout.field("IPv4", "ttl").unwrap();
+        assert_eq!((ttl.bit_offset, ttl.bits, ttl.bytes()), (208, 8, 26..27));
+        let vid = layout.field("Vlan", "vid").unwrap();
+        assert_eq!(vid.bytes(), 14..16);
+        let at: Vec<_> = layout.fields_in(26..28).map(|(l, f)| (l, f.name)).collect();
+        assert_eq!(at, [("IPv4", "ttl"), ("IPv4", "protocol")]);
+
+        let dump = layout.hexdump(&pkt.to_vec());
+        let lines: Vec<_> = dump.lines().collect();
+        assert_eq!(lines.len(), pkt.len().div_ceil(16));
+        assert!(lines[1].starts_with("0010  08 00 45 00 "));
+        assert!(lines[1].contains(" IPv4.ttl IPv4.protocol "));
+        assert!(lines.last().unwrap().ends_with("HwTimestamp32.ticks"));
+    }
+    #[test]
+    fn checksum_over_test() {
+        for pkt in [
+            builders::create_tcp_packet(&Default::default()),
+            builders::create_udp_packet(&Default::default()),
+            builders::create_ipv6_tcp_packet(&Default::default()),
+        ] {
+            let v = pkt.to_vec();
+            let (src, dst) = match pkt.layer::<IPv4>() {
+                Some(ip) => (
+                    ip.bytes(IPv4::src_msb(), IPv4::src_lsb()),
+                    ip.bytes(IPv4::dst_msb(), IPv4::dst_lsb()),
+                ),
+                None => {
+                    let ip = pkt.layer::<IPv6>().unwrap();
+                    (
+                        ip.bytes(IPv6::src_msb(), IPv6::src_lsb()),
+                        ip.bytes(IPv6::dst_msb(), IPv6::dst_lsb()),
+                    )
+                }
+            };
+            if let Some(tcp) = pkt.layer::<TCP>() {
+                let payload = &v[pkt.layer_offset::<TCP>().unwrap() + TCP::size()..];
+                assert_eq!(
+                    tcp.compute_checksum(&src, &dst, payload),
+                    tcp.checksum() as u16
+                );
+            }
+            if let Some(udp) = pkt.layer::<UDP>() {
+                let payload = &v[pkt.layer_offset::<UDP>().unwrap() + UDP::size()..];
+                assert_eq!(
+                    udp.compute_checksum(&src, &dst, payload),
+                    udp.checksum() as u16
+                );
+            }
+        }
+
+        // a pseudo header of its own, summing a tag before the segment
+        let mut udp = UDP::new();
+        let pseudo = [0x12, 0x34];
+        let segment = [&pseudo[..], &udp.to_vec(), b"abcd"].concat();
+        assert_eq!(
+            udp.checksum_over(&pseudo, b"abcd"),
+            ipv4_checksum_verify(&segment)
+        );
+        udp.set_checksum(0x5555);
+        assert_eq!(
+            udp.checksum_over(&pseudo, b"abcd"),
+            ipv4_checksum_verify(&segment)
+        );
+        let mut tcp = TCP::new();
+        let chksum = tcp.checksum_over(&[], &[]);
+        tcp.set_checksum(0);
+        assert_eq!(
+            chksum,
+            ipv4_checksum_verify(&tcp.to_vec())
+        );
+    }
+    #[test]
+    fn parse_options_test() {
+        use packet_rs::parser::{LayerKind, ParseOptions};
+        let inner = builders::create_tcp_packet(&Default::default());
+        let v = builders::create_vxlan_packet(&Default::default(), 2000, inner).to_vec();
+        let names = |pkt: &Packet| pkt.iter().map(|x| x.name().to_string()).collect::<Vec<_>>();
+
+        let full = parser::slow::parse(&v);
+        assert_eq!(full.len(), v.len());
+        let pkt = Packet::parse_with_options(&v, &Default::default());
+        assert_eq!(names(&pkt), names(&full));
+        assert!(pkt.raw().is_none());
+
+        let opts = ParseOptions {
+            max_layers: 2,
+            ..Default::default()
+        };
+        let pkt = Packet::parse_with_options(&v, &opts);
+        assert_eq!(names(&pkt), ["Ether", "IPv4"]);
+        assert_eq!(pkt.to_vec(), v);
+
+        let opts = ParseOptions {
+            stop_after: Some(LayerKind::Network),
+            ..Default::default()
+        };
+        let pkt = Packet::parse_with_options(&v, &opts);
+        assert_eq!(names(&pkt), ["Ether", "IPv4"]);
+
+        let opts = ParseOptions {
+            parse_tunnels: false,
+            ..Default::default()
+        };
+        let mut pkt = Packet::parse_with_options(&v, &opts);
+        assert_eq!(names(&pkt), ["Ether", "IPv4", "UDP", "Vxlan"]);
+        assert_eq!(pkt.to_vec(), v);
+
+        // one more layer, then the rest
+        let opts = ParseOptions {
+            max_layers: 1,
+            ..Default::default()
+        };
+        pkt.parse_more_with(&opts, packet_rs::bind::current());
+        assert_eq!(names(&pkt), ["Ether", "IPv4", "UDP", "Vxlan", "Ether"]);
+        pkt.parse_more(packet_rs::bind::current());
+        assert_eq!(names(&pkt), names(&full));
+        assert_eq!(pkt.to_vec(), v);
+
+        let opts = ParseOptions {
+            stop_after: Some(LayerKind::Transport),
+            keep_raw: true,
+            ..Default::default()
+        };
+        let mut pkt = Packet::parse_with_options(&v, &opts);
+        assert_eq!(pkt.raw(), Some(v.as_slice()));
+        assert_eq!(pkt.to_vec(), v);
+        pkt.layer_mut::<IPv4>().unwrap().set_ttl(1);
+        assert!(pkt.raw().is_none());
+        let out = pkt.to_vec();
+        assert_eq!(out.len(), v.len());
+        assert_eq!(out[22], 1);
+
+        // a header sharing its bytes may write behind the packet, the raw bytes are not used
+        let pkt = Packet::parse_with_options(&v, &opts);
+        let mut ip = IPv4::from(&pkt[1]);
+        ip.set_ttl(1);
+        assert!(pkt.raw().is_none());
+        assert_eq!(pkt.to_vec()[22], 1);
+        let mut out = vec![0; v.len()];
+        pkt.write_into(&mut out).unwrap();
+        assert_eq!(out[22], 1);
+        // copy-on-write clones share their bytes without writing to them
+        let pkt = Packet::parse_with_options(&v, &opts);
+        let mut clone = pkt.clone_cow();
+        clone.layer_mut::<IPv4>().unwrap().set_ttl(1);
+        assert_eq!(pkt.raw(), Some(v.as_slice()));
+        assert_eq!(pkt.to_vec(), v);
+    }
+    #[test]
+    fn dissect_with_test() {
+        use packet_rs::parser::DissectConfig;
+        let names = |pkt: &Packet| pkt.iter().map(|x| x.name().to_string()).collect::<Vec<_>>();
+        let v = builders::create_tcp_packet(&Default::default()).to_vec();
+
+        let pkt = Packet::dissect_with(&v, &Default::default());
+        assert_eq!(names(&pkt), ["Ether", "IPv4", "TCP"]);
+        let config = DissectConfig {
+            max_layers: 1,
+            ..Default::default()
+        };
+        let pkt = Packet::dissect_with(&v, &config);
+        assert_eq!(names(&pkt), ["Ether"]);
+        assert_eq!(pkt.to_vec(), v);
+        // the name may be borrowed from a string built at runtime
+        let layer = String::from("IPv4");
+        let config = DissectConfig {
+            stop_at: Some(&layer),
+            ..Default::default()
+        };
+        assert_eq!(names(&Packet::dissect_with(&v, &config)), ["Ether", "IPv4"]);
+        // a name absent from the packet dissects it all
+        let config = DissectConfig {
+            stop_at: Some("UDP"),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&Packet::dissect_with(&v, &config)),
+            ["Ether", "IPv4", "TCP"]
+        );
+
+        let mut pkt = Packet::new();
+        pkt.push(Ether::new());
+        pkt.push(IPv4::new());
+        pkt.push(GRE::new());
+        pkt.push(GREKey::new());
+        pkt.push(IPv4::new());
+        pkt.push(TCP::new());
+        let v = pkt.to_vec_with_fixup();
+        assert_eq!(
+            names(&Packet::dissect_with(&v, &Default::default())),
+            names(&pkt)
+        );
+        let config = DissectConfig {
+            parse_options: false,
+            ..Default::default()
+        };
+        let mut pkt = Packet::dissect_with(&v, &config);
+        assert_eq!(names(&pkt), ["Ether", "IPv4", "GRE"]);
+        assert_eq!(pkt.to_vec(), v);
+        pkt.parse_more(&packet_rs::bind::Bindings::new());
+        assert_eq!(
+            names(&pkt),
+            ["Ether", "IPv4", "GRE", "GREKey", "IPv4", "TCP"]
+        );
+    }
+    #[test]
+    fn compose_test() {
+        let names = |pkt: &Packet| pkt.iter().map(|x| x.name().to_string()).collect::<Vec<_>>();
+        let pkt = Ether::new() / Vlan::new() / IPv6::new() / UDP::new() / b"hi";
+        let golden = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x81, 0x00,
+            0x00, 0x0a, 0x86, 0xdd, 0x60, 0x00, 0x00, 0x00, 0x00, 0x2e, 0x11, 0x40, 0x20, 0x01,
+            0x0d, 0xb8, 0x85, 0xa3, 0x00, 0x00, 0x00, 0x00, 0x8a, 0x2e, 0x03, 0x70, 0x73, 0x34,
+            0x20, 0x01, 0x0d, 0xb8, 0x85, 0xa3, 0x00, 0x00, 0x00, 0x00, 0x8a, 0x2e, 0x03, 0x70,
+            0x73, 0x35, 0x04, 0xd2, 0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x68, 0x69,
+        ];
+        assert_eq!(pkt.to_vec(), golden);
+        assert_eq!(pkt.to_vec(), pkt.to_vec_with_fixup());
+
+        let pkt = Ether::new() / MPLS::new() / MPLS::new() / IPv4::new() / ICMP::new();
+        let golden = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x88, 0x47,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x45, 0x00, 0x00, 0x14, 0x00, 0x33,
+            0x40, 0xdd, 0x40, 0x01, 0xb8, 0x80, 0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0x02,
+            0x08, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(pkt.to_vec(), golden);
+
+        // the same bytes as the headers pushed and fixed up
+        let mut pushed = Packet::new();
+        pushed.push(Ether::new());
+        pushed.push(IPv4::new());
+        pushed.push(TCP::new());
+        pushed.set_payload(b"hello");
+        let pkt = Ether::new() / IPv4::new() / TCP::new() / b"hello".as_slice();
+        assert_eq!(pkt.to_vec(), pushed.to_vec_with_fixup());
+
+        // a tunnel embeds a packet, grouped stacks keep their order
+        let inner = IPv4::new() / UDP::new() / Vxlan::new() / (Ether::new() / IPv6::new());
+        let pkt =
+            Ether::new() / IPv6::new() / GRE::new() / GREKey::new() / GRESequenceNum::new() / inner;
+        assert_eq!(
+            names(&pkt),
+            [
+                "Ether",
+                "IPv6",
+                "GRE",
+                "GREKey",
+                "GRESequenceNum",
+                "IPv4",
+                "UDP",
+                "Vxlan",
+                "Ether",
+                "IPv6"
+            ]
+        );
+        let gre = pkt.layer::<GRE>().unwrap();
+        assert!(gre.key_present() && gre.seqnum_present() && !gre.chksum_present());
+        assert_eq!(gre.proto(), 0x0800);
+        assert_eq!(pkt.layer::<IPv6>().unwrap().next_hdr(), 47);
+        assert_eq!(pkt.layer::<UDP>().unwrap().dst(), 4789);
+        assert_eq!(pkt.layers::<Ether>()[1].etype(), 0x86dd);
+        assert_eq!(names(&parser::slow::parse(&pkt.to_vec())), names(&pkt));
+
+        // a poisoned field keeps its value across a join
+        let mut pkt = Ether::new() / Vlan::new();
+        pkt.layer_mut::<Vlan>().unwrap().set_etype(0x1234);
+        pkt.poison(1, "etype").unwrap();
+        let pkt = pkt / IPv4::new();
+        assert_eq!(pkt.layer::<Vlan>().unwrap().etype(), 0x1234);
+
+        // headers after a payload are bytes
+        let pkt = Ether::new() / b"ab".as_slice() / Vlan::new();
+        assert_eq!(names(&pkt), ["Ether"]);
+        assert_eq!(pkt.len(), Ether::size() + 2 + Vlan::size());
+        let pkt = (Ether::new() / b"ab") / (IPv4::new() / UDP::new());
+        assert_eq!(names(&pkt), ["Ether"]);
+        assert_eq!(pkt.len(), Ether::size() + 2 + IPv4::size() + UDP::size());
+    }
+    #[test]
+    fn parse_roundtrip_fuzz_test() {
+        use packet_rs::testutil::{
+            assert_fields_roundtrip, assert_parse_roundtrips, random_packet,
+        };
+        let names = [
+            "Ether",
+            "ARP",
+            "Vlan",
+            "ICMP",
+            "Igmp",
+            "IgmpV3Report",
+            "Mld",
+            "MldV2Report",
+            "IPv4",
+            "IPv6",
+            "UDP",
+            "UdpLite",
+            "TCP",
+            "Vxlan",
+            "L2tp",
+            "L2tpLength",
+            "L2tpTunnel",
+            "L2tpSequence",
+            "L2tpOffset",
+            "L2tpV3Session",
+            "Dot3",
+            "LLC",
+            "SNAP",
+            "STP",
+            "GRE",
+            "GREChksumOffset",
+            "GREKey",
+            "GRESequenceNum",
+            "ERSPAN2",
+            "ERSPAN3",
+            "ERSPANPLATFORM",
+            "MPLS",
+            "EthFcs",
+            "HwTimestamp",
+            "HwTimestamp32",
+        ];
+        for (seed, name) in names.iter().enumerate() {
+            assert_fields_roundtrip(header_from_name(name).unwrap().as_ref(), seed as u64);
+        }
+        assert_fields_roundtrip(&Tester::new(), 0);
+
+        let mut tunnels = 0;
+        for seed in 0..2000 {
+            let pkt = random_packet(seed);
+            assert_eq!(pkt.to_vec(), random_packet(seed).to_vec());
+            let parsed = assert_parse_roundtrips(&pkt);
+            tunnels += parsed.layer::<Vxlan>().is_some() as usize;
+        }
+        assert!(tunnels > 0);
+
+        // a bottom of stack label is followed by the packet it carries
+        let pkt = Ether::new() / MPLS::new() / IPv6::new() / TCP::new();
+        assert_parse_roundtrips(&pkt);
+    }
+    #[test]
+    #[allow(clippy::unused_unit)]
+    fn src_dst_bytes_test() {
+        let pkt = builders::create_tcp_packet(&Default::default());
+        let src: Vec<_> = (0..3).map(|i| pkt[i].src_bytes()).collect();
+        let dst: Vec<_> = (0..3).map(|i| pkt[i].dst_bytes()).collect();
+        let ether = pkt.layer::<Ether>().unwrap();
+        let ipv4 = pkt.layer::<IPv4>().unwrap();
+        let tcp = pkt.layer::<TCP>().unwrap();
+        assert_eq!(src[0], ether.get_field_bytes("src"));
+        assert_eq!(dst[0], ether.get_field_bytes("dst"));
+        assert_eq!(src[1], Some(ipv4.src_ip().octets().to_vec()));
+        assert_eq!(dst[1], Some(ipv4.dst_ip().octets().to_vec()));
+        assert_eq!(src[2], Some((tcp.src() as u16).to_be_bytes().to_vec()));
+        assert_eq!(dst[2], Some((tcp.dst() as u16).to_be_bytes().to_vec()));
+
+        let ipv6 = IPv6::new();
+        assert_eq!(ipv6.src_bytes(), Some(ipv6.src_ip().octets().to_vec()));
+        assert_eq!(UDP::new().dst_bytes(), Some(vec![0, 80]));
+        assert_eq!(ICMP::new().src_bytes(), None);
+        assert_eq!(Vlan::new().dst_bytes(), None);
+
+        // a header of the application named TCP has no ports in the flow
+        {
+            make_header!(
+            TCP 2
+            (
+                src: 0-7,
+                dst: 8-15
+            )
+            );
+            let mut pkt = Packet::new();
+            pkt.push(IPv4::new());
+            pkt.push(TCP::new());
+            assert_eq!(pkt[1].src_bytes(), Some(vec![0]));
+            assert_eq!(pkt.flow_tuple().unwrap().ports, None);
+        }
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_flat_test() {
+        use packet_rs::json::FlatJsonOptions;
+
+        let cfg = builders::PacketConfig {
+            payload_len: 4,
+            ..Default::default()
+        };
+        let tcp = builders::create_tcp_packet(&cfg);
+        let doc = tcp.to_json_flat();
+        let json = serde_json::to_string_pretty(&doc).unwrap();
+        assert_eq!(json + "\n", include_str!("data/tcp_flat.json"));
+        assert!(Packet::from_json_flat(&doc).unwrap().compare(&tcp));
+
+        // the layers found again are keyed with their occurrence
+        let inner = builders::create_ipv6_tcp_packet(&Default::default());
+        let vxlan = builders::create_vxlan_packet(&Default::default(), 100, inner);
+        let vxlan = parser::slow::parse(&vxlan.to_vec());
+        let options = FlatJsonOptions { payload: false };
+        let doc = vxlan.to_json_flat_with(&options);
+        let json = serde_json::to_string_pretty(&doc).unwrap();
+        assert_eq!(json + "\n", include_str!("data/vxlan_flat.json"));
+        assert!(Packet::from_json_flat(&doc).unwrap().compare(&vxlan));
+
+        // missing fields keep their defaults, the flags are set bit by bit
+        let doc = serde_json::json!({
+            "_layers": ["IPv4", "TCP"],
+            "ipv4.dst": "10.0.0.1",
+            "tcp.flags.ack": true,
+            "_payload": "aGk=",
+        });
+        let pkt = Packet::from_json_flat(&doc).unwrap();
+        assert_eq!(
+            pkt.layer::<IPv4>().unwrap().dst_ip().to_string(),
+            "10.0.0.1"
+        );
+        assert_eq!(pkt.layer::<TCP>().unwrap().flags(), 0x12);
+        assert!(pkt.to_vec().ends_with(b"hi"));
+
+        // the values of enum fields are their names, the values without a name are numbers
+        let mut arp = builders::create_arp_packet(&Default::default());
+        assert_eq!(arp.to_json_flat()["arp.opcode"], "REQUEST");
+        arp.layer_mut::<ARP>().unwrap().set_opcode(9);
+        assert_eq!(arp.to_json_flat()["arp.opcode"], 9);
+        let mut pkt = Packet::new();
+        pkt.push(IPv4::new());
+        pkt.push(Packet::icmp(8, 0));
+        let doc = pkt.to_json_flat();
+        assert_eq!(doc["icmp.icmp_type"], "ECHO_REQUEST");
+        assert!(Packet::from_json_flat(&doc).unwrap().compare(&pkt));
+        let doc = serde_json::json!({ "_layers": ["ICMP"], "icmp.icmp_type": "TIME_EXCEEDED" });
+        let pkt = Packet::from_json_flat(&doc).unwrap();
+        assert_eq!(pkt.layer::<ICMP>().unwrap().icmp_type(), 11);
+
+        let bad = serde_json::json!({ "_layers": ["UDP"], "udp.dst": 65536 });
+        assert!(Packet::from_json_flat(&bad).is_err());
+        let bad = serde_json::json!({ "_layers": ["TCP"], "tcp.flags.syn": 1 });
+        assert!(Packet::from_json_flat(&bad).is_err());
+        let bad = serde_json::json!({ "_layers": ["ICMP"], "icmp.icmp_type": "PING" });
+        assert!(Packet::from_json_flat(&bad).is_err());
+        let bad = serde_json::json!({ "_layers": ["DNS"] });
+        assert!(Packet::from_json_flat(&bad).is_err());
+        let bad = serde_json::json!({ "_layers": [], "_payload": "a" });
+        assert!(Packet::from_json_flat(&bad).is_err());
+    }
+    #[test]
+    fn edit_test() {
+        let cfg = builders::PacketConfig {
+            payload_len: 11,
+            ..Default::default()
+        };
+        let fixed = |pkt: &Packet| {
+            let mut fixed = pkt.clone();
+            fixed.update_checksums();
+            fixed
+        };
+        let mut pkt = builders::create_tcp_packet(&cfg);
+        let ttl = pkt.edit(|pkt| {
+            pkt[1].set_field("src", 0xcb007107);
+            pkt[1].set_field("ttl", 63);
+            pkt[2].set_field("src", 61000);
+            pkt[1].get_field("ttl")
+        });
+        assert_eq!(ttl, Some(63));
+        assert!(pkt.compare(&fixed(&pkt)));
+
+        // the inner checksums are updated before the outer ones covering them
+        let inner = builders::create_udp_packet(&cfg);
+        let vxlan = builders::create_vxlan_packet(&Default::default(), 100, inner);
+        let mut pkt = parser::slow::parse(&vxlan.to_vec());
+        pkt.edit(|pkt| {
+            pkt.layer_mut::<Vxlan>().unwrap().set_vni(200);
+            pkt[5].set_field("dst", 0x0a000002);
+            pkt[6].set_field("dst", 53);
+        });
+        assert!(pkt.compare(&fixed(&pkt)));
+        assert!(pkt.verify_checksums().is_ok());
+
+        // a wrong checksum stays wrong, a checksum set by the edit is kept
+        let mut pkt = builders::create_tcp_packet(&cfg);
+        pkt[1].set_field("header_checksum", 0x1234);
+        pkt.edit(|pkt| {
+            pkt[1].set_field("dst", 0x0a000001);
+            pkt[2].set_field("dst", 8080);
+            pkt[2].set_field("checksum", 0xbeef);
+        });
+        assert_ne!(
+            pkt[1].get_field("header_checksum"),
+            fixed(&pkt)[1].get_field("header_checksum")
+        );
+        assert_eq!(pkt[2].get_field("checksum"), Some(0xbeef));
+
+        // no UDP checksum over IPv4 stays so
+        let mut pkt = builders::create_udp_packet(&cfg);
+        pkt[2].set_field("checksum", 0);
+        pkt.edit(|pkt| pkt[2].set_field("dst", 5353));
+        assert_eq!(pkt[2].get_field("checksum"), Some(0));
+
+        // a resized packet has all its checksums recomputed
+        let mut pkt = builders::create_tcp_packet(&cfg);
+        pkt.edit(|pkt| pkt.set_payload(b"more"));
+        assert!(pkt.compare(&fixed(&pkt)));
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fixtures_test() {
+        let expected = [
+            (
+                "arp_request.json",
+                "Ether 00:06:07:08:09:0a > ff:ff:ff:ff:ff:ff / ARP hwtype=1 proto_type=2048 hwlen=6 len=18",
+            ),
+            (
+                "tcp_syn.json",
+                "Ether 06:07:08:09:0a:0b > 00:01:02:03:04:05 / IPv4 192.168.0.1 > 192.168.0.2 ttl=64 / TCP 40000 > 443 [SYN] len=5",
+            ),
+            (
+                "icmp_echo.yaml",
+                "Ether 00:66:77:88:99:aa > 00:11:22:33:44:55 / IPv4 192.0.2.1 > 198.51.100.7 ttl=64 / ICMP icmp_type=8 icmp_code=0 chksum=60399 len=8",
+            ),
+            (
+                "udp_total_len.toml",
+                "Ether 06:07:08:09:0a:0b > 00:01:02:03:04:05 / IPv4 10.0.0.1 > 10.0.0.2 ttl=64 / UDP 1234 > 53 len=5",
+            ),
+            (
+                "vlan_udp_unfixed.json",
+                "Ether 06:07:08:09:0a:0b > 00:01:02:03:04:05 / Vlan vid=100 pcp=5 / IPv6 2001:db8::1 > 2001:db8::2 hlim=64 / UDP 1234 > 4791 len=4",
+            ),
+        ];
+        // each fixture of the directory loads, in the format of its extension
+        let mut seen = Vec::new();
+        for path in std::fs::read_dir("tests/fixtures").unwrap() {
+            let path = path.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            let pkt = match path.extension().and_then(|x| x.to_str()) {
+                Some("json") => {
+                    Packet::from_value(&serde_json::from_str::<serde_json::Value>(&text).unwrap())
+                }
+                Some("yaml") => {
+                    Packet::from_value(&serde_yaml::from_str::<serde_yaml::Value>(&text).unwrap())
+                }
+                Some("toml") => Packet::from_value(&text.parse::<toml::Value>().unwrap()),
+                _ => continue,
+            };
+            let pkt = pkt.unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            if let Some((_, summary)) = expected.iter().find(|x| x.0 == name) {
+                assert_eq!(pkt.summary(), *summary);
+            }
+            match name.as_str() {
+                // the fixture left unfixed keeps its lengths and checksums
+                "vlan_udp_unfixed.json" => {
+                    assert_eq!(pkt.layer::<UDP>().unwrap().length(), 0);
+                    assert_eq!(pkt.layer::<UDP>().unwrap().checksum(), 0xdead);
+                }
+                // the fields given by the fixture are not fixed up
+                "udp_total_len.toml" => {
+                    assert_eq!(pkt.layer::<IPv4>().unwrap().total_len(), 64);
+                    assert_eq!(pkt.layer::<UDP>().unwrap().length(), 13);
+                    let ipv4 = pkt.layer::<IPv4>().unwrap();
+                    let sum = Packet::ipv4_checksum(&ipv4.to_vec());
+                    assert_eq!(ipv4.header_checksum(), sum as u64);
+                }
+                "icmp_echo.yaml" => {
+                    assert_eq!(pkt.layer::<IPv4>().unwrap().identification(), 0x1234);
+                    assert_eq!(pkt.layer::<IPv4>().unwrap().total_len(), 32);
+                    assert!(pkt.verify_checksums().is_ok());
+                }
+                _ => assert!(pkt.verify_checksums().is_ok()),
+            }
+            seen.push(name);
+        }
+        for (name, _) in expected {
+            assert!(seen.iter().any(|x| x == name), "{} not found", name);
+        }
+        // a checksum given inline is kept as well
+        let fixture = serde_json::json!([{"IP": {"header_checksum": "0x1234"}}, "UDP"]);
+        let pkt = Packet::from_value(&fixture).unwrap();
+        assert_eq!(pkt.layer::<IPv4>().unwrap().header_checksum(), 0x1234);
+        assert_eq!(pkt.layer::<IPv4>().unwrap().total_len(), 28);
+        assert!(!pkt.is_poisoned(0, "header_checksum"));
+
+        let path = |fixture: serde_json::Value| match Packet::from_value(&fixture) {
+            Err(PacketError::InvalidFixture { path, .. }) => path,
+            _ => panic!("{}", fixture),
+        };
+        let fixture = serde_json::json!(["Ether", {"TCP": {"port": 1}}]);
+        assert_eq!(path(fixture), "[1].TCP.port");
+        let fixture = serde_json::json!(["Ether", "DNS"]);
+        assert_eq!(path(fixture), "[1].DNS");
+        let fixture = serde_json::json!(["Ether", {"IPv4": {"src": "1.2.3"}}]);
+        assert_eq!(path(fixture), "[1].IPv4.src");
+        let fixture = serde_json::json!(["UDP", {"payload": {"pattern": "zeros", "len": 4}}]);
+        assert_eq!(path(fixture), "[1].payload");
+        let fixture = serde_json::json!({"layers": ["UDP", 7]});
+        assert_eq!(path(fixture), "layers[1]");
+        let fixture = serde_json::json!({"layers": [], "fixup": "no"});
+        assert_eq!(path(fixture), "fixup");
+        let fixture = serde_json::json!({"headers": []});
+        assert_eq!(path(fixture), "headers");
+    }
+    #[test]
+    fn ops_decode_test() {
+        let reader = packet_rs::pcap::PcapReader::open("tests/data/filter.pcap").unwrap();
+        let v = reader.records()[0].packet.to_vec();
+        let out = ops::decode(&v, &Default::default());
+        let layers: Vec<&str> = out
+            .lines()
+            .filter_map(|x| x.trim_start().strip_prefix("#### "))
+            .map(|x| x.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(layers, ["Ether", "IPv4", "UDP", "Vxlan"]);
+        assert!(out.contains("\n0000  00 00 00 00 00 02 00 00 00 00 00 01 08 00 45 00  Ether.dst"));
+        assert!(out.contains("  IPv4.dst UDP.src UDP.dst UDP.length UDP.checksum Vxlan.flags"));
+        assert!(!out.contains("#### raw"));
+
+        let options = ops::DecodeOptions {
+            hexdump: false,
+            ..Default::default()
+        };
+        assert_eq!(ops::decode(&v, &options), parser::slow::parse(&v).dump());
+        let options = ops::DecodeOptions {
+            dissect: parser::DissectConfig {
+                stop_at: Some("IPv4"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let out = ops::decode(&v, &options);
+        assert!(!out.contains("#### UDP"));
+        assert!(out.contains("#### Payload "));
+        assert!(out.contains("IPv4.dst\n"));
+        // bytes the parser cannot dissect decode as a payload
+        let out = ops::decode(&[0xff; 5], &Default::default());
+        assert!(out.starts_with("#### Payload          offset    0 length    5\n"));
+        assert!(out.contains("\n0000  ff ff ff ff ff "));
+    }
+    #[test]
+    fn ops_rewrite_test() {
+        let input = "tests/data/flows.pcap";
+        let out = std::env::temp_dir().join("ops_rewrite_test.pcap");
+        let edits = [
+            ops::FieldEdit::parse("Ether.dst=00:11:22:33:44:55")
+                .unwrap()
+                .filter("tcp")
+                .unwrap(),
+            ops::FieldEdit::new("IP", "ttl", "32")
+                .unwrap()
+                .filter("udp and port 53")
+                .unwrap(),
+        ];
+        assert_eq!(ops::rewrite(input, &out, &edits).unwrap(), 18);
+        let before = packet_rs::pcap::PcapReader::open(input).unwrap();
+        let after = packet_rs::pcap::PcapReader::open(&out).unwrap();
+        assert_eq!(after.records().len(), before.records().len());
+        for (old, new) in before.records().iter().zip(after.records()) {
+            assert_eq!(
+                (new.ts_sec, new.ts_nsec, new.orig_len),
+                (old.ts_sec, old.ts_nsec, old.orig_len)
+            );
+            let (old, new) = (&old.packet, &new.packet);
+            let dst = new.layer::<Ether>().unwrap().dst();
+            let ttl = new.layer::<IPv4>().map(|x| x.ttl());
+            // the packets edited have their checksums recomputed, the others are untouched
+            match (new.layer::<TCP>(), new.layer::<UDP>()) {
+                (Some(_), _) => assert_eq!((dst, ttl), (0x001122334455, Some(64))),
+                (_, Some(_)) => assert_eq!((dst, ttl), (0x000102030405, Some(32))),
+                _ => assert_eq!(new.to_vec(), old.to_vec()),
+            }
+            if new.layer::<TCP>().is_some() || new.layer::<UDP>().is_some() {
+                assert!(new.verify_checksums().is_ok());
+                assert_eq!(new.len(), old.len());
+            }
+        }
+
+        // a truncated record keeps its length on the wire and has its checksums updated
+        // incrementally
+        let mut pkt = builders::create_tcp_packet(&Default::default());
+        pkt.set_payload(&payload::incrementing(40));
+        pkt.fixup();
+        let (short, len) = pkt.clone_truncated(60);
+        let data = packet_rs::pcap::write_pcap_truncated(&[(short.to_vec(), len)]);
+        std::fs::write(&out, data).unwrap();
+        let edit = ops::FieldEdit::parse("IPv4.dst=10.9.8.7").unwrap();
+        assert_eq!(ops::rewrite(&out, &out, std::slice::from_ref(&edit)).unwrap(), 1);
+        let reader = packet_rs::pcap::PcapReader::open(&out).unwrap();
+        let record = &reader.records()[0];
+        assert_eq!(
+            (record.packet.len(), record.orig_len as usize),
+            (60, pkt.len())
+        );
+        edit.apply(&mut pkt).unwrap();
+        assert_eq!(record.packet.to_vec(), pkt.to_vec()[..60]);
+        std::fs::remove_file(&out).unwrap();
+
+        // errors name the file, the edit or the filter
+        let err = |e: PacketError| e.to_string();
+        assert_eq!(
+            err(ops::rewrite("tests/data/missing.pcap", &out, &[])
+                .err()
+                .unwrap()),
+            "tests/data/missing.pcap: i/o error: No such file or directory (os error 2)"
+        );
+        assert_eq!(
+            err(ops::rewrite("Cargo.toml", &out, &[]).err().unwrap()),
+            "Cargo.toml: cannot parse pcap at offset 0: unknown magic number"
+        );
+        let dir = std::env::temp_dir().join("ops_rewrite_missing_dir/out.pcap");
+        assert!(err(ops::rewrite(input, &dir, &[]).err().unwrap())
+            .starts_with(&format!("{}: i/o error: ", dir.display())));
+        assert_eq!(
+            err(ops::FieldEdit::parse("Ether.dst").err().unwrap()),
+            "Ether.dst: invalid packet spec: expected header.field=value"
+        );
+        assert_eq!(
+            err(ops::FieldEdit::parse("IP.ttl=300").err().unwrap()),
+            "IP.ttl=300: invalid value 300 for ttl field"
+        );
+        assert_eq!(
+            err(ops::FieldEdit::parse("TCP.window_size=1").err().unwrap()),
+            "TCP.window_size=1: window_size field not found in TCP header"
+        );
+        assert_eq!(
+            err(ops::FieldEdit::parse("Ipx.dst=1").err().unwrap()),
+            "Ipx.dst=1: Ipx header not found"
+        );
+        let edit = ops::FieldEdit::parse("IP.ttl=1").unwrap();
+        assert_eq!(
+            err(edit.filter("tcp and").err().unwrap()),
+            "filter \"tcp and\": invalid filter at position 7: expected a primitive"
+        );
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ops_build_from_fixture_test() {
+        for name in ["arp_request", "tcp_syn", "vlan_udp_unfixed"] {
+            let path = format!("tests/fixtures/{}.json", name);
+            let fixture: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let expected = Packet::from_value(&fixture).unwrap().to_vec();
+            assert_eq!(ops::build_from_fixture(&path).unwrap(), expected);
+        }
+
+        let dir = std::env::temp_dir();
+        let error = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            let err = ops::build_from_fixture(&path).err().unwrap();
+            std::fs::remove_file(&path).unwrap();
+            err.to_string()
+                .strip_prefix(&format!("{}: ", path.display()))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            error("ops_bad_ttl.json", r#"[{"IP": {"ttl": 256}}]"#),
+            "invalid fixture entry [0].IP.ttl: invalid value 256 for ttl field"
+        );
+        assert_eq!(
+            error("ops_bad_layer.json", r#"{"layers": ["Ether", "IPX"]}"#),
+            "invalid fixture entry layers[1].IPX: IPX header not found"
+        );
+        assert_eq!(
+            error("ops_bad_syntax.json", r#"[{"IP": "#),
+            "invalid fixture: EOF while parsing a value at line 1 column 8"
+        );
+        assert_eq!(
+            error("ops_fixture.xml", "<Ether/>"),
+            "invalid fixture: unsupported .xml file, expected a .json, .yaml or .toml fixture"
+        );
+        #[cfg(not(feature = "yaml"))]
+        assert_eq!(
+            error("ops_fixture.yaml", "- Ether"),
+            "invalid fixture: .yaml fixtures need the yaml feature"
+        );
+        #[cfg(feature = "yaml")]
+        {
+            let path = "tests/fixtures/icmp_echo.yaml";
+            let fixture: serde_yaml::Value =
+                serde_yaml::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+            let expected = Packet::from_value(&fixture).unwrap().to_vec();
+            assert_eq!(ops::build_from_fixture(path).unwrap(), expected);
+            assert_eq!(
+                error("ops_bad_ttl.yml", "- IP:\n    ttl: 256"),
+                "invalid fixture entry [0].IP.ttl: invalid value 256 for ttl field"
+            );
+        }
+        #[cfg(feature = "toml")]
+        {
+            let path = "tests/fixtures/udp_total_len.toml";
+            let fixture: toml::Value = std::fs::read_to_string(path).unwrap().parse().unwrap();
+            let expected = Packet::from_value(&fixture).unwrap().to_vec();
+            assert_eq!(ops::build_from_fixture(path).unwrap(), expected);
+        }
+        let err = ops::build_from_fixture("tests/fixtures/missing.json")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            &err,
+            PacketError::Context { context, error }
+                if context == "tests/fixtures/missing.json"
+                    && matches!(**error, PacketError::Io { kind: std::io::ErrorKind::NotFound, .. })
+        ));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+    #[test]
+    fn anonymize_test() {
+        let input = "tests/data/flows.pcap";
+        let out = std::env::temp_dir().join("anonymize_test.pcap");
+        let mut anonymizer = Anonymizer::new(0x1234);
+        assert_eq!(anonymizer.anonymize_pcap(input, &out).unwrap(), 21);
+        let before = packet_rs::pcap::PcapReader::open(input).unwrap();
+        let after = packet_rs::pcap::PcapReader::open(&out).unwrap();
+
+        // flows stay correlated: the same flows with the same counters
+        let flows = |reader: &packet_rs::pcap::PcapReader| {
+            let mut tracker = FlowTracker::new();
+            for record in reader.records() {
+                tracker.add_record(record);
+            }
+            let flows: Vec<_> = tracker
+                .by_bytes()
+                .iter()
+                .map(|x| (x.tuple.ports, x.forward.packets, x.reverse.bytes))
+                .collect();
+            (tracker.len(), flows)
+        };
+        assert_eq!(flows(&after), flows(&before));
+        for (old, new) in before.records().iter().zip(after.records()) {
+            let (old, new) = (&old.packet, &new.packet);
+            assert_eq!(new.len(), old.len());
+            let (old_eth, new_eth) = (old.layer::<Ether>().unwrap(), new.layer::<Ether>().unwrap());
+            assert_eq!(new_eth.src() >> 24, old_eth.src() >> 24);
+            assert_ne!(new_eth.src(), old_eth.src());
+            if let Some(ip) = new.layer::<IPv4>() {
+                assert_ne!(ip.src(), old.layer::<IPv4>().unwrap().src());
+                assert!(new.verify_checksums().is_ok());
+            }
+            if new.layer::<TCP>().is_some() || new.layer::<UDP>().is_some() {
+                let hdrs: usize = new.iter().map(|x| x.len()).sum();
+                assert!(new.to_vec()[hdrs..].iter().all(|x| *x == 0));
+            }
+        }
+        // the broadcast and the unknown target of an arp request are kept, the addresses of the
+        // sender are mapped the same in both layers
+        let mut arp = builders::create_arp_packet(&Default::default());
+        arp.layer_mut::<Ether>()
+            .unwrap()
+            .set_dst_mac(MacAddr::BROADCAST);
+        let old = arp.clone();
+        anonymizer.anonymize_packet(&mut arp);
+        let (eth, hdr) = (arp.layer::<Ether>().unwrap(), arp.layer::<ARP>().unwrap());
+        let old_hdr = old.layer::<ARP>().unwrap();
+        assert_eq!((eth.dst(), hdr.target_hw_addr()), (0xffffffffffff, 0));
+        assert_eq!(hdr.sender_hw_addr(), eth.src());
+        assert_ne!(hdr.sender_hw_addr(), old_hdr.sender_hw_addr());
+        assert_ne!(hdr.sender_proto_addr(), old_hdr.sender_proto_addr());
+
+        // prefixes are preserved: mapped addresses share as many leading bits as the originals
+        let ips: Vec<_> = anonymizer.ip_mapping().iter().collect();
+        assert!(ips.len() >= 7);
+        let bits = |a: &std::net::IpAddr, b: &std::net::IpAddr| match (a, b) {
+            (std::net::IpAddr::V4(a), std::net::IpAddr::V4(b)) => {
+                (u32::from(*a) ^ u32::from(*b)).leading_zeros()
+            }
+            _ => unreachable!(),
+        };
+        for (a, mapped_a) in &ips {
+            for (b, mapped_b) in &ips {
+                assert_eq!(bits(mapped_a, mapped_b), bits(a, b));
+            }
+        }
+        let table = anonymizer.mapping_table();
+        let mapped = anonymizer.ip_mapping()[&"10.0.0.1".parse().unwrap()];
+        assert!(table.contains(&format!("\n10.0.0.1 -> {}\n", mapped)));
+        assert_eq!(
+            table.lines().count(),
+            ips.len() + anonymizer.mac_mapping().len()
+        );
+
+        // the same key maps the same way, another key does not
+        let mut pkt = builders::create_tcp_packet(&Default::default());
+        pkt.set_payload(b"secret");
+        pkt.fixup();
+        let anonymized = |key: u64, options: AnonymizeOptions| {
+            let mut pkt = pkt.clone();
+            let len = Anonymizer::with_options(key, options).anonymize_packet(&mut pkt);
+            (pkt, len)
+        };
+        let options = AnonymizeOptions::default();
+        assert_eq!(
+            anonymized(1, options).0.to_vec(),
+            anonymized(1, options).0.to_vec()
+        );
+        assert_ne!(
+            anonymized(1, options).0.to_vec(),
+            anonymized(2, options).0.to_vec()
+        );
+
+        let options = AnonymizeOptions {
+            mac: MacMode::Randomize,
+            payload: PayloadMode::Truncate(2),
+        };
+        let (short, len) = anonymized(1, options);
+        assert_eq!((short.len(), len), (pkt.len() - 4, pkt.len()));
+        assert_eq!(short.to_vec()[short.len() - 2..], *b"se");
+        let mac = short.layer::<Ether>().unwrap().src_mac();
+        assert!(mac.is_local() && mac.is_unicast());
+        let options = AnonymizeOptions {
+            mac: MacMode::Keep,
+            payload: PayloadMode::Keep,
+        };
+        let (kept, _) = anonymized(1, options);
+        assert_eq!(
+            kept.layer::<Ether>().unwrap().src(),
+            pkt.layer::<Ether>().unwrap().src()
+        );
+        assert_eq!(kept.to_vec()[kept.len() - 6..], *b"secret");
+
+        // a record truncated by the snaplen keeps its length and has its checksums updated
+        let (short, len) = pkt.clone_truncated(60);
+        let data = packet_rs::pcap::write_pcap_truncated(&[(short.to_vec(), len)]);
+        std::fs::write(&out, data).unwrap();
+        let mut anonymizer = Anonymizer::new(1);
+        anonymizer.anonymize_pcap(&out, &out).unwrap();
+        let reader = packet_rs::pcap::PcapReader::open(&out).unwrap();
+        let record = &reader.records()[0];
+        assert_eq!((record.packet.len(), record.orig_len as usize), (60, len));
+        let (expected, _) = anonymized(1, AnonymizeOptions::default());
+        assert_eq!(record.packet.to_vec(), expected.to_vec()[..60]);
+        std::fs::remove_file(&out).unwrap();
+
+        // the addresses of the packet an icmp error quotes are mapped as well
+        let tcp = builders::create_tcp_packet(&Default::default());
+        let tcpv6 = builders::create_ipv6_tcp_packet(&Default::default());
+        let router = "10.0.0.1".parse().unwrap();
+        let errors = [
+            (icmp::icmp_time_exceeded(&tcp, router).unwrap(), &tcp),
+            (icmp::icmp_dest_unreachable(&tcp, 1, router).unwrap(), &tcp),
+            (
+                icmp::icmpv6_time_exceeded(&tcpv6, "2001:db8::1".parse().unwrap()).unwrap(),
+                &tcpv6,
+            ),
+        ];
+        let mut anonymizer = Anonymizer::new(1);
+        for (mut error, offending) in errors {
+            anonymizer.anonymize_packet(&mut error);
+            assert!(error.verify_checksums().is_ok());
+            let v = error.to_vec();
+            for field in ["src", "dst"] {
+                let ip = offending[1].get_field_bytes(field).unwrap();
+                assert!(!v.windows(ip.len()).any(|x| x == ip), "{:02x?} leaked", ip);
+            }
+            let hdrs: usize = error.iter().map(|x| x.len()).sum();
+            let quoted = &v[hdrs + 4..];
+            let quoted = parser::slow::parse_from(offending[1].name(), quoted);
+            let mapped = anonymizer.ip_mapping()[&offending.flow_tuple().unwrap().src];
+            assert_eq!(quoted.flow_tuple().unwrap().src, mapped);
+            if let Some(ipv4) = quoted.layer::<IPv4>() {
+                assert_eq!(
+                    ipv4.header_checksum(),
+                    Packet::ipv4_checksum(&ipv4.to_vec()) as u64
+                );
+            }
+        }
+
+        assert_eq!(
+            anonymizer
+                .anonymize_pcap("tests/data/missing.pcap", &out)
+                .err()
+                .unwrap()
+                .to_string(),
+            "tests/data/missing.pcap: i/o error: No such file or directory (os error 2)"
+        );
+    }
+    #[test]
+    fn rebase_test() {
+        let reader =
+            packet_rs::pcap::PcapReader::from_bytes(include_bytes!("data/flows.pcap")).unwrap();
+        // the request of the http flow, the first segment carrying data to port 80
+        let captured = reader
+            .records()
+            .iter()
+            .map(|x| &x.packet)
+            .find(|x| {
+                let (ip, tcp) = (x.layer::<IPv4>(), x.layer::<TCP>());
+                let data = ip.map_or(0, |i| i.total_len() - i.ihl() * 4);
+                tcp.is_some_and(|t| t.dst() == 80 && data > t.data_offset() * 4)
+            })
+            .unwrap();
+        let mut lab = builders::create_tcp_packet(&PacketConfig {
+            eth_dst: String::from("02:00:00:00:00:01"),
+            eth_src: String::from("02:00:00:00:00:02"),
+            ip_src: String::from("172.16.0.1"),
+            ip_dst: String::from("172.16.0.2"),
+            ip_tos: 0xb8,
+            ip_ttl: 32,
+            ip_id: 999,
+            sport: 1111,
+            dport: 8080,
+            ..Default::default()
+        });
+        lab.layer_mut::<IPv4>().unwrap().set_frag_offset(0);
+
+        // only the addressing, ttl and tos change, the checksums follow
+        let pkt = captured.rebase(&lab, &[LayerKind::Link, LayerKind::Network]);
+        let ip = pkt.layer::<IPv4>().unwrap();
+        assert_eq!(pkt.layer::<Ether>().unwrap().dst(), 0x020000000001);
+        assert_eq!(ip.src_ip().to_string(), "172.16.0.1");
+        assert_eq!(
+            (ip.dst_ip().to_string(), ip.ttl()),
+            (String::from("172.16.0.2"), 32)
+        );
+        let old_ip = captured.layer::<IPv4>().unwrap();
+        assert_eq!(
+            (ip.identification(), ip.total_len()),
+            (old_ip.identification(), old_ip.total_len())
+        );
+        assert!(pkt.verify_checksums().is_ok());
+        let changed = [
+            ("Ether", "dst"),
+            ("Ether", "src"),
+            ("IPv4", "src"),
+            ("IPv4", "dst"),
+            ("IPv4", "ttl"),
+            ("IPv4", "diffserv"),
+            ("IPv4", "header_checksum"),
+            ("TCP", "checksum"),
+        ];
+        assert!(pkt.eq_ignoring(captured, &changed));
+        assert!(!pkt.eq_ignoring(captured, &changed[1..]));
+        assert_eq!(pkt.len(), captured.len());
+
+        // the template's MACs and ports but the capture's tos
+        let opts = RebaseOptions {
+            layers: &[LayerKind::Link],
+            include: &[("TCP", "dst"), ("IPv4", "identification")],
+            exclude: &[("Ether", "src")],
+        };
+        let pkt = captured.rebase_with(&lab, &opts);
+        let eth = pkt.layer::<Ether>().unwrap();
+        assert_eq!(
+            (eth.dst(), eth.src()),
+            (0x020000000001, captured.layer::<Ether>().unwrap().src())
+        );
+        let (ip, tcp) = (pkt.layer::<IPv4>().unwrap(), pkt.layer::<TCP>().unwrap());
+        assert_eq!((ip.identification(), ip.src()), (999, old_ip.src()));
+        assert_eq!((tcp.src(), tcp.dst()), (40000, 8080));
+        let changed = [
+            ("Ether", "dst"),
+            ("IPv4", "identification"),
+            ("IPv4", "header_checksum"),
+            ("TCP", "dst"),
+            ("TCP", "checksum"),
+        ];
+        assert!(pkt.eq_ignoring(captured, &changed));
+        assert!(pkt.verify_checksums().is_ok());
+
+        // a layer of the packet with no counterpart in the template is left as it is
+        let tunneled = parser::slow::parse(&captured.vxlan_encap(&Default::default()).unwrap().to_vec());
+        let pkt = tunneled.rebase(&lab, &[LayerKind::Network]);
+        let ips = pkt.layers::<IPv4>();
+        assert_eq!(ips[0].dst_ip().to_string(), "172.16.0.2");
+        assert_eq!(ips[1].dst(), old_ip.dst());
+        assert!(pkt.verify_checksums().is_ok());
+    }
+    #[test]
     fn pktgen_perf_test() {
         let cnt = 300000;
         let mut pkt = test_tcp_packet();
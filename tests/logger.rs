@@ -0,0 +1,72 @@
+use std::cell::RefCell;
+use std::sync::Once;
+
+use log::kv::{Key, VisitSource};
+use log::{Level, Log, Metadata, Record};
+
+/// Level, message and key-values of a log record
+pub struct Captured {
+    pub level: Level,
+    pub message: String,
+    pub kvs: Vec<(String, String)>,
+}
+
+impl Captured {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.kvs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+thread_local! {
+    static RECORDS: RefCell<Vec<Captured>> = const { RefCell::new(Vec::new()) };
+}
+
+struct CaptureLogger;
+
+struct Visitor<'a>(&'a mut Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for Visitor<'_> {
+    fn visit_pair(
+        &mut self,
+        key: Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+impl Log for CaptureLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &Record) {
+        let mut kvs = Vec::new();
+        record.key_values().visit(&mut Visitor(&mut kvs)).unwrap();
+        RECORDS.with(|r| {
+            r.borrow_mut().push(Captured {
+                level: record.level(),
+                message: record.args().to_string(),
+                kvs,
+            })
+        });
+    }
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger;
+static INIT: Once = Once::new();
+
+/// Run f and return the records it logged on this thread
+pub fn capture<F: FnOnce()>(f: F) -> Vec<Captured> {
+    INIT.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+    RECORDS.with(|r| r.borrow_mut().clear());
+    f();
+    RECORDS.with(|r| r.borrow_mut().drain(..).collect())
+}
@@ -0,0 +1,54 @@
+//! Field access through the core header machinery, also run against the no_std build with
+//! `cargo test --no-default-features --features alloc,host-shim --test no_std`
+
+#![allow(clippy::len_without_is_empty, clippy::unused_unit)]
+
+#[macro_use]
+extern crate packet_rs;
+
+use packet_rs::headers::*;
+use packet_rs::parser;
+use packet_rs::Packet;
+
+make_header!(
+Custom 4
+(
+    flag: 0-0,
+    kind: 1-3,
+    value: 4-15,
+    reserved: 16-31 [reserved]
+)
+vec![0x80, 0x0a, 0x00, 0x00]
+);
+
+#[test]
+fn field_access_test() {
+    let mut hdr = Custom::new();
+    assert!(hdr.flag());
+    assert_eq!(hdr.value(), 0x0a);
+    hdr.set_kind(5);
+    hdr.set_value(0xabc);
+    assert_eq!(hdr.to_vec(), [0xda, 0xbc, 0x00, 0x00]);
+    assert_eq!(hdr.get_field("kind"), Some(5));
+    assert!(hdr.set_field("reserved", 1));
+    assert!(!hdr.validate_reserved());
+    assert_eq!(hdr.dump().lines().count(), 6);
+}
+
+#[test]
+fn packet_test() {
+    let mut pkt = Packet::new();
+    pkt.push(Packet::ethernet(
+        "00:01:02:03:04:05",
+        "00:06:07:08:09:0a",
+        0x0800,
+    ));
+    pkt.push(Packet::ipv4(5, 0, 1, 64, 0, 17, "10.0.0.1", "10.0.0.2", 28));
+    pkt.push(Packet::udp(1234, 80, 8));
+    let v = pkt.to_vec();
+    assert_eq!(v.len(), 42);
+    assert_eq!(pkt.verify_checksums(), Ok(()));
+
+    assert_eq!(parser::fast::parse(&v).to_vec(), v);
+    assert!(parser::slow::parse(&v).compare(&pkt));
+}
@@ -1,22 +1,20 @@
 use std::{
-    fs::OpenOptions,
+    fs::File,
     io::Write,
+    path::PathBuf,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-pub fn pcap_write(packets: &Vec<Vec<u8>>) {
+/// Write the packets as a capture in the temporary directory, returns its path
+pub fn pcap_write(packets: &Vec<Vec<u8>>) -> PathBuf {
     let start = SystemTime::now();
     let since_the_epoch = start
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards");
     let tv_sec = since_the_epoch.as_secs() as u32;
     let tv_usec = since_the_epoch.subsec_millis() as u32;
-    let mut temp = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(false)
-        .open("temp.pcap")
-        .unwrap();
+    let path = std::env::temp_dir().join(format!("packet_rs_{}.pcap", std::process::id()));
+    let mut temp = File::create(&path).unwrap();
     let global_header = vec![
         0xd4, 0xc3, 0xb2, 0xa1, 0x2, 0x0, 0x4, 0x0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 1, 0,
         0, 0,
@@ -34,4 +32,5 @@ pub fn pcap_write(packets: &Vec<Vec<u8>>) {
         temp.write_all(pcap_header.as_slice()).unwrap();
         temp.write_all(packet).unwrap();
     }
+    path
 }
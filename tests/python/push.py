@@ -0,0 +1,24 @@
+# Headers pushed onto a packet from Python
+#
+#     maturin develop --features python-module && python tests/python/push.py
+
+from packet_rs import *
+
+# the header type is picked by class name, whatever str() prints
+eth = Ether()
+assert str(eth) == repr(eth)
+assert "Ether" in str(eth)
+
+pkt = Packet() + Ether()
+assert len(pkt.to_vec()) == 14
+
+pkt = Packet() + Ether() + IPv4() + TCP()
+assert len(pkt.to_vec()) == 14 + 20 + 20
+assert pkt.to_vec()[:14] == Ether().to_vec()
+
+try:
+    Packet() + 1
+except TypeError:
+    pass
+else:
+    raise AssertionError("an int was pushed as a header")
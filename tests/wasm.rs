@@ -0,0 +1,64 @@
+//! JSON bindings of the `wasm` feature, run natively with `cargo test --features wasm --test wasm`
+//! and in a browser or node with `wasm-pack test --node -- --features wasm --test wasm`
+
+#![cfg(feature = "wasm")]
+
+use packet_rs::pcap;
+use packet_rs::wasm::*;
+use packet_rs::Packet;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::wasm_bindgen_test as test;
+
+/// Ether/IPv4/UDP query for the A record of example.com
+const DNS_QUERY: [u8; 71] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x06, 0x07, 0x08, 0x09, 0x0a, //
+    0x08, 0x00, 0x45, 0x00, 0x00, 0x39, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, //
+    0x66, 0x7e, 0x0a, 0x00, 0x00, 0x01, 0x0a, 0x00, 0x00, 0x35, 0x14, 0xe9, //
+    0x00, 0x35, 0x00, 0x25, 0x00, 0x00, 0x12, 0x34, 0x01, 0x00, 0x00, 0x01, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, //
+    0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+];
+
+const DNS_QUERY_JSON: &str = r#"{"layers":[{"fields":{"dst":4328719365,"etype":2048,"src":25887770890},"name":"Ether"},{"fields":{"diffserv":0,"dst":167772213,"flags":0,"frag_offset":0,"header_checksum":26238,"identification":1,"ihl":5,"protocol":17,"src":167772161,"total_len":57,"ttl":64,"version":4},"name":"IPv4"},{"fields":{"checksum":0,"dst":53,"length":37,"src":5353},"name":"UDP"}],"payload":"0x123401000001000000000000076578616d706c6503636f6d0000010001"}"#;
+
+#[test]
+fn dns_query_json_test() {
+    let json = packet_to_json(&packet_rs::parser::slow::parse(&DNS_QUERY));
+    assert_eq!(json.to_string(), DNS_QUERY_JSON);
+    #[cfg(target_arch = "wasm32")]
+    assert_eq!(
+        parse_packet(&DNS_QUERY).as_string().unwrap(),
+        DNS_QUERY_JSON
+    );
+
+    let pkt = packet_from_json(&json).unwrap();
+    assert_eq!(pkt.to_vec(), DNS_QUERY);
+}
+
+#[test]
+fn build_packet_test() {
+    let json = r#"{"layers": [
+        {"name": "Ether", "fields": {"dst": "00:01:02:03:04:05", "etype": 2048}},
+        {"name": "IPv4", "fields": {"src": "10.0.0.1", "dst": "10.0.0.53", "ttl": 64}},
+        {"name": "UDP", "fields": {"dst": 53}}
+    ]}"#;
+    let pkt = packet_from_json(&serde_json::from_str(json).unwrap()).unwrap();
+    assert_eq!(pkt.to_vec()[..6], DNS_QUERY[..6]);
+    assert_eq!(pkt.to_vec()[26..34], DNS_QUERY[26..34]);
+
+    let bad = r#"{"layers": [{"name": "UDP", "fields": {"dst": 65536}}]}"#;
+    assert!(packet_from_json(&serde_json::from_str(bad).unwrap()).is_err());
+    let bad = r#"{"layers": [{"name": "DNS"}]}"#;
+    assert!(packet_from_json(&serde_json::from_str(bad).unwrap()).is_err());
+}
+
+#[test]
+fn pcap_bytes_test() {
+    let data = pcap::write_pcap(&[DNS_QUERY.to_vec(), DNS_QUERY[..42].to_vec()]);
+    let reader = pcap::PcapReader::from_bytes(&data).unwrap();
+    assert_eq!(reader.linktype(), pcap::LINKTYPE_ETHERNET);
+    let packets: Vec<Packet> = reader.into_packets();
+    assert_eq!(packets.len(), 2);
+    assert_eq!(packets[0].to_vec(), DNS_QUERY);
+    assert!(pcap::PcapReader::from_bytes(&data[..data.len() - 1]).is_err());
+}